@@ -3,10 +3,9 @@
 
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 use std::hint::black_box;
-use std::io::Cursor;
 
 use forest_filecoin::benchmark_private::{
-    car_index::{CarIndex, CarIndexBuilder, FrameOffset},
+    car_index::{CarIndex, CarIndexBuilder, FrameOffset, Hash},
     cid::CidCborExt,
 };
 
@@ -30,15 +29,16 @@ fn bench_car_index(c: &mut Criterion) {
     let live_key = Cid::from_cbor_blake2b256(&0xbeef_u64).unwrap();
     let dead_key = Cid::from_cbor_blake2b256(&"hash miss").unwrap();
 
-    let builder = CarIndexBuilder::new((0..map_size).map(|i| {
-        let i = i as u64;
-        (Cid::from_cbor_blake2b256(&i).unwrap(), i as FrameOffset)
-    }));
+    let builder = CarIndexBuilder::new_parallel(
+        (0..map_size as u64)
+            .map(|i| (Hash::from(Cid::from_cbor_blake2b256(&i).unwrap()), i))
+            .collect(),
+    );
 
     let mut index_vec = vec![];
     builder.write(&mut index_vec).unwrap();
 
-    let mut car_index = CarIndex::open(Cursor::new(index_vec), 0).unwrap();
+    let car_index = CarIndex::open(index_vec, 0).unwrap();
 
     assert!(map.contains_key(&live_key));
     assert!(!map.contains_key(&dead_key));
@@ -9,9 +9,55 @@ const PROTO_DIR: &str = "proto";
 const CARGO_OUT_DIR: &str = "proto";
 
 fn main() -> anyhow::Result<()> {
+    emit_fvm_versions()?;
+    emit_features()?;
     generate_protobuf_code()
 }
 
+/// Exposes the versions of the `fvm` crates in the lock file as
+/// `FOREST_FVM_VERSIONS`, e.g. `2.5.0,3.5.0`.
+fn emit_fvm_versions() -> anyhow::Result<()> {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+
+    let lock_file = std::fs::read_to_string("Cargo.lock").unwrap_or_default();
+    let mut versions = vec![];
+    let mut lines = lock_file.lines();
+    while let Some(line) = lines.next() {
+        if line == "name = \"fvm\"" {
+            if let Some(version) = lines.next().and_then(|l| l.strip_prefix("version = ")) {
+                versions.push(version.trim_matches('"').to_owned());
+            }
+        }
+    }
+    if versions.is_empty() {
+        versions.push("unknown".into());
+    }
+    println!("cargo:rustc-env=FOREST_FVM_VERSIONS={}", versions.join(","));
+    Ok(())
+}
+
+/// Exposes the features of `Cargo.toml` enabled in the build as
+/// `FOREST_FEATURES`, e.g. `jemalloc,graphsync-server`.
+fn emit_features() -> anyhow::Result<()> {
+    println!("cargo:rerun-if-changed=Cargo.toml");
+
+    let manifest = std::fs::read_to_string("Cargo.toml")?;
+    let features: Vec<_> = manifest
+        .lines()
+        .skip_while(|line| *line != "[features]")
+        .skip(1)
+        .take_while(|line| !line.starts_with('['))
+        .filter_map(|line| Some(line.split_once('=')?.0.trim()))
+        .filter(|feature| *feature != "default" && !feature.starts_with('#'))
+        .filter(|feature| {
+            let var = format!("CARGO_FEATURE_{}", feature.to_uppercase().replace('-', "_"));
+            std::env::var_os(var).is_some()
+        })
+        .collect();
+    println!("cargo:rustc-env=FOREST_FEATURES={}", features.join(","));
+    Ok(())
+}
+
 fn generate_protobuf_code() -> anyhow::Result<()> {
     println!("cargo:rerun-if-changed=proto");
 
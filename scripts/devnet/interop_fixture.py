@@ -0,0 +1,203 @@
+#!/usr/bin/env python3
+# Copyright 2019-2023 ChainSafe Systems
+# SPDX-License-Identifier: Apache-2.0, MIT
+"""Generates a fixture of the `interop` tests from the running local devnet.
+
+The fixture contains the genesis of the devnet, a snapshot exported by Lotus,
+messages signed by the Lotus wallet and valid on top of the snapshot head, and
+the results of RPC calls on Lotus at the snapshot head. See
+`tests/interop/fixture.rs` for the format.
+
+Usage, with the devnet of `docker-compose.yml` running:
+    ./interop_fixture.py /path/to/fixture [--epoch 30] [--messages 3]
+"""
+
+import argparse
+import base64
+import json
+import os
+import subprocess
+import sys
+import time
+import urllib.request
+
+LOTUS_CONTAINER = os.environ.get("LOTUS_CONTAINER", "lotus")
+LOTUS_RPC = os.environ.get("LOTUS_RPC", "http://127.0.0.1:1234/rpc/v1")
+LOTUS_DATA_DIR = "/lotus_data"
+
+
+def lotus(*args):
+    return subprocess.run(
+        ["docker", "exec", LOTUS_CONTAINER, "lotus", *args],
+        check=True,
+        capture_output=True,
+        text=True,
+    ).stdout.strip()
+
+
+def docker_cp(path, dest):
+    subprocess.run(["docker", "cp", f"{LOTUS_CONTAINER}:{path}", dest], check=True)
+
+
+class Rpc:
+    def __init__(self, url, token):
+        self.url = url
+        self.token = token
+
+    def call(self, method, params):
+        request = urllib.request.Request(
+            self.url,
+            data=json.dumps(
+                {"jsonrpc": "2.0", "id": 1, "method": method, "params": params}
+            ).encode(),
+            headers={
+                "Content-Type": "application/json",
+                "Authorization": f"Bearer {self.token}",
+            },
+        )
+        with urllib.request.urlopen(request) as response:
+            body = json.load(response)
+        if "error" in body:
+            raise RuntimeError(f"{method}: {body['error']}")
+        return body["result"]
+
+
+# Minimal CBOR encoder, for the signed messages that are gossiped.
+def cbor_head(major, n):
+    if n < 24:
+        return bytes([major << 5 | n])
+    for info, size in ((24, 1), (25, 2), (26, 4), (27, 8)):
+        if n < 1 << (8 * size):
+            return bytes([major << 5 | info]) + n.to_bytes(size, "big")
+    raise ValueError(f"{n} doesn't fit in a CBOR head")
+
+
+def cbor_int(n):
+    return cbor_head(0, n) if n >= 0 else cbor_head(1, -1 - n)
+
+
+def cbor_bytes(b):
+    return cbor_head(2, len(b)) + b
+
+
+def cbor_array(items):
+    return cbor_head(4, len(items)) + b"".join(items)
+
+
+def big_int(value):
+    n = int(value)
+    if n == 0:
+        return cbor_bytes(b"")
+    sign = b"\x00" if n > 0 else b"\x01"
+    n = abs(n)
+    return cbor_bytes(sign + n.to_bytes((n.bit_length() + 7) // 8, "big"))
+
+
+def address(text):
+    protocol = int(text[1])
+    if protocol == 0:
+        n = int(text[2:])
+        payload = bytearray()
+        while True:
+            byte = n & 0x7F
+            n >>= 7
+            payload.append(byte | (0x80 if n else 0))
+            if not n:
+                break
+        return cbor_bytes(bytes([0]) + bytes(payload))
+    if protocol in (1, 2, 3):
+        encoded = text[2:].upper()
+        decoded = base64.b32decode(encoded + "=" * (-len(encoded) % 8))
+        # The last 4 bytes are the checksum.
+        return cbor_bytes(bytes([protocol]) + decoded[:-4])
+    raise ValueError(f"unsupported address {text}")
+
+
+def signed_message_cbor(signed):
+    message = signed["Message"]
+    params = base64.b64decode(message.get("Params") or "")
+    signature = signed["Signature"]
+    return cbor_array(
+        [
+            cbor_array(
+                [
+                    cbor_int(message["Version"]),
+                    address(message["To"]),
+                    address(message["From"]),
+                    cbor_int(message["Nonce"]),
+                    big_int(message["Value"]),
+                    cbor_int(message["GasLimit"]),
+                    big_int(message["GasFeeCap"]),
+                    big_int(message["GasPremium"]),
+                    cbor_int(message["Method"]),
+                    cbor_bytes(params),
+                ]
+            ),
+            cbor_bytes(
+                bytes([signature["Type"]]) + base64.b64decode(signature["Data"] or "")
+            ),
+        ]
+    )
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("output", help="directory of the fixture")
+    parser.add_argument("--epoch", type=int, default=30, help="head of the snapshot")
+    parser.add_argument("--messages", type=int, default=3, help="messages to sign")
+    args = parser.parse_args()
+    os.makedirs(args.output, exist_ok=True)
+
+    rpc = Rpc(LOTUS_RPC, lotus("auth", "create-token", "--perm", "admin"))
+    while rpc.call("Filecoin.ChainHead", [])["Height"] < args.epoch:
+        time.sleep(5)
+    head = rpc.call("Filecoin.ChainGetTipSetByHeight", [args.epoch, None])
+    key = head["Cids"]
+
+    docker_cp(f"{LOTUS_DATA_DIR}/devgen.car", os.path.join(args.output, "genesis.car"))
+    lotus("chain", "export", "--tipset", f"@{args.epoch}", "/tmp/chain.car")
+    docker_cp("/tmp/chain.car", os.path.join(args.output, "chain.car"))
+
+    wallet = rpc.call("Filecoin.WalletDefaultAddress", [])
+    nonce = rpc.call("Filecoin.StateGetActor", [wallet, key])["Nonce"]
+    messages = []
+    for i in range(args.messages):
+        message = rpc.call(
+            "Filecoin.GasEstimateMessageGas",
+            [
+                {"To": wallet, "From": wallet, "Nonce": nonce + i, "Value": "1"},
+                {"MaxFee": "0"},
+                key,
+            ],
+        )
+        signed = rpc.call("Filecoin.WalletSignMessage", [wallet, message])
+        messages.append(
+            {
+                "Cid": signed["CID"],
+                "Bytes": base64.b64encode(signed_message_cbor(signed)).decode(),
+            }
+        )
+
+    calls = [
+        ("Filecoin.ChainGetTipSetByHeight", [args.epoch, key]),
+        ("Filecoin.ChainGetTipSet", [key]),
+        ("Filecoin.StateGetActor", [wallet, key]),
+        ("Filecoin.StateMinerPower", ["t01000", key]),
+        ("Filecoin.StateNetworkVersion", [key]),
+    ]
+    scenario = {
+        "Network": rpc.call("Filecoin.StateNetworkName", []),
+        "HeadEpoch": args.epoch,
+        "Messages": messages,
+        "Rpc": [
+            {"Method": method, "Params": params, "Result": rpc.call(method, params)}
+            for method, params in calls
+        ],
+    }
+    with open(os.path.join(args.output, "scenario.json"), "w") as f:
+        json.dump(scenario, f, indent=2)
+    print(f"Fixture written to {args.output}", file=sys.stderr)
+
+
+if __name__ == "__main__":
+    main()
@@ -2,7 +2,10 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 use std::borrow::Cow;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::db::{setting_keys::DRAND_ENTRY_KEY_PREFIX, SettingsStore};
 use crate::shim::clock::ChainEpoch;
 use crate::shim::version::NetworkVersion;
 use crate::utils::net::global_http_client;
@@ -11,9 +14,10 @@ use anyhow::Context;
 use async_trait::async_trait;
 use bls_signatures::{PublicKey, Serialize, Signature};
 use byteorder::{BigEndian, WriteBytesExt};
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use serde::{Deserialize as SerdeDeserialize, Serialize as SerdeSerialize};
 use sha2::Digest;
+use tracing::{debug, warn};
 
 use super::beacon_entries::BeaconEntry;
 
@@ -21,6 +25,13 @@ use super::beacon_entries::BeaconEntry;
 /// `LOTUS_IGNORE_DRAND`
 pub const IGNORE_DRAND_VAR: &str = "IGNORE_DRAND";
 
+/// Timeout of a request to a `Drand` server.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Delay before a failed server is tried again. It doubles with every
+/// consecutive failure, up to [`MAX_RETRY_DELAY`].
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(5);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(5 * 60);
+
 /// Coefficients of the publicly available `Drand` keys.
 /// This is shared by all participants on the `Drand` network.
 #[derive(Clone, Debug, SerdeSerialize, SerdeDeserialize)]
@@ -47,8 +58,8 @@ pub enum DrandNetwork {
 #[derive(Clone)]
 /// Configuration used when initializing a `Drand` beacon.
 pub struct DrandConfig<'a> {
-    /// URL endpoint to send JSON HTTP requests to.
-    pub server: &'static str,
+    /// URL endpoints to send JSON HTTP requests to, in order of preference.
+    pub servers: &'static [&'static str],
     /// Info about the beacon chain, used to verify correctness of endpoint.
     pub chain_info: ChainInfo<'a>,
     /// Network type
@@ -194,10 +205,33 @@ pub struct BeaconEntryJson {
     previous_signature: String,
 }
 
+/// Failures of a `Drand` server. A server that failed is only tried after the
+/// others until its retry time.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct ServerHealth {
+    consecutive_failures: u32,
+    retry_at: Option<Instant>,
+}
+
+/// Key of the entry of `round` of the beacon chain `chain_hash` in the settings
+/// store. The entries seen in the headers of the chain are kept there too, see
+/// [`ChainStore`](crate::chain::ChainStore).
+pub fn drand_entry_key(chain_hash: &str, round: u64) -> String {
+    format!("{DRAND_ENTRY_KEY_PREFIX}{chain_hash}/{round}")
+}
+
 /// `Drand` randomness beacon that can be used to generate randomness for the
 /// Filecoin chain. Primary use is to satisfy the [Beacon] trait.
+///
+/// Entries are fetched from the first healthy server, and the next servers are
+/// tried if it fails. Verified entries are also kept in the settings store, if
+/// any, so they aren't fetched again after a restart.
 pub struct DrandBeacon {
-    url: &'static str,
+    servers: Vec<String>,
+    health: Mutex<Vec<ServerHealth>>,
+    /// Hash of the beacon chain, which namespaces the stored entries.
+    chain_hash: String,
+    store: Option<Arc<dyn SettingsStore + Sync + Send>>,
 
     pub_key: DrandPublic,
     /// Interval between beacons, in seconds.
@@ -218,7 +252,7 @@ impl DrandBeacon {
         let chain_info = &config.chain_info;
 
         if cfg!(debug_assertions) && config.network_type == DrandNetwork::Mainnet {
-            let server = config.server;
+            let server = config.servers[0];
             let remote_chain_info = std::thread::spawn(move || {
                 let rt = tokio::runtime::Runtime::new()?;
                 rt.block_on(async {
@@ -241,7 +275,10 @@ impl DrandBeacon {
         }
 
         Self {
-            url: config.server,
+            servers: config.servers.iter().map(|s| s.to_string()).collect(),
+            health: Mutex::new(vec![ServerHealth::default(); config.servers.len()]),
+            chain_hash: chain_info.hash.to_string(),
+            store: None,
             pub_key: DrandPublic {
                 coefficient: hex::decode(chain_info.public_key.as_ref())
                     .expect("invalid static encoding of drand hex public key"),
@@ -253,6 +290,111 @@ impl DrandBeacon {
             local_cache: Default::default(),
         }
     }
+
+    /// Replaces the servers of the configuration.
+    pub fn with_servers(mut self, servers: Vec<String>) -> Self {
+        assert!(!servers.is_empty(), "at least one drand server is required");
+        self.health = Mutex::new(vec![ServerHealth::default(); servers.len()]);
+        self.servers = servers;
+        self
+    }
+
+    /// Keeps the verified entries in `store`.
+    pub fn with_store(mut self, store: Arc<dyn SettingsStore + Sync + Send>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    fn entry_key(&self, round: u64) -> String {
+        drand_entry_key(&self.chain_hash, round)
+    }
+
+    fn stored_entry(&self, round: u64) -> anyhow::Result<Option<BeaconEntry>> {
+        let Some(store) = &self.store else {
+            return Ok(None);
+        };
+        match store.read_bin(&self.entry_key(round))? {
+            Some(bytes) => Ok(Some(fvm_ipld_encoding::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn store_entry(&self, entry: &BeaconEntry) -> anyhow::Result<()> {
+        if let Some(store) = &self.store {
+            store.write_bin(
+                &self.entry_key(entry.round()),
+                &fvm_ipld_encoding::to_vec(entry)?,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Returns the indices of the servers in the order they should be tried:
+    /// the healthy servers in order of preference, then the others by retry
+    /// time.
+    fn server_order(&self, now: Instant) -> Vec<usize> {
+        let health = self.health.lock();
+        let (mut order, mut failed): (Vec<usize>, Vec<usize>) = (0..self.servers.len())
+            .partition(|&i| health[i].retry_at.map_or(true, |retry_at| retry_at <= now));
+        failed.sort_by_key(|&i| health[i].retry_at);
+        order.extend(failed);
+        order
+    }
+
+    fn record_success(&self, server: usize) {
+        self.health.lock()[server] = ServerHealth::default();
+    }
+
+    fn record_failure(&self, server: usize, now: Instant) {
+        let mut health = self.health.lock();
+        let health = &mut health[server];
+        let delay = MIN_RETRY_DELAY
+            .saturating_mul(1 << health.consecutive_failures.min(16))
+            .min(MAX_RETRY_DELAY);
+        health.consecutive_failures += 1;
+        health.retry_at = Some(now + delay);
+    }
+
+    /// Fetches an entry, failing over to the next servers when a server can't
+    /// be reached or returns a server error.
+    async fn fetch_entry(&self, round: u64) -> anyhow::Result<BeaconEntry> {
+        let mut errors = vec![];
+        for i in self.server_order(Instant::now()) {
+            let server = &self.servers[i];
+            match fetch_entry_from(server, round).await {
+                Ok(resp) => {
+                    self.record_success(i);
+                    return Ok(BeaconEntry::new(resp.round, hex::decode(resp.signature)?));
+                }
+                // The server works, but doesn't have the round.
+                Err(e) if e.status().map_or(false, |s| s.is_client_error()) => {
+                    self.record_success(i);
+                    return Err(e.into());
+                }
+                Err(e) => {
+                    debug!("Failed to fetch beacon entry {round} from {server}: {e}");
+                    self.record_failure(i, Instant::now());
+                    errors.push(format!("{server}: {e}"));
+                }
+            }
+        }
+        warn!("All drand servers failed to return beacon entry {round}");
+        anyhow::bail!(
+            "failed to fetch beacon entry {round}: {}",
+            errors.join(", ")
+        )
+    }
+}
+
+async fn fetch_entry_from(server: &str, round: u64) -> Result<BeaconEntryJson, reqwest::Error> {
+    global_http_client()
+        .get(format!("{server}/public/{round}"))
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
 }
 
 #[async_trait]
@@ -277,26 +419,23 @@ impl Beacon for DrandBeacon {
         let contains_curr = self.local_cache.read().contains_key(&curr.round());
         if sig_match && !contains_curr {
             self.local_cache.write().insert(curr.round(), curr.clone());
+            if let Err(e) = self.store_entry(curr) {
+                warn!("Failed to store beacon entry {}: {e}", curr.round());
+            }
         }
         Ok(sig_match)
     }
 
     async fn entry(&self, round: u64) -> Result<BeaconEntry, anyhow::Error> {
         let cached: Option<BeaconEntry> = self.local_cache.read().get(&round).cloned();
-        match cached {
-            Some(cached_entry) => Ok(cached_entry),
-            None => {
-                let client = global_http_client();
-                let resp: BeaconEntryJson = client
-                    .get(format!("{}/public/{}", self.url, round))
-                    .send()
-                    .await?
-                    .error_for_status()?
-                    .json()
-                    .await?;
-                Ok(BeaconEntry::new(resp.round, hex::decode(resp.signature)?))
-            }
+        if let Some(cached_entry) = cached {
+            return Ok(cached_entry);
+        }
+        if let Some(stored) = self.stored_entry(round)? {
+            self.local_cache.write().insert(round, stored.clone());
+            return Ok(stored);
         }
+        self.fetch_entry(round).await
     }
 
     fn max_beacon_round_for_epoch(
@@ -322,3 +461,59 @@ impl Beacon for DrandBeacon {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MemoryDB;
+
+    fn new_beacon(servers: &[&str]) -> DrandBeacon {
+        DrandBeacon::new(
+            15904451751,
+            25,
+            &DrandConfig {
+                servers: &["http://localhost"],
+                chain_info: ChainInfo {
+                    public_key: "922a2e93828ff83345bae533f5172669a26c02dc76d6bf59c80892e12ab1455c229211886f35bb56af6d5bea981024df"
+                        .into(),
+                    hash: "beacon".into(),
+                    ..Default::default()
+                },
+                network_type: DrandNetwork::Incentinet,
+            },
+        )
+        .with_servers(servers.iter().map(|s| s.to_string()).collect())
+    }
+
+    #[test]
+    fn failed_servers_are_tried_last() {
+        let beacon = new_beacon(&["a", "b", "c"]);
+        let now = Instant::now();
+        assert_eq!(beacon.server_order(now), [0, 1, 2]);
+
+        beacon.record_failure(0, now);
+        beacon.record_failure(1, now - Duration::from_secs(1));
+        assert_eq!(beacon.server_order(now), [2, 1, 0]);
+        // Failed servers are tried again after their retry delay.
+        assert_eq!(beacon.server_order(now + MIN_RETRY_DELAY), [0, 1, 2]);
+
+        // The delay grows with consecutive failures.
+        beacon.record_failure(0, now);
+        assert_eq!(beacon.server_order(now + MIN_RETRY_DELAY), [1, 2, 0]);
+        beacon.record_success(0);
+        assert_eq!(beacon.server_order(now), [0, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn stored_entries_are_not_fetched() {
+        let store = Arc::new(MemoryDB::default());
+        let beacon = new_beacon(&["http://localhost:0"]).with_store(store.clone());
+        let entry = BeaconEntry::new(10, vec![1; 96]);
+        beacon.store_entry(&entry).unwrap();
+
+        // A restarted node gets the entry from the store.
+        let beacon = new_beacon(&["http://localhost:0"]).with_store(store);
+        assert_eq!(beacon.entry(10).await.unwrap(), entry);
+        assert!(beacon.entry(11).await.is_err());
+    }
+}
@@ -10,7 +10,7 @@ fn new_beacon() -> DrandBeacon {
         25,
         // TODO this could maybe be referencing existing config
         &DrandConfig {
-            server: "https://pl-us.incentinet.drand.sh",
+            servers: &["https://pl-us.incentinet.drand.sh"],
             chain_info: ChainInfo {
                 public_key: "922a2e93828ff83345bae533f5172669a26c02dc76d6bf59c80892e12ab1455c229211886f35bb56af6d5bea981024df"
                     .into(),
@@ -0,0 +1,220 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Progress of the chain exports, for UIs and orchestrators.
+//!
+//! [`export`](super::export) reports the tipsets it walks, the blocks it
+//! writes and the size of the compressed frames to the tracker of the export,
+//! so that concurrent exports don't mix their progress. The RPC runs one export
+//! at a time and reports to [`RPC_EXPORT_PROGRESS`], where a new export
+//! replaces the progress of the previous one. The scheduled snapshots have
+//! their own tracker.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::time::Instant;
+
+use crate::shim::clock::ChainEpoch;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+lazy_static! {
+    /// Progress of the exports started over the RPC.
+    pub static ref RPC_EXPORT_PROGRESS: Arc<ExportProgressTracker> = Default::default();
+}
+
+/// Progress of the running or last chain export.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ExportProgress {
+    /// Sequence number of the export since the node started.
+    pub id: u64,
+    /// Epoch of the exported tipset.
+    pub epoch: ChainEpoch,
+    /// Oldest epoch whose state tree is exported. Only block headers are
+    /// exported below.
+    pub stateroot_limit: ChainEpoch,
+    /// Epoch of the tipset being walked, from `epoch` down to genesis.
+    pub current_epoch: ChainEpoch,
+    /// Number of blocks written.
+    pub records: u64,
+    /// Number of compressed bytes written.
+    pub bytes_written: u64,
+    pub elapsed_secs: f64,
+    /// Estimated remaining time, once some epochs have been walked.
+    pub eta_secs: Option<f64>,
+    pub finished: bool,
+    /// Error of a failed export.
+    pub error: Option<String>,
+}
+
+#[derive(Debug)]
+struct Run {
+    id: u64,
+    epoch: ChainEpoch,
+    stateroot_limit: ChainEpoch,
+    current_epoch: ChainEpoch,
+    started: Instant,
+    /// Time spent on the epochs with state trees, once they are walked.
+    state_phase_secs: Option<f64>,
+    /// Duration and error of the export, once finished.
+    finished: Option<(f64, Option<String>)>,
+}
+
+#[derive(Debug, Default)]
+pub struct ExportProgressTracker {
+    records: AtomicU64,
+    bytes_written: AtomicU64,
+    run: Mutex<Option<Run>>,
+}
+
+impl ExportProgressTracker {
+    pub(super) fn start(&self, epoch: ChainEpoch, stateroot_limit: ChainEpoch) {
+        let mut run = self.run.lock();
+        self.records.store(0, Ordering::Relaxed);
+        self.bytes_written.store(0, Ordering::Relaxed);
+        *run = Some(Run {
+            id: run.as_ref().map_or(1, |run| run.id + 1),
+            epoch,
+            stateroot_limit,
+            current_epoch: epoch,
+            started: Instant::now(),
+            state_phase_secs: None,
+            finished: None,
+        });
+    }
+
+    pub(super) fn record_tipset(&self, epoch: ChainEpoch) {
+        if let Some(run) = self.run.lock().as_mut() {
+            run.current_epoch = epoch;
+            if epoch < run.stateroot_limit && run.state_phase_secs.is_none() {
+                run.state_phase_secs = Some(run.started.elapsed().as_secs_f64());
+            }
+        }
+    }
+
+    pub(super) fn record_block(&self) {
+        self.records.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn record_bytes(&self, bytes: usize) {
+        self.bytes_written
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(super) fn finish(&self, error: Option<String>) {
+        if let Some(run) = self.run.lock().as_mut() {
+            run.finished = Some((run.started.elapsed().as_secs_f64(), error));
+        }
+    }
+
+    /// Returns the progress of the running export, or of the last one if
+    /// none is running.
+    pub fn progress(&self) -> Option<ExportProgress> {
+        let run = self.run.lock();
+        let run = run.as_ref()?;
+        let (elapsed_secs, eta_secs, error) = match &run.finished {
+            Some((elapsed_secs, error)) => (*elapsed_secs, None, error.clone()),
+            None => {
+                let elapsed_secs = run.started.elapsed().as_secs_f64();
+                let eta_secs = estimate_eta(
+                    run.epoch,
+                    run.stateroot_limit,
+                    run.current_epoch,
+                    elapsed_secs,
+                    run.state_phase_secs,
+                );
+                (elapsed_secs, eta_secs, None)
+            }
+        };
+        Some(ExportProgress {
+            id: run.id,
+            epoch: run.epoch,
+            stateroot_limit: run.stateroot_limit,
+            current_epoch: run.current_epoch,
+            records: self.records.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            elapsed_secs,
+            eta_secs,
+            finished: run.finished.is_some(),
+            error,
+        })
+    }
+}
+
+/// The state trees make most of an export, so while they are walked the
+/// remaining time is extrapolated from the epochs with state trees, leaving
+/// out the walk of the older block headers. Afterwards, it is extrapolated
+/// from the walk of the headers down to genesis.
+fn estimate_eta(
+    epoch: ChainEpoch,
+    stateroot_limit: ChainEpoch,
+    current_epoch: ChainEpoch,
+    elapsed_secs: f64,
+    state_phase_secs: Option<f64>,
+) -> Option<f64> {
+    let (walked, remaining, secs) = match state_phase_secs {
+        None => (
+            epoch - current_epoch,
+            current_epoch - stateroot_limit.max(0),
+            elapsed_secs,
+        ),
+        Some(state_phase_secs) => (
+            stateroot_limit - current_epoch,
+            current_epoch,
+            elapsed_secs - state_phase_secs,
+        ),
+    };
+    (walked > 0).then(|| secs * remaining.max(0) as f64 / walked as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eta_of_both_phases() {
+        // Nothing walked yet.
+        assert_eq!(estimate_eta(1000, 100, 1000, 5.0, None), None);
+        // 100 of the 900 epochs with state trees in 10s.
+        assert_eq!(estimate_eta(1000, 100, 900, 10.0, None), Some(80.0));
+        // The block headers took 5s for 50 epochs after 90s of state trees.
+        assert_eq!(estimate_eta(1000, 100, 50, 95.0, Some(90.0)), Some(5.0));
+        // The whole chain has state trees.
+        assert_eq!(estimate_eta(1000, -10, 0, 10.0, None), Some(0.0));
+    }
+
+    #[test]
+    fn track_exports() {
+        let tracker = ExportProgressTracker::default();
+        assert_eq!(tracker.progress(), None);
+
+        tracker.start(1000, 100);
+        tracker.record_tipset(1000);
+        tracker.record_block();
+        tracker.record_block();
+        tracker.record_bytes(42);
+        tracker.record_tipset(50);
+        let progress = tracker.progress().unwrap();
+        assert_eq!(progress.id, 1);
+        assert_eq!(progress.current_epoch, 50);
+        assert_eq!((progress.records, progress.bytes_written), (2, 42));
+        assert!(!progress.finished);
+
+        tracker.finish(Some("disk full".into()));
+        let progress = tracker.progress().unwrap();
+        assert!(progress.finished);
+        assert_eq!(progress.eta_secs, None);
+        assert_eq!(progress.error.as_deref(), Some("disk full"));
+
+        // A new export resets the counters.
+        tracker.start(2000, 1100);
+        let progress = tracker.progress().unwrap();
+        assert_eq!(progress.id, 2);
+        assert_eq!(progress.records, 0);
+        assert!(!progress.finished);
+    }
+}
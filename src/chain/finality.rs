@@ -0,0 +1,347 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Light validation of finality certificates.
+//!
+//! A [`FinalityCertificate`] states that the storage providers listed as
+//! signers agree that a tipset is final. It's accepted when the signers hold
+//! more than two thirds of the quality-adjusted power in the parent state of
+//! the tipset, and their aggregated BLS signature is valid. The tipset is then
+//! marked as finalized in the [`ChainStore`](super::ChainStore), which refuses
+//! to switch to a head that isn't on its chain.
+//!
+//! The certificate format is specific to Forest: it is neither the F3
+//! (`go-f3`) certificate format nor compatible with it, and certificates
+//! are only exchanged between Forest nodes.
+//!
+//! Certificates are read from a [`CertificateSource`], so that they can come
+//! from a network protocol as well as be imported over RPC, see
+//! [`RpcCertificateSource`].
+
+use crate::blocks::{Tipset, TipsetKeys};
+use crate::shim::address::{Address, Payload};
+use crate::shim::clock::ChainEpoch;
+use crate::shim::crypto::{verify_bls_aggregate, Signature};
+use crate::shim::sector::StoragePower;
+use crate::state_manager::StateManager;
+use ahash::{HashSet, HashSetExt};
+use anyhow::{ensure, Context as _};
+use async_trait::async_trait;
+use cid::Cid;
+use fil_actor_interface::power;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_shared3::ActorID;
+use num_traits::Zero;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Domain separation tag of the signed payloads of Forest finality
+/// certificates.
+const SIGNING_TAG: &str = "forest/finality";
+
+/// A Forest finality certificate, see the [module](self) documentation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct FinalityCertificate {
+    pub epoch: ChainEpoch,
+    #[serde(with = "crate::lotus_json")]
+    pub tipset: TipsetKeys,
+    /// Miner actors whose signatures are aggregated in `signature`.
+    pub signers: Vec<ActorID>,
+    /// Aggregate of the BLS signatures of the signers' workers, each over
+    /// [`FinalityCertificate::signing_payload`].
+    #[serde(with = "crate::lotus_json")]
+    pub signature: Signature,
+}
+
+/// A signer of finality certificates, see [`PowerTable`].
+#[derive(Debug, Clone)]
+pub struct Participant {
+    pub power: StoragePower,
+    /// BLS public key of the miner's worker.
+    pub public_key: Vec<u8>,
+}
+
+/// Power distribution against which certificates are checked.
+pub trait PowerTable {
+    fn total_power(&self) -> anyhow::Result<StoragePower>;
+
+    /// Returns `None` if the miner can't sign certificates, e.g. because it has
+    /// no power.
+    fn participant(&self, miner: ActorID) -> anyhow::Result<Option<Participant>>;
+}
+
+impl FinalityCertificate {
+    /// Payload signed by `signer`. It includes the signer, as the aggregated
+    /// signatures have to be over distinct messages.
+    pub fn signing_payload(&self, signer: ActorID) -> Vec<u8> {
+        fvm_ipld_encoding::to_vec(&(SIGNING_TAG, self.epoch, &self.tipset, signer))
+            .expect("infallible")
+    }
+
+    /// Checks that the signers hold a quorum of the power and that the
+    /// signature is valid.
+    pub fn verify(&self, power_table: &impl PowerTable) -> anyhow::Result<()> {
+        let mut seen = HashSet::with_capacity(self.signers.len());
+        let mut signed_power = StoragePower::zero();
+        let mut public_keys = Vec::with_capacity(self.signers.len());
+        for &signer in &self.signers {
+            ensure!(seen.insert(signer), "duplicate signer f0{signer}");
+            let participant = power_table
+                .participant(signer)?
+                .with_context(|| format!("f0{signer} can't sign finality certificates"))?;
+            signed_power += participant.power;
+            public_keys.push(participant.public_key);
+        }
+
+        let total_power = power_table.total_power()?;
+        ensure!(
+            signed_power.clone() * 3 > total_power.clone() * 2,
+            "signers hold {signed_power} of {total_power} power, short of a quorum"
+        );
+
+        let payloads: Vec<_> = self
+            .signers
+            .iter()
+            .map(|&signer| self.signing_payload(signer))
+            .collect();
+        let payloads: Vec<_> = payloads.iter().map(Vec::as_slice).collect();
+        let public_keys: Vec<_> = public_keys.iter().map(Vec::as_slice).collect();
+        ensure!(
+            verify_bls_aggregate(&payloads, &public_keys, &self.signature),
+            "invalid aggregate signature"
+        );
+        Ok(())
+    }
+}
+
+/// [`PowerTable`] of the power actor in a state tree.
+pub struct StatePowerTable<'a, DB> {
+    state_manager: &'a StateManager<DB>,
+    state_root: Cid,
+}
+
+impl<'a, DB: Blockstore> StatePowerTable<'a, DB> {
+    pub fn new(state_manager: &'a StateManager<DB>, state_root: Cid) -> Self {
+        Self {
+            state_manager,
+            state_root,
+        }
+    }
+
+    fn power_state(&self) -> anyhow::Result<power::State> {
+        let actor = self
+            .state_manager
+            .get_actor(&Address::POWER_ACTOR, self.state_root)?
+            .context("power actor not found")?;
+        power::State::load(self.state_manager.blockstore(), actor.code, actor.state)
+    }
+}
+
+impl<DB: Blockstore> PowerTable for StatePowerTable<'_, DB> {
+    fn total_power(&self) -> anyhow::Result<StoragePower> {
+        Ok(self.power_state()?.total_power().quality_adj_power)
+    }
+
+    fn participant(&self, miner: ActorID) -> anyhow::Result<Option<Participant>> {
+        let miner = Address::new_id(miner);
+        let claim = self
+            .power_state()?
+            .miner_power(self.state_manager.blockstore(), &miner.into())?;
+        let Some(power) = claim.map(|claim| claim.quality_adj_power) else {
+            return Ok(None);
+        };
+        if power.is_zero() {
+            return Ok(None);
+        }
+        let worker = self
+            .state_manager
+            .get_miner_work_addr(self.state_root, &miner)?;
+        Ok(match worker.into_payload() {
+            Payload::BLS(key) => Some(Participant {
+                power,
+                public_key: key.to_vec(),
+            }),
+            _ => None,
+        })
+    }
+}
+
+/// Verifies `certificate` against the parent state of its tipset, and marks
+/// the tipset as finalized.
+pub fn import<DB: Blockstore>(
+    state_manager: &StateManager<DB>,
+    certificate: &FinalityCertificate,
+) -> anyhow::Result<Arc<Tipset>> {
+    let chain_store = state_manager.chain_store();
+    let tipset = chain_store.chain_index.load_tipset(&certificate.tipset)?;
+    ensure!(
+        tipset.epoch() == certificate.epoch,
+        "tipset {} is at epoch {}, not {}",
+        certificate.tipset,
+        tipset.epoch(),
+        certificate.epoch
+    );
+    certificate.verify(&StatePowerTable::new(state_manager, *tipset.parent_state()))?;
+    chain_store.mark_finalized(Arc::clone(&tipset))?;
+    Ok(tipset)
+}
+
+/// Where finality certificates come from.
+#[async_trait]
+pub trait CertificateSource: Send {
+    /// Returns the next certificate, or `None` once the source is closed.
+    async fn next(&mut self) -> Option<FinalityCertificate>;
+
+    /// Reports the outcome of the import of the certificate last returned by
+    /// [`CertificateSource::next`].
+    fn imported(&mut self, _result: &anyhow::Result<()>) {}
+}
+
+/// A certificate submitted over RPC, with the channel its import result is
+/// sent back on.
+pub type CertificateImport = (FinalityCertificate, flume::Sender<anyhow::Result<()>>);
+
+/// Certificates imported with `Forest.ChainImportFinalityCertificate`.
+pub struct RpcCertificateSource {
+    rx: flume::Receiver<CertificateImport>,
+    reply: Option<flume::Sender<anyhow::Result<()>>>,
+}
+
+impl RpcCertificateSource {
+    pub fn new(rx: flume::Receiver<CertificateImport>) -> Self {
+        Self { rx, reply: None }
+    }
+}
+
+#[async_trait]
+impl CertificateSource for RpcCertificateSource {
+    async fn next(&mut self) -> Option<FinalityCertificate> {
+        let (certificate, reply) = self.rx.recv_async().await.ok()?;
+        self.reply = Some(reply);
+        Some(certificate)
+    }
+
+    fn imported(&mut self, result: &anyhow::Result<()>) {
+        if let Some(reply) = self.reply.take() {
+            let result = match result {
+                Ok(()) => Ok(()),
+                Err(e) => Err(anyhow::anyhow!("{e:#}")),
+            };
+            // The caller may have gone away.
+            let _ = reply.send(result);
+        }
+    }
+}
+
+/// Imports the certificates of `source` until it's closed.
+pub async fn run<DB>(
+    mut source: impl CertificateSource,
+    state_manager: Arc<StateManager<DB>>,
+) -> anyhow::Result<()>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    while let Some(certificate) = source.next().await {
+        let result = match import(&state_manager, &certificate) {
+            Ok(tipset) => {
+                info!(
+                    "Finalized tipset {} (EPOCH = {})",
+                    tipset.key(),
+                    tipset.epoch()
+                );
+                Ok(())
+            }
+            Err(e) => {
+                warn!(
+                    "Rejected finality certificate for epoch {}: {e:#}",
+                    certificate.epoch
+                );
+                Err(e)
+            }
+        };
+        source.imported(&result);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipld::FrozenCids;
+    use ahash::HashMap;
+    use bls_signatures::Serialize as _;
+
+    struct MapPowerTable {
+        participants: HashMap<ActorID, (bls_signatures::PrivateKey, u64)>,
+    }
+
+    impl PowerTable for MapPowerTable {
+        fn total_power(&self) -> anyhow::Result<StoragePower> {
+            Ok(self
+                .participants
+                .values()
+                .map(|(_, power)| power)
+                .sum::<u64>()
+                .into())
+        }
+
+        fn participant(&self, miner: ActorID) -> anyhow::Result<Option<Participant>> {
+            Ok(self
+                .participants
+                .get(&miner)
+                .map(|(key, power)| Participant {
+                    power: (*power).into(),
+                    public_key: key.public_key().as_bytes(),
+                }))
+        }
+    }
+
+    fn certificate(table: &MapPowerTable, signers: &[ActorID]) -> FinalityCertificate {
+        let mut certificate = FinalityCertificate {
+            epoch: 10,
+            tipset: TipsetKeys::new(FrozenCids::from_iter([Cid::default()])),
+            signers: signers.to_vec(),
+            signature: Signature::new_bls(vec![]),
+        };
+        let signatures: Vec<_> = signers
+            .iter()
+            .map(|signer| {
+                table.participants[signer]
+                    .0
+                    .sign(certificate.signing_payload(*signer))
+            })
+            .collect();
+        certificate.signature =
+            Signature::new_bls(bls_signatures::aggregate(&signatures).unwrap().as_bytes());
+        certificate
+    }
+
+    #[test]
+    fn certificates_need_a_quorum_and_valid_signatures() {
+        let mut rng = rand::thread_rng();
+        let table = MapPowerTable {
+            participants: [(1, 40), (2, 30), (3, 30)]
+                .into_iter()
+                .map(|(id, power)| (id, (bls_signatures::PrivateKey::generate(&mut rng), power)))
+                .collect(),
+        };
+
+        certificate(&table, &[1, 2]).verify(&table).unwrap();
+        // 60% of the power isn't a quorum.
+        certificate(&table, &[2, 3]).verify(&table).unwrap_err();
+        certificate(&table, &[1, 1, 2]).verify(&table).unwrap_err();
+
+        let mut tampered = certificate(&table, &[1, 2]);
+        tampered.epoch += 1;
+        tampered.verify(&table).unwrap_err();
+        let mut tampered = certificate(&table, &[1, 2]);
+        tampered.signers = vec![1, 2, 3];
+        tampered.verify(&table).unwrap_err();
+        // Unknown signers are refused.
+        let mut tampered = certificate(&table, &[1, 2]);
+        tampered.signers = vec![1, 2, 4];
+        tampered.verify(&table).unwrap_err();
+    }
+}
@@ -0,0 +1,232 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Compact proofs that a message is included in a tipset.
+//!
+//! A proof holds the blocks linking a block header of the tipset to the
+//! message CID: the header, the [`TxMeta`] of its messages, and the nodes of
+//! the message AMT on the path to the message. Given the tipset key from a
+//! trusted source, [`verify_inclusion`] checks the proof without a node: every
+//! block is checked against its CID, so the path can't be forged, and the
+//! message is looked up in the AMT from the root referenced by the header.
+
+use crate::blocks::{BlockHeader, Tipset, TipsetKeys, TxMeta};
+use crate::db::MemoryDB;
+use crate::utils::db::car_stream::Block;
+use anyhow::Context as _;
+use cid::Cid;
+use fvm_ipld_amt::Amtv0 as Amt;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::CborStore;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct InclusionProof {
+    #[serde(with = "crate::lotus_json")]
+    pub message: Cid,
+    /// Header of the block that includes the message.
+    #[serde(with = "crate::lotus_json")]
+    pub block: Cid,
+    /// Whether the message is in the AMT of the `secp256k1` signed messages
+    /// of the block, rather than in the one of the BLS messages.
+    pub secp: bool,
+    /// Index of the message in the AMT.
+    pub index: u64,
+    /// The header, the message roots, then the AMT nodes from the root to the
+    /// message.
+    pub blocks: Vec<ProofBlock>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ProofBlock {
+    #[serde(with = "crate::lotus_json")]
+    pub cid: Cid,
+    #[serde(with = "crate::lotus_json")]
+    pub data: Vec<u8>,
+}
+
+/// Blockstore that records the blocks read from the inner store.
+struct RecordingBlockstore<'a, DB> {
+    inner: &'a DB,
+    read: Mutex<Vec<ProofBlock>>,
+}
+
+impl<DB: Blockstore> Blockstore for RecordingBlockstore<'_, DB> {
+    fn get(&self, k: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
+        let data = self.inner.get(k)?;
+        if let Some(data) = &data {
+            self.read.lock().push(ProofBlock {
+                cid: *k,
+                data: data.clone(),
+            });
+        }
+        Ok(data)
+    }
+
+    fn put_keyed(&self, _: &Cid, _: &[u8]) -> anyhow::Result<()> {
+        anyhow::bail!("the recording blockstore is read-only")
+    }
+}
+
+/// Returns the proof that `message` is included in `tipset`, through the
+/// first block that includes it.
+pub fn prove_inclusion(
+    db: &impl Blockstore,
+    tipset: &Tipset,
+    message: &Cid,
+) -> anyhow::Result<InclusionProof> {
+    for header in tipset.blocks() {
+        let meta: TxMeta = db
+            .get_cbor(header.messages())?
+            .with_context(|| format!("message root {} not found", header.messages()))?;
+        for (secp, root) in [
+            (false, meta.bls_message_root),
+            (true, meta.secp_message_root),
+        ] {
+            let Some(index) = find_index(db, &root, message)? else {
+                continue;
+            };
+            let mut blocks = vec![];
+            for cid in [*header.cid(), *header.messages()] {
+                let data = db
+                    .get(&cid)?
+                    .with_context(|| format!("block {cid} not found"))?;
+                blocks.push(ProofBlock { cid, data });
+            }
+            // A fresh AMT only loads the nodes on the path to the index.
+            let recorder = RecordingBlockstore {
+                inner: db,
+                read: Mutex::default(),
+            };
+            Amt::<Cid, _>::load(&root, &recorder)?.get(index)?;
+            blocks.extend(recorder.read.into_inner());
+            return Ok(InclusionProof {
+                message: *message,
+                block: *header.cid(),
+                secp,
+                index,
+                blocks,
+            });
+        }
+    }
+    anyhow::bail!(
+        "message {message} isn't included in the tipset at epoch {}",
+        tipset.epoch()
+    )
+}
+
+fn find_index(db: &impl Blockstore, root: &Cid, message: &Cid) -> anyhow::Result<Option<u64>> {
+    let mut found = None;
+    Amt::<Cid, _>::load(root, db)?.for_each_while(|index, cid| {
+        if cid == message {
+            found = Some(index);
+        }
+        Ok(found.is_none())
+    })?;
+    Ok(found)
+}
+
+/// Checks that `proof` proves the inclusion of its message in the tipset
+/// with the key `tipset`.
+pub fn verify_inclusion(proof: &InclusionProof, tipset: &TipsetKeys) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        Vec::<Cid>::from(&tipset.cids).contains(&proof.block),
+        "block {} isn't in the tipset",
+        proof.block
+    );
+    let db = MemoryDB::default();
+    for ProofBlock { cid, data } in &proof.blocks {
+        let block = Block {
+            cid: *cid,
+            data: data.clone(),
+        };
+        anyhow::ensure!(block.valid(), "block {cid} doesn't match its CID");
+        db.put_keyed(cid, data)?;
+    }
+
+    let header: BlockHeader = db
+        .get_cbor(&proof.block)?
+        .context("the proof has no block header")?;
+    let meta: TxMeta = db
+        .get_cbor(header.messages())?
+        .context("the proof has no message root")?;
+    let root = if proof.secp {
+        meta.secp_message_root
+    } else {
+        meta.bls_message_root
+    };
+    let amt = Amt::<Cid, _>::load(&root, &db).context("the proof has no AMT root")?;
+    match amt.get(proof.index) {
+        Ok(Some(cid)) if *cid == proof.message => Ok(()),
+        Ok(Some(cid)) => anyhow::bail!(
+            "the message at index {} is {cid}, not {}",
+            proof.index,
+            proof.message
+        ),
+        Ok(None) => anyhow::bail!("there is no message at index {}", proof.index),
+        Err(e) => anyhow::bail!("the proof is missing AMT nodes: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shim::address::Address;
+    use crate::utils::db::CborStoreExt;
+
+    /// Returns a tipset with a block including `count` BLS messages, using a
+    /// multi-level AMT.
+    fn tipset(db: &MemoryDB, count: u64) -> (Tipset, Vec<Cid>) {
+        let messages: Vec<Cid> = (0..count)
+            .map(|i| db.put_cbor_default(&("message", i)).unwrap())
+            .collect();
+        let bls_message_root = Amt::new_from_iter(db, messages.iter().copied()).unwrap();
+        let secp_message_root = Amt::<Cid, _>::new(db).flush().unwrap();
+        let meta = db
+            .put_cbor_default(&TxMeta {
+                bls_message_root,
+                secp_message_root,
+            })
+            .unwrap();
+        let header = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .messages(meta)
+            .build()
+            .unwrap();
+        db.put_cbor_default(&header).unwrap();
+        (Tipset::from(header), messages)
+    }
+
+    #[test]
+    fn prove_and_verify() {
+        let db = MemoryDB::default();
+        let (tipset, messages) = tipset(&db, 100);
+        let proof = prove_inclusion(&db, &tipset, &messages[70]).unwrap();
+        assert_eq!(proof.index, 70);
+        assert!(!proof.secp);
+        // The proof is a path, not the whole AMT.
+        assert!(proof.blocks.len() < 10);
+        verify_inclusion(&proof, tipset.key()).unwrap();
+
+        let other = TipsetKeys::from(vec![Cid::default()]);
+        assert!(verify_inclusion(&proof, &other).is_err());
+
+        let mut wrong_message = proof.clone();
+        wrong_message.message = messages[71];
+        assert!(verify_inclusion(&wrong_message, tipset.key()).is_err());
+
+        let mut tampered = proof.clone();
+        tampered.blocks.last_mut().unwrap().data[0] ^= 1;
+        assert!(verify_inclusion(&tampered, tipset.key()).is_err());
+
+        let mut truncated = proof;
+        truncated.blocks.pop();
+        assert!(verify_inclusion(&truncated, tipset.key()).is_err());
+
+        let missing = db.put_cbor_default(&"not included").unwrap();
+        assert!(prove_inclusion(&db, &tipset, &missing).is_err());
+    }
+}
@@ -1,21 +1,91 @@
 // Copyright 2019-2023 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
+pub mod export_progress;
+pub mod finality;
+pub mod inclusion_proof;
 pub mod store;
 mod weight;
+use self::export_progress::ExportProgressTracker;
 use crate::blocks::Tipset;
 use crate::db::car::forest;
-use crate::ipld::{stream_chain, CidHashSet};
-use crate::utils::io::{AsyncWriterWithChecksum, Checksum};
+use crate::ipld::{diff::shared_blocks, stream_chain, CidHashSet};
+use crate::utils::io::{scheduler::IO_SCHEDULER, AsyncWriterWithChecksum, Checksum};
 use crate::utils::stream::par_buffer;
 use anyhow::{Context, Result};
 use cid::Cid;
 use digest::Digest;
+use futures::TryStreamExt;
 use fvm_ipld_blockstore::Blockstore;
 use std::sync::Arc;
 use tokio::io::{AsyncWrite, AsyncWriteExt, BufWriter};
+use tracing::warn;
 
 pub use self::{store::*, weight::*};
 
+/// Returns the CIDs of the blocks of a snapshot exported from `base` with the
+/// state trees of the last `lookup_depth` epochs, or all of them if `None`,
+/// that the export of `tipset` may link to. Passed as `seen` to [`export`],
+/// they make a diff snapshot that only has the blocks added since `base`, and
+/// applies on top of that snapshot.
+///
+/// The state trees of `base` aren't walked: the blocks they share with the
+/// state of the first exported tipset after `base` are found with
+/// [`shared_blocks`], so the cost depends on the size of the changes. Blocks
+/// of `base` that only reappear in later state trees are exported again.
+pub fn diff_seen(
+    db: &impl Blockstore,
+    base: &Tipset,
+    lookup_depth: Option<ChainEpochDelta>,
+    tipset: &Tipset,
+) -> CidHashSet {
+    let stateroot_lookup_limit = lookup_depth.map_or(0, |depth| base.epoch() - depth);
+    let mut seen = CidHashSet::default();
+    // The roots of the graphs `stream_chain` walks for `base`.
+    for ts in base.clone().chain(db) {
+        for block in ts.blocks() {
+            seen.insert(*block.cid());
+            if block.epoch() == 0 {
+                for parent in &block.parents().cids {
+                    seen.insert(parent);
+                }
+            }
+            if block.epoch() > stateroot_lookup_limit {
+                seen.insert(*block.messages());
+            }
+            if block.epoch() == 0 || block.epoch() > stateroot_lookup_limit {
+                seen.insert(*block.state_root());
+            }
+        }
+    }
+    if base.epoch() > stateroot_lookup_limit {
+        let next = tipset
+            .clone()
+            .chain(db)
+            .take_while(|ts| ts.epoch() > base.epoch())
+            .last();
+        if let Some(next) = next {
+            if let Err(e) =
+                shared_blocks(db, base.parent_state(), next.parent_state(), &mut |cid| {
+                    seen.insert(cid);
+                })
+            {
+                warn!(
+                    "Failed to compare the states of epochs {} and {}, exporting more blocks: {e}",
+                    base.epoch(),
+                    next.epoch()
+                );
+            }
+        }
+    }
+    seen
+}
+
+/// Exports the chain from `tipset` with the state trees of the last
+/// `lookup_depth` epochs. The output is written in the seekable `ForestCAR.zst`
+/// format, which is also a valid zstd-compressed CAR file, so it can be opened
+/// directly as a read-only store with [`ForestCar`](crate::db::car::ForestCar).
+/// The progress is reported to `progress`.
+#[allow(clippy::too_many_arguments)]
 pub async fn export<D: Digest>(
     db: impl Blockstore + Send + Sync + 'static,
     tipset: &Tipset,
@@ -23,39 +93,150 @@ pub async fn export<D: Digest>(
     writer: impl AsyncWrite + Unpin,
     seen: CidHashSet,
     skip_checksum: bool,
+    canonical: bool,
+    progress: Arc<ExportProgressTracker>,
 ) -> Result<Option<digest::Output<D>>, Error> {
     let db = Arc::new(db);
     let stateroot_lookup_limit = tipset.epoch() - lookup_depth;
     let roots = Vec::<Cid>::from(&tipset.key().cids);
+    progress.start(tipset.epoch(), stateroot_lookup_limit);
+    let result = async {
+        // Wrap writer in optional checksum calculator
+        let mut writer =
+            AsyncWriterWithChecksum::<D, _>::new(BufWriter::new(writer), !skip_checksum);
 
-    // Wrap writer in optional checksum calculator
-    let mut writer = AsyncWriterWithChecksum::<D, _>::new(BufWriter::new(writer), !skip_checksum);
-
-    // Stream stateroots in range stateroot_lookup_limit..=tipset.epoch(). Also
-    // stream all block headers until genesis.
-    let blocks = par_buffer(
-        // Queue 1k blocks. This is enuogh to saturate the compressor and blocks
-        // are small enough that keeping 1k in memory isn't a problem. Average
-        // block size is between 1kb and 2kb.
-        1024,
-        stream_chain(
+        // Stream stateroots in range stateroot_lookup_limit..=tipset.epoch(). Also
+        // stream all block headers until genesis.
+        let stream = stream_chain(
             Arc::clone(&db),
-            tipset.clone().chain(Arc::clone(&db)),
+            tipset.clone().chain(Arc::clone(&db)).inspect({
+                let progress = Arc::clone(&progress);
+                move |tipset| progress.record_tipset(tipset.epoch())
+            }),
             stateroot_lookup_limit,
         )
-        .with_seen(seen),
-    );
+        .with_seen(seen);
+        // A canonical block order makes exports of the same chain byte-identical.
+        let stream = if canonical {
+            stream.canonical()
+        } else {
+            stream
+        };
+        let stream = stream
+            .inspect_ok({
+                let progress = Arc::clone(&progress);
+                move |_| progress.record_block()
+            })
+            .and_then(|block| async {
+                IO_SCHEDULER.throttle(block.data.len()).await;
+                Ok(block)
+            });
+        let blocks = par_buffer(
+            // Queue 1k blocks. This is enuogh to saturate the compressor and blocks
+            // are small enough that keeping 1k in memory isn't a problem. Average
+            // block size is between 1kb and 2kb.
+            1024, stream,
+        );
+
+        // Encode Ipld key-value pairs in zstd frames
+        let frames = forest::Encoder::compress_stream(8000usize.next_power_of_two(), 3, blocks)
+            .inspect_ok(|(_, frame)| progress.record_bytes(frame.len()));
+
+        // Write zstd frames and include a skippable index
+        forest::Encoder::write(&mut writer, roots, frames).await?;
+
+        // Flush to ensure everything has been successfully written
+        writer.flush().await.context("failed to flush")?;
+
+        let digest = writer.finalize().map_err(|e| Error::Other(e.to_string()))?;
+
+        Ok::<_, Error>(digest)
+    }
+    .await;
+    progress.finish(result.as_ref().err().map(ToString::to_string));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MemoryDB;
+    use crate::genesis::EXPORT_SR_40;
+    use fvm_ipld_car::load_car;
+    use tokio_util::compat::TokioAsyncReadCompatExt;
+
+    #[tokio::test]
+    async fn diffs_apply_on_top_of_their_base() {
+        let db = MemoryDB::default();
+        let roots = load_car(&db, tokio::io::BufReader::new(EXPORT_SR_40).compat())
+            .await
+            .unwrap();
+        let head = Tipset::load(&db, &roots.into()).unwrap().unwrap();
+        let base = head.clone().chain(&db).find(|ts| ts.epoch() == 30).unwrap();
 
-    // Encode Ipld key-value pairs in zstd frames
-    let frames = forest::Encoder::compress_stream(8000usize.next_power_of_two(), 3, blocks);
+        // The snapshot has no state trees, only export the headers.
+        let base_blocks = diff_seen(&db, &base, Some(0), &head);
+        let diff_blocks: Vec<_> = stream_chain(&db, head.clone().chain(&db), head.epoch())
+            .with_seen(base_blocks.clone())
+            .try_collect()
+            .await
+            .unwrap();
+        assert!(!diff_blocks.is_empty());
+        let full_blocks = diff_seen(&db, &head, Some(0), &head);
+        assert_eq!(full_blocks.len(), base_blocks.len() + diff_blocks.len());
+        for block in &diff_blocks {
+            assert!(!base_blocks.contains(&block.cid));
+            assert!(full_blocks.contains(&block.cid));
+        }
+    }
 
-    // Write zstd frames and include a skippable index
-    forest::Encoder::write(&mut writer, roots, frames).await?;
+    #[tokio::test]
+    async fn canonical_exports_are_byte_identical() {
+        use sha2::Sha256;
 
-    // Flush to ensure everything has been successfully written
-    writer.flush().await.context("failed to flush")?;
+        // The blocks of a short chain with its head state, inserted into a
+        // database in the given order, and exported with all of its headers.
+        let export = |reversed: bool| async move {
+            let mut reader = fvm_ipld_car::CarReader::new(EXPORT_SR_40).await.unwrap();
+            let mut blocks = vec![];
+            while let Some(block) = reader.next_block().await.unwrap() {
+                blocks.push(block);
+            }
+            if reversed {
+                blocks.reverse();
+            }
+            let db = MemoryDB::default();
+            for block in blocks {
+                db.put_keyed(&block.cid, &block.data).unwrap();
+            }
+            let head = Tipset::load(&db, &reader.header.roots.into())
+                .unwrap()
+                .unwrap();
+            let tipsets = head.clone().chain(&db).count();
 
-    let digest = writer.finalize().map_err(|e| Error::Other(e.to_string()))?;
+            let mut car = vec![];
+            export::<Sha256>(
+                db,
+                &head,
+                0,
+                &mut car,
+                CidHashSet::default(),
+                true,
+                true,
+                Default::default(),
+            )
+            .await
+            .unwrap();
+            (head, tipsets, car)
+        };
 
-    Ok(digest)
+        let (head, tipsets, car) = export(false).await;
+        assert!(tipsets > 1);
+        // Every header down to genesis is in the export.
+        let exported = crate::db::car::ForestCar::new(car.clone()).unwrap();
+        assert_eq!(head.chain(&exported).count(), tipsets);
+        for _ in 0..2 {
+            assert_eq!(car, export(true).await.2);
+        }
+    }
 }
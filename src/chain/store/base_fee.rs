@@ -61,6 +61,25 @@ pub fn compute_base_fee<DB>(
     ts: &Tipset,
     smoke_height: ChainEpoch,
 ) -> Result<TokenAmount, crate::chain::Error>
+where
+    DB: Blockstore,
+{
+    let (total_limit, _) = tipset_gas_limit(db, ts)?;
+
+    // Compute next base fee based on the current gas limit and parent base fee.
+    let parent_base_fee = ts.blocks()[0].parent_base_fee();
+    Ok(compute_next_base_fee(
+        parent_base_fee,
+        total_limit,
+        ts.blocks().len(),
+        ts.epoch(),
+        smoke_height,
+    ))
+}
+
+/// Returns the total gas limit and the number of the unique messages of a
+/// tipset.
+pub fn tipset_gas_limit<DB>(db: &DB, ts: &Tipset) -> Result<(u64, usize), crate::chain::Error>
 where
     DB: Blockstore,
 {
@@ -85,16 +104,7 @@ where
             }
         }
     }
-
-    // Compute next base fee based on the current gas limit and parent base fee.
-    let parent_base_fee = ts.blocks()[0].parent_base_fee();
-    Ok(compute_next_base_fee(
-        parent_base_fee,
-        total_limit,
-        ts.blocks().len(),
-        ts.epoch(),
-        smoke_height,
-    ))
+    Ok((total_limit, seen.len()))
 }
 
 #[cfg(test)]
@@ -1,27 +1,27 @@
 // Copyright 2019-2023 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-use std::sync::Arc;
+use std::{collections::VecDeque, sync::Arc};
 
+use crate::beacon::drand_entry_key;
 use crate::blocks::{BlockHeader, Tipset, TipsetKeys, TxMeta};
 use crate::fil_cns;
-use crate::interpreter::BlockMessages;
+use crate::interpreter::TipsetMessages;
 use crate::ipld::FrozenCids;
 use crate::libp2p_bitswap::{BitswapStoreRead, BitswapStoreReadWrite};
-use crate::message::{ChainMessage, Message as MessageTrait, SignedMessage};
+use crate::message::{ChainMessage, SignedMessage};
 use crate::networks::ChainConfig;
 use crate::shim::clock::ChainEpoch;
-use crate::shim::{
-    address::Address, econ::TokenAmount, executor::Receipt, message::Message,
-    state_tree::StateTree, version::NetworkVersion,
-};
+use crate::shim::econ::TokenAmount;
+use crate::shim::{executor::Receipt, message::Message, version::NetworkVersion};
 use crate::utils::db::{BlockstoreExt, CborStoreExt};
-use ahash::{HashMap, HashMapExt, HashSet};
+use ahash::HashSet;
 use anyhow::Result;
 use cid::Cid;
 use fvm_ipld_amt::Amtv0 as Amt;
 use fvm_ipld_blockstore::Blockstore;
 use fvm_ipld_encoding::CborStore;
+use num::BigInt;
 use parking_lot::Mutex;
 use serde::{de::DeserializeOwned, Serialize};
 use tokio::sync::broadcast::{self, Sender as Publisher};
@@ -29,15 +29,27 @@ use tracing::{debug, info, warn};
 
 use super::{
     index::{ChainIndex, ResolveNullTipset},
+    invalidation::Invalidation,
     tipset_tracker::TipsetTracker,
-    Error,
+    Error, MissingData,
+};
+use crate::db::setting_keys::{
+    ESTIMATED_RECORDS_KEY, FINALIZED_TIPSET_KEY, HEAD_KEY, PINNED_STATES_KEY,
+    VALIDATED_BLOCK_KEY_PREFIX,
 };
-use crate::db::setting_keys::{ESTIMATED_RECORDS_KEY, HEAD_KEY};
-use crate::db::{SettingsStore, SettingsStoreExt};
+use crate::db::{BatchStore, DbBatch, SettingsStore, SettingsStoreExt};
 
 // A cap on the size of the future_sink
 const SINK_CAP: usize = 200;
 
+/// Number of recent tipsets of the heaviest chain whose base fees are kept,
+/// see [`ChainStore::base_fee_history`].
+pub const BASE_FEE_HISTORY_LEN: usize = 20;
+
+/// Maximum number of tipsets walked back from a new head to index the beacon
+/// entries of the tipsets since the previous head.
+const BEACON_INDEX_DEPTH: usize = 900;
+
 /// Disambiguate the type to signify that we are expecting a delta and not an actual epoch/height
 /// while maintaining the same type.
 pub type ChainEpochDelta = ChainEpoch;
@@ -49,6 +61,13 @@ pub enum HeadChange {
     Apply(Arc<Tipset>),
 }
 
+/// Step of the path between two tipsets, see [`ChainStore::chain_get_path`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathChange {
+    Revert(Arc<Tipset>),
+    Apply(Arc<Tipset>),
+}
+
 /// Stores chain data such as heaviest tipset and cached tipset info at each
 /// epoch. This structure is thread-safe, and all caches are wrapped in a mutex
 /// to allow a consistent `ChainStore` to be shared across tasks.
@@ -56,12 +75,19 @@ pub struct ChainStore<DB> {
     /// Publisher for head change events
     publisher: Publisher<HeadChange>,
 
+    /// Publisher of the cache invalidations, see [`super::invalidation`].
+    invalidations: Publisher<Invalidation>,
+
     /// key-value `datastore`.
     pub db: Arc<DB>,
 
     /// Settings store
     settings: Arc<dyn SettingsStore + Sync + Send>,
 
+    /// Store of the head changes, which are committed atomically. It is the
+    /// settings store, and shares its blocks with `db`.
+    batches: Arc<dyn BatchStore + Sync + Send>,
+
     /// Used as a cache for tipset `lookbacks`.
     pub chain_index: Arc<ChainIndex<Arc<DB>>>,
 
@@ -70,8 +96,21 @@ pub struct ChainStore<DB> {
 
     genesis_block_header: BlockHeader,
 
-    /// validated blocks
+    /// Blocks validated since the last head change. Their markers are
+    /// persisted with the next head, see [`ChainStore::set_heaviest_tipset`].
     validated_blocks: Mutex<HashSet<Cid>>,
+
+    /// Base fees of the recent tipsets of the heaviest chain, newest first.
+    base_fee_history: Mutex<VecDeque<(TipsetKeys, TokenAmount)>>,
+
+    /// Serializes the updates of the pinned states.
+    pinned_states_lock: Mutex<()>,
+
+    /// Whether the states of the tipsets are unavailable, see
+    /// [`ChainStore::with_headers_only`].
+    headers_only: bool,
+
+    chain_config: Arc<ChainConfig>,
 }
 
 impl<DB> BitswapStoreRead for ChainStore<DB>
@@ -102,13 +141,18 @@ impl<DB> ChainStore<DB>
 where
     DB: Blockstore,
 {
-    pub fn new(
+    /// The blocks written to `settings` must be readable from `db`.
+    pub fn new<S>(
         db: Arc<DB>,
-        settings: Arc<dyn SettingsStore + Sync + Send>,
+        settings: Arc<S>,
         chain_config: Arc<ChainConfig>,
         genesis_block_header: BlockHeader,
-    ) -> Result<Self> {
+    ) -> Result<Self>
+    where
+        S: SettingsStore + BatchStore + Sync + Send + 'static,
+    {
         let (publisher, _) = broadcast::channel(SINK_CAP);
+        let (invalidations, _) = broadcast::channel(SINK_CAP);
         let chain_index = Arc::new(ChainIndex::new(Arc::clone(&db)));
 
         if !settings
@@ -123,33 +167,157 @@ where
 
         let cs = Self {
             publisher,
+            invalidations,
             chain_index,
-            tipset_tracker: TipsetTracker::new(Arc::clone(&db), chain_config),
+            tipset_tracker: TipsetTracker::new(Arc::clone(&db), chain_config.clone()),
             db,
-            settings,
+            settings: settings.clone(),
+            batches: settings,
             genesis_block_header,
             validated_blocks,
+            base_fee_history: Default::default(),
+            pinned_states_lock: Default::default(),
+            headers_only: false,
+            chain_config,
         };
 
         Ok(cs)
     }
 
+    /// Compares tipsets by the weights declared in their headers, as a node
+    /// that doesn't execute messages doesn't have the power tables of their
+    /// states. The declared weights of the synced tipsets are checked against
+    /// the power tables of their parents, see
+    /// [`SyncConfig::headers_only`](crate::chain_sync::SyncConfig::headers_only).
+    pub fn with_headers_only(mut self, headers_only: bool) -> Self {
+        self.headers_only = headers_only;
+        self
+    }
+
     /// Sets heaviest tipset within `ChainStore` and store its tipset keys in
     /// the settings store under the [`crate::db::setting_keys::HEAD_KEY`] key.
+    ///
+    /// The headers of the tipset, the new head, the markers of the validated
+    /// blocks and the indices are committed in a single batch, so that an
+    /// interrupted update never leaves the head pointing at a tipset whose
+    /// data was not fully persisted.
+    ///
+    /// Fails if `ts` isn't on the chain of the finalized tipset, see
+    /// [`ChainStore::mark_finalized`].
     pub fn set_heaviest_tipset(&self, ts: Arc<Tipset>) -> Result<(), Error> {
-        self.settings.write_obj(HEAD_KEY, ts.key())?;
+        if let Some(finalized) = self.conflicting_finalized_tipset(&ts)? {
+            return Err(Error::Other(format!(
+                "tipset {} (EPOCH = {}) isn't on the chain of the finalized tipset at epoch {}",
+                ts.key(),
+                ts.epoch(),
+                finalized.epoch()
+            )));
+        }
+        let previous = self.settings.require_obj::<TipsetKeys>(HEAD_KEY)?;
+        let mut batch = DbBatch::default();
+        let validated = std::mem::take(&mut *self.validated_blocks.lock());
+        for cid in &validated {
+            batch.write_obj(&validated_block_key(cid), &Some(ts.epoch()))?;
+        }
+        for header in ts.blocks() {
+            batch.put_cbor(header)?;
+        }
+        batch.write_obj(HEAD_KEY, ts.key())?;
+        if let Err(e) = self.index_beacon_entries(&ts, &mut batch) {
+            warn!("failed to index the beacon entries of the new head: {e}");
+        }
+        if let Err(e) = self.batches.commit_batch(batch) {
+            self.validated_blocks.lock().extend(validated);
+            return Err(e.into());
+        }
+        self.update_base_fee_history(&ts);
+        self.publish_invalidation(&previous, &ts);
+
         if self.publisher.send(HeadChange::Apply(ts)).is_err() {
             debug!("did not publish head change, no active receivers");
         }
         Ok(())
     }
 
+    /// Keeps the beacon entries of `head` and of its ancestors, up to the
+    /// first tipset whose entries are already kept, along with the verified
+    /// entries of the `drand` beacons, see [`drand_entry_key`]. All blocks of a
+    /// tipset have the same beacon entries.
+    fn index_beacon_entries(&self, head: &Tipset, batch: &mut DbBatch) -> Result<(), Error> {
+        for tipset in head
+            .clone()
+            .chain(self.blockstore())
+            .take(BEACON_INDEX_DEPTH)
+        {
+            let chain_hash = self.chain_config.drand_chain_hash(tipset.epoch());
+            let entries = tipset.min_ticket_block().beacon_entries();
+            if let Some(last) = entries.last() {
+                if self
+                    .settings
+                    .exists(&drand_entry_key(chain_hash, last.round()))?
+                {
+                    break;
+                }
+            }
+            for entry in entries {
+                batch.write_bin(
+                    &drand_entry_key(chain_hash, entry.round()),
+                    &fvm_ipld_encoding::to_vec(entry)?,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the base fees of the last [`BASE_FEE_HISTORY_LEN`] tipsets of
+    /// the heaviest chain, newest first. The base fee of a tipset is the one
+    /// its messages were executed with, i.e. its parent base fee.
+    pub fn base_fee_history(&self) -> Vec<TokenAmount> {
+        if self.base_fee_history.lock().is_empty() {
+            self.update_base_fee_history(&self.heaviest_tipset());
+        }
+        let history = self.base_fee_history.lock();
+        history
+            .iter()
+            .map(|(_, base_fee)| base_fee.clone())
+            .collect()
+    }
+
+    fn update_base_fee_history(&self, head: &Tipset) {
+        let base_fee = |tipset: &Tipset| {
+            (
+                tipset.key().clone(),
+                tipset.min_ticket_block().parent_base_fee().clone(),
+            )
+        };
+        let mut history = self.base_fee_history.lock();
+        if history.front().map(|(key, _)| key) != Some(head.parents()) {
+            // The history is rebuilt from the chain on start-up and re-orgs.
+            history.clear();
+            history.extend(
+                head.clone()
+                    .chain(self.blockstore())
+                    .skip(1)
+                    .take(BASE_FEE_HISTORY_LEN - 1)
+                    .map(|tipset| base_fee(&tipset)),
+            );
+        }
+        history.push_front(base_fee(head));
+        history.truncate(BASE_FEE_HISTORY_LEN);
+    }
+
     /// Adds a [`BlockHeader`] to the tipset tracker, which tracks valid
     /// headers.
     pub fn add_to_tipset_tracker(&self, header: &BlockHeader) {
         self.tipset_tracker.add(header);
     }
 
+    /// Returns the estimated number of IPLD records in the database, see
+    /// [`ChainStore::set_estimated_records`].
+    pub fn estimated_records(&self) -> anyhow::Result<Option<u64>> {
+        self.settings.read_obj(ESTIMATED_RECORDS_KEY)
+    }
+
     pub fn set_estimated_records(&self, records: u64) -> anyhow::Result<()> {
         self.settings.write_obj(ESTIMATED_RECORDS_KEY, &records)?;
         Ok(())
@@ -196,6 +364,22 @@ where
         &self.publisher
     }
 
+    /// Returns a reference to the publisher of the cache invalidations that
+    /// follow head changes.
+    pub fn invalidations(&self) -> &Publisher<Invalidation> {
+        &self.invalidations
+    }
+
+    /// Returns the settings store.
+    pub fn settings(&self) -> Arc<dyn SettingsStore + Sync + Send> {
+        Arc::clone(&self.settings)
+    }
+
+    /// Returns the store used to commit batches of blocks and settings.
+    pub fn batches(&self) -> Arc<dyn BatchStore + Sync + Send> {
+        Arc::clone(&self.batches)
+    }
+
     /// Returns key-value store instance.
     pub fn blockstore(&self) -> &DB {
         &self.db
@@ -210,16 +394,59 @@ where
         self.chain_index.load_tipset(tsk)
     }
 
+    /// Returns the tipsets to revert, from `from` down to the common ancestor
+    /// (excluded), followed by the tipsets to apply up to `to`. This is the
+    /// sequence of head changes that leads from head `from` to head `to`.
+    pub fn chain_get_path(
+        &self,
+        from: &TipsetKeys,
+        to: &TipsetKeys,
+    ) -> Result<Vec<PathChange>, Error> {
+        let mut left = self.tipset_from_keys(from)?;
+        let mut right = self.tipset_from_keys(to)?;
+        let mut reverts = vec![];
+        let mut applies = vec![];
+        let parent_of = |tipset: &Tipset| {
+            // The parents of genesis would resolve to the heaviest tipset.
+            if tipset.epoch() == 0 {
+                return Err(Error::Other(format!(
+                    "Tipsets {from} and {to} have no common ancestor"
+                )));
+            }
+            self.tipset_from_keys(tipset.parents())
+        };
+        while left.key() != right.key() {
+            if left.epoch() > right.epoch() {
+                let parent = parent_of(&left)?;
+                reverts.push(PathChange::Revert(std::mem::replace(&mut left, parent)));
+            } else {
+                let parent = parent_of(&right)?;
+                applies.push(PathChange::Apply(std::mem::replace(&mut right, parent)));
+            }
+        }
+        reverts.extend(applies.into_iter().rev());
+        Ok(reverts)
+    }
+
     /// Determines if provided tipset is heavier than existing known heaviest
     /// tipset
     fn update_heaviest(&self, ts: Arc<Tipset>) -> Result<(), Error> {
         // Calculate heaviest weight before matching to avoid deadlock with mutex
-        let heaviest_weight = fil_cns::weight(self.blockstore(), &self.heaviest_tipset())?;
+        let heaviest_weight = self.weight(&self.heaviest_tipset())?;
 
-        let new_weight = fil_cns::weight(self.blockstore(), ts.as_ref())?;
+        let new_weight = self.weight(ts.as_ref())?;
         let curr_weight = heaviest_weight;
 
         if new_weight > curr_weight {
+            if let Some(finalized) = self.conflicting_finalized_tipset(&ts)? {
+                warn!(
+                    "Refusing to switch to tipset {} (EPOCH = {}), it isn't on the chain of the finalized tipset at epoch {}",
+                    ts.key(),
+                    ts.epoch(),
+                    finalized.epoch()
+                );
+                return Ok(());
+            }
             // TODO potentially need to deal with re-orgs here
             info!("New heaviest tipset! {} (EPOCH = {})", ts.key(), ts.epoch());
             self.set_heaviest_tipset(ts)?;
@@ -227,9 +454,122 @@ where
         Ok(())
     }
 
+    fn weight(&self, ts: &Tipset) -> Result<BigInt, Error> {
+        match self.headers_only {
+            // The weight of a tipset is only known to its children, so
+            // tipsets are compared by the weight of their parents.
+            true => Ok(ts.weight().clone()),
+            false => Ok(fil_cns::weight(self.blockstore(), ts)?),
+        }
+    }
+
+    /// Returns the latest tipset marked as final with
+    /// [`ChainStore::mark_finalized`], if any.
+    pub fn finalized_tipset(&self) -> Result<Option<Arc<Tipset>>, Error> {
+        self.settings
+            .read_obj::<TipsetKeys>(FINALIZED_TIPSET_KEY)?
+            .map(|tsk| self.chain_index.load_tipset(&tsk))
+            .transpose()
+    }
+
+    /// Marks `ts` as final: the heaviest tipset will no longer be switched to
+    /// a chain that doesn't include it. Finality never goes back, so `ts` has
+    /// to descend from the previously finalized tipset.
+    pub fn mark_finalized(&self, ts: Arc<Tipset>) -> Result<(), Error> {
+        if let Some(finalized) = self.finalized_tipset()? {
+            if !self.descends_from(&ts, &finalized)? {
+                return Err(Error::Other(format!(
+                    "tipset {} doesn't descend from the finalized tipset {}",
+                    ts.key(),
+                    finalized.key()
+                )));
+            }
+        }
+        self.settings.write_obj(FINALIZED_TIPSET_KEY, ts.key())?;
+        Ok(())
+    }
+
+    /// Returns the state roots pinned with [`ChainStore::pin_state`].
+    pub fn pinned_states(&self) -> Result<Vec<Cid>, Error> {
+        Ok(self
+            .settings
+            .read_obj(PINNED_STATES_KEY)?
+            .unwrap_or_default())
+    }
+
+    /// Pins `state_root`: its state tree is kept by the garbage collectors,
+    /// even once it is older than their retention window. Returns whether it
+    /// wasn't pinned yet. The state tree must be complete, which is checked by
+    /// walking it entirely.
+    pub fn pin_state(&self, state_root: Cid) -> Result<bool, Error> {
+        if !self.db.has(&state_root)? {
+            return Err(Error::NotFound(format!("state root {state_root}")));
+        }
+        if let Some(missing) = crate::ipld::find_missing_block(&self.db, state_root)? {
+            return Err(Error::NotFound(format!(
+                "block {missing} of the state tree {state_root}"
+            )));
+        }
+        let _lock = self.pinned_states_lock.lock();
+        let mut pinned = self.pinned_states()?;
+        if pinned.contains(&state_root) {
+            return Ok(false);
+        }
+        pinned.push(state_root);
+        self.settings.write_obj(PINNED_STATES_KEY, &pinned)?;
+        Ok(true)
+    }
+
+    /// Unpins `state_root`, see [`ChainStore::pin_state`]. Returns whether it
+    /// was pinned.
+    pub fn unpin_state(&self, state_root: &Cid) -> Result<bool, Error> {
+        let _lock = self.pinned_states_lock.lock();
+        let mut pinned = self.pinned_states()?;
+        let len = pinned.len();
+        pinned.retain(|cid| cid != state_root);
+        if pinned.len() == len {
+            return Ok(false);
+        }
+        self.settings.write_obj(PINNED_STATES_KEY, &pinned)?;
+        Ok(true)
+    }
+
+    /// Returns the finalized tipset if `ts` is neither one of its ancestors
+    /// nor one of its descendants, that is if switching to `ts` would re-org
+    /// behind the finalized epoch.
+    fn conflicting_finalized_tipset(&self, ts: &Arc<Tipset>) -> Result<Option<Arc<Tipset>>, Error> {
+        let Some(finalized) = self.finalized_tipset()? else {
+            return Ok(None);
+        };
+        let on_finalized_chain = if ts.epoch() >= finalized.epoch() {
+            self.descends_from(ts, &finalized)?
+        } else {
+            self.descends_from(&finalized, ts)?
+        };
+        Ok((!on_finalized_chain).then_some(finalized))
+    }
+
+    /// Returns whether `ancestor` is `ts` or one of its ancestors.
+    fn descends_from(&self, ts: &Arc<Tipset>, ancestor: &Tipset) -> Result<bool, Error> {
+        if ts.epoch() < ancestor.epoch() {
+            return Ok(false);
+        }
+        let ts = self.chain_index.tipset_by_height(
+            ancestor.epoch(),
+            Arc::clone(ts),
+            ResolveNullTipset::TakeOlder,
+        )?;
+        Ok(ts.key() == ancestor.key())
+    }
+
     /// Checks metadata file if block has already been validated.
     pub fn is_block_validated(&self, cid: &Cid) -> bool {
-        let validated = self.validated_blocks.lock().contains(cid);
+        let validated = self.validated_blocks.lock().contains(cid)
+            || matches!(
+                self.settings
+                    .read_obj::<Option<ChainEpoch>>(&validated_block_key(cid)),
+                Ok(Some(Some(_)))
+            );
         if validated {
             debug!("Block {cid} was previously validated");
         }
@@ -245,13 +585,18 @@ where
     pub fn unmark_block_as_validated(&self, cid: &Cid) {
         let mut file = self.validated_blocks.lock();
         let _did_work = file.remove(cid);
+        if let Err(e) = self
+            .settings
+            .write_obj(&validated_block_key(cid), &None::<ChainEpoch>)
+        {
+            warn!("failed to unmark block {cid} as validated: {e}");
+        }
     }
 
     /// Retrieves ordered valid messages from a `Tipset`. This will only include
     /// messages that will be passed through the VM.
     pub fn messages_for_tipset(&self, ts: &Tipset) -> Result<Vec<ChainMessage>, Error> {
-        let bmsgs = BlockMessages::for_tipset(&self.db, ts)?;
-        Ok(bmsgs.into_iter().flat_map(|bm| bm.messages).collect())
+        Ok(TipsetMessages::load(&self.db, ts, &self.chain_config)?.into_messages())
     }
 
     /// Gets look-back tipset (and state-root of that tipset) for block
@@ -368,9 +713,8 @@ where
         let secpk_cids = read_amt_cids(db, &roots.secp_message_root)?;
         Ok((bls_cids, secpk_cids))
     } else {
-        Err(Error::UndefinedKey(format!(
-            "no msg root with cid {msg_cid}"
-        )))
+        // The root is referenced by a block header that we have.
+        Err(MissingData::pruned(format!("message root {msg_cid}")).into())
     }
 }
 
@@ -410,63 +754,20 @@ where
     DB: Blockstore,
 {
     db.get_cbor(key)?
-        .ok_or_else(|| Error::UndefinedKey(key.to_string()))
+        .ok_or_else(|| MissingData::unknown(format!("message {key}")).into())
 }
 
-/// Given a tipset this function will return all unique messages in that tipset.
-pub fn messages_for_tipset<DB>(db: Arc<DB>, ts: &Tipset) -> Result<Vec<ChainMessage>, Error>
+/// Given a tipset this function will return all unique messages in that tipset,
+/// see [`TipsetMessages`].
+pub fn messages_for_tipset<DB>(
+    db: Arc<DB>,
+    ts: &Tipset,
+    chain_config: &ChainConfig,
+) -> Result<Vec<ChainMessage>, Error>
 where
     DB: Blockstore,
 {
-    let mut applied: HashMap<Address, u64> = HashMap::new();
-    let mut balances: HashMap<Address, TokenAmount> = HashMap::new();
-    let state = StateTree::new_from_root(Arc::clone(&db), ts.parent_state())?;
-
-    // message to get all messages for block_header into a single iterator
-    let mut get_message_for_block_header = |b: &BlockHeader| -> Result<Vec<ChainMessage>, Error> {
-        let (unsigned, signed) = block_messages(&db, b)?;
-        let mut messages = Vec::with_capacity(unsigned.len() + signed.len());
-        let unsigned_box = unsigned.into_iter().map(ChainMessage::Unsigned);
-        let signed_box = signed.into_iter().map(ChainMessage::Signed);
-
-        for message in unsigned_box.chain(signed_box) {
-            let from_address = &message.from();
-            if applied.contains_key(from_address) {
-                let actor_state = state
-                    .get_actor(from_address)?
-                    .ok_or_else(|| Error::Other("Actor state not found".to_string()))?;
-                applied.insert(*from_address, actor_state.sequence);
-                balances.insert(*from_address, actor_state.balance.clone().into());
-            }
-            if let Some(seq) = applied.get_mut(from_address) {
-                if *seq != message.sequence() {
-                    continue;
-                }
-                *seq += 1;
-            } else {
-                continue;
-            }
-            if let Some(bal) = balances.get_mut(from_address) {
-                if *bal < message.required_funds() {
-                    continue;
-                }
-                *bal -= message.required_funds();
-            } else {
-                continue;
-            }
-
-            messages.push(message)
-        }
-
-        Ok(messages)
-    };
-
-    ts.blocks().iter().fold(Ok(Vec::new()), |vec, b| {
-        let mut message_vec = vec?;
-        let mut messages = get_message_for_block_header(b)?;
-        message_vec.append(&mut messages);
-        Ok(message_vec)
-    })
+    Ok(TipsetMessages::load(db, ts, chain_config)?.into_messages())
 }
 
 /// Returns messages from key-value store based on a slice of [`Cid`]s.
@@ -478,12 +779,15 @@ where
     keys.iter()
         .map(|k| {
             db.get_cbor(k)?
-                .ok_or_else(|| Error::UndefinedKey(k.to_string()))
+                .ok_or_else(|| MissingData::pruned(format!("message {k}")).into())
         })
         .collect()
 }
 
 /// Returns parent message receipt given `block_header` and message index.
+/// Missing receipts are reported as pruned when the parent state of the block,
+/// which results from the same execution, is available, and as not computed
+/// otherwise.
 pub fn get_parent_reciept<DB>(
     db: &DB,
     block_header: &BlockHeader,
@@ -492,11 +796,25 @@ pub fn get_parent_reciept<DB>(
 where
     DB: Blockstore,
 {
-    let amt = Amt::load(block_header.message_receipts(), db)?;
+    let root = block_header.message_receipts();
+    if !db.has(root)? {
+        let what = format!("receipts root {root}");
+        return Err(if db.has(block_header.state_root())? {
+            MissingData::pruned(what)
+        } else {
+            MissingData::not_computed(what)
+        }
+        .into());
+    }
+    let amt = Amt::load(root, db)?;
     let receipts = amt.get(i as u64)?;
     Ok(receipts.cloned())
 }
 
+fn validated_block_key(cid: &Cid) -> String {
+    format!("{VALIDATED_BLOCK_KEY_PREFIX}{cid}")
+}
+
 pub mod headchange_json {
     use crate::lotus_json::LotusJson;
     use serde::{Deserialize, Serialize};
@@ -507,6 +825,9 @@ pub mod headchange_json {
     #[serde(rename_all = "lowercase")]
     #[serde(tag = "type", content = "val")]
     pub enum HeadChangeJson {
+        /// The head of the chain when a subscription starts or resynchronizes.
+        Current(LotusJson<Tipset>),
+        Revert(LotusJson<Tipset>),
         Apply(LotusJson<Tipset>),
     }
 
@@ -517,6 +838,15 @@ pub mod headchange_json {
             }
         }
     }
+
+    impl From<PathChange> for HeadChangeJson {
+        fn from(change: PathChange) -> Self {
+            match change {
+                PathChange::Revert(arc) => Self::Revert((*arc).clone().into()),
+                PathChange::Apply(arc) => Self::Apply((*arc).clone().into()),
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -532,6 +862,7 @@ mod tests {
     use fvm_ipld_encoding::DAG_CBOR;
 
     use super::*;
+    use crate::chain::MissingReason;
 
     #[test]
     fn genesis_test() {
@@ -569,4 +900,337 @@ mod tests {
         cs.mark_block_as_validated(&cid);
         assert!(cs.is_block_validated(&cid));
     }
+
+    #[test]
+    fn head_change_is_committed_with_its_data() {
+        let db = Arc::new(crate::db::MemoryDB::default());
+        let chain_config = Arc::new(ChainConfig::default());
+        let gen_block = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .build()
+            .unwrap();
+        db.put_cbor_default(&gen_block).unwrap();
+        let cs = ChainStore::new(db.clone(), db.clone(), chain_config.clone(), gen_block).unwrap();
+
+        // The header of the new head is only written with the head itself.
+        let head = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .epoch(1)
+            .parents(cs.heaviest_tipset().key().clone())
+            .build()
+            .unwrap();
+        assert!(!db.has(head.cid()).unwrap());
+        cs.mark_block_as_validated(head.cid());
+        cs.set_heaviest_tipset(Arc::new(Tipset::from(&head)))
+            .unwrap();
+
+        let gen_block = cs.genesis().clone();
+        let cs = ChainStore::new(db.clone(), db, chain_config, gen_block).unwrap();
+        assert_eq!(cs.heaviest_tipset().key(), Tipset::from(&head).key());
+        assert!(cs.is_block_validated(head.cid()));
+        cs.unmark_block_as_validated(head.cid());
+        assert!(!cs.is_block_validated(head.cid()));
+    }
+
+    #[test]
+    fn path_between_forks() {
+        let db = Arc::new(crate::db::MemoryDB::default());
+        let genesis = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .build()
+            .unwrap();
+        db.put_cbor_default(&genesis).unwrap();
+        let cs = ChainStore::new(
+            db.clone(),
+            db.clone(),
+            Arc::new(ChainConfig::default()),
+            genesis.clone(),
+        )
+        .unwrap();
+        let child = |parent: &BlockHeader, miner: u64| {
+            let header = BlockHeader::builder()
+                .miner_address(Address::new_id(miner))
+                .parents(TipsetKeys::new(FrozenCids::from_iter([*parent.cid()])))
+                .epoch(parent.epoch() + 1)
+                .build()
+                .unwrap();
+            db.put_cbor_default(&header).unwrap();
+            header
+        };
+        let a1 = child(&genesis, 1);
+        let a2 = child(&a1, 1);
+        let b1 = child(&genesis, 2);
+        let key = |header: &BlockHeader| Tipset::from(header.clone()).key().clone();
+        let epochs = |path: Vec<PathChange>| {
+            path.into_iter()
+                .map(|change| match change {
+                    PathChange::Revert(ts) => (false, ts.epoch()),
+                    PathChange::Apply(ts) => (true, ts.epoch()),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert!(cs.chain_get_path(&key(&a2), &key(&a2)).unwrap().is_empty());
+        assert_eq!(
+            epochs(cs.chain_get_path(&key(&a2), &key(&b1)).unwrap()),
+            [(false, 2), (false, 1), (true, 1)]
+        );
+        assert_eq!(
+            epochs(cs.chain_get_path(&key(&genesis), &key(&a2)).unwrap()),
+            [(true, 1), (true, 2)]
+        );
+    }
+
+    #[test]
+    fn finality_never_goes_back() {
+        let db = Arc::new(crate::db::MemoryDB::default());
+        let genesis = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .build()
+            .unwrap();
+        db.put_cbor_default(&genesis).unwrap();
+        let chain_config = Arc::new(ChainConfig::default());
+        let cs = ChainStore::new(
+            db.clone(),
+            db.clone(),
+            chain_config.clone(),
+            genesis.clone(),
+        )
+        .unwrap();
+        let child = |parent: &BlockHeader, miner: u64| {
+            let header = BlockHeader::builder()
+                .miner_address(Address::new_id(miner))
+                .parents(TipsetKeys::new(FrozenCids::from_iter([*parent.cid()])))
+                .epoch(parent.epoch() + 1)
+                .build()
+                .unwrap();
+            db.put_cbor_default(&header).unwrap();
+            Arc::new(Tipset::from(header))
+        };
+        let a1 = child(&genesis, 1);
+        let a2 = child(a1.min_ticket_block(), 1);
+        let b2 = child(a1.min_ticket_block(), 2);
+        let b3 = child(b2.min_ticket_block(), 2);
+
+        assert!(cs.finalized_tipset().unwrap().is_none());
+        cs.mark_finalized(a2.clone()).unwrap();
+        cs.mark_finalized(a1.clone()).unwrap_err();
+        cs.mark_finalized(b3.clone()).unwrap_err();
+        assert!(cs.descends_from(&a2, &a2).unwrap());
+
+        // The head may be rolled back along the finalized chain, but not
+        // switched to a fork of it.
+        cs.set_heaviest_tipset(a1).unwrap();
+        cs.set_heaviest_tipset(a2.clone()).unwrap();
+        cs.set_heaviest_tipset(b2).unwrap_err();
+        cs.set_heaviest_tipset(b3).unwrap_err();
+        assert_eq!(cs.heaviest_tipset(), a2);
+
+        let cs = ChainStore::new(db.clone(), db, chain_config, genesis).unwrap();
+        assert_eq!(cs.finalized_tipset().unwrap(), Some(a2));
+    }
+
+    #[test]
+    fn headers_only_stores_follow_the_declared_weights() {
+        let db = Arc::new(crate::db::MemoryDB::default());
+        let genesis = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .build()
+            .unwrap();
+        db.put_cbor_default(&genesis).unwrap();
+        let cs = ChainStore::new(
+            db.clone(),
+            db.clone(),
+            Arc::new(ChainConfig::default()),
+            genesis.clone(),
+        )
+        .unwrap()
+        .with_headers_only(true);
+        // None of the states exist.
+        let child = |parent: &BlockHeader, miner: u64, weight: u64| {
+            let header = BlockHeader::builder()
+                .miner_address(Address::new_id(miner))
+                .parents(TipsetKeys::new(FrozenCids::from_iter([*parent.cid()])))
+                .epoch(parent.epoch() + 1)
+                .weight(weight.into())
+                .build()
+                .unwrap();
+            Tipset::from(header)
+        };
+        let a1 = child(&genesis, 1, 10);
+        let b1 = child(&genesis, 2, 5);
+
+        cs.put_tipset(&a1).unwrap();
+        assert_eq!(cs.heaviest_tipset().key(), a1.key());
+        cs.put_tipset(&b1).unwrap();
+        assert_eq!(cs.heaviest_tipset().key(), a1.key());
+    }
+
+    #[test]
+    fn beacon_entries_are_indexed() {
+        use crate::beacon::BeaconEntry;
+
+        let db = Arc::new(crate::db::MemoryDB::default());
+        let genesis = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .build()
+            .unwrap();
+        let cs = ChainStore::new(
+            db.clone(),
+            db.clone(),
+            Arc::new(ChainConfig::default()),
+            genesis.clone(),
+        )
+        .unwrap();
+        let entry = |round: u64| BeaconEntry::new(round, vec![round as u8; 96]);
+        let mut parent = genesis;
+        for epoch in 1..=3 {
+            parent = BlockHeader::builder()
+                .miner_address(Address::new_id(1))
+                .parents(TipsetKeys::new(FrozenCids::from_iter([*parent.cid()])))
+                .epoch(epoch)
+                .beacon_entries(vec![entry(epoch as u64 * 2), entry(epoch as u64 * 2 + 1)])
+                .build()
+                .unwrap();
+            db.put_cbor_default(&parent).unwrap();
+        }
+
+        // The entries of the tipsets between the previous and the new head are
+        // indexed as well.
+        cs.set_heaviest_tipset(Arc::new(Tipset::from(parent)))
+            .unwrap();
+        // They are kept with the entries of the beacon chain of their epoch.
+        let config = ChainConfig::default();
+        let chain_hash = config.drand_chain_hash(1);
+        assert_ne!(
+            chain_hash,
+            config.drand_chain_hash(config.epoch(crate::networks::Height::Smoke))
+        );
+        let stored = |round| {
+            cs.settings()
+                .read_bin(&drand_entry_key(chain_hash, round))
+                .unwrap()
+                .map(|bytes| fvm_ipld_encoding::from_slice::<BeaconEntry>(&bytes).unwrap())
+        };
+        for round in 2..=7 {
+            assert_eq!(stored(round), Some(entry(round)));
+        }
+        assert_eq!(stored(8), None);
+    }
+
+    #[test]
+    fn base_fee_history_follows_the_head() {
+        let db = Arc::new(crate::db::MemoryDB::default());
+        let genesis = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .build()
+            .unwrap();
+        db.put_cbor_default(&genesis).unwrap();
+        let cs = ChainStore::new(
+            db.clone(),
+            db.clone(),
+            Arc::new(ChainConfig::default()),
+            genesis.clone(),
+        )
+        .unwrap();
+        let child = |parent: &BlockHeader, miner: u64, base_fee: u64| {
+            let header = BlockHeader::builder()
+                .miner_address(Address::new_id(miner))
+                .parents(TipsetKeys::new(FrozenCids::from_iter([*parent.cid()])))
+                .epoch(parent.epoch() + 1)
+                .parent_base_fee(TokenAmount::from_atto(base_fee))
+                .build()
+                .unwrap();
+            db.put_cbor_default(&header).unwrap();
+            header
+        };
+        let base_fees = |fees: &[u64]| {
+            fees.iter()
+                .map(|fee| TokenAmount::from_atto(*fee))
+                .collect::<Vec<_>>()
+        };
+
+        // The history is loaded from the chain at first.
+        let a1 = child(&genesis, 1, 100);
+        let a2 = child(&a1, 1, 200);
+        cs.set_heaviest_tipset(Arc::new(Tipset::from(&a2))).unwrap();
+        assert_eq!(cs.base_fee_history(), base_fees(&[200, 100, 0]));
+
+        let a3 = child(&a2, 1, 300);
+        cs.set_heaviest_tipset(Arc::new(Tipset::from(&a3))).unwrap();
+        assert_eq!(cs.base_fee_history(), base_fees(&[300, 200, 100, 0]));
+
+        // Re-orgs replace the history.
+        let b2 = child(&a1, 2, 150);
+        let b3 = child(&b2, 2, 250);
+        cs.set_heaviest_tipset(Arc::new(Tipset::from(&b3))).unwrap();
+        assert_eq!(cs.base_fee_history(), base_fees(&[250, 150, 100, 0]));
+    }
+
+    #[test]
+    fn missing_data_reasons() {
+        let db = crate::db::MemoryDB::default();
+        let header = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .messages(Cid::new_v1(DAG_CBOR, Blake2b256.digest(&[1])))
+            .message_receipts(Cid::new_v1(DAG_CBOR, Blake2b256.digest(&[2])))
+            .state_root(Cid::new_v1(DAG_CBOR, Blake2b256.digest(&[3])))
+            .build()
+            .unwrap();
+
+        let reason = |e: Error| match e {
+            Error::Missing(missing) => missing.reason,
+            other => panic!("unexpected error: {other}"),
+        };
+        assert_eq!(
+            reason(get_chain_message(&db, header.messages()).unwrap_err()),
+            MissingReason::Unknown
+        );
+        assert_eq!(
+            reason(block_messages(&db, &header).unwrap_err()),
+            MissingReason::Pruned
+        );
+        assert_eq!(
+            reason(get_parent_reciept(&db, &header, 0).unwrap_err()),
+            MissingReason::NotComputed
+        );
+        // The parent state and the receipts result from the same execution.
+        db.put_keyed(header.state_root(), &[]).unwrap();
+        assert_eq!(
+            reason(get_parent_reciept(&db, &header, 0).unwrap_err()),
+            MissingReason::Pruned
+        );
+    }
+
+    #[test]
+    fn pinned_states() {
+        let db = Arc::new(crate::db::MemoryDB::default());
+        let gen_block = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .build()
+            .unwrap();
+        db.put_cbor_default(&gen_block).unwrap();
+        let cs = ChainStore::new(
+            db.clone(),
+            db.clone(),
+            Arc::new(ChainConfig::default()),
+            gen_block,
+        )
+        .unwrap();
+        let state = db.put_cbor_default(&"state").unwrap();
+
+        assert!(cs.pin_state(state).unwrap());
+        assert!(!cs.pin_state(state).unwrap());
+        assert!(cs.pin_state(Cid::default()).is_err());
+        let pruned = crate::db::MemoryDB::default()
+            .put_cbor_default(&"pruned")
+            .unwrap();
+        let partial = db.put_cbor_default(&("state", pruned)).unwrap();
+        assert!(cs.pin_state(partial).is_err());
+        assert_eq!(cs.pinned_states().unwrap(), vec![state]);
+
+        assert!(cs.unpin_state(&state).unwrap());
+        assert!(!cs.unpin_state(&state).unwrap());
+        assert!(cs.pinned_states().unwrap().is_empty());
+    }
 }
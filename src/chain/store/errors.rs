@@ -18,6 +18,9 @@ pub enum Error {
     /// Key not found in database
     #[error("{0} not found")]
     NotFound(String),
+    /// Requested chain data is not available locally
+    #[error(transparent)]
+    Missing(#[from] MissingData),
     /// Error originating constructing blockchain structures
     #[error(transparent)]
     Blockchain(#[from] BlkErr),
@@ -35,6 +38,73 @@ pub enum Error {
     Other(String),
 }
 
+/// Why requested chain data is not available locally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingReason {
+    /// The data is not known to this node. It may never have existed.
+    Unknown,
+    /// The data was part of the chain but is no longer stored, e.g. because
+    /// the node was started from a snapshot, or it has been garbage collected.
+    Pruned,
+    /// The data is ahead of the current head of this node.
+    NotYetSynced,
+    /// The data is the result of executing the chain, e.g. receipts, and
+    /// this node never executed the corresponding tipset.
+    NotComputed,
+}
+
+/// Requested blocks, messages, receipts or tipsets are not available locally.
+/// The messages follow Lotus where possible, so that clients matching on
+/// them keep working.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub struct MissingData {
+    /// Description of the missing data, e.g. `message bafy2...`
+    pub what: String,
+    pub reason: MissingReason,
+}
+
+impl MissingData {
+    pub fn unknown(what: impl std::fmt::Display) -> Self {
+        Self::new(what, MissingReason::Unknown)
+    }
+
+    pub fn pruned(what: impl std::fmt::Display) -> Self {
+        Self::new(what, MissingReason::Pruned)
+    }
+
+    pub fn not_yet_synced(what: impl std::fmt::Display) -> Self {
+        Self::new(what, MissingReason::NotYetSynced)
+    }
+
+    pub fn not_computed(what: impl std::fmt::Display) -> Self {
+        Self::new(what, MissingReason::NotComputed)
+    }
+
+    fn new(what: impl std::fmt::Display, reason: MissingReason) -> Self {
+        MissingData {
+            what: what.to_string(),
+            reason,
+        }
+    }
+}
+
+impl std::fmt::Display for MissingData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let what = &self.what;
+        match self.reason {
+            MissingReason::Unknown => write!(f, "ipld: could not find {what}"),
+            MissingReason::Pruned => {
+                write!(
+                    f,
+                    "ipld: could not find {what} (pruned from the local store)"
+                )
+            }
+            MissingReason::NotYetSynced => write!(f, "{what} has not been synced yet"),
+            MissingReason::NotComputed => write!(f, "{what} has not been computed"),
+        }
+    }
+}
+
 impl From<EncErr> for Error {
     fn from(e: EncErr) -> Error {
         Error::Encoding(e.to_string())
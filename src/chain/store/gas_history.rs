@@ -0,0 +1,319 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Time series of the gas market, for charting.
+//!
+//! A compact sample is kept in the settings store for every tipset of the
+//! heaviest chain, keyed by epoch. The gas used by a tipset is read from the
+//! receipts referenced by its child, so a tipset is sampled once it has a child
+//! on the heaviest chain and its messages were executed.
+//!
+//! Sampling runs in the background, see [`ChainStore::sample_gas_history`].
+//! When the head changes, the tipsets since the previous head are sampled, and
+//! the samples of reverted tipsets are replaced or cleared. Only tipsets that
+//! were the head of this node, or their recent ancestors, are sampled.
+
+use std::sync::Arc;
+
+use super::{base_fee::tipset_gas_limit, ChainStore, Error, HeadChange, BLOCK_GAS_TARGET};
+use crate::blocks::Tipset;
+use crate::db::{setting_keys::GAS_HISTORY_KEY_PREFIX, DbBatch, SettingsStore};
+use crate::shim::{clock::ChainEpoch, econ::TokenAmount, executor::Receipt};
+use cid::Cid;
+use fvm_ipld_amt::Amtv0 as Amt;
+use fvm_ipld_blockstore::Blockstore;
+use num::BigInt;
+use serde::{Deserialize, Serialize};
+use serde_tuple::{self, Deserialize_tuple, Serialize_tuple};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+/// Maximum number of tipsets walked back from a new head to sample the
+/// tipsets since the previous head.
+const GAS_HISTORY_DEPTH: usize = 900;
+
+/// Maximum number of epochs queried by [`ChainStore::gas_history`], one week.
+pub const MAX_GAS_HISTORY_EPOCHS: ChainEpoch = 7 * 2880;
+
+#[derive(Debug, Clone, PartialEq, Serialize_tuple, Deserialize_tuple)]
+struct GasSample {
+    /// Block with the smallest ticket, which identifies the sampled tipset.
+    block: Cid,
+    /// Base fee paid by the messages of the tipset.
+    base_fee: TokenAmount,
+    /// Total gas used by the unique messages.
+    gas_used: u64,
+    /// Total gas limit of the unique messages, which drives the base fee.
+    gas_limit: u64,
+    blocks: u64,
+    messages: u64,
+}
+
+/// Aggregated gas market over an interval of epochs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct GasHistoryPoint {
+    /// First epoch of the interval.
+    pub epoch: ChainEpoch,
+    /// Number of sampled tipsets in the interval. Null rounds and tipsets that
+    /// weren't sampled are skipped.
+    pub tipsets: u64,
+    /// Average base fee of the tipsets.
+    #[serde(with = "crate::lotus_json")]
+    pub base_fee: TokenAmount,
+    #[serde(with = "crate::lotus_json")]
+    pub min_base_fee: TokenAmount,
+    #[serde(with = "crate::lotus_json")]
+    pub max_base_fee: TokenAmount,
+    /// Gas used by the messages relative to the gas target of the blocks.
+    pub gas_used_ratio: f64,
+    /// Total number of unique messages.
+    pub message_count: u64,
+}
+
+fn gas_history_key(epoch: ChainEpoch) -> String {
+    format!("{GAS_HISTORY_KEY_PREFIX}{epoch}")
+}
+
+impl<DB> ChainStore<DB>
+where
+    DB: Blockstore,
+{
+    /// Samples the gas market of every new head in the background, until the
+    /// head changes are no longer published.
+    pub async fn sample_gas_history(self: Arc<Self>) -> anyhow::Result<()>
+    where
+        DB: Send + Sync + 'static,
+    {
+        let mut head_changes = self.publisher().subscribe();
+        let mut head = self.heaviest_tipset();
+        loop {
+            let chain_store = Arc::clone(&self);
+            if let Err(e) =
+                tokio::task::spawn_blocking(move || chain_store.update_gas_history(&head)).await?
+            {
+                warn!("Failed to sample the gas market: {e}");
+            }
+            head = match head_changes.recv().await {
+                Ok(HeadChange::Apply(tipset)) => tipset,
+                // The ancestors of the head are sampled too, so missed heads
+                // are caught up with.
+                Err(RecvError::Lagged(_)) => self.heaviest_tipset(),
+                Err(RecvError::Closed) => return Ok(()),
+            };
+        }
+    }
+
+    /// Samples the ancestors of `head`, up to the first tipset that is already
+    /// sampled.
+    fn update_gas_history(&self, head: &Tipset) -> Result<(), Error> {
+        let settings = self.settings();
+        let mut batch = DbBatch::default();
+        // A reverted tipset at the epoch of the head may have been sampled.
+        if let Some(stored) = read_sample(settings.as_ref(), head.epoch())? {
+            if stored.block != *head.min_ticket_block().cid() {
+                write_sample(&mut batch, head.epoch(), None)?;
+            }
+        }
+        let mut tipsets = head
+            .clone()
+            .chain(self.blockstore())
+            .take(GAS_HISTORY_DEPTH);
+        let Some(mut child) = tipsets.next() else {
+            return Ok(());
+        };
+        for tipset in tipsets {
+            // Null rounds of the new chain may hold samples of reverted tipsets.
+            for epoch in tipset.epoch() + 1..child.epoch() {
+                if settings.exists(&gas_history_key(epoch))? {
+                    write_sample(&mut batch, epoch, None)?;
+                }
+            }
+            let block = *tipset.min_ticket_block().cid();
+            let stored = read_sample(settings.as_ref(), tipset.epoch())?;
+            if stored.map(|sample| sample.block) == Some(block) {
+                break;
+            }
+            // The messages of the tipset may not be executed yet.
+            if let Some(gas_used) = receipts_gas_used(self.blockstore(), &child)? {
+                let (gas_limit, messages) = tipset_gas_limit(self.blockstore(), &tipset)?;
+                let sample = GasSample {
+                    block,
+                    base_fee: tipset.min_ticket_block().parent_base_fee().clone(),
+                    gas_used,
+                    gas_limit,
+                    blocks: tipset.blocks().len() as u64,
+                    messages: messages as u64,
+                };
+                write_sample(&mut batch, tipset.epoch(), Some(&sample))?;
+            }
+            child = tipset;
+        }
+        self.batches().commit_batch(batch)?;
+        Ok(())
+    }
+
+    /// Returns the gas market between `first` and `last`, inclusive, with one
+    /// point for every `resolution` epochs. Intervals without samples are
+    /// skipped.
+    pub fn gas_history(
+        &self,
+        first: ChainEpoch,
+        last: ChainEpoch,
+        resolution: ChainEpoch,
+    ) -> anyhow::Result<Vec<GasHistoryPoint>> {
+        anyhow::ensure!(resolution > 0, "the resolution must be positive");
+        anyhow::ensure!(first <= last, "the range {first}..={last} is empty");
+        anyhow::ensure!(
+            last - first < MAX_GAS_HISTORY_EPOCHS,
+            "the range {first}..={last} spans more than {MAX_GAS_HISTORY_EPOCHS} epochs"
+        );
+        let settings = self.settings();
+        let mut history = vec![];
+        for start in (first..=last).step_by(resolution as usize) {
+            let end = (start + resolution - 1).min(last);
+            let mut samples = vec![];
+            for epoch in start..=end {
+                if let Some(sample) = read_sample(settings.as_ref(), epoch)? {
+                    samples.push(sample);
+                }
+            }
+            history.extend(aggregate(start, &samples));
+        }
+        Ok(history)
+    }
+}
+
+/// Returns the total gas used by the parent messages of `child`, or `None` if
+/// their receipts aren't available.
+fn receipts_gas_used(db: &impl Blockstore, child: &Tipset) -> anyhow::Result<Option<u64>> {
+    let root = child.min_ticket_block().message_receipts();
+    if !db.has(root)? {
+        return Ok(None);
+    }
+    let mut gas_used = 0;
+    Amt::<Receipt, _>::load(root, db)?.for_each(|_, receipt| {
+        gas_used += receipt.gas_used();
+        Ok(())
+    })?;
+    Ok(Some(gas_used))
+}
+
+fn read_sample(
+    settings: &dyn SettingsStore,
+    epoch: ChainEpoch,
+) -> anyhow::Result<Option<GasSample>> {
+    match settings.read_bin(&gas_history_key(epoch))? {
+        Some(bytes) => Ok(fvm_ipld_encoding::from_slice(&bytes)?),
+        None => Ok(None),
+    }
+}
+
+fn write_sample(
+    batch: &mut DbBatch,
+    epoch: ChainEpoch,
+    sample: Option<&GasSample>,
+) -> anyhow::Result<()> {
+    batch.write_bin(
+        &gas_history_key(epoch),
+        &fvm_ipld_encoding::to_vec(&sample)?,
+    );
+    Ok(())
+}
+
+fn aggregate(epoch: ChainEpoch, samples: &[GasSample]) -> Option<GasHistoryPoint> {
+    let min_base_fee = samples.iter().map(|s| &s.base_fee).min()?.clone();
+    let max_base_fee = samples.iter().map(|s| &s.base_fee).max()?.clone();
+    let total_base_fee: BigInt = samples.iter().map(|s| s.base_fee.atto()).sum();
+    let gas_used: u64 = samples.iter().map(|s| s.gas_used).sum();
+    let blocks: u64 = samples.iter().map(|s| s.blocks).sum();
+    Some(GasHistoryPoint {
+        epoch,
+        tipsets: samples.len() as u64,
+        base_fee: TokenAmount::from_atto(total_base_fee / samples.len()),
+        min_base_fee,
+        max_base_fee,
+        gas_used_ratio: gas_used as f64 / (blocks * BLOCK_GAS_TARGET) as f64,
+        message_count: samples.iter().map(|s| s.messages).sum(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(base_fee: u64, gas_used: u64, blocks: u64) -> GasSample {
+        GasSample {
+            block: Cid::default(),
+            base_fee: TokenAmount::from_atto(base_fee),
+            gas_used,
+            // Ignored by the aggregation.
+            gas_limit: gas_used * 2,
+            blocks,
+            messages: 2,
+        }
+    }
+
+    #[test]
+    fn aggregate_samples() {
+        assert_eq!(aggregate(10, &[]), None);
+        let point = aggregate(
+            10,
+            &[
+                sample(100, BLOCK_GAS_TARGET * 3, 2),
+                sample(200, BLOCK_GAS_TARGET, 2),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            point,
+            GasHistoryPoint {
+                epoch: 10,
+                tipsets: 2,
+                base_fee: TokenAmount::from_atto(150),
+                min_base_fee: TokenAmount::from_atto(100),
+                max_base_fee: TokenAmount::from_atto(200),
+                gas_used_ratio: 1.0,
+                message_count: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn query_samples() {
+        use crate::db::BatchStore;
+
+        let db = std::sync::Arc::new(crate::db::MemoryDB::default());
+        let mut batch = DbBatch::default();
+        for epoch in [1, 2, 3, 5] {
+            write_sample(&mut batch, epoch, Some(&sample(epoch as u64, 0, 1))).unwrap();
+        }
+        // Cleared sample of a reverted tipset.
+        write_sample(&mut batch, 4, None).unwrap();
+        db.commit_batch(batch).unwrap();
+        let genesis = crate::blocks::BlockHeader::builder()
+            .miner_address(crate::shim::address::Address::new_id(0))
+            .build()
+            .unwrap();
+        let cs = ChainStore::new(
+            db.clone(),
+            db,
+            std::sync::Arc::new(crate::networks::ChainConfig::default()),
+            genesis,
+        )
+        .unwrap();
+
+        let history = cs.gas_history(1, 6, 2).unwrap();
+        let summary: Vec<_> = history
+            .iter()
+            .map(|p| (p.epoch, p.tipsets, p.base_fee.atto().clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            [(1, 2, 1.into()), (3, 1, 3.into()), (5, 1, 5.into())]
+        );
+        assert!(cs.gas_history(1, 6, 0).is_err());
+        assert!(cs.gas_history(0, MAX_GAS_HISTORY_EPOCHS, 1000).is_err());
+        assert!(cs.gas_history(1, MAX_GAS_HISTORY_EPOCHS, 1000).is_ok());
+    }
+}
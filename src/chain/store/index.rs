@@ -13,7 +13,9 @@ use lru::LruCache;
 use nonzero_ext::nonzero;
 use parking_lot::Mutex;
 
-use crate::chain::Error;
+use crate::chain::{Error, MissingData};
+
+use super::state_cache::{BlockCache, CachedBlockstore};
 
 const DEFAULT_TIPSET_CACHE_SIZE: NonZeroUsize = nonzero!(8192usize);
 
@@ -23,7 +25,10 @@ type TipsetCache = Mutex<LruCache<TipsetKeys, Arc<Tipset>>>;
 /// be used to look-back at the chain to retrieve an old tipset.
 pub struct ChainIndex<DB> {
     /// `Arc` reference tipset cache.
-    ts_cache: TipsetCache,
+    ts_cache: Arc<TipsetCache>,
+
+    /// Cache of the state tree blocks read by the VM externs.
+    state_cache: Arc<BlockCache>,
 
     /// `Blockstore` pointer needed to load tipsets from cold storage.
     pub db: DB,
@@ -40,8 +45,35 @@ pub enum ResolveNullTipset {
 
 impl<DB: Blockstore> ChainIndex<DB> {
     pub fn new(db: DB) -> Self {
-        let ts_cache = Mutex::new(LruCache::new(DEFAULT_TIPSET_CACHE_SIZE));
-        Self { ts_cache, db }
+        let ts_cache = Arc::new(Mutex::new(LruCache::new(DEFAULT_TIPSET_CACHE_SIZE)));
+        Self {
+            ts_cache,
+            state_cache: Default::default(),
+            db,
+        }
+    }
+
+    /// Returns an index over `db` that shares the caches of this one, e.g. to
+    /// execute messages on an overlay of the chain store.
+    pub fn with_db<D: Blockstore>(&self, db: D) -> ChainIndex<D> {
+        ChainIndex {
+            ts_cache: Arc::clone(&self.ts_cache),
+            state_cache: Arc::clone(&self.state_cache),
+            db,
+        }
+    }
+
+    pub fn state_cache(&self) -> &BlockCache {
+        &self.state_cache
+    }
+
+    /// Blockstore reading state tree blocks through the state cache, see
+    /// [`state_cache`](super::state_cache).
+    pub fn state_store(&self) -> CachedBlockstore<DB>
+    where
+        DB: Clone,
+    {
+        CachedBlockstore::new(self.db.clone(), Arc::clone(&self.state_cache))
     }
 
     /// Loads a tipset from memory given the tipset keys and cache. Semantically
@@ -113,10 +145,11 @@ impl<DB: Blockstore> ChainIndex<DB> {
             return Ok(Arc::new(Tipset::from(from.genesis(&self.db)?)));
         }
         if to > from.epoch() {
-            return Err(Error::Other(format!(
-                "Looking for tipset {to} with height greater than start point {from}",
+            return Err(MissingData::not_yet_synced(format!(
+                "tipset {to}, the start point is at height {from}",
                 from = from.epoch()
-            )));
+            ))
+            .into());
         }
 
         for (child, parent) in self.chain(from).tuple_windows() {
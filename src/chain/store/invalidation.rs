@@ -0,0 +1,193 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Invalidation of the caches that follow the head of the chain.
+//!
+//! Every head change, the [`ChainStore`] publishes an [`Invalidation`] on
+//! [`ChainStore::invalidations`], with the tipsets reverted and applied since
+//! the previous head. Caches drop the entries the change affects, and only
+//! those: entries of reverted tipsets, and entries derived from the state of
+//! the actors that [`Invalidation::dirty_actors`] lists. Diffing the states is
+//! left to the listeners, off the head change path. Caches keyed by tipset keys
+//! or state roots, whose entries are immutable, don't need to listen.
+
+use crate::blocks::{Tipset, TipsetKeys};
+use crate::shim::address::Address;
+use crate::state_manager::diff::diff_state_trees;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use std::sync::Arc;
+use tracing::debug;
+
+use super::{ChainStore, PathChange};
+
+/// Longest path between two heads for which the changes are worked out. Past
+/// it, e.g. when a snapshot is imported, everything is invalidated.
+const MAX_PATH_LENGTH: i64 = 900;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invalidation {
+    /// Tipsets reverted from the previous head, newest first.
+    pub reverted: Vec<TipsetKeys>,
+    /// Tipsets applied up to the new head, oldest first.
+    pub applied: Vec<TipsetKeys>,
+    /// Parent states of the previous and the new head. `None` if the path
+    /// between the heads couldn't be worked out, in which case every actor is
+    /// dirty.
+    pub states: Option<(Cid, Cid)>,
+}
+
+impl Invalidation {
+    /// Invalidation of everything derived from the chain.
+    pub fn all() -> Self {
+        Self {
+            reverted: vec![],
+            applied: vec![],
+            states: None,
+        }
+    }
+
+    /// Changes from head `from` to head `to`.
+    pub fn between<DB: Blockstore>(cs: &ChainStore<DB>, from: &Tipset, to: &Tipset) -> Self {
+        if (from.epoch() - to.epoch()).abs() > MAX_PATH_LENGTH {
+            return Self::all();
+        }
+        let path = match cs.chain_get_path(from.key(), to.key()) {
+            Ok(path) => path,
+            Err(e) => {
+                debug!("no path between heads {} and {}: {e}", from.key(), to.key());
+                return Self::all();
+            }
+        };
+        let (mut reverted, mut applied) = (vec![], vec![]);
+        for change in path {
+            match change {
+                PathChange::Revert(ts) => reverted.push(ts.key().clone()),
+                PathChange::Apply(ts) => applied.push(ts.key().clone()),
+            }
+        }
+        Self {
+            reverted,
+            applied,
+            states: Some((*from.parent_state(), *to.parent_state())),
+        }
+    }
+
+    /// Actors whose state differs between the parent states of the previous
+    /// and the new head. `None` if they can't be worked out, in which case
+    /// every actor is dirty. Diffs the state trees, which can be slow.
+    pub fn dirty_actors<DB: Blockstore>(&self, db: &Arc<DB>) -> Option<Vec<Address>> {
+        let (from, to) = self.states?;
+        match diff_state_trees(db, &from, &to) {
+            Ok(actors) => Some(actors.into_iter().map(|actor| actor.address).collect()),
+            Err(e) => {
+                debug!("failed to diff the states {from} and {to}: {e}");
+                None
+            }
+        }
+    }
+
+    pub fn is_reverted(&self, tsk: &TipsetKeys) -> bool {
+        self.reverted.contains(tsk)
+    }
+}
+
+impl<DB: Blockstore> ChainStore<DB> {
+    /// Works out and publishes the changes from head `from` to head `to`, if
+    /// any cache listens to them.
+    pub(super) fn publish_invalidation(&self, from: &TipsetKeys, to: &Tipset) {
+        let invalidations = self.invalidations();
+        if invalidations.receiver_count() == 0 {
+            return;
+        }
+        let invalidation = match self.chain_index.load_tipset(from) {
+            Ok(from) => Invalidation::between(self, &from, to),
+            Err(_) => Invalidation::all(),
+        };
+        if invalidations.send(invalidation).is_err() {
+            debug!("did not publish invalidation, no active receivers");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::BlockHeader;
+    use crate::ipld::FrozenCids;
+    use crate::networks::ChainConfig;
+    use crate::shim::{
+        econ::TokenAmount,
+        state_tree::{ActorState, StateTree, StateTreeVersion},
+    };
+    use crate::utils::db::CborStoreExt;
+
+    #[test]
+    fn reorgs_invalidate_reverted_tipsets_and_dirty_actors() {
+        let db = Arc::new(crate::db::MemoryDB::default());
+        let state_root = |balance: u64| {
+            let mut tree = StateTree::new(db.clone(), StateTreeVersion::V5).unwrap();
+            for id in [100, 101] {
+                let balance = if id == 100 { balance } else { 1 };
+                let actor = ActorState::new(
+                    Cid::default(),
+                    Cid::default(),
+                    TokenAmount::from_atto(balance),
+                    0,
+                    None,
+                );
+                tree.set_actor(&Address::new_id(id), actor).unwrap();
+            }
+            tree.flush().unwrap()
+        };
+        let genesis = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .state_root(state_root(0))
+            .build()
+            .unwrap();
+        db.put_cbor_default(&genesis).unwrap();
+        let cs = ChainStore::new(
+            db.clone(),
+            db.clone(),
+            Arc::new(ChainConfig::default()),
+            genesis.clone(),
+        )
+        .unwrap();
+        let child = |parent: &BlockHeader, miner: u64, balance: u64| {
+            let header = BlockHeader::builder()
+                .miner_address(Address::new_id(miner))
+                .parents(TipsetKeys::new(FrozenCids::from_iter([*parent.cid()])))
+                .epoch(parent.epoch() + 1)
+                .state_root(state_root(balance))
+                .build()
+                .unwrap();
+            db.put_cbor_default(&header).unwrap();
+            Arc::new(Tipset::from(header))
+        };
+        let a1 = child(&genesis, 1, 0);
+        let a2 = child(a1.min_ticket_block(), 1, 5);
+        let b2 = child(a1.min_ticket_block(), 2, 7);
+
+        let mut invalidations = cs.invalidations().subscribe();
+        cs.set_heaviest_tipset(a2.clone()).unwrap();
+        invalidations.try_recv().unwrap();
+        cs.set_heaviest_tipset(b2.clone()).unwrap();
+        let invalidation = invalidations.try_recv().unwrap();
+        assert_eq!(invalidation.reverted, [a2.key().clone()]);
+        assert_eq!(invalidation.applied, [b2.key().clone()]);
+        assert_eq!(
+            invalidation.dirty_actors(&db),
+            Some(vec![Address::new_id(100)])
+        );
+        assert!(invalidation.is_reverted(a2.key()));
+
+        // Without a path to the new head, every actor is dirty.
+        let orphan = BlockHeader::builder()
+            .miner_address(Address::new_id(3))
+            .epoch(1)
+            .build()
+            .unwrap();
+        let invalidation = Invalidation::between(&cs, &b2, &Tipset::from(orphan));
+        assert_eq!(invalidation.dirty_actors(&db), None);
+    }
+}
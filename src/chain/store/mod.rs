@@ -4,7 +4,10 @@
 pub mod base_fee;
 mod chain_store;
 mod errors;
+pub mod gas_history;
 pub mod index;
+pub mod invalidation;
+pub mod state_cache;
 mod tipset_tracker;
 
 pub use self::{base_fee::*, chain_store::*, errors::*};
@@ -0,0 +1,276 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Cache of the state tree blocks read by the VM externs.
+//!
+//! Validating a block looks up the worker key of its miner in the state of a
+//! lookback tipset, which walks the state HAMT from its root. The blocks of the
+//! lookback states are shared by the blocks of an epoch and of the following
+//! ones, so they are cached by [`ChainIndex`](super::index::ChainIndex),
+//! bounded by their total size.
+//!
+//! The bit widths of the HAMTs and AMTs are consensus parameters and can't be
+//! tuned, only the size of the cache can, see [`StateCacheConfig`]. Reads are
+//! tracked for gas accounting above the cache, so the gas charged is the same
+//! whether blocks are cached or not. Cache sizes can be compared with
+//! `forest-tool benchmark state-cache`.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::metrics;
+use crate::utils::monitoring::meminfo_bytes;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use lru::LruCache;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_STATE_CACHE_SIZE: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[cfg_attr(test, derive(derive_quickcheck_arbitrary::Arbitrary))]
+pub enum StateCacheMode {
+    /// Blocks are always read from the database.
+    Disabled,
+    /// The cache holds up to `size` bytes.
+    #[default]
+    Fixed,
+    /// The cache is sized to `memory_percent` of the memory available when
+    /// the node starts, or to `size` if that can't be worked out.
+    Adaptive,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+#[cfg_attr(test, derive(derive_quickcheck_arbitrary::Arbitrary))]
+pub struct StateCacheConfig {
+    pub mode: StateCacheMode,
+    /// Size of the cache in bytes.
+    #[cfg_attr(test, arbitrary(gen(|g| u32::arbitrary(g) as _)))]
+    pub size: usize,
+    /// Share of the available memory used by the cache in
+    /// [`StateCacheMode::Adaptive`] mode, in percent.
+    pub memory_percent: u8,
+}
+
+impl Default for StateCacheConfig {
+    fn default() -> Self {
+        Self {
+            mode: StateCacheMode::default(),
+            size: DEFAULT_STATE_CACHE_SIZE,
+            memory_percent: 2,
+        }
+    }
+}
+
+impl StateCacheConfig {
+    /// Size of the cache in bytes.
+    pub fn capacity(&self) -> usize {
+        match self.mode {
+            StateCacheMode::Disabled => 0,
+            StateCacheMode::Fixed => self.size,
+            StateCacheMode::Adaptive => match available_memory() {
+                Some(available) => {
+                    (available / 100 * u64::from(self.memory_percent.min(100))) as usize
+                }
+                None => self.size,
+            },
+        }
+    }
+}
+
+/// Memory available to the node, in bytes. Only known on Linux.
+fn available_memory() -> Option<u64> {
+    parse_mem_available(&std::fs::read_to_string("/proc/meminfo").ok()?)
+}
+
+fn parse_mem_available(meminfo: &str) -> Option<u64> {
+    meminfo_bytes(meminfo, "MemAvailable")
+}
+
+#[derive(Debug)]
+struct Inner {
+    blocks: LruCache<Cid, Vec<u8>>,
+    /// Total size of the cached blocks.
+    size: usize,
+    capacity: usize,
+}
+
+impl Inner {
+    fn evict(&mut self) {
+        while self.size > self.capacity {
+            match self.blocks.pop_lru() {
+                Some((_, data)) => self.size -= data.len(),
+                None => break,
+            }
+        }
+    }
+}
+
+/// Blocks cache bounded by the total size of the blocks.
+#[derive(Debug)]
+pub struct BlockCache {
+    inner: Mutex<Inner>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Default for BlockCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_STATE_CACHE_SIZE)
+    }
+}
+
+impl BlockCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                blocks: LruCache::unbounded(),
+                size: 0,
+                capacity,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.lock().capacity
+    }
+
+    /// Resizes the cache, evicting the least recently used blocks if needed.
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut inner = self.inner.lock();
+        inner.capacity = capacity;
+        inner.evict();
+    }
+
+    /// Total size of the cached blocks.
+    pub fn size(&self) -> usize {
+        self.inner.lock().size
+    }
+
+    /// Numbers of cache hits and misses.
+    pub fn stats(&self) -> (u64, u64) {
+        (
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+        )
+    }
+
+    fn get(&self, cid: &Cid) -> Option<Vec<u8>> {
+        let data = self.inner.lock().blocks.get(cid).cloned();
+        let (counter, metric) = match data.is_some() {
+            true => (&self.hits, &**metrics::LRU_CACHE_HIT),
+            false => (&self.misses, &**metrics::LRU_CACHE_MISS),
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        metric
+            .with_label_values(&[metrics::values::STATE_BLOCK])
+            .inc();
+        data
+    }
+
+    fn put(&self, cid: Cid, data: &[u8]) {
+        let mut inner = self.inner.lock();
+        if data.len() > inner.capacity {
+            return;
+        }
+        inner.size += data.len();
+        if let Some(previous) = inner.blocks.put(cid, data.to_vec()) {
+            inner.size -= previous.len();
+        }
+        inner.evict();
+    }
+}
+
+/// Blockstore reading through a [`BlockCache`].
+pub struct CachedBlockstore<DB> {
+    db: DB,
+    cache: Arc<BlockCache>,
+}
+
+impl<DB> CachedBlockstore<DB> {
+    pub fn new(db: DB, cache: Arc<BlockCache>) -> Self {
+        Self { db, cache }
+    }
+}
+
+impl<DB: Blockstore> Blockstore for CachedBlockstore<DB> {
+    fn get(&self, k: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
+        if let Some(data) = self.cache.get(k) {
+            return Ok(Some(data));
+        }
+        let data = self.db.get(k)?;
+        if let Some(data) = &data {
+            self.cache.put(*k, data);
+        }
+        Ok(data)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> anyhow::Result<()> {
+        self.db.put_keyed(k, block)
+    }
+
+    fn has(&self, k: &Cid) -> anyhow::Result<bool> {
+        if self.cache.inner.lock().blocks.contains(k) {
+            return Ok(true);
+        }
+        self.db.has(k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MemoryDB;
+    use cid::multihash::{Code, MultihashDigest};
+    use fvm_ipld_encoding::DAG_CBOR;
+
+    fn put(db: &MemoryDB, data: &[u8]) -> Cid {
+        let cid = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(data));
+        db.put_keyed(&cid, data).unwrap();
+        cid
+    }
+
+    #[test]
+    fn cache_is_bounded_by_size() {
+        let db = MemoryDB::default();
+        let (a, b, c) = (put(&db, &[1; 40]), put(&db, &[2; 40]), put(&db, &[3; 40]));
+        let cache = Arc::new(BlockCache::new(100));
+        let store = CachedBlockstore::new(&db, cache.clone());
+
+        store.get(&a).unwrap();
+        store.get(&b).unwrap();
+        store.get(&a).unwrap();
+        assert_eq!(cache.stats(), (1, 2));
+        assert_eq!(cache.size(), 80);
+
+        // `b` is the least recently used block.
+        store.get(&c).unwrap();
+        assert_eq!(cache.size(), 80);
+        store.get(&a).unwrap();
+        store.get(&b).unwrap();
+        assert_eq!(cache.stats(), (2, 4));
+
+        cache.set_capacity(0);
+        assert_eq!(cache.size(), 0);
+        assert_eq!(store.get(&c).unwrap(), Some(vec![3; 40]));
+        assert_eq!(cache.size(), 0);
+    }
+
+    #[test]
+    fn adaptive_size() {
+        let meminfo = "MemTotal:       16303932 kB\nMemAvailable:    8000000 kB\n";
+        assert_eq!(parse_mem_available(meminfo), Some(8_192_000_000));
+        assert_eq!(parse_mem_available("MemTotal: 1 kB"), None);
+        let disabled = StateCacheConfig {
+            mode: StateCacheMode::Disabled,
+            ..Default::default()
+        };
+        assert_eq!(disabled.capacity(), 0);
+        assert_eq!(StateCacheConfig::default().capacity(), 64 * 1024 * 1024);
+    }
+}
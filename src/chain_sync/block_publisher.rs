@@ -0,0 +1,232 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::sync::Arc;
+
+use crate::blocks::{Block, FullTipset, GossipBlock, Tipset};
+use crate::chain::ChainStore;
+use crate::chain_sync::{
+    bad_block_cache::BadBlockCache, chain_muxer::ChainMuxerError, clock_skew::ClockSkew,
+    validation::TipsetValidator,
+};
+use crate::libp2p::{NetworkMessage, Topic, PUBSUB_BLOCK_STR};
+use crate::message::SignedMessage;
+use crate::shim::message::Message;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::CborStore;
+use serde::de::DeserializeOwned;
+use tracing::{info, warn};
+
+/// Broadcasts the blocks produced by this node on the `/fil/blocks` topic.
+///
+/// Blocks go through the same validation as the blocks received over
+/// `GossipSub` before they are published, and are then synced like them.
+/// Handles are taken from the [`ChainMuxer`](super::ChainMuxer), see
+/// [`ChainMuxer::block_publisher`](super::ChainMuxer::block_publisher).
+pub struct BlockPublisher<DB> {
+    chain_store: Arc<ChainStore<DB>>,
+    bad_blocks: Arc<BadBlockCache>,
+    clock_skew: Arc<ClockSkew>,
+    genesis: Arc<Tipset>,
+    block_delay: u64,
+    network_send: flume::Sender<NetworkMessage>,
+    network_name: String,
+    tipset_sender: flume::Sender<Arc<Tipset>>,
+}
+
+impl<DB> Clone for BlockPublisher<DB> {
+    fn clone(&self) -> Self {
+        Self {
+            chain_store: self.chain_store.clone(),
+            bad_blocks: self.bad_blocks.clone(),
+            clock_skew: self.clock_skew.clone(),
+            genesis: self.genesis.clone(),
+            block_delay: self.block_delay,
+            network_send: self.network_send.clone(),
+            network_name: self.network_name.clone(),
+            tipset_sender: self.tipset_sender.clone(),
+        }
+    }
+}
+
+impl<DB> BlockPublisher<DB>
+where
+    DB: Blockstore + Sync + Send + 'static,
+{
+    #[allow(clippy::too_many_arguments)]
+    pub(in crate::chain_sync) fn new(
+        chain_store: Arc<ChainStore<DB>>,
+        bad_blocks: Arc<BadBlockCache>,
+        clock_skew: Arc<ClockSkew>,
+        genesis: Arc<Tipset>,
+        block_delay: u64,
+        network_send: flume::Sender<NetworkMessage>,
+        network_name: String,
+        tipset_sender: flume::Sender<Arc<Tipset>>,
+    ) -> Self {
+        Self {
+            chain_store,
+            bad_blocks,
+            clock_skew,
+            genesis,
+            block_delay,
+            network_send,
+            network_name,
+            tipset_sender,
+        }
+    }
+
+    /// Validates a locally produced block and publishes it. The messages of
+    /// the block have to be in the block store already. A warning is logged
+    /// while the local clock seems skewed, as peers may reject the block.
+    pub async fn publish(&self, block: GossipBlock) -> Result<(), ChainMuxerError> {
+        if let Some(estimate) = self.clock_skew.estimate().filter(|e| e.skewed) {
+            warn!(
+                "Publishing block {} while the local clock is off by about {}s ({:?})",
+                block.header.cid(),
+                estimate.offset_secs,
+                estimate.source
+            );
+        }
+
+        // Blocks on unknown parents would not be synced by peers either.
+        self.chain_store
+            .tipset_from_keys(block.header.parents())
+            .map_err(|e| ChainMuxerError::PublishBlock(format!("unknown parents: {e}")))?;
+
+        let tipset = FullTipset::from(Block {
+            header: block.header.clone(),
+            bls_messages: self.load_messages::<Message>(&block.bls_messages)?,
+            secp_messages: self.load_messages::<SignedMessage>(&block.secpk_messages)?,
+        });
+        TipsetValidator(&tipset).validate(
+            self.chain_store.clone(),
+            self.bad_blocks.clone(),
+            self.genesis.clone(),
+            self.block_delay,
+        )?;
+        crate::chain::persist_objects(&self.chain_store.db, &[&block.header])?;
+
+        let message = fvm_ipld_encoding::to_vec(&block)
+            .map_err(|e| ChainMuxerError::PublishBlock(e.to_string()))?;
+        self.network_send
+            .send_async(NetworkMessage::PubsubMessage {
+                topic: Topic::new(format!("{PUBSUB_BLOCK_STR}/{}", self.network_name)),
+                message,
+            })
+            .await
+            .map_err(|e| ChainMuxerError::PublishBlock(e.to_string()))?;
+        info!(
+            "Published block {} at epoch {}",
+            block.header.cid(),
+            block.header.epoch()
+        );
+
+        // Peers don't send our own blocks back, so they are synced directly.
+        self.tipset_sender
+            .send_async(Arc::new(tipset.into_tipset()))
+            .await
+            .map_err(|e| ChainMuxerError::TipsetChannelSend(e.to_string()))
+    }
+
+    fn load_messages<T: DeserializeOwned>(&self, cids: &[Cid]) -> Result<Vec<T>, ChainMuxerError> {
+        cids.iter()
+            .map(|cid| {
+                self.chain_store
+                    .blockstore()
+                    .get_cbor(cid)
+                    .map_err(|e| ChainMuxerError::PublishBlock(e.to_string()))?
+                    .ok_or_else(|| {
+                        ChainMuxerError::PublishBlock(format!("message {cid} is missing"))
+                    })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::BlockHeader;
+    use crate::db::MemoryDB;
+    use crate::networks::ChainConfig;
+    use crate::shim::address::Address;
+
+    #[tokio::test]
+    async fn publish_block() {
+        let db = Arc::new(MemoryDB::default());
+        let genesis = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .build()
+            .unwrap();
+        crate::chain::persist_objects(&db, &[&genesis]).unwrap();
+        let chain_store = Arc::new(
+            ChainStore::new(
+                db.clone(),
+                db.clone(),
+                Arc::new(ChainConfig::default()),
+                genesis.clone(),
+            )
+            .unwrap(),
+        );
+        let genesis = Arc::new(Tipset::from(genesis));
+        let (network_send, network_rx) = flume::unbounded();
+        let (tipset_sender, tipset_rx) = flume::unbounded();
+        let clock_skew = Arc::new(ClockSkew::new(10));
+        let publisher = BlockPublisher::new(
+            chain_store,
+            Default::default(),
+            clock_skew.clone(),
+            genesis.clone(),
+            30,
+            network_send,
+            "test".into(),
+            tipset_sender,
+        );
+
+        let messages = TipsetValidator::compute_msg_root(&db, &[], &[]).unwrap();
+        let header = BlockHeader::builder()
+            .miner_address(Address::new_id(1000))
+            .parents(genesis.key().clone())
+            .epoch(1)
+            .messages(messages)
+            .build()
+            .unwrap();
+        let block = GossipBlock {
+            header: header.clone(),
+            bls_messages: vec![],
+            secpk_messages: vec![],
+        };
+        publisher.publish(block.clone()).await.unwrap();
+
+        let NetworkMessage::PubsubMessage { topic, message } = network_rx.try_recv().unwrap()
+        else {
+            panic!("expected a pubsub message");
+        };
+        assert_eq!(topic.to_string(), "/fil/blocks/test");
+        assert_eq!(
+            fvm_ipld_encoding::from_slice::<GossipBlock>(&message).unwrap(),
+            block
+        );
+        assert_eq!(tipset_rx.try_recv().unwrap().blocks(), [header]);
+
+        // Blocks with messages that are missing from the store are rejected.
+        let missing = GossipBlock {
+            bls_messages: vec![messages],
+            ..block.clone()
+        };
+        assert!(publisher.publish(missing).await.is_err());
+        assert!(network_rx.is_empty());
+
+        // The clock skew is only reported, blocks are still published.
+        let future =
+            crate::shim::clock::ALLOWABLE_CLOCK_DRIFT + 60 + chrono::Utc::now().timestamp() as u64;
+        for _ in 0..5 {
+            clock_skew.record_block(future);
+        }
+        assert!(clock_skew.estimate().unwrap().skewed);
+        publisher.publish(block).await.unwrap();
+        assert!(!network_rx.is_empty());
+    }
+}
@@ -13,7 +13,8 @@ use crate::blocks::{
 };
 use crate::chain::{ChainStore, Error as ChainStoreError};
 use crate::libp2p::{
-    hello::HelloRequest, NetworkEvent, NetworkMessage, PeerId, PeerManager, PubsubMessage,
+    hello::HelloRequest, record_gossip_rejected, NetworkEvent, NetworkMessage, PeerId, PeerManager,
+    PeerPenalty, PubsubMessage, PUBSUB_BLOCK_STR, PUBSUB_MSG_STR,
 };
 use crate::message::SignedMessage;
 use crate::message_pool::{MessagePool, Provider};
@@ -33,6 +34,9 @@ use tracing::{debug, error, info, trace, warn};
 
 use crate::chain_sync::{
     bad_block_cache::BadBlockCache,
+    block_publisher::BlockPublisher,
+    clock_skew::ClockSkew,
+    execution::{execution_stage, ExecutionStage},
     metrics,
     network_context::SyncNetworkContext,
     sync_state::SyncState,
@@ -68,6 +72,8 @@ pub enum ChainMuxerError {
     Block(#[from] ForestBlockError),
     #[error("Following network unexpectedly failed: {0}")]
     NetworkFollowingFailure(String),
+    #[error("Publishing block failed: {0}")]
+    PublishBlock(String),
 }
 
 /// Structure that defines syncing configuration options
@@ -80,6 +86,19 @@ pub struct SyncConfig {
     /// head is
     #[cfg_attr(test, arbitrary(gen(|g| u32::arbitrary(g) as _)))]
     pub tipset_sample_size: usize,
+    /// Optional NTP server, e.g. `pool.ntp.org:123`, used to check the local
+    /// clock. The gossiped blocks are used otherwise.
+    #[serde(default)]
+    pub ntp_server: Option<String>,
+    /// Append the states computed while validating tipsets to a hash-chained
+    /// audit log, see [`audit_log`](crate::state_manager::audit_log).
+    #[serde(default)]
+    pub audit_log: bool,
+    /// Follow the chain without executing messages, as a header relay node:
+    /// blocks are only checked against the rules that need a few nodes of the
+    /// states at most, see [`execution`](super::execution).
+    #[serde(default)]
+    pub headers_only: bool,
 }
 
 impl Default for SyncConfig {
@@ -87,6 +106,9 @@ impl Default for SyncConfig {
         Self {
             req_window: 200,
             tipset_sample_size: 5,
+            ntp_server: None,
+            audit_log: false,
+            headers_only: false,
         }
     }
 }
@@ -138,11 +160,15 @@ pub struct ChainMuxer<DB, M> {
     /// cache
     bad_blocks: Arc<BadBlockCache>,
 
+    /// Offset of the local clock, estimated from the gossiped blocks
+    clock_skew: Arc<ClockSkew>,
+
     /// Incoming network events to be handled by synchronizer
     net_handler: flume::Receiver<NetworkEvent>,
 
-    /// Message pool
-    mpool: Arc<MessagePool<M>>,
+    /// Message pool. Gossiped messages are dropped if the node runs without
+    /// one.
+    mpool: Option<Arc<MessagePool<M>>>,
 
     /// Tipset channel sender
     tipset_sender: flume::Sender<Arc<Tipset>>,
@@ -152,6 +178,16 @@ pub struct ChainMuxer<DB, M> {
 
     /// Syncing configurations
     sync_config: SyncConfig,
+
+    /// Checks of the blocks against the states of their parents, see
+    /// [`SyncConfig::headers_only`]
+    execution: Arc<dyn ExecutionStage<DB>>,
+
+    /// Network name, e.g. `calibrationnet`, used in the `GossipSub` topics
+    network_name: String,
+
+    /// Sender of the messages published by this node
+    network_send: flume::Sender<NetworkMessage>,
 }
 
 impl<DB, M> ChainMuxer<DB, M>
@@ -163,17 +199,24 @@ where
     pub fn new(
         state_manager: Arc<StateManager<DB>>,
         peer_manager: Arc<PeerManager>,
-        mpool: Arc<MessagePool<M>>,
+        mpool: Option<Arc<MessagePool<M>>>,
         network_send: flume::Sender<NetworkMessage>,
         network_rx: flume::Receiver<NetworkEvent>,
         genesis: Arc<Tipset>,
         tipset_sender: flume::Sender<Arc<Tipset>>,
         tipset_receiver: flume::Receiver<Arc<Tipset>>,
         cfg: SyncConfig,
+        network_name: String,
     ) -> Result<Self, ChainMuxerError> {
-        let network =
-            SyncNetworkContext::new(network_send, peer_manager, state_manager.blockstore_owned());
+        let network = SyncNetworkContext::new(
+            network_send.clone(),
+            peer_manager,
+            state_manager.blockstore_owned(),
+        );
 
+        let clock_skew = Arc::new(ClockSkew::new(
+            state_manager.chain_config().propagation_delay_secs,
+        ));
         Ok(Self {
             state: ChainMuxerState::Idle,
             worker_state: Default::default(),
@@ -181,11 +224,15 @@ where
             genesis,
             state_manager,
             bad_blocks: Arc::new(BadBlockCache::default()),
+            clock_skew,
             net_handler: network_rx,
             mpool,
             tipset_sender,
             tipset_receiver,
+            execution: execution_stage(&cfg, network_send.clone()),
             sync_config: cfg,
+            network_name,
+            network_send,
         })
     }
 
@@ -195,11 +242,32 @@ where
         self.bad_blocks.clone()
     }
 
+    /// Returns a clone of the clock skew estimation to be used outside of
+    /// chain sync.
+    pub fn clock_skew_cloned(&self) -> Arc<ClockSkew> {
+        self.clock_skew.clone()
+    }
+
     /// Returns a cloned `Arc` of the sync worker state.
     pub fn sync_state_cloned(&self) -> WorkerState {
         self.worker_state.clone()
     }
 
+    /// Returns a handle to publish the blocks produced by this node, to be
+    /// used outside of chain sync.
+    pub fn block_publisher(&self) -> BlockPublisher<DB> {
+        BlockPublisher::new(
+            self.state_manager.chain_store().clone(),
+            self.bad_blocks.clone(),
+            self.clock_skew.clone(),
+            self.genesis.clone(),
+            self.state_manager.chain_config().block_delay_secs,
+            self.network_send.clone(),
+            self.network_name.clone(),
+            self.tipset_sender.clone(),
+        )
+    }
+
     async fn get_full_tipset(
         network: SyncNetworkContext<DB>,
         chain_store: Arc<ChainStore<DB>>,
@@ -256,6 +324,8 @@ where
                 heaviest_tipset_height: heaviest.epoch(),
                 heaviest_tipset_weight: heaviest.weight().clone().into(),
                 genesis_cid: genesis_block_cid,
+                // Filled in by the network service.
+                identity: None,
             };
             let (peer_id, moment_sent, response) =
                 match network.hello_request(peer_id, request).await {
@@ -333,6 +403,7 @@ where
 
     fn handle_pubsub_message(mem_pool: Arc<MessagePool<M>>, message: SignedMessage) {
         if let Err(why) = mem_pool.add(message) {
+            record_gossip_rejected(PUBSUB_MSG_STR);
             debug!(
                 "GossipSub message could not be added to the mem pool: {}",
                 why
@@ -346,11 +417,13 @@ where
         network: SyncNetworkContext<DB>,
         chain_store: Arc<ChainStore<DB>>,
         bad_block_cache: Arc<BadBlockCache>,
-        mem_pool: Arc<MessagePool<M>>,
+        clock_skew: Arc<ClockSkew>,
+        mem_pool: Option<Arc<MessagePool<M>>>,
         genesis: Arc<Tipset>,
         message_processing_strategy: PubsubMessageProcessingStrategy,
         block_delay: u64,
     ) -> Result<Option<(FullTipset, PeerId)>, ChainMuxerError> {
+        let from_gossip = matches!(event, NetworkEvent::PubsubMessage { .. });
         let (tipset, source) = match event {
             NetworkEvent::HelloRequestInbound { source, request } => {
                 metrics::LIBP2P_MESSAGE_TOTAL
@@ -359,6 +432,15 @@ where
                 metrics::PEER_TIPSET_EPOCH
                     .with_label_values(&[source.to_string().as_str()])
                     .set(request.heaviest_tipset_height);
+                network
+                    .peer_manager()
+                    .update_peer_head_hint(
+                        source,
+                        TipsetKeys::from(request.heaviest_tip_set),
+                        request.heaviest_tipset_height,
+                        request.heaviest_tipset_weight.inner().clone(),
+                    )
+                    .await;
                 return Ok(None);
             }
             NetworkEvent::HelloResponseOutbound { request, source } => {
@@ -436,7 +518,9 @@ where
                     metrics::LIBP2P_MESSAGE_TOTAL
                         .with_label_values(&[metrics::values::PUBSUB_MESSAGE])
                         .inc();
-                    if let PubsubMessageProcessingStrategy::Process = message_processing_strategy {
+                    if let (PubsubMessageProcessingStrategy::Process, Some(mem_pool)) =
+                        (message_processing_strategy, mem_pool)
+                    {
                         Self::handle_pubsub_message(mem_pool, m);
                     }
                     return Ok(None);
@@ -486,13 +570,35 @@ where
             block_delay,
         ) {
             metrics::INVALID_TIPSET_TOTAL.inc();
+            if from_gossip {
+                record_gossip_rejected(PUBSUB_BLOCK_STR);
+            }
             warn!(
                 "Validating tipset received through GossipSub failed: {}",
                 why
             );
+            // Tipsets ahead of the local clock may be caused by clock skew,
+            // and blocks found in the bad block cache may be relayed in good
+            // faith: only malformed tipsets are the fault of the peer.
+            if !matches!(
+                *why,
+                TipsetValidationError::EpochTooLarge | TipsetValidationError::InvalidBlock(..)
+            ) {
+                network
+                    .peer_manager()
+                    .penalize(source, PeerPenalty::InvalidTipset)
+                    .await;
+            }
             return Err(why.into());
         }
 
+        // Only validated blocks contribute to the clock skew estimation.
+        if from_gossip {
+            for block in tipset.blocks() {
+                clock_skew.record_block(block.header().timestamp());
+            }
+        }
+
         // Store block messages in the block store
         for block in tipset.blocks() {
             crate::chain::persist_objects(&chain_store.db, &[block.header()])?;
@@ -519,6 +625,7 @@ where
         let network = self.network.clone();
         let genesis = self.genesis.clone();
         let bad_block_cache = self.bad_blocks.clone();
+        let clock_skew = self.clock_skew.clone();
         let mem_pool = self.mpool.clone();
         let tipset_sample_size = self.sync_config.tipset_sample_size;
         let block_delay = self.state_manager.chain_config().block_delay_secs;
@@ -539,6 +646,7 @@ where
                     network.clone(),
                     chain_store.clone(),
                     bad_block_cache.clone(),
+                    clock_skew.clone(),
                     mem_pool.clone(),
                     genesis.clone(),
                     PubsubMessageProcessingStrategy::Process,
@@ -598,6 +706,7 @@ where
     ) -> ChainMuxerFuture<(), ChainMuxerError> {
         // Instantiate a TipsetRangeSyncer
         let trs_state_manager = self.state_manager.clone();
+        let trs_execution = self.execution.clone();
         let trs_bad_block_cache = self.bad_blocks.clone();
         let trs_chain_store = self.state_manager.chain_store().clone();
         let trs_network = self.network.clone();
@@ -610,6 +719,7 @@ where
                 Arc::new(network_head.into_tipset()),
                 local_head,
                 trs_state_manager,
+                trs_execution,
                 trs_network,
                 trs_chain_store,
                 trs_bad_block_cache,
@@ -637,6 +747,7 @@ where
         let network = self.network.clone();
         let genesis = self.genesis.clone();
         let bad_block_cache = self.bad_blocks.clone();
+        let clock_skew = self.clock_skew.clone();
         let mem_pool = self.mpool.clone();
         let block_delay = self.state_manager.chain_config().block_delay_secs;
         let stream_processor: ChainMuxerFuture<(), ChainMuxerError> = Box::pin(async move {
@@ -654,6 +765,7 @@ where
                     network.clone(),
                     chain_store.clone(),
                     bad_block_cache.clone(),
+                    clock_skew.clone(),
                     mem_pool.clone(),
                     genesis.clone(),
                     PubsubMessageProcessingStrategy::DoNotProcess,
@@ -694,6 +806,7 @@ where
     fn follow(&self, tipset_opt: Option<FullTipset>) -> ChainMuxerFuture<(), ChainMuxerError> {
         // Instantiate a TipsetProcessor
         let tp_state_manager = self.state_manager.clone();
+        let tp_execution = self.execution.clone();
         let tp_network = self.network.clone();
         let tp_chain_store = self.state_manager.chain_store().clone();
         let tp_bad_block_cache = self.bad_blocks.clone();
@@ -709,6 +822,7 @@ where
                     tp_tracker,
                     Box::pin(tp_tipset_receiver.into_stream()),
                     tp_state_manager,
+                    tp_execution,
                     tp_network,
                     tp_chain_store,
                     tp_bad_block_cache,
@@ -727,6 +841,7 @@ where
         let network = self.network.clone();
         let genesis = self.genesis.clone();
         let bad_block_cache = self.bad_blocks.clone();
+        let clock_skew = self.clock_skew.clone();
         let mem_pool = self.mpool.clone();
         let tipset_sender = self.tipset_sender.clone();
         let block_delay = self.state_manager.chain_config().block_delay_secs;
@@ -756,6 +871,7 @@ where
                         network.clone(),
                         chain_store.clone(),
                         bad_block_cache.clone(),
+                        clock_skew.clone(),
                         mem_pool.clone(),
                         genesis.clone(),
                         PubsubMessageProcessingStrategy::Process,
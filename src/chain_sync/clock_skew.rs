@@ -0,0 +1,248 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Estimation of the offset of the local clock from the network time. The
+//! timestamps of the blocks are the start of their epoch, and miners publish
+//! their blocks after the propagation delay of the network, so the median delay
+//! between the timestamp of a validated block and its arrival, minus the
+//! propagation delay, estimates the offset. An NTP server, if configured, gives
+//! a precise offset instead. The estimation is advisory: it is reported in the
+//! logs, the metrics and the node status.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::chain_sync::metrics;
+use crate::shim::clock::ALLOWABLE_CLOCK_DRIFT;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::net::UdpSocket;
+use tracing::{info, warn};
+
+/// Number of recent gossiped blocks used for the estimation.
+const BLOCK_SAMPLES: usize = 50;
+/// Minimum number of gossiped blocks before an estimation is made.
+const MIN_BLOCK_SAMPLES: usize = 5;
+/// Offset beyond which a clock that is behind is skewed. Such a clock makes the
+/// node reject the blocks of its peers as coming from the future.
+const MAX_BEHIND_SECS: i64 = ALLOWABLE_CLOCK_DRIFT as i64;
+/// Offset beyond which a clock that is ahead is skewed. Such a clock makes the
+/// node produce blocks that its peers reject. Blocks arrive after the time it
+/// takes to mine and gossip them, hence the larger margin.
+const MAX_AHEAD_SECS: i64 = 6;
+/// Interval between two queries to the NTP server.
+const NTP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Seconds between the NTP epoch (1900) and the Unix epoch.
+const NTP_UNIX_OFFSET_SECS: u64 = 2_208_988_800;
+
+/// Where an estimation of the clock skew comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClockSkewSource {
+    Ntp,
+    Blocks,
+}
+
+/// Estimated offset of the local clock, positive if it is ahead of the
+/// network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClockSkewEstimate {
+    pub offset_secs: i64,
+    pub source: ClockSkewSource,
+    pub skewed: bool,
+}
+
+#[derive(Debug, Default)]
+struct Samples {
+    /// Arrival time minus timestamp minus propagation delay of the recent
+    /// validated gossiped blocks, in seconds.
+    blocks: VecDeque<i64>,
+    ntp: Option<i64>,
+    skewed: bool,
+}
+
+/// Tracks the offset of the local clock. Shared by the chain muxer, which
+/// records the validated gossiped blocks, the block publisher and the RPC.
+#[derive(Debug, Default)]
+pub struct ClockSkew {
+    samples: Mutex<Samples>,
+    /// Delay after the start of an epoch before its blocks are published.
+    propagation_delay_secs: i64,
+}
+
+impl ClockSkew {
+    pub fn new(propagation_delay_secs: u64) -> Self {
+        Self {
+            samples: Default::default(),
+            propagation_delay_secs: propagation_delay_secs as i64,
+        }
+    }
+
+    /// Records a validated block received over `GossipSub` with the given
+    /// timestamp.
+    pub fn record_block(&self, timestamp: u64) {
+        self.record_block_at(timestamp, now_secs());
+    }
+
+    fn record_block_at(&self, timestamp: u64, now: i64) {
+        let mut samples = self.samples.lock();
+        if samples.blocks.len() == BLOCK_SAMPLES {
+            samples.blocks.pop_front();
+        }
+        samples
+            .blocks
+            .push_back(now - timestamp as i64 - self.propagation_delay_secs);
+        Self::update(&mut samples);
+    }
+
+    fn record_ntp(&self, offset_secs: i64) {
+        let mut samples = self.samples.lock();
+        samples.ntp = Some(offset_secs);
+        Self::update(&mut samples);
+    }
+
+    /// Returns the current estimation, if enough data is available.
+    pub fn estimate(&self) -> Option<ClockSkewEstimate> {
+        Self::estimate_from(&self.samples.lock())
+    }
+
+    fn estimate_from(samples: &Samples) -> Option<ClockSkewEstimate> {
+        let (offset_secs, source) = match samples.ntp {
+            Some(offset) => (offset, ClockSkewSource::Ntp),
+            None if samples.blocks.len() >= MIN_BLOCK_SAMPLES => {
+                (median(&samples.blocks), ClockSkewSource::Blocks)
+            }
+            None => return None,
+        };
+        Some(ClockSkewEstimate {
+            offset_secs,
+            source,
+            skewed: !(-MAX_BEHIND_SECS..=MAX_AHEAD_SECS).contains(&offset_secs),
+        })
+    }
+
+    /// Logs the changes of the skew status.
+    fn update(samples: &mut Samples) {
+        let Some(estimate) = Self::estimate_from(samples) else {
+            return;
+        };
+        metrics::CLOCK_SKEW_SECONDS.set(estimate.offset_secs);
+        if estimate.skewed && !samples.skewed {
+            warn!(
+                "The local clock is off by about {}s ({:?}), blocks may be rejected. Please synchronize the system clock.",
+                estimate.offset_secs, estimate.source
+            );
+        } else if !estimate.skewed && samples.skewed {
+            info!("The local clock is synchronized again");
+        }
+        samples.skewed = estimate.skewed;
+    }
+}
+
+/// Median of the samples, robust to a few peers gossiping blocks with
+/// misleading timestamps.
+fn median(samples: &VecDeque<i64>) -> i64 {
+    let mut sorted: Vec<_> = samples.iter().copied().collect();
+    sorted.sort_unstable();
+    let mid = sorted.len() / 2;
+    if sorted.len() % 2 == 0 {
+        (sorted[mid - 1] + sorted[mid]).div_euclid(2)
+    } else {
+        sorted[mid]
+    }
+}
+
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Queries `server`, e.g. `pool.ntp.org:123`, periodically and records the
+/// offset of the local clock.
+pub async fn watch_ntp(server: String, clock_skew: Arc<ClockSkew>) -> anyhow::Result<()> {
+    let mut interval = tokio::time::interval(NTP_INTERVAL);
+    loop {
+        interval.tick().await;
+        match query_ntp(&server).await {
+            Ok(offset) => clock_skew.record_ntp(offset),
+            Err(e) => warn!("Failed to query the NTP server {server}: {e}"),
+        }
+    }
+}
+
+/// Returns the offset of the local clock from the time of an (S)NTP server, in
+/// seconds. The server time is corrected by half the round-trip time.
+async fn query_ntp(server: &str) -> anyhow::Result<i64> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(server).await?;
+    // Leap indicator 0, version 3, client mode.
+    let mut request = [0u8; 48];
+    request[0] = 0x1b;
+    let sent = SystemTime::now();
+    socket.send(&request).await?;
+    let mut response = [0u8; 48];
+    let len = tokio::time::timeout(Duration::from_secs(5), socket.recv(&mut response)).await??;
+    let received = SystemTime::now();
+    anyhow::ensure!(len == response.len(), "invalid NTP response of {len} bytes");
+
+    // Transmit timestamp of the server.
+    let seconds = u32::from_be_bytes(response[40..44].try_into()?) as u64;
+    let fraction = u32::from_be_bytes(response[44..48].try_into()?) as u64;
+    anyhow::ensure!(seconds >= NTP_UNIX_OFFSET_SECS, "invalid NTP timestamp");
+    let server_time = UNIX_EPOCH
+        + Duration::from_secs(seconds - NTP_UNIX_OFFSET_SECS)
+        + Duration::from_nanos((fraction * 1_000_000_000) >> 32);
+    let round_trip = received.duration_since(sent).unwrap_or_default();
+    let network_time = server_time + round_trip / 2;
+    Ok(match received.duration_since(network_time) {
+        Ok(ahead) => ahead.as_secs_f64().round() as i64,
+        Err(behind) => -(behind.duration().as_secs_f64().round() as i64),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimate_from_blocks() {
+        let skew = ClockSkew::new(10);
+        let now = 1_000_000;
+        for delay in [13, 12, 14, 18] {
+            skew.record_block_at(now - delay, now as i64);
+        }
+        // Too few blocks.
+        assert_eq!(skew.estimate(), None);
+
+        // The propagation delay is expected.
+        skew.record_block_at(now - 15, now as i64);
+        assert_eq!(
+            skew.estimate(),
+            Some(ClockSkewEstimate {
+                offset_secs: 4,
+                source: ClockSkewSource::Blocks,
+                skewed: false,
+            })
+        );
+
+        // A few blocks from the future barely move the estimation.
+        skew.record_block_at(now + 600, now as i64);
+        skew.record_block_at(now + 600, now as i64);
+        assert_eq!(skew.estimate().unwrap().offset_secs, 3);
+        assert!(!skew.estimate().unwrap().skewed);
+
+        // Most blocks from the future mean that the local clock is behind.
+        for _ in 0..5 {
+            skew.record_block_at(now + 10, now as i64);
+        }
+        assert_eq!(skew.estimate().unwrap().offset_secs, -20);
+        assert!(skew.estimate().unwrap().skewed);
+
+        // NTP takes precedence.
+        skew.record_ntp(0);
+        assert_eq!(skew.estimate().unwrap().source, ClockSkewSource::Ntp);
+        assert!(!skew.estimate().unwrap().skewed);
+    }
+}
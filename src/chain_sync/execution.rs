@@ -0,0 +1,290 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Execution stage of the validation of blocks.
+//!
+//! The checks of a block that don't depend on any state (sanity, timestamp,
+//! base fee) are always run by the tipset syncer. The ones that do, from the
+//! execution of the messages of the parent tipset to the eligibility of the
+//! miner, are delegated to an [`ExecutionStage`]:
+//! - [`ExecuteMessages`] runs them all, as a full node does.
+//! - [`HeadersOnly`] keeps the checks that only need a few nodes of the
+//!   states, the block signature, the parent weight and the beacon entries,
+//!   for header relay nodes which follow the chain, serve `ChainExchange` and
+//!   relay gossip without executing messages. The state nodes they need are
+//!   fetched over `Bitswap`, and the other state-related fields of the headers
+//!   are trusted.
+
+use std::sync::Arc;
+
+use crate::beacon::IGNORE_DRAND_VAR;
+use crate::blocks::{Block, FullTipset, Tipset};
+use crate::chain::ChainStore;
+use crate::fil_cns::{self, FilecoinConsensus};
+use crate::libp2p::NetworkMessage;
+use crate::state_manager::{miner_work_addr, StateManager};
+use futures::stream::FuturesUnordered;
+use fvm_ipld_blockstore::Blockstore;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use super::{
+    metrics,
+    network_context::BitswapBlockstore,
+    receipt_validation::validate_receipts,
+    tipset_syncer::{check_block_messages, TipsetRangeSyncerError},
+    SyncConfig,
+};
+
+pub(in crate::chain_sync) type Validations =
+    FuturesUnordered<JoinHandle<Result<(), TipsetRangeSyncerError>>>;
+
+pub(in crate::chain_sync) trait ExecutionStage<DB>: Send + Sync {
+    /// Spawns the checks of `block` that depend on the state of its parent
+    /// tipset, `base_tipset`, onto `validations`.
+    fn validate_block(
+        &self,
+        state_manager: &Arc<StateManager<DB>>,
+        block: &Arc<Block>,
+        base_tipset: &Arc<Tipset>,
+        validations: &mut Validations,
+    ) -> Result<(), TipsetRangeSyncerError>;
+
+    /// Called once every block of `tipset` is valid, before the tipset
+    /// becomes the head.
+    fn tipset_validated(&self, _state_manager: &StateManager<DB>, _tipset: &FullTipset) {}
+}
+
+/// Returns the execution stage selected by `config`.
+pub(in crate::chain_sync) fn execution_stage<DB>(
+    config: &SyncConfig,
+    network_send: flume::Sender<NetworkMessage>,
+) -> Arc<dyn ExecutionStage<DB>>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    match config.headers_only {
+        true => Arc::new(HeadersOnly { network_send }),
+        false => Arc::new(ExecuteMessages),
+    }
+}
+
+/// Executes the messages of the parent tipsets and checks the blocks against
+/// the resulting states.
+pub(in crate::chain_sync) struct ExecuteMessages;
+
+impl<DB: Blockstore + Send + Sync + 'static> ExecutionStage<DB> for ExecuteMessages {
+    fn validate_block(
+        &self,
+        state_manager: &Arc<StateManager<DB>>,
+        block: &Arc<Block>,
+        base_tipset: &Arc<Tipset>,
+        validations: &mut Validations,
+    ) -> Result<(), TipsetRangeSyncerError> {
+        let header = block.header();
+
+        // Retrieve lookback tipset for validation
+        let (_, lookback_state) = ChainStore::get_lookback_tipset_for_round(
+            state_manager.chain_store().chain_index.clone(),
+            state_manager.chain_config(),
+            base_tipset.clone(),
+            header.epoch(),
+        )?;
+
+        // Work address needed for async validations, so necessary
+        // to do sync to avoid duplication
+        let work_addr =
+            state_manager.get_miner_work_addr(lookback_state, header.miner_address())?;
+
+        // Check block messages
+        validations.push(tokio::task::spawn(check_block_messages(
+            Arc::clone(state_manager),
+            Arc::clone(block),
+            Arc::clone(base_tipset),
+        )));
+
+        // Parent weight calculation check
+        let v_block_store = state_manager.blockstore_owned();
+        let v_base_tipset = Arc::clone(base_tipset);
+        let weight = header.weight().clone();
+        validations.push(tokio::task::spawn_blocking(move || {
+            let _timer = metrics::BLOCK_VALIDATION_TASKS_TIME
+                .with_label_values(&[metrics::values::PARENT_WEIGHT_CAL])
+                .start_timer();
+            let calc_weight = fil_cns::weight(&v_block_store, &v_base_tipset).map_err(|e| {
+                TipsetRangeSyncerError::Calculation(format!("Error calculating weight: {e}"))
+            })?;
+            if weight != calc_weight {
+                return Err(TipsetRangeSyncerError::Validation(format!(
+                    "Parent weight doesn't match: {weight} (header), {calc_weight} (computed)"
+                )));
+            }
+            Ok(())
+        }));
+
+        // State root and receipt root validations
+        let v_state_manager = Arc::clone(state_manager);
+        let v_base_tipset = Arc::clone(base_tipset);
+        let v_block = Arc::clone(block);
+        validations.push(tokio::task::spawn(async move {
+            let header = v_block.header();
+            // Only the computation of the state is timed, not the cache hits of the
+            // other blocks of the tipset
+            let (state_root, receipt_root) = v_state_manager
+                .tipset_state_timed(
+                    &v_base_tipset,
+                    &metrics::SYNC_STAGE_TIME
+                        .with_label_values(&[metrics::values::STATE_EXECUTION]),
+                )
+                .await
+                .map_err(|e| {
+                    TipsetRangeSyncerError::Calculation(format!("Failed to calculate state: {e}"))
+                })?;
+
+            if &state_root != header.state_root() {
+                v_state_manager.spawn_state_mismatch_dump(
+                    Arc::clone(&v_base_tipset),
+                    header.clone(),
+                    (state_root, receipt_root),
+                );
+                return Err(TipsetRangeSyncerError::Validation(format!(
+                    "Parent state root did not match computed state: {} (header), {} (computed)",
+                    header.state_root(),
+                    state_root,
+                )));
+            }
+
+            validate_receipts(
+                v_state_manager.blockstore(),
+                &v_state_manager.chain_config(),
+                &v_base_tipset,
+                header,
+                &receipt_root,
+            )
+        }));
+
+        // Block signature check
+        let v_block = block.clone();
+        validations.push(tokio::task::spawn_blocking(move || {
+            let _timer = metrics::BLOCK_VALIDATION_TASKS_TIME
+                .with_label_values(&[metrics::values::BLOCK_SIGNATURE_CHECK])
+                .start_timer();
+            v_block.header().check_block_signature(&work_addr)?;
+            Ok(())
+        }));
+
+        let consensus = FilecoinConsensus::new(state_manager.beacon_schedule());
+        let v_state_manager = Arc::clone(state_manager);
+        let v_block = block.clone();
+        validations.push(tokio::task::spawn(async move {
+            consensus
+                .validate_block(v_state_manager, v_block)
+                .await
+                .map_err(|errs| {
+                    // NOTE: Concatenating errors here means the wrapper type of error
+                    // never surfaces, yet we always pay the cost of the generic argument.
+                    // But there's no reason `validate_block` couldn't return a list of all
+                    // errors instead of a single one that has all the error messages,
+                    // removing the caller's ability to distinguish between them.
+                    let errs = errs.map(TipsetRangeSyncerError::ConsensusError);
+
+                    TipsetRangeSyncerError::concat(errs)
+                })
+        }));
+        Ok(())
+    }
+
+    fn tipset_validated(&self, state_manager: &StateManager<DB>, tipset: &FullTipset) {
+        if let Err(e) = state_manager.audit_validated_tipset(tipset.blocks()[0].header()) {
+            warn!("Failed to append to the audit log: {e:#}");
+        }
+    }
+}
+
+/// Checks the block signatures, parent weights and beacon entries without
+/// executing messages.
+pub(in crate::chain_sync) struct HeadersOnly {
+    network_send: flume::Sender<NetworkMessage>,
+}
+
+impl<DB: Blockstore + Send + Sync + 'static> ExecutionStage<DB> for HeadersOnly {
+    fn validate_block(
+        &self,
+        state_manager: &Arc<StateManager<DB>>,
+        block: &Arc<Block>,
+        base_tipset: &Arc<Tipset>,
+        validations: &mut Validations,
+    ) -> Result<(), TipsetRangeSyncerError> {
+        let header = block.header();
+        let store = Arc::new(BitswapBlockstore::new(
+            state_manager.blockstore_owned(),
+            self.network_send.clone(),
+        ));
+
+        let (_, lookback_state) = ChainStore::get_lookback_tipset_for_round(
+            state_manager.chain_store().chain_index.clone(),
+            state_manager.chain_config(),
+            base_tipset.clone(),
+            header.epoch(),
+        )?;
+
+        // Parent weight calculation check, from the power table of the parent
+        // state
+        let v_store = Arc::clone(&store);
+        let v_base_tipset = Arc::clone(base_tipset);
+        let weight = header.weight().clone();
+        validations.push(tokio::task::spawn_blocking(move || {
+            let calc_weight = fil_cns::weight(v_store.as_ref(), &v_base_tipset).map_err(|e| {
+                TipsetRangeSyncerError::Calculation(format!("Error calculating weight: {e}"))
+            })?;
+            if weight != calc_weight {
+                return Err(TipsetRangeSyncerError::Validation(format!(
+                    "Parent weight doesn't match: {weight} (header), {calc_weight} (computed)"
+                )));
+            }
+            Ok(())
+        }));
+
+        // Block signature check, with the worker key of the lookback state
+        let v_block = Arc::clone(block);
+        validations.push(tokio::task::spawn_blocking(move || {
+            let _timer = metrics::BLOCK_VALIDATION_TASKS_TIME
+                .with_label_values(&[metrics::values::BLOCK_SIGNATURE_CHECK])
+                .start_timer();
+            let header = v_block.header();
+            let work_addr = miner_work_addr(store.as_ref(), lookback_state, header.miner_address())
+                .map_err(|e| {
+                    TipsetRangeSyncerError::Calculation(format!(
+                        "Failed to get the worker of {}: {e}",
+                        header.miner_address()
+                    ))
+                })?;
+            header.check_block_signature(&work_addr)?;
+            Ok(())
+        }));
+
+        // Beacon values check
+        if std::env::var(IGNORE_DRAND_VAR) != Ok("1".to_owned()) {
+            let prev_beacon = state_manager
+                .chain_store()
+                .chain_index
+                .latest_beacon_entry(base_tipset)?;
+            let network_version = state_manager.get_network_version(base_tipset.epoch());
+            let beacon_schedule = state_manager.beacon_schedule();
+            let parent_epoch = base_tipset.epoch();
+            let v_block = Arc::clone(block);
+            validations.push(tokio::task::spawn(async move {
+                v_block
+                    .header()
+                    .validate_block_drand(
+                        network_version,
+                        beacon_schedule.as_ref(),
+                        parent_epoch,
+                        &prev_beacon,
+                    )
+                    .map_err(|e| TipsetRangeSyncerError::Validation(e.to_string()))
+            }));
+        }
+        Ok(())
+    }
+}
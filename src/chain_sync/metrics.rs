@@ -7,7 +7,7 @@ use prometheus::{
         AtomicI64, AtomicU64, GenericCounter, GenericCounterVec, GenericGauge, GenericGaugeVec,
         Opts,
     },
-    Histogram, HistogramOpts, HistogramVec,
+    Histogram, HistogramOpts, HistogramTimer, HistogramVec,
 };
 
 lazy_static! {
@@ -62,6 +62,27 @@ lazy_static! {
         );
         block_validation_tasks_time
     };
+    pub static ref SYNC_STAGE_TIME: Box<HistogramVec> = {
+        let sync_stage_time = Box::new(
+            HistogramVec::new(
+                HistogramOpts {
+                    common_opts: Opts::new(
+                        "sync_stage_time",
+                        "Duration of the stages of tipset synchronization",
+                    ),
+                    buckets: vec![],
+                },
+                &[labels::SYNC_STAGE],
+            )
+            .expect("Defining the sync_stage_time metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(sync_stage_time.clone())
+            .expect(
+                "Registering the sync_stage_time metric with the metrics registry must succeed",
+            );
+        sync_stage_time
+    };
     pub static ref LIBP2P_MESSAGE_TOTAL: Box<GenericCounterVec<AtomicU64>> = {
         let libp2p_message_total = Box::new(
             GenericCounterVec::<AtomicU64>::new(
@@ -124,6 +145,36 @@ lazy_static! {
             .expect("Registering the last_validated_tipset_epoch metric with the metrics registry must succeed");
         last_validated_tipset_epoch
     };
+    pub static ref SYNC_EPOCHS_BEHIND: Box<GenericGauge<AtomicI64>> = {
+        let sync_epochs_behind = Box::new(
+            GenericGauge::<AtomicI64>::new(
+                "sync_epochs_behind",
+                "Number of epochs between the head being synced to and the last validated tipset",
+            )
+            .expect("Defining the sync_epochs_behind metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(sync_epochs_behind.clone())
+            .expect(
+                "Registering the sync_epochs_behind metric with the metrics registry must succeed",
+            );
+        sync_epochs_behind
+    };
+    pub static ref CLOCK_SKEW_SECONDS: Box<GenericGauge<AtomicI64>> = {
+        let clock_skew_seconds = Box::new(
+            GenericGauge::<AtomicI64>::new(
+                "clock_skew_seconds",
+                "Estimated offset of the local clock from the network time, positive if ahead",
+            )
+            .expect("Defining the clock_skew_seconds metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(clock_skew_seconds.clone())
+            .expect(
+                "Registering the clock_skew_seconds metric with the metrics registry must succeed",
+            );
+        clock_skew_seconds
+    };
     pub static ref PEER_TIPSET_EPOCH: Box<GenericGaugeVec<AtomicI64>> = {
         let peer_tipset_epoch = Box::new(
             GenericGaugeVec::new(
@@ -199,8 +250,19 @@ lazy_static! {
     };
 }
 
+/// Records the duration measured by `timer` only if `result` is a success, so
+/// that failed attempts that are retried later are not counted twice.
+pub fn observe_if_ok<T, E>(timer: HistogramTimer, result: &Result<T, E>) {
+    if result.is_ok() {
+        timer.observe_duration();
+    } else {
+        timer.stop_and_discard();
+    }
+}
+
 pub mod labels {
     pub const GOSSIPSUB_MESSAGE_KIND: &str = "libp2p_message_kind";
+    pub const SYNC_STAGE: &str = "stage";
 }
 
 pub mod values {
@@ -222,6 +284,14 @@ pub mod values {
     pub const BASE_FEE_CHECK: &str = "base_fee_check";
     pub const PARENT_WEIGHT_CAL: &str = "parent_weight_check";
     pub const BLOCK_SIGNATURE_CHECK: &str = "block_signature_check";
+    pub const BLS_AGGREGATE_CHECK: &str = "bls_aggregate_check";
+    pub const SECP_SIGNATURES_CHECK: &str = "secp_signatures_check";
+
+    // sync stages
+    pub const HEADER_SYNC: &str = "header_sync";
+    pub const MESSAGE_FETCH: &str = "message_fetch";
+    pub const STATE_EXECUTION: &str = "state_execution";
+    pub const FLUSH: &str = "flush";
 }
 
 #[cfg(test)]
@@ -249,6 +319,8 @@ mod tests {
         test_counter!(TIPSET_RANGE_SYNC_FAILURE_TOTAL);
         test_counter!(HEAD_EPOCH);
         test_counter!(LAST_VALIDATED_TIPSET_EPOCH);
+        test_counter_vec!(SYNC_STAGE_TIME);
+        test_counter!(SYNC_EPOCHS_BEHIND);
         test_counter!(NETWORK_HEAD_EVALUATION_ERRORS);
         test_counter!(BOOTSTRAP_ERRORS);
         test_counter!(FOLLOW_NETWORK_INTERRUPTIONS);
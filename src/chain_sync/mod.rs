@@ -2,17 +2,23 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 mod bad_block_cache;
+mod block_publisher;
 mod chain_muxer;
+mod clock_skew;
 pub mod consensus;
+mod execution;
 mod metrics;
 mod network_context;
+mod receipt_validation;
 mod sync_state;
 mod tipset_syncer;
 mod validation;
 
 pub use self::{
     bad_block_cache::BadBlockCache,
+    block_publisher::BlockPublisher,
     chain_muxer::{ChainMuxer, SyncConfig},
+    clock_skew::{watch_ntp, ClockSkew},
     consensus::{collect_errs, Consensus},
     sync_state::{SyncStage, SyncState},
     validation::TipsetValidator,
@@ -13,13 +13,14 @@ use std::{
 use crate::blocks::{FullTipset, Tipset, TipsetKeys};
 use crate::libp2p::{
     chain_exchange::{
-        ChainExchangeRequest, ChainExchangeResponse, CompactedMessages, TipsetBundle, HEADERS,
-        MESSAGES,
+        ChainExchangeRequest, ChainExchangeResponse, ChainExchangeResponseStatus,
+        CompactedMessages, TipsetBundle, HEADERS, MESSAGES,
     },
     hello::{HelloRequest, HelloResponse},
     rpc::RequestResponseError,
-    NetworkMessage, PeerId, PeerManager, BITSWAP_TIMEOUT,
+    NetworkMessage, PeerId, PeerManager, PeerPenalty, RequestOutcome, BITSWAP_TIMEOUT,
 };
+use crate::rpc_api::net_api::RequestProtocol;
 use anyhow::Context;
 use cid::Cid;
 use fvm_ipld_blockstore::Blockstore;
@@ -40,6 +41,42 @@ const CHAIN_EXCHANGE_TIMEOUT: Duration = Duration::from_secs(5);
 /// network.
 const MAX_CONCURRENT_CHAIN_EXCHANGE_REQUESTS: usize = 2;
 
+/// Blockstore that requests the missing blocks over `Bitswap`, for the nodes
+/// that only have some of the state trees. Reads block the thread until the
+/// block is received or [`BITSWAP_TIMEOUT`] elapses, so they must not be done
+/// on the async runtime.
+pub(in crate::chain_sync) struct BitswapBlockstore<DB> {
+    db: Arc<DB>,
+    network_send: flume::Sender<NetworkMessage>,
+}
+
+impl<DB> BitswapBlockstore<DB> {
+    pub fn new(db: Arc<DB>, network_send: flume::Sender<NetworkMessage>) -> Self {
+        Self { db, network_send }
+    }
+}
+
+impl<DB: Blockstore> Blockstore for BitswapBlockstore<DB> {
+    fn get(&self, k: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
+        if let Some(block) = self.db.get(k)? {
+            return Ok(Some(block));
+        }
+        let (tx, rx) = flume::bounded(1);
+        self.network_send
+            .send(NetworkMessage::BitswapRequest {
+                cid: *k,
+                response_channel: tx,
+            })
+            .context("failed to send bitswap request, network receiver dropped")?;
+        let _ = rx.recv_timeout(BITSWAP_TIMEOUT);
+        self.db.get(k)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> anyhow::Result<()> {
+        self.db.put_keyed(k, block)
+    }
+}
+
 /// Context used in chain sync to handle network requests.
 /// This contains the peer manager, P2P service interface, and [`Blockstore`]
 /// required to make network requests.
@@ -136,8 +173,11 @@ where
         tsk: &TipsetKeys,
         count: u64,
     ) -> Result<Vec<Arc<Tipset>>, String> {
-        self.handle_chain_exchange_request(peer_id, tsk, count, HEADERS)
-            .await
+        let start = tsk.clone();
+        self.handle_chain_exchange_request(peer_id, tsk, count, HEADERS, move |tipsets| {
+            validate_header_chain(tipsets, &start)
+        })
+        .await
     }
     /// Send a `chain_exchange` request for only messages (ignore block
     /// headers). If `peer_id` is `None`, requests will be sent to a set of
@@ -148,7 +188,7 @@ where
         tsk: &TipsetKeys,
         count: u64,
     ) -> Result<Vec<CompactedMessages>, String> {
-        self.handle_chain_exchange_request(peer_id, tsk, count, MESSAGES)
+        self.handle_chain_exchange_request(peer_id, tsk, count, MESSAGES, |_| Ok(()))
             .await
     }
 
@@ -160,8 +200,20 @@ where
         peer_id: Option<PeerId>,
         tsk: &TipsetKeys,
     ) -> Result<FullTipset, String> {
+        let start = tsk.clone();
         let mut fts = self
-            .handle_chain_exchange_request(peer_id, tsk, 1, HEADERS | MESSAGES)
+            .handle_chain_exchange_request(
+                peer_id,
+                tsk,
+                1,
+                HEADERS | MESSAGES,
+                move |fts: &[FullTipset]| match fts.first() {
+                    Some(fts) if fts.key() != &start => {
+                        Err("response doesn't start at the requested tipset".into())
+                    }
+                    _ => Ok(()),
+                },
+            )
             .await?;
 
         if fts.len() != 1 {
@@ -214,35 +266,42 @@ where
 
     /// Helper function to handle the peer retrieval if no peer supplied as well
     /// as the logging and updating of the peer info in the `PeerManager`.
-    async fn handle_chain_exchange_request<T>(
+    /// Responses are checked with `validate`, peers serving invalid tipsets
+    /// are penalized.
+    async fn handle_chain_exchange_request<T, F>(
         &self,
         peer_id: Option<PeerId>,
         tsk: &TipsetKeys,
         request_len: u64,
         options: u64,
+        validate: F,
     ) -> Result<Vec<T>, String>
     where
         T: TryFrom<TipsetBundle, Error = String> + Send + Sync + 'static,
+        F: Fn(&[T]) -> Result<(), String> + Clone + Send + Sync + 'static,
     {
         let request = ChainExchangeRequest {
             start: Vec::<Cid>::from(&tsk.cids),
             request_len,
             options,
         };
+        request.validate()?;
 
         let global_pre_time = SystemTime::now();
         let network_failures = Arc::new(AtomicU64::new(0));
         let lookup_failures = Arc::new(AtomicU64::new(0));
         let chain_exchange_result = match peer_id {
             // Specific peer is given to send request, send specifically to that peer.
-            Some(id) => Self::chain_exchange_request(
-                self.peer_manager.clone(),
-                self.network_send.clone(),
-                id,
-                request,
-            )
-            .await?
-            .into_result()?,
+            Some(id) => {
+                let response = Self::chain_exchange_request(
+                    self.peer_manager.clone(),
+                    self.network_send.clone(),
+                    id,
+                    request,
+                )
+                .await?;
+                Self::into_validated_result(&self.peer_manager, id, response, validate).await?
+            }
             None => {
                 // No specific peer set, send requests to a shuffled set of top peers until
                 // a request succeeds.
@@ -255,9 +314,10 @@ where
                     let request = request.clone();
                     let network_failures = network_failures.clone();
                     let lookup_failures = lookup_failures.clone();
+                    let validate = validate.clone();
                     batch.add(async move {
                         match Self::chain_exchange_request(
-                            peer_manager,
+                            peer_manager.clone(),
                             network_send,
                             peer_id,
                             request,
@@ -265,7 +325,14 @@ where
                         .await
                         {
                             Ok(chain_exchange_result) => {
-                                match chain_exchange_result.into_result::<T>() {
+                                match Self::into_validated_result(
+                                    &peer_manager,
+                                    peer_id,
+                                    chain_exchange_result,
+                                    validate,
+                                )
+                                .await
+                                {
                                     Ok(r) => Ok(r),
                                     Err(e) => {
                                         lookup_failures.fetch_add(1, Ordering::Relaxed);
@@ -315,6 +382,52 @@ where
         Ok(chain_exchange_result)
     }
 
+    /// Converts and validates the tipsets of a response. Error statuses are
+    /// not penalized, but malformed bundles and invalid tipsets are.
+    async fn into_validated_result<T, F>(
+        peer_manager: &PeerManager,
+        peer_id: PeerId,
+        response: ChainExchangeResponse,
+        validate: F,
+    ) -> Result<Vec<T>, String>
+    where
+        T: TryFrom<TipsetBundle, Error = String>,
+        F: Fn(&[T]) -> Result<(), String>,
+    {
+        let is_success = matches!(
+            response.status,
+            ChainExchangeResponseStatus::Success | ChainExchangeResponseStatus::PartialResponse
+        );
+        let record =
+            |outcome| peer_manager.record_request(peer_id, RequestProtocol::ChainExchange, outcome);
+        let result = match response.into_result::<T>() {
+            Err(e) if is_success => {
+                peer_manager
+                    .penalize(peer_id, PeerPenalty::ProtocolViolation)
+                    .await;
+                record(RequestOutcome::Invalid).await;
+                return Err(e);
+            }
+            Err(e) => {
+                // Error statuses are valid responses, so they aren't penalized.
+                record(RequestOutcome::Error).await;
+                return Err(e);
+            }
+            Ok(result) => result,
+        };
+        if let Err(e) = validate(&result) {
+            peer_manager
+                .penalize(peer_id, PeerPenalty::InvalidTipset)
+                .await;
+            record(RequestOutcome::Invalid).await;
+            return Err(format!(
+                "Invalid chain exchange response from {peer_id}: {e}"
+            ));
+        }
+        record(RequestOutcome::Success).await;
+        Ok(result)
+    }
+
     /// Send a `chain_exchange` request to the network and await response.
     async fn chain_exchange_request(
         peer_manager: Arc<PeerManager>,
@@ -366,6 +479,14 @@ where
                     // specified timeout is adequate time.
                     RequestResponseError::Timeout => {
                         peer_manager.log_failure(peer_id, res_duration).await;
+                        peer_manager.penalize(peer_id, PeerPenalty::Timeout).await;
+                        peer_manager
+                            .record_request(
+                                peer_id,
+                                RequestProtocol::ChainExchange,
+                                RequestOutcome::Timeout,
+                            )
+                            .await;
                     }
                 }
                 debug!("Failed: ChainExchange Request to {peer_id}");
@@ -375,6 +496,14 @@ where
                 // Sender channel internally dropped or timeout, both should log failure which
                 // will negatively score the peer, but not drop yet.
                 peer_manager.log_failure(peer_id, res_duration).await;
+                peer_manager.penalize(peer_id, PeerPenalty::Timeout).await;
+                peer_manager
+                    .record_request(
+                        peer_id,
+                        RequestProtocol::ChainExchange,
+                        RequestOutcome::Timeout,
+                    )
+                    .await;
                 debug!("Timeout: ChainExchange Request to {peer_id}");
                 Err(format!("Chain exchange request to {peer_id} timed out"))
             }
@@ -412,12 +541,76 @@ where
     }
 }
 
+/// Checks that the tipsets of a headers response start at the requested
+/// tipset, and that each tipset is the parent of the previous one.
+fn validate_header_chain(tipsets: &[Arc<Tipset>], start: &TipsetKeys) -> Result<(), String> {
+    if tipsets.first().is_some_and(|tipset| tipset.key() != start) {
+        return Err("response doesn't start at the requested tipset".into());
+    }
+    for pair in tipsets.windows(2) {
+        if pair[0].parents() != pair[1].key() || pair[0].epoch() <= pair[1].epoch() {
+            return Err(format!(
+                "tipset at epoch {} isn't the parent of the tipset at epoch {}",
+                pair[1].epoch(),
+                pair[0].epoch()
+            ));
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::blocks::BlockHeader;
+    use crate::shim::address::Address;
 
     use std::sync::atomic::{AtomicBool, AtomicUsize};
 
+    #[test]
+    fn bitswap_blockstore_fetches_missing_blocks() {
+        let db = Arc::new(crate::db::MemoryDB::default());
+        let (network_send, network_rx) = flume::unbounded();
+        let store = BitswapBlockstore::new(db.clone(), network_send);
+        let cid = Cid::default();
+
+        // A peer serves the block.
+        std::thread::spawn(move || {
+            if let Ok(NetworkMessage::BitswapRequest {
+                cid,
+                response_channel,
+            }) = network_rx.recv()
+            {
+                db.put_keyed(&cid, b"block").unwrap();
+                response_channel.send(true).unwrap();
+            }
+        });
+        assert_eq!(store.get(&cid).unwrap(), Some(b"block".to_vec()));
+    }
+
+    #[test]
+    fn header_chain_validation() {
+        let tipset = |epoch, parents: &TipsetKeys| {
+            Arc::new(Tipset::from(
+                BlockHeader::builder()
+                    .miner_address(Address::new_id(0))
+                    .epoch(epoch)
+                    .parents(parents.clone())
+                    .build()
+                    .unwrap(),
+            ))
+        };
+        let genesis = tipset(0, &TipsetKeys::default());
+        let first = tipset(1, genesis.key());
+        let second = tipset(2, first.key());
+
+        let chain = [second.clone(), first.clone(), genesis.clone()];
+        assert!(validate_header_chain(&chain, second.key()).is_ok());
+        assert!(validate_header_chain(&[], second.key()).is_ok());
+        assert!(validate_header_chain(&chain, first.key()).is_err());
+        assert!(validate_header_chain(&[second.clone(), genesis], second.key()).is_err());
+    }
+
     #[tokio::test]
     async fn race_batch_ok() {
         let mut batch = RaceBatch::new(3);
@@ -0,0 +1,187 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Validation of the receipt root declared by a block header, and of the
+//! events roots of the receipts computed for its parent tipset.
+//!
+//! Which events roots are valid depends on the network version the receipts
+//! were computed at, see [`EVENTS_ROOT_RULES`]. The strictness of both checks
+//! is configured by [`ReceiptValidation`].
+
+use crate::blocks::{BlockHeader, Tipset};
+use crate::ipld::Ipld;
+use crate::networks::{ChainConfig, ReceiptValidation, Strictness};
+use crate::shim::version::NetworkVersion;
+use cid::Cid;
+use fvm_ipld_amt::{Amt, Amtv0};
+use fvm_ipld_blockstore::Blockstore;
+use tracing::warn;
+
+use crate::chain_sync::tipset_syncer::TipsetRangeSyncerError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EventsRootRule {
+    /// Receipts have no events root.
+    Absent,
+    /// Receipts of messages that emitted events have an events root, which
+    /// must refer to a non-empty AMT.
+    Present,
+}
+
+/// Events root rules, by the first network version they apply to.
+const EVENTS_ROOT_RULES: &[(NetworkVersion, EventsRootRule)] = &[
+    (NetworkVersion::V0, EventsRootRule::Absent),
+    // FIP-0049, actor events
+    (NetworkVersion::V18, EventsRootRule::Present),
+];
+
+fn events_root_rule(network_version: NetworkVersion) -> EventsRootRule {
+    EVENTS_ROOT_RULES
+        .iter()
+        .rev()
+        .find(|(since, _)| network_version >= *since)
+        .map(|(_, rule)| *rule)
+        .unwrap_or(EventsRootRule::Absent)
+}
+
+fn enforce(
+    strictness: Strictness,
+    result: Result<(), String>,
+) -> Result<(), TipsetRangeSyncerError> {
+    match (strictness, result) {
+        (_, Ok(())) | (Strictness::Ignore, _) => Ok(()),
+        (Strictness::Warn, Err(e)) => {
+            warn!("Ignoring invalid receipts: {e}");
+            Ok(())
+        }
+        (Strictness::Reject, Err(e)) => Err(TipsetRangeSyncerError::Validation(e)),
+    }
+}
+
+/// Checks the receipt root of `header` and the events roots of the
+/// `receipt_root` computed for `parent`.
+pub(super) fn validate_receipts(
+    db: &impl Blockstore,
+    chain_config: &ChainConfig,
+    parent: &Tipset,
+    header: &BlockHeader,
+    receipt_root: &Cid,
+) -> Result<(), TipsetRangeSyncerError> {
+    let ReceiptValidation { receipts, events } = chain_config.receipt_validation;
+    enforce(
+        receipts,
+        if receipt_root == header.message_receipts() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Parent receipt root did not match computed root: {} (header), {} (computed)",
+                header.message_receipts(),
+                receipt_root
+            ))
+        },
+    )?;
+    if events != Strictness::Ignore {
+        let rule = events_root_rule(chain_config.network_version(parent.epoch()));
+        enforce(events, check_events_roots(db, receipt_root, rule))?;
+    }
+    Ok(())
+}
+
+fn check_events_roots(
+    db: &impl Blockstore,
+    receipt_root: &Cid,
+    rule: EventsRootRule,
+) -> Result<(), String> {
+    let receipts = Amtv0::<Ipld, _>::load(receipt_root, db)
+        .map_err(|e| format!("Failed to load receipts {receipt_root}: {e}"))?;
+    let mut result = Ok(());
+    receipts
+        .for_each_while(|i, receipt| {
+            // Receipts are tuples, the events root is the optional fourth field.
+            let events_root = match receipt {
+                Ipld::List(fields) => match fields.get(3) {
+                    Some(Ipld::Link(cid)) => Some(*cid),
+                    _ => None,
+                },
+                _ => None,
+            };
+            result = match (rule, events_root) {
+                (_, None) => Ok(()),
+                (EventsRootRule::Absent, Some(root)) => Err(format!(
+                    "Receipt {i} has events root {root} before events were introduced"
+                )),
+                (EventsRootRule::Present, Some(root)) => {
+                    match Amt::<Ipld, _>::load(&root, db).map(|events| events.count()) {
+                        Ok(0) => Err(format!("Receipt {i} has an empty events root {root}")),
+                        Ok(_) => Ok(()),
+                        Err(e) => Err(format!(
+                            "Receipt {i} has an invalid events root {root}: {e}"
+                        )),
+                    }
+                }
+            };
+            Ok(result.is_ok())
+        })
+        .map_err(|e| format!("Failed to load receipts {receipt_root}: {e}"))?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MemoryDB;
+
+    fn receipts(db: &MemoryDB, events_root: Option<Cid>) -> Cid {
+        let mut fields = vec![Ipld::Integer(0), Ipld::Bytes(vec![]), Ipld::Integer(100)];
+        if let Some(root) = events_root {
+            fields.push(Ipld::Link(root));
+        }
+        let mut amt = Amtv0::new(db);
+        amt.set(0, Ipld::List(fields)).unwrap();
+        amt.flush().unwrap()
+    }
+
+    #[test]
+    fn rules_by_network_version() {
+        assert_eq!(
+            events_root_rule(NetworkVersion::V17),
+            EventsRootRule::Absent
+        );
+        assert_eq!(
+            events_root_rule(NetworkVersion::V18),
+            EventsRootRule::Present
+        );
+        assert_eq!(
+            events_root_rule(NetworkVersion::V20),
+            EventsRootRule::Present
+        );
+    }
+
+    #[test]
+    fn events_roots() {
+        let db = MemoryDB::default();
+        let mut events = Amt::new(&db);
+        events.set(0, Ipld::String("event".into())).unwrap();
+        let events_root = events.flush().unwrap();
+        let empty_events_root = Amt::<Ipld, _>::new(&db).flush().unwrap();
+
+        let without_events = receipts(&db, None);
+        let with_events = receipts(&db, Some(events_root));
+        let with_empty_events = receipts(&db, Some(empty_events_root));
+
+        assert!(check_events_roots(&db, &without_events, EventsRootRule::Absent).is_ok());
+        assert!(check_events_roots(&db, &without_events, EventsRootRule::Present).is_ok());
+        assert!(check_events_roots(&db, &with_events, EventsRootRule::Absent).is_err());
+        assert!(check_events_roots(&db, &with_events, EventsRootRule::Present).is_ok());
+        assert!(check_events_roots(&db, &with_empty_events, EventsRootRule::Present).is_err());
+    }
+
+    #[test]
+    fn strictness() {
+        let err = || Err("mismatch".to_string());
+        assert!(enforce(Strictness::Reject, err()).is_err());
+        assert!(enforce(Strictness::Warn, err()).is_ok());
+        assert!(enforce(Strictness::Ignore, err()).is_ok());
+        assert!(enforce(Strictness::Reject, Ok(())).is_ok());
+    }
+}
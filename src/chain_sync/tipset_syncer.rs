@@ -8,7 +8,7 @@ use std::{
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use crate::chain::{persist_objects, ChainStore, Error as ChainStoreError};
@@ -17,19 +17,25 @@ use crate::message::{valid_for_block_inclusion, Message as MessageTrait};
 use crate::networks::Height;
 use crate::shim::clock::ALLOWABLE_CLOCK_DRIFT;
 use crate::shim::{
-    address::Address, clock::ChainEpoch, crypto::verify_bls_aggregate, econ::BLOCK_GAS_LIMIT,
-    gas::price_list_by_network_version, message::Message, state_tree::StateTree,
+    address::{Address, BLS_PUB_LEN},
+    clock::ChainEpoch,
+    crypto::verify_bls_aggregate,
+    econ::BLOCK_GAS_LIMIT,
+    gas::price_list_by_network_version,
+    message::Message,
+    state_tree::StateTree,
 };
 use crate::state_manager::{is_valid_for_sending, Error as StateManagerError, StateManager};
-use crate::utils::io::WithProgressRaw;
+use crate::utils::io::{scheduler::IO_SCHEDULER, WithProgressRaw};
+use crate::utils::monitoring::RESOURCE_GOVERNOR;
 use crate::{
     blocks::{Block, BlockHeader, Error as ForestBlockError, FullTipset, Tipset, TipsetKeys},
-    fil_cns::{self, FilecoinConsensus, FilecoinConsensusError},
+    fil_cns::FilecoinConsensusError,
 };
 use ahash::{HashMap, HashMapExt, HashSet};
 use cid::Cid;
 use futures::stream::TryStreamExt as _;
-use futures::{stream, stream::FuturesUnordered, Stream, StreamExt, TryFutureExt};
+use futures::{stream, stream::FuturesUnordered, Stream, StreamExt};
 use fvm_ipld_blockstore::Blockstore;
 use fvm_ipld_encoding::to_vec;
 use nonempty::NonEmpty;
@@ -38,7 +44,7 @@ use thiserror::Error;
 use tracing::{debug, error, info, trace, warn};
 
 use crate::chain_sync::{
-    bad_block_cache::BadBlockCache, consensus::collect_errs, metrics,
+    bad_block_cache::BadBlockCache, consensus::collect_errs, execution::ExecutionStage, metrics,
     network_context::SyncNetworkContext, sync_state::SyncStage, validation::TipsetValidator,
 };
 
@@ -121,9 +127,27 @@ impl From<tokio::task::JoinError> for TipsetRangeSyncerError {
 }
 
 impl TipsetRangeSyncerError {
+    /// Whether the block that failed with this error is invalid, as opposed to
+    /// not being checkable by this node yet.
+    pub(in crate::chain_sync) fn invalidates_block(&self) -> bool {
+        !matches!(
+            self,
+            TipsetRangeSyncerError::TimeTravellingBlock(_, _)
+                | TipsetRangeSyncerError::TipsetParentNotFound(_)
+                | TipsetRangeSyncerError::ConsensusError(
+                    FilecoinConsensusError::ProofParametersUnavailable(_)
+                )
+        )
+    }
+
     /// Concatenate all validation error messages into one comma separated
-    /// version.
-    fn concat(errs: NonEmpty<TipsetRangeSyncerError>) -> Self {
+    /// version. An error that doesn't invalidate the block is returned as is
+    /// instead, so that the block isn't marked as bad.
+    pub(in crate::chain_sync) fn concat(errs: NonEmpty<TipsetRangeSyncerError>) -> Self {
+        let mut errs = Vec::from(errs);
+        if let Some(i) = errs.iter().position(|e| !e.invalidates_block()) {
+            return errs.swap_remove(i);
+        }
         let msg = errs
             .iter()
             .map(|e| e.to_string())
@@ -248,6 +272,7 @@ pub(in crate::chain_sync) struct TipsetProcessor<DB> {
     /// `TipsetValidator`
     tipsets: Pin<Box<dyn futures::Stream<Item = Arc<Tipset>> + Send>>,
     state_manager: Arc<StateManager<DB>>,
+    execution: Arc<dyn ExecutionStage<DB>>,
     network: SyncNetworkContext<DB>,
     chain_store: Arc<ChainStore<DB>>,
     bad_block_cache: Arc<BadBlockCache>,
@@ -263,6 +288,7 @@ where
         tracker: crate::chain_sync::chain_muxer::WorkerState,
         tipsets: Pin<Box<dyn futures::Stream<Item = Arc<Tipset>> + Send>>,
         state_manager: Arc<StateManager<DB>>,
+        execution: Arc<dyn ExecutionStage<DB>>,
         network: SyncNetworkContext<DB>,
         chain_store: Arc<ChainStore<DB>>,
         bad_block_cache: Arc<BadBlockCache>,
@@ -273,6 +299,7 @@ where
             tracker,
             tipsets,
             state_manager,
+            execution,
             network,
             chain_store,
             bad_block_cache,
@@ -285,6 +312,7 @@ where
         mut tipset_group: TipsetGroup,
     ) -> TipsetProcessorFuture<TipsetRangeSyncer<DB>, TipsetProcessorError> {
         let state_manager = self.state_manager.clone();
+        let execution = self.execution.clone();
         let chain_store = self.chain_store.clone();
         let network = self.network.clone();
         let bad_block_cache = self.bad_block_cache.clone();
@@ -307,6 +335,7 @@ where
                 proposed_head,
                 current_head,
                 state_manager,
+                execution,
                 network,
                 chain_store,
                 bad_block_cache,
@@ -617,6 +646,7 @@ pub(in crate::chain_sync) struct TipsetRangeSyncer<DB> {
     tipsets_included: HashSet<TipsetKeys>,
     tipset_tasks: Pin<Box<FuturesUnordered<TipsetRangeSyncerFuture>>>,
     state_manager: Arc<StateManager<DB>>,
+    execution: Arc<dyn ExecutionStage<DB>>,
     network: SyncNetworkContext<DB>,
     chain_store: Arc<ChainStore<DB>>,
     bad_block_cache: Arc<BadBlockCache>,
@@ -633,6 +663,7 @@ where
         proposed_head: Arc<Tipset>,
         current_head: Arc<Tipset>,
         state_manager: Arc<StateManager<DB>>,
+        execution: Arc<dyn ExecutionStage<DB>>,
         network: SyncNetworkContext<DB>,
         chain_store: Arc<ChainStore<DB>>,
         bad_block_cache: Arc<BadBlockCache>,
@@ -654,6 +685,7 @@ where
             // the value is greater than 0
             tipset_range_length as u64,
             state_manager.clone(),
+            execution.clone(),
             chain_store.clone(),
             network.clone(),
             bad_block_cache.clone(),
@@ -667,6 +699,7 @@ where
             tipsets_included,
             tipset_tasks,
             state_manager,
+            execution,
             network,
             chain_store,
             bad_block_cache,
@@ -703,6 +736,7 @@ where
         self.tipset_tasks.push(sync_tipset(
             additional_head,
             self.state_manager.clone(),
+            self.execution.clone(),
             self.chain_store.clone(),
             self.network.clone(),
             self.bad_block_cache.clone(),
@@ -749,6 +783,7 @@ fn sync_tipset_range<DB: Blockstore + Sync + Send + 'static>(
     tracker: crate::chain_sync::chain_muxer::WorkerState,
     tipset_range_length: u64,
     state_manager: Arc<StateManager<DB>>,
+    execution: Arc<dyn ExecutionStage<DB>>,
     chain_store: Arc<ChainStore<DB>>,
     network: SyncNetworkContext<DB>,
     bad_block_cache: Arc<BadBlockCache>,
@@ -759,7 +794,10 @@ fn sync_tipset_range<DB: Blockstore + Sync + Send + 'static>(
             .write()
             .init(current_head.clone(), proposed_head.clone());
 
-        let parent_tipsets = match sync_headers_in_reverse(
+        let timer = metrics::SYNC_STAGE_TIME
+            .with_label_values(&[metrics::values::HEADER_SYNC])
+            .start_timer();
+        let result = sync_headers_in_reverse(
             tracker.clone(),
             tipset_range_length,
             proposed_head.clone(),
@@ -768,8 +806,9 @@ fn sync_tipset_range<DB: Blockstore + Sync + Send + 'static>(
             &chain_store,
             network.clone(),
         )
-        .await
-        {
+        .await;
+        metrics::observe_if_ok(timer, &result);
+        let parent_tipsets = match result {
             Ok(parent_tipsets) => parent_tipsets,
             Err(why) => {
                 tracker.write().error(why.to_string());
@@ -780,7 +819,12 @@ fn sync_tipset_range<DB: Blockstore + Sync + Send + 'static>(
         // Persist the blocks from the synced Tipsets into the store
         tracker.write().set_stage(SyncStage::Headers);
         let headers: Vec<&BlockHeader> = parent_tipsets.iter().flat_map(|t| t.blocks()).collect();
-        if let Err(why) = persist_objects(chain_store.blockstore(), &headers) {
+        let timer = metrics::SYNC_STAGE_TIME
+            .with_label_values(&[metrics::values::FLUSH])
+            .start_timer();
+        let result = persist_objects(chain_store.blockstore(), &headers);
+        metrics::observe_if_ok(timer, &result);
+        if let Err(why) = result {
             tracker.write().error(why.to_string());
             return Err(why.into());
         };
@@ -790,6 +834,7 @@ fn sync_tipset_range<DB: Blockstore + Sync + Send + 'static>(
         if let Err(why) = sync_messages_check_state(
             tracker.clone(),
             state_manager,
+            execution,
             network,
             chain_store.clone(),
             &bad_block_cache,
@@ -813,7 +858,12 @@ fn sync_tipset_range<DB: Blockstore + Sync + Send + 'static>(
             current_head.epoch(),
             proposed_head.key()
         );
-        if let Err(why) = chain_store.put_tipset(&proposed_head) {
+        let timer = metrics::SYNC_STAGE_TIME
+            .with_label_values(&[metrics::values::FLUSH])
+            .start_timer();
+        let result = chain_store.put_tipset(&proposed_head);
+        metrics::observe_if_ok(timer, &result);
+        if let Err(why) = result {
             error!(
                 "Putting tipset range head [EPOCH = {}, KEYS = {:?}] in the store failed: {}",
                 proposed_head.epoch(),
@@ -949,6 +999,7 @@ async fn sync_headers_in_reverse<DB: Blockstore + Sync + Send + 'static>(
 fn sync_tipset<DB: Blockstore + Sync + Send + 'static>(
     proposed_head: Arc<Tipset>,
     state_manager: Arc<StateManager<DB>>,
+    execution: Arc<dyn ExecutionStage<DB>>,
     chain_store: Arc<ChainStore<DB>>,
     network: SyncNetworkContext<DB>,
     bad_block_cache: Arc<BadBlockCache>,
@@ -964,6 +1015,7 @@ fn sync_tipset<DB: Blockstore + Sync + Send + 'static>(
             // Include a dummy WorkerState
             crate::chain_sync::chain_muxer::WorkerState::default(),
             state_manager,
+            execution,
             network,
             chain_store.clone(),
             &bad_block_cache,
@@ -980,7 +1032,12 @@ fn sync_tipset<DB: Blockstore + Sync + Send + 'static>(
         // Add the tipset to the store. The tipset will be expanded with other blocks
         // with the same [epoch, parents] before updating the heaviest Tipset in
         // the store.
-        if let Err(why) = chain_store.put_tipset(&proposed_head) {
+        let timer = metrics::SYNC_STAGE_TIME
+            .with_label_values(&[metrics::values::FLUSH])
+            .start_timer();
+        let result = chain_store.put_tipset(&proposed_head);
+        metrics::observe_if_ok(timer, &result);
+        if let Err(why) = result {
             error!(
                 "Putting tipset [EPOCH = {}, KEYS = {:?}] in the store failed: {}",
                 proposed_head.epoch(),
@@ -999,58 +1056,75 @@ fn sync_tipset<DB: Blockstore + Sync + Send + 'static>(
 async fn fetch_batch<DB: Blockstore>(
     batch: Vec<Arc<Tipset>>,
     network: &SyncNetworkContext<DB>,
-    db: &DB,
+    chain_store: &ChainStore<DB>,
 ) -> Result<Vec<FullTipset>, TipsetRangeSyncerError> {
-    if let Some(cached) = batch
+    let mut full_tipsets: Vec<Option<FullTipset>> = batch
         .iter()
-        .map(|tipset| tipset.fill_from_blockstore(db))
-        .collect()
-    {
+        .map(|tipset| tipset.fill_from_blockstore(chain_store.blockstore()))
+        .collect();
+
+    // Only request the messages of the tipsets that are missing from the
+    // database. The headers are already known, so the request is for
+    // messages only.
+    let (Some(first), Some(last)) = (
+        full_tipsets.iter().position(Option::is_none),
+        full_tipsets.iter().rposition(Option::is_none),
+    ) else {
         // user has already seeded the database with this information (or we're
         // recovering from e.g a crash)
-        return Ok(cached);
-    }
+        return Ok(full_tipsets.into_iter().flatten().collect());
+    };
 
     // Tipsets in `batch` are already in chronological order
-    if let Some(head) = batch.last() {
-        let epoch = head.epoch();
-        let len = batch.len();
+    let missing = &batch[first..=last];
+    let head = &batch[last];
+    let epoch = head.epoch();
+    let len = missing.len();
+
+    debug!("ChainExchange message sync tipsets: epoch: {epoch}, len: {len}");
+
+    let timer = metrics::SYNC_STAGE_TIME
+        .with_label_values(&[metrics::values::MESSAGE_FETCH])
+        .start_timer();
+    let result = network
+        .chain_exchange_messages(None, head.key(), len as u64)
+        .await;
+    metrics::observe_if_ok(timer, &result);
+    let compacted_messages = result.map_err(TipsetRangeSyncerError::NetworkMessageQueryFailed)?;
+    if compacted_messages.len() != len {
+        return Err(TipsetRangeSyncerError::NetworkMessageQueryFailed(format!(
+            "ChainExchange returned messages for {} tipsets, expected {len}",
+            compacted_messages.len()
+        )));
+    }
 
-        debug!("ChainExchange message sync tipsets: epoch: {epoch}, len: {len}");
+    // inflate our tipsets with the messages from the wire format
+    for (slot, (messages, tipset)) in full_tipsets[first..=last]
+        .iter_mut()
+        .zip(compacted_messages.into_iter().rev().zip(missing))
+    {
+        // Construct full tipset from fetched messages
+        let bundle = TipsetBundle {
+            blocks: tipset.blocks().to_vec(),
+            messages: Some(messages),
+        };
 
-        let compacted_messages = network
-            .chain_exchange_messages(None, head.key(), len as u64)
-            .await
-            .map_err(TipsetRangeSyncerError::NetworkMessageQueryFailed)?;
-
-        // inflate our tipsets with the messages from the wire format
-        compacted_messages
-            .into_iter()
-            .rev()
-            .zip(batch.iter())
-            .map(|(messages, tipset)| {
-                // Construct full tipset from fetched messages
-                let bundle = TipsetBundle {
-                    blocks: tipset.blocks().to_vec(),
-                    messages: Some(messages),
-                };
-
-                let full_tipset = FullTipset::try_from(&bundle)
-                    .map_err(TipsetRangeSyncerError::GeneratingTipsetFromTipsetBundle)?;
-
-                // Persist the messages in the store
-                if let Some(m) = bundle.messages {
-                    crate::chain::persist_objects(db, &m.bls_msgs)?;
-                    crate::chain::persist_objects(db, &m.secp_msgs)?;
-                } else {
-                    warn!("ChainExchange request for messages returned null messages");
-                }
-                Ok(full_tipset)
-            })
-            .collect()
-    } else {
-        Ok(vec![])
+        let full_tipset = FullTipset::try_from(&bundle)
+            .map_err(TipsetRangeSyncerError::GeneratingTipsetFromTipsetBundle)?;
+
+        // Persist the messages in the store
+        if let Some(m) = bundle.messages {
+            let timer = metrics::SYNC_STAGE_TIME
+                .with_label_values(&[metrics::values::FLUSH])
+                .start_timer();
+            let result = persist_objects(chain_store.blockstore(), &m.bls_msgs)
+                .and_then(|_| persist_objects(chain_store.blockstore(), &m.secp_msgs));
+            metrics::observe_if_ok(timer, &result);
+            result?;
+        }
+        *slot = Some(full_tipset);
     }
+    Ok(full_tipsets.into_iter().flatten().collect())
 }
 
 /// Going forward along the tipsets, try to load the messages in them from the
@@ -1060,6 +1134,7 @@ async fn fetch_batch<DB: Blockstore>(
 async fn sync_messages_check_state<DB: Blockstore + Send + Sync + 'static>(
     tracker: crate::chain_sync::chain_muxer::WorkerState,
     state_manager: Arc<StateManager<DB>>,
+    execution: Arc<dyn ExecutionStage<DB>>,
     network: SyncNetworkContext<DB>,
     chainstore: Arc<ChainStore<DB>>,
     bad_block_cache: &BadBlockCache,
@@ -1068,14 +1143,16 @@ async fn sync_messages_check_state<DB: Blockstore + Send + Sync + 'static>(
     invalid_block_strategy: InvalidBlockStrategy,
 ) -> Result<(), TipsetRangeSyncerError> {
     let request_window = state_manager.chain_config().request_window;
-    let db = chainstore.blockstore();
-
+    let target_epoch = tipsets
+        .first()
+        .map(|tipset| tipset.epoch())
+        .unwrap_or_default();
     // Stream through the tipsets from lowest epoch to highest epoch
     stream::iter(tipsets.into_iter().rev())
         // Chunk tipsets in batches (default batch size is 8)
         .chunks(request_window)
         // Request batches from the p2p network
-        .map(|batch| fetch_batch(batch, &network, db))
+        .map(|batch| fetch_batch(batch, &network, &chainstore))
         // run 64 batches concurrently
         .buffered(64)
         // validate each full tipset in each batch
@@ -1085,6 +1162,7 @@ async fn sync_messages_check_state<DB: Blockstore + Send + Sync + 'static>(
                 let timer = metrics::TIPSET_PROCESSING_TIME.start_timer();
                 validate_tipset(
                     state_manager.clone(),
+                    execution.clone(),
                     &chainstore,
                     bad_block_cache,
                     full_tipset.clone(),
@@ -1092,10 +1170,18 @@ async fn sync_messages_check_state<DB: Blockstore + Send + Sync + 'static>(
                     invalid_block_strategy,
                 )
                 .await?;
-                drop(timer);
-                chainstore.set_heaviest_tipset(Arc::new(full_tipset.into_tipset()))?;
+                IO_SCHEDULER
+                    .record_validation_latency(Duration::from_secs_f64(timer.stop_and_record()));
+                execution.tipset_validated(&state_manager, &full_tipset);
+                let timer = metrics::SYNC_STAGE_TIME
+                    .with_label_values(&[metrics::values::FLUSH])
+                    .start_timer();
+                let result = chainstore.set_heaviest_tipset(Arc::new(full_tipset.into_tipset()));
+                metrics::observe_if_ok(timer, &result);
+                result?;
                 tracker.write().set_epoch(current_epoch);
                 metrics::LAST_VALIDATED_TIPSET_EPOCH.set(current_epoch as u64);
+                metrics::SYNC_EPOCHS_BEHIND.set(target_epoch - current_epoch);
             }
             Ok(())
         })
@@ -1108,6 +1194,7 @@ async fn sync_messages_check_state<DB: Blockstore + Send + Sync + 'static>(
 /// validation.
 async fn validate_tipset<DB: Blockstore + Send + Sync + 'static>(
     state_manager: Arc<StateManager<DB>>,
+    execution: Arc<dyn ExecutionStage<DB>>,
     chainstore: &ChainStore<DB>,
     bad_block_cache: &BadBlockCache,
     full_tipset: FullTipset,
@@ -1132,7 +1219,11 @@ async fn validate_tipset<DB: Blockstore + Send + Sync + 'static>(
     debug!("Tipset keys: {:?}", full_tipset_key.cids);
 
     for b in blocks {
-        let validation_fn = tokio::task::spawn(validate_block(state_manager.clone(), Arc::new(b)));
+        let validation_fn = tokio::task::spawn(validate_block(
+            state_manager.clone(),
+            execution.clone(),
+            Arc::new(b),
+        ));
         validations.push(validation_fn);
     }
 
@@ -1151,12 +1242,8 @@ async fn validate_tipset<DB: Blockstore + Send + Sync + 'static>(
                 // Only do bad block accounting if the function was called with
                 // `is_strict` = true
                 if let InvalidBlockStrategy::Strict = invalid_block_strategy {
-                    match &why {
-                        TipsetRangeSyncerError::TimeTravellingBlock(_, _)
-                        | TipsetRangeSyncerError::TipsetParentNotFound(_) => (),
-                        why => {
-                            bad_block_cache.put(cid, why.to_string());
-                        }
+                    if why.invalidates_block() {
+                        bad_block_cache.put(cid, why.to_string());
                     }
                 }
                 return Err(why);
@@ -1179,7 +1266,8 @@ async fn validate_tipset<DB: Blockstore + Send + Sync + 'static>(
 /// * Signatures
 /// * Message inclusion (fees, sequences)
 /// * Parent related fields: base fee, weight, the state root
-/// * NB: This is where the messages in the *parent* tipset are executed.
+/// * NB: This is where the messages in the *parent* tipset are executed, by
+///   the [`ExecutionStage`]. Only the base fee is checked without it.
 ///
 /// Consensus specific validation should include:
 /// * Checking that the messages in the block correspond to the agreed upon
@@ -1187,9 +1275,9 @@ async fn validate_tipset<DB: Blockstore + Send + Sync + 'static>(
 /// * That the block is a deterministic derivative of the underlying consensus
 async fn validate_block<DB: Blockstore + Sync + Send + 'static>(
     state_manager: Arc<StateManager<DB>>,
+    execution: Arc<dyn ExecutionStage<DB>>,
     block: Arc<Block>,
 ) -> Result<Arc<Block>, (Cid, TipsetRangeSyncerError)> {
-    let consensus = FilecoinConsensus::new(state_manager.beacon_schedule());
     trace!(
         "Validating block: epoch = {}, weight = {}, key = {}",
         block.header().epoch(),
@@ -1205,6 +1293,7 @@ async fn validate_block<DB: Blockstore + Sync + Send + 'static>(
         return Ok(block);
     }
 
+    let _permit = RESOURCE_GOVERNOR.validation_permit().await;
     let _timer = metrics::BLOCK_VALIDATION_TIME.start_timer();
 
     let header = block.header();
@@ -1227,31 +1316,8 @@ async fn validate_block<DB: Blockstore + Sync + Send + 'static>(
             )
         })?;
 
-    // Retrieve lookback tipset for validation
-    let lookback_state = ChainStore::get_lookback_tipset_for_round(
-        state_manager.chain_store().chain_index.clone(),
-        state_manager.chain_config(),
-        base_tipset.clone(),
-        block.header().epoch(),
-    )
-    .map_err(|e| (*block_cid, e.into()))
-    .map(|(_, s)| Arc::new(s))?;
-
-    // Work address needed for async validations, so necessary
-    // to do sync to avoid duplication
-    let work_addr = state_manager
-        .get_miner_work_addr(*lookback_state, header.miner_address())
-        .map_err(|e| (*block_cid, e.into()))?;
-
     // Async validations
-    let validations = FuturesUnordered::new();
-
-    // Check block messages
-    validations.push(tokio::task::spawn(check_block_messages(
-        Arc::clone(&state_manager),
-        Arc::clone(&block),
-        Arc::clone(&base_tipset),
-    )));
+    let mut validations = FuturesUnordered::new();
 
     // Base fee check
     let smoke_height = state_manager.chain_config().epoch(Height::Smoke);
@@ -1275,82 +1341,9 @@ async fn validate_block<DB: Blockstore + Sync + Send + 'static>(
         Ok(())
     }));
 
-    // Parent weight calculation check
-    let v_block_store = state_manager.blockstore_owned();
-    let v_base_tipset = Arc::clone(&base_tipset);
-    let weight = header.weight().clone();
-    validations.push(tokio::task::spawn_blocking(move || {
-        let _timer = metrics::BLOCK_VALIDATION_TASKS_TIME
-            .with_label_values(&[metrics::values::PARENT_WEIGHT_CAL])
-            .start_timer();
-        let calc_weight = fil_cns::weight(&v_block_store, &v_base_tipset).map_err(|e| {
-            TipsetRangeSyncerError::Calculation(format!("Error calculating weight: {e}"))
-        })?;
-        if weight != calc_weight {
-            return Err(TipsetRangeSyncerError::Validation(format!(
-                "Parent weight doesn't match: {weight} (header), {calc_weight} (computed)"
-            )));
-        }
-        Ok(())
-    }));
-
-    // State root and receipt root validations
-    let v_state_manager = Arc::clone(&state_manager);
-    let v_base_tipset = Arc::clone(&base_tipset);
-    let v_block = Arc::clone(&block);
-    validations.push(tokio::task::spawn(async move {
-        let header = v_block.header();
-        let (state_root, receipt_root) = v_state_manager
-            .tipset_state(&v_base_tipset)
-            .await
-            .map_err(|e| {
-                TipsetRangeSyncerError::Calculation(format!("Failed to calculate state: {e}"))
-            })?;
-
-        if &state_root != header.state_root() {
-            return Err(TipsetRangeSyncerError::Validation(format!(
-                "Parent state root did not match computed state: {} (header), {} (computed)",
-                header.state_root(),
-                state_root,
-            )));
-        }
-
-        if &receipt_root != header.message_receipts() {
-            return Err(TipsetRangeSyncerError::Validation(format!(
-                "Parent receipt root did not match computed root: {} (header), {} (computed)",
-                header.message_receipts(),
-                receipt_root
-            )));
-        }
-        Ok(())
-    }));
-
-    // Block signature check
-    let v_block = block.clone();
-    validations.push(tokio::task::spawn_blocking(move || {
-        let _timer = metrics::BLOCK_VALIDATION_TASKS_TIME
-            .with_label_values(&[metrics::values::BLOCK_SIGNATURE_CHECK])
-            .start_timer();
-        v_block.header().check_block_signature(&work_addr)?;
-        Ok(())
-    }));
-
-    let v_block = block.clone();
-    validations.push(tokio::task::spawn(async move {
-        consensus
-            .validate_block(state_manager, v_block)
-            .map_err(|errs| {
-                // NOTE: Concatenating errors here means the wrapper type of error
-                // never surfaces, yet we always pay the cost of the generic argument.
-                // But there's no reason `validate_block` couldn't return a list of all
-                // errors instead of a single one that has all the error messages,
-                // removing the caller's ability to distinguish between them.
-                let errs = errs.map(TipsetRangeSyncerError::ConsensusError);
-
-                TipsetRangeSyncerError::concat(errs)
-            })
-            .await
-    }));
+    execution
+        .validate_block(&state_manager, &block, &base_tipset, &mut validations)
+        .map_err(|e| (*block_cid, e))?;
 
     // Collect the errors from the async validations
     if let Err(errs) = collect_errs(validations).await {
@@ -1372,7 +1365,7 @@ async fn validate_block<DB: Blockstore + Sync + Send + 'static>(
 ///
 /// NB: This loads/computes the state resulting from the execution of the parent
 /// tipset.
-async fn check_block_messages<DB: Blockstore + Send + Sync + 'static>(
+pub(in crate::chain_sync) async fn check_block_messages<DB: Blockstore + Send + Sync + 'static>(
     state_manager: Arc<StateManager<DB>>,
     block: Arc<Block>,
     base_tipset: Arc<Tipset>,
@@ -1381,38 +1374,23 @@ async fn check_block_messages<DB: Blockstore + Send + Sync + 'static>(
         .chain_config()
         .network_version(block.header.epoch());
 
-    // Do the initial loop here
-    // check block message and signatures in them
+    // The BLS messages are checked with a single pairing against the
+    // aggregate signature of the header, while the state is loaded.
     let mut pub_keys = Vec::new();
-    let mut cids = Vec::new();
     let db = state_manager.blockstore_owned();
     for m in block.bls_msgs() {
         let pk = StateManager::get_bls_public_key(&db, &m.from, *base_tipset.parent_state())?;
         pub_keys.push(pk);
-        cids.push(m.cid().unwrap().to_bytes());
-    }
-
-    if let Some(sig) = block.header().bls_aggregate() {
-        if !verify_bls_aggregate(
-            cids.iter()
-                .map(|x| x.as_slice())
-                .collect::<Vec<&[u8]>>()
-                .as_slice(),
-            pub_keys
-                .iter()
-                .map(|x| &x[..])
-                .collect::<Vec<&[u8]>>()
-                .as_slice(),
-            sig,
-        ) {
-            return Err(TipsetRangeSyncerError::BlsAggregateSignatureInvalid(
-                format!("{sig:?}"),
-                format!("{cids:?}"),
-            ));
-        }
-    } else {
-        return Err(TipsetRangeSyncerError::BlockWithoutBlsAggregate);
     }
+    let v_block = Arc::clone(&block);
+    let permit = RESOURCE_GOVERNOR.verification_permit().await;
+    let bls_check = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let _timer = metrics::BLOCK_VALIDATION_TASKS_TIME
+            .with_label_values(&[metrics::values::BLS_AGGREGATE_CHECK])
+            .start_timer();
+        verify_bls_messages(&v_block, &pub_keys)
+    });
 
     let price_list = price_list_by_network_version(network_version);
     let mut sum_gas_limit = 0;
@@ -1485,6 +1463,7 @@ async fn check_block_messages<DB: Blockstore + Send + Sync + 'static>(
     }
 
     // Check validity for SECP messages
+    let mut key_addrs = Vec::with_capacity(block.secp_msgs().len());
     for (i, msg) in block.secp_msgs().iter().enumerate() {
         check_msg(msg.message(), &mut account_sequences, &tree).map_err(|e| {
             TipsetRangeSyncerError::Validation(format!(
@@ -1496,12 +1475,23 @@ async fn check_block_messages<DB: Blockstore + Send + Sync + 'static>(
             .resolve_to_key_addr(&msg.from(), &base_tipset)
             .await
             .map_err(|e| TipsetRangeSyncerError::ResolvingAddressFromMessage(e.to_string()))?;
-        // SecP256K1 Signature validation
-        msg.signature
-            .verify(&msg.message().cid().unwrap().to_bytes(), &key_addr)
-            .map_err(TipsetRangeSyncerError::MessageSignatureInvalid)?;
+        key_addrs.push(key_addr);
     }
 
+    // SecP256K1 and delegated signatures are verified in parallel.
+    let v_block = Arc::clone(&block);
+    let eth_chain_id = state_manager.chain_config().eth_chain_id;
+    let permit = RESOURCE_GOVERNOR.verification_permit().await;
+    let secp_check = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let _timer = metrics::BLOCK_VALIDATION_TASKS_TIME
+            .with_label_values(&[metrics::values::SECP_SIGNATURES_CHECK])
+            .start_timer();
+        verify_secp_messages(&v_block, &key_addrs, eth_chain_id)
+    });
+    bls_check.await??;
+    secp_check.await??;
+
     // Validate message root from header matches message root
     let msg_root = TipsetValidator::compute_msg_root(
         state_manager.blockstore(),
@@ -1519,6 +1509,57 @@ async fn check_block_messages<DB: Blockstore + Send + Sync + 'static>(
     Ok(())
 }
 
+/// Verifies the signatures of the BLS messages of `block`, sent by the keys
+/// `pub_keys`, against the aggregate signature of the header.
+fn verify_bls_messages(
+    block: &Block,
+    pub_keys: &[[u8; BLS_PUB_LEN]],
+) -> Result<(), TipsetRangeSyncerError> {
+    let Some(sig) = block.header().bls_aggregate() else {
+        return Err(TipsetRangeSyncerError::BlockWithoutBlsAggregate);
+    };
+    let cids = block
+        .bls_msgs()
+        .iter()
+        .map(|m| Ok(m.cid()?.to_bytes()))
+        .collect::<Result<Vec<_>, fvm_ipld_encoding::Error>>()
+        .map_err(|e| TipsetRangeSyncerError::Validation(e.to_string()))?;
+    let data: Vec<&[u8]> = cids.iter().map(Vec::as_slice).collect();
+    let pub_keys: Vec<&[u8]> = pub_keys.iter().map(|pk| &pk[..]).collect();
+    if !verify_bls_aggregate(&data, &pub_keys, sig) {
+        return Err(TipsetRangeSyncerError::BlsAggregateSignatureInvalid(
+            format!("{sig:?}"),
+            format!("{cids:?}"),
+        ));
+    }
+    Ok(())
+}
+
+/// Verifies the signatures of the SECP and delegated messages of `block`, in
+/// parallel, given the key addresses of their senders.
+fn verify_secp_messages(
+    block: &Block,
+    key_addrs: &[Address],
+    eth_chain_id: u64,
+) -> Result<(), TipsetRangeSyncerError> {
+    use rayon::prelude::*;
+    block
+        .secp_msgs()
+        .par_iter()
+        .zip(key_addrs)
+        .enumerate()
+        .try_for_each(|(i, (msg, key_addr))| {
+            let signing_bytes = msg
+                .signing_bytes(eth_chain_id)
+                .map_err(|e| TipsetRangeSyncerError::MessageSignatureInvalid(e.to_string()))?;
+            msg.signature.verify(&signing_bytes, key_addr).map_err(|e| {
+                TipsetRangeSyncerError::MessageSignatureInvalid(format!(
+                    "secp message at index {i}: {e}"
+                ))
+            })
+        })
+}
+
 /// Checks optional values in header.
 ///
 /// It only looks for fields which are common to all consensus types.
@@ -1627,4 +1668,67 @@ mod test {
         assert_eq!(index, 2);
         assert_eq!(weight, &BigInt::from(10));
     }
+
+    #[test]
+    fn verify_message_signatures() {
+        use crate::key_management::{KeyStore, KeyStoreConfig, Wallet};
+        use crate::message::SignedMessage;
+        use crate::shim::crypto::SignatureType;
+
+        let mut wallet = Wallet::new(KeyStore::new(KeyStoreConfig::Memory).unwrap());
+        let mut key_addrs = vec![];
+        let mut secp_messages = vec![];
+        for sequence in 0..4 {
+            let from = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+            let message = Message {
+                from,
+                to: Address::new_id(1000),
+                sequence,
+                ..Message::default()
+            };
+            let sig = wallet
+                .sign(&from, &message.cid().unwrap().to_bytes())
+                .unwrap();
+            key_addrs.push(from);
+            secp_messages.push(SignedMessage::new_unchecked(message, sig));
+        }
+        let mut block = Block {
+            header: mock_block(1, 1, 1),
+            bls_messages: vec![],
+            secp_messages,
+        };
+        assert!(verify_secp_messages(&block, &key_addrs, 314).is_ok());
+
+        // The header has no aggregate signature.
+        assert!(matches!(
+            verify_bls_messages(&block, &[]),
+            Err(TipsetRangeSyncerError::BlockWithoutBlsAggregate)
+        ));
+
+        block.secp_messages[2].message.sequence = 7;
+        match verify_secp_messages(&block, &key_addrs, 314) {
+            Err(TipsetRangeSyncerError::MessageSignatureInvalid(e)) => {
+                assert!(e.contains("index 2"), "{e}")
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+    }
+
+    #[test]
+    fn unavailable_proof_parameters_spare_the_block() {
+        let spared = TipsetRangeSyncerError::ConsensusError(
+            FilecoinConsensusError::ProofParametersUnavailable("offline".into()),
+        );
+        let invalid = TipsetRangeSyncerError::Validation("bad weight".into());
+        assert!(!spared.invalidates_block());
+        assert!(invalid.invalidates_block());
+        let why = TipsetRangeSyncerError::concat(nonempty::nonempty![invalid, spared]);
+        assert!(!why.invalidates_block());
+        let why = TipsetRangeSyncerError::concat(nonempty::nonempty![
+            TipsetRangeSyncerError::Validation("bad weight".into()),
+            TipsetRangeSyncerError::Validation("bad state root".into())
+        ]);
+        assert!(why.invalidates_block());
+        assert!(why.to_string().contains("bad state root"));
+    }
 }
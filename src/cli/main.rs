@@ -29,7 +29,7 @@ where
         .unwrap()
         .block_on(async {
             match opts.to_config() {
-                Ok((mut config, _)) => {
+                Ok((mut config, config_path)) => {
                     logger::setup_logger(&opts);
                     ProgressBar::set_progress_bars_visibility(config.client.show_progress_bars);
                     if opts.dry_run {
@@ -58,7 +58,8 @@ where
                         Subcommand::Sync(cmd) => cmd.run(config).await,
                         Subcommand::Mpool(cmd) => cmd.run(config).await,
                         Subcommand::State(cmd) => cmd.run(config).await,
-                        Subcommand::Config(cmd) => cmd.run(&config, &mut std::io::stdout()),
+                        Subcommand::Config(cmd) => cmd.run(&config, &mut std::io::stdout()).await,
+                        Subcommand::Backup(cmd) => cmd.run(config).await,
                         Subcommand::Send(cmd) => cmd.run(config).await,
                         Subcommand::Info(cmd) => cmd.run(config, opts).await,
                         Subcommand::DB(cmd) => cmd.run(&config).await,
@@ -67,6 +68,7 @@ where
                         Subcommand::Attach(cmd) => cmd.run(config),
                         Subcommand::Shutdown(cmd) => cmd.run(config).await,
                         Subcommand::Car(cmd) => cmd.run().await,
+                        Subcommand::Doctor(cmd) => cmd.run(config, config_path).await,
                     }
                 }
                 Err(e) => {
@@ -33,10 +33,10 @@ use crate::chain::{
 };
 use crate::cli_shared::{snapshot, snapshot::TrustedVendor};
 use crate::db::car::{AnyCar, ManyCar, RandomAccessFileReader};
-use crate::ipld::{stream_graph, CidHashSet};
+use crate::ipld::{stats::GraphStats, stream_graph, CidHashSet};
 use crate::networks::{calibnet, mainnet, ChainConfig, NetworkChain};
 use crate::shim::clock::{ChainEpoch, EPOCHS_IN_DAY, EPOCH_DURATION_SECONDS};
-use anyhow::{bail, Context as _};
+use anyhow::{bail, ensure, Context as _};
 use chrono::NaiveDateTime;
 use clap::Subcommand;
 use futures::TryStreamExt;
@@ -44,6 +44,7 @@ use fvm_ipld_blockstore::Blockstore;
 use indicatif::ProgressIterator;
 use itertools::Itertools;
 use sha2::Sha256;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tracing::info;
@@ -84,6 +85,23 @@ pub enum ArchiveCommands {
         /// Path to snapshot file.
         #[arg(required = true)]
         snapshot_files: Vec<PathBuf>,
+        /// Epoch of the tipset the chain is walked from. Defaults to the
+        /// heaviest tipset of the snapshot.
+        #[arg(short, long)]
+        epoch: Option<ChainEpoch>,
+        /// Merge the checkpoints into this known blocks file, e.g.
+        /// `build/known_blocks.yaml`, instead of printing them. Checkpoints of
+        /// other networks are kept.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Print block count and size histograms by codec and inferred type, the
+    /// link fan-out distribution, and the dedup ratio of the graph of a
+    /// snapshot
+    Stats {
+        /// Path to snapshot file.
+        #[arg(required = true)]
+        snapshot_files: Vec<PathBuf>,
     },
 }
 
@@ -116,8 +134,39 @@ impl ArchiveCommands {
                 .await
             }
             Self::Checkpoints {
-                snapshot_files: snapshot,
-            } => print_checkpoints(snapshot),
+                snapshot_files,
+                epoch,
+                output,
+            } => {
+                let (chain, checkpoints) = generate_checkpoints(snapshot_files, epoch)?;
+                match output {
+                    Some(path) => {
+                        let known_blocks = match std::fs::read_to_string(&path) {
+                            Ok(known_blocks) => known_blocks,
+                            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+                            Err(e) => {
+                                return Err(e)
+                                    .with_context(|| format!("failed to read {}", path.display()))
+                            }
+                        };
+                        let merged =
+                            merge_checkpoints(&known_blocks, &chain.to_string(), &checkpoints)?;
+                        std::fs::write(&path, merged)?;
+                        println!(
+                            "Wrote {} {chain} checkpoints to {}",
+                            checkpoints.len(),
+                            path.display()
+                        );
+                    }
+                    None => print!("{}", format_checkpoints(&chain.to_string(), &checkpoints)),
+                }
+                Ok(())
+            }
+            Self::Stats { snapshot_files } => {
+                let store = ManyCar::try_from(snapshot_files)?;
+                println!("{}", graph_stats(&store, store.heaviest_tipset()?).await?);
+                Ok(())
+            }
         }
     }
 }
@@ -228,11 +277,41 @@ async fn do_export(
     pb.enable_steady_tick(std::time::Duration::from_secs_f32(0.1));
     let writer = pb.wrap_async_write(writer);
 
-    crate::chain::export::<Sha256>(store, &ts, depth, writer, seen, true).await?;
+    crate::chain::export::<Sha256>(
+        store,
+        &ts,
+        depth,
+        writer,
+        seen,
+        true,
+        false,
+        Default::default(),
+    )
+    .await?;
 
     Ok(())
 }
 
+// Walk the whole graph reachable from `root`. Progress is rendered to stdout.
+async fn graph_stats(store: &impl Blockstore, root: Tipset) -> anyhow::Result<GraphStats> {
+    let pb = indicatif::ProgressBar::new_spinner().with_style(
+        indicatif::ProgressStyle::with_template(
+            "{spinner} visited {human_pos} blocks in {elapsed}",
+        )
+        .expect("indicatif template must be valid"),
+    );
+    pb.enable_steady_tick(std::time::Duration::from_secs_f32(0.1));
+
+    let mut stats = GraphStats::default();
+    let mut stream = stream_graph(store, root.chain(store));
+    while let Some(block) = stream.try_next().await? {
+        stats.add_block(&block)?;
+        pb.inc(1);
+    }
+    pb.finish_and_clear();
+    Ok(stats)
+}
+
 #[derive(Debug)]
 struct ArchiveInfo {
     variant: String,
@@ -339,11 +418,21 @@ impl ArchiveInfo {
     }
 }
 
-// Print a mapping of epochs to block headers in yaml format. This mapping can
-// be used by Forest to quickly identify tipsets.
-fn print_checkpoints(snapshot_files: Vec<PathBuf>) -> anyhow::Result<()> {
+// Generate a mapping of epochs to block headers. This mapping can be used by
+// Forest to quickly identify tipsets, see `build/known_blocks.yaml`.
+fn generate_checkpoints(
+    snapshot_files: Vec<PathBuf>,
+    epoch: Option<ChainEpoch>,
+) -> anyhow::Result<(NetworkChain, Vec<(ChainEpoch, cid::Cid)>)> {
     let store = ManyCar::try_from(snapshot_files).context("couldn't read input CAR file")?;
-    let root = store.heaviest_tipset()?;
+    let mut root = store.heaviest_tipset()?;
+    if let Some(epoch) = epoch {
+        root = Tipset::clone(
+            &*ChainIndex::new(&store)
+                .tipset_by_height(epoch, Arc::new(root), ResolveNullTipset::TakeOlder)
+                .context("unable to get a tipset at given height")?,
+        );
+    }
 
     let genesis = root.genesis(&store)?;
     let chain_name = if genesis.cid() == &*calibnet::GENESIS_CID {
@@ -354,11 +443,73 @@ fn print_checkpoints(snapshot_files: Vec<PathBuf>) -> anyhow::Result<()> {
         bail!("Unrecognizable genesis block");
     };
 
-    println!("{}:", chain_name);
-    for (epoch, cid) in list_checkpoints(store, root) {
-        println!("  {}: {}", epoch, cid);
+    Ok((chain_name, list_checkpoints(store, root).collect()))
+}
+
+// Format checkpoints of a network as an entry of the known blocks file, with the
+// most recent checkpoints first.
+fn format_checkpoints(chain: &str, checkpoints: &[(ChainEpoch, cid::Cid)]) -> String {
+    let mut out = format!("{chain}:\n");
+    for (epoch, cid) in checkpoints.iter().sorted().rev() {
+        out.push_str(&format!("  {epoch}: {cid}\n"));
     }
-    Ok(())
+    out
+}
+
+// Merge the checkpoints of `chain` into the contents of a known blocks file,
+// keeping its header comment and the checkpoints of other networks. A checkpoint
+// that disagrees with a known block of the same epoch is an error, as the
+// snapshot is then from another fork.
+fn merge_checkpoints(
+    known_blocks: &str,
+    chain: &str,
+    checkpoints: &[(ChainEpoch, cid::Cid)],
+) -> anyhow::Result<String> {
+    let header: String = known_blocks
+        .lines()
+        .take_while(|line| line.starts_with('#') || line.trim().is_empty())
+        .map(|line| format!("{line}\n"))
+        .collect();
+    let mut networks: Vec<(String, BTreeMap<ChainEpoch, cid::Cid>)> = serde_yaml::from_str::<
+        Option<indexmap::IndexMap<String, BTreeMap<ChainEpoch, String>>>,
+    >(known_blocks)
+    .context("invalid known blocks file")?
+    .unwrap_or_default()
+    .into_iter()
+    .map(|(network, blocks)| {
+        let blocks = blocks
+            .into_iter()
+            .map(|(epoch, cid)| Ok((epoch, cid.parse()?)))
+            .collect::<anyhow::Result<_>>()?;
+        Ok((network, blocks))
+    })
+    .collect::<anyhow::Result<_>>()?;
+
+    let index = match networks.iter().position(|(network, _)| network == chain) {
+        Some(index) => index,
+        None => {
+            networks.push((chain.to_string(), BTreeMap::new()));
+            networks.len() - 1
+        }
+    };
+    let blocks = &mut networks[index].1;
+    for (epoch, cid) in checkpoints {
+        if let Some(known) = blocks.insert(*epoch, *cid) {
+            ensure!(
+                known == *cid,
+                "the {chain} checkpoint of epoch {epoch} is {cid}, but {known} is known"
+            );
+        }
+    }
+
+    let mut out = header;
+    for (network, blocks) in networks {
+        out.push_str(&format_checkpoints(
+            &network,
+            &blocks.into_iter().collect_vec(),
+        ));
+    }
+    Ok(out)
 }
 
 fn list_checkpoints(
@@ -408,6 +559,45 @@ mod tests {
         assert_eq!(info.epoch, 0);
     }
 
+    #[test]
+    fn merge_known_blocks() {
+        let cid = |data: &[u8]| {
+            use crate::utils::cid::CidCborExt;
+            cid::Cid::from_cbor_blake2b256(&data).unwrap()
+        };
+        let (a, b, c) = (cid(b"a"), cid(b"b"), cid(b"c"));
+        let known_blocks = format!("# Header\n\ncalibnet:\n  100: {a}\nmainnet:\n  200: {b}\n");
+
+        let merged = merge_checkpoints(&known_blocks, "calibnet", &[(300, c), (100, a)]).unwrap();
+        assert_eq!(
+            merged,
+            format!("# Header\n\ncalibnet:\n  300: {c}\n  100: {a}\nmainnet:\n  200: {b}\n")
+        );
+        // Another fork of a known epoch
+        assert!(merge_checkpoints(&known_blocks, "mainnet", &[(200, c)]).is_err());
+        assert_eq!(
+            merge_checkpoints("", "mainnet", &[(200, b)]).unwrap(),
+            format_checkpoints("mainnet", &[(200, b)])
+        );
+    }
+
+    #[tokio::test]
+    async fn graph_stats_calibnet() {
+        let store = AnyCar::try_from(calibnet::DEFAULT_GENESIS).unwrap();
+        let stats = graph_stats(&store, store.heaviest_tipset().unwrap())
+            .await
+            .unwrap();
+        assert!(stats.total.blocks > 0);
+        assert_eq!(
+            stats.total.blocks,
+            stats
+                .by_codec
+                .values()
+                .map(|codec| codec.blocks)
+                .sum::<u64>()
+        );
+    }
+
     fn genesis_timestamp(genesis_car: &'static [u8]) -> u64 {
         let db = crate::db::car::PlainCar::try_from(genesis_car).unwrap();
         let ts = db.heaviest_tipset().unwrap();
@@ -421,7 +611,7 @@ mod tests {
         let heaviest_tipset = store.heaviest_tipset().unwrap();
         do_export(
             store,
-            heaviest_tipset,
+            heaviest_tipset.clone(),
             output_path.path().into(),
             Some(0),
             1,
@@ -430,17 +620,21 @@ mod tests {
         )
         .await
         .unwrap();
-        let file = tokio::fs::File::open(build_output_path(
+        let snapshot_path = build_output_path(
             NetworkChain::Calibnet.to_string(),
             genesis_timestamp(calibnet::DEFAULT_GENESIS),
             0,
             output_path.path().into(),
-        ))
-        .await
-        .unwrap();
+        );
+        let file = tokio::fs::File::open(&snapshot_path).await.unwrap();
         let file = BufReader::new(file);
         CarReader::new(ZstdDecoder::new(file).compat())
             .await
             .unwrap();
+
+        // Exports are seekable and can be used as a store without conversion.
+        let car =
+            crate::db::car::ForestCar::new(std::fs::File::open(&snapshot_path).unwrap()).unwrap();
+        assert_eq!(car.heaviest_tipset().unwrap(), heaviest_tipset);
     }
 }
@@ -0,0 +1,364 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Backups of the state of a node that can't be recovered from a snapshot.
+//!
+//! A backup bundles the keystore and the `libp2p` identity of the data
+//! directory with the configuration of the node. When the node is running,
+//! the pending messages of the local wallet, the connected peers and the head
+//! tipset are included as well, as they only live in memory. Chain data is
+//! excluded, a node is rebuilt from a snapshot and the backup.
+//!
+//! The bundle is encrypted with a key derived from a passphrase, the same way
+//! as the encrypted keystore.
+
+use std::path::{Path, PathBuf};
+
+use crate::blocks::TipsetKeys;
+use crate::cli_shared::cli::Config;
+use crate::json::cid::vec::CidJsonVec;
+use crate::key_management::{
+    decrypt_with_passphrase, encrypt_with_passphrase, ENCRYPTED_KEYSTORE_NAME, KEYSTORE_NAME,
+};
+use crate::libp2p::Multiaddr;
+use crate::lotus_json::LotusJson;
+use crate::message::SignedMessage;
+use crate::rpc_client::{chain_head, mpool_pending, net_peers, wallet_list};
+use crate::shim::clock::ChainEpoch;
+use crate::utils::io::set_user_perm;
+use ahash::HashSet;
+use anyhow::{bail, ensure, Context as _};
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::cli::subcommands::handle_rpc_err;
+
+/// Version of the backup format.
+const BACKUP_VERSION: u64 = 1;
+
+/// Environment variable holding the passphrase of backups, for
+/// non-interactive use.
+const FOREST_BACKUP_PHRASE_ENV: &str = "FOREST_BACKUP_PHRASE";
+
+/// Files of the data directory that are backed up, if present.
+const BACKUP_FILES: [&str; 3] = [KEYSTORE_NAME, ENCRYPTED_KEYSTORE_NAME, "libp2p/keypair"];
+
+/// Directory of the data directory the pending messages are restored to.
+const RESTORED_MESSAGES_DIR: &str = "restored_messages";
+
+#[derive(Debug, Subcommand)]
+pub enum BackupCommands {
+    /// Create an encrypted backup of the keystore, the configuration, the
+    /// local pending messages and the peers of the node
+    Create {
+        /// Path of the backup file
+        output: PathBuf,
+        /// Overwrite an existing backup file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Restore a backup into the data directory of the configuration
+    Restore {
+        /// Path of the backup file
+        input: PathBuf,
+        /// Overwrite existing files of the data directory
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BackupFile {
+    /// Path relative to the data directory.
+    path: String,
+    #[serde(with = "crate::lotus_json")]
+    data: Vec<u8>,
+}
+
+/// Head of the node when the backup was created. Snapshots used to rebuild
+/// the node should be at least as recent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Checkpoint {
+    epoch: ChainEpoch,
+    #[serde(with = "crate::lotus_json")]
+    tipset: TipsetKeys,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Backup {
+    version: u64,
+    network: String,
+    /// Configuration of the node, in TOML format.
+    config: String,
+    files: Vec<BackupFile>,
+    /// Pending messages sent from the addresses of the local wallet.
+    #[serde(with = "crate::lotus_json")]
+    messages: Vec<SignedMessage>,
+    /// Addresses of the connected peers, including their peer IDs.
+    peers: Vec<Multiaddr>,
+    checkpoint: Option<Checkpoint>,
+}
+
+impl BackupCommands {
+    pub async fn run(self, config: Config) -> anyhow::Result<()> {
+        match self {
+            Self::Create { output, force } => {
+                ensure!(
+                    force || !output.exists(),
+                    "{} already exists, use --force to overwrite it",
+                    output.display()
+                );
+                let mut backup = Backup::from_data_dir(&config)?;
+                if let Err(e) = backup.add_node_state(&config).await {
+                    warn!("Failed to query the node, the pending messages, peers and head are not backed up: {e:#}");
+                }
+                let passphrase = passphrase(true)?;
+                let encrypted =
+                    encrypt_with_passphrase(&passphrase, &serde_json::to_vec(&backup)?)?;
+                let file = std::fs::File::create(&output)?;
+                set_user_perm(&file)?;
+                std::fs::write(&output, encrypted)?;
+                println!(
+                    "Backed up {} files, {} pending messages and {} peers to {}",
+                    backup.files.len(),
+                    backup.messages.len(),
+                    backup.peers.len(),
+                    output.display()
+                );
+                Ok(())
+            }
+            Self::Restore { input, force } => {
+                let encrypted = std::fs::read(&input)
+                    .with_context(|| format!("Failed to read {}", input.display()))?;
+                let passphrase = passphrase(false)?;
+                let backup: Backup = serde_json::from_slice(
+                    &decrypt_with_passphrase(&passphrase, &encrypted)
+                        .context("Failed to decrypt the backup, is the passphrase correct?")?,
+                )
+                .context("Invalid backup")?;
+                let config_path = backup.restore(&config.client.data_dir, force)?;
+                if let Some(checkpoint) = &backup.checkpoint {
+                    println!(
+                        "The backup was created at epoch {}, import a more recent {} snapshot",
+                        checkpoint.epoch, backup.network
+                    );
+                }
+                if !backup.messages.is_empty() {
+                    println!(
+                        "Pending messages were restored to {}, resubmit them with `forest-cli mpool push` once the node is synced",
+                        config.client.data_dir.join(RESTORED_MESSAGES_DIR).display()
+                    );
+                }
+                println!(
+                    "Restored the backup, start the node with `forest --config {}`",
+                    config_path.display()
+                );
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Backup {
+    fn from_data_dir(config: &Config) -> anyhow::Result<Self> {
+        let mut files = vec![];
+        for path in BACKUP_FILES {
+            let full_path = config.client.data_dir.join(path);
+            if full_path.exists() {
+                files.push(BackupFile {
+                    path: path.into(),
+                    data: std::fs::read(&full_path)
+                        .with_context(|| format!("Failed to read {}", full_path.display()))?,
+                });
+            }
+        }
+        ensure!(
+            !files.is_empty(),
+            "No keystore or keypair found in {}",
+            config.client.data_dir.display()
+        );
+        Ok(Self {
+            version: BACKUP_VERSION,
+            network: config.chain.network.to_string(),
+            config: toml::to_string(config)
+                .context("Could not convert configuration to TOML format")?,
+            files,
+            messages: vec![],
+            peers: vec![],
+            checkpoint: None,
+        })
+    }
+
+    /// Adds the state that only lives in the memory of a running node.
+    async fn add_node_state(&mut self, config: &Config) -> anyhow::Result<()> {
+        let token = &config.client.rpc_token;
+        let local_addrs: HashSet<_> = wallet_list((), token)
+            .await
+            .map_err(handle_rpc_err)?
+            .into_iter()
+            .map(|addr| addr.0)
+            .collect();
+        let LotusJson(pending) = mpool_pending((CidJsonVec(vec![]),), token)
+            .await
+            .map_err(handle_rpc_err)?;
+        self.messages = pending
+            .into_iter()
+            .filter(|msg| local_addrs.contains(&msg.message.from))
+            .collect();
+
+        for peer in net_peers((), token).await.map_err(handle_rpc_err)? {
+            for addr in peer.addrs {
+                self.peers.push(format!("{addr}/p2p/{}", peer.id).parse()?);
+            }
+        }
+
+        let LotusJson(head) = chain_head(token).await.map_err(handle_rpc_err)?;
+        self.checkpoint = Some(Checkpoint {
+            epoch: head.epoch(),
+            tipset: head.key().clone(),
+        });
+        Ok(())
+    }
+
+    /// Writes the files and pending messages of the backup to `data_dir`, and
+    /// the configuration, pointing to `data_dir` and bootstrapping from the
+    /// backed up peers, to `config.toml` in it. Returns the path of the
+    /// configuration.
+    fn restore(&self, data_dir: &Path, force: bool) -> anyhow::Result<PathBuf> {
+        if self.version != BACKUP_VERSION {
+            bail!("Unsupported backup version {}", self.version);
+        }
+        let mut config: Config =
+            toml::from_str(&self.config).context("Invalid configuration in the backup")?;
+        config.client.data_dir = data_dir.to_path_buf();
+        for peer in &self.peers {
+            if !config.network.bootstrap_peers.contains(peer) {
+                config.network.bootstrap_peers.push(peer.clone());
+            }
+        }
+
+        let config_path = data_dir.join("config.toml");
+        let mut writes = vec![(config_path.clone(), toml::to_string(&config)?.into_bytes())];
+        for file in &self.files {
+            ensure!(
+                Path::new(&file.path)
+                    .components()
+                    .all(|c| matches!(c, std::path::Component::Normal(_))),
+                "Invalid path {} in the backup",
+                file.path
+            );
+            writes.push((data_dir.join(&file.path), file.data.clone()));
+        }
+        for message in &self.messages {
+            writes.push((
+                data_dir
+                    .join(RESTORED_MESSAGES_DIR)
+                    .join(format!("{}.json", message.cid()?)),
+                serde_json::to_vec_pretty(&LotusJson(message.clone()))?,
+            ));
+        }
+
+        if !force {
+            if let Some((path, _)) = writes.iter().find(|(path, _)| path.exists()) {
+                bail!(
+                    "{} already exists, use --force to overwrite it",
+                    path.display()
+                );
+            }
+        }
+        for (path, data) in writes {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let file = std::fs::File::create(&path)?;
+            set_user_perm(&file)?;
+            std::fs::write(&path, data)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+        Ok(config_path)
+    }
+}
+
+/// Reads the passphrase of a backup from [`FOREST_BACKUP_PHRASE_ENV`], or
+/// prompts for it. New passphrases have to be confirmed.
+fn passphrase(new: bool) -> anyhow::Result<String> {
+    if let Ok(passphrase) = std::env::var(FOREST_BACKUP_PHRASE_ENV) {
+        return Ok(passphrase);
+    }
+    let term = dialoguer::console::Term::stderr();
+    ensure!(
+        term.is_term(),
+        "Cannot read the passphrase from a non-terminal, set {FOREST_BACKUP_PHRASE_ENV}"
+    );
+    let mut prompt = dialoguer::Password::new();
+    prompt.with_prompt("Enter the passphrase of the backup");
+    if new {
+        prompt.with_confirmation(
+            "Confirm passphrase",
+            "Error: the passphrases do not match. Try again or press Ctrl+C to abort.",
+        );
+    }
+    Ok(prompt.interact_on(&term)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shim::{address::Address, crypto::Signature, message::Message};
+
+    #[test]
+    fn restore_backup() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::write(source.path().join(KEYSTORE_NAME), b"keys").unwrap();
+        std::fs::create_dir(source.path().join("libp2p")).unwrap();
+        std::fs::write(source.path().join("libp2p/keypair"), b"keypair").unwrap();
+        let mut config = Config::default();
+        config.client.data_dir = source.path().to_path_buf();
+
+        let mut backup = Backup::from_data_dir(&config).unwrap();
+        assert_eq!(backup.files.len(), 2);
+        let message = SignedMessage {
+            message: Message {
+                from: Address::new_id(1000),
+                ..Default::default()
+            },
+            signature: Signature::new_secp256k1(vec![0; 65]),
+        };
+        backup.messages.push(message.clone());
+        let peer: Multiaddr =
+            "/ip4/127.0.0.1/tcp/1234/p2p/12D3KooWBBHDpVbkqBHCGqwzyj4ZJN2aBfW3pT9wAdkEWjuNsu7S"
+                .parse()
+                .unwrap();
+        backup.peers.push(peer.clone());
+        let encrypted =
+            encrypt_with_passphrase("passphrase", &serde_json::to_vec(&backup).unwrap()).unwrap();
+        let decrypted: Backup =
+            serde_json::from_slice(&decrypt_with_passphrase("passphrase", &encrypted).unwrap())
+                .unwrap();
+        assert_eq!(decrypted, backup);
+
+        let target = tempfile::tempdir().unwrap();
+        let config_path = backup.restore(target.path(), false).unwrap();
+        assert_eq!(
+            std::fs::read(target.path().join("libp2p/keypair")).unwrap(),
+            b"keypair"
+        );
+        assert!(target
+            .path()
+            .join(RESTORED_MESSAGES_DIR)
+            .join(format!("{}.json", message.cid().unwrap()))
+            .exists());
+        let restored: Config =
+            toml::from_str(&std::fs::read_to_string(config_path).unwrap()).unwrap();
+        assert_eq!(restored.client.data_dir, target.path());
+        assert!(restored.network.bootstrap_peers.contains(&peer));
+
+        // Existing files are only overwritten with `force`.
+        assert!(backup.restore(target.path(), false).is_err());
+        backup.restore(target.path(), true).unwrap();
+    }
+}
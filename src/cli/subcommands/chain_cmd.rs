@@ -2,13 +2,19 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 use crate::blocks::{Tipset, TipsetKeys};
+use crate::chain::headchange_json::HeadChangeJson;
+use crate::chain::inclusion_proof::verify_inclusion;
 use crate::json::cid::CidJson;
 use crate::lotus_json::LotusJson;
+use crate::rpc_api::{chain_api::ChainPinTarget, tipset_selector::TipsetSelector};
 use crate::rpc_client::chain_ops::*;
+use crate::shim::clock::ChainEpoch;
+use crate::state_manager::chain_validation::ValidateFrom;
 use anyhow::bail;
 use cid::Cid;
 use clap::Subcommand;
-use futures::TryFutureExt;
+use futures::{StreamExt, TryFutureExt};
+use itertools::Itertools as _;
 
 use super::*;
 
@@ -26,6 +32,10 @@ pub enum ChainCommands {
     /// Prints out the canonical head of the chain
     Head,
 
+    /// Prints out the head changes of the chain as they happen, starting with
+    /// the current head
+    Notify,
+
     /// Reads and prints out a message referenced by the specified CID from the
     /// chain block store
     Message {
@@ -54,6 +64,66 @@ pub enum ChainCommands {
         #[arg(short, long, aliases = ["yes", "no-confirm"], short_alias = 'y')]
         force: bool,
     },
+
+    /// Prints the tipsets to revert and apply to move from one tipset to
+    /// another, as JSON
+    Path {
+        /// Epoch of the tipset to start from, or an offset from the head if
+        /// negative
+        #[arg(allow_hyphen_values = true)]
+        from: i64,
+        /// Epoch of the tipset to move to, or an offset from the head if
+        /// negative
+        #[arg(allow_hyphen_values = true)]
+        to: i64,
+    },
+
+    /// Fetches the proof that a message is included in a tipset, checks it
+    /// locally and prints it as JSON
+    InclusionProof {
+        /// CID of the message
+        message: Cid,
+        /// Epoch of the tipset, or an offset from the head if negative
+        #[arg(allow_hyphen_values = true)]
+        epoch: i64,
+    },
+
+    /// Re-executes the chain and verifies every state root. An interrupted
+    /// validation is resumed. Without `--from`, prints the progress of the
+    /// current or last validation.
+    Validate {
+        /// Where to start: `genesis`, `snapshot` (the oldest tipset whose
+        /// parent state is available) or an epoch.
+        #[arg(long)]
+        from: Option<ValidateFrom>,
+        /// Epoch to stop at, defaults to the head.
+        #[arg(long, requires = "from")]
+        to: Option<ChainEpoch>,
+        /// Discard the progress of a previous validation.
+        #[arg(long, requires = "from")]
+        restart: bool,
+        /// Return once the validation has started, instead of following its
+        /// progress.
+        #[arg(long)]
+        detach: bool,
+    },
+
+    /// Pins a state, so that the garbage collector keeps it whatever its age,
+    /// and prints its root.
+    Pin {
+        /// State root to pin.
+        #[arg(long, required_unless_present = "epoch")]
+        state_root: Option<Cid>,
+        /// Pin the parent state of the tipset at this epoch instead.
+        #[arg(long, conflicts_with = "state_root")]
+        epoch: Option<ChainEpoch>,
+    },
+
+    /// Unpins a state root.
+    Unpin { state_root: Cid },
+
+    /// Lists the pinned state roots.
+    Pins,
 }
 
 impl ChainCommands {
@@ -66,6 +136,26 @@ impl ChainCommands {
                 print_rpc_res_pretty(chain_get_genesis(&config.client.rpc_token).await)
             }
             Self::Head => print_rpc_res_cids(chain_head(&config.client.rpc_token).await),
+            Self::Notify => {
+                let changes = chain_notify((), &config.client.rpc_token).await?;
+                futures::pin_mut!(changes);
+                while let Some(changes) = changes.next().await {
+                    for change in changes? {
+                        let (kind, tipset) = match change {
+                            HeadChangeJson::Current(tipset) => ("current", tipset),
+                            HeadChangeJson::Revert(tipset) => ("revert", tipset),
+                            HeadChangeJson::Apply(tipset) => ("apply", tipset),
+                        };
+                        let tipset = tipset.into_inner();
+                        println!(
+                            "{kind} {}: {}",
+                            tipset.epoch(),
+                            tipset.cids().iter().map(ToString::to_string).join(", ")
+                        );
+                    }
+                }
+                Ok(())
+            }
             Self::Message { cid } => print_rpc_res_pretty(
                 chain_get_message((CidJson(*cid),), &config.client.rpc_token).await,
             ),
@@ -82,7 +172,7 @@ impl ChainCommands {
                 tipset_by_epoch_or_offset(*epoch, &config.client.rpc_token)
                     .and_then(|tipset_json| {
                         chain_set_head(
-                            (tipset_json.into_inner().key().clone(),),
+                            (LotusJson(tipset_json.into_inner().key().clone().into()),),
                             &config.client.rpc_token,
                         )
                     })
@@ -95,17 +185,135 @@ impl ChainCommands {
                 force: no_confirm,
             } => {
                 maybe_confirm(*no_confirm, SET_HEAD_CONFIRMATION_MESSAGE)?;
-                chain_set_head((TipsetKeys::from(cids.clone()),), &config.client.rpc_token)
+                chain_set_head(
+                    (LotusJson(TipsetKeys::from(cids.clone()).into()),),
+                    &config.client.rpc_token,
+                )
+                .await
+                .map_err(handle_rpc_err)
+            }
+            Self::Path { from, to } => {
+                let auth_token = &config.client.rpc_token;
+                let from = tipset_by_epoch_or_offset(*from, auth_token)
                     .await
-                    .map_err(handle_rpc_err)
+                    .map_err(handle_rpc_err)?
+                    .into_inner();
+                let to = tipset_by_epoch_or_offset(*to, auth_token)
+                    .await
+                    .map_err(handle_rpc_err)?
+                    .into_inner();
+                print_rpc_res_pretty(
+                    chain_get_path(
+                        (
+                            LotusJson(from.key().clone().into()),
+                            LotusJson(to.key().clone().into()),
+                        ),
+                        auth_token,
+                    )
+                    .await,
+                )
+            }
+            Self::InclusionProof { message, epoch } => {
+                let auth_token = &config.client.rpc_token;
+                let tipset = tipset_by_epoch_or_offset(*epoch, auth_token)
+                    .await
+                    .map_err(handle_rpc_err)?
+                    .into_inner();
+                let proof = chain_get_message_inclusion_proof(
+                    (CidJson(*message), LotusJson(tipset.key().clone().into())),
+                    auth_token,
+                )
+                .await
+                .map_err(handle_rpc_err)?;
+                verify_inclusion(&proof, tipset.key())?;
+                println!("{}", serde_json::to_string_pretty(&proof)?);
+                Ok(())
+            }
+            Self::Validate {
+                from,
+                to,
+                restart,
+                detach,
+            } => {
+                let token = &config.client.rpc_token;
+                let mut progress = chain_validate((*from, *to, *restart), token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                loop {
+                    let Some(current) = &progress else {
+                        println!("No chain validation has been started");
+                        return Ok(());
+                    };
+                    println!(
+                        "Validated epochs {} to {} of {} ({:.2} epochs/s)",
+                        current.from,
+                        current.validated,
+                        current.to,
+                        current.epochs_per_sec()
+                    );
+                    if let Some(divergence) = &current.divergence {
+                        println!(
+                            "First divergence at epoch {}, tipset {}",
+                            divergence.epoch, divergence.tipset
+                        );
+                    }
+                    if let Some(error) = &current.error {
+                        bail!("Chain validation failed: {error}");
+                    }
+                    if !current.running || *detach {
+                        if current.is_finished() {
+                            println!("Chain validated");
+                        }
+                        return Ok(());
+                    }
+                    tokio::time::sleep(VALIDATE_POLL_INTERVAL).await;
+                    progress = chain_validate((None, None, false), token)
+                        .await
+                        .map_err(handle_rpc_err)?;
+                }
+            }
+            Self::Pin { state_root, epoch } => {
+                let target = match (state_root, epoch) {
+                    (Some(state_root), _) => ChainPinTarget::StateRoot(*state_root),
+                    (None, Some(epoch)) => ChainPinTarget::Tipset(TipsetSelector::Height {
+                        height: *epoch,
+                        anchor: Default::default(),
+                    }),
+                    (None, None) => unreachable!("should be disallowed by clap"),
+                };
+                let LotusJson(state_root) = chain_pin((target,), &config.client.rpc_token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                println!("Pinned {state_root}");
+                Ok(())
+            }
+            Self::Unpin { state_root } => {
+                let unpinned = chain_unpin((LotusJson(*state_root),), &config.client.rpc_token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                if !unpinned {
+                    bail!("{state_root} is not pinned");
+                }
+                Ok(())
+            }
+            Self::Pins => {
+                let LotusJson(pinned) = chain_pins(&config.client.rpc_token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                for state_root in pinned {
+                    println!("{state_root}");
+                }
+                Ok(())
             }
         }
     }
 }
 
+const VALIDATE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
 /// If `epoch_or_offset` is negative, get the tipset that many blocks before the
 /// current head. Else treat `epoch_or_offset` as an epoch, and get that tipset.
-async fn tipset_by_epoch_or_offset(
+pub(super) async fn tipset_by_epoch_or_offset(
     epoch_or_offset: i64,
     auth_token: &Option<String>,
 ) -> Result<LotusJson<Tipset>, JsonRpcError> {
@@ -116,7 +324,11 @@ async fn tipset_by_epoch_or_offset(
         false => epoch_or_offset,
     };
 
-    chain_get_tipset_by_height((target_epoch, current_head.key().clone()), auth_token).await
+    chain_get_tipset_by_height(
+        (target_epoch, LotusJson(current_head.key().clone().into())),
+        auth_token,
+    )
+    .await
 }
 
 const SET_HEAD_CONFIRMATION_MESSAGE: &str =
@@ -6,16 +6,20 @@ use std::io::Write;
 use anyhow::Context;
 use clap::Subcommand;
 
-use crate::cli::subcommands::Config;
+use crate::cli::subcommands::{handle_rpc_err, Config};
+use crate::rpc_client::node_ops::get_config;
 
 #[derive(Debug, Subcommand)]
 pub enum ConfigCommands {
     /// Dump current configuration to standard output
     Dump,
+    /// Print the effective configuration of the running node, with secrets
+    /// redacted, along with its feature flags and component versions
+    Node,
 }
 
 impl ConfigCommands {
-    pub fn run<W: Write + Unpin>(&self, config: &Config, sink: &mut W) -> anyhow::Result<()> {
+    pub async fn run<W: Write + Unpin>(&self, config: &Config, sink: &mut W) -> anyhow::Result<()> {
         match self {
             Self::Dump => writeln!(
                 sink,
@@ -24,6 +28,13 @@ impl ConfigCommands {
                     .context("Could not convert configuration to TOML format")?
             )
             .context("Failed to write the configuration"),
+            Self::Node => {
+                let node_config = get_config(&config.client.rpc_token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                writeln!(sink, "{}", serde_json::to_string_pretty(&node_config)?)
+                    .context("Failed to write the configuration")
+            }
         }
     }
 }
@@ -39,6 +50,7 @@ mod tests {
 
         ConfigCommands::Dump
             .run(&expected_config, &mut sink)
+            .await
             .unwrap();
 
         let actual_config: Config = toml::from_str(std::str::from_utf8(sink.buffer()).unwrap())
@@ -0,0 +1,405 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::blocks::{Tipset, TipsetKeys};
+use crate::cli_shared::{
+    chain_path,
+    cli::{unknown_keys, Config, ConfigPath},
+};
+use crate::db::{
+    db_engine::{db_root, open_proxy_db},
+    setting_keys::HEAD_KEY,
+    SettingsStoreExt,
+};
+use crate::shim::clock::ALLOWABLE_CLOCK_DRIFT;
+use crate::utils::net::global_http_client;
+use crate::utils::proofs_api::paramfetch::{
+    missing_verification_keys, set_proofs_parameter_cache_dir_env,
+};
+use anyhow::Context as _;
+use fvm_ipld_blockstore::Blockstore;
+use human_repr::HumanCount;
+use libp2p::{multiaddr::Protocol, Multiaddr};
+
+/// Minimum free space on the disk of the data directory. Snapshots alone take
+/// more than 100 GiB on mainnet.
+const MIN_FREE_DISK_SPACE: u64 = 200 * 1024 * 1024 * 1024;
+/// Number of tipsets below the head whose headers are loaded by the database
+/// scan.
+const DB_SCAN_DEPTH: usize = 20;
+/// Timeout of each network check.
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Checks the environment of the node: the data directory, the database, the
+/// configuration, the network connectivity, the proof parameters, the clock
+/// and the disk space. Each problem is reported with a way to fix it.
+#[derive(Debug, clap::Args)]
+pub struct DoctorCommand {
+    /// Skip the checks that require network access
+    #[arg(long)]
+    offline: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    Pass(String),
+    Warn { problem: String, fix: String },
+    Fail { problem: String, fix: String },
+}
+
+fn pass(message: impl Into<String>) -> Outcome {
+    Outcome::Pass(message.into())
+}
+
+fn warn(problem: impl Into<String>, fix: impl Into<String>) -> Outcome {
+    Outcome::Warn {
+        problem: problem.into(),
+        fix: fix.into(),
+    }
+}
+
+fn fail(problem: impl Into<String>, fix: impl Into<String>) -> Outcome {
+    Outcome::Fail {
+        problem: problem.into(),
+        fix: fix.into(),
+    }
+}
+
+impl DoctorCommand {
+    pub async fn run(&self, config: Config, config_path: Option<ConfigPath>) -> anyhow::Result<()> {
+        let mut checks = vec![
+            ("data directory", check_data_dir(&config.client.data_dir)),
+            ("database", check_database(&config).await),
+            ("configuration", check_config(&config, config_path.as_ref())),
+            ("proof parameters", check_params(&config)),
+            ("disk space", check_disk_space(&config.client.data_dir)),
+        ];
+        if !self.offline {
+            checks.push(("bootstrap peers", check_bootstrap_peers(&config).await));
+            let (drand, clock) = check_drand_and_clock(&config).await;
+            checks.push(("drand", drand));
+            checks.push(("clock", clock));
+        }
+
+        let mut failures = 0;
+        for (name, outcome) in &checks {
+            match outcome {
+                Outcome::Pass(message) => println!("[ OK ] {name}: {message}"),
+                Outcome::Warn { problem, fix } => {
+                    println!("[WARN] {name}: {problem}");
+                    println!("       fix: {fix}");
+                }
+                Outcome::Fail { problem, fix } => {
+                    failures += 1;
+                    println!("[FAIL] {name}: {problem}");
+                    println!("       fix: {fix}");
+                }
+            }
+        }
+        anyhow::ensure!(
+            failures == 0,
+            "{failures} of {} checks failed",
+            checks.len()
+        );
+        Ok(())
+    }
+}
+
+fn check_data_dir(data_dir: &Path) -> Outcome {
+    if !data_dir.exists() {
+        return warn(
+            format!("{} does not exist", data_dir.display()),
+            "it is created on the first start, or set `client.data_dir` in the configuration",
+        );
+    }
+    match tempfile::tempfile_in(data_dir) {
+        Ok(_) => pass(format!("{} is writable", data_dir.display())),
+        Err(e) => fail(
+            format!("{} is not writable: {e}", data_dir.display()),
+            "make the directory writable by the user running the node, or set `client.data_dir`",
+        ),
+    }
+}
+
+async fn check_database(config: &Config) -> Outcome {
+    let root = db_root(&chain_path(config));
+    if !root.exists() {
+        return warn(
+            format!("there is no database in {}", root.display()),
+            "start the node with `--auto-download-snapshot` or import a snapshot",
+        );
+    }
+    let db_config = config.db_config().clone();
+    let scan = tokio::task::spawn_blocking(move || scan_database(root, db_config)).await;
+    match scan {
+        Ok(Ok(message)) => pass(message),
+        Ok(Err(e)) if is_locked(&e) => warn(
+            "the database is locked by a running node, it was not scanned",
+            "stop the node with `forest-cli shutdown` to scan the database",
+        ),
+        Ok(Err(e)) => fail(
+            format!("{e:#}"),
+            "the database may be corrupted, run `forest-cli db clean` and import a snapshot",
+        ),
+        Err(e) => fail(e.to_string(), "report the issue to the Forest maintainers"),
+    }
+}
+
+fn is_locked(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<parity_db::Error>(),
+            Some(parity_db::Error::Locked(_))
+        )
+    })
+}
+
+/// Loads the head and the tipsets below it, and checks that the state of the
+/// head is in the database.
+fn scan_database(
+    root: PathBuf,
+    db_config: crate::db::db_engine::DbConfig,
+) -> anyhow::Result<String> {
+    let db = open_proxy_db(root, db_config)?;
+    let head_key: TipsetKeys = db
+        .read_obj(HEAD_KEY)?
+        .context("the head of the chain is not set")?;
+    let head =
+        Tipset::load(&db, &head_key)?.with_context(|| format!("the head {head_key} is missing"))?;
+    anyhow::ensure!(
+        db.has(head.parent_state())?,
+        "the state of the head at epoch {} is missing",
+        head.epoch()
+    );
+    let (scanned, lowest) = head
+        .clone()
+        .chain(&db)
+        .take(DB_SCAN_DEPTH)
+        .fold((0, head.epoch()), |(scanned, _), tipset| {
+            (scanned + 1, tipset.epoch())
+        });
+    anyhow::ensure!(
+        scanned == DB_SCAN_DEPTH || lowest == 0,
+        "the parents of the tipset at epoch {lowest} are missing"
+    );
+    Ok(format!(
+        "the head is at epoch {} and the last {scanned} tipsets are readable",
+        head.epoch()
+    ))
+}
+
+fn check_config(config: &Config, config_path: Option<&ConfigPath>) -> Outcome {
+    let mut problems = vec![];
+    if let Some(path) = config_path {
+        problems.extend(unknown_keys(path.to_path_buf(), config));
+    }
+    let finality = config.chain.policy.chain_finality;
+    if let Some(retention) = config.client.gc_retention {
+        if retention < finality {
+            problems.push(format!(
+                "`client.gc_retention` is {retention}, below the chain finality of {finality}"
+            ));
+        }
+    }
+    if config.network.bootstrap_peers.is_empty() && config.chain.bootstrap_peers.is_empty() {
+        problems.push("no bootstrap peers are configured".into());
+    }
+    match (problems.is_empty(), config_path) {
+        (true, Some(path)) => pass(format!("{} is valid", path.to_path_buf().display())),
+        (true, None) => pass("no configuration file, the defaults are used"),
+        (false, _) => fail(
+            problems.join(", "),
+            "fix the configuration file, `forest-cli config dump` shows the valid keys",
+        ),
+    }
+}
+
+fn check_params(config: &Config) -> Outcome {
+    set_proofs_parameter_cache_dir_env(
+        &config.client.data_dir,
+        config.client.proofs_parameter_cache.as_deref(),
+    );
+    match missing_verification_keys(&config.client.data_dir) {
+        Ok(missing) if missing.is_empty() => pass("all verification keys are present"),
+        Ok(missing) => warn(
+            format!("{} verification keys are missing", missing.len()),
+            "they are downloaded on start, or run `forest-cli fetch-params --keys`",
+        ),
+        Err(e) => fail(e.to_string(), "report the issue to the Forest maintainers"),
+    }
+}
+
+fn check_disk_space(data_dir: &Path) -> Outcome {
+    // The data directory may not exist yet, its closest ancestor is on the
+    // same disk.
+    let Some(dir) = data_dir.ancestors().find(|dir| dir.exists()) else {
+        return fail(
+            format!("no ancestor of {} exists", data_dir.display()),
+            "set `client.data_dir` to an absolute path",
+        );
+    };
+    match available_space(dir) {
+        Ok(free) if free >= MIN_FREE_DISK_SPACE => {
+            pass(format!("{} available", free.human_count_bytes()))
+        }
+        Ok(free) => warn(
+            format!(
+                "only {} available, at least {} are recommended",
+                free.human_count_bytes(),
+                MIN_FREE_DISK_SPACE.human_count_bytes()
+            ),
+            "free up disk space or move `client.data_dir` to a larger disk",
+        ),
+        Err(e) => fail(
+            format!("failed to query the free space: {e}"),
+            "check that the data directory is on a mounted file system",
+        ),
+    }
+}
+
+fn available_space(path: &Path) -> std::io::Result<u64> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is a valid C string and `stat` is only read on success.
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let stat = unsafe { stat.assume_init() };
+    #[allow(clippy::unnecessary_cast)] // the field types depend on the platform
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Returns the host and port to dial a TCP multi-address.
+fn tcp_address(addr: &Multiaddr) -> Option<(String, u16)> {
+    let mut host = None;
+    for protocol in addr.iter() {
+        match protocol {
+            Protocol::Ip4(ip) => host = Some(ip.to_string()),
+            Protocol::Ip6(ip) => host = Some(ip.to_string()),
+            Protocol::Dns(name) | Protocol::Dns4(name) | Protocol::Dns6(name) => {
+                host = Some(name.to_string())
+            }
+            Protocol::Tcp(port) => return host.map(|host| (host, port)),
+            _ => {}
+        }
+    }
+    None
+}
+
+async fn check_bootstrap_peers(config: &Config) -> Outcome {
+    let peers = match config.network.bootstrap_peers.is_empty() {
+        true => &config.chain.bootstrap_peers,
+        false => &config.network.bootstrap_peers,
+    };
+    let addresses: Vec<_> = peers.iter().filter_map(tcp_address).collect();
+    if addresses.is_empty() {
+        return fail(
+            "no bootstrap peer has a TCP address",
+            "add TCP multi-addresses to `network.bootstrap_peers`",
+        );
+    }
+    let dials = addresses.iter().map(|(host, port)| {
+        tokio::time::timeout(
+            NETWORK_TIMEOUT,
+            tokio::net::TcpStream::connect((host.as_str(), *port)),
+        )
+    });
+    let reachable = futures::future::join_all(dials)
+        .await
+        .into_iter()
+        .filter(|dial| matches!(dial, Ok(Ok(_))))
+        .count();
+    let message = format!("{reachable} of {} peers are reachable", addresses.len());
+    match reachable {
+        0 => fail(
+            message,
+            "check the internet connection and that outgoing TCP connections are allowed",
+        ),
+        n if n < addresses.len() => warn(
+            message,
+            "some peers may be down, check the connection if the node can't find peers",
+        ),
+        _ => pass(message),
+    }
+}
+
+/// Checks that the drand servers answer and compares the local clock with the
+/// `Date` of their responses. Gossiped blocks are rejected if the clocks are
+/// too far apart.
+async fn check_drand_and_clock(config: &Config) -> (Outcome, Outcome) {
+    let servers = config.chain.drand_servers();
+    let mut reachable = 0;
+    let mut skew = None;
+    for server in &servers {
+        let request = global_http_client()
+            .get(format!("{server}/info"))
+            .timeout(NETWORK_TIMEOUT)
+            .send();
+        let Ok(Ok(response)) = request.await.map(|r| r.error_for_status()) else {
+            continue;
+        };
+        reachable += 1;
+        let date = response
+            .headers()
+            .get(reqwest::header::DATE)
+            .and_then(|date| date.to_str().ok())
+            .and_then(|date| chrono::DateTime::parse_from_rfc2822(date).ok());
+        if let Some(date) = date {
+            skew.get_or_insert((chrono::Utc::now().timestamp() - date.timestamp()).abs());
+        }
+    }
+
+    let drand = match reachable {
+        0 => fail(
+            format!("none of the {} drand servers are reachable", servers.len()),
+            "check the internet connection, the beacon is needed to validate blocks",
+        ),
+        _ => pass(format!(
+            "{reachable} of {} servers are reachable",
+            servers.len()
+        )),
+    };
+    let clock = match skew {
+        None => warn(
+            "the clock could not be compared with a server",
+            "make sure the clock is synchronized, e.g. with NTP",
+        ),
+        // The `Date` header only has a precision of one second.
+        Some(skew) if skew > ALLOWABLE_CLOCK_DRIFT as i64 + 1 => fail(
+            format!("the clock is off by about {skew}s"),
+            "synchronize the clock with NTP, e.g. `timedatectl set-ntp true`",
+        ),
+        Some(skew) => pass(format!("the clock is within {skew}s of the drand servers")),
+    };
+    (drand, clock)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tcp_addresses() {
+        let addr = |s: &str| s.parse::<Multiaddr>().unwrap();
+        assert_eq!(
+            tcp_address(&addr("/dns4/bootstrap-0.mainnet.filops.net/tcp/1347/p2p/12D3KooWCVe8MmsEMes2FzgTpt9fXtmCY7wrq91GRiaC8PHSCCBj")),
+            Some(("bootstrap-0.mainnet.filops.net".into(), 1347))
+        );
+        assert_eq!(
+            tcp_address(&addr("/ip4/127.0.0.1/tcp/1234")),
+            Some(("127.0.0.1".into(), 1234))
+        );
+        assert_eq!(tcp_address(&addr("/ip4/127.0.0.1/udp/1234/quic")), None);
+    }
+
+    #[test]
+    fn disk_space() {
+        assert!(available_space(&std::env::temp_dir()).is_ok());
+        assert!(available_space(Path::new("/does/not/exist")).is_err());
+    }
+}
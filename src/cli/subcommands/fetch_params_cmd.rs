@@ -2,7 +2,9 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 use crate::shim::sector::SectorSize;
-use crate::utils::proofs_api::paramfetch::{get_params_default, SectorSizeOpt};
+use crate::utils::proofs_api::paramfetch::{
+    get_params_default, set_proofs_parameter_cache_dir_env, SectorSizeOpt,
+};
 
 use super::cli_error_and_die;
 use crate::cli::subcommands::Config;
@@ -39,6 +41,10 @@ impl FetchCommands {
             );
         };
 
+        set_proofs_parameter_cache_dir_env(
+            &config.client.data_dir,
+            config.client.proofs_parameter_cache.as_deref(),
+        );
         get_params_default(&config.client.data_dir, sizes, self.dry_run).await
     }
 }
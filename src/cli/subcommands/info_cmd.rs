@@ -2,17 +2,20 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 use crate::blocks::Tipset;
-use crate::cli_shared::cli::CliOpts;
+use crate::cli_shared::{chain_path, cli::CliOpts};
+use crate::db::db_engine::db_root;
+use crate::json::cid::vec::CidJsonVec;
 use crate::lotus_json::LotusJson;
 use crate::rpc_client::{
-    chain_get_name, chain_head, node_ops::node_status, start_time, wallet_balance,
-    wallet_default_address,
+    chain_get_name, chain_head, mpool_pending, net_peers, node_ops::node_status, start_time,
+    version, wallet_balance, wallet_default_address,
 };
 use crate::shim::econ::TokenAmount;
 use chrono::{DateTime, Utc};
 use clap::Subcommand;
 
 use crate::shim::clock::{ChainEpoch, BLOCKS_PER_EPOCH, EPOCH_DURATION_SECONDS};
+use human_repr::HumanCount;
 use humantime::format_duration;
 use num::BigInt;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
@@ -45,6 +48,15 @@ pub struct NodeStatusInfo {
     pub network: String,
     pub default_wallet_address: Option<String>,
     pub default_wallet_address_balance: Option<String>,
+    /// Version of the node
+    pub version: String,
+    /// Number of epochs the node has yet to sync
+    pub behind: u64,
+    pub peers: usize,
+    /// Number of messages pending in the message pool
+    pub mpool_size: usize,
+    /// Size of the database in bytes, if it is on this machine
+    pub disk_usage: Option<u64>,
 }
 
 #[derive(Debug, strum::Display, PartialEq)]
@@ -96,12 +108,19 @@ impl NodeStatusInfo {
             network,
             default_wallet_address,
             default_wallet_address_balance,
+            version: String::new(),
+            behind: 0,
+            peers: 0,
+            mpool_size: 0,
+            disk_usage: None,
         }
     }
 
     fn format(&self, now: DateTime<Utc>) -> String {
         let network = format!("Network: {}", self.network);
 
+        let version = format!("Version: {}", self.version);
+
         let uptime = {
             let uptime = (now - self.start_time)
                 .to_std()
@@ -129,7 +148,20 @@ impl NodeStatusInfo {
             )
         };
 
-        let chain_health = format!("Chain health: {:.2}%\n\n", self.health);
+        let chain_health = format!("Chain health: {:.2}%", self.health);
+
+        let sync_lag = format!("Sync lag: {} epochs", self.behind);
+
+        let peers = format!("Peers: {}", self.peers);
+
+        let mpool = format!("Message pool: {} pending messages", self.mpool_size);
+
+        let disk_usage = format!(
+            "Disk usage: {}\n\n",
+            self.disk_usage
+                .map(|size| size.human_count_bytes().to_string())
+                .unwrap_or("unknown".to_string())
+        );
 
         let wallet_info = {
             let wallet_address = self
@@ -156,22 +188,50 @@ impl NodeStatusInfo {
             )
         };
 
-        vec![network, uptime, chain, chain_health, wallet_info].join("\n")
+        vec![
+            network,
+            version,
+            uptime,
+            chain,
+            chain_health,
+            sync_lag,
+            peers,
+            mpool,
+            disk_usage,
+            wallet_info,
+        ]
+        .join("\n")
     }
 }
 
 impl InfoCommand {
     pub async fn run(&self, config: Config, _opts: &CliOpts) -> anyhow::Result<()> {
+        // Only known when the database is on this machine.
+        let db_dir = db_root(&chain_path(&config));
+        let disk_usage = tokio::task::spawn_blocking(move || fs_extra::dir::get_size(db_dir).ok());
+
         let res = tokio::try_join!(
             node_status((), &config.client.rpc_token),
             chain_head(&config.client.rpc_token),
             chain_get_name((), &config.client.rpc_token),
             start_time(&config.client.rpc_token),
-            wallet_default_address((), &config.client.rpc_token)
+            wallet_default_address((), &config.client.rpc_token),
+            version((), &config.client.rpc_token),
+            net_peers((), &config.client.rpc_token),
+            mpool_pending((CidJsonVec(vec![]),), &config.client.rpc_token),
         );
 
         match res {
-            Ok((node_status, LotusJson(head), network, start_time, default_wallet_address)) => {
+            Ok((
+                node_status,
+                LotusJson(head),
+                network,
+                start_time,
+                default_wallet_address,
+                version,
+                peers,
+                LotusJson(pending),
+            )) => {
                 let cur_duration: Duration = SystemTime::now().duration_since(UNIX_EPOCH)?;
                 let blocks_per_tipset_last_finality =
                     node_status.chain_status.blocks_per_tipset_last_finality;
@@ -186,7 +246,7 @@ impl InfoCommand {
                     None
                 };
 
-                let node_status_info = NodeStatusInfo::new(
+                let mut node_status_info = NodeStatusInfo::new(
                     cur_duration,
                     blocks_per_tipset_last_finality,
                     &head,
@@ -195,6 +255,11 @@ impl InfoCommand {
                     default_wallet_address.clone(),
                     default_wallet_address_balance,
                 );
+                node_status_info.version = version.version;
+                node_status_info.behind = node_status.sync_status.behind;
+                node_status_info.peers = peers.len();
+                node_status_info.mpool_size = pending.len();
+                node_status_info.disk_usage = disk_usage.await?;
 
                 println!("{}", node_status_info.format(Utc::now()));
 
@@ -216,6 +281,7 @@ mod tests {
     use crate::shim::clock::EPOCH_DURATION_SECONDS;
     use crate::shim::{address::Address, econ::TokenAmount};
     use chrono::DateTime;
+    use human_repr::HumanCount;
     use quickcheck_macros::quickcheck;
     use std::{str::FromStr, sync::Arc, time::Duration};
 
@@ -243,6 +309,11 @@ mod tests {
             network: "calibnet".to_string(),
             default_wallet_address: Some("-".to_string()),
             default_wallet_address_balance: None,
+            version: "0.0.0".to_string(),
+            behind: 0,
+            peers: 0,
+            mpool_size: 0,
+            disk_usage: None,
         }
     }
 
@@ -313,6 +384,21 @@ mod tests {
             .contains("6m ahead"));
     }
 
+    #[test]
+    fn node_resources_are_shown() {
+        let mut status = mock_node_status();
+        status.behind = 3;
+        status.peers = 42;
+        status.mpool_size = 7;
+        status.disk_usage = Some(2048);
+        let fmt = status.format(DateTime::<chrono::Utc>::MIN_UTC);
+        assert!(fmt.contains("Version: 0.0.0"));
+        assert!(fmt.contains("Sync lag: 3 epochs"));
+        assert!(fmt.contains("Peers: 42"));
+        assert!(fmt.contains("Message pool: 7 pending messages"));
+        assert!(fmt.contains(&format!("Disk usage: {}", 2048u64.human_count_bytes())));
+    }
+
     #[test]
     fn chain_status_test() {
         let duration = Duration::from_secs(100_000);
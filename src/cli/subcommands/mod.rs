@@ -9,10 +9,12 @@
 mod archive_cmd;
 mod attach_cmd;
 mod auth_cmd;
+mod backup_cmd;
 mod car_cmd;
 mod chain_cmd;
 mod config_cmd;
 mod db_cmd;
+mod doctor_cmd;
 mod fetch_params_cmd;
 mod info_cmd;
 mod mpool_cmd;
@@ -39,7 +41,8 @@ use tracing::error;
 
 pub(super) use self::{
     archive_cmd::ArchiveCommands, attach_cmd::AttachCommand, auth_cmd::AuthCommands,
-    car_cmd::CarCommands, chain_cmd::ChainCommands, config_cmd::ConfigCommands, db_cmd::DBCommands,
+    backup_cmd::BackupCommands, car_cmd::CarCommands, chain_cmd::ChainCommands,
+    config_cmd::ConfigCommands, db_cmd::DBCommands, doctor_cmd::DoctorCommand,
     fetch_params_cmd::FetchCommands, mpool_cmd::MpoolCommands, net_cmd::NetCommands,
     send_cmd::SendCommand, shutdown_cmd::ShutdownCommand, snapshot_cmd::SnapshotCommands,
     state_cmd::StateCommands, sync_cmd::SyncCommands, wallet_cmd::WalletCommands,
@@ -104,6 +107,10 @@ pub enum Subcommand {
     #[command(subcommand)]
     Archive(ArchiveCommands),
 
+    /// Back up and restore the keystore and configuration of a node
+    #[command(subcommand)]
+    Backup(BackupCommands),
+
     /// Send funds between accounts
     Send(SendCommand),
 
@@ -124,6 +131,9 @@ pub enum Subcommand {
     /// Utilities for manipulating CAR files
     #[command(subcommand)]
     Car(CarCommands),
+
+    /// Check the environment of the node and suggest fixes
+    Doctor(DoctorCommand),
 }
 
 /// Pretty-print a JSON-RPC error and exit
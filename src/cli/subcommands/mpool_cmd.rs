@@ -1,19 +1,35 @@
 // Copyright 2019-2023 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use crate::blocks::Tipset;
+use crate::db::MemoryDB;
 use crate::json::cid::vec::CidJsonVec;
 use crate::lotus_json::LotusJson;
 use crate::message::SignedMessage;
-use crate::rpc_client::{chain_ops::*, mpool_pending, state_ops::*, wallet_ops::*};
+use crate::rpc_client::{
+    chain_ops::*, mpool_export, mpool_import, mpool_pending, mpool_push, mpool_resubmit_log,
+    mpool_resubmit_pause, mpool_resubmit_resume, mpool_resubmit_status, state_ops::*,
+    wallet_ops::*,
+};
 use crate::shim::address::StrictAddress;
 use crate::shim::message::Message;
 use crate::shim::{address::Address, econ::TokenAmount};
+use crate::utils::db::car_stream::{Block, CarHeader};
+use crate::utils::io::read_file_to_string;
 
 use ahash::{HashMap, HashSet};
+use anyhow::Context as _;
+use cid::{
+    multihash::{Code::Blake2b256, MultihashDigest},
+    Cid,
+};
 use clap::Subcommand;
+use fvm_ipld_car::load_car;
+use fvm_ipld_encoding::{CborStore, DAG_CBOR};
+use integer_encoding::VarInt;
 use num::BigInt;
 use std::sync::Arc;
 
@@ -45,6 +61,82 @@ pub enum MpoolCommands {
         #[arg(long)]
         local: bool,
     },
+    /// Add a signed message to the message pool and print its CID
+    Push {
+        /// Path to a JSON encoded signed message, e.g. the output of
+        /// `wallet sign --file`
+        #[arg(long)]
+        signed: PathBuf,
+    },
+    /// Write the messages of the message pool to a bundle, e.g. to move them
+    /// to another node with `mpool import`
+    Export {
+        /// Path of the bundle: a CAR file if it ends with `.car`, a JSON array
+        /// of signed messages otherwise
+        output: PathBuf,
+        /// Export messages from addresses in local wallet only
+        #[arg(long)]
+        local: bool,
+    },
+    /// Add the signed messages of a bundle written by `mpool export` to the
+    /// message pool
+    Import {
+        /// Path of the bundle, a CAR file if it ends with `.car`, JSON otherwise
+        input: PathBuf,
+    },
+    /// Print the policy of the automatic resubmission of the underpriced local
+    /// messages, and the messages it tracks
+    ResubmitStatus,
+    /// Pause the automatic resubmission, tracked messages are left as they are
+    ResubmitPause,
+    /// Resume the automatic resubmission
+    ResubmitResume,
+    /// Print the audit log of the automatic resubmission
+    ResubmitLog {
+        /// Number of most recent records to print
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+}
+
+fn is_car(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "car")
+}
+
+/// Encodes messages in a CAR file whose roots are the messages, in order.
+fn encode_car_bundle(messages: &[SignedMessage]) -> anyhow::Result<Vec<u8>> {
+    let blocks = messages
+        .iter()
+        .map(|msg| {
+            let data = fvm_ipld_encoding::to_vec(msg)?;
+            Ok(Block {
+                cid: Cid::new_v1(DAG_CBOR, Blake2b256.digest(&data)),
+                data,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let header = fvm_ipld_encoding::to_vec(&CarHeader {
+        roots: blocks.iter().map(|block| block.cid).collect(),
+        version: 1,
+    })?;
+    let mut car = header.len().encode_var_vec();
+    car.extend(header);
+    for block in &blocks {
+        block.write(&mut car)?;
+    }
+    Ok(car)
+}
+
+async fn decode_car_bundle(car: &[u8]) -> anyhow::Result<Vec<SignedMessage>> {
+    let db = MemoryDB::default();
+    let roots = load_car(&db, car).await?;
+    roots
+        .iter()
+        .map(|root| {
+            db.get_cbor(root)?
+                .with_context(|| format!("message {root} is missing from the bundle"))
+        })
+        .collect()
 }
 
 fn to_addr(value: &Option<String>) -> anyhow::Result<Option<StrictAddress>> {
@@ -87,7 +179,10 @@ async fn get_actor_sequence(
 ) -> Option<u64> {
     let address = message.from;
     let get_actor_result = state_get_actor(
-        (address.to_owned().into(), tipset.key().to_owned().into()),
+        (
+            address.to_owned().into(),
+            LotusJson(tipset.key().to_owned().into()),
+        ),
         &config.client.rpc_token,
     )
     .await;
@@ -306,6 +401,140 @@ impl MpoolCommands {
 
                 Ok(())
             }
+            Self::Push { signed } => {
+                let LotusJson(signed_message): LotusJson<SignedMessage> =
+                    serde_json::from_str(&read_file_to_string(&signed)?)
+                        .context("Invalid signed message file")?;
+                let cid = mpool_push((LotusJson(signed_message),), &config.client.rpc_token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                println!("{}", cid.0);
+                Ok(())
+            }
+            Self::Export { output, local } => {
+                let LotusJson(messages) = mpool_export((), &config.client.rpc_token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                let local_addrs = if local {
+                    let response = wallet_list((), &config.client.rpc_token)
+                        .await
+                        .map_err(handle_rpc_err)?;
+                    Some(HashSet::from_iter(response.iter().map(|addr| addr.0)))
+                } else {
+                    None
+                };
+                let messages = filter_messages(messages, local_addrs, &None, &None)?;
+                let count = messages.len();
+                let bundle = if is_car(&output) {
+                    encode_car_bundle(&messages)?
+                } else {
+                    serde_json::to_vec_pretty(&LotusJson(messages))?
+                };
+                std::fs::write(&output, bundle)
+                    .with_context(|| format!("Failed to write {}", output.display()))?;
+                println!("Exported {count} messages to {}", output.display());
+                Ok(())
+            }
+            Self::Import { input } => {
+                let bundle = std::fs::read(&input)
+                    .with_context(|| format!("Failed to read {}", input.display()))?;
+                let messages = if is_car(&input) {
+                    decode_car_bundle(&bundle).await?
+                } else {
+                    serde_json::from_slice::<LotusJson<_>>(&bundle)
+                        .context("Invalid message bundle")?
+                        .into_inner()
+                };
+                let summary = mpool_import((LotusJson(messages),), &config.client.rpc_token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                for rejection in &summary.rejected {
+                    println!("Rejected {}: {}", rejection.cid, rejection.error);
+                }
+                println!(
+                    "Imported {} messages, rejected {}",
+                    summary.imported.len(),
+                    summary.rejected.len()
+                );
+                Ok(())
+            }
+            Self::ResubmitStatus => {
+                let status = mpool_resubmit_status(&config.client.rpc_token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                let state = match (status.enabled, status.paused) {
+                    (false, _) => "disabled",
+                    (true, true) => "paused",
+                    (true, false) => "running",
+                };
+                println!("Automatic resubmission: {state}");
+                println!("Max fee cap: {}", status.max_fee_cap);
+                println!(
+                    "Bump schedule: {}",
+                    status
+                        .bump_schedule
+                        .iter()
+                        .map(|bump| format!("{bump}%"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+                println!("Abandon after: {} epochs", status.abandon_after);
+                for tracked in &status.tracked {
+                    println!(
+                        "{} nonce {}: {}, first seen at epoch {}, {} replacements{}",
+                        tracked.from,
+                        tracked.nonce,
+                        tracked.message,
+                        tracked.first_epoch,
+                        tracked.replacements,
+                        if tracked.abandoned { ", abandoned" } else { "" }
+                    );
+                }
+                Ok(())
+            }
+            Self::ResubmitPause => {
+                match mpool_resubmit_pause(&config.client.rpc_token)
+                    .await
+                    .map_err(handle_rpc_err)?
+                {
+                    true => println!("Automatic resubmission paused"),
+                    false => println!("Automatic resubmission is already paused"),
+                }
+                Ok(())
+            }
+            Self::ResubmitResume => {
+                match mpool_resubmit_resume(&config.client.rpc_token)
+                    .await
+                    .map_err(handle_rpc_err)?
+                {
+                    true => println!("Automatic resubmission resumed"),
+                    false => println!("Automatic resubmission is already running"),
+                }
+                Ok(())
+            }
+            Self::ResubmitLog { limit } => {
+                let log = mpool_resubmit_log((limit,), &config.client.rpc_token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                for record in log {
+                    let message = match (record.from, record.nonce) {
+                        (Some(from), Some(nonce)) => format!(" {from} nonce {nonce}"),
+                        _ => String::new(),
+                    };
+                    let replacement = record
+                        .replacement
+                        .map(|cid| format!(" by {cid}"))
+                        .unwrap_or_default();
+                    println!(
+                        "{} epoch {}: {:?}{message}{replacement} {}",
+                        record.time.to_rfc3339(),
+                        record.epoch,
+                        record.action,
+                        record.detail
+                    );
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -528,4 +757,20 @@ mod tests {
 
         assert_eq!(stats, expected);
     }
+
+    #[tokio::test]
+    async fn car_bundle_round_trip() {
+        let keystore = KeyStore::new(KeyStoreConfig::Memory).unwrap();
+        let mut wallet = Wallet::new(keystore);
+        let sender = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+        let target = wallet.generate_addr(SignatureType::Bls).unwrap();
+
+        let messages: Vec<_> = (0..3)
+            .map(|i| create_smsg(&target, &sender, wallet.borrow_mut(), i, 1000000, 1))
+            .collect();
+        let car = encode_car_bundle(&messages).unwrap();
+        assert_eq!(decode_car_bundle(&car).await.unwrap(), messages);
+        assert!(is_car(Path::new("mpool.car")));
+        assert!(!is_car(Path::new("mpool.json")));
+    }
 }
@@ -3,10 +3,12 @@
 
 use crate::libp2p::{Multiaddr, Protocol};
 use crate::rpc_api::data_types::AddrInfo;
+use crate::rpc_api::net_api::{RequestOutcomes, RequestProtocol};
 use crate::rpc_client::net_ops::*;
 use ahash::HashSet;
 use cid::multibase;
 use clap::Subcommand;
+use human_repr::HumanCount;
 use itertools::Itertools;
 
 use super::{handle_rpc_err, print_stdout, Config};
@@ -19,7 +21,24 @@ pub enum NetCommands {
     /// Lists `libp2p` swarm network info
     Info,
     /// Lists `libp2p` swarm peers
-    Peers,
+    Peers {
+        /// Shows the direction, transport, client, latency and received
+        /// gossip of each connection, and the score of the peer
+        #[arg(long)]
+        details: bool,
+        /// Column the detailed listing is sorted by
+        #[arg(long, value_enum)]
+        sort: Option<PeerSort>,
+        /// Only lists the peers connected in this direction
+        #[arg(long, value_enum)]
+        direction: Option<PeerDirection>,
+        /// Only lists the peers whose client version contains this string
+        #[arg(long)]
+        agent: Option<String>,
+        /// Prints the detailed listing in JSON
+        #[arg(long)]
+        json: bool,
+    },
     /// Connects to a peer by its peer ID and multi-addresses
     Connect {
         /// Multi-address (with `/p2p/` protocol)
@@ -30,6 +49,86 @@ pub enum NetCommands {
         /// Peer ID to disconnect from
         id: String,
     },
+    /// Lists the heads advertised by peers and the estimated network head
+    Heads,
+    /// Shows statistics of the gossip topics
+    PubsubStats {
+        /// Also print the recently received messages. Messages are only
+        /// sampled when `network.gossip_samples` is set in the node
+        /// configuration.
+        #[arg(long)]
+        samples: bool,
+    },
+    /// Shows the client versions of the peers
+    Agents,
+    /// Shows the outcomes of the requests sent to each peer, by protocol,
+    /// peers failing the most requests first
+    ProtocolStats {
+        /// Number of days to aggregate, including today
+        #[arg(long, default_value_t = 7)]
+        days: u64,
+        /// Only shows the requests of this protocol
+        #[arg(long, value_enum)]
+        protocol: Option<StatsProtocol>,
+        /// Only shows the peers sent at least this many requests
+        #[arg(long, default_value_t = 1)]
+        min_requests: u64,
+        /// Prints the statistics in JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum StatsProtocol {
+    ChainExchange,
+    Bitswap,
+}
+
+impl From<StatsProtocol> for RequestProtocol {
+    fn from(protocol: StatsProtocol) -> Self {
+        match protocol {
+            StatsProtocol::ChainExchange => RequestProtocol::ChainExchange,
+            StatsProtocol::Bitswap => RequestProtocol::Bitswap,
+        }
+    }
+}
+
+/// Outcomes of the requests of a protocol sent to a peer, over several days.
+#[derive(Debug, serde::Serialize)]
+struct PeerProtocolStats {
+    peer: String,
+    protocol: RequestProtocol,
+    #[serde(flatten)]
+    outcomes: RequestOutcomes,
+    /// Number of days the peer was sent requests.
+    days: u64,
+}
+
+impl PeerProtocolStats {
+    fn failure_rate(&self) -> f64 {
+        (self.outcomes.timeout + self.outcomes.invalid) as f64 / self.outcomes.total() as f64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PeerSort {
+    /// Lowest latency first
+    Latency,
+    /// Highest score first
+    Score,
+    /// Oldest connection first
+    Connected,
+    /// Most received gossip first
+    Gossip,
+    /// By client version
+    Agent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PeerDirection {
+    Inbound,
+    Outbound,
 }
 
 impl NetCommands {
@@ -60,7 +159,79 @@ impl NetCommands {
                 println!("num established: {}", info.num_established);
                 Ok(())
             }
-            Self::Peers => {
+            Self::Peers {
+                details,
+                sort,
+                direction,
+                agent,
+                json,
+            } if *details || sort.is_some() || direction.is_some() || agent.is_some() || *json => {
+                let mut peers = net_peer_details((), &config.client.rpc_token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                if let Some(direction) = direction {
+                    let direction = match direction {
+                        PeerDirection::Inbound => "inbound",
+                        PeerDirection::Outbound => "outbound",
+                    };
+                    peers.retain(|peer| peer.direction == direction);
+                }
+                if let Some(agent) = agent {
+                    peers.retain(|peer| {
+                        peer.agent
+                            .as_deref()
+                            .is_some_and(|peer_agent| peer_agent.contains(agent.as_str()))
+                    });
+                }
+                match sort {
+                    Some(PeerSort::Latency) => {
+                        peers.sort_by_key(|peer| (peer.latency_ms.is_none(), peer.latency_ms))
+                    }
+                    Some(PeerSort::Score) => {
+                        peers.sort_by_key(|peer| std::cmp::Reverse(peer.score))
+                    }
+                    Some(PeerSort::Connected) => peers.sort_by_key(|peer| peer.connected_since),
+                    Some(PeerSort::Gossip) => {
+                        peers.sort_by_key(|peer| std::cmp::Reverse(peer.gossip_bytes_received))
+                    }
+                    Some(PeerSort::Agent) => peers.sort_by(|a, b| a.agent.cmp(&b.agent)),
+                    None => peers.sort_by(|a, b| a.peer.cmp(&b.peer)),
+                }
+                if *json {
+                    print_stdout(serde_json::to_string_pretty(&peers)?);
+                    return Ok(());
+                }
+                println!(
+                    "{:<52} {:<8} {:<9} {:>8} {:>10} {:>6} {:<20} {:<30} PROTOCOLS",
+                    "PEER",
+                    "DIR",
+                    "TRANSPORT",
+                    "LATENCY",
+                    "GOSSIP IN",
+                    "SCORE",
+                    "CONNECTED",
+                    "AGENT"
+                );
+                for peer in peers {
+                    let latency = peer
+                        .latency_ms
+                        .map_or_else(|| "-".into(), |latency| format!("{latency}ms"));
+                    println!(
+                        "{:<52} {:<8} {:<9} {:>8} {:>10} {:>6} {:<20} {:<30} {}",
+                        peer.peer,
+                        peer.direction,
+                        peer.transport,
+                        latency,
+                        peer.gossip_bytes_received.human_count_bytes(),
+                        peer.score,
+                        peer.connected_since.format("%Y-%m-%d %H:%M:%S"),
+                        peer.agent.as_deref().unwrap_or("-"),
+                        peer.protocols.join(","),
+                    );
+                }
+                Ok(())
+            }
+            Self::Peers { .. } => {
                 let addrs = net_peers((), &config.client.rpc_token)
                     .await
                     .map_err(handle_rpc_err)?;
@@ -127,6 +298,138 @@ impl NetCommands {
                 println!("disconnect {id}: success");
                 Ok(())
             }
+            Self::Heads => {
+                let heads = net_peer_heads((), &config.client.rpc_token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                for head in &heads.peers {
+                    println!(
+                        "{}: epoch {}, weight {}, {} (updated {})",
+                        head.peer, head.epoch, head.weight, head.tipset_keys, head.updated
+                    );
+                }
+                match heads.network_head {
+                    Some(head) => println!(
+                        "network head: epoch {}, {} ({} of {} peers)",
+                        head.epoch, head.tipset_keys, head.peers, head.total_peers
+                    ),
+                    None => println!("network head: unknown"),
+                }
+                if heads.minority_fork {
+                    println!("warning: the local chain is on a minority fork");
+                }
+                Ok(())
+            }
+            Self::PubsubStats { samples } => {
+                let stats = net_pubsub_stats((), &config.client.rpc_token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                for topic in stats {
+                    println!("{}:", topic.topic);
+                    println!("  mesh peers: {}", topic.mesh_peers);
+                    println!("  received: {}", topic.received);
+                    println!("  received last minute: {}", topic.received_last_minute);
+                    println!("  published: {}", topic.published);
+                    for (reason, count) in &topic.rejected {
+                        println!("  rejected ({reason}): {count}");
+                    }
+                    if *samples {
+                        for sample in &topic.samples {
+                            println!(
+                                "  {} from {} ({} bytes): {}",
+                                sample.received, sample.source, sample.size, sample.message
+                            );
+                        }
+                    }
+                }
+                Ok(())
+            }
+            Self::Agents => {
+                let agents = net_agent_versions((), &config.client.rpc_token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                for agent in agents {
+                    let version = agent.version.as_deref().unwrap_or("unknown version");
+                    print!("{:>5} {} {version}", agent.peers, agent.client);
+                    if agent.deprioritized > 0 {
+                        print!(" ({} deprioritized)", agent.deprioritized);
+                    }
+                    println!();
+                }
+                Ok(())
+            }
+            Self::ProtocolStats {
+                days,
+                protocol,
+                min_requests,
+                json,
+            } => {
+                let stats = net_protocol_stats((), &config.client.rpc_token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                let since =
+                    chrono::Utc::now().date_naive() - chrono::Days::new(days.saturating_sub(1));
+                let protocol = protocol.map(RequestProtocol::from);
+                let mut peers: Vec<PeerProtocolStats> = vec![];
+                for day in stats {
+                    if day.day < since || protocol.is_some_and(|protocol| protocol != day.protocol)
+                    {
+                        continue;
+                    }
+                    match peers
+                        .iter_mut()
+                        .find(|peer| peer.peer == day.peer && peer.protocol == day.protocol)
+                    {
+                        Some(peer) => {
+                            peer.outcomes.add(&day.outcomes);
+                            peer.days += 1;
+                        }
+                        None => peers.push(PeerProtocolStats {
+                            peer: day.peer,
+                            protocol: day.protocol,
+                            outcomes: day.outcomes,
+                            days: 1,
+                        }),
+                    }
+                }
+                peers.retain(|peer| peer.outcomes.total() >= (*min_requests).max(1));
+                peers.sort_by(|a, b| {
+                    b.failure_rate()
+                        .total_cmp(&a.failure_rate())
+                        .then_with(|| b.outcomes.total().cmp(&a.outcomes.total()))
+                });
+                if *json {
+                    print_stdout(serde_json::to_string_pretty(&peers)?);
+                    return Ok(());
+                }
+                println!(
+                    "{:<52} {:<14} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>4}",
+                    "PEER",
+                    "PROTOCOL",
+                    "REQUESTS",
+                    "SUCCESS",
+                    "TIMEOUT",
+                    "INVALID",
+                    "ERROR",
+                    "FAILURES",
+                    "DAYS"
+                );
+                for peer in peers {
+                    println!(
+                        "{:<52} {:<14} {:>8} {:>8} {:>8} {:>8} {:>8} {:>7.1}% {:>4}",
+                        peer.peer,
+                        peer.protocol,
+                        peer.outcomes.total(),
+                        peer.outcomes.success,
+                        peer.outcomes.timeout,
+                        peer.outcomes.invalid,
+                        peer.outcomes.error,
+                        peer.failure_rate() * 100.0,
+                        peer.days,
+                    );
+                }
+                Ok(())
+            }
         }
     }
 }
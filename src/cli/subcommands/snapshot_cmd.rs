@@ -23,7 +23,7 @@ use futures::TryStreamExt;
 use fvm_ipld_blockstore::Blockstore;
 use human_repr::HumanCount;
 use indicatif::{ProgressBar, ProgressStyle};
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use std::sync::Arc;
 use tempfile::NamedTempFile;
 use tokio::fs::File;
@@ -42,12 +42,29 @@ pub enum SnapshotCommands {
         /// Don't write the archive.
         #[arg(long)]
         dry_run: bool,
+        /// Write blocks in a canonical order so that exports of the same chain
+        /// are byte-identical. Slower than the default order.
+        #[arg(long)]
+        canonical: bool,
         /// Tipset to start the export from, default is the chain head
         #[arg(short, long)]
         tipset: Option<i64>,
         /// How many state-roots to include. Lower limit is 900 for `calibnet` and `mainnet`.
         #[arg(short, long)]
         depth: Option<crate::chain::ChainEpochDelta>,
+        /// Export a diff snapshot, with only the blocks that are not in the
+        /// snapshot exported at this epoch
+        #[arg(long)]
+        diff: Option<i64>,
+        /// How many state-roots the snapshot exported at the `--diff` epoch
+        /// includes. Defaults to the chain finality, the depth of default
+        /// exports.
+        #[arg(long, requires = "diff")]
+        diff_depth: Option<crate::chain::ChainEpochDelta>,
+        /// Print the progress as JSON lines, for other programs, instead of
+        /// the size of the snapshot
+        #[arg(long)]
+        progress_json: bool,
     },
 
     /// Fetches the most recent snapshot from a trusted, pre-defined location.
@@ -103,8 +120,12 @@ impl SnapshotCommands {
                 output_path,
                 skip_checksum,
                 dry_run,
+                canonical,
                 tipset,
                 depth,
+                diff,
+                diff_depth,
+                progress_json,
             } => {
                 let chain_head = match chain_head(&config.client.rpc_token).await {
                     Ok(LotusJson(head)) => head,
@@ -135,9 +156,12 @@ impl SnapshotCommands {
                     epoch,
                     recent_roots: depth.unwrap_or(config.chain.recent_state_roots),
                     output_path: temp_path.to_path_buf(),
-                    tipset_keys: chain_head.key().clone(),
+                    tipset: chain_head.key().clone().into(),
                     skip_checksum,
                     dry_run,
+                    canonical,
+                    diff,
+                    diff_depth,
                 };
 
                 let finality = config.chain.policy.chain_finality.min(epoch);
@@ -148,46 +172,70 @@ impl SnapshotCommands {
                     );
                 }
 
-                let handle = tokio::spawn({
-                    let tmp_file = temp_path.to_owned();
-                    let output_path = output_path.clone();
-                    async move {
-                        let mut interval =
-                            tokio::time::interval(tokio::time::Duration::from_secs_f32(0.25));
-                        println!("Getting ready to export...");
-                        loop {
-                            interval.tick().await;
-                            let snapshot_size = std::fs::metadata(&tmp_file)
-                                .map(|meta| meta.len())
-                                .unwrap_or(0);
-                            print!(
-                                "{}{}",
-                                anes::MoveCursorToPreviousLine(1),
-                                anes::ClearLine::All
-                            );
-                            println!(
-                                "{}: {}",
-                                &output_path.to_string_lossy(),
-                                snapshot_size.human_count_bytes()
-                            );
-                            let _ = std::io::stdout().flush();
+                let handle = if progress_json {
+                    // Skips the progress of the previous export.
+                    let previous = chain_export_progress(&config.client.rpc_token)
+                        .await
+                        .map_err(handle_rpc_err)?
+                        .map(|progress| progress.id);
+                    let auth_token = config.client.rpc_token.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = print_export_progress(auth_token, previous).await {
+                            eprintln!("Failed to follow the export progress: {e}");
                         }
-                    }
-                });
+                    })
+                } else {
+                    tokio::spawn({
+                        let tmp_file = temp_path.to_owned();
+                        let output_path = output_path.clone();
+                        async move {
+                            let mut interval =
+                                tokio::time::interval(tokio::time::Duration::from_secs_f32(0.25));
+                            println!("Getting ready to export...");
+                            loop {
+                                interval.tick().await;
+                                let snapshot_size = std::fs::metadata(&tmp_file)
+                                    .map(|meta| meta.len())
+                                    .unwrap_or(0);
+                                print!(
+                                    "{}{}",
+                                    anes::MoveCursorToPreviousLine(1),
+                                    anes::ClearLine::All
+                                );
+                                println!(
+                                    "{}: {}",
+                                    &output_path.to_string_lossy(),
+                                    snapshot_size.human_count_bytes()
+                                );
+                                let _ = std::io::stdout().flush();
+                            }
+                        }
+                    })
+                };
 
-                let hash_result = chain_export(params, &config.client.rpc_token)
-                    .await
-                    .map_err(handle_rpc_err)?;
+                let hash_result = chain_export(params, &config.client.rpc_token).await;
 
                 handle.abort();
                 let _ = handle.await;
 
+                if progress_json {
+                    if let Some(progress) = chain_export_progress(&config.client.rpc_token)
+                        .await
+                        .map_err(handle_rpc_err)?
+                    {
+                        println!("{}", serde_json::to_string(&progress)?);
+                    }
+                }
+                let hash_result = hash_result.map_err(handle_rpc_err)?;
+
                 if let Some(hash) = hash_result {
-                    save_checksum(&output_path, hash).await?;
+                    snapshot::save_checksum(&output_path, hash).await?;
                 }
                 temp_path.persist(output_path)?;
 
-                println!("Export completed.");
+                if !progress_json {
+                    println!("Export completed.");
+                }
                 Ok(())
             }
             Self::Fetch { directory, vendor } => {
@@ -282,27 +330,6 @@ impl SnapshotCommands {
     }
 }
 
-/// Prints hex-encoded representation of SHA-256 checksum and saves it to a file
-/// with the same name but with a `.sha256sum` extension.
-async fn save_checksum(source: &Path, encoded_hash: String) -> Result<()> {
-    let checksum_file_content = format!(
-        "{encoded_hash} {}\n",
-        source
-            .file_name()
-            .and_then(std::ffi::OsStr::to_str)
-            .context("Failed to retrieve file name while saving checksum")?
-    );
-
-    let checksum_path = PathBuf::from(source).with_extension("sha256sum");
-
-    let mut checksum_file = tokio::fs::File::create(&checksum_path).await?;
-    checksum_file
-        .write_all(checksum_file_content.as_bytes())
-        .await?;
-    checksum_file.flush().await?;
-    Ok(())
-}
-
 // Check the validity of a snapshot by looking at IPLD links, the genesis block,
 // and message output. More checks may be added in the future.
 //
@@ -319,6 +346,19 @@ async fn save_checksum(source: &Path, encoded_hash: String) -> Result<()> {
 //     Identifying genesis block:     ✅ found!
 //     Verifying network identity:    ❌ wrong!
 //   Error: Expected mainnet but found calibnet
+/// Prints the progress of the exports, except the one with ID `previous`, as
+/// JSON lines.
+async fn print_export_progress(auth_token: Option<String>, previous: Option<u64>) -> Result<()> {
+    let progress = chain_export_progress_notify(&auth_token).await?;
+    futures::pin_mut!(progress);
+    while let Some(progress) = progress.try_next().await? {
+        if let Some(progress) = progress.filter(|progress| Some(progress.id) != previous) {
+            println!("{}", serde_json::to_string(&progress)?);
+        }
+    }
+    Ok(())
+}
+
 async fn validate_with_blockstore<BlockstoreT>(
     root: Tipset,
     store: Arc<BlockstoreT>,
@@ -454,6 +494,7 @@ where
     // Set proof parameter data dir and make sure the proofs are available
     crate::utils::proofs_api::paramfetch::set_proofs_parameter_cache_dir_env(
         &Config::default().client.data_dir,
+        None,
     );
 
     ensure_params_downloaded().await?;
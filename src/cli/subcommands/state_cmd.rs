@@ -6,15 +6,26 @@ use std::sync::Arc;
 
 use crate::db::db_engine::db_root;
 use crate::db::db_engine::open_proxy_db;
-use crate::json::cid::CidJson;
-use crate::rpc_client::state_ops::state_fetch_root;
+use crate::json::{address::json::AddressJson, cid::CidJson};
+use crate::lotus_json::LotusJson;
+use crate::rpc_api::tipset_selector::TipsetSelector;
+use crate::rpc_client::state_ops::{
+    state_actor_changes_notify, state_actor_events, state_diff, state_fetch_root, state_get_actor,
+    state_list_actors, state_list_new_actors, state_miner_rewards, state_mismatch_reports,
+    state_read_state, watch_list_history, watch_list_notify,
+};
+use crate::shim::address::Address;
 use crate::shim::clock::ChainEpoch;
 use crate::shim::econ::TokenAmount;
+use crate::state_manager::rewards::MinerReward;
 use crate::statediff::print_state_diff;
+use anyhow::Context as _;
 use cid::Cid;
 use clap::Subcommand;
+use futures::StreamExt;
 use serde_tuple::{self, Deserialize_tuple, Serialize_tuple};
 
+use super::chain_cmd::tipset_by_epoch_or_offset;
 use super::handle_rpc_err;
 use super::Config;
 
@@ -46,6 +57,90 @@ pub enum StateCommands {
         #[arg(short, long)]
         depth: Option<u64>,
     },
+    /// Print the actors that differ between the parent states of two tipsets
+    /// as JSON
+    DiffTipsets {
+        /// Epoch of the first tipset, or an offset from the head if negative
+        #[arg(allow_hyphen_values = true)]
+        from: i64,
+        /// Epoch of the second tipset, or an offset from the head if negative
+        #[arg(allow_hyphen_values = true)]
+        to: i64,
+    },
+    /// Print the reports of the state root mismatches found while validating
+    /// the chain, along with the paths of their forensic bundles, as JSON
+    MismatchReports,
+    /// Print the block rewards and penalties of a miner between two epochs.
+    /// Only tipsets executed by this node are included.
+    MinerRewards {
+        miner: Address,
+        /// First epoch
+        from: ChainEpoch,
+        /// Last epoch
+        to: ChainEpoch,
+        /// Print CSV instead of JSON, with amounts in `attoFIL`
+        #[arg(long)]
+        csv: bool,
+    },
+    /// Print the events emitted by an actor between two epochs as JSON.
+    /// Only tipsets executed by this node are included.
+    Events {
+        emitter: Address,
+        /// First epoch
+        from: ChainEpoch,
+        /// Last epoch
+        to: ChainEpoch,
+        /// Only print the events with this hex encoded topic
+        #[arg(long)]
+        topic: Option<String>,
+    },
+    /// Print the actors created between two epochs as JSON. Only tipsets
+    /// executed by this node are included.
+    NewActors {
+        /// First epoch
+        from: ChainEpoch,
+        /// Last epoch
+        to: ChainEpoch,
+        /// Keep printing the actors created and deleted as JSON lines
+        #[arg(long)]
+        follow: bool,
+    },
+    /// Print the balance, nonce and code of an actor as JSON
+    GetActor {
+        address: Address,
+        /// Epoch of the tipset, or an offset from the head if negative.
+        /// Defaults to the head.
+        #[arg(long, allow_hyphen_values = true)]
+        tipset: Option<i64>,
+    },
+    /// Print the addresses of all the actors
+    ListActors {
+        /// Epoch of the tipset, or an offset from the head if negative.
+        /// Defaults to the head.
+        #[arg(long, allow_hyphen_values = true)]
+        tipset: Option<i64>,
+    },
+    /// Print the state of an actor as JSON. Links, HAMTs and AMTs are
+    /// expanded up to a small depth.
+    ReadState {
+        address: Address,
+        /// Epoch of the tipset, or an offset from the head if negative.
+        /// Defaults to the head.
+        #[arg(long, allow_hyphen_values = true)]
+        tipset: Option<i64>,
+        /// Print the state decoded by the builtin actors instead, if the actor
+        /// is a builtin actor
+        #[arg(long)]
+        decoded: bool,
+    },
+    /// Print the recent balance, nonce and state changes of an address of the
+    /// watch-list of the node as JSON
+    WatchHistory {
+        address: Address,
+        /// Keep printing the changes of the address as JSON lines
+        #[arg(long)]
+        follow: bool,
+    },
 }
 
 impl StateCommands {
@@ -70,7 +165,171 @@ impl StateCommands {
                     eprintln!("Failed to print state diff: {err}");
                 }
             }
+            Self::DiffTipsets { from, to } => {
+                let auth_token = &config.client.rpc_token;
+                let from = tipset_by_epoch_or_offset(from, auth_token)
+                    .await
+                    .map_err(handle_rpc_err)?
+                    .into_inner();
+                let to = tipset_by_epoch_or_offset(to, auth_token)
+                    .await
+                    .map_err(handle_rpc_err)?
+                    .into_inner();
+                let diff = state_diff(
+                    (
+                        LotusJson(from.key().clone().into()),
+                        LotusJson(to.key().clone().into()),
+                    ),
+                    auth_token,
+                )
+                .await
+                .map_err(handle_rpc_err)?;
+                println!("{}", serde_json::to_string_pretty(&diff)?);
+            }
+            Self::MismatchReports => {
+                let reports = state_mismatch_reports((), &config.client.rpc_token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                println!("{}", serde_json::to_string_pretty(&reports)?);
+            }
+            Self::MinerRewards {
+                miner,
+                from,
+                to,
+                csv,
+            } => {
+                let rewards =
+                    state_miner_rewards((AddressJson(miner), from, to), &config.client.rpc_token)
+                        .await
+                        .map_err(handle_rpc_err)?;
+                if csv {
+                    println!("{}", MinerReward::CSV_HEADER);
+                    for reward in rewards {
+                        println!("{}", reward.to_csv_row());
+                    }
+                } else {
+                    println!("{}", serde_json::to_string_pretty(&rewards)?);
+                }
+            }
+            Self::Events {
+                emitter,
+                from,
+                to,
+                topic,
+            } => {
+                let topic = topic
+                    .map(|topic| hex::decode(topic.trim_start_matches("0x")))
+                    .transpose()
+                    .context("Topic has to be a hex string")?;
+                let events = state_actor_events(
+                    (AddressJson(emitter), from, to, LotusJson(topic)),
+                    &config.client.rpc_token,
+                )
+                .await
+                .map_err(handle_rpc_err)?;
+                println!("{}", serde_json::to_string_pretty(&events)?);
+            }
+            Self::NewActors { from, to, follow } => {
+                let auth_token = &config.client.rpc_token;
+                let changes = if follow {
+                    Some(state_actor_changes_notify(auth_token).await?)
+                } else {
+                    None
+                };
+                let created = state_list_new_actors((from, to), auth_token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                let Some(changes) = changes else {
+                    println!("{}", serde_json::to_string_pretty(&created)?);
+                    return Ok(());
+                };
+                for change in &created {
+                    println!("{}", serde_json::to_string(change)?);
+                }
+                futures::pin_mut!(changes);
+                while let Some(change) = changes.next().await {
+                    println!("{}", serde_json::to_string(&change?)?);
+                }
+            }
+            Self::GetActor { address, tipset } => {
+                let auth_token = &config.client.rpc_token;
+                let tipset = tipset_selector(tipset, auth_token).await?;
+                let actor = state_get_actor((AddressJson(address), LotusJson(tipset)), auth_token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                anyhow::ensure!(actor.0.is_some(), "actor {address} not found");
+                println!("{}", serde_json::to_string_pretty(&actor)?);
+            }
+            Self::ListActors { tipset } => {
+                let auth_token = &config.client.rpc_token;
+                let tipset = tipset_selector(tipset, auth_token).await?;
+                let LotusJson(actors) = state_list_actors((LotusJson(tipset),), auth_token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                for actor in actors {
+                    println!("{actor}");
+                }
+            }
+            Self::ReadState {
+                address,
+                tipset,
+                decoded,
+            } => {
+                let auth_token = &config.client.rpc_token;
+                let tipset = tipset_selector(tipset, auth_token).await?;
+                let state = state_read_state((AddressJson(address), LotusJson(tipset)), auth_token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                match (decoded, &state.name, &state.decoded) {
+                    (true, Some(name), Some(decoded)) => {
+                        println!("{name} actor");
+                        println!("{decoded}");
+                    }
+                    _ => println!("{}", serde_json::to_string_pretty(&state)?),
+                }
+            }
+            Self::WatchHistory { address, follow } => {
+                let auth_token = &config.client.rpc_token;
+                // Subscribing first ensures that no change is missed
+                // in-between.
+                let changes = if follow {
+                    Some(watch_list_notify(auth_token).await?)
+                } else {
+                    None
+                };
+                let history = watch_list_history((AddressJson(address),), auth_token)
+                    .await
+                    .map_err(handle_rpc_err)?;
+                let Some(changes) = changes else {
+                    println!("{}", serde_json::to_string_pretty(&history)?);
+                    return Ok(());
+                };
+                for event in &history {
+                    println!("{}", serde_json::to_string(event)?);
+                }
+                futures::pin_mut!(changes);
+                while let Some(event) = changes.next().await {
+                    let event = event?;
+                    if event.address == address {
+                        println!("{}", serde_json::to_string(&event)?);
+                    }
+                }
+            }
         }
         Ok(())
     }
 }
+
+/// Selects the tipset at the given epoch or offset, or the head.
+async fn tipset_selector(
+    epoch_or_offset: Option<i64>,
+    auth_token: &Option<String>,
+) -> anyhow::Result<TipsetSelector> {
+    let Some(epoch_or_offset) = epoch_or_offset else {
+        return Ok(TipsetSelector::default());
+    };
+    let tipset = tipset_by_epoch_or_offset(epoch_or_offset, auth_token)
+        .await
+        .map_err(handle_rpc_err)?;
+    Ok(tipset.into_inner().key().clone().into())
+}
@@ -12,6 +12,7 @@ use crate::shim::{
     address::{Protocol, StrictAddress},
     crypto::{Signature, SignatureType},
     econ::TokenAmount,
+    message::Message,
 };
 use crate::utils::io::read_file_to_string;
 use crate::{json::address::json::AddressJson, lotus_json::LotusJson};
@@ -28,7 +29,8 @@ use crate::cli::humantoken::TokenAmountPretty as _;
 pub enum WalletCommands {
     /// Create a new wallet
     New {
-        /// The signature type to use. One of SECP256k1, or BLS
+        /// The signature type to use. One of SECP256k1, BLS, or Delegated for
+        /// an Ethereum (`f410`) account
         #[arg(default_value = "secp256k1")]
         signature_type: String,
     },
@@ -72,13 +74,21 @@ pub enum WalletCommands {
         key: String,
     },
     /// Sign a message
+    ///
+    /// With `--file`, the JSON encoded unsigned message of the file is signed
+    /// and the signed message is printed as JSON. It can be sent from another
+    /// node with `mpool push --signed`.
     Sign {
         /// The hex encoded message to sign
-        #[arg(short)]
-        message: String,
-        /// The address to be used to sign the message
-        #[arg(short)]
-        address: String,
+        #[arg(short, required_unless_present = "file", conflicts_with = "file")]
+        message: Option<String>,
+        /// The address to be used to sign the message. Defaults to the sender
+        /// of the message of `--file`
+        #[arg(short, required_unless_present = "file")]
+        address: Option<String>,
+        /// Path to a JSON encoded unsigned message
+        #[arg(long)]
+        file: Option<PathBuf>,
     },
     /// Verify the signature of a message. Returns true if the signature matches
     /// the message and address
@@ -101,6 +111,7 @@ impl WalletCommands {
             Self::New { signature_type } => {
                 let signature_type = match signature_type.to_lowercase().as_str() {
                     "secp256k1" => SignatureType::Secp256k1,
+                    "delegated" => SignatureType::Delegated,
                     _ => SignatureType::Bls,
                 };
 
@@ -229,10 +240,37 @@ impl WalletCommands {
                     .map_err(handle_rpc_err)?;
                 Ok(())
             }
-            Self::Sign { address, message } => {
-                let StrictAddress(address) = StrictAddress::from_str(address)
-                    .with_context(|| format!("Invalid address: {address}"))?;
+            Self::Sign {
+                address,
+                message,
+                file,
+            } => {
+                let address = address
+                    .as_deref()
+                    .map(|address| {
+                        StrictAddress::from_str(address)
+                            .with_context(|| format!("Invalid address: {address}"))
+                    })
+                    .transpose()?
+                    .map(|StrictAddress(address)| address);
+
+                if let Some(file) = file {
+                    let LotusJson(message): LotusJson<Message> =
+                        serde_json::from_str(&read_file_to_string(file)?)
+                            .context("Invalid message file")?;
+                    let address = address.unwrap_or(message.from);
+                    let signed = wallet_sign_message(
+                        (AddressJson(address), LotusJson(message)),
+                        &config.client.rpc_token,
+                    )
+                    .await
+                    .map_err(handle_rpc_err)?;
+                    println!("{}", serde_json::to_string_pretty(&signed)?);
+                    return Ok(());
+                }
 
+                let address = address.context("An address is required")?;
+                let message = message.as_deref().context("A message is required")?;
                 let message = hex::decode(message).context("Message has to be a hex string")?;
                 let message = BASE64_STANDARD.encode(message);
 
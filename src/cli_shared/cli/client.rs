@@ -7,6 +7,7 @@ use std::{
     str::FromStr,
 };
 
+use super::NodeRole;
 use crate::rpc_client::DEFAULT_PORT;
 use crate::utils::io::ProgressBarVisibility;
 use chrono::Duration;
@@ -41,6 +42,9 @@ impl Default for BufferSize {
 pub struct Client {
     pub data_dir: PathBuf,
     pub genesis_file: Option<String>,
+    /// Deployment profile, see [`NodeRole`]. `enable_rpc = false` can further
+    /// disable the JSON-RPC server of a role that would otherwise run it.
+    pub role: NodeRole,
     pub enable_rpc: bool,
     pub rpc_token: Option<String>,
     /// If this is true, then we do not validate the imported snapshot.
@@ -49,6 +53,9 @@ pub struct Client {
     pub snapshot_height: Option<i64>,
     pub snapshot_head: Option<i64>,
     pub snapshot_path: Option<PathBuf>,
+    /// Diff snapshots imported in order after the snapshot, each on top of
+    /// the head left by the previous one.
+    pub snapshot_diffs: Vec<PathBuf>,
     /// Skips loading import CAR file and assumes it's already been loaded.
     /// Will use the CIDs in the header of the file to index the chain.
     pub skip_load: bool,
@@ -71,6 +78,19 @@ pub struct Client {
     pub token_exp: Duration,
     /// Display progress bars mode. Auto will display if TTY.
     pub show_progress_bars: ProgressBarVisibility,
+    /// Number of recent epochs whose state-roots and messages are kept by the
+    /// database garbage collector. Defaults to the `recent_state_roots` of the
+    /// chain, and can't be lower than the chain finality.
+    pub gc_retention: Option<i64>,
+    /// Number of recent epochs whose message receipts are kept by the database
+    /// garbage collector. Older receipts are pruned, and recomputed from the
+    /// parent state when they are queried, if it is still kept. Defaults to
+    /// the `gc_retention`.
+    pub receipt_retention: Option<i64>,
+    /// Directory of the proof parameter files, which may be shared by several
+    /// nodes. Defaults to `filecoin-proof-parameters` in the data directory.
+    /// The `FIL_PROOFS_PARAMETER_CACHE` environment variable takes precedence.
+    pub proofs_parameter_cache: Option<PathBuf>,
 }
 
 impl Default for Client {
@@ -79,9 +99,11 @@ impl Default for Client {
         Self {
             data_dir: dir.data_dir().to_path_buf(),
             genesis_file: None,
+            role: NodeRole::default(),
             enable_rpc: true,
             rpc_token: None,
             snapshot_path: None,
+            snapshot_diffs: vec![],
             snapshot: false,
             snapshot_height: None,
             snapshot_head: None,
@@ -93,6 +115,9 @@ impl Default for Client {
             rpc_address: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), DEFAULT_PORT),
             token_exp: Duration::seconds(5184000), // 60 Days = 5184000 Seconds
             show_progress_bars: Default::default(),
+            gc_retention: None,
+            receipt_retention: None,
+            proofs_parameter_cache: None,
         }
     }
 }
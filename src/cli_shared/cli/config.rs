@@ -1,14 +1,25 @@
 // Copyright 2019-2023 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+use crate::chain::store::state_cache::StateCacheConfig;
 use crate::chain_sync::SyncConfig;
+use crate::daemon::{
+    post_watchdog::PostWatchdogConfig, snapshot_scheduler::SnapshotSchedulerConfig,
+};
 use crate::db::db_engine::DbConfig;
+use crate::key_management::WalletConfig;
 use crate::libp2p::Libp2pConfig;
+use crate::message_pool::AutoResubmitConfig;
 use crate::networks::ChainConfig;
+use crate::state_manager::{actor_trace::ActorTraceConfig, watch_list::WatchListConfig};
+use crate::utils::io::scheduler::IoSchedulerConfig;
+use crate::utils::monitoring::ResourceGovernorConfig;
+use crate::utils::tls::TlsConfig;
 use serde::{Deserialize, Serialize};
 use std::{path::PathBuf, sync::Arc};
 
 use super::client::Client;
+use super::role::Subsystems;
 
 /// Structure that defines daemon configuration when process is detached
 #[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone)]
@@ -45,12 +56,32 @@ pub struct Config {
     pub sync: SyncConfig,
     pub chain: Arc<ChainConfig>,
     pub daemon: DaemonConfig,
+    pub snapshot_scheduler: SnapshotSchedulerConfig,
+    pub post_watchdog: PostWatchdogConfig,
+    pub watch_list: WatchListConfig,
+    pub auto_resubmit: AutoResubmitConfig,
+    pub actor_trace: ActorTraceConfig,
+    pub state_cache: StateCacheConfig,
+    pub io_scheduler: IoSchedulerConfig,
+    pub resource_governor: ResourceGovernorConfig,
+    pub wallet: WalletConfig,
+    pub tls: TlsConfig,
 }
 
 impl Config {
     pub fn db_config(&self) -> &DbConfig {
         &self.parity_db
     }
+
+    /// Subsystems to start, as selected by the node role and narrowed by the
+    /// individual toggles.
+    pub fn subsystems(&self) -> Subsystems {
+        let subsystems = self.client.role.subsystems();
+        match self.client.enable_rpc {
+            true => subsystems,
+            false => subsystems.without_rpc(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -68,6 +99,16 @@ mod test {
         parity_db: crate::db::parity_db_config::ParityDbConfig,
         network: crate::libp2p::Libp2pConfig,
         sync: crate::chain_sync::SyncConfig,
+        snapshot_scheduler: SnapshotSchedulerConfig,
+        post_watchdog: PostWatchdogConfig,
+        watch_list: WatchListConfig,
+        auto_resubmit: AutoResubmitConfig,
+        actor_trace: ActorTraceConfig,
+        state_cache: StateCacheConfig,
+        io_scheduler: IoSchedulerConfig,
+        resource_governor: ResourceGovernorConfig,
+        wallet: WalletConfig,
+        tls: TlsConfig,
     }
 
     impl From<ConfigPartial> for Config {
@@ -79,6 +120,16 @@ mod test {
                 sync: val.sync,
                 chain: Arc::new(ChainConfig::default()),
                 daemon: DaemonConfig::default(),
+                snapshot_scheduler: val.snapshot_scheduler,
+                post_watchdog: val.post_watchdog,
+                watch_list: val.watch_list,
+                auto_resubmit: val.auto_resubmit,
+                actor_trace: val.actor_trace,
+                state_cache: val.state_cache,
+                io_scheduler: val.io_scheduler,
+                resource_governor: val.resource_governor,
+                wallet: val.wallet,
+                tls: val.tls,
             }
         }
     }
@@ -3,6 +3,7 @@
 
 mod client;
 mod config;
+mod role;
 
 use std::{
     net::SocketAddr,
@@ -20,7 +21,7 @@ use clap::Parser;
 use directories::ProjectDirs;
 use tracing::error;
 
-pub use self::{client::*, config::*};
+pub use self::{client::*, config::*, role::*};
 
 pub static HELP_MESSAGE: &str = "\
 {name} {version}
@@ -49,6 +50,9 @@ pub struct CliOpts {
     /// Allow RPC to be active or not (default: true)
     #[arg(short, long)]
     pub rpc: Option<bool>,
+    /// Deployment profile selecting which subsystems to run (default: full)
+    #[arg(long)]
+    pub role: Option<NodeRole>,
     /// Client JWT token to use for JSON-RPC authentication
     #[arg(short, long)]
     pub token: Option<String>,
@@ -83,6 +87,10 @@ pub struct CliOpts {
     /// Import a chain from a local CAR file or URL
     #[arg(long)]
     pub import_chain: Option<String>,
+    /// Import diff snapshots, in order, after the snapshot or chain. Each diff
+    /// applies on top of the head left by the previous import.
+    #[arg(long)]
+    pub import_diff: Vec<String>,
     /// Skips loading CAR file and uses header to index chain. Assumes a
     /// pre-loaded database
     #[arg(long)]
@@ -103,6 +111,10 @@ pub struct CliOpts {
     /// Choose network chain to sync to
     #[arg(long)]
     pub chain: Option<NetworkChain>,
+    /// Fetch the genesis, network spec and bootstrap peers of a devnet from
+    /// the coordinator at this URL, and join it
+    #[arg(long, conflicts_with_all = ["chain", "genesis"])]
+    pub bootstrap_from: Option<url::Url>,
     /// Daemonize Forest process
     #[arg(long)]
     pub detach: bool,
@@ -144,6 +156,10 @@ pub struct CliOpts {
     /// Check your command-line options and configuration file if one is used
     #[arg(long)]
     pub dry_run: bool,
+    /// Start even if the database or the imported snapshot belong to another
+    /// network than the one selected by the configuration
+    #[arg(long)]
+    pub force: bool,
 }
 
 impl CliOpts {
@@ -159,6 +175,8 @@ impl CliOpts {
             None => Config::default(),
         };
 
+        let receipt_validation = cfg.chain.receipt_validation;
+        let drand_servers = cfg.chain.drand_servers.clone();
         if let Some(chain) = &self.chain {
             // override the chain configuration
             cfg.chain = Arc::new(ChainConfig::from_chain(chain));
@@ -167,7 +185,25 @@ impl CliOpts {
             // network.
             cfg.chain = Arc::new(ChainConfig::from_chain(&cfg.chain.network));
         }
+        // Receipt validation can only be relaxed on devnets.
+        if cfg.chain.network.is_devnet() {
+            cfg.chain = Arc::new(ChainConfig {
+                receipt_validation,
+                ..ChainConfig::from_chain(&cfg.chain.network)
+            });
+        }
+        // The drand servers can be changed on all networks.
+        if !drand_servers.is_empty() {
+            cfg.chain = Arc::new(ChainConfig {
+                drand_servers,
+                receipt_validation: cfg.chain.receipt_validation,
+                ..ChainConfig::from_chain(&cfg.chain.network)
+            });
+        }
 
+        if let Some(role) = self.role {
+            cfg.client.role = role;
+        }
         if let Some(genesis_file) = &self.genesis {
             cfg.client.genesis_file = Some(genesis_file.to_owned());
         }
@@ -198,6 +234,9 @@ impl CliOpts {
             cfg.client.snapshot_path = Some(snapshot_path.into());
             cfg.client.snapshot = false;
         }
+        if !self.import_diff.is_empty() {
+            cfg.client.snapshot_diffs = self.import_diff.iter().map(PathBuf::from).collect();
+        }
         cfg.client.snapshot_height = self.height;
         cfg.client.snapshot_head = self.head.map(|head| head as i64);
         if let Some(skip_load) = self.skip_load {
@@ -290,7 +329,9 @@ fn find_unknown_keys<'a>(
     }
 }
 
-pub fn check_for_unknown_keys(path: &Path, config: &Config) {
+/// Returns a description of each key of the configuration file in `path` that
+/// is not part of `config`, which was loaded from that file.
+pub fn unknown_keys(path: &Path, config: &Config) -> Vec<String> {
     // `config` has been loaded successfully from toml file in `path` so we can
     // always serialize it back to a valid TOML value or get the TOML value from
     // `path`
@@ -302,12 +343,22 @@ pub fn check_for_unknown_keys(path: &Path, config: &Config) {
 
     let mut result = vec![];
     find_unknown_keys(vec![], &value, &config_value, &mut result);
-    for (tables, k) in result.iter() {
-        if tables.is_empty() {
-            error!("Unknown key `{k}` in top-level table");
-        } else {
-            error!("Unknown key `{k}` in [{}]", tables.join("."));
-        }
+    result
+        .iter()
+        .map(|(tables, k)| {
+            if tables.is_empty() {
+                format!("Unknown key `{k}` in top-level table")
+            } else {
+                format!("Unknown key `{k}` in [{}]", tables.join("."))
+            }
+        })
+        .collect()
+}
+
+pub fn check_for_unknown_keys(path: &Path, config: &Config) {
+    let result = unknown_keys(path, config);
+    for message in result.iter() {
+        error!("{message}");
     }
     if !result.is_empty() {
         let path = path.display();
@@ -0,0 +1,82 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use serde::{Deserialize, Serialize};
+
+/// Deployment profile of a node. Each role enables a subset of the daemon's
+/// subsystems, see [`NodeRole::subsystems`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+#[cfg_attr(test, derive(derive_quickcheck_arbitrary::Arbitrary))]
+pub enum NodeRole {
+    /// Follows the chain, serves JSON-RPC and takes part in message gossip.
+    #[default]
+    Full,
+    /// Serves JSON-RPC from the local database without following the network.
+    Rpc,
+    /// Follows and validates the chain, without JSON-RPC or a message pool.
+    Sync,
+    /// Only takes part in peer discovery and answers `ChainExchange` and
+    /// `Bitswap` requests, e.g. a dedicated bootstrap node.
+    Bootstrap,
+}
+
+/// Subsystems that are started by the daemon. Peer-to-peer networking is
+/// always enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subsystems {
+    /// JSON-RPC server. Requires the message pool.
+    pub rpc: bool,
+    /// Message pool, including ingestion of gossiped messages.
+    pub mpool: bool,
+    /// Chain synchronizer and state validation.
+    pub chain_sync: bool,
+}
+
+impl NodeRole {
+    pub fn subsystems(self) -> Subsystems {
+        let (rpc, mpool, chain_sync) = match self {
+            NodeRole::Full => (true, true, true),
+            NodeRole::Rpc => (true, true, false),
+            NodeRole::Sync => (false, false, true),
+            NodeRole::Bootstrap => (false, false, false),
+        };
+        Subsystems {
+            rpc,
+            mpool,
+            chain_sync,
+        }
+    }
+}
+
+impl Subsystems {
+    /// Disables the JSON-RPC server, keeping the other subsystems as they are.
+    pub fn without_rpc(self) -> Self {
+        Subsystems { rpc: false, ..self }
+    }
+
+    /// Whether the node needs a populated chain database (and proof
+    /// parameters) to do its job.
+    pub fn requires_chain(&self) -> bool {
+        self.rpc || self.chain_sync
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck_macros::quickcheck;
+
+    #[quickcheck]
+    fn rpc_requires_mpool(role: NodeRole) {
+        let subsystems = role.subsystems();
+        assert!(!subsystems.rpc || subsystems.mpool);
+    }
+
+    #[test]
+    fn bootstrap_only_networks() {
+        let subsystems = NodeRole::Bootstrap.subsystems();
+        assert!(!subsystems.requires_chain());
+        assert!(!subsystems.mpool);
+    }
+}
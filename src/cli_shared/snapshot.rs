@@ -11,6 +11,7 @@ use std::{
 use crate::networks::NetworkChain;
 use anyhow::{anyhow, bail, Context as _};
 use chrono::NaiveDate;
+use tokio::io::AsyncWriteExt as _;
 use tracing::{info, warn};
 use url::Url;
 
@@ -59,6 +60,41 @@ pub fn filename(
     .to_string()
 }
 
+/// Returns the vendor, chain and height of a snapshot named in the "full"
+/// format, see [`filename`].
+pub fn parse_full_filename(filename: &str) -> Option<(String, String, i64)> {
+    match ParsedFilename::parse_str(filename) {
+        Ok(ParsedFilename::Full {
+            vendor,
+            chain,
+            height,
+            ..
+        }) => Some((vendor.to_owned(), chain.to_owned(), height)),
+        _ => None,
+    }
+}
+
+/// Prints hex-encoded representation of SHA-256 checksum and saves it to a file
+/// with the same name but with a `.sha256sum` extension.
+pub async fn save_checksum(source: &Path, encoded_hash: String) -> anyhow::Result<()> {
+    let checksum_file_content = format!(
+        "{encoded_hash} {}\n",
+        source
+            .file_name()
+            .and_then(std::ffi::OsStr::to_str)
+            .context("Failed to retrieve file name while saving checksum")?
+    );
+
+    let checksum_path = PathBuf::from(source).with_extension("sha256sum");
+
+    let mut checksum_file = tokio::fs::File::create(&checksum_path).await?;
+    checksum_file
+        .write_all(checksum_file_content.as_bytes())
+        .await?;
+    checksum_file.flush().await?;
+    Ok(())
+}
+
 /// Fetch a compressed snapshot with `aria2c`, falling back to our own HTTP client.
 /// Returns the path to the downloaded file, which matches the format in .
 pub async fn fetch(
@@ -0,0 +1,185 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Self-configuration of a node from a devnet coordinator, for ephemeral test
+//! networks.
+//!
+//! With `--bootstrap-from <url>`, the node fetches a JSON document from the
+//! coordinator:
+//!
+//! ```json
+//! {
+//!   "genesis": { "url": "genesis.car", "sha256": "<hex>" },
+//!   "network": { "url": "network.toml", "sha256": "<hex>" },
+//!   "bootstrap_peers": ["/ip4/10.0.0.1/tcp/1347/p2p/12D3KooW..."]
+//! }
+//! ```
+//!
+//! The URLs may be relative to the coordinator endpoint. The network spec has
+//! the format of the `[chain]` section of the configuration file, and the
+//! fields it leaves out keep their devnet defaults. Both artifacts are
+//! verified against their hashes before the node uses them.
+
+use std::sync::Arc;
+
+use crate::cli_shared::cli::Config;
+use crate::networks::{ChainConfig, NetworkChain};
+use crate::utils::net::global_http_client;
+use anyhow::Context as _;
+use hex::ToHex;
+use libp2p::Multiaddr;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::info;
+use url::Url;
+
+/// Document served by the coordinator.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DevnetSpec {
+    pub genesis: Artifact,
+    pub network: Artifact,
+    #[serde(default)]
+    pub bootstrap_peers: Vec<Multiaddr>,
+}
+
+/// File served by the coordinator.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Artifact {
+    pub url: String,
+    /// Hex encoded SHA-256 digest of the file.
+    pub sha256: String,
+}
+
+/// Fetches the spec of the devnet from the coordinator at `url` and returns
+/// `config` set up to join it. The genesis is saved in the data directory.
+pub async fn bootstrap_from(url: &Url, mut config: Config) -> anyhow::Result<Config> {
+    info!("Fetching the devnet spec from {url}");
+    let spec: DevnetSpec = serde_json::from_slice(&fetch(url).await?)
+        .with_context(|| format!("invalid devnet spec at {url}"))?;
+
+    let network = fetch_artifact(url, &spec.network).await?;
+    let mut chain_config = parse_network(&network)?;
+    for peer in spec.bootstrap_peers {
+        if !chain_config.bootstrap_peers.contains(&peer) {
+            chain_config.bootstrap_peers.push(peer);
+        }
+    }
+
+    let genesis = fetch_artifact(url, &spec.genesis).await?;
+    let genesis_dir = config.client.data_dir.join("bootstrap");
+    tokio::fs::create_dir_all(&genesis_dir).await?;
+    let genesis_file = genesis_dir.join(format!("{}.car", chain_config.network));
+    tokio::fs::write(&genesis_file, genesis).await?;
+
+    info!(
+        "Joining devnet {} with {} bootstrap peers",
+        chain_config.network,
+        chain_config.bootstrap_peers.len()
+    );
+    config.client.genesis_file = Some(genesis_file.display().to_string());
+    config.chain = Arc::new(chain_config);
+    Ok(config)
+}
+
+async fn fetch(url: &Url) -> anyhow::Result<Vec<u8>> {
+    let response = global_http_client()
+        .get(url.clone())
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(response.bytes().await?.to_vec())
+}
+
+async fn fetch_artifact(base: &Url, artifact: &Artifact) -> anyhow::Result<Vec<u8>> {
+    let url = base
+        .join(&artifact.url)
+        .with_context(|| format!("invalid URL {}", artifact.url))?;
+    let bytes = fetch(&url).await?;
+    verify_sha256(&bytes, &artifact.sha256).with_context(|| format!("rejected {url}"))?;
+    Ok(bytes)
+}
+
+fn verify_sha256(bytes: &[u8], expected: &str) -> anyhow::Result<()> {
+    let digest: String = Sha256::digest(bytes).encode_hex();
+    anyhow::ensure!(
+        digest.eq_ignore_ascii_case(expected),
+        "expected the SHA-256 digest {expected}, found {digest}"
+    );
+    Ok(())
+}
+
+/// Parses the TOML network spec on top of the devnet defaults.
+fn parse_network(bytes: &[u8]) -> anyhow::Result<ChainConfig> {
+    let spec: serde_json::Map<String, serde_json::Value> =
+        toml::from_str(std::str::from_utf8(bytes)?).context("invalid network spec")?;
+    let network: NetworkChain = serde_json::from_value(
+        spec.get("network")
+            .cloned()
+            .context("the network spec has no network")?,
+    )?;
+    anyhow::ensure!(
+        network.is_devnet(),
+        "can't bootstrap {network}, only devnets are supported"
+    );
+    let serde_json::Value::Object(mut chain_config) =
+        serde_json::to_value(ChainConfig::from_chain(&network))?
+    else {
+        anyhow::bail!("unexpected chain config encoding");
+    };
+    chain_config.extend(spec);
+    Ok(serde_json::from_value(chain_config.into())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_digests() {
+        let digest = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        assert!(verify_sha256(b"hello", digest).is_ok());
+        assert!(verify_sha256(b"hello", &digest.to_uppercase()).is_ok());
+        assert!(verify_sha256(b"hello!", digest).is_err());
+    }
+
+    #[test]
+    fn parse_networks() {
+        let chain_config = parse_network(
+            br#"
+            block_delay_secs = 4
+            network = { type = "devnet", name = "ci-1234" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(chain_config.network, NetworkChain::Devnet("ci-1234".into()));
+        assert_eq!(chain_config.block_delay_secs, 4);
+        assert_eq!(
+            chain_config.height_infos,
+            ChainConfig::devnet().height_infos
+        );
+
+        assert!(parse_network(br#"network = { type = "mainnet" }"#).is_err());
+        assert!(parse_network(b"block_delay_secs = 4").is_err());
+    }
+
+    #[test]
+    fn parse_specs() {
+        let spec: DevnetSpec = serde_json::from_str(
+            r#"{
+                "genesis": { "url": "genesis.car", "sha256": "00" },
+                "network": { "url": "https://example.com/network.toml", "sha256": "11" }
+            }"#,
+        )
+        .unwrap();
+        assert!(spec.bootstrap_peers.is_empty());
+        let base = Url::parse("http://coordinator:8080/devnet/spec.json").unwrap();
+        assert_eq!(
+            base.join(&spec.genesis.url).unwrap().as_str(),
+            "http://coordinator:8080/devnet/genesis.car"
+        );
+        assert_eq!(
+            base.join(&spec.network.url).unwrap().as_str(),
+            "https://example.com/network.toml"
+        );
+    }
+}
@@ -1,13 +1,20 @@
 // Copyright 2019-2023 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+pub mod bootstrap;
 pub mod bundle;
 pub mod main;
+pub mod network_identity;
+pub mod post_watchdog;
+pub mod snapshot_scheduler;
 
 use crate::auth::{create_token, generate_priv_key, ADMIN, JWT_IDENTIFIER};
 use crate::blocks::Tipset;
-use crate::chain::ChainStore;
-use crate::chain_sync::ChainMuxer;
+use crate::chain::{
+    finality::{self, RpcCertificateSource},
+    ChainStore,
+};
+use crate::chain_sync::{watch_ntp, BadBlockCache, ChainMuxer};
 use crate::cli_shared::{
     chain_path,
     cli::{CliOpts, Config},
@@ -16,14 +23,17 @@ use crate::cli_shared::{
 use crate::db::car::ManyCar;
 use crate::db::{
     db_engine::{db_root, open_proxy_db},
-    rolling::DbGarbageCollector,
+    rolling::{DbGarbageCollector, RollingDB},
+};
+use crate::genesis::{
+    get_network_name_from_genesis, import_chain, import_chain_diff, read_genesis_header,
 };
-use crate::genesis::{get_network_name_from_genesis, import_chain, read_genesis_header};
 use crate::key_management::{
-    KeyStore, KeyStoreConfig, ENCRYPTED_KEYSTORE_NAME, FOREST_KEYSTORE_PHRASE_ENV,
+    KeyStore, KeyStoreConfig, RemoteSigners, ENCRYPTED_KEYSTORE_NAME, FOREST_KEYSTORE_PHRASE_ENV,
 };
 use crate::libp2p::{Libp2pConfig, Libp2pService, PeerId, PeerManager};
-use crate::message_pool::{MessagePool, MpoolConfig, MpoolRpcProvider};
+use crate::message_pool::{AutoResubmit, MessagePool, MpoolConfig, MpoolRpcProvider};
+use crate::networks::actor_bundle::ensure_actor_bundles;
 use crate::rpc::start_rpc;
 use crate::rpc_api::data_types::RPCState;
 use crate::shim::{
@@ -31,16 +41,27 @@ use crate::shim::{
     clock::ChainEpoch,
     version::NetworkVersion,
 };
-use crate::state_manager::StateManager;
+use crate::state_manager::{
+    actor_trace::ActorTracer, audit_log::AuditLog, watch_list::WatchList, StateManager,
+};
 use crate::utils::{
-    monitoring::MemStatsTracker, proofs_api::paramfetch::ensure_params_downloaded, retry,
-    version::FOREST_VERSION_STRING, RetryArgs,
+    io::scheduler::IO_SCHEDULER,
+    monitoring::{MemStatsTracker, RESOURCE_GOVERNOR},
+    proofs_api::paramfetch::ensure_params_downloaded,
+    retry,
+    tls::TlsServer,
+    version::FOREST_VERSION_STRING,
+    RetryArgs,
 };
 use anyhow::{bail, Context};
 use bundle::load_actor_bundles;
 use dialoguer::{console::Term, theme::ColorfulTheme};
 use futures::{select, Future, FutureExt};
 use lazy_static::lazy_static;
+use network_identity::{
+    check_database_identity, check_snapshot_identity, imported_identity, record_identity,
+    snapshot_identity, NetworkIdentity,
+};
 use raw_sync::events::{Event, EventInit as _, EventState};
 use shared_memory::ShmemConf;
 use std::{
@@ -57,11 +78,14 @@ use tokio::{
         ctrl_c,
         unix::{signal, SignalKind},
     },
-    sync::{mpsc, RwLock},
+    sync::{mpsc, oneshot, RwLock},
     task::JoinSet,
 };
 use tracing::{debug, info, warn};
 
+/// Block store type backing a running daemon.
+pub type DaemonDb = ManyCar<Arc<RollingDB>>;
+
 lazy_static! {
     static ref IPC_PATH: TempPath = Builder::new()
         .prefix("forest-ipc")
@@ -118,7 +142,7 @@ pub async fn start_interruptable(opts: CliOpts, config: Config) -> anyhow::Resul
     let (shutdown_send, mut shutdown_recv) = mpsc::channel(1);
 
     let result = tokio::select! {
-        ret = start(opts, config, shutdown_send) => ret,
+        ret = start(opts, config, shutdown_send, None) => ret,
         _ = ctrl_c() => {
             info!("Keyboard interrupt.");
             Ok(())
@@ -136,12 +160,19 @@ pub async fn start_interruptable(opts: CliOpts, config: Config) -> anyhow::Resul
     result
 }
 
-/// Starts daemon process
-pub(super) async fn start(
+/// Starts daemon process. If `on_ready` is provided, it receives the
+/// [`StateManager`] once the chain has been imported and the node is about to
+/// join the network.
+pub(crate) async fn start(
     opts: CliOpts,
     config: Config,
     shutdown_send: mpsc::Sender<()>,
+    on_ready: Option<oneshot::Sender<Arc<StateManager<DaemonDb>>>>,
 ) -> anyhow::Result<()> {
+    let config = match &opts.bootstrap_from {
+        Some(url) => bootstrap::bootstrap_from(url, config).await?,
+        None => config,
+    };
     if config.chain.is_testnet() {
         CurrentNetwork::set_global(Network::Testnet);
     }
@@ -187,6 +218,12 @@ pub(super) async fn start(
         });
     }
 
+    let tls = TlsServer::new(&config.tls).context("invalid TLS configuration")?;
+    if let Some(tls) = &tls {
+        services.spawn(Arc::clone(tls).reload_on_hangup());
+    }
+    let tls_for = |enabled: bool| tls.clone().filter(|_| enabled);
+
     {
         // Start Prometheus server port
         let prometheus_listener = TcpListener::bind(config.client.metrics_address).context(
@@ -198,8 +235,9 @@ pub(super) async fn start(
         );
         let db_directory = crate::db::db_engine::db_root(&chain_path(&config));
         let db = db.writer().clone();
+        let tls = tls_for(config.tls.metrics);
         services.spawn(async {
-            crate::metrics::init_prometheus(prometheus_listener, db_directory, db)
+            crate::metrics::init_prometheus(prometheus_listener, tls, db_directory, db)
                 .await
                 .context("Failed to initiate prometheus server")
         });
@@ -214,23 +252,53 @@ pub(super) async fn start(
         &db,
     )
     .await?;
+    let network_identity = NetworkIdentity::new(&config.chain.network, &genesis_header);
+    // Recorded once the snapshots are checked as well.
+    let mut identity = check_database_identity(
+        &chain_data_path,
+        &db,
+        db.writer().as_ref(),
+        &network_identity,
+        opts.force,
+    )?;
 
     // Initialize ChainStore
-    let chain_store = Arc::new(ChainStore::new(
-        Arc::clone(&db),
-        db.writer().clone(),
-        config.chain.clone(),
-        genesis_header.clone(),
-    )?);
+    let chain_store = Arc::new(
+        ChainStore::new(
+            Arc::clone(&db),
+            db.writer().clone(),
+            config.chain.clone(),
+            genesis_header.clone(),
+        )?
+        .with_headers_only(config.sync.headers_only),
+    );
+    chain_store
+        .chain_index
+        .state_cache()
+        .set_capacity(config.state_cache.capacity());
+
+    IO_SCHEDULER.configure(config.io_scheduler.clone());
+    RESOURCE_GOVERNOR.configure(config.resource_governor.clone());
+    if config.resource_governor.enabled {
+        services.spawn(RESOURCE_GOVERNOR.run());
+    }
 
     let db_garbage_collector = {
         let db = db.clone();
         let chain_store = chain_store.clone();
         let get_tipset = move || chain_store.heaviest_tipset().as_ref().clone();
+        let chain_finality = config.chain.policy.chain_finality;
+        let gc_retention = config
+            .client
+            .gc_retention
+            .unwrap_or(config.chain.recent_state_roots)
+            .max(chain_finality);
+        let receipt_retention = config.client.receipt_retention.unwrap_or(gc_retention);
         Arc::new(DbGarbageCollector::new(
             db,
-            config.chain.policy.chain_finality,
-            config.chain.recent_state_roots,
+            chain_finality,
+            gc_retention,
+            receipt_retention,
             get_tipset,
         ))
     };
@@ -249,10 +317,23 @@ pub(super) async fn start(
     let publisher = chain_store.publisher();
 
     // Initialize StateManager
-    let sm = StateManager::new(Arc::clone(&chain_store), Arc::clone(&config.chain))?;
+    let mut sm = StateManager::new(Arc::clone(&chain_store), Arc::clone(&config.chain))?
+        .with_forensics_dir(chain_data_path.join("forensics"));
+    if config.sync.audit_log {
+        sm = sm.with_audit_log(AuditLog::open(
+            &chain_data_path.join("audit.log"),
+            net_keypair.clone(),
+        )?);
+    }
 
     let state_manager = Arc::new(sm);
 
+    let actor_tracer = Arc::new(ActorTracer::new(&config.actor_trace)?);
+    if !actor_tracer.is_empty() {
+        actor_tracer.resolve_ids(&state_manager);
+        state_manager.register_execution_callback(actor_tracer.clone());
+    }
+
     let network_name = get_network_name_from_genesis(&genesis_header, &state_manager)?;
 
     info!("Using network :: {}", get_actual_chain_name(&network_name));
@@ -279,16 +360,38 @@ pub(super) async fn start(
     }
 
     let epoch = chain_store.heaviest_tipset().epoch();
+    let subsystems = config.subsystems();
+    info!("Running as {:?} node: {subsystems:?}", config.client.role);
 
-    load_actor_bundles(&db).await?;
+    // The embedded bundles are missing when the assets weren't fetched with
+    // `git lfs`.
+    if let Err(e) = load_actor_bundles(&db).await {
+        warn!("{e:#}");
+    }
+    ensure_actor_bundles(
+        &db,
+        &state_manager.chain_config(),
+        &config.client.data_dir.join("actor_bundles"),
+    )
+    .await?;
 
-    let peer_manager = Arc::new(PeerManager::default());
+    let peer_manager = Arc::new(
+        PeerManager::with_ban_list(db.writer().clone())
+            .with_agent_rules(config.network.agent_rules.clone())
+            .with_protocol_stats(chain_data_path.join("protocol_stats.json"))
+            .with_peer_heads(db.writer().clone()),
+    );
     services.spawn(peer_manager.clone().peer_operation_event_loop_task());
+    // Also runs when the daemon is interrupted, which drops this future.
+    let _save_protocol_stats = scopeguard::guard(peer_manager.clone(), |peer_manager| {
+        peer_manager.save_protocol_stats_now()
+    });
     let genesis_cid = *genesis_header.cid();
     // Libp2p service setup
     let p2p_service = Libp2pService::new(
         config.network.clone(),
         Arc::clone(&chain_store),
+        state_manager.chain_config(),
         peer_manager.clone(),
         net_keypair,
         &network_name,
@@ -299,37 +402,116 @@ pub(super) async fn start(
     let network_send = p2p_service.network_sender();
 
     // Initialize mpool
-    let provider = MpoolRpcProvider::new(publisher.clone(), Arc::clone(&state_manager));
-    let mpool = MessagePool::new(
-        provider,
-        network_name.clone(),
-        network_send.clone(),
-        MpoolConfig::load_config(db.writer().as_ref())?,
-        state_manager.chain_config(),
-        &mut services,
-    )?;
-
-    let mpool = Arc::new(mpool);
+    let mpool = match subsystems.mpool {
+        true => {
+            let provider = MpoolRpcProvider::new(publisher.clone(), Arc::clone(&state_manager));
+            let mpool = MessagePool::new(
+                provider,
+                network_name.clone(),
+                network_send.clone(),
+                MpoolConfig::load_config(db.writer().as_ref())?,
+                state_manager.chain_config(),
+                &mut services,
+            )?;
+            Some(Arc::new(mpool))
+        }
+        false => None,
+    };
 
     // Initialize ChainMuxer
-    let chain_muxer_tipset_sink = tipset_sink.clone();
-    let chain_muxer = ChainMuxer::new(
-        Arc::clone(&state_manager),
-        peer_manager,
-        mpool.clone(),
-        network_send.clone(),
-        network_rx,
-        Arc::new(Tipset::from(genesis_header)),
-        chain_muxer_tipset_sink,
-        tipset_stream,
-        config.sync.clone(),
-    )?;
-    let bad_blocks = chain_muxer.bad_blocks_cloned();
-    let sync_state = chain_muxer.sync_state_cloned();
-    services.spawn(async { Err(anyhow::anyhow!("{}", chain_muxer.await)) });
+    let (bad_blocks, sync_state, clock_skew, block_publisher) = match subsystems.chain_sync {
+        true => {
+            let chain_muxer_tipset_sink = tipset_sink.clone();
+            let chain_muxer = ChainMuxer::new(
+                Arc::clone(&state_manager),
+                peer_manager.clone(),
+                mpool.clone(),
+                network_send.clone(),
+                network_rx,
+                Arc::new(Tipset::from(genesis_header)),
+                chain_muxer_tipset_sink,
+                tipset_stream,
+                config.sync.clone(),
+                network_name.clone(),
+            )?;
+            let bad_blocks = chain_muxer.bad_blocks_cloned();
+            let sync_state = chain_muxer.sync_state_cloned();
+            let clock_skew = chain_muxer.clock_skew_cloned();
+            let block_publisher = chain_muxer.block_publisher();
+            services.spawn(async { Err(anyhow::anyhow!("{}", chain_muxer.await)) });
+            if let Some(ntp_server) = config.sync.ntp_server.clone() {
+                services.spawn(watch_ntp(ntp_server, clock_skew.clone()));
+            }
+            (bad_blocks, sync_state, clock_skew, Some(block_publisher))
+        }
+        false => {
+            // Without a syncer, network events still have to be consumed or
+            // they pile up in the unbounded channel.
+            services.spawn(async move {
+                while network_rx.recv_async().await.is_ok() {}
+                Ok(())
+            });
+            (
+                Arc::new(BadBlockCache::default()),
+                Default::default(),
+                Default::default(),
+                None,
+            )
+        }
+    };
+
+    if config.snapshot_scheduler.interval > 0 {
+        let directory = config
+            .snapshot_scheduler
+            .directory
+            .clone()
+            .unwrap_or_else(|| chain_data_path.join("snapshots"));
+        services.spawn(snapshot_scheduler::run(
+            config.snapshot_scheduler.clone(),
+            directory,
+            Arc::clone(&state_manager),
+        ));
+    }
+
+    if !config.post_watchdog.miners.is_empty() {
+        match &mpool {
+            Some(mpool) => {
+                services.spawn(post_watchdog::run(
+                    config.post_watchdog.clone(),
+                    Arc::clone(&state_manager),
+                    Arc::clone(mpool),
+                ));
+            }
+            None => warn!("The window PoSt watchdog requires the message pool, it is disabled"),
+        }
+    }
+
+    services.spawn(Arc::clone(&state_manager).run_scheduled_tasks());
+    services.spawn(Arc::clone(&chain_store).sample_gas_history());
+
+    let watch_list = Arc::new(WatchList::new(&config.watch_list)?);
+    if !watch_list.is_empty() {
+        services.spawn(Arc::clone(&watch_list).run(Arc::clone(&state_manager)));
+    }
+
+    let remote_signers = Arc::new(RemoteSigners::new(&config.wallet)?);
+    let auto_resubmit = Arc::new(AutoResubmit::new(config.auto_resubmit.clone()));
+    if auto_resubmit.is_enabled() {
+        match &mpool {
+            Some(mpool) => {
+                services.spawn(Arc::clone(&auto_resubmit).run(
+                    Arc::clone(mpool),
+                    Arc::clone(&chain_store),
+                    Arc::clone(&keystore),
+                    Arc::clone(&remote_signers),
+                ));
+            }
+            None => warn!("The automatic resubmission requires the message pool, it is disabled"),
+        }
+    }
 
     // Start services
-    if config.client.enable_rpc {
+    if let (true, Some(mpool)) = (subsystems.rpc, mpool) {
         let keystore_rpc = Arc::clone(&keystore);
         let rpc_listen =
             std::net::TcpListener::bind(config.client.rpc_address).context(format!(
@@ -339,16 +521,22 @@ pub(super) async fn start(
 
         let rpc_state_manager = Arc::clone(&state_manager);
         let rpc_chain_store = Arc::clone(&chain_store);
+        let rpc_tls = tls_for(config.tls.rpc);
 
         let gc_event_tx = db_garbage_collector.get_tx();
+        let (finality_tx, finality_rx) = flume::unbounded();
+        let rpc_config = Arc::new(config.clone());
+        services.spawn(finality::run(
+            RpcCertificateSource::new(finality_rx),
+            Arc::clone(&state_manager),
+        ));
         services.spawn(async move {
-            info!("JSON-RPC endpoint started at {}", config.client.rpc_address);
-            // XXX: The JSON error message are a nightmare to print.
-            let beacon = Arc::new(
-                rpc_state_manager
-                    .chain_config()
-                    .get_beacon_schedule(chain_store.genesis().timestamp()),
+            info!(
+                "JSON-RPC endpoint started at {}",
+                rpc_config.client.rpc_address
             );
+            // XXX: The JSON error message are a nightmare to print.
+            let beacon = rpc_state_manager.beacon_schedule();
             start_rpc(
                 Arc::new(RPCState {
                     state_manager: Arc::clone(&rpc_state_manager),
@@ -356,6 +544,7 @@ pub(super) async fn start(
                     mpool,
                     bad_blocks,
                     sync_state,
+                    clock_skew,
                     network_send,
                     network_name,
                     start_time,
@@ -363,9 +552,18 @@ pub(super) async fn start(
                     beacon,
                     chain_store: rpc_chain_store,
                     new_mined_block_tx: tipset_sink,
+                    block_publisher,
                     gc_event_tx,
+                    finality_tx,
+                    remote_signers,
+                    peer_manager,
+                    watch_list,
+                    actor_tracer,
+                    auto_resubmit,
+                    config: rpc_config,
                 }),
                 rpc_listen,
+                rpc_tls,
                 FOREST_VERSION_STRING.as_str(),
                 shutdown_send,
             )
@@ -383,24 +581,66 @@ pub(super) async fn start(
     // downloaded later right after snapshot import step
     crate::utils::proofs_api::paramfetch::set_proofs_parameter_cache_dir_env(
         &config.client.data_dir,
+        config.client.proofs_parameter_cache.as_deref(),
     );
 
     let mut config = config;
-    fetch_snapshot_if_required(&mut config, epoch, opts.auto_download_snapshot).await?;
+    if subsystems.requires_chain() {
+        fetch_snapshot_if_required(&mut config, epoch, opts.auto_download_snapshot).await?;
+    }
 
     if let Some(path) = &config.client.snapshot_path {
+        let found = snapshot_identity(path)?;
+        if let Some(found) = found.clone() {
+            identity = check_snapshot_identity(found, &network_identity, opts.force)?;
+        }
+        let previous_head = state_manager.chain_store().heaviest_tipset();
         let stopwatch = time::Instant::now();
         import_chain(
             &state_manager,
             &path.display().to_string(),
             config.client.skip_load,
-            config.client.chunk_size,
-            config.client.buffer_size,
+            config.client.chunk_size.clone(),
+            config.client.buffer_size.clone(),
         )
         .await
         .context("Failed miserably while importing chain from snapshot")?;
         info!("Imported snapshot in: {}s", stopwatch.elapsed().as_secs());
+        let imported = match found {
+            Some(_) => None,
+            None => imported_identity(
+                &state_manager.chain_store().heaviest_tipset(),
+                state_manager.blockstore(),
+            ),
+        };
+        if let Some(imported) = imported {
+            match check_snapshot_identity(imported, &network_identity, opts.force) {
+                Ok(imported) => identity = imported,
+                Err(e) => {
+                    state_manager
+                        .chain_store()
+                        .set_heaviest_tipset(previous_head)?;
+                    return Err(e);
+                }
+            }
+        }
     }
+    for path in &config.client.snapshot_diffs {
+        let stopwatch = time::Instant::now();
+        import_chain_diff(
+            &state_manager,
+            &path.display().to_string(),
+            config.client.chunk_size.clone(),
+            config.client.buffer_size.clone(),
+        )
+        .await
+        .context("Failed to import diff snapshot")?;
+        info!(
+            "Imported diff snapshot in: {}s",
+            stopwatch.elapsed().as_secs()
+        );
+    }
+    record_identity(&chain_data_path, &identity)?;
 
     if let (true, Some(validate_from)) = (config.client.snapshot, config.client.snapshot_height) {
         // We've been provided a snapshot and asked to validate it
@@ -427,9 +667,16 @@ pub(super) async fn start(
         return Ok(());
     }
 
-    ensure_params_downloaded().await?;
+    if subsystems.requires_chain() {
+        ensure_params_downloaded().await?;
+    }
     services.spawn(p2p_service.run());
 
+    if let Some(on_ready) = on_ready {
+        // The receiving end is allowed to lose interest
+        let _ = on_ready.send(Arc::clone(&state_manager));
+    }
+
     // blocking until any of the services returns an error,
     propagate_error(&mut services)
         .await
@@ -0,0 +1,282 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Network identity of a chain data directory.
+//!
+//! The network a database belongs to is anchored by its genesis block. The
+//! identity is checked against the genesis selected by the configuration on
+//! every start, and recorded in `meta.yaml` in the chain data directory once
+//! every check passed. Databases created before the identity was recorded are
+//! identified by the genesis of their head. Local snapshots are checked before
+//! they are imported, remote and compressed ones, which are streamed, once
+//! imported. Mismatches stop the daemon, unless it is started with `--force`,
+//! in which case the identity of the data is recorded, so that the mismatch is
+//! reported again on the next start.
+
+use std::path::Path;
+
+use crate::blocks::{BlockHeader, Tipset, TipsetKeys};
+use crate::db::car::{ForestCar, PlainCar};
+use crate::db::{setting_keys::HEAD_KEY, SettingsStore, SettingsStoreExt};
+use crate::networks::{calibnet, mainnet};
+use crate::utils::io::random_access::RandomAccessFile;
+use anyhow::Context as _;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+const META_FILE: &str = "meta.yaml";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkIdentity {
+    /// Name of the network, for error messages.
+    pub network: String,
+    #[serde(with = "crate::lotus_json")]
+    pub genesis: Cid,
+}
+
+impl NetworkIdentity {
+    pub fn new(network: impl ToString, genesis: &BlockHeader) -> Self {
+        Self {
+            network: network.to_string(),
+            genesis: *genesis.cid(),
+        }
+    }
+
+    /// Identity of the network of a genesis block, named after the well-known
+    /// networks.
+    fn of_genesis(genesis: Cid) -> Self {
+        let network = if genesis == *mainnet::GENESIS_CID {
+            "mainnet"
+        } else if genesis == *calibnet::GENESIS_CID {
+            "calibnet"
+        } else {
+            "unknown network"
+        };
+        Self {
+            network: network.into(),
+            genesis,
+        }
+    }
+}
+
+fn read_identity(path: &Path) -> anyhow::Result<Option<NetworkIdentity>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let yaml = std::fs::read_to_string(path)?;
+    Ok(Some(
+        serde_yaml::from_str(&yaml).with_context(|| format!("invalid {}", path.display()))?,
+    ))
+}
+
+fn check(
+    found: &NetworkIdentity,
+    expected: &NetworkIdentity,
+    what: &str,
+    force: bool,
+) -> anyhow::Result<()> {
+    if found.genesis == expected.genesis {
+        return Ok(());
+    }
+    let message = format!(
+        "{what} belongs to {} (genesis {}), but the configuration selects {} (genesis {})",
+        found.network, found.genesis, expected.network, expected.genesis
+    );
+    match force {
+        true => {
+            warn!("{message}. Starting anyway, as requested.");
+            Ok(())
+        }
+        false => anyhow::bail!(
+            "{message}. Check the `--chain` flag, the configuration file and the data directory, \
+             or start with `--force` to ignore this check."
+        ),
+    }
+}
+
+/// Checks that the database in `chain_dir` belongs to the network of
+/// `expected`. Returns the identity to record once every check passed, see
+/// [`record_identity`].
+pub fn check_database_identity(
+    chain_dir: &Path,
+    db: &impl Blockstore,
+    settings: &impl SettingsStore,
+    expected: &NetworkIdentity,
+    force: bool,
+) -> anyhow::Result<NetworkIdentity> {
+    let found = match read_identity(&chain_dir.join(META_FILE))? {
+        Some(identity) => Some(identity),
+        None => head_genesis(db, settings).map(NetworkIdentity::of_genesis),
+    };
+    match found {
+        Some(found) => {
+            check(
+                &found,
+                expected,
+                &format!("The database in {}", chain_dir.display()),
+                force,
+            )?;
+            Ok(identity_of_data(found, expected))
+        }
+        None => Ok(expected.clone()),
+    }
+}
+
+/// Identity of the snapshot at `path`, worked out from its head without
+/// importing it. `None` for remote and compressed snapshots, which can only be
+/// streamed, see [`imported_identity`].
+pub fn snapshot_identity(path: &Path) -> anyhow::Result<Option<NetworkIdentity>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let reader = RandomAccessFile::open(path)?;
+    let head = if ForestCar::is_valid(&reader) {
+        let car = ForestCar::new(reader)?;
+        let head = car.heaviest_tipset()?;
+        head.genesis(&car)
+    } else {
+        match PlainCar::new(reader) {
+            Ok(car) => {
+                let head = car.heaviest_tipset()?;
+                head.genesis(&car)
+            }
+            Err(_) => return Ok(None),
+        }
+    };
+    let genesis = head.with_context(|| {
+        format!(
+            "failed to find the genesis of the snapshot {}",
+            path.display()
+        )
+    })?;
+    Ok(Some(NetworkIdentity::of_genesis(*genesis.cid())))
+}
+
+/// Identity of an imported head, for the snapshots [`snapshot_identity`]
+/// can't work out.
+pub fn imported_identity(head: &Tipset, db: &impl Blockstore) -> Option<NetworkIdentity> {
+    match head.genesis(db) {
+        Ok(genesis) => Some(NetworkIdentity::of_genesis(*genesis.cid())),
+        Err(e) => {
+            warn!("Failed to find the genesis of the imported snapshot: {e}");
+            None
+        }
+    }
+}
+
+/// Checks that a snapshot of identity `found` belongs to the network of
+/// `expected`. Returns the identity to record once every check passed, see
+/// [`record_identity`].
+pub fn check_snapshot_identity(
+    found: NetworkIdentity,
+    expected: &NetworkIdentity,
+    force: bool,
+) -> anyhow::Result<NetworkIdentity> {
+    check(&found, expected, "The snapshot", force)?;
+    Ok(identity_of_data(found, expected))
+}
+
+/// Records the identity of the database in `chain_dir`.
+pub fn record_identity(chain_dir: &Path, identity: &NetworkIdentity) -> anyhow::Result<()> {
+    let path = chain_dir.join(META_FILE);
+    std::fs::write(&path, serde_yaml::to_string(identity)?)
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// The expected identity if `found` matches it, with its network name, and
+/// the identity of the data otherwise, when the mismatch is forced.
+fn identity_of_data(found: NetworkIdentity, expected: &NetworkIdentity) -> NetworkIdentity {
+    if found.genesis == expected.genesis {
+        expected.clone()
+    } else {
+        found
+    }
+}
+
+/// Genesis of the head of an existing database.
+fn head_genesis(db: &impl Blockstore, settings: &impl SettingsStore) -> Option<Cid> {
+    let head_key: TipsetKeys = settings.read_obj(HEAD_KEY).ok()??;
+    let head = Tipset::load(db, &head_key).ok()??;
+    match head.genesis(db) {
+        Ok(genesis) => Some(*genesis.cid()),
+        Err(e) => {
+            warn!("Failed to find the genesis of the database: {e}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MemoryDB;
+    use crate::shim::address::Address;
+    use crate::utils::db::CborStoreExt;
+
+    #[test]
+    fn mismatching_databases_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = MemoryDB::default();
+        let genesis = |miner| {
+            BlockHeader::builder()
+                .miner_address(Address::new_id(miner))
+                .build()
+                .unwrap()
+        };
+        let (ours, theirs) = (genesis(0), genesis(1));
+        db.put_cbor_default(&theirs).unwrap();
+        let ours = NetworkIdentity::new("devnet", &ours);
+
+        // A new database is ours.
+        let identity = check_database_identity(dir.path(), &db, &db, &ours, false).unwrap();
+        assert_eq!(identity, ours);
+        record_identity(dir.path(), &identity).unwrap();
+        check_database_identity(dir.path(), &db, &db, &ours, false).unwrap();
+
+        // An existing database is identified by the genesis of its head.
+        let other = tempfile::tempdir().unwrap();
+        db.write_obj(HEAD_KEY, &TipsetKeys::from(vec![*theirs.cid()]))
+            .unwrap();
+        let e = check_database_identity(other.path(), &db, &db, &ours, false).unwrap_err();
+        assert!(e.to_string().contains("--force"), "{e}");
+
+        // Forcing keeps the identity of the database, so that the mismatch is
+        // reported again.
+        let identity = check_database_identity(other.path(), &db, &db, &ours, true).unwrap();
+        assert_eq!(identity.genesis, *theirs.cid());
+        record_identity(other.path(), &identity).unwrap();
+        assert!(check_database_identity(other.path(), &db, &db, &ours, false).is_err());
+    }
+
+    #[test]
+    fn snapshots_are_identified_before_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chain4.car");
+        std::fs::write(&path, include_bytes!("../../test-snapshots/chain4.car")).unwrap();
+        let found = snapshot_identity(&path).unwrap().unwrap();
+        let car = PlainCar::new(&include_bytes!("../../test-snapshots/chain4.car")[..]).unwrap();
+        let head = car.heaviest_tipset().unwrap();
+        assert_eq!(imported_identity(&head, &car), Some(found.clone()));
+
+        let ours = NetworkIdentity::new(
+            "devnet",
+            &BlockHeader::builder()
+                .miner_address(Address::new_id(0))
+                .build()
+                .unwrap(),
+        );
+        assert!(check_snapshot_identity(found.clone(), &ours, false).is_err());
+        assert_eq!(
+            check_snapshot_identity(found.clone(), &ours, true).unwrap(),
+            found
+        );
+
+        // Remote snapshots are checked once imported.
+        assert_eq!(
+            snapshot_identity(Path::new("https://example.com/snapshot.car")).unwrap(),
+            None
+        );
+    }
+}
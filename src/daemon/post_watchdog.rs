@@ -0,0 +1,465 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Watchdog of the window proofs of spacetime of a set of miners.
+//!
+//! Every epoch, the `SubmitWindowedPoSt` messages sent to the watched miners
+//! are collected from the message pool and the recent tipsets, and their
+//! proofs are verified locally, so that an invalid proof is noticed while the
+//! deadline is still open. Partitions of the current deadline that have no
+//! submission [`PostWatchdogConfig::alert_epochs`] epochs before the deadline
+//! closes are reported, and so are the partitions left without a submission
+//! when it closes.
+//!
+//! Alerts are logged, and counted by the `post_watchdog_alert_total` metric.
+//! Submissions for partitions with faulty or skipped sectors aren't verified,
+//! as the sectors challenged in their place are not reconstructed.
+
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::blocks::Tipset;
+use crate::chain::ChainEpochDelta;
+use crate::message::ChainMessage;
+use crate::message_pool::{MessagePool, Provider};
+use crate::metrics::{self, POST_WATCHDOG_ALERT_TOTAL};
+use crate::networks::proof_policy;
+use crate::shim::{
+    address::{Address, StrictAddress},
+    clock::{ChainEpoch, EPOCH_DURATION_SECONDS},
+    message::Message,
+    randomness::Randomness,
+    sector::{PoStProof, SectorInfo},
+};
+use crate::state_manager::StateManager;
+use ahash::{HashMap, HashSet};
+use anyhow::{ensure, Context as _};
+use cid::Cid;
+use fil_actor_interface::miner;
+use fil_actor_miner_state::v10::new_deadline_info_from_offset_and_epoch;
+use fil_actor_miner_state::v11::{Method as MinerMethod, SubmitWindowedPoStParams};
+use fil_actors_shared::v10::runtime::DomainSeparationTag;
+use fvm_ipld_bitfield::BitField;
+use fvm_ipld_blockstore::Blockstore;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+#[cfg_attr(test, derive(derive_quickcheck_arbitrary::Arbitrary))]
+pub struct PostWatchdogConfig {
+    /// Addresses of the watched miners. No miners disables the watchdog.
+    pub miners: Vec<String>,
+    /// Missing submissions are reported this many epochs before the close of
+    /// a deadline.
+    pub alert_epochs: ChainEpochDelta,
+}
+
+impl Default for PostWatchdogConfig {
+    fn default() -> Self {
+        Self {
+            miners: vec![],
+            alert_epochs: 10,
+        }
+    }
+}
+
+/// Submissions of the current deadline of a miner.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DeadlineWatch {
+    index: u64,
+    challenge: ChainEpoch,
+    close: ChainEpoch,
+    /// Partitions with active sectors, that have to be proven.
+    partitions: BTreeSet<u64>,
+    /// Partitions proven by the submissions seen so far.
+    submitted: BTreeSet<u64>,
+    /// Submissions that were checked already.
+    checked: HashSet<Cid>,
+    /// Whether the missing partitions were reported before the close.
+    alerted: bool,
+}
+
+impl DeadlineWatch {
+    fn new(
+        index: u64,
+        challenge: ChainEpoch,
+        close: ChainEpoch,
+        partitions: BTreeSet<u64>,
+    ) -> Self {
+        Self {
+            index,
+            challenge,
+            close,
+            partitions,
+            submitted: BTreeSet::new(),
+            checked: HashSet::default(),
+            alerted: false,
+        }
+    }
+
+    fn missing(&self) -> Vec<u64> {
+        self.partitions
+            .difference(&self.submitted)
+            .copied()
+            .collect()
+    }
+
+    /// Returns the partitions to report at `epoch` as missing before the
+    /// close of the deadline, once.
+    fn due_alert(&mut self, epoch: ChainEpoch, alert_epochs: ChainEpochDelta) -> Option<Vec<u64>> {
+        if self.alerted || epoch < self.close - alert_epochs {
+            return None;
+        }
+        let missing = self.missing();
+        if missing.is_empty() {
+            return None;
+        }
+        self.alerted = true;
+        Some(missing)
+    }
+}
+
+/// Result of the local verification of a submission.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Verification {
+    Valid,
+    /// The submission couldn't be verified, for the given reason.
+    Skipped(String),
+}
+
+fn alert(kind: &str, message: String) {
+    POST_WATCHDOG_ALERT_TOTAL.with_label_values(&[kind]).inc();
+    warn!("Window PoSt alert: {message}");
+}
+
+/// Watches the window `PoSt` submissions of the miners of `config` forever.
+pub async fn run<DB, T>(
+    config: PostWatchdogConfig,
+    state_manager: Arc<StateManager<DB>>,
+    mpool: Arc<MessagePool<T>>,
+) -> anyhow::Result<()>
+where
+    DB: Blockstore + Send + Sync + 'static,
+    T: Provider + Send + Sync + 'static,
+{
+    let miners = config
+        .miners
+        .iter()
+        .map(|miner| {
+            StrictAddress::from_str(miner)
+                .map(Address::from)
+                .with_context(|| format!("Invalid miner address {miner}"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    info!(
+        "Watching the window PoSt submissions of {}",
+        config.miners.join(", ")
+    );
+
+    let mut watches: HashMap<Address, DeadlineWatch> = HashMap::default();
+    let mut ticker = tokio::time::interval(Duration::from_secs(EPOCH_DURATION_SECONDS as u64));
+    loop {
+        ticker.tick().await;
+        let head = state_manager.chain_store().heaviest_tipset();
+        let submissions = match submissions(&state_manager, &mpool, &head, &miners) {
+            Ok(submissions) => submissions,
+            Err(e) => {
+                warn!("Failed to collect window PoSt submissions: {e:#}");
+                continue;
+            }
+        };
+        for miner in &miners {
+            if let Err(e) = watch_miner(
+                &state_manager,
+                &head,
+                miner,
+                &submissions,
+                &mut watches,
+                config.alert_epochs,
+            ) {
+                warn!("Failed to check the window PoSt of {miner}: {e:#}");
+            }
+        }
+    }
+}
+
+/// Returns the `SubmitWindowedPoSt` messages to `miners` in the message pool
+/// and in the tipsets of the last deadline.
+fn submissions<DB, T>(
+    state_manager: &StateManager<DB>,
+    mpool: &MessagePool<T>,
+    head: &Arc<Tipset>,
+    miners: &[Address],
+) -> anyhow::Result<Vec<(Cid, Message)>>
+where
+    DB: Blockstore,
+    T: Provider,
+{
+    let is_submission = |message: &Message| {
+        message.method_num == MinerMethod::SubmitWindowedPoSt as u64 && miners.contains(&message.to)
+    };
+    let mut submissions = vec![];
+    let (pending, _) = mpool.pending()?;
+    for message in pending {
+        if is_submission(message.message()) {
+            submissions.push((message.cid()?, message.message));
+        }
+    }
+
+    let chain_store = state_manager.chain_store();
+    let since = head.epoch() - state_manager.chain_config().policy.wpost_challenge_window;
+    for tipset in chain_store
+        .chain_index
+        .chain(head.clone())
+        .take_while(|tipset| tipset.epoch() >= since)
+    {
+        for message in chain_store.messages_for_tipset(&tipset)? {
+            if is_submission(message.message()) {
+                let cid = message.cid()?;
+                let message = match message {
+                    ChainMessage::Unsigned(message) => message,
+                    ChainMessage::Signed(signed) => signed.message,
+                };
+                submissions.push((cid, message));
+            }
+        }
+    }
+    Ok(submissions)
+}
+
+fn watch_miner<DB: Blockstore + Send + Sync + 'static>(
+    state_manager: &StateManager<DB>,
+    head: &Arc<Tipset>,
+    miner: &Address,
+    submissions: &[(Cid, Message)],
+    watches: &mut HashMap<Address, DeadlineWatch>,
+    alert_epochs: ChainEpochDelta,
+) -> anyhow::Result<()> {
+    let current = current_deadline(state_manager, head, miner)?;
+    let watch = match watches.get_mut(miner) {
+        Some(watch) if watch.index == current.index && watch.close == current.close => watch,
+        _ => {
+            if let Some(closed) = watches.insert(*miner, current) {
+                let missing = closed.missing();
+                if !missing.is_empty() {
+                    alert(
+                        metrics::values::MISSING_POST,
+                        format!(
+                            "deadline {} of {miner} closed without submissions for partitions {missing:?}",
+                            closed.index
+                        ),
+                    );
+                }
+            }
+            watches.get_mut(miner).expect("inserted above")
+        }
+    };
+
+    for (cid, message) in submissions {
+        if &message.to != miner || !watch.checked.insert(*cid) {
+            continue;
+        }
+        let params: SubmitWindowedPoStParams =
+            match fvm_ipld_encoding::from_slice(message.params.bytes()) {
+                Ok(params) => params,
+                Err(e) => {
+                    alert(
+                        metrics::values::INVALID_POST,
+                        format!("submission {cid} to {miner} has invalid parameters: {e}"),
+                    );
+                    continue;
+                }
+            };
+        if params.deadline != watch.index {
+            // Submissions of the previous deadline are still in the recent
+            // tipsets.
+            debug!(
+                "Ignoring submission {cid} for deadline {} of {miner}",
+                params.deadline
+            );
+            continue;
+        }
+        match verify_submission(state_manager, head, miner, watch, &params) {
+            Ok(verification) => {
+                if let Verification::Skipped(reason) = verification {
+                    debug!("Submission {cid} to {miner} wasn't verified: {reason}");
+                }
+                watch
+                    .submitted
+                    .extend(params.partitions.iter().map(|partition| partition.index));
+            }
+            Err(e) => alert(
+                metrics::values::INVALID_POST,
+                format!(
+                    "submission {cid} for deadline {} of {miner} failed verification: {e:#}",
+                    watch.index
+                ),
+            ),
+        }
+    }
+
+    if let Some(missing) = watch.due_alert(head.epoch(), alert_epochs) {
+        alert(
+            metrics::values::MISSING_POST,
+            format!(
+                "deadline {} of {miner} closes at epoch {} and partitions {missing:?} have no submission",
+                watch.index, watch.close
+            ),
+        );
+    }
+    Ok(())
+}
+
+fn load_miner_state<DB: Blockstore>(
+    state_manager: &StateManager<DB>,
+    head: &Tipset,
+    miner: &Address,
+) -> anyhow::Result<miner::State> {
+    let actor = state_manager
+        .get_actor(miner, *head.parent_state())?
+        .with_context(|| format!("Miner actor {miner} not found"))?;
+    miner::State::load(state_manager.blockstore(), actor.code, actor.state)
+}
+
+/// Returns the open deadline of `miner`, with the partitions to prove.
+fn current_deadline<DB: Blockstore>(
+    state_manager: &StateManager<DB>,
+    head: &Tipset,
+    miner: &Address,
+) -> anyhow::Result<DeadlineWatch> {
+    let store = state_manager.blockstore();
+    let policy = &state_manager.chain_config().policy;
+    let state = load_miner_state(state_manager, head, miner)?;
+    let proving_period_start = match &state {
+        miner::State::V8(st) => st.proving_period_start,
+        miner::State::V9(st) => st.proving_period_start,
+        miner::State::V10(st) => st.proving_period_start,
+        miner::State::V11(st) => st.proving_period_start,
+    };
+    let info = new_deadline_info_from_offset_and_epoch(policy, proving_period_start, head.epoch())
+        .next_not_elapsed();
+
+    let mut partitions = BTreeSet::new();
+    state.for_each_deadline(policy, store, |index, deadline| {
+        if index == info.index {
+            deadline.for_each(store, |index, partition: miner::Partition| {
+                if !partition.active_sectors().is_empty() {
+                    partitions.insert(index);
+                }
+                Ok(())
+            })?;
+        }
+        Ok(())
+    })?;
+    Ok(DeadlineWatch::new(
+        info.index,
+        info.challenge,
+        info.close,
+        partitions,
+    ))
+}
+
+/// Verifies the proofs of a submission against the active sectors of the
+/// proven partitions.
+fn verify_submission<DB: Blockstore + Send + Sync + 'static>(
+    state_manager: &StateManager<DB>,
+    head: &Arc<Tipset>,
+    miner: &Address,
+    watch: &DeadlineWatch,
+    params: &SubmitWindowedPoStParams,
+) -> anyhow::Result<Verification> {
+    let store = state_manager.blockstore();
+    let policy = &state_manager.chain_config().policy;
+    let state = load_miner_state(state_manager, head, miner)?;
+
+    let proven: HashMap<u64, &BitField> = params
+        .partitions
+        .iter()
+        .map(|partition| (partition.index, &partition.skipped))
+        .collect();
+    let mut sectors = BitField::new();
+    let mut skipped = None;
+    state.for_each_deadline(policy, store, |index, deadline| {
+        if index != params.deadline {
+            return Ok(());
+        }
+        deadline.for_each(store, |index, partition: miner::Partition| {
+            if let Some(skipped_sectors) = proven.get(&index) {
+                if !skipped_sectors.is_empty() || !partition.faulty_sectors().is_empty() {
+                    skipped = Some(format!("partition {index} has faulty or skipped sectors"));
+                }
+                sectors |= &partition.active_sectors();
+            }
+            Ok(())
+        })?;
+        Ok(())
+    })?;
+    if let Some(reason) = skipped {
+        return Ok(Verification::Skipped(reason));
+    }
+    ensure!(!sectors.is_empty(), "the proven partitions have no sectors");
+
+    let network_version = state_manager.get_network_version(head.epoch());
+    let seal_proof =
+        proof_policy::seal_proof(state.info(store)?.sector_size().into(), network_version)?;
+    let challenged: Vec<SectorInfo> = state
+        .load_sectors(store, Some(&sectors))?
+        .into_iter()
+        .map(|sector| SectorInfo::new(*seal_proof, sector.sector_number, sector.sealed_cid))
+        .collect();
+
+    let entropy = fvm_ipld_encoding::to_vec(miner)?;
+    let randomness = state_manager
+        .chain_rand(head.clone())
+        .get_beacon_randomness_v3(
+            DomainSeparationTag::WindowedPoStChallengeSeed as i64,
+            watch.challenge,
+            &entropy,
+        )?;
+    let network = &state_manager.chain_config().network;
+    for proof in &params.proofs {
+        ensure!(
+            proof_policy::is_valid_post_proof(network, proof.post_proof, network_version),
+            "PoSt proof {:?} isn't accepted by {network} in {network_version:?}",
+            proof.post_proof
+        );
+    }
+    let proofs: Vec<PoStProof> = params.proofs.iter().cloned().map(PoStProof::from).collect();
+    crate::fil_cns::verify_window_post(
+        Randomness::new(randomness.to_vec()),
+        &proofs,
+        &challenged,
+        miner.id()?,
+    )?;
+    Ok(Verification::Valid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_partitions_are_reported_once() {
+        let mut watch = DeadlineWatch::new(3, 100, 160, BTreeSet::from([0, 1, 2]));
+        watch.submitted.insert(1);
+        assert_eq!(watch.due_alert(149, 10), None);
+        assert_eq!(watch.due_alert(150, 10), Some(vec![0, 2]));
+        assert_eq!(watch.due_alert(151, 10), None);
+        assert_eq!(watch.missing(), [0, 2]);
+
+        let mut watch = DeadlineWatch::new(3, 100, 160, BTreeSet::from([0, 1]));
+        watch.submitted.extend([0, 1]);
+        assert_eq!(watch.due_alert(155, 10), None);
+        assert!(watch.missing().is_empty());
+    }
+
+    #[test]
+    fn default_config_is_disabled() {
+        let config: PostWatchdogConfig = toml::from_str("").unwrap();
+        assert_eq!(config, PostWatchdogConfig::default());
+        assert!(config.miners.is_empty());
+    }
+}
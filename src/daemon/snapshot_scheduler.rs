@@ -0,0 +1,294 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Periodic snapshot exports of the daemon.
+//!
+//! Every [`SnapshotSchedulerConfig::interval`] epochs, the tipset at the
+//! multiple of the interval is exported to the snapshot directory, together
+//! with its `.sha256sum` checksum file. Only the `retention` most recent
+//! scheduled snapshots of the chain are kept.
+//!
+//! The height of the last snapshot is read back from the file names in the
+//! directory, so the schedule survives restarts.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::blocks::Tipset;
+use crate::chain::{index::ResolveNullTipset, ChainEpochDelta};
+use crate::cli_shared::snapshot::{self, TrustedVendor};
+use crate::ipld::CidHashSet;
+use crate::metrics::{SNAPSHOT_EXPORT_BYTES, SNAPSHOT_EXPORT_EPOCH, SNAPSHOT_EXPORT_FAILURE_TOTAL};
+use crate::shim::clock::{ChainEpoch, EPOCH_DURATION_SECONDS};
+use crate::state_manager::StateManager;
+use anyhow::{ensure, Context as _};
+use chrono::Utc;
+use fvm_ipld_blockstore::Blockstore;
+use hex::ToHex;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tempfile::NamedTempFile;
+use tracing::{debug, info, warn};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+#[cfg_attr(test, derive(derive_quickcheck_arbitrary::Arbitrary))]
+pub struct SnapshotSchedulerConfig {
+    /// Export a snapshot every `interval` epochs. Zero disables the
+    /// scheduler.
+    pub interval: ChainEpochDelta,
+    /// Directory of the snapshots. Defaults to `snapshots` in the chain data
+    /// directory.
+    pub directory: Option<PathBuf>,
+    /// Number of scheduled snapshots that are kept.
+    #[cfg_attr(test, arbitrary(gen(|g| u32::arbitrary(g) as _)))]
+    pub retention: usize,
+    /// Number of recent state roots included in the snapshots. Defaults to
+    /// the `recent_state_roots` of the chain, and can't be lower than the
+    /// chain finality.
+    pub depth: Option<ChainEpochDelta>,
+}
+
+impl Default for SnapshotSchedulerConfig {
+    fn default() -> Self {
+        Self {
+            interval: 0,
+            directory: None,
+            retention: 3,
+            depth: None,
+        }
+    }
+}
+
+/// Exports snapshots according to `config` forever. Failed exports are
+/// reported and retried at the next epoch.
+pub async fn run<DB>(
+    config: SnapshotSchedulerConfig,
+    directory: PathBuf,
+    state_manager: Arc<StateManager<DB>>,
+) -> anyhow::Result<()>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    let chain_config = state_manager.chain_config();
+    let depth = config.depth.unwrap_or(chain_config.recent_state_roots);
+    let chain_finality = chain_config.policy.chain_finality;
+    ensure!(
+        depth >= chain_finality,
+        "The depth of scheduled snapshots has to be at least {chain_finality}"
+    );
+    ensure!(
+        config.retention > 0,
+        "The retention of scheduled snapshots has to be at least 1"
+    );
+    tokio::fs::create_dir_all(&directory).await?;
+    let chain = chain_config.network.to_string();
+    info!(
+        "Exporting a snapshot every {} epochs to {}",
+        config.interval,
+        directory.display()
+    );
+
+    let mut ticker = tokio::time::interval(Duration::from_secs(EPOCH_DURATION_SECONDS as u64));
+    loop {
+        ticker.tick().await;
+        if let Err(e) = export_if_due(&state_manager, &config, &directory, &chain, depth).await {
+            SNAPSHOT_EXPORT_FAILURE_TOTAL.inc();
+            warn!("Scheduled snapshot export failed: {e:#}");
+        }
+    }
+}
+
+async fn export_if_due<DB>(
+    state_manager: &StateManager<DB>,
+    config: &SnapshotSchedulerConfig,
+    directory: &Path,
+    chain: &str,
+    depth: ChainEpochDelta,
+) -> anyhow::Result<()>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    let last_height = scheduled_snapshots(directory, chain)?
+        .last()
+        .map(|(height, _)| *height);
+    let head = state_manager.chain_store().heaviest_tipset();
+    let Some(epoch) = next_export_epoch(head.epoch(), config.interval, last_height) else {
+        return Ok(());
+    };
+    let tipset = state_manager.chain_store().chain_index.tipset_by_height(
+        epoch,
+        head,
+        ResolveNullTipset::TakeOlder,
+    )?;
+    if !state_manager.blockstore().has(tipset.parent_state())? {
+        debug!("Skipping the snapshot of epoch {epoch}, its state is missing");
+        return Ok(());
+    }
+
+    let path = directory.join(snapshot::filename(
+        TrustedVendor::Forest,
+        chain,
+        Utc::now().date_naive(),
+        epoch,
+        true,
+    ));
+    info!("Exporting a snapshot to {}", path.display());
+    export_snapshot(state_manager, &tipset, depth, &path)
+        .await
+        .with_context(|| format!("Failed to export the snapshot of epoch {epoch}"))?;
+    SNAPSHOT_EXPORT_EPOCH.set(epoch as u64);
+    info!("Exported a snapshot to {}", path.display());
+    if let Err(e) = prune_snapshots(directory, chain, config.retention) {
+        warn!("Failed to remove old snapshots: {e}");
+    }
+    Ok(())
+}
+
+/// Returns the epoch of the next snapshot, if one is due, i.e. the latest
+/// multiple of `interval` up to the head that is above the last snapshot.
+fn next_export_epoch(
+    head: ChainEpoch,
+    interval: ChainEpochDelta,
+    last_height: Option<ChainEpoch>,
+) -> Option<ChainEpoch> {
+    if interval <= 0 {
+        return None;
+    }
+    let epoch = head - head % interval;
+    (epoch > 0 && last_height.map_or(true, |last| epoch > last)).then_some(epoch)
+}
+
+async fn export_snapshot<DB>(
+    state_manager: &StateManager<DB>,
+    tipset: &Tipset,
+    depth: ChainEpochDelta,
+    path: &Path,
+) -> anyhow::Result<()>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    let directory = path.parent().context("invalid snapshot path")?;
+    let temp_path = NamedTempFile::new_in(directory)?.into_temp_path();
+    let file = tokio::fs::File::create(&temp_path).await?;
+
+    SNAPSHOT_EXPORT_BYTES.set(0);
+    let progress = tokio::spawn({
+        let temp_path = temp_path.to_path_buf();
+        async move {
+            let mut ticker = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                ticker.tick().await;
+                if let Ok(metadata) = tokio::fs::metadata(&temp_path).await {
+                    SNAPSHOT_EXPORT_BYTES.set(metadata.len());
+                }
+            }
+        }
+    });
+    let digest = crate::chain::export::<Sha256>(
+        Arc::clone(&state_manager.chain_store().db),
+        tipset,
+        depth,
+        file,
+        CidHashSet::default(),
+        false,
+        false,
+        Default::default(),
+    )
+    .await;
+    progress.abort();
+    let digest = digest?.context("the checksum of the snapshot is missing")?;
+    SNAPSHOT_EXPORT_BYTES.set(tokio::fs::metadata(&temp_path).await?.len());
+
+    snapshot::save_checksum(path, digest.encode_hex()).await?;
+    temp_path.persist(path)?;
+    Ok(())
+}
+
+/// Returns the heights and paths of the scheduled snapshots of `chain` in
+/// `directory`, ordered by height.
+fn scheduled_snapshots(directory: &Path, chain: &str) -> anyhow::Result<Vec<(i64, PathBuf)>> {
+    let vendor = TrustedVendor::Forest.to_string();
+    let mut snapshots = vec![];
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        let Some(filename) = path.file_name().and_then(std::ffi::OsStr::to_str) else {
+            continue;
+        };
+        if let Some((file_vendor, file_chain, height)) = snapshot::parse_full_filename(filename) {
+            if file_vendor == vendor && file_chain == chain {
+                snapshots.push((height, path));
+            }
+        }
+    }
+    snapshots.sort();
+    Ok(snapshots)
+}
+
+/// Removes the oldest scheduled snapshots and their checksum files, keeping
+/// `retention` snapshots.
+fn prune_snapshots(directory: &Path, chain: &str, retention: usize) -> anyhow::Result<()> {
+    let snapshots = scheduled_snapshots(directory, chain)?;
+    let prunable = snapshots.len().saturating_sub(retention);
+    for (_, path) in snapshots.into_iter().take(prunable) {
+        info!("Removing old snapshot {}", path.display());
+        std::fs::remove_file(&path)?;
+        let checksum_path = path.with_extension("sha256sum");
+        if checksum_path.exists() {
+            std::fs::remove_file(checksum_path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_schedule() {
+        assert_eq!(next_export_epoch(1234, 0, None), None);
+        assert_eq!(next_export_epoch(1234, 100, None), Some(1200));
+        assert_eq!(next_export_epoch(1234, 100, Some(1100)), Some(1200));
+        assert_eq!(next_export_epoch(1234, 100, Some(1200)), None);
+        // Genesis isn't exported.
+        assert_eq!(next_export_epoch(50, 100, None), None);
+    }
+
+    #[test]
+    fn rotation() {
+        let directory = tempfile::tempdir().unwrap();
+        let touch = |name: &str| std::fs::write(directory.path().join(name), b"").unwrap();
+        let date = Utc::now().date_naive();
+        for height in [100, 300, 200] {
+            let name = snapshot::filename(TrustedVendor::Forest, "calibnet", date, height, true);
+            touch(&name);
+            touch(&name.replace(".car.zst", ".car.sha256sum"));
+        }
+        // Snapshots of other chains and vendors are kept.
+        touch(&snapshot::filename(
+            TrustedVendor::Forest,
+            "mainnet",
+            date,
+            10,
+            true,
+        ));
+        touch(&snapshot::filename(
+            TrustedVendor::Filops,
+            "calibnet",
+            date,
+            10,
+            false,
+        ));
+
+        prune_snapshots(directory.path(), "calibnet", 2).unwrap();
+        let heights: Vec<_> = scheduled_snapshots(directory.path(), "calibnet")
+            .unwrap()
+            .into_iter()
+            .map(|(height, _)| height)
+            .collect();
+        assert_eq!(heights, [200, 300]);
+        assert_eq!(std::fs::read_dir(directory.path()).unwrap().count(), 6);
+    }
+}
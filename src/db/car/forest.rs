@@ -254,19 +254,36 @@ impl Encoder {
 
         offset += header_len;
 
+        // Hash the CIDs in a background thread while the frames are compressed
+        // and written, then build the index on all cores.
+        let (cids_tx, cids_rx) = flume::unbounded::<(Vec<Cid>, FrameOffset)>();
+        let (index_tx, index_rx) = flume::bounded(1);
+        std::thread::spawn(move || {
+            let mut cid_map = HashMap::new();
+            for (cids, offset) in cids_rx {
+                for cid in cids {
+                    cid_map.insert(Hash::from(cid), offset);
+                }
+            }
+            let _ = index_tx.send(CarIndexBuilder::new_parallel(cid_map.into_iter().collect()));
+        });
+
         // Write seekable zstd and collect a mapping of CIDs to frame_offset+data_offset.
-        let mut cid_map = HashMap::new();
         while let Some((cids, zstd_frame)) = stream.try_next().await? {
-            for cid in cids {
-                cid_map.insert(Hash::from(cid), offset as FrameOffset);
-            }
+            cids_tx
+                .send((cids, offset as FrameOffset))
+                .map_err(|_| anyhow::anyhow!("CAR index builder stopped unexpectedly"))?;
             sink.write_all(&zstd_frame).await?;
             offset += zstd_frame.len();
         }
+        drop(cids_tx);
 
         // Create index
         let index_offset = offset as u64 + 8;
-        let builder = CarIndexBuilder::new(cid_map.into_iter());
+        let builder = index_rx
+            .recv_async()
+            .await
+            .map_err(|_| anyhow::anyhow!("CAR index builder stopped unexpectedly"))?;
         write_skip_frame_header_async(sink, builder.encoded_len()).await?;
         builder.write_async(sink).await?;
 
@@ -9,7 +9,7 @@ use fvm_ipld_blockstore::Blockstore;
 use itertools::Itertools;
 use parking_lot::RwLock;
 
-use super::SettingsStore;
+use super::{BatchStore, DbBatch, SettingsStore};
 
 #[derive(Debug, Default)]
 pub struct MemoryDB {
@@ -51,6 +51,18 @@ impl Blockstore for MemoryDB {
     }
 }
 
+impl BatchStore for MemoryDB {
+    fn commit_batch(&self, batch: DbBatch) -> Result<()> {
+        let mut blocks = self.blockchain_db.write();
+        let mut settings = self.settings_db.write();
+        for (k, block) in batch.blocks {
+            blocks.insert(k.to_bytes(), block);
+        }
+        settings.extend(batch.settings);
+        Ok(())
+    }
+}
+
 impl BitswapStoreRead for MemoryDB {
     fn contains(&self, cid: &Cid) -> Result<bool> {
         Ok(self.blockchain_db.read().contains_key(&cid.to_bytes()))
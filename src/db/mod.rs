@@ -6,9 +6,14 @@ mod metrics;
 pub mod parity_db;
 pub mod parity_db_config;
 
+use crate::blocks::TipsetKeys;
+use crate::shim::clock::ChainEpoch;
+use cid::multihash::{Code::Blake2b256, MultihashDigest};
+use cid::Cid;
+use fvm_ipld_encoding::DAG_CBOR;
 pub use memory::MemoryDB;
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 pub mod car;
 
 pub mod rolling;
@@ -18,8 +23,77 @@ pub mod setting_keys {
     pub const HEAD_KEY: &str = "head";
     /// Estimated number of IPLD records in the database.
     pub const ESTIMATED_RECORDS_KEY: &str = "estimated_reachable_records";
+    /// Prefix of the keys of the markers of the validated blocks, by CID. A
+    /// marker holds the epoch of the head it was persisted with, and is
+    /// dropped with the stale index records.
+    pub const VALIDATED_BLOCK_KEY_PREFIX: &str = "/validated_blocks/";
     /// Key used to store the memory pool configuration in the settings store.
     pub const MPOOL_CONFIG_KEY: &str = "/mpool/config";
+    /// Progress of the current or last full chain validation.
+    pub const CHAIN_VALIDATION_KEY: &str = "/chain_validation/progress";
+    /// Prefix of the keys of the entries of the `drand` beacons, verified or
+    /// seen in the headers of the chain, by beacon chain hash and round.
+    pub const DRAND_ENTRY_KEY_PREFIX: &str = "/drand_entries/";
+    /// Prefix of the keys of the gas market samples indexed by epoch.
+    pub const GAS_HISTORY_KEY_PREFIX: &str = "/gas_history/";
+    /// Prefix of the keys of the recent changes of the watched actors, by
+    /// address.
+    pub const WATCH_LIST_KEY_PREFIX: &str = "/watch_list/";
+    /// Key used to store the latest tipset proven final by a finality
+    /// certificate.
+    pub const FINALIZED_TIPSET_KEY: &str = "/finality/tipset";
+    /// State roots kept by the garbage collectors, whatever their epoch.
+    pub const PINNED_STATES_KEY: &str = "/pinned_states";
+    /// Whether the automatic resubmission of the local messages is paused.
+    pub const AUTO_RESUBMIT_PAUSED_KEY: &str = "/mpool/auto_resubmit/paused";
+    /// Audit log of the automatic resubmission of the local messages.
+    pub const AUTO_RESUBMIT_LOG_KEY: &str = "/mpool/auto_resubmit/log";
+    /// Local messages tracked by the automatic resubmission.
+    pub const AUTO_RESUBMIT_TRACKED_KEY: &str = "/mpool/auto_resubmit/tracked";
+    /// Pending tasks of the state manager scheduler.
+    pub const SCHEDULED_TASKS_KEY: &str = "/scheduled_tasks";
+    /// Latest heads advertised by the peers.
+    pub const PEER_HEADS_KEY: &str = "/peer_heads";
+    /// Bans of the misbehaving peers, with their expiration.
+    pub const BANNED_PEERS_KEY: &str = "/banned_peers";
+    /// Latest epoch whose block headers have had their receipts pruned by the
+    /// garbage collector.
+    pub const RECEIPTS_PRUNED_EPOCH_KEY: &str = "/gc/receipts_pruned_epoch";
+    /// Prefix of the keys of the indices built when executing tipsets. Their
+    /// records hold [`IndexEntry`](super::IndexEntry) values, and are dropped
+    /// by the garbage collector with the state roots of their epochs.
+    pub const INDEX_KEY_PREFIX: &str = "/indices/";
+}
+
+/// Entry of an index record, for the tipset that indexed `value`. Records are
+/// JSON arrays of entries, so that forks of the same epoch can be kept side by
+/// side.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct IndexEntry<T> {
+    pub epoch: ChainEpoch,
+    #[serde(with = "crate::lotus_json")]
+    pub tipset: TipsetKeys,
+    pub value: T,
+}
+
+/// Returns whether the index record `bytes` has entries of `min_epoch` or
+/// later. Records that can't be decoded are kept.
+pub(in crate::db) fn is_recent_index_record(bytes: &[u8], min_epoch: ChainEpoch) -> bool {
+    match serde_json::from_slice::<Vec<IndexEntry<serde::de::IgnoredAny>>>(bytes) {
+        Ok(entries) => entries.iter().any(|entry| entry.epoch >= min_epoch),
+        Err(_) => true,
+    }
+}
+
+/// Returns whether the validated block marker `bytes` was persisted with a
+/// head of `min_epoch` or later. Unmarked blocks and the markers written
+/// before their epoch was kept are stale.
+pub(in crate::db) fn is_recent_validated_block_marker(bytes: &[u8], min_epoch: ChainEpoch) -> bool {
+    matches!(
+        serde_json::from_slice::<Option<ChainEpoch>>(bytes),
+        Ok(Some(epoch)) if epoch >= min_epoch
+    )
 }
 
 /// Interface used to store and retrieve settings from the database.
@@ -70,6 +144,47 @@ impl<T: ?Sized + SettingsStore> SettingsStoreExt for T {
     }
 }
 
+/// Blocks and settings written together with [`BatchStore::commit_batch`]:
+/// after a crash, the database holds either all of them or none.
+#[derive(Debug, Default)]
+pub struct DbBatch {
+    pub(in crate::db) blocks: Vec<(Cid, Vec<u8>)>,
+    pub(in crate::db) settings: Vec<(String, Vec<u8>)>,
+}
+
+impl DbBatch {
+    /// Queues the `DAG_CBOR` encoding of `object`, and returns its CID, see
+    /// [`CidCborExt::from_cbor_blake2b256`](crate::utils::cid::CidCborExt).
+    pub fn put_cbor<S: Serialize>(&mut self, object: &S) -> anyhow::Result<Cid> {
+        let bytes = fvm_ipld_encoding::to_vec(object)?;
+        let cid = Cid::new_v1(DAG_CBOR, Blake2b256.digest(&bytes));
+        self.blocks.push((cid, bytes));
+        Ok(cid)
+    }
+
+    /// Queues a binary setting, see [`SettingsStore::write_bin`].
+    pub fn write_bin(&mut self, key: &str, value: &[u8]) {
+        self.settings.push((key.to_owned(), value.to_vec()));
+    }
+
+    /// Queues a serializable setting, see [`SettingsStoreExt::write_obj`].
+    pub fn write_obj<V: Serialize>(&mut self, key: &str, value: &V) -> anyhow::Result<()> {
+        self.write_bin(key, &serde_json::to_vec(value)?);
+        Ok(())
+    }
+}
+
+/// Interface used to commit a [`DbBatch`] atomically.
+pub trait BatchStore {
+    fn commit_batch(&self, batch: DbBatch) -> anyhow::Result<()>;
+}
+
+impl<T: ?Sized + BatchStore> BatchStore for std::sync::Arc<T> {
+    fn commit_batch(&self, batch: DbBatch) -> anyhow::Result<()> {
+        self.as_ref().commit_batch(batch)
+    }
+}
+
 /// Traits for collecting DB stats
 pub trait DBStatistics {
     fn get_statistics(&self) -> Option<String> {
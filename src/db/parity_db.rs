@@ -3,7 +3,7 @@
 
 use std::path::PathBuf;
 
-use super::SettingsStore;
+use super::{BatchStore, DbBatch, SettingsStore};
 
 use crate::db::{parity_db_config::ParityDbConfig, DBStatistics};
 use crate::libp2p_bitswap::{BitswapStoreRead, BitswapStoreReadWrite};
@@ -197,24 +197,30 @@ impl Blockstore for ParityDb {
     }
 }
 
+impl BatchStore for ParityDb {
+    fn commit_batch(&self, batch: DbBatch) -> anyhow::Result<()> {
+        let blocks = batch.blocks.into_iter().map(|(k, v)| {
+            let column = Self::choose_column(&k);
+            (column as u8, Operation::Set(k.to_bytes(), v))
+        });
+        let settings = batch
+            .settings
+            .into_iter()
+            .map(|(k, v)| (DbColumn::Settings as u8, Operation::Set(k.into_bytes(), v)));
+        self.db
+            .commit_changes(blocks.chain(settings))
+            .map_err(|e| anyhow!("error committing batch: {e}"))
+    }
+}
+
 impl BitswapStoreRead for ParityDb {
     fn contains(&self, cid: &Cid) -> anyhow::Result<bool> {
-        // We need to check both columns because we don't know which one
-        // the data is in. The order is important because most data will
-        // be in the [`DbColumn::GraphDagCborBlake2b256`] column and so
-        // it directly affects performance. If this assumption ever changes
-        // then this code should be modified accordingly.
-        for column in [DbColumn::GraphDagCborBlake2b256, DbColumn::GraphFull] {
-            if self
-                .db
-                .get_size(column as u8, &cid.to_bytes())
-                .context("error checking if key exists")?
-                .is_some()
-            {
-                return Ok(true);
-            }
-        }
-        Ok(false)
+        let column = Self::choose_column(cid);
+        Ok(self
+            .db
+            .get_size(column as u8, &cid.to_bytes())
+            .context("error checking if key exists")?
+            .is_some())
     }
 
     fn get(&self, cid: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
@@ -320,6 +326,24 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn commit_batch_test() -> anyhow::Result<()> {
+        let db = TempParityDB::new();
+        let mut batch = DbBatch::default();
+        let cid = batch.put_cbor(&"header")?;
+        batch.write_obj("key", &"value")?;
+        db.commit_batch(batch)?;
+
+        assert!(db
+            .read_from_column(cid.to_bytes(), DbColumn::GraphDagCborBlake2b256)?
+            .is_some());
+        assert_eq!(
+            crate::db::SettingsStoreExt::read_obj::<String>(db.as_ref(), "key")?,
+            Some("value".into())
+        );
+        Ok(())
+    }
+
     #[test]
     fn choose_column_test() {
         let data = [0u8; 32];
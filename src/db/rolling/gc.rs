@@ -17,7 +17,8 @@
 //!
 //! ## GC workflow
 //! 1. Walk back from the current heaviest tipset to the genesis block, collect
-//! all the blocks that are reachable from the snapshot
+//! all the blocks that are reachable from the snapshot, and from the pinned
+//! states
 //! 2. writes blocks that are absent from the `current` database to it
 //! 3. delete `old` database(s)
 //! 4. sets `current` database to a newly created one
@@ -73,11 +74,15 @@ use std::{
 };
 
 use crate::blocks::Tipset;
-use crate::db::setting_keys::ESTIMATED_RECORDS_KEY;
+use crate::db::setting_keys::{
+    ESTIMATED_RECORDS_KEY, PINNED_STATES_KEY, RECEIPTS_PRUNED_EPOCH_KEY,
+};
 use crate::db::SettingsStoreExt;
 use crate::ipld::util::*;
 use crate::utils::db::{BlockstoreBufferedWriteExt, DB_KEY_BYTES};
+use crate::utils::io::scheduler::IO_SCHEDULER;
 use chrono::Utc;
+use cid::Cid;
 use fvm_ipld_blockstore::Blockstore;
 use human_repr::HumanCount;
 use tokio::sync::Mutex;
@@ -92,6 +97,7 @@ where
     get_tipset: F,
     chain_finality: i64,
     recent_state_roots: i64,
+    recent_receipts: i64,
     lock: Mutex<()>,
     gc_tx: flume::Sender<flume::Sender<anyhow::Result<()>>>,
     gc_rx: flume::Receiver<flume::Sender<anyhow::Result<()>>>,
@@ -106,6 +112,7 @@ where
         db: Arc<ManyCar<Arc<RollingDB>>>,
         chain_finality: i64,
         recent_state_roots: i64,
+        recent_receipts: i64,
         get_tipset: F,
     ) -> Self {
         let (gc_tx, gc_rx) = flume::unbounded();
@@ -115,6 +122,7 @@ where
             get_tipset,
             chain_finality,
             recent_state_roots,
+            recent_receipts,
             lock: Default::default(),
             gc_tx,
             gc_rx,
@@ -229,43 +237,69 @@ where
             async move { db.buffered_write(rx, BUFFER_CAPCITY_BYTES).await }
         });
         let estimated_reachable_records = self.db.writer().read_obj(ESTIMATED_RECORDS_KEY)?;
-        let n_records = walk_snapshot(
-            &tipset,
-            self.recent_state_roots,
-            |cid| {
-                let db = db.clone();
-                let tx = tx.clone();
-                let reachable_bytes = reachable_bytes.clone();
-                async move {
-                    let block = db
-                        .get(&cid)?
-                        .ok_or_else(|| anyhow::anyhow!("Cid {cid} not found in blockstore"))?;
+        let load_block = |cid| {
+            let db = db.clone();
+            let tx = tx.clone();
+            let reachable_bytes = reachable_bytes.clone();
+            async move {
+                let block = db
+                    .get(&cid)?
+                    .ok_or_else(|| anyhow::anyhow!("Cid {cid} not found in blockstore"))?;
+                IO_SCHEDULER.throttle(block.len()).await;
 
-                    let pair = (cid, block.clone());
-                    if db.writer().has(&cid)? {
-                        reachable_bytes
-                            .fetch_add(DB_KEY_BYTES + pair.1.len(), atomic::Ordering::Relaxed);
+                let pair = (cid, block.clone());
+                if db.writer().has(&cid)? {
+                    reachable_bytes
+                        .fetch_add(DB_KEY_BYTES + pair.1.len(), atomic::Ordering::Relaxed);
 
-                        if !db.writer().current().has(&cid)? {
-                            tx.send_async(pair).await?;
-                        }
+                    if !db.writer().current().has(&cid)? {
+                        tx.send_async(pair).await?;
                     }
-
-                    Ok(block)
                 }
-            },
+
+                Ok(block)
+            }
+        };
+        let n_records = walk_snapshot(
+            &tipset,
+            self.recent_state_roots,
+            load_block,
             Some("Running DB GC | blocks"),
             Some(WALK_SNAPSHOT_PROGRESS_DB_GC.clone()),
             estimated_reachable_records,
         )
         .await?;
+        // Older receipts are recomputed on demand, see
+        // `StateManager::get_receipt`.
+        let n_receipts = walk_receipts(&tipset, self.recent_receipts, load_block).await?;
+        let pinned_states: Vec<Cid> = self
+            .db
+            .writer()
+            .read_obj(PINNED_STATES_KEY)?
+            .unwrap_or_default();
+        let n_pinned = walk_state_roots(&pinned_states, load_block).await;
         drop(tx);
 
         self.db
             .writer()
             .write_obj(ESTIMATED_RECORDS_KEY, &n_records)?;
+        info!(
+            "Kept {n_receipts} receipt blocks of the last {} epochs",
+            self.recent_receipts
+        );
+        if !pinned_states.is_empty() {
+            info!(
+                "Kept {n_pinned} blocks of {} pinned states",
+                pinned_states.len()
+            );
+        }
 
         write_task.await??;
+        // Tells the pruned receipts apart from those never computed.
+        self.db.writer().write_obj(
+            RECEIPTS_PRUNED_EPOCH_KEY,
+            &(tipset.epoch() - self.recent_receipts),
+        )?;
 
         let reachable_bytes = reachable_bytes.load(atomic::Ordering::Relaxed);
         self.last_reachable_bytes
@@ -277,8 +311,12 @@ where
             reachable_bytes.human_count_bytes(),
         );
 
-        // Use the latest head here
-        self.db.writer().next_current((self.get_tipset)().epoch())?;
+        // Use the latest head here, and keep the indices of the retained state
+        // roots.
+        self.db.writer().next_current(
+            (self.get_tipset)().epoch(),
+            tipset.epoch() - self.recent_state_roots,
+        )?;
 
         Ok(())
     }
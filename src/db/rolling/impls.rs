@@ -12,6 +12,7 @@ use parking_lot::RwLock;
 use uuid::Uuid;
 
 use super::*;
+use crate::db::setting_keys::{INDEX_KEY_PREFIX, VALIDATED_BLOCK_KEY_PREFIX};
 use crate::db::*;
 
 impl Blockstore for RollingDB {
@@ -70,6 +71,12 @@ impl Blockstore for RollingDB {
     }
 }
 
+impl BatchStore for RollingDB {
+    fn commit_batch(&self, batch: DbBatch) -> anyhow::Result<()> {
+        BatchStore::commit_batch(self.current().as_ref(), batch)
+    }
+}
+
 impl SettingsStore for RollingDB {
     fn read_bin(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
         for db in self.db_queue() {
@@ -167,8 +174,14 @@ impl RollingDB {
     }
 
     /// Sets `current` as `old`, and sets a new DB as `current`, finally delete
-    /// the dangling `old` DB.
-    pub(super) fn next_current(&self, current_epoch: i64) -> anyhow::Result<()> {
+    /// the dangling `old` DB. The index records without entries of
+    /// `min_index_epoch` or later are left in `old`, see
+    /// [`INDEX_KEY_PREFIX`], and so are the older validated block markers.
+    pub(super) fn next_current(
+        &self,
+        current_epoch: i64,
+        min_index_epoch: i64,
+    ) -> anyhow::Result<()> {
         let new_db_name = Uuid::new_v4().simple().to_string();
         info!("Setting {new_db_name} as current db");
         let db = open_db(&self.db_root.join(&new_db_name), &self.db_config)?;
@@ -184,7 +197,7 @@ impl RollingDB {
 
         delete_db(&old_db_path);
 
-        self.transfer_settings()?;
+        self.transfer_settings(min_index_epoch)?;
 
         Ok(())
     }
@@ -222,11 +235,21 @@ impl RollingDB {
         [self.current.read().clone(), self.old.read().clone()]
     }
 
-    fn transfer_settings(&self) -> anyhow::Result<()> {
+    fn transfer_settings(&self, min_index_epoch: i64) -> anyhow::Result<()> {
         let current = self.current.read();
         for key in self.setting_keys()? {
             if !current.exists(&key)? {
                 if let Some(v) = self.read_bin(&key)? {
+                    if key.starts_with(INDEX_KEY_PREFIX)
+                        && !is_recent_index_record(&v, min_index_epoch)
+                    {
+                        continue;
+                    }
+                    if key.starts_with(VALIDATED_BLOCK_KEY_PREFIX)
+                        && !is_recent_validated_block_marker(&v, min_index_epoch)
+                    {
+                        continue;
+                    }
                     current.write_bin(&key, &v)?;
                 }
             }
@@ -294,8 +317,8 @@ mod tests {
         }
 
         // Roll DB twice
-        rolling_db.next_current(1)?;
-        rolling_db.next_current(2)?;
+        rolling_db.next_current(1, 0)?;
+        rolling_db.next_current(2, 0)?;
 
         // Check if settings are rolled over
         for key in keys.iter() {
@@ -306,6 +329,46 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn stale_index_records_are_dropped() -> Result<()> {
+        let db_root = TempDir::new()?;
+        let rolling_db = RollingDB::load_or_create(db_root.path().into(), Default::default())?;
+        let entry = |epoch| IndexEntry {
+            epoch,
+            tipset: crate::blocks::TipsetKeys::default(),
+            value: (),
+        };
+        let stale = format!("{INDEX_KEY_PREFIX}stale");
+        let recent = format!("{INDEX_KEY_PREFIX}recent");
+        rolling_db.write_obj(&stale, &vec![entry(5)])?;
+        rolling_db.write_obj(&recent, &vec![entry(5), entry(10)])?;
+
+        rolling_db.next_current(1, 10)?;
+        rolling_db.next_current(2, 10)?;
+
+        ensure!(!rolling_db.exists(&stale)?);
+        ensure!(rolling_db.exists(&recent)?);
+        Ok(())
+    }
+
+    #[test]
+    fn stale_validated_block_markers_are_dropped() -> Result<()> {
+        let db_root = TempDir::new()?;
+        let rolling_db = RollingDB::load_or_create(db_root.path().into(), Default::default())?;
+        let marker = |name| format!("{VALIDATED_BLOCK_KEY_PREFIX}{name}");
+        rolling_db.write_obj(&marker("stale"), &Some(5))?;
+        rolling_db.write_obj(&marker("recent"), &Some(10))?;
+        rolling_db.write_obj(&marker("unmarked"), &None::<i64>)?;
+
+        rolling_db.next_current(1, 10)?;
+        rolling_db.next_current(2, 10)?;
+
+        ensure!(!rolling_db.exists(&marker("stale"))?);
+        ensure!(rolling_db.exists(&marker("recent"))?);
+        ensure!(!rolling_db.exists(&marker("unmarked"))?);
+        Ok(())
+    }
+
     #[test]
     fn rolling_db_behaviour_tests() -> Result<()> {
         let db_root = TempDir::new()?;
@@ -327,7 +390,7 @@ mod tests {
             if i == split_index {
                 sleep(Duration::from_millis(1));
                 println!("Creating a new current db");
-                rolling_db.next_current(0)?;
+                rolling_db.next_current(0, 0)?;
                 println!("Created a new current db");
             }
             rolling_db.put_keyed(k, block)?;
@@ -341,7 +404,7 @@ mod tests {
             );
         }
 
-        rolling_db.next_current(0)?;
+        rolling_db.next_current(0, 0)?;
 
         for (i, (k, _)) in pairs.iter().enumerate() {
             if i < split_index {
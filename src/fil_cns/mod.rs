@@ -16,6 +16,8 @@ mod metrics;
 mod validation;
 mod weight;
 
+pub(crate) use validation::verify_window_post;
+
 #[derive(Debug, Error)]
 pub enum FilecoinConsensusError {
     #[error("Block must have an election proof included in tipset")]
@@ -48,6 +50,10 @@ pub enum FilecoinConsensusError {
     BeaconValidation(String),
     #[error("Failed to verify winning PoSt: {0}")]
     WinningPoStValidation(String),
+    /// The proofs can't be verified by this node, which says nothing about the
+    /// block.
+    #[error("Proof parameters are unavailable: {0}")]
+    ProofParametersUnavailable(String),
     #[error("Chain store error: {0}")]
     ChainStore(#[from] ChainStoreError),
     #[error("StateManager error: {0}")]
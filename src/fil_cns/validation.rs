@@ -14,11 +14,13 @@ use crate::shim::crypto::{
 use crate::shim::{
     address::Address,
     randomness::Randomness,
-    sector::{PoStProof, SectorInfo},
+    sector::{PoStProof, RegisteredPoStProof, SectorInfo},
     version::NetworkVersion,
 };
 use crate::state_manager::StateManager;
 use crate::utils::encoding::prover_id_from_u64;
+use crate::utils::monitoring::RESOURCE_GOVERNOR;
+use crate::utils::proofs_api::paramfetch::ensure_params_downloaded;
 use cid::Cid;
 use fil_actor_interface::power;
 use fil_actors_shared::v10::runtime::DomainSeparationTag;
@@ -111,7 +113,9 @@ pub(in crate::fil_cns) async fn validate_block<DB: Blockstore + Sync + Send + 's
     let v_base_tipset = Arc::clone(&base_tipset);
     let v_state_manager = Arc::clone(&state_manager);
     let v_lookback_state = lookback_state.clone();
+    let permit = RESOURCE_GOVERNOR.verification_permit().await;
     validations.push(tokio::task::spawn_blocking(move || {
+        let _permit = permit;
         validate_winner_election(
             v_block.header(),
             v_base_tipset.as_ref(),
@@ -146,7 +150,9 @@ pub(in crate::fil_cns) async fn validate_block<DB: Blockstore + Sync + Send + 's
     let v_base_tipset = Arc::clone(&base_tipset);
     let v_prev_beacon = Arc::clone(&prev_beacon);
     let v_state_manager = Arc::clone(&state_manager);
+    let permit = RESOURCE_GOVERNOR.verification_permit().await;
     validations.push(tokio::task::spawn_blocking(move || {
+        let _permit = permit;
         validate_ticket_election(
             v_block.header(),
             v_base_tipset.as_ref(),
@@ -156,10 +162,18 @@ pub(in crate::fil_cns) async fn validate_block<DB: Blockstore + Sync + Send + 's
         )
     }));
 
-    // Winning PoSt proof validation
+    // Winning PoSt proof validation. The parameters are fetched on the first
+    // verification if they are missing.
+    ensure_params_downloaded().await.map_err(|e| {
+        to_errs(FilecoinConsensusError::ProofParametersUnavailable(format!(
+            "{e:#}"
+        )))
+    })?;
     let v_block = block.clone();
     let v_prev_beacon = Arc::clone(&prev_beacon);
+    let permit = RESOURCE_GOVERNOR.verification_permit().await;
     validations.push(tokio::task::spawn_blocking(move || {
+        let _permit = permit;
         verify_winning_post_proof::<_>(
             &state_manager,
             win_p_nv,
@@ -405,7 +419,7 @@ fn to_fil_public_replica_infos(
             let commr = cid_to_replica_commitment_v1(&sector_info.sealed_cid)?;
             let proof = match typ {
                 ProofType::Winning => sector_info.proof.registered_winning_post_proof()?,
-                // ProofType::Window => sector_info.proof.registered_window_post_proof()?,
+                ProofType::Window => sector_info.proof.registered_window_post_proof()?,
             };
             let replica = PublicReplicaInfo::new(proof.try_into()?, commr);
             Ok((SectorId::from(sector_info.sector_number), replica))
@@ -424,7 +438,7 @@ fn to_fil_public_replica_infos(
 /// PoSt proof variants.
 enum ProofType {
     Winning,
-    // Window,
+    Window,
 }
 
 fn verify_winning_post(
@@ -455,3 +469,168 @@ fn verify_winning_post(
     }
     Ok(())
 }
+
+/// Verifies window proof of spacetime. These proofs are submitted by the
+/// miners once per deadline to prove that the sectors of the challenged
+/// partitions are still stored. A failed window proof leads to the sectors
+/// being marked as faulty.
+///
+/// All proofs must have the same proof type. The proofs of multiple
+/// partitions are concatenated in the proof bytes, and the number of
+/// partitions is derived from the number of challenged sectors.
+// Window proofs are also checked by the miner actor when they are submitted,
+// so block validation doesn't call this, only the window PoSt watchdog does.
+pub(crate) fn verify_window_post(
+    mut rand: Randomness,
+    proofs: &[PoStProof],
+    challenged_sectors: &[SectorInfo],
+    prover: u64,
+) -> Result<(), anyhow::Error> {
+    // Necessary to be valid bls12 381 element.
+    rand.0[31] &= 0x3f;
+
+    let Some(first) = proofs.first() else {
+        anyhow::bail!("Window post has no proofs")
+    };
+    if proofs.iter().any(|p| p.post_proof != first.post_proof) {
+        anyhow::bail!("Window post proofs have different proof types")
+    }
+
+    // Convert sector info into public replica
+    let replicas = to_fil_public_replica_infos(challenged_sectors, ProofType::Window)
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+
+    // Convert PoSt proofs into proofs-api format
+    let proofs = proofs
+        .iter()
+        .map(|p| {
+            let proof_type = RegisteredPoStProof::from(p.post_proof).try_into()?;
+            Ok((proof_type, p.proof_bytes.as_slice()))
+        })
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+    // Generate prover bytes from ID
+    let prover_id = prover_id_from_u64(prover);
+
+    // Verify Proof
+    if !post::verify_window_post(&bytes_32(&rand.0), &proofs, &replicas, prover_id)? {
+        anyhow::bail!("Window post was invalid")
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fvm_shared3::sector::{RegisteredPoStProof as RegisteredPoStProofV3, RegisteredSealProof};
+
+    fn sector(number: u64) -> SectorInfo {
+        let sealed_cid = fvm_shared3::commcid::replica_commitment_v1_to_cid(&[number as u8; 32])
+            .expect("valid commitment");
+        SectorInfo::new(RegisteredSealProof::StackedDRG2KiBV1P1, number, sealed_cid)
+    }
+
+    #[test]
+    fn window_post_replicas() {
+        let replicas =
+            to_fil_public_replica_infos(&[sector(1), sector(2)], ProofType::Window).unwrap();
+        assert_eq!(
+            replicas.keys().copied().collect::<Vec<_>>(),
+            [SectorId::from(1), SectorId::from(2)]
+        );
+    }
+
+    #[test]
+    fn window_post_requires_proofs() {
+        let rand = Randomness::new(vec![0; 32]);
+        let err = verify_window_post(rand, &[], &[sector(1)], 1000).unwrap_err();
+        assert_eq!(err.to_string(), "Window post has no proofs");
+    }
+
+    #[test]
+    fn window_post_rejects_mixed_proof_types() {
+        let proofs = [
+            PoStProof::new(
+                RegisteredPoStProofV3::StackedDRGWindow2KiBV1P1.into(),
+                vec![0; 192],
+            ),
+            PoStProof::new(
+                RegisteredPoStProofV3::StackedDRGWindow8MiBV1P1.into(),
+                vec![0; 192],
+            ),
+        ];
+        let rand = Randomness::new(vec![0; 32]);
+        let err = verify_window_post(rand, &proofs, &[sector(1)], 1000).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Window post proofs have different proof types"
+        );
+    }
+
+    /// Proves a faux 2KiB replica with the real window PoSt circuit, so the
+    /// proof is only accepted for the challenge it was generated for.
+    #[tokio::test]
+    async fn window_post_vectors() {
+        use crate::shim::sector::SectorSize;
+        use crate::utils::proofs_api::paramfetch::{get_params_default, SectorSizeOpt};
+        use filecoin_proofs_api::{
+            post::generate_window_post, seal::fauxrep, PrivateReplicaInfo,
+            RegisteredSealProof as SealProofApi,
+        };
+
+        // Without `FIL_PROOFS_PARAMETER_CACHE`, the proofs are read from
+        // `/var/tmp/filecoin-proof-parameters`.
+        get_params_default(
+            std::path::Path::new("/var/tmp"),
+            SectorSizeOpt::Size(SectorSize::_2KiB),
+            false,
+        )
+        .await
+        .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let replica_path = dir.path().join("replica");
+        std::fs::File::create(&replica_path).unwrap();
+        let comm_r = fauxrep(SealProofApi::StackedDrg2KiBV1_1, dir.path(), &replica_path).unwrap();
+
+        let post_proof = RegisteredPoStProofV3::StackedDRGWindow2KiBV1P1;
+        let private = PrivateReplicaInfo::new(
+            RegisteredPoStProof::from(post_proof).try_into().unwrap(),
+            comm_r,
+            dir.path().to_owned(),
+            replica_path,
+        );
+        let rand = Randomness::new([7; 32].into());
+        let mut seed = bytes_32(&rand.0);
+        seed[31] &= 0x3f;
+        let proofs = generate_window_post(
+            &seed,
+            &BTreeMap::from([(SectorId::from(1), private)]),
+            prover_id_from_u64(1000),
+        )
+        .unwrap()
+        .into_iter()
+        .map(|(_, bytes)| PoStProof::new(post_proof.into(), bytes))
+        .collect::<Vec<_>>();
+
+        let sealed_cid = fvm_shared3::commcid::replica_commitment_v1_to_cid(&comm_r).unwrap();
+        let sectors = [SectorInfo::new(
+            RegisteredSealProof::StackedDRG2KiBV1P1,
+            1,
+            sealed_cid,
+        )];
+        verify_window_post(rand.clone(), &proofs, &sectors, 1000).unwrap();
+
+        // Different randomness, prover or sector.
+        let other_rand = Randomness::new([8; 32].into());
+        assert!(verify_window_post(other_rand, &proofs, &sectors, 1000).is_err());
+        assert!(verify_window_post(rand.clone(), &proofs, &sectors, 1001).is_err());
+        assert!(verify_window_post(rand.clone(), &proofs, &[sector(1)], 1000).is_err());
+
+        // Corrupted proof bytes.
+        let mut bytes = proofs[0].proof_bytes.clone();
+        bytes[0] ^= 0xff;
+        let corrupted = [PoStProof::new(post_proof.into(), bytes)];
+        assert!(verify_window_post(rand, &corrupted, &sectors, 1000).is_err());
+    }
+}
@@ -3,7 +3,7 @@
 
 use std::{sync::Arc, time};
 
-use crate::blocks::{BlockHeader, TipsetKeys};
+use crate::blocks::{BlockHeader, Tipset, TipsetKeys};
 use crate::chain::index::ResolveNullTipset;
 use crate::cli_shared::cli::{BufferSize, ChunkSize};
 use crate::state_manager::StateManager;
@@ -91,6 +91,30 @@ pub async fn import_chain<DB>(
     chunk_size: ChunkSize,
     buffer_size: BufferSize,
 ) -> anyhow::Result<()>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    let (ts, n_records) = load_chain(sm, path, skip_load, chunk_size, buffer_size).await?;
+    if let Some(n_records) = n_records {
+        sm.chain_store().set_estimated_records(n_records as u64)?;
+    }
+
+    // Update head with snapshot header tipset
+    info!("Accepting {:?} as new head.", ts.cids());
+    sm.chain_store().set_heaviest_tipset(ts)?;
+
+    Ok(())
+}
+
+/// Loads the blocks of a CAR file and returns its root tipset, and the number
+/// of loaded records unless `skip_load` is set. The head is left untouched.
+async fn load_chain<DB>(
+    sm: &Arc<StateManager<DB>>,
+    path: &str,
+    skip_load: bool,
+    chunk_size: ChunkSize,
+    buffer_size: BufferSize,
+) -> anyhow::Result<(Arc<Tipset>, Option<usize>)>
 where
     DB: Blockstore + Send + Sync + 'static,
 {
@@ -113,10 +137,6 @@ where
         n_records.unwrap_or_default(),
         stopwatch.elapsed().as_secs()
     );
-    if let Some(n_records) = n_records {
-        sm.chain_store().set_estimated_records(n_records as u64)?;
-    }
-
     let ts = sm.chain_store().tipset_from_keys(&TipsetKeys::from(cids))?;
 
     if !skip_load {
@@ -136,10 +156,43 @@ where
         }
     }
 
-    // Update head with snapshot header tipset
-    info!("Accepting {:?} as new head.", ts.cids());
-    sm.chain_store().set_heaviest_tipset(ts)?;
+    Ok((ts, n_records))
+}
 
+/// Imports a diff snapshot, see [`crate::chain::diff_seen`], on top of the
+/// current head. The head and the estimated number of records are only
+/// updated if the diff descends from the head.
+pub async fn import_chain_diff<DB>(
+    sm: &Arc<StateManager<DB>>,
+    path: &str,
+    chunk_size: ChunkSize,
+    buffer_size: BufferSize,
+) -> anyhow::Result<()>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    let base = sm.chain_store().heaviest_tipset();
+    let (head, n_records) = load_chain(sm, path, false, chunk_size, buffer_size).await?;
+    let descends = head.epoch() > base.epoch()
+        && sm
+            .chain_store()
+            .chain_index
+            .tipset_by_height(base.epoch(), head.clone(), ResolveNullTipset::TakeOlder)
+            .map_or(false, |ancestor| ancestor.key() == base.key());
+    if !descends {
+        bail!(
+            "The diff at {path} doesn't apply on top of the head at epoch {}",
+            base.epoch()
+        );
+    }
+    // A diff only holds the records added since its base.
+    if let Some(n_records) = n_records {
+        let base_records = sm.chain_store().estimated_records()?.unwrap_or_default();
+        sm.chain_store()
+            .set_estimated_records(base_records + n_records as u64)?;
+    }
+    info!("Accepting {:?} as new head.", head.cids());
+    sm.chain_store().set_heaviest_tipset(head)?;
     Ok(())
 }
 
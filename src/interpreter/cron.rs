@@ -0,0 +1,173 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Monitoring of the end of epoch cron.
+//!
+//! Cron runs the deferred work of all built-in actors (e.g. miner deadline
+//! processing) and its cost is not bounded by the block gas limit. It can't be
+//! interrupted either: the state it produces is part of consensus. A
+//! [`CronWatchdog`] raises alerts while cron runs for too long, and the gas and
+//! time of every cron are recorded in metrics.
+
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use fvm_shared2::clock::ChainEpoch;
+use lazy_static::lazy_static;
+use prometheus::{
+    core::{AtomicU64, GenericCounter, GenericGauge, Opts},
+    Histogram, HistogramOpts,
+};
+use tracing::warn;
+
+/// Cron running for longer than this is reported. Alerts are repeated with
+/// exponential back-off until cron finishes.
+pub const CRON_ALERT_THRESHOLD: Duration = Duration::from_secs(10);
+
+/// Result of a cron tick that ran to completion. A failing cron doesn't
+/// invalidate the tipset, unlike errors of the VM itself (e.g. missing state),
+/// which are returned as errors by [`super::VM::run_cron`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CronOutcome {
+    Applied { gas_used: u64 },
+    Failed { gas_used: u64, reason: String },
+}
+
+lazy_static! {
+    pub static ref CRON_TIME: Box<Histogram> = {
+        let cron_time = Box::new(
+            Histogram::with_opts(HistogramOpts {
+                common_opts: Opts::new("cron_time", "Duration of the end of epoch cron"),
+                buckets: prometheus::exponential_buckets(0.1, 2.0, 10)
+                    .expect("Defining the cron_time buckets must succeed"),
+            })
+            .expect("Defining the cron_time metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(cron_time.clone())
+            .expect("Registering the cron_time metric with the metrics registry must succeed");
+        cron_time
+    };
+    pub static ref CRON_GAS_USED: Box<GenericGauge<AtomicU64>> = {
+        let cron_gas_used = Box::new(
+            GenericGauge::<AtomicU64>::new(
+                "cron_gas_used",
+                "Gas used by the most recently executed cron",
+            )
+            .expect("Defining the cron_gas_used metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(cron_gas_used.clone())
+            .expect("Registering the cron_gas_used metric with the metrics registry must succeed");
+        cron_gas_used
+    };
+    pub static ref CRON_FAILURE_TOTAL: Box<GenericCounter<AtomicU64>> = {
+        let cron_failure_total = Box::new(
+            GenericCounter::<AtomicU64>::new(
+                "cron_failure_total",
+                "Total number of failed end of epoch crons",
+            )
+            .expect("Defining the cron_failure_total metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(cron_failure_total.clone())
+            .expect(
+                "Registering the cron_failure_total metric with the metrics registry must succeed",
+            );
+        cron_failure_total
+    };
+    pub static ref CRON_OVERRUN_TOTAL: Box<GenericCounter<AtomicU64>> = {
+        let cron_overrun_total = Box::new(
+            GenericCounter::<AtomicU64>::new(
+                "cron_overrun_total",
+                "Total number of crons that ran for longer than the alert threshold",
+            )
+            .expect("Defining the cron_overrun_total metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(cron_overrun_total.clone())
+            .expect(
+                "Registering the cron_overrun_total metric with the metrics registry must succeed",
+            );
+        cron_overrun_total
+    };
+}
+
+/// Watches a cron from a separate thread, which stops when the watchdog is
+/// finished or dropped.
+pub struct CronWatchdog {
+    started: Instant,
+    _done: mpsc::Sender<()>,
+}
+
+impl CronWatchdog {
+    pub fn start(epoch: ChainEpoch, threshold: Duration) -> Self {
+        let started = Instant::now();
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        let spawned = std::thread::Builder::new()
+            .name("cron-watchdog".into())
+            .spawn(move || {
+                let mut next_alert = threshold;
+                loop {
+                    let wait = next_alert.saturating_sub(started.elapsed());
+                    match done_rx.recv_timeout(wait) {
+                        Err(RecvTimeoutError::Timeout) => {
+                            if next_alert == threshold {
+                                CRON_OVERRUN_TOTAL.inc();
+                            }
+                            warn!(
+                                "Cron of epoch {epoch} has been running for {}s",
+                                started.elapsed().as_secs()
+                            );
+                            next_alert *= 2;
+                        }
+                        Ok(()) | Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+            });
+        if let Err(e) = spawned {
+            warn!("Failed to start the cron watchdog: {e}");
+        }
+        CronWatchdog {
+            started,
+            _done: done_tx,
+        }
+    }
+
+    /// Stops watching and records the cron in the metrics.
+    pub fn finish(self, outcome: &CronOutcome) -> Duration {
+        let elapsed = self.started.elapsed();
+        CRON_TIME.observe(elapsed.as_secs_f64());
+        match outcome {
+            CronOutcome::Applied { gas_used } => CRON_GAS_USED.set(*gas_used),
+            CronOutcome::Failed { gas_used, .. } => {
+                CRON_GAS_USED.set(*gas_used);
+                CRON_FAILURE_TOTAL.inc();
+            }
+        }
+        elapsed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alerts_on_overrun() {
+        let overruns = CRON_OVERRUN_TOTAL.get();
+        let failures = CRON_FAILURE_TOTAL.get();
+
+        let watchdog = CronWatchdog::start(10, Duration::from_millis(10));
+        std::thread::sleep(Duration::from_millis(100));
+        let elapsed = watchdog.finish(&CronOutcome::Failed {
+            gas_used: 7,
+            reason: "out of gas".into(),
+        });
+
+        assert!(elapsed >= Duration::from_millis(100));
+        // Other tests may run crons concurrently.
+        assert!(CRON_OVERRUN_TOTAL.get() > overruns);
+        assert!(CRON_FAILURE_TOTAL.get() > failures);
+    }
+}
@@ -88,19 +88,21 @@ impl<DB: Blockstore + Send + Sync + 'static> ForestExterns<DB> {
         }
 
         let prev_root = self.get_lookback_tipset_state_root_for_round(height)?;
-        let lb_state = StateTree::new_from_root(Arc::clone(&self.chain_index.db), &prev_root)?;
+        let store = Arc::new(self.chain_index.state_store());
+        let lb_state = StateTree::new_from_root(Arc::clone(&store), &prev_root)?;
 
         let actor = lb_state
             .get_actor(miner_addr)?
             .ok_or_else(|| anyhow::anyhow!("actor not found {:?}", miner_addr))?;
 
-        let tbs = TrackingBlockstore::new(&self.chain_index.db);
+        // Reads are tracked above the cache, gas doesn't depend on it.
+        let tbs = TrackingBlockstore::new(&store);
 
         let ms = fil_actor_interface::miner::State::load(&tbs, actor.code, actor.state)?;
 
         let worker = ms.info(&tbs)?.worker.into();
 
-        let state = StateTree::new_from_root(Arc::clone(&self.chain_index.db), &self.root)?;
+        let state = StateTree::new_from_root(Arc::clone(&store), &self.root)?;
 
         let addr = resolve_to_key_addr(&state, &tbs, &worker)?;
 
@@ -1,9 +1,11 @@
 // Copyright 2019-2023 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+pub mod cron;
 mod errors;
 mod fvm2;
 pub mod fvm3;
+mod tipset_messages;
 mod vm;
 
 use crate::shim::{
@@ -13,6 +15,7 @@ use crate::shim::{
 use fil_actor_interface::account;
 use fvm_ipld_blockstore::Blockstore;
 
+pub use self::tipset_messages::TipsetMessages;
 pub use self::vm::*;
 
 /// returns the public key type of address (`BLS`/`SECP256K1`) of an account
@@ -0,0 +1,299 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use crate::blocks::Tipset;
+use crate::chain::block_messages;
+use crate::chain::store::Error;
+use crate::interpreter::BlockMessages;
+use crate::message::{ChainMessage, Message as MessageTrait};
+use crate::networks::{ChainConfig, Height};
+use crate::shim::address::{Address, Protocol};
+use crate::shim::state_tree::StateTree;
+use ahash::{HashMap, HashMapExt};
+use anyhow::Context as _;
+use fvm_ipld_blockstore::Blockstore;
+use std::sync::Arc;
+
+/// The messages of a tipset that are executed by the VM, grouped by block and
+/// in execution order.
+///
+/// Messages are selected like Lotus does:
+/// - Blocks are visited in tipset order, and the BLS messages of a block come
+///   before its SECP messages.
+/// - The first message of a sender sets the expected nonce. Later messages of
+///   that sender are only included if their nonce follows sequentially, which
+///   drops messages that are included in more than one block (first block
+///   wins) as well as messages with out-of-order nonces.
+/// - From the Hyperdrive upgrade, senders are compared by their ID address as
+///   soon as a message of the tipset is sent from an ID address, see
+///   [`TipsetMessages::load`] and [`TipsetMessages::select`].
+///
+/// Use this type wherever the executed messages of a tipset are needed, so
+/// that all consumers agree on what was executed.
+#[derive(Debug)]
+pub struct TipsetMessages {
+    blocks: Vec<BlockMessages>,
+}
+
+impl TipsetMessages {
+    /// Loads the messages of `ts`. Like Lotus, senders are compared by their
+    /// address as written in the messages before the Hyperdrive upgrade, and
+    /// by their ID address in the parent state of `ts` from then on. Failing
+    /// to resolve a sender, e.g. on a node that only syncs headers, is an
+    /// error.
+    pub fn load(
+        db: impl Blockstore,
+        ts: &Tipset,
+        chain_config: &ChainConfig,
+    ) -> Result<Self, Error> {
+        if ts.epoch() < chain_config.epoch(Height::Hyperdrive) {
+            return Self::load_with_resolver(&db, ts, |address| Ok(*address));
+        }
+        let mut state = None;
+        let mut ids = HashMap::new();
+        Self::load_with_resolver(&db, ts, |address| {
+            if let Some(id) = ids.get(address) {
+                return Ok(*id);
+            }
+            let state = match &mut state {
+                Some(state) => state,
+                None => state.insert(StateTree::new_from_root(Arc::new(&db), ts.parent_state())?),
+            };
+            let id = state
+                .lookup_id(address)?
+                .map(Address::new_id)
+                .with_context(|| format!("failed to resolve sender {address}"))?;
+            ids.insert(*address, id);
+            Ok(id)
+        })
+    }
+
+    /// Loads the messages of `ts`, comparing senders by `resolve(from)`. For
+    /// example, resolving to ID addresses makes messages signed with a key
+    /// address and sent from the corresponding ID address share a nonce
+    /// sequence.
+    pub fn load_with_resolver(
+        db: impl Blockstore,
+        ts: &Tipset,
+        resolve: impl FnMut(&Address) -> anyhow::Result<Address>,
+    ) -> Result<Self, Error> {
+        let blocks = ts
+            .blocks()
+            .iter()
+            .map(|b| {
+                let (usm, sm) = block_messages(&db, b)?;
+                let mut messages = Vec::with_capacity(usm.len() + sm.len());
+                messages.extend(usm.into_iter().map(ChainMessage::Unsigned));
+                messages.extend(sm.into_iter().map(ChainMessage::Signed));
+                Ok(BlockMessages {
+                    miner: *b.miner_address(),
+                    messages,
+                    win_count: b
+                        .election_proof()
+                        .as_ref()
+                        .map(|e| e.win_count)
+                        .unwrap_or_default(),
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Self::select(blocks, resolve)
+    }
+
+    /// Applies the selection rules to all messages included in `blocks`. Like
+    /// Lotus, senders are compared by their address as written until a message
+    /// is sent from an ID address, and by `resolve(from)` from then on, so
+    /// that `resolve` is only called when addresses of both kinds are mixed.
+    pub fn select(
+        blocks: impl IntoIterator<Item = BlockMessages>,
+        mut resolve: impl FnMut(&Address) -> anyhow::Result<Address>,
+    ) -> Result<Self, Error> {
+        let mut applied: HashMap<Address, u64> = HashMap::new();
+        let mut use_ids = false;
+        let mut select_msg = |m: &ChainMessage| -> anyhow::Result<bool> {
+            let from = m.from();
+            let sender = if use_ids {
+                resolve(&from)?
+            } else if from.protocol() != Protocol::ID {
+                from
+            } else {
+                // The senders seen so far are compared by ID from now on.
+                use_ids = true;
+                let robust: Vec<_> = applied.iter().map(|(a, n)| (*a, *n)).collect();
+                for (address, sequence) in robust {
+                    applied.insert(resolve(&address)?, sequence);
+                }
+                resolve(&from)?
+            };
+            // The first match for a sender is guaranteed to have correct nonce
+            // the block isn't valid otherwise.
+            let entry = applied.entry(sender).or_insert_with(|| m.sequence());
+
+            if *entry != m.sequence() {
+                return Ok(false);
+            }
+
+            *entry += 1;
+            Ok(true)
+        };
+
+        let mut selected = vec![];
+        for mut block in blocks {
+            let mut messages = Vec::with_capacity(block.messages.len());
+            for m in block.messages {
+                if select_msg(&m)? {
+                    messages.push(m);
+                }
+            }
+            block.messages = messages;
+            selected.push(block);
+        }
+        Ok(TipsetMessages { blocks: selected })
+    }
+
+    pub fn into_blocks(self) -> Vec<BlockMessages> {
+        self.blocks
+    }
+
+    pub fn into_messages(self) -> Vec<ChainMessage> {
+        self.blocks.into_iter().flat_map(|b| b.messages).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shim::message::Message;
+
+    fn message(from: u64, sequence: u64) -> ChainMessage {
+        ChainMessage::Unsigned(Message {
+            from: Address::new_id(from),
+            sequence,
+            ..Message::default()
+        })
+    }
+
+    fn block(messages: Vec<ChainMessage>) -> BlockMessages {
+        BlockMessages {
+            miner: Address::new_id(1000),
+            messages,
+            win_count: 1,
+        }
+    }
+
+    fn sequences(selected: TipsetMessages) -> Vec<Vec<(Address, u64)>> {
+        selected
+            .into_blocks()
+            .iter()
+            .map(|b| {
+                b.messages
+                    .iter()
+                    .map(|m| (m.from(), m.sequence()))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn first_block_wins() {
+        let selected = TipsetMessages::select(
+            [
+                block(vec![message(1, 5), message(1, 6)]),
+                block(vec![message(1, 6), message(1, 7)]),
+            ],
+            |a| Ok(*a),
+        )
+        .unwrap();
+        assert_eq!(
+            sequences(selected),
+            [
+                vec![(Address::new_id(1), 5), (Address::new_id(1), 6)],
+                vec![(Address::new_id(1), 7)],
+            ]
+        );
+    }
+
+    #[test]
+    fn nonces_are_sequential() {
+        let selected = TipsetMessages::select(
+            [block(vec![
+                message(1, 0),
+                message(1, 2),
+                message(2, 9),
+                message(1, 1),
+            ])],
+            |a| Ok(*a),
+        )
+        .unwrap();
+        assert_eq!(
+            selected.into_messages().len(),
+            3,
+            "only the out-of-order nonce is dropped"
+        );
+    }
+
+    #[test]
+    fn senders_are_resolved() {
+        // Addresses 1 and 2 are the same actor.
+        let resolve = |a: &Address| {
+            Ok(if *a == Address::new_id(2) {
+                Address::new_id(1)
+            } else {
+                *a
+            })
+        };
+        let selected = TipsetMessages::select(
+            [block(vec![message(1, 0), message(2, 0), message(2, 1)])],
+            resolve,
+        )
+        .unwrap();
+        assert_eq!(
+            sequences(selected),
+            [vec![(Address::new_id(1), 0), (Address::new_id(2), 1)]]
+        );
+    }
+
+    #[test]
+    fn robust_senders_are_resolved_once_an_id_sender_is_seen() {
+        let robust = Address::new_secp256k1(&[7; 65]).unwrap();
+        let from_robust = |sequence| {
+            ChainMessage::Unsigned(Message {
+                from: robust,
+                sequence,
+                ..Message::default()
+            })
+        };
+        let resolve = |a: &Address| {
+            if *a == robust {
+                Ok(Address::new_id(1))
+            } else if a.protocol() == Protocol::ID {
+                Ok(*a)
+            } else {
+                anyhow::bail!("unknown sender {a}")
+            }
+        };
+
+        // Without ID senders, nothing is resolved.
+        let unknown = ChainMessage::Unsigned(Message {
+            from: Address::new_secp256k1(&[8; 65]).unwrap(),
+            ..Message::default()
+        });
+        let selected =
+            TipsetMessages::select([block(vec![from_robust(0), unknown.clone()])], resolve)
+                .unwrap();
+        assert_eq!(selected.into_messages().len(), 2);
+
+        // The robust sender and its ID share a nonce sequence.
+        let selected = TipsetMessages::select(
+            [block(vec![from_robust(0), message(1, 0), message(1, 1)])],
+            resolve,
+        )
+        .unwrap();
+        assert_eq!(
+            sequences(selected),
+            [vec![(robust, 0), (Address::new_id(1), 1)]]
+        );
+
+        // Senders that can't be resolved are an error.
+        assert!(TipsetMessages::select([block(vec![unknown, message(1, 0)])], resolve).is_err());
+    }
+}
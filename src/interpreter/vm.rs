@@ -4,12 +4,10 @@
 use std::sync::Arc;
 
 use crate::blocks::Tipset;
-use crate::chain::block_messages;
 use crate::chain::index::ChainIndex;
 use crate::chain::store::Error;
 use crate::message::ChainMessage;
-use crate::message::Message as MessageTrait;
-use crate::networks::{ChainConfig, NetworkChain};
+use crate::networks::{actor_bundle, ChainConfig, NetworkChain};
 use crate::shim::{
     address::Address,
     econ::TokenAmount,
@@ -20,7 +18,7 @@ use crate::shim::{
     state_tree::ActorState,
     version::NetworkVersion,
 };
-use ahash::{HashMap, HashMapExt, HashSet};
+use ahash::HashSet;
 use anyhow::bail;
 use cid::Cid;
 use fil_actor_interface::{cron, reward, AwardBlockRewardParams};
@@ -42,8 +40,14 @@ use fvm_ipld_blockstore::Blockstore;
 use fvm_ipld_encoding::{to_vec, RawBytes};
 use fvm_shared2::{clock::ChainEpoch, BLOCK_GAS_LIMIT};
 use num::Zero;
+use serde::{Deserialize, Serialize};
 
-use crate::interpreter::{fvm2::ForestExternsV2, fvm3::ForestExterns as ForestExternsV3};
+use crate::interpreter::{
+    cron::{CronOutcome, CronWatchdog, CRON_ALERT_THRESHOLD},
+    fvm2::ForestExternsV2,
+    fvm3::ForestExterns as ForestExternsV3,
+    TipsetMessages,
+};
 
 pub(in crate::interpreter) type ForestMachineV2<DB> =
     DefaultMachine_v2<Arc<DB>, ForestExternsV2<DB>>;
@@ -68,48 +72,40 @@ pub struct BlockMessages {
 
 impl BlockMessages {
     /// Retrieves block messages to be passed through the VM and removes duplicate messages which appear in multiple blocks.
-    pub fn for_tipset(db: impl Blockstore, ts: &Tipset) -> Result<Vec<BlockMessages>, Error> {
-        let mut applied = HashMap::new();
-        let mut select_msg = |m: ChainMessage| -> Option<ChainMessage> {
-            // The first match for a sender is guaranteed to have correct nonce
-            // the block isn't valid otherwise.
-            let entry = applied.entry(m.from()).or_insert_with(|| m.sequence());
-
-            if *entry != m.sequence() {
-                return None;
-            }
+    /// See [`TipsetMessages`] for the selection rules.
+    pub fn for_tipset(
+        db: impl Blockstore,
+        ts: &Tipset,
+        chain_config: &ChainConfig,
+    ) -> Result<Vec<BlockMessages>, Error> {
+        Ok(TipsetMessages::load(db, ts, chain_config)?.into_blocks())
+    }
+}
 
-            *entry += 1;
-            Some(m)
-        };
+/// Rewards of the miner of a block, as applied by [`VM::apply_block_messages`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct BlockReward {
+    #[serde(with = "crate::lotus_json")]
+    pub miner: Address,
+    pub win_count: i64,
+    /// Paid out by the reward actor: the block reward and the gas reward.
+    #[serde(with = "crate::lotus_json")]
+    pub reward: TokenAmount,
+    #[serde(with = "crate::lotus_json")]
+    pub gas_reward: TokenAmount,
+    #[serde(with = "crate::lotus_json")]
+    pub penalty: TokenAmount,
+}
 
-        ts.blocks()
-            .iter()
-            .map(|b| {
-                let (usm, sm) = block_messages(&db, b)?;
-
-                let mut messages = Vec::with_capacity(usm.len() + sm.len());
-                messages.extend(
-                    usm.into_iter()
-                        .filter_map(|m| select_msg(ChainMessage::Unsigned(m))),
-                );
-                messages.extend(
-                    sm.into_iter()
-                        .filter_map(|m| select_msg(ChainMessage::Signed(m))),
-                );
-
-                Ok(BlockMessages {
-                    miner: *b.miner_address(),
-                    messages,
-                    win_count: b
-                        .election_proof()
-                        .as_ref()
-                        .map(|e| e.win_count)
-                        .unwrap_or_default(),
-                })
-            })
-            .collect()
-    }
+/// Whether the VM records the execution traces of the messages, see
+/// [`ApplyRet::exec_trace`]. Tracing slows the execution down, so it is only
+/// enabled to inspect messages.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum VMTrace {
+    Traced,
+    #[default]
+    NotTraced,
 }
 
 /// Interpreter which handles execution of state transitioning messages and
@@ -159,8 +155,11 @@ where
             timestamp,
         }: ExecutionContext<DB>,
         multi_engine: &MultiEngine,
+        trace: VMTrace,
     ) -> Result<Self, anyhow::Error> {
         let network_version = chain_config.network_version(epoch);
+        let bundle = actor_bundle::manifest_cid(&chain_config, network_version);
+        let db = Arc::clone(&chain_index.db);
         if network_version >= NetworkVersion::V18 {
             let mut config = NetworkConfig_v3::new(network_version.into());
             // ChainId defines the chain ID used in the Ethereum JSON-RPC endpoint.
@@ -173,9 +172,12 @@ where
             let mut context = config.for_epoch(epoch, timestamp, state_tree_root);
             context.set_base_fee(base_fee.into());
             context.set_circulating_supply(circ_supply.into());
+            if trace == VMTrace::Traced {
+                context.enable_tracing();
+            }
             let fvm: ForestMachineV3<DB> = ForestMachineV3::new(
                 &context,
-                Arc::clone(&chain_index.db),
+                Arc::clone(&db),
                 ForestExternsV3::new(
                     RandWrapper::from(rand),
                     heaviest_tipset,
@@ -184,7 +186,8 @@ where
                     chain_index,
                     chain_config,
                 ),
-            )?;
+            )
+            .map_err(|e| with_bundle_context(e, &db, bundle, network_version))?;
             let exec: ForestExecutorV3<DB> = DefaultExecutor_v3::new(engine, fvm)?;
             Ok(VM::VM3(exec))
         } else {
@@ -193,10 +196,13 @@ where
             let mut context = config.for_epoch(epoch, state_tree_root);
             context.set_base_fee(base_fee.into());
             context.set_circulating_supply(circ_supply.into());
+            if trace == VMTrace::Traced {
+                context.enable_tracing();
+            }
             let fvm: ForestMachineV2<DB> = ForestMachineV2::new(
                 &engine,
                 &context,
-                Arc::clone(&chain_index.db),
+                Arc::clone(&db),
                 ForestExternsV2::new(
                     RandWrapper::from(rand),
                     heaviest_tipset,
@@ -205,7 +211,8 @@ where
                     chain_index,
                     chain_config,
                 ),
-            )?;
+            )
+            .map_err(|e| with_bundle_context(e, &db, bundle, network_version))?;
             let exec: ForestExecutorV2<DB> = DefaultExecutor_v2::new(fvm);
             Ok(VM::VM2(exec))
         }
@@ -239,13 +246,16 @@ where
         }
     }
 
+    /// Runs the end of epoch cron. Errors are failures to execute cron at all,
+    /// whereas a cron that ran and failed is reported as
+    /// [`CronOutcome::Failed`].
     pub fn run_cron(
         &mut self,
         epoch: ChainEpoch,
         callback: Option<
             &mut impl FnMut(&Cid, &ChainMessage, &ApplyRet) -> Result<(), anyhow::Error>,
         >,
-    ) -> Result<(), anyhow::Error> {
+    ) -> Result<CronOutcome, anyhow::Error> {
         let cron_msg: Message = Message_v3 {
             from: Address::SYSTEM_ACTOR.into(),
             to: Address::CRON_ACTOR.into(),
@@ -262,19 +272,32 @@ where
         }
         .into();
 
+        let watchdog = CronWatchdog::start(epoch, CRON_ALERT_THRESHOLD);
         let ret = self.apply_implicit_message(&cron_msg)?;
-        if let Some(err) = ret.failure_info() {
-            anyhow::bail!("failed to apply block cron message: {}", err);
-        }
+        let gas_used = ret.msg_receipt().gas_used();
+        let outcome = match ret.failure_info() {
+            Some(reason) => CronOutcome::Failed { gas_used, reason },
+            None => CronOutcome::Applied { gas_used },
+        };
+        let elapsed = watchdog.finish(&outcome);
+        tracing::debug!(epoch, gas_used, ?elapsed, "Cron executed");
 
-        if let Some(callback) = callback {
-            callback(&(cron_msg.cid()?), &ChainMessage::Unsigned(cron_msg), &ret)?;
+        if let CronOutcome::Applied { .. } = outcome {
+            if let Some(callback) = callback {
+                callback(&(cron_msg.cid()?), &ChainMessage::Unsigned(cron_msg), &ret)?;
+            }
         }
-        Ok(())
+        Ok(outcome)
     }
 
     /// Apply block messages from a Tipset.
-    /// Returns the receipts from the transactions.
+    /// Returns the receipts from the transactions, and the rewards of the
+    /// miners of the blocks.
+    ///
+    /// Messages are applied serially. Messages of different senders are not
+    /// independent: every message burns gas to the burnt funds actor and the
+    /// executor applies them to a single state tree, so executing them in
+    /// parallel would always conflict.
     pub fn apply_block_messages(
         &mut self,
         messages: &[BlockMessages],
@@ -282,8 +305,9 @@ where
         mut callback: Option<
             impl FnMut(&Cid, &ChainMessage, &ApplyRet) -> Result<(), anyhow::Error>,
         >,
-    ) -> Result<Vec<Receipt>, anyhow::Error> {
+    ) -> Result<(Vec<Receipt>, Vec<BlockReward>), anyhow::Error> {
         let mut receipts = Vec::new();
+        let mut rewards = Vec::new();
         let mut processed = HashSet::<Cid>::default();
 
         for block in messages.iter() {
@@ -317,9 +341,14 @@ where
             }
 
             // Generate reward transaction for the miner of the block
-            if let Some(rew_msg) =
-                self.reward_message(epoch, block.miner, block.win_count, penalty, gas_reward)?
-            {
+            if let Some(rew_msg) = self.reward_message(
+                epoch,
+                block.miner,
+                block.win_count,
+                penalty.clone(),
+                gas_reward.clone(),
+            )? {
+                let balance_before = self.reward_actor_balance()?;
                 let ret = self.apply_implicit_message(&rew_msg)?;
                 if let Some(err) = ret.failure_info() {
                     anyhow::bail!(
@@ -335,16 +364,23 @@ where
                         ret.msg_receipt().exit_code()
                     );
                 }
+                rewards.push(BlockReward {
+                    miner: block.miner,
+                    win_count: block.win_count,
+                    reward: balance_before - &self.reward_actor_balance()?,
+                    gas_reward,
+                    penalty,
+                });
                 if let Some(callback) = &mut callback {
                     callback(&(rew_msg.cid()?), &ChainMessage::Unsigned(rew_msg), &ret)?;
                 }
             }
         }
 
-        if let Err(e) = self.run_cron(epoch, callback.as_mut()) {
-            tracing::error!("End of epoch cron failed to run: {}", e);
+        if let CronOutcome::Failed { reason, .. } = self.run_cron(epoch, callback.as_mut())? {
+            tracing::error!("End of epoch cron failed: {reason}");
         }
-        Ok(receipts)
+        Ok((receipts, rewards))
     }
 
     /// Applies single message through VM and returns result from execution.
@@ -419,6 +455,13 @@ where
         Ok(ret)
     }
 
+    fn reward_actor_balance(&self) -> anyhow::Result<TokenAmount> {
+        Ok(self
+            .get_actor(&Address::REWARD_ACTOR)?
+            .map(|actor| TokenAmount::from(&actor.balance))
+            .unwrap_or_default())
+    }
+
     fn reward_message(
         &self,
         epoch: ChainEpoch,
@@ -449,3 +492,20 @@ where
         Ok(Some(rew_msg.into()))
     }
 }
+
+/// Machines fail to load the actors of a bundle that isn't stored with an
+/// error that doesn't say so.
+fn with_bundle_context(
+    error: anyhow::Error,
+    db: &impl Blockstore,
+    bundle: Option<Cid>,
+    network_version: NetworkVersion,
+) -> anyhow::Error {
+    match bundle {
+        Some(manifest) if !actor_bundle::is_bundle_stored(db, &manifest).unwrap_or(false) => error
+            .context(format!(
+                "the actor bundle {manifest} of network version {network_version:?} is missing"
+            )),
+        _ => error,
+    }
+}
@@ -4,7 +4,7 @@
 use crate::ipld::CidHashMap;
 use cid::Cid;
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct CidHashSet(CidHashMap<()>);
 
 impl CidHashSet {
@@ -13,6 +13,11 @@ impl CidHashSet {
         self.0.insert(cid, ()).is_none()
     }
 
+    /// Returns `true` if the set contains the value.
+    pub fn contains(&self, cid: &Cid) -> bool {
+        self.0.contains_key(*cid)
+    }
+
     /// Returns the number of items in the set.
     pub fn len(&self) -> usize {
         self.0.len()
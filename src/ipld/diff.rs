@@ -0,0 +1,711 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Structure-aware diffs of HAMT and AMT collections.
+//!
+//! HAMTs and AMTs are canonical: their shape only depends on the set of keys,
+//! and identical subtrees have identical CIDs. The diffs below walk both trees
+//! side by side and skip every pair of subtrees with matching CIDs, so the
+//! cost is proportional to the size of the change rather than to the size of
+//! the collections. [`shared_blocks`] uses them to find the blocks two DAGs
+//! have in common, e.g. two state trees, without visiting those blocks.
+//!
+//! The nodes are decoded as raw [`Ipld`], which makes the diffs independent of
+//! the key and value types, and of the specific crate versions that wrote
+//! them. [`hamt_for_each`] and [`amt_for_each`] visit the entries of a single
+//! HAMT or AMT the same way.
+
+use crate::ipld::Ipld;
+use anyhow::{bail, Context as _};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::CborStore;
+use fvm_ipld_encoding::DAG_CBOR;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Called with the CIDs of the blocks found in both collections of a diff.
+type Shared<'a> = &'a mut dyn FnMut(Cid);
+
+/// Entries that differ between two collections, sorted by key.
+#[derive(Debug, PartialEq)]
+pub struct Diff<K> {
+    pub added: Vec<(K, Ipld)>,
+    pub removed: Vec<(K, Ipld)>,
+    /// Entries with their old and new value.
+    pub modified: Vec<(K, Ipld, Ipld)>,
+}
+
+impl<K> Default for Diff<K> {
+    fn default() -> Self {
+        Diff {
+            added: vec![],
+            removed: vec![],
+            modified: vec![],
+        }
+    }
+}
+
+impl<K: Ord> Diff<K> {
+    /// Keys of all added, removed or modified entries.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.added
+            .iter()
+            .map(|(k, _)| k)
+            .chain(self.removed.iter().map(|(k, _)| k))
+            .chain(self.modified.iter().map(|(k, _, _)| k))
+    }
+
+    /// Compares two fully expanded sets of entries. The links of unchanged
+    /// entries are `shared`.
+    fn push_entries(&mut self, from: BTreeMap<K, Ipld>, mut to: BTreeMap<K, Ipld>, shared: Shared) {
+        for (key, old) in from {
+            match to.remove(&key) {
+                Some(new) if new != old => self.modified.push((key, old, new)),
+                Some(_) => for_each_link(&old, shared),
+                None => self.removed.push((key, old)),
+            }
+        }
+        self.added.extend(to);
+    }
+
+    fn sort(&mut self) {
+        self.added.sort_by(|(a, _), (b, _)| a.cmp(b));
+        self.removed.sort_by(|(a, _), (b, _)| a.cmp(b));
+        self.modified.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+    }
+}
+
+/// Returns the entries that differ between the HAMTs rooted at `from` and
+/// `to`. Keys are the raw HAMT keys, e.g. the bytes of an address. Both HAMTs
+/// must have been built with the same bit-width and hash function.
+pub fn hamt_diff(db: &impl Blockstore, from: &Cid, to: &Cid) -> anyhow::Result<Diff<Vec<u8>>> {
+    hamt_diff_with(db, from, to, &mut |_| {})
+}
+
+fn hamt_diff_with(
+    db: &impl Blockstore,
+    from: &Cid,
+    to: &Cid,
+    shared: Shared,
+) -> anyhow::Result<Diff<Vec<u8>>> {
+    let mut diff = Diff::default();
+    if from == to {
+        shared(*from);
+    } else {
+        hamt_diff_nodes(db, &load(db, from)?, &load(db, to)?, &mut diff, shared)?;
+    }
+    diff.sort();
+    Ok(diff)
+}
+
+fn load(db: &impl Blockstore, cid: &Cid) -> anyhow::Result<Ipld> {
+    db.get_cbor(cid)?
+        .with_context(|| format!("block {cid} not found in blockstore"))
+}
+
+fn list(ipld: &Ipld) -> anyhow::Result<&[Ipld]> {
+    match ipld {
+        Ipld::List(list) => Ok(list),
+        other => bail!("expected a list, found {other:?}"),
+    }
+}
+
+fn for_each_link(ipld: &Ipld, f: Shared) {
+    match ipld {
+        Ipld::Link(cid) => f(*cid),
+        Ipld::List(list) => list.iter().for_each(|ipld| for_each_link(ipld, f)),
+        Ipld::Map(map) => map.values().for_each(|ipld| for_each_link(ipld, f)),
+        _ => {}
+    }
+}
+
+fn bytes(ipld: &Ipld) -> anyhow::Result<&[u8]> {
+    match ipld {
+        Ipld::Bytes(bytes) => Ok(bytes),
+        other => bail!("expected bytes, found {other:?}"),
+    }
+}
+
+/// A HAMT pointer, either a link to a child node or a bucket of entries.
+enum HamtPointer<'a> {
+    Link(Cid),
+    Bucket(&'a [Ipld]),
+}
+
+/// Returns the pointers of a HAMT node, keyed by their bit index.
+fn hamt_pointers(node: &Ipld) -> anyhow::Result<BTreeMap<usize, HamtPointer>> {
+    let [bitfield, pointers] = list(node)? else {
+        bail!("invalid HAMT node: {node:?}");
+    };
+    // The bitfield is a big-endian integer with leading zeros stripped.
+    let bitfield = bytes(bitfield)?;
+    let bits = (0..bitfield.len() * 8)
+        .filter(|bit| bitfield[bitfield.len() - 1 - bit / 8] & (1 << (bit % 8)) != 0);
+    let pointers = list(pointers)?;
+    if bits.clone().count() != pointers.len() {
+        bail!("invalid HAMT node: bitfield doesn't match the pointers");
+    }
+    bits.zip(pointers)
+        .map(|(bit, pointer)| {
+            let pointer = match pointer {
+                Ipld::Link(cid) => HamtPointer::Link(*cid),
+                Ipld::List(bucket) => HamtPointer::Bucket(bucket),
+                // Legacy encoding used by early actors versions
+                Ipld::Map(map) => match (map.get("0"), map.get("1")) {
+                    (Some(Ipld::Link(cid)), _) => HamtPointer::Link(*cid),
+                    (_, Some(Ipld::List(bucket))) => HamtPointer::Bucket(bucket),
+                    _ => bail!("invalid HAMT pointer: {pointer:?}"),
+                },
+                other => bail!("invalid HAMT pointer: {other:?}"),
+            };
+            Ok((bit, pointer))
+        })
+        .collect()
+}
+
+/// Collects all entries below a HAMT pointer.
+fn hamt_entries(
+    db: &impl Blockstore,
+    pointer: &HamtPointer,
+    entries: &mut BTreeMap<Vec<u8>, Ipld>,
+) -> anyhow::Result<()> {
+    match pointer {
+        HamtPointer::Link(cid) => {
+            for pointer in hamt_pointers(&load(db, cid)?)?.values() {
+                hamt_entries(db, pointer, entries)?;
+            }
+        }
+        HamtPointer::Bucket(bucket) => {
+            for entry in bucket.iter() {
+                let [key, value] = list(entry)? else {
+                    bail!("invalid HAMT entry: {entry:?}");
+                };
+                entries.insert(bytes(key)?.to_vec(), value.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Calls `f` with the raw key and the value of every entry of the HAMT rooted
+/// at `root`, in the order of the tree. Nodes are loaded one at a time, so only
+/// the path to the current entry is kept in memory.
+pub fn hamt_for_each(
+    db: &impl Blockstore,
+    root: &Cid,
+    mut f: impl FnMut(&[u8], &Ipld) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    hamt_for_each_node(db, &load(db, root)?, &mut f)
+}
+
+fn hamt_for_each_node(
+    db: &impl Blockstore,
+    node: &Ipld,
+    f: &mut impl FnMut(&[u8], &Ipld) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    for pointer in hamt_pointers(node)?.values() {
+        match pointer {
+            HamtPointer::Link(cid) => hamt_for_each_node(db, &load(db, cid)?, f)?,
+            HamtPointer::Bucket(bucket) => {
+                for entry in bucket.iter() {
+                    let [key, value] = list(entry)? else {
+                        bail!("invalid HAMT entry: {entry:?}");
+                    };
+                    f(bytes(key)?, value)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn hamt_diff_nodes(
+    db: &impl Blockstore,
+    from: &Ipld,
+    to: &Ipld,
+    diff: &mut Diff<Vec<u8>>,
+    shared: Shared,
+) -> anyhow::Result<()> {
+    let from = hamt_pointers(from)?;
+    let mut to = hamt_pointers(to)?;
+    for (bit, from_pointer) in from {
+        match (from_pointer, to.remove(&bit)) {
+            (HamtPointer::Link(a), Some(HamtPointer::Link(b))) if a == b => shared(a),
+            (HamtPointer::Link(a), Some(HamtPointer::Link(b))) => {
+                hamt_diff_nodes(db, &load(db, &a)?, &load(db, &b)?, diff, shared)?;
+            }
+            (from_pointer, to_pointer) => {
+                let mut from_entries = BTreeMap::new();
+                hamt_entries(db, &from_pointer, &mut from_entries)?;
+                let mut to_entries = BTreeMap::new();
+                if let Some(to_pointer) = to_pointer {
+                    hamt_entries(db, &to_pointer, &mut to_entries)?;
+                }
+                diff.push_entries(from_entries, to_entries, shared);
+            }
+        }
+    }
+    for to_pointer in to.values() {
+        let mut to_entries = BTreeMap::new();
+        hamt_entries(db, to_pointer, &mut to_entries)?;
+        diff.push_entries(BTreeMap::new(), to_entries, shared);
+    }
+    Ok(())
+}
+
+struct AmtRoot {
+    bit_width: u32,
+    height: u32,
+    node: Ipld,
+}
+
+impl AmtRoot {
+    fn load(db: &impl Blockstore, cid: &Cid) -> anyhow::Result<Self> {
+        let root = load(db, cid)?;
+        let (bit_width, height, node) = match list(&root)? {
+            // Current encoding: [bit_width, height, count, node]
+            [Ipld::Integer(bit_width), Ipld::Integer(height), _, node] => {
+                (*bit_width, *height, node)
+            }
+            // Legacy encoding with a fixed bit-width: [height, count, node]
+            [Ipld::Integer(height), _, node] => (3, *height, node),
+            _ => bail!("invalid AMT root: {root:?}"),
+        };
+        Ok(AmtRoot {
+            bit_width: bit_width.try_into().context("invalid AMT bit-width")?,
+            height: height.try_into().context("invalid AMT height")?,
+            node: node.clone(),
+        })
+    }
+}
+
+enum AmtNode {
+    Link(Cid),
+    Inline(Ipld),
+}
+
+/// Decoded AMT node. Children are links at height `> 0` and values at height
+/// `0`, keyed by their index within the node.
+enum AmtChildren {
+    Links(BTreeMap<usize, AmtNode>),
+    Values(BTreeMap<usize, Ipld>),
+}
+
+impl AmtNode {
+    fn children(self, db: &impl Blockstore, height: u32) -> anyhow::Result<AmtChildren> {
+        let node = match self {
+            AmtNode::Link(cid) => load(db, &cid)?,
+            AmtNode::Inline(node) => node,
+        };
+        let [bitmap, links, values] = list(&node)? else {
+            bail!("invalid AMT node: {node:?}");
+        };
+        // Bit `i` of the bitmap is bit `i % 8` of byte `i / 8`.
+        let bitmap = bytes(bitmap)?;
+        let indices = (0..bitmap.len() * 8).filter(|i| bitmap[i / 8] & (1 << (i % 8)) != 0);
+        if height > 0 {
+            let links = list(links)?
+                .iter()
+                .map(|link| match link {
+                    Ipld::Link(cid) => Ok(AmtNode::Link(*cid)),
+                    other => bail!("invalid AMT link: {other:?}"),
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            Ok(AmtChildren::Links(indices.zip(links).collect()))
+        } else {
+            Ok(AmtChildren::Values(
+                indices.zip(list(values)?.iter().cloned()).collect(),
+            ))
+        }
+    }
+}
+
+/// Calls `f` with the index and the value of every entry of the AMT rooted at
+/// `root`, in increasing index order. Like [`hamt_for_each`], nodes are loaded
+/// one at a time.
+pub fn amt_for_each(
+    db: &impl Blockstore,
+    root: &Cid,
+    mut f: impl FnMut(u64, &Ipld) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let root = AmtRoot::load(db, root)?;
+    amt_for_each_node(
+        db,
+        root.bit_width,
+        root.height,
+        0,
+        AmtNode::Inline(root.node),
+        &mut f,
+    )
+}
+
+fn amt_for_each_node(
+    db: &impl Blockstore,
+    bit_width: u32,
+    height: u32,
+    offset: u64,
+    node: AmtNode,
+    f: &mut impl FnMut(u64, &Ipld) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    match node.children(db, height)? {
+        AmtChildren::Values(values) => {
+            for (i, value) in values {
+                f(offset + i as u64, &value)?;
+            }
+        }
+        AmtChildren::Links(links) => {
+            let span = 1u64 << (bit_width * height);
+            for (i, child) in links {
+                amt_for_each_node(
+                    db,
+                    bit_width,
+                    height - 1,
+                    offset + span * i as u64,
+                    child,
+                    f,
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the entries that differ between the AMTs rooted at `from` and `to`,
+/// keyed by index. Both AMTs must have the same bit-width, their heights may
+/// differ.
+fn amt_diff(
+    db: &impl Blockstore,
+    from: &Cid,
+    to: &Cid,
+    shared: Shared,
+) -> anyhow::Result<Diff<u64>> {
+    let mut diff = Diff::default();
+    if from == to {
+        shared(*from);
+    } else {
+        let (from, to) = (AmtRoot::load(db, from)?, AmtRoot::load(db, to)?);
+        if from.bit_width != to.bit_width {
+            bail!("AMTs with different bit-widths can't be compared");
+        }
+        amt_diff_nodes(
+            db,
+            from.bit_width,
+            0,
+            Some((from.height, AmtNode::Inline(from.node))),
+            Some((to.height, AmtNode::Inline(to.node))),
+            &mut diff,
+            shared,
+        )?;
+    }
+    diff.sort();
+    Ok(diff)
+}
+
+/// An AMT node with its height, if any.
+type AmtSubtree = Option<(u32, AmtNode)>;
+
+/// Compares two subtrees covering the indices from `offset`. A subtree lower
+/// than the other one is the first child of the other one's nodes, as it is
+/// once the AMT grows.
+fn amt_diff_nodes(
+    db: &impl Blockstore,
+    bit_width: u32,
+    offset: u64,
+    from: AmtSubtree,
+    to: AmtSubtree,
+    diff: &mut Diff<u64>,
+    shared: Shared,
+) -> anyhow::Result<()> {
+    if let (Some((from_height, AmtNode::Link(a))), Some((to_height, AmtNode::Link(b)))) =
+        (&from, &to)
+    {
+        if a == b && from_height == to_height {
+            shared(*a);
+            return Ok(());
+        }
+    }
+    let height = match (&from, &to) {
+        (None, None) => return Ok(()),
+        (Some((height, _)), None) | (None, Some((height, _))) => *height,
+        (Some((a, _)), Some((b, _))) => *a.max(b),
+    };
+    if height == 0 {
+        let from = amt_values(db, from, offset)?;
+        let to = amt_values(db, to, offset)?;
+        diff.push_entries(from, to, shared);
+        return Ok(());
+    }
+    let mut from = amt_links(db, from, height)?;
+    let mut to = amt_links(db, to, height)?;
+    let span = 1u64 << (bit_width * height);
+    let indices: BTreeSet<usize> = from.keys().chain(to.keys()).copied().collect();
+    for i in indices {
+        amt_diff_nodes(
+            db,
+            bit_width,
+            offset + span * i as u64,
+            from.remove(&i),
+            to.remove(&i),
+            diff,
+            shared,
+        )?;
+    }
+    Ok(())
+}
+
+/// Returns the children of `subtree` seen from a node at `height`.
+fn amt_links(
+    db: &impl Blockstore,
+    subtree: AmtSubtree,
+    height: u32,
+) -> anyhow::Result<BTreeMap<usize, (u32, AmtNode)>> {
+    Ok(match subtree {
+        None => BTreeMap::new(),
+        Some((node_height, node)) if node_height == height => match node.children(db, height)? {
+            AmtChildren::Links(links) => links
+                .into_iter()
+                .map(|(i, child)| (i, (height - 1, child)))
+                .collect(),
+            AmtChildren::Values(_) => bail!("invalid AMT node: values above height 0"),
+        },
+        Some(lower) => BTreeMap::from([(0, lower)]),
+    })
+}
+
+fn amt_values(
+    db: &impl Blockstore,
+    subtree: AmtSubtree,
+    offset: u64,
+) -> anyhow::Result<BTreeMap<u64, Ipld>> {
+    Ok(match subtree {
+        None => BTreeMap::new(),
+        Some((_, node)) => match node.children(db, 0)? {
+            AmtChildren::Values(values) => values
+                .into_iter()
+                .map(|(i, value)| (offset + i as u64, value))
+                .collect(),
+            AmtChildren::Links(_) => bail!("invalid AMT node: links at height 0"),
+        },
+    })
+}
+
+/// Calls `shared` with the CIDs of blocks that the DAGs rooted at `from` and
+/// `to` have in common, without visiting them, so that their descendants
+/// don't need to be visited either. HAMTs and AMTs are compared by key, other
+/// blocks link by link. Blocks are only recognized by their shape: a block
+/// mistaken for a HAMT or an AMT makes fewer common blocks be found, never
+/// blocks that aren't common.
+pub fn shared_blocks(
+    db: &impl Blockstore,
+    from: &Cid,
+    to: &Cid,
+    shared: &mut dyn FnMut(Cid),
+) -> anyhow::Result<()> {
+    if from == to {
+        shared(*from);
+        return Ok(());
+    }
+    if from.codec() != DAG_CBOR || to.codec() != DAG_CBOR {
+        return Ok(());
+    }
+    let (from_node, to_node) = (load(db, from)?, load(db, to)?);
+    let modified = if is_amt_root(&from_node) && is_amt_root(&to_node) {
+        amt_diff(db, from, to, shared).map(|diff| {
+            diff.modified
+                .into_iter()
+                .map(|(_, old, new)| (old, new))
+                .collect::<Vec<_>>()
+        })
+    } else if is_hamt_node(&from_node) && is_hamt_node(&to_node) {
+        hamt_diff_with(db, from, to, shared).map(|diff| {
+            diff.modified
+                .into_iter()
+                .map(|(_, old, new)| (old, new))
+                .collect()
+        })
+    } else {
+        return shared_links(db, &from_node, &to_node, shared);
+    };
+    match modified {
+        Ok(modified) => {
+            for (old, new) in &modified {
+                shared_links(db, old, new, shared)?;
+            }
+            Ok(())
+        }
+        // Not a collection after all.
+        Err(_) => shared_links(db, &from_node, &to_node, shared),
+    }
+}
+
+/// Compares the links of `from` and `to` position by position.
+fn shared_links(
+    db: &impl Blockstore,
+    from: &Ipld,
+    to: &Ipld,
+    shared: Shared,
+) -> anyhow::Result<()> {
+    match (from, to) {
+        (Ipld::Link(a), Ipld::Link(b)) => shared_blocks(db, a, b, shared)?,
+        (Ipld::List(a), Ipld::List(b)) if a.len() == b.len() => {
+            for (a, b) in a.iter().zip(b) {
+                shared_links(db, a, b, shared)?;
+            }
+        }
+        (Ipld::Map(a), Ipld::Map(b)) => {
+            for (key, a) in a {
+                if let Some(b) = b.get(key) {
+                    shared_links(db, a, b, shared)?;
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn is_hamt_node(ipld: &Ipld) -> bool {
+    matches!(ipld, Ipld::List(list) if matches!(list.as_slice(), [Ipld::Bytes(_), Ipld::List(_)]))
+}
+
+fn is_amt_root(ipld: &Ipld) -> bool {
+    let is_node = |node: &Ipld| matches!(node, Ipld::List(node) if matches!(node.as_slice(), [Ipld::Bytes(_), Ipld::List(_), Ipld::List(_)]));
+    match ipld {
+        Ipld::List(list) => match list.as_slice() {
+            [Ipld::Integer(_), Ipld::Integer(_), Ipld::Integer(_), node]
+            | [Ipld::Integer(_), Ipld::Integer(_), node] => is_node(node),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MemoryDB;
+    use fvm_ipld_amt::Amt;
+    use fvm_ipld_hamt::{BytesKey, Hamt};
+
+    fn hamt_root(db: &MemoryDB, entries: impl IntoIterator<Item = (u64, u64)>) -> Cid {
+        let mut hamt: Hamt<_, u64> = Hamt::new(db);
+        for (k, v) in entries {
+            hamt.set(BytesKey(k.to_be_bytes().to_vec()), v).unwrap();
+        }
+        hamt.flush().unwrap()
+    }
+
+    #[test]
+    fn hamt_diff_finds_changes() {
+        let db = MemoryDB::default();
+        let from = hamt_root(&db, (0..1000).map(|k| (k, k)));
+        let to = hamt_root(
+            &db,
+            (1..1000)
+                .map(|k| if k == 500 { (k, 0) } else { (k, k) })
+                .chain([(2000, 2000)]),
+        );
+
+        let diff = hamt_diff(&db, &from, &to).unwrap();
+        let key = |k: u64| k.to_be_bytes().to_vec();
+        assert_eq!(diff.added, [(key(2000), Ipld::Integer(2000))]);
+        assert_eq!(diff.removed, [(key(0), Ipld::Integer(0))]);
+        assert_eq!(
+            diff.modified,
+            [(key(500), Ipld::Integer(500), Ipld::Integer(0))]
+        );
+        assert_eq!(hamt_diff(&db, &from, &from).unwrap(), Diff::default());
+    }
+
+    fn amt_root(db: &MemoryDB, entries: impl IntoIterator<Item = (u64, u64)>) -> Cid {
+        let mut amt = Amt::new(db);
+        for (k, v) in entries {
+            amt.set(k, v).unwrap();
+        }
+        amt.flush().unwrap()
+    }
+
+    #[test]
+    fn amt_for_each_visits_entries_in_order() {
+        let db = MemoryDB::default();
+        let entries = [(1, 10), (7, 70), (100_000, 1)];
+        let root = amt_root(&db, entries);
+        let mut visited = vec![];
+        amt_for_each(&db, &root, |i, value| {
+            visited.push((i, value.clone()));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(visited, entries.map(|(i, v)| (i, Ipld::Integer(v as i128))));
+    }
+
+    #[test]
+    fn amt_diff_finds_changes() {
+        let db = MemoryDB::default();
+        let from = amt_root(&db, (0..1000).map(|i| (i, i)));
+        // The new index grows the AMT by several levels.
+        let to = amt_root(
+            &db,
+            (1..1000)
+                .map(|i| if i == 500 { (i, 0) } else { (i, i) })
+                .chain([(100_000, 1)]),
+        );
+
+        let mut shared = vec![];
+        let diff = amt_diff(&db, &from, &to, &mut |cid| shared.push(cid)).unwrap();
+        assert_eq!(diff.added, [(100_000, Ipld::Integer(1))]);
+        assert_eq!(diff.removed, [(0, Ipld::Integer(0))]);
+        assert_eq!(diff.modified, [(500, Ipld::Integer(500), Ipld::Integer(0))]);
+        assert!(!shared.is_empty());
+        assert_eq!(
+            amt_diff(&db, &to, &from, &mut |_| {}).unwrap().added,
+            [(0, Ipld::Integer(0))]
+        );
+        assert_eq!(
+            amt_diff(&db, &from, &from, &mut |_| {}).unwrap(),
+            Diff::default()
+        );
+    }
+
+    /// Returns the blocks reachable from `root` without going through `seen`.
+    fn unseen_blocks(db: &MemoryDB, root: Cid, seen: &mut ahash::HashSet<Cid>) -> usize {
+        if !seen.insert(root) {
+            return 0;
+        }
+        let mut links = vec![];
+        for_each_link(&load(db, &root).unwrap(), &mut |cid| links.push(cid));
+        1 + links
+            .into_iter()
+            .map(|cid| unseen_blocks(db, cid, seen))
+            .sum::<usize>()
+    }
+
+    #[test]
+    fn shared_blocks_skip_common_subtrees() {
+        let db = MemoryDB::default();
+        // HAMTs of AMTs, like the actors of a state tree and their sectors.
+        let state = |changed: u64| {
+            let mut hamt: Hamt<_, Cid> = Hamt::new(&db);
+            for k in 0..100u64 {
+                let entries = (0..100).map(|i| (i, if k == changed && i == 50 { 0 } else { i }));
+                hamt.set(BytesKey(k.to_be_bytes().to_vec()), amt_root(&db, entries))
+                    .unwrap();
+            }
+            hamt.flush().unwrap()
+        };
+        let (from, to) = (state(u64::MAX), state(7));
+
+        let mut shared = ahash::HashSet::default();
+        shared_blocks(&db, &from, &to, &mut |cid| {
+            shared.insert(cid);
+        })
+        .unwrap();
+        let mut from_blocks = ahash::HashSet::default();
+        unseen_blocks(&db, from, &mut from_blocks);
+        assert!(shared.iter().all(|cid| from_blocks.contains(cid)));
+        // Only the path to the changed value is left: HAMT nodes, then AMT
+        // root and node.
+        let new_blocks = unseen_blocks(&db, to, &mut shared);
+        assert!(new_blocks <= 4, "{new_blocks}");
+    }
+}
@@ -3,9 +3,12 @@
 
 mod cid_hashmap;
 mod cid_hashset;
+pub mod diff;
 mod frozen_cids;
 pub mod json;
+pub mod render;
 pub mod selector;
+pub mod stats;
 pub mod util;
 
 pub use libipld::Path;
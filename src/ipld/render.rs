@@ -0,0 +1,223 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Rendering of IPLD graphs as JSON for humans, used to inspect actor states.
+//!
+//! Links to `DAG_CBOR` blocks are followed up to a given depth. HAMTs and AMTs
+//! are recognized by the shape of their root node, and rendered as the map of
+//! their entries rather than as a tree of nodes. A node that merely looks like
+//! a collection fails to be walked and is rendered as it is.
+//!
+//! Unlike [`IpldJson`](super::json::IpldJson), the output isn't meant to be
+//! parsed back: integers are plain numbers when they fit and bytes are hex
+//! strings.
+
+use crate::ipld::diff::{amt_for_each, hamt_for_each};
+use crate::ipld::Ipld;
+use crate::shim::address::Address;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::{CborStore, DAG_CBOR};
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// Number of nested links that are followed.
+    pub depth: usize,
+    /// Number of entries rendered for each HAMT or AMT.
+    pub max_entries: usize,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            depth: 2,
+            max_entries: 50,
+        }
+    }
+}
+
+/// Stops the walk of a collection once enough entries are rendered.
+#[derive(Debug, thiserror::Error)]
+#[error("collection truncated")]
+struct Truncated;
+
+/// Renders `ipld`. Links that aren't followed, because of the depth or because
+/// the block is missing, are rendered as `{"/": cid}`. Collections are
+/// rendered as `{"/": cid, "Hamt": entries}` or `{"/": cid, "Amt": entries}`,
+/// with `"Truncated": true` if they have more than `max_entries` entries.
+pub fn render_ipld(db: &impl Blockstore, ipld: &Ipld, options: &RenderOptions) -> Value {
+    render(db, ipld, options.depth, options)
+}
+
+fn render(db: &impl Blockstore, ipld: &Ipld, depth: usize, options: &RenderOptions) -> Value {
+    match ipld {
+        Ipld::Null => Value::Null,
+        Ipld::Bool(bool) => Value::Bool(*bool),
+        Ipld::Integer(int) => i64::try_from(*int)
+            .map(Value::from)
+            .or_else(|_| u64::try_from(*int).map(Value::from))
+            .unwrap_or_else(|_| Value::String(int.to_string())),
+        Ipld::Float(float) => Value::from(*float),
+        Ipld::String(string) => Value::String(string.clone()),
+        Ipld::Bytes(bytes) => Value::String(format!("0x{}", hex::encode(bytes))),
+        Ipld::List(list) => Value::Array(
+            list.iter()
+                .map(|ipld| render(db, ipld, depth, options))
+                .collect(),
+        ),
+        Ipld::Map(map) => Value::Object(
+            map.iter()
+                .map(|(key, ipld)| (key.clone(), render(db, ipld, depth, options)))
+                .collect(),
+        ),
+        Ipld::Link(cid) => render_link(db, cid, depth, options),
+    }
+}
+
+fn render_link(db: &impl Blockstore, cid: &Cid, depth: usize, options: &RenderOptions) -> Value {
+    let link = json!({ "/": cid.to_string() });
+    if depth == 0 || cid.codec() != DAG_CBOR {
+        return link;
+    }
+    let Ok(Some(node)) = db.get_cbor::<Ipld>(cid) else {
+        return link;
+    };
+    let collection = if is_hamt_node(&node) {
+        render_collection(db, cid, "Hamt", depth, options, |f| {
+            hamt_for_each(db, cid, |key, value| f(hamt_key(key), value))
+        })
+    } else if is_amt_root(&node) {
+        render_collection(db, cid, "Amt", depth, options, |f| {
+            amt_for_each(db, cid, |index, value| f(index.to_string(), value))
+        })
+    } else {
+        None
+    };
+    collection.unwrap_or_else(|| render(db, &node, depth - 1, options))
+}
+
+/// Called with the rendered key and the value of each entry of a collection.
+type EntryVisitor<'a> = dyn FnMut(String, &Ipld) -> anyhow::Result<()> + 'a;
+
+/// Renders the entries visited by `for_each`, or returns `None` if the walk
+/// fails.
+fn render_collection<DB: Blockstore>(
+    db: &DB,
+    cid: &Cid,
+    kind: &str,
+    depth: usize,
+    options: &RenderOptions,
+    for_each: impl FnOnce(&mut EntryVisitor) -> anyhow::Result<()>,
+) -> Option<Value> {
+    let mut entries = serde_json::Map::new();
+    let result = for_each(&mut |key, value| {
+        if entries.len() == options.max_entries {
+            return Err(Truncated.into());
+        }
+        entries.insert(key, render(db, value, depth - 1, options));
+        Ok(())
+    });
+    let truncated = match result {
+        Ok(()) => false,
+        Err(e) if e.is::<Truncated>() => true,
+        Err(_) => return None,
+    };
+    let mut value = json!({ "/": cid.to_string(), kind: entries });
+    if truncated {
+        value["Truncated"] = Value::Bool(true);
+    }
+    Some(value)
+}
+
+/// HAMT keys are mostly addresses or integers, see `fil_actors_runtime::u64_key`.
+fn hamt_key(key: &[u8]) -> String {
+    if let Ok(address) = Address::from_bytes(key) {
+        return address.to_string();
+    }
+    if let Ok((int, [])) = unsigned_varint::decode::u64(key) {
+        return int.to_string();
+    }
+    format!("0x{}", hex::encode(key))
+}
+
+/// `[bitfield, pointers]`
+fn is_hamt_node(node: &Ipld) -> bool {
+    match node {
+        Ipld::List(fields) => matches!(fields.as_slice(), [Ipld::Bytes(_), Ipld::List(_)]),
+        _ => false,
+    }
+}
+
+/// `[bit_width, height, count, node]`, or `[height, count, node]` for the
+/// legacy encoding, where a node is `[bitmap, links, values]`.
+fn is_amt_root(root: &Ipld) -> bool {
+    let is_node = |node: &Ipld| match node {
+        Ipld::List(fields) => matches!(
+            fields.as_slice(),
+            [Ipld::Bytes(_), Ipld::List(_), Ipld::List(_)]
+        ),
+        _ => false,
+    };
+    match root {
+        Ipld::List(fields) => match fields.as_slice() {
+            [Ipld::Integer(_), Ipld::Integer(_), Ipld::Integer(_), node]
+            | [Ipld::Integer(_), Ipld::Integer(_), node] => is_node(node),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MemoryDB;
+    use crate::utils::db::CborStoreExt;
+    use fvm_ipld_amt::Amt;
+    use fvm_ipld_hamt::{BytesKey, Hamt};
+
+    #[test]
+    fn render_collections() {
+        let db = MemoryDB::default();
+        let mut hamt: Hamt<_, u64> = Hamt::new(&db);
+        hamt.set(BytesKey(Address::new_id(5).to_bytes()), 1)
+            .unwrap();
+        let hamt = hamt.flush().unwrap();
+        let mut amt = Amt::new(&db);
+        for i in 0..3 {
+            amt.set(i, i * 10).unwrap();
+        }
+        let amt = amt.flush().unwrap();
+        let state = db
+            .put_cbor_default(&Ipld::List(vec![
+                Ipld::Link(hamt),
+                Ipld::Link(amt),
+                Ipld::Bytes(vec![0xab]),
+            ]))
+            .unwrap();
+
+        let options = RenderOptions {
+            depth: 2,
+            max_entries: 2,
+        };
+        assert_eq!(
+            render_ipld(&db, &Ipld::Link(state), &options),
+            json!([
+                { "/": hamt.to_string(), "Hamt": { "f05": 1 } },
+                { "/": amt.to_string(), "Amt": { "0": 0, "1": 10 }, "Truncated": true },
+                "0xab",
+            ])
+        );
+
+        // Links below the depth are not followed.
+        let options = RenderOptions {
+            depth: 1,
+            max_entries: 2,
+        };
+        assert_eq!(
+            render_ipld(&db, &Ipld::Link(state), &options),
+            json!([{ "/": hamt.to_string() }, { "/": amt.to_string() }, "0xab"])
+        );
+    }
+}
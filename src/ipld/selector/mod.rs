@@ -7,11 +7,11 @@ use std::ops::SubAssign;
 
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-#[cfg(test)]
+#[cfg(any(test, feature = "graphsync-server"))]
 use Selector::*;
 
 pub use self::walk::*;
-#[cfg(test)]
+#[cfg(any(test, feature = "graphsync-server"))]
 use super::Ipld;
 
 /// Selectors are expressions that identify and select a subset of data from an
@@ -204,7 +204,7 @@ pub enum Condition {
     Or,
 }
 
-#[cfg(test)]
+#[cfg(any(test, feature = "graphsync-server"))]
 impl Selector {
     /// Processes and returns resultant selector node
     pub fn explore(self, ipld: &Ipld, p: &str) -> Option<Selector> {
@@ -313,7 +313,7 @@ impl Selector {
     }
 }
 
-#[cfg(test)]
+#[cfg(any(test, feature = "graphsync-server"))]
 fn replace_recursive_edge(next_sel: Selector, replace: Option<Selector>) -> Option<Selector> {
     match next_sel {
         ExploreRecursiveEdge => replace,
@@ -329,7 +329,7 @@ fn replace_recursive_edge(next_sel: Selector, replace: Option<Selector>) -> Opti
         _ => Some(next_sel),
     }
 }
-#[cfg(test)]
+#[cfg(any(test, feature = "graphsync-server"))]
 fn has_recursive_edge(next_sel: &Selector) -> bool {
     match next_sel {
         ExploreRecursiveEdge { .. } => true,
@@ -0,0 +1,307 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Statistics of the blocks of an IPLD graph, e.g. as yielded by
+//! [`stream_graph`](super::stream_graph), to reason about the compression and
+//! pruning of archives.
+//!
+//! The type of a block is inferred from its shape: block headers, messages,
+//! and the nodes of `HAMT` and `AMT` structures are recognized by their number
+//! and kind of fields, so the classification is a best effort.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::blocks::BlockHeader;
+use crate::ipld::Ipld;
+use crate::shim::crypto::IPLD_RAW;
+use crate::utils::db::car_stream::Block;
+use crate::utils::encoding::from_slice_with_fallback;
+use cid::Cid;
+use fvm_ipld_encoding::DAG_CBOR;
+use fvm_shared3::commcid::FIL_COMMITMENT_SEALED;
+use indicatif::HumanBytes;
+
+/// Histogram with power-of-two buckets.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    /// Counts by bit length of the values.
+    buckets: BTreeMap<u32, u64>,
+}
+
+impl Histogram {
+    pub fn record(&mut self, value: u64) {
+        *self
+            .buckets
+            .entry(u64::BITS - value.leading_zeros())
+            .or_default() += 1;
+    }
+
+    /// Returns the inclusive bounds and the count of the non-empty buckets.
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, u64, u64)> + '_ {
+        self.buckets.iter().map(|(&bits, &count)| match bits {
+            0 => (0, 0, count),
+            bits => (1 << (bits - 1), u64::MAX >> (u64::BITS - bits), count),
+        })
+    }
+}
+
+/// Number, total size and size distribution of blocks.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SizeStats {
+    pub blocks: u64,
+    pub bytes: u64,
+    pub sizes: Histogram,
+}
+
+impl SizeStats {
+    fn record(&mut self, size: u64) {
+        self.blocks += 1;
+        self.bytes += size;
+        self.sizes.record(size);
+    }
+}
+
+/// Inferred type of a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BlockKind {
+    Header,
+    Message,
+    SignedMessage,
+    /// Roots of the BLS and SECP messages of a block.
+    MessageMeta,
+    HamtNode,
+    AmtRoot,
+    AmtNode,
+    /// `AMT` node holding the sector infos of a miner.
+    SectorInfos,
+    /// Raw blocks, e.g. actor code.
+    Raw,
+    Other,
+}
+
+impl BlockKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlockKind::Header => "block header",
+            BlockKind::Message => "message",
+            BlockKind::SignedMessage => "signed message",
+            BlockKind::MessageMeta => "message meta",
+            BlockKind::HamtNode => "HAMT node",
+            BlockKind::AmtRoot => "AMT root",
+            BlockKind::AmtNode => "AMT node",
+            BlockKind::SectorInfos => "sector infos",
+            BlockKind::Raw => "raw",
+            BlockKind::Other => "other",
+        }
+    }
+
+    fn infer(cid: &Cid, data: &[u8], ipld: Option<&Ipld>) -> Self {
+        use Ipld::{Bytes, Integer, Link, List};
+        if cid.codec() == IPLD_RAW {
+            return BlockKind::Raw;
+        }
+        let Some(List(fields)) = ipld else {
+            return BlockKind::Other;
+        };
+        match fields.as_slice() {
+            [..] if fields.len() == 16
+                && fvm_ipld_encoding::from_slice::<BlockHeader>(data).is_ok() =>
+            {
+                BlockKind::Header
+            }
+            [Integer(0), ..] if fields.len() == 10 => BlockKind::Message,
+            [List(message), Bytes(_)] if message.len() == 10 => BlockKind::SignedMessage,
+            [Link(_), Link(_)] => BlockKind::MessageMeta,
+            [Bytes(_), List(_)] => BlockKind::HamtNode,
+            // Versions 0 and 3 of the AMT, without and with the bit width.
+            [Integer(_), Integer(_), List(_)] | [Integer(_), Integer(_), Integer(_), List(_)] => {
+                BlockKind::AmtRoot
+            }
+            [Bytes(_), List(_), List(values)] => {
+                if values.first().is_some_and(is_sector_info) {
+                    BlockKind::SectorInfos
+                } else {
+                    BlockKind::AmtNode
+                }
+            }
+            _ => BlockKind::Other,
+        }
+    }
+}
+
+/// Sector infos start with the sector number, the seal proof, and the sealed
+/// sector CID.
+fn is_sector_info(ipld: &Ipld) -> bool {
+    matches!(
+        ipld,
+        Ipld::List(fields) if matches!(
+            fields.as_slice(),
+            [Ipld::Integer(_), Ipld::Integer(_), Ipld::Link(sealed), ..]
+                if sealed.codec() == FIL_COMMITMENT_SEALED
+        )
+    )
+}
+
+fn codec_name(codec: u64) -> String {
+    match codec {
+        DAG_CBOR => "dag-cbor".into(),
+        IPLD_RAW => "raw".into(),
+        codec => format!("{codec:#x}"),
+    }
+}
+
+/// Statistics of the unique blocks of a graph.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GraphStats {
+    pub total: SizeStats,
+    pub by_codec: BTreeMap<u64, SizeStats>,
+    pub by_kind: BTreeMap<BlockKind, SizeStats>,
+    /// Distribution of the number of links of `DAG-CBOR` blocks.
+    pub fan_out: Histogram,
+    /// Number of links of all blocks, including repeated links to the same
+    /// block.
+    pub links: u64,
+}
+
+impl GraphStats {
+    pub fn add_block(&mut self, block: &Block) -> anyhow::Result<()> {
+        let size = block.data.len() as u64;
+        let ipld = if block.cid.codec() == DAG_CBOR {
+            let ipld: Ipld = from_slice_with_fallback(&block.data)?;
+            let links = crate::ipld::DfsIter::new(ipld.clone())
+                .filter(|ipld| matches!(ipld, Ipld::Link(_)))
+                .count() as u64;
+            self.fan_out.record(links);
+            self.links += links;
+            Some(ipld)
+        } else {
+            None
+        };
+        self.total.record(size);
+        self.by_codec
+            .entry(block.cid.codec())
+            .or_default()
+            .record(size);
+        self.by_kind
+            .entry(BlockKind::infer(&block.cid, &block.data, ipld.as_ref()))
+            .or_default()
+            .record(size);
+        Ok(())
+    }
+
+    /// Average number of links pointing to each unique block. Higher ratios
+    /// mean more sharing between the states of different epochs.
+    pub fn dedup_ratio(&self) -> f64 {
+        if self.total.blocks == 0 {
+            0.0
+        } else {
+            self.links as f64 / self.total.blocks as f64
+        }
+    }
+}
+
+fn write_sizes(f: &mut fmt::Formatter<'_>, name: &str, stats: &SizeStats) -> fmt::Result {
+    writeln!(
+        f,
+        "  {name}: {} blocks, {}",
+        stats.blocks,
+        HumanBytes(stats.bytes)
+    )?;
+    for (lower, upper, count) in stats.sizes.buckets() {
+        writeln!(
+            f,
+            "    {} - {}: {count}",
+            HumanBytes(lower),
+            HumanBytes(upper)
+        )?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for GraphStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Blocks:      {} ({})",
+            self.total.blocks,
+            HumanBytes(self.total.bytes)
+        )?;
+        writeln!(f, "Links:       {}", self.links)?;
+        writeln!(f, "Dedup ratio: {:.2}", self.dedup_ratio())?;
+        writeln!(f, "By codec:")?;
+        for (codec, stats) in &self.by_codec {
+            write_sizes(f, &codec_name(*codec), stats)?;
+        }
+        writeln!(f, "By type:")?;
+        for (kind, stats) in &self.by_kind {
+            write_sizes(f, kind.name(), stats)?;
+        }
+        write!(f, "Link fan-out:")?;
+        for (lower, upper, count) in self.fan_out.buckets() {
+            write!(f, "\n  {lower} - {upper}: {count}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shim::{address::Address, message::Message};
+    use crate::utils::cid::CidCborExt;
+
+    fn block<S: serde::Serialize>(obj: &S) -> Block {
+        Block {
+            cid: Cid::from_cbor_blake2b256(obj).unwrap(),
+            data: fvm_ipld_encoding::to_vec(obj).unwrap(),
+        }
+    }
+
+    #[test]
+    fn histogram_buckets() {
+        let mut histogram = Histogram::default();
+        for value in [0, 1, 2, 3, 4, 1000, u64::MAX] {
+            histogram.record(value);
+        }
+        assert_eq!(
+            histogram.buckets().collect::<Vec<_>>(),
+            [
+                (0, 0, 1),
+                (1, 1, 1),
+                (2, 3, 2),
+                (4, 7, 1),
+                (512, 1023, 1),
+                (1 << 63, u64::MAX, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn graph_stats() {
+        let header = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .build()
+            .unwrap();
+        let message = Message::default();
+        let meta = (message.cid().unwrap(), message.cid().unwrap());
+
+        let mut stats = GraphStats::default();
+        for block in [block(&header), block(&message), block(&meta)] {
+            stats.add_block(&block).unwrap();
+        }
+        assert_eq!(
+            stats.by_kind.keys().collect::<Vec<_>>(),
+            [
+                &BlockKind::Header,
+                &BlockKind::Message,
+                &BlockKind::MessageMeta
+            ]
+        );
+        assert_eq!(stats.total.blocks, 3);
+        assert_eq!(stats.by_codec[&DAG_CBOR].blocks, 3);
+        // The header links to its state, messages and receipts.
+        assert!(stats.links >= 5);
+        assert!(stats.dedup_ratio() > 1.0);
+    }
+}
@@ -25,6 +25,7 @@ use lazy_static::lazy_static;
 use pin_project_lite::pin_project;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use tracing::{debug, warn};
 
 /// Traverses all Cid links, hashing and loading all unique values and using the
 /// callback function to interact with the data.
@@ -188,6 +189,95 @@ where
     Ok(seen.len())
 }
 
+/// Walks over the message receipts, and the events they link to, of the
+/// tipsets of the `recent_receipts` most recent epochs, and loads all blocks
+/// not yet seen. [`walk_snapshot`] doesn't visit receipts. Receipts that can't
+/// be loaded, e.g. of tipsets that were never executed locally, are skipped.
+pub async fn walk_receipts<F, T>(
+    tipset: &Tipset,
+    recent_receipts: i64,
+    mut load_block: F,
+) -> anyhow::Result<usize>
+where
+    F: FnMut(Cid) -> T + Send,
+    T: Future<Output = anyhow::Result<Vec<u8>>> + Send,
+{
+    let mut seen = CidHashSet::default();
+    let mut walked_headers = CidHashSet::default();
+    let mut headers_to_walk: VecDeque<Cid> = tipset.cids().into();
+    let incl_receipts_epoch = tipset.epoch() - recent_receipts;
+
+    while let Some(next) = headers_to_walk.pop_front() {
+        if !walked_headers.insert(next) {
+            continue;
+        }
+        let data = load_block(next).await?;
+        let h = from_slice_with_fallback::<BlockHeader>(&data)?;
+        if h.epoch() <= incl_receipts_epoch {
+            continue;
+        }
+        let receipts = *h.message_receipts();
+        if let Err(e) = recurse_links_hash(&mut seen, receipts, &mut load_block, &|_| {}).await {
+            debug!(
+                "Skipping the receipts {receipts} of epoch {}: {e}",
+                h.epoch()
+            );
+        }
+        if h.epoch() > 0 {
+            for p in &h.parents().cids {
+                headers_to_walk.push_back(p);
+            }
+        }
+    }
+
+    Ok(seen.len())
+}
+
+/// Walks over the state trees of `state_roots`, and loads all blocks not yet
+/// seen. Used to keep the states pinned with
+/// [`ChainStore::pin_state`](crate::chain::ChainStore::pin_state) out of reach
+/// of the garbage collectors. States that can't be loaded entirely are skipped.
+pub async fn walk_state_roots<F, T>(state_roots: &[Cid], mut load_block: F) -> usize
+where
+    F: FnMut(Cid) -> T + Send,
+    T: Future<Output = anyhow::Result<Vec<u8>>> + Send,
+{
+    let mut seen = CidHashSet::default();
+    for state_root in state_roots {
+        if let Err(e) = recurse_links_hash(&mut seen, *state_root, &mut load_block, &|_| {}).await {
+            warn!("Skipping the pinned state {state_root}: {e}");
+        }
+    }
+    seen.len()
+}
+
+/// Returns a block of the graph of `root` that is missing from `db`, if any,
+/// following the same links as [`recurse_links_hash`].
+pub fn find_missing_block<DB: Blockstore>(db: &DB, root: Cid) -> anyhow::Result<Option<Cid>> {
+    let mut seen = CidHashSet::default();
+    let mut to_walk = vec![root];
+    while let Some(cid) = to_walk.pop() {
+        if !seen.insert(cid) {
+            continue;
+        }
+        if cid.codec() == crate::shim::crypto::IPLD_RAW && !db.has(&cid)? {
+            return Ok(Some(cid));
+        }
+        if cid.codec() != fvm_ipld_encoding::DAG_CBOR {
+            continue;
+        }
+        let Some(bytes) = db.get(&cid)? else {
+            return Ok(Some(cid));
+        };
+        let ipld: Ipld = from_slice_with_fallback(&bytes)?;
+        to_walk.extend(DfsIter::new(ipld).filter_map(|ipld| match ipld {
+            Ipld::Link(link) => Some(link),
+            _ => None,
+        }));
+    }
+    Ok(None)
+}
+
 fn should_save_block_to_snapshot(cid: Cid) -> bool {
     // Don't include identity CIDs.
     // We only include raw and dagcbor, for now.
@@ -263,11 +353,75 @@ impl Iterator for DfsIter {
     }
 }
 
+/// Breadth-first-search over links where every level is visited in ascending
+/// [`Cid`] order. Unlike [`DfsIter`], the visiting order only depends on the
+/// set of reachable blocks and not on how they link to each other.
+#[derive(Default)]
+struct SortedBfs {
+    level: std::vec::IntoIter<Cid>,
+    /// Links of the current level, sorted and deduplicated once the current
+    /// level is exhausted. A level can hold most of a state tree, so it is
+    /// kept in a flat vector and blocks that were already exported are left
+    /// out.
+    next_level: Vec<Cid>,
+}
+
+impl SortedBfs {
+    fn new(root: Cid) -> Self {
+        SortedBfs {
+            level: vec![root].into_iter(),
+            next_level: vec![],
+        }
+    }
+
+    /// Queue the links of `ipld` that are not in `seen` for the next level.
+    fn walk_next(&mut self, ipld: Ipld, seen: &CidHashSet) {
+        self.next_level
+            .extend(DfsIter::new(ipld).filter_map(|ipld| match ipld {
+                Ipld::Link(cid) if should_save_block_to_snapshot(cid) && !seen.contains(&cid) => {
+                    Some(cid)
+                }
+                _ => None,
+            }));
+    }
+}
+
+impl Iterator for SortedBfs {
+    type Item = Cid;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(cid) = self.level.next() {
+                return Some(cid);
+            }
+            if self.next_level.is_empty() {
+                return None;
+            }
+            let mut level = std::mem::take(&mut self.next_level);
+            level.sort_unstable();
+            level.dedup();
+            self.level = level.into_iter();
+        }
+    }
+}
+
 enum Task {
     // Yield the block, don't visit it.
     Emit(Cid),
     // Visit all the elements, recursively.
     Iterate(DfsIter),
+    // Visit all the elements, level by level in canonical order.
+    IterateSorted(SortedBfs),
+}
+
+impl Task {
+    fn walk(root: Cid, canonical: bool) -> Self {
+        if canonical {
+            Task::IterateSorted(SortedBfs::new(root))
+        } else {
+            Task::Iterate(DfsIter::from(root))
+        }
+    }
 }
 
 pin_project! {
@@ -279,6 +433,7 @@ pin_project! {
         seen: CidHashSet,
         stateroot_limit: ChainEpoch,
         fail_on_dead_links: bool,
+        canonical: bool,
     }
 }
 
@@ -290,6 +445,16 @@ impl<DB, T> ChainStream<DB, T> {
     pub fn into_seen(self) -> CidHashSet {
         self.seen
     }
+
+    /// Walk the state and message graphs breadth-first, sorting the blocks of
+    /// each level by [`Cid`]. Two streams over the same chain then yield the
+    /// same blocks in the same order, regardless of the database backing them.
+    pub fn canonical(self) -> Self {
+        ChainStream {
+            canonical: true,
+            ..self
+        }
+    }
 }
 
 /// Stream all blocks that are reachable before the `stateroot_limit` epoch. After this limit, only
@@ -314,6 +479,7 @@ pub fn stream_chain<DB: Blockstore, T: Iterator<Item = Tipset> + Unpin>(
         seen: CidHashSet::default(),
         stateroot_limit,
         fail_on_dead_links: true,
+        canonical: false,
     }
 }
 
@@ -330,6 +496,7 @@ pub fn stream_graph<DB: Blockstore, T: Iterator<Item = Tipset> + Unpin>(
         seen: CidHashSet::default(),
         stateroot_limit: 0,
         fail_on_dead_links: false,
+        canonical: false,
     }
 }
 
@@ -341,6 +508,7 @@ impl<DB: Blockstore, T: Iterator<Item = Tipset> + Unpin> Stream for ChainStream<
         let mut this = self.project();
 
         let stateroot_limit = *this.stateroot_limit;
+        let canonical = *this.canonical;
         loop {
             while let Some(task) = this.dfs.front_mut() {
                 match task {
@@ -379,6 +547,25 @@ impl<DB: Blockstore, T: Iterator<Item = Tipset> + Unpin> Stream for ChainStream<
                         }
                         this.dfs.pop_front();
                     }
+                    IterateSorted(bfs) => {
+                        while let Some(cid) = bfs.next() {
+                            if should_save_block_to_snapshot(cid) && this.seen.insert(cid) {
+                                if let Some(data) = this.db.get(&cid)? {
+                                    if cid.codec() == fvm_ipld_encoding::DAG_CBOR {
+                                        let ipld: Ipld = from_slice_with_fallback(&data)?;
+                                        bfs.walk_next(ipld, this.seen);
+                                    }
+                                    return Poll::Ready(Some(Ok(Block { cid, data })));
+                                } else if *this.fail_on_dead_links {
+                                    return Poll::Ready(Some(Err(anyhow::anyhow!(
+                                        "missing key: {}",
+                                        cid
+                                    ))));
+                                }
+                            }
+                        }
+                        this.dfs.pop_front();
+                    }
                 }
             }
 
@@ -400,8 +587,7 @@ impl<DB: Blockstore, T: Iterator<Item = Tipset> + Unpin> Stream for ChainStream<
 
                         // Process block messages.
                         if block.epoch() > stateroot_limit {
-                            this.dfs
-                                .push_back(Iterate(DfsIter::from(*block.messages())));
+                            this.dfs.push_back(Task::walk(*block.messages(), canonical));
                         }
 
                         // Visit the block if it's within required depth. And a special case for `0`
@@ -410,7 +596,7 @@ impl<DB: Blockstore, T: Iterator<Item = Tipset> + Unpin> Stream for ChainStream<
                             // NOTE: In the original `walk_snapshot` implementation we walk the dag
                             // immediately. Which is what we do here as well, but using a queue.
                             this.dfs
-                                .push_back(Iterate(DfsIter::from(*block.state_root())));
+                                .push_back(Task::walk(*block.state_root(), canonical));
                         }
                     }
                 }
@@ -421,3 +607,80 @@ impl<DB: Blockstore, T: Iterator<Item = Tipset> + Unpin> Stream for ChainStream<
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MemoryDB;
+    use crate::utils::cid::CidCborExt;
+    use crate::utils::db::CborStoreExt;
+
+    #[test]
+    fn sorted_bfs_visits_levels_in_cid_order() {
+        let db = MemoryDB::default();
+        let leaves = ["a", "b", "c", "d"].map(|leaf| db.put_cbor_default(&leaf).unwrap());
+        let left = db.put_cbor_default(&vec![leaves[3], leaves[0]]).unwrap();
+        let right = db.put_cbor_default(&vec![leaves[2], leaves[1]]).unwrap();
+
+        let mut bfs = SortedBfs::new(db.put_cbor_default(&vec![right, left]).unwrap());
+        let mut visited = vec![];
+        while let Some(cid) = bfs.next() {
+            if let Some(data) = db.get(&cid).unwrap() {
+                bfs.walk_next(
+                    from_slice_with_fallback(&data).unwrap(),
+                    &CidHashSet::default(),
+                );
+            }
+            visited.push(cid);
+        }
+
+        let mut inner = vec![left, right];
+        inner.sort();
+        let mut leaves = leaves.to_vec();
+        leaves.sort();
+        assert_eq!(&visited[1..3], inner.as_slice());
+        assert_eq!(&visited[3..], leaves.as_slice());
+    }
+
+    #[tokio::test]
+    async fn recent_receipts_are_walked() {
+        use crate::blocks::TipsetKeys;
+        use crate::shim::address::Address;
+
+        let db = MemoryDB::default();
+        let mut parents = TipsetKeys::default();
+        let mut receipts = vec![];
+        let mut head = None;
+        for epoch in 0..4 {
+            let receipts_obj = format!("receipts {epoch}");
+            // The receipts of the head are missing.
+            let receipt = if epoch == 3 {
+                Cid::from_cbor_blake2b256(&receipts_obj).unwrap()
+            } else {
+                db.put_cbor_default(&receipts_obj).unwrap()
+            };
+            let header = BlockHeader::builder()
+                .miner_address(Address::new_id(0))
+                .epoch(epoch)
+                .parents(parents)
+                .message_receipts(receipt)
+                .build()
+                .unwrap();
+            db.put_cbor_default(&header).unwrap();
+            parents = TipsetKeys::from(vec![*header.cid()]);
+            receipts.push(receipt);
+            head = Some(Tipset::from(header));
+        }
+
+        let mut loaded = vec![];
+        walk_receipts(&head.unwrap(), 3, |cid| {
+            loaded.push(cid);
+            let block = db.get(&cid);
+            async move { block?.ok_or_else(|| anyhow::anyhow!("{cid} not found")) }
+        })
+        .await
+        .unwrap();
+        assert!(loaded.contains(&receipts[1]) && loaded.contains(&receipts[2]));
+        assert!(!loaded.contains(&receipts[0]));
+    }
+}
@@ -0,0 +1,299 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Signing of Ethereum messages with delegated keys, for the `personal_sign`
+//! and `eth_signTypedData` methods used by Ethereum wallets.
+//!
+//! Delegated keys sign the `keccak256` hash of the signed bytes, so the
+//! functions below return the bytes whose hash is signed by Ethereum wallets,
+//! see [`sign`](super::sign).
+
+use std::collections::BTreeSet;
+
+use crate::message::delegated::keccak256;
+use crate::shim::crypto::{Signature, SignatureType};
+use anyhow::{bail, ensure, Context as _};
+use num_bigint::{BigInt, Sign};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Returns the bytes signed by `personal_sign`, i.e. `data` prefixed as in
+/// `EIP-191`.
+pub fn personal_message(data: &[u8]) -> Vec<u8> {
+    let mut message = format!("\x19Ethereum Signed Message:\n{}", data.len()).into_bytes();
+    message.extend_from_slice(data);
+    message
+}
+
+/// Returns the signature of a delegated key in the Ethereum format, i.e.
+/// `r || s || v` with `v` of 27 or 28.
+pub fn eth_signature(signature: &Signature) -> anyhow::Result<[u8; 65]> {
+    ensure!(
+        signature.sig_type == SignatureType::Delegated,
+        "expected a delegated signature, got {:?}",
+        signature.sig_type
+    );
+    let mut bytes: [u8; 65] = signature
+        .bytes
+        .as_slice()
+        .try_into()
+        .context("invalid delegated signature length")?;
+    bytes[64] += 27;
+    Ok(bytes)
+}
+
+/// Field of a struct type of typed data.
+#[derive(Debug, Deserialize)]
+struct Field {
+    name: String,
+    #[serde(rename = "type")]
+    ty: String,
+}
+
+/// Typed data, as defined by `EIP-712`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypedData {
+    types: ahash::HashMap<String, Vec<Field>>,
+    primary_type: String,
+    domain: Value,
+    message: Value,
+}
+
+impl TypedData {
+    /// Returns the bytes signed by `eth_signTypedData`, i.e.
+    /// `0x19 || 0x01 || hashStruct(domain) || hashStruct(message)`.
+    pub fn signing_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = vec![0x19, 0x01];
+        bytes.extend(self.hash_struct("EIP712Domain", &self.domain)?);
+        bytes.extend(self.hash_struct(&self.primary_type, &self.message)?);
+        Ok(bytes)
+    }
+
+    fn fields(&self, ty: &str) -> anyhow::Result<&[Field]> {
+        self.types
+            .get(ty)
+            .map(Vec::as_slice)
+            .with_context(|| format!("unknown type {ty}"))
+    }
+
+    /// Adds the struct types referenced by `ty` to `deps`.
+    fn dependencies<'a>(&'a self, ty: &'a str, deps: &mut BTreeSet<&'a str>) -> anyhow::Result<()> {
+        let ty = ty.split('[').next().unwrap_or(ty);
+        if !self.types.contains_key(ty) || !deps.insert(ty) {
+            return Ok(());
+        }
+        for field in self.fields(ty)? {
+            self.dependencies(&field.ty, deps)?;
+        }
+        Ok(())
+    }
+
+    /// Encodes `ty` and the types it references, e.g.
+    /// `Mail(Person from,Person to,string contents)Person(string name)`.
+    fn encode_type(&self, ty: &str) -> anyhow::Result<String> {
+        let mut deps = BTreeSet::new();
+        self.dependencies(ty, &mut deps)?;
+        deps.remove(ty);
+        let mut encoded = String::new();
+        for ty in std::iter::once(ty).chain(deps) {
+            let fields = self
+                .fields(ty)?
+                .iter()
+                .map(|field| format!("{} {}", field.ty, field.name))
+                .collect::<Vec<_>>();
+            encoded.push_str(&format!("{ty}({})", fields.join(",")));
+        }
+        Ok(encoded)
+    }
+
+    fn hash_struct(&self, ty: &str, value: &Value) -> anyhow::Result<[u8; 32]> {
+        let mut encoded = keccak256(self.encode_type(ty)?.as_bytes()).to_vec();
+        for field in self.fields(ty)? {
+            let value = value.get(&field.name).unwrap_or(&Value::Null);
+            encoded.extend(self.encode_value(&field.ty, value)?);
+        }
+        Ok(keccak256(&encoded))
+    }
+
+    fn encode_value(&self, ty: &str, value: &Value) -> anyhow::Result<[u8; 32]> {
+        if let Some(item_ty) = ty.strip_suffix(']').and_then(|ty| ty.rsplit_once('[')) {
+            let items = value
+                .as_array()
+                .with_context(|| format!("expected an array of {}", item_ty.0))?;
+            let mut encoded = vec![];
+            for item in items {
+                encoded.extend(self.encode_value(item_ty.0, item)?);
+            }
+            return Ok(keccak256(&encoded));
+        }
+        if self.types.contains_key(ty) {
+            return self.hash_struct(ty, value);
+        }
+        let mut word = [0; 32];
+        match ty {
+            "string" => {
+                let s = value.as_str().context("expected a string")?;
+                return Ok(keccak256(s.as_bytes()));
+            }
+            "bytes" => return Ok(keccak256(&hex_bytes(value)?)),
+            "bool" => {
+                word[31] = value.as_bool().context("expected a boolean")? as u8;
+            }
+            "address" => {
+                let address = hex_bytes(value)?;
+                ensure!(address.len() == 20, "invalid address {value}");
+                word[12..].copy_from_slice(&address);
+            }
+            _ if ty.starts_with("bytes") => {
+                let bytes = hex_bytes(value)?;
+                ensure!(bytes.len() <= 32, "invalid {ty} {value}");
+                word[..bytes.len()].copy_from_slice(&bytes);
+            }
+            _ if ty.starts_with("uint") || ty.starts_with("int") => {
+                let (signed, bits) = match ty.strip_prefix("uint") {
+                    Some(bits) => (false, bits),
+                    None => (true, &ty[3..]),
+                };
+                let bits: u32 = match bits {
+                    "" => 256,
+                    bits => bits.parse().with_context(|| format!("unknown type {ty}"))?,
+                };
+                ensure!(
+                    bits > 0 && bits <= 256 && bits % 8 == 0,
+                    "unknown type {ty}"
+                );
+                let mut int = integer(value)?;
+                let (min, max) = match signed {
+                    true => (
+                        -(BigInt::from(1) << (bits - 1)),
+                        BigInt::from(1) << (bits - 1),
+                    ),
+                    false => (BigInt::from(0), BigInt::from(1) << bits),
+                };
+                ensure!(
+                    int >= min && int < max,
+                    "{value} is out of the range of {ty}"
+                );
+                if int.sign() == Sign::Minus {
+                    // Two's complement
+                    int += BigInt::from(1) << 256;
+                }
+                let (_, bytes) = int.to_bytes_be();
+                word[32 - bytes.len()..].copy_from_slice(&bytes);
+            }
+            _ => bail!("unknown type {ty}"),
+        }
+        Ok(word)
+    }
+}
+
+fn hex_bytes(value: &Value) -> anyhow::Result<Vec<u8>> {
+    let s = value.as_str().context("expected a hex string")?;
+    Ok(hex::decode(s.trim_start_matches("0x"))?)
+}
+
+/// Integers are JSON numbers, or decimal or `0x`-prefixed hex strings.
+fn integer(value: &Value) -> anyhow::Result<BigInt> {
+    let parsed = match value {
+        Value::Number(number) => number.to_string().parse().ok(),
+        Value::String(s) => match s.strip_prefix("0x") {
+            Some(digits) => BigInt::parse_bytes(digits.as_bytes(), 16),
+            None => s.parse().ok(),
+        },
+        _ => None,
+    };
+    parsed.with_context(|| format!("invalid integer {value}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_management::{generate_key, sign};
+    use serde_json::json;
+
+    /// Example of `EIP-712`.
+    fn mail() -> TypedData {
+        serde_json::from_value(json!({
+            "types": {
+                "EIP712Domain": [
+                    {"name": "name", "type": "string"},
+                    {"name": "version", "type": "string"},
+                    {"name": "chainId", "type": "uint256"},
+                    {"name": "verifyingContract", "type": "address"}
+                ],
+                "Person": [
+                    {"name": "name", "type": "string"},
+                    {"name": "wallet", "type": "address"}
+                ],
+                "Mail": [
+                    {"name": "from", "type": "Person"},
+                    {"name": "to", "type": "Person"},
+                    {"name": "contents", "type": "string"}
+                ]
+            },
+            "primaryType": "Mail",
+            "domain": {
+                "name": "Ether Mail",
+                "version": "1",
+                "chainId": 1,
+                "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+            },
+            "message": {
+                "from": {"name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"},
+                "to": {"name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"},
+                "contents": "Hello, Bob!"
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn typed_data_hash() {
+        let mail = mail();
+        assert_eq!(
+            mail.encode_type("Mail").unwrap(),
+            "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+        );
+        assert_eq!(
+            hex::encode(keccak256(&mail.signing_bytes().unwrap())),
+            "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+        );
+    }
+
+    #[test]
+    fn integers() {
+        let data = TypedData {
+            types: Default::default(),
+            primary_type: String::new(),
+            domain: Value::Null,
+            message: Value::Null,
+        };
+        let word = |ty, value| hex::encode(data.encode_value(ty, &value).unwrap());
+        assert_eq!(word("uint8", json!("0x10")), format!("{:064x}", 16));
+        assert_eq!(word("uint256", json!("16")), format!("{:064x}", 16));
+        assert_eq!(word("int256", json!(-1)), "f".repeat(64));
+        assert!(data.encode_value("uint256", &json!("x")).is_err());
+        // Values must fit in their type, and unsigned ones can't be negative.
+        assert!(data.encode_value("uint256", &json!(-1)).is_err());
+        assert!(data.encode_value("uint8", &json!(256)).is_err());
+        assert_eq!(word("int8", json!(-128)), "f".repeat(62) + "80");
+        assert!(data.encode_value("int8", &json!(128)).is_err());
+        assert!(data.encode_value("uint7", &json!(1)).is_err());
+    }
+
+    #[test]
+    fn personal_signatures_are_recoverable() {
+        let key = generate_key(SignatureType::Delegated).unwrap();
+        let message = personal_message(b"hello");
+        assert!(message.starts_with(b"\x19Ethereum Signed Message:\n5"));
+        let signature = sign(
+            SignatureType::Delegated,
+            key.key_info.private_key(),
+            &message,
+        )
+        .unwrap();
+        signature.verify(&message, &key.address).unwrap();
+        assert!(matches!(eth_signature(&signature).unwrap()[64], 27 | 28));
+    }
+}
@@ -430,6 +430,28 @@ impl EncryptedKeyStore {
     }
 }
 
+/// Encrypts `data` with a key derived from `passphrase`. The output has the
+/// format of the encrypted keystore, i.e. the salt is prepended.
+pub fn encrypt_with_passphrase(passphrase: &str, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let (salt, encryption_key) = EncryptedKeyStore::derive_key(passphrase, None)?;
+    let mut encrypted = salt.to_vec();
+    encrypted.extend(EncryptedKeyStore::encrypt(&encryption_key, data)?);
+    Ok(encrypted)
+}
+
+/// Decrypts data encrypted with [`encrypt_with_passphrase`].
+pub fn decrypt_with_passphrase(passphrase: &str, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    anyhow::ensure!(
+        data.len() >= RECOMMENDED_SALT_LEN + NONCE_SIZE,
+        "the encrypted data is truncated"
+    );
+    let (salt, data) = data.split_at(RECOMMENDED_SALT_LEN);
+    let mut prev_salt = [0; RECOMMENDED_SALT_LEN];
+    prev_salt.copy_from_slice(salt);
+    let (_, encryption_key) = EncryptedKeyStore::derive_key(passphrase, Some(prev_salt))?;
+    EncryptedKeyStore::decrypt(&encryption_key, data)
+}
+
 fn map_err_to_anyhow<T: Display>(e: T) -> anyhow::Error {
     anyhow::Error::msg(e.to_string())
 }
@@ -480,6 +502,17 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_passphrase_encryption() -> Result<()> {
+        let message = "foo is coming";
+        let ciphertext = encrypt_with_passphrase(PASSPHRASE, message.as_bytes())?;
+        let plaintext = decrypt_with_passphrase(PASSPHRASE, &ciphertext)?;
+        ensure!(plaintext == message.as_bytes());
+        ensure!(decrypt_with_passphrase("wrong", &ciphertext).is_err());
+        ensure!(decrypt_with_passphrase(PASSPHRASE, &ciphertext[..10]).is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_read_old_encrypted_keystore() -> Result<()> {
         let dir: PathBuf = "src/key_management/tests/keystore_encrypted_old".into();
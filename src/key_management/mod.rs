@@ -2,12 +2,16 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 mod errors;
+mod eth_signing;
 mod keystore;
+mod signer;
 mod wallet;
 mod wallet_helpers;
 
 pub use errors::*;
+pub use eth_signing::*;
 pub use keystore::*;
+pub use signer::*;
 pub use wallet::*;
 pub use wallet_helpers::*;
 #[cfg(test)]
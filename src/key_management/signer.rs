@@ -0,0 +1,377 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Signers of the wallet addresses.
+//!
+//! By default, an address is signed for with its private key from the
+//! [`KeyStore`]. Operators who keep their keys out of the node configure a
+//! [`RemoteSigner`] for the address instead: the bytes to sign are sent to an
+//! external service, and the signature it returns is checked against the
+//! address before it's used.
+
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::shim::address::{Address, Protocol, StrictAddress};
+use crate::shim::crypto::{Signature, SignatureType};
+use crate::utils::net::global_http_client;
+use ahash::HashMap;
+use anyhow::Context as _;
+use async_trait::async_trait;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
+use super::{errors::Error, try_find, wallet_helpers, Key, KeyStore};
+
+/// Time a remote signer is given to answer.
+const REMOTE_SIGNER_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[async_trait]
+pub trait Signer: Send + Sync {
+    fn sig_type(&self) -> SignatureType;
+
+    async fn sign(&self, msg: &[u8]) -> Result<Signature, Error>;
+}
+
+#[async_trait]
+impl Signer for Key {
+    fn sig_type(&self) -> SignatureType {
+        *self.key_info.key_type()
+    }
+
+    async fn sign(&self, msg: &[u8]) -> Result<Signature, Error> {
+        wallet_helpers::sign(self.sig_type(), self.key_info.private_key(), msg)
+    }
+}
+
+/// Configuration of the external signer of an address. Access tokens are read
+/// from the environment, so that they don't end up in configuration files.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+#[cfg_attr(test, derive(derive_quickcheck_arbitrary::Arbitrary))]
+pub enum RemoteSignerConfig {
+    /// Generic remote signing API. The signer is sent a `POST` request with
+    /// the JSON body `{"Address": .., "Type": .., "Data": ..}` and answers
+    /// `{"Data": ..}`, with the data base64-encoded.
+    Http {
+        address: String,
+        url: String,
+        /// Environment variable holding a bearer token, if the API needs one.
+        token_env: Option<String>,
+    },
+    /// HashiCorp Vault secrets engine with the transit API, signing with
+    /// `POST {url}/v1/{mount}/sign/{key}`. The engine has to support the key
+    /// type of the address, e.g. through a Filecoin plugin.
+    Vault {
+        address: String,
+        url: String,
+        mount: String,
+        key: String,
+        /// Environment variable holding the Vault token.
+        token_env: String,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+#[cfg_attr(test, derive(derive_quickcheck_arbitrary::Arbitrary))]
+pub struct WalletConfig {
+    /// External signers, by address. Their keys don't have to be in the
+    /// keystore.
+    pub remote_signers: Vec<RemoteSignerConfig>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct HttpSignRequest {
+    address: String,
+    #[serde(rename = "Type")]
+    sig_type: SignatureType,
+    data: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HttpSignResponse {
+    data: String,
+}
+
+#[derive(Debug, Serialize)]
+struct VaultSignRequest {
+    input: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct VaultSignResponse {
+    data: VaultSignature,
+}
+
+#[derive(Debug, Deserialize)]
+struct VaultSignature {
+    /// Prefixed with the key version, as in `vault:v1:<base64>`.
+    signature: String,
+}
+
+/// Signer of an address whose key is held by an external service.
+#[derive(Debug)]
+pub struct RemoteSigner {
+    address: Address,
+    sig_type: SignatureType,
+    config: RemoteSignerConfig,
+    token: Option<String>,
+    timeout: Duration,
+}
+
+impl RemoteSigner {
+    /// Creates the signer of `config`, with its access token read from the
+    /// environment.
+    pub fn from_env(config: &RemoteSignerConfig) -> anyhow::Result<Self> {
+        let token_env = match config {
+            RemoteSignerConfig::Http { token_env, .. } => token_env.as_ref(),
+            RemoteSignerConfig::Vault { token_env, .. } => Some(token_env),
+        };
+        let token = token_env
+            .map(|var| {
+                std::env::var(var).with_context(|| format!("{var} is not set for remote signer"))
+            })
+            .transpose()?;
+        Self::new(config, token)
+    }
+
+    pub fn new(config: &RemoteSignerConfig, token: Option<String>) -> anyhow::Result<Self> {
+        let address = match config {
+            RemoteSignerConfig::Http { address, .. }
+            | RemoteSignerConfig::Vault { address, .. } => address,
+        };
+        let address = StrictAddress::from_str(address)
+            .map(Address::from)
+            .with_context(|| format!("Invalid remote signer address {address}"))?;
+        let sig_type = match address.protocol() {
+            Protocol::BLS => SignatureType::Bls,
+            Protocol::Secp256k1 => SignatureType::Secp256k1,
+            Protocol::Delegated => SignatureType::Delegated,
+            _ => anyhow::bail!("{address} is not a key address"),
+        };
+        Ok(Self {
+            address,
+            sig_type,
+            config: config.clone(),
+            token,
+            timeout: REMOTE_SIGNER_TIMEOUT,
+        })
+    }
+
+    pub fn address(&self) -> &Address {
+        &self.address
+    }
+
+    async fn request(&self, msg: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let client = global_http_client();
+        let data = BASE64_STANDARD.encode(msg);
+        match &self.config {
+            RemoteSignerConfig::Http { url, .. } => {
+                let mut request = client.post(url).json(&HttpSignRequest {
+                    address: self.address.to_string(),
+                    sig_type: self.sig_type,
+                    data,
+                });
+                if let Some(token) = &self.token {
+                    request = request.bearer_auth(token);
+                }
+                let response: HttpSignResponse =
+                    request.send().await?.error_for_status()?.json().await?;
+                Ok(BASE64_STANDARD.decode(response.data)?)
+            }
+            RemoteSignerConfig::Vault {
+                url, mount, key, ..
+            } => {
+                let url = format!("{}/v1/{mount}/sign/{key}", url.trim_end_matches('/'));
+                let response: VaultSignResponse = client
+                    .post(url)
+                    .header("X-Vault-Token", self.token.as_deref().unwrap_or_default())
+                    .json(&VaultSignRequest { input: data })
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await?;
+                let signature = response.data.signature;
+                let encoded = signature.rsplit(':').next().unwrap_or(&signature);
+                Ok(BASE64_STANDARD.decode(encoded)?)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Signer for RemoteSigner {
+    fn sig_type(&self) -> SignatureType {
+        self.sig_type
+    }
+
+    async fn sign(&self, msg: &[u8]) -> Result<Signature, Error> {
+        let bytes = tokio::time::timeout(self.timeout, self.request(msg))
+            .await
+            .map_err(|_| {
+                Error::Other(format!(
+                    "remote signer of {} timed out after {:?}",
+                    self.address, self.timeout
+                ))
+            })?
+            .map_err(|e| Error::Other(format!("remote signer of {}: {e}", self.address)))?;
+        let signature = Signature::new(self.sig_type, bytes);
+        signature.verify(msg, &self.address).map_err(|e| {
+            Error::Other(format!(
+                "invalid signature from the remote signer of {}: {e}",
+                self.address
+            ))
+        })?;
+        Ok(signature)
+    }
+}
+
+/// The configured remote signers, by address.
+#[derive(Debug, Default)]
+pub struct RemoteSigners(HashMap<Address, Arc<RemoteSigner>>);
+
+impl RemoteSigners {
+    pub fn new(config: &WalletConfig) -> anyhow::Result<Self> {
+        config
+            .remote_signers
+            .iter()
+            .map(|config| {
+                let signer = RemoteSigner::from_env(config)?;
+                Ok((*signer.address(), Arc::new(signer)))
+            })
+            .collect::<anyhow::Result<_>>()
+            .map(Self)
+    }
+
+    /// Returns the signer of `addr`: its remote signer if one is configured,
+    /// its key in `keystore` otherwise.
+    pub fn find(&self, addr: &Address, keystore: &mut KeyStore) -> Result<Arc<dyn Signer>, Error> {
+        if let Some(signer) = self.0.get(addr) {
+            return Ok(signer.clone());
+        }
+        Ok(Arc::new(Key::try_from(try_find(addr, keystore)?)?))
+    }
+
+    /// Returns whether [`RemoteSigners::find`] has a signer for `addr`.
+    pub fn can_sign(&self, addr: &Address, keystore: &mut KeyStore) -> bool {
+        self.0.contains_key(addr) || try_find(addr, keystore).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_management::{generate_key, KeyStoreConfig};
+    use axum::{routing::post, Json, Router};
+
+    async fn serve(router: Router) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(router.into_make_service()),
+        );
+        url
+    }
+
+    fn signers(signer: RemoteSigner) -> RemoteSigners {
+        RemoteSigners(
+            [(*signer.address(), Arc::new(signer))]
+                .into_iter()
+                .collect(),
+        )
+    }
+
+    #[tokio::test]
+    async fn remote_signers_take_precedence_over_the_keystore() {
+        let key = generate_key(SignatureType::Secp256k1).unwrap();
+        let remote_key = key.clone();
+        let url = serve(Router::new().route(
+            "/v1/transit/sign/miner",
+            post(|headers: axum::http::HeaderMap, Json(request): Json<serde_json::Value>| async move {
+                assert_eq!(headers["X-Vault-Token"], "token");
+                let input = BASE64_STANDARD
+                    .decode(request["input"].as_str().unwrap())
+                    .unwrap();
+                let signature = remote_key.sign(&input).await.unwrap();
+                Json(serde_json::json!({
+                    "data": {
+                        "signature": format!("vault:v1:{}", BASE64_STANDARD.encode(signature.bytes)),
+                    },
+                }))
+            }),
+        ))
+        .await;
+        let config = RemoteSignerConfig::Vault {
+            address: key.address.to_string(),
+            url,
+            mount: "transit".into(),
+            key: "miner".into(),
+            token_env: "FOREST_TEST_VAULT_TOKEN".into(),
+        };
+        let signers = signers(RemoteSigner::new(&config, Some("token".into())).unwrap());
+
+        // The key isn't in the keystore.
+        let mut keystore = KeyStore::new(KeyStoreConfig::Memory).unwrap();
+        let signer = signers.find(&key.address, &mut keystore).unwrap();
+        assert_eq!(signer.sig_type(), SignatureType::Secp256k1);
+        let signature = signer.sign(b"message").await.unwrap();
+        signature.verify(b"message", &key.address).unwrap();
+    }
+
+    #[tokio::test]
+    async fn signatures_from_the_wrong_key_are_refused() {
+        let key = generate_key(SignatureType::Secp256k1).unwrap();
+        let other_key = generate_key(SignatureType::Secp256k1).unwrap();
+        let url = serve(Router::new().route(
+            "/",
+            post(|Json(request): Json<serde_json::Value>| async move {
+                let data = BASE64_STANDARD
+                    .decode(request["Data"].as_str().unwrap())
+                    .unwrap();
+                let signature = other_key.sign(&data).await.unwrap();
+                Json(serde_json::json!({ "Data": BASE64_STANDARD.encode(signature.bytes) }))
+            }),
+        ))
+        .await;
+        let config = RemoteSignerConfig::Http {
+            address: key.address.to_string(),
+            url,
+            token_env: None,
+        };
+        let signers = signers(RemoteSigner::new(&config, None).unwrap());
+
+        let mut keystore = KeyStore::new(KeyStoreConfig::Memory).unwrap();
+        let signer = signers.find(&key.address, &mut keystore).unwrap();
+        signer.sign(b"message").await.unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn slow_signers_time_out() {
+        let key = generate_key(SignatureType::Secp256k1).unwrap();
+        let url = serve(Router::new().route(
+            "/",
+            post(|| async {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Json(serde_json::json!({ "Data": "" }))
+            }),
+        ))
+        .await;
+        let config = RemoteSignerConfig::Http {
+            address: key.address.to_string(),
+            url,
+            token_env: None,
+        };
+        let mut signer = RemoteSigner::new(&config, None).unwrap();
+        signer.timeout = Duration::from_millis(100);
+
+        let err = signer.sign(b"message").await.unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{err}");
+    }
+}
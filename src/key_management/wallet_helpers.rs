@@ -19,15 +19,12 @@ pub fn to_public(sig_type: SignatureType, private_key: &[u8]) -> Result<Vec<u8>,
             .map_err(|err| Error::Other(err.to_string()))?
             .public_key()
             .as_bytes()),
-        SignatureType::Secp256k1 => {
+        SignatureType::Secp256k1 | SignatureType::Delegated => {
             let private_key = SecpPrivate::parse_slice(private_key)
                 .map_err(|err| Error::Other(err.to_string()))?;
             let public_key = SecpPublic::from_secret_key(&private_key);
             Ok(public_key.serialize().to_vec())
         }
-        SignatureType::Delegated => {
-            unimplemented!()
-        }
     }
 }
 
@@ -44,8 +41,16 @@ pub fn new_address(sig_type: SignatureType, public_key: &[u8]) -> Result<Address
                 Address::new_secp256k1(public_key).map_err(|err| Error::Other(err.to_string()))?;
             Ok(addr)
         }
+        // Delegated keys are Ethereum keys, whose address is the last 20 bytes
+        // of the `keccak256` hash of the uncompressed public key.
         SignatureType::Delegated => {
-            unimplemented!()
+            use crate::message::delegated::{keccak256, EAM_NAMESPACE};
+            let public_key = public_key
+                .strip_prefix(&[0x04])
+                .ok_or_else(|| Error::Other("expected an uncompressed public key".to_string()))?;
+            let addr = Address::new_delegated(EAM_NAMESPACE, &keccak256(public_key)[12..])
+                .map_err(|err| Error::Other(err.to_string()))?;
+            Ok(addr)
         }
     }
 }
@@ -75,8 +80,16 @@ pub fn sign(sig_type: SignatureType, private_key: &[u8], msg: &[u8]) -> Result<S
             let crypto_sig = Signature::new_secp256k1(new_bytes.to_vec());
             Ok(crypto_sig)
         }
+        // Delegated signatures cover the `keccak256` hash of the message, see
+        // `crate::message::delegated`.
         SignatureType::Delegated => {
-            unimplemented!()
+            let priv_key = SecpPrivate::parse_slice(private_key)
+                .map_err(|err| Error::Other(err.to_string()))?;
+            let message = SecpMessage::parse(&crate::message::delegated::keccak256(msg));
+            let (sig, recovery_id) = libsecp256k1::sign(&message, &priv_key);
+            let mut new_bytes = sig.serialize().to_vec();
+            new_bytes.push(recovery_id.serialize());
+            Ok(Signature::new_delegated(new_bytes))
         }
     }
 }
@@ -89,12 +102,9 @@ pub fn generate(sig_type: SignatureType) -> Result<Vec<u8>, Error> {
             let key = BlsPrivate::generate(rng);
             Ok(key.as_bytes())
         }
-        SignatureType::Secp256k1 => {
+        SignatureType::Secp256k1 | SignatureType::Delegated => {
             let key = SecpPrivate::random(rng);
             Ok(key.serialize().to_vec())
         }
-        SignatureType::Delegated => {
-            unimplemented!()
-        }
     }
 }
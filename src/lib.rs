@@ -39,6 +39,7 @@ mod message_pool;
 mod metrics;
 mod r#mod;
 mod networks;
+pub mod node;
 mod rpc;
 mod rpc_api;
 mod rpc_client;
@@ -96,3 +97,6 @@ pub use key_management::{
     KeyStore, KeyStoreConfig, ENCRYPTED_KEYSTORE_NAME, FOREST_KEYSTORE_PHRASE_ENV, KEYSTORE_NAME,
 };
 pub use tool::main::main as forest_tool_main;
+
+// Stable building blocks for downstream crates embedding Forest
+pub use utils::db::overlay::OverlayBlockstore;
@@ -0,0 +1,170 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Policy on the client versions of the peers, parsed from the agent version
+//! they announce with the `identify` protocol, e.g.
+//! `lotus-1.23.3+mainnet+git.7b5c1c4`. During coordinated upgrades, peers that
+//! run a client version known to be buggy can be deprioritized for requests,
+//! or refused.
+
+use std::fmt::Display;
+
+use ahash::HashMap;
+use serde::{Deserialize, Serialize};
+
+/// What happens to the peers that run a client version below the minimum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(test, derive(derive_quickcheck_arbitrary::Arbitrary))]
+pub enum AgentAction {
+    /// Send requests to other peers first.
+    Deprioritize,
+    /// Disconnect and ban the peer.
+    Refuse,
+}
+
+/// Minimum version of a client, e.g.
+/// `{ client = "lotus", min_version = "1.23.3", action = "refuse" }`. Peers
+/// whose version can't be parsed are left alone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(test, derive(derive_quickcheck_arbitrary::Arbitrary))]
+pub struct AgentRule {
+    pub client: String,
+    pub min_version: String,
+    pub action: AgentAction,
+}
+
+/// Client and version of a peer.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AgentVersion {
+    pub client: String,
+    /// Version without the build metadata.
+    pub version: Option<semver::Version>,
+}
+
+impl AgentVersion {
+    /// Parses agents of the form `<client>-<version>`, `<client>/<version>` or
+    /// `<client>/v<version>`. The client may contain dashes.
+    pub fn parse(agent: &str) -> Self {
+        let separators = agent.char_indices().filter(|(_, c)| matches!(c, '-' | '/'));
+        for (i, _) in separators {
+            let version = &agent[i + 1..];
+            let version = version.strip_prefix('v').unwrap_or(version);
+            if !version.starts_with(|c: char| c.is_ascii_digit()) {
+                continue;
+            }
+            let core = version.split(&['+', '/'][..]).next().unwrap_or_default();
+            return AgentVersion {
+                client: agent[..i].to_lowercase(),
+                version: semver::Version::parse(core).ok(),
+            };
+        }
+        AgentVersion {
+            client: agent.to_lowercase(),
+            version: None,
+        }
+    }
+
+    /// Returns the action of the first rule that the agent violates.
+    pub fn check(&self, rules: &[AgentRule]) -> Option<AgentAction> {
+        let version = self.version.as_ref()?;
+        rules
+            .iter()
+            .filter(|rule| rule.client.eq_ignore_ascii_case(&self.client))
+            .find(|rule| match semver::Version::parse(&rule.min_version) {
+                Ok(min_version) => *version < min_version,
+                Err(_) => false,
+            })
+            .map(|rule| rule.action)
+    }
+}
+
+impl Display for AgentVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{}-{version}", self.client),
+            None => write!(f, "{}", self.client),
+        }
+    }
+}
+
+/// Returns the number of peers running each client version, most common
+/// first.
+pub fn version_distribution<'a>(
+    agents: impl IntoIterator<Item = &'a str>,
+) -> Vec<(AgentVersion, usize)> {
+    let mut counts: HashMap<AgentVersion, usize> = HashMap::default();
+    for agent in agents {
+        *counts.entry(AgentVersion::parse(agent)).or_default() += 1;
+    }
+    let mut distribution: Vec<_> = counts.into_iter().collect();
+    distribution.sort_by(|(a, a_peers), (b, b_peers)| {
+        b_peers
+            .cmp(a_peers)
+            .then_with(|| a.to_string().cmp(&b.to_string()))
+    });
+    distribution
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_agents() {
+        let parsed = |agent| AgentVersion::parse(agent).to_string();
+        assert_eq!(parsed("lotus-1.23.3+mainnet+git.7b5c1c4"), "lotus-1.23.3");
+        assert_eq!(parsed("forest-0.12.1+git.29a5d37"), "forest-0.12.1");
+        assert_eq!(parsed("go-ipfs/0.18.1/675f8bd"), "go-ipfs-0.18.1");
+        assert_eq!(parsed("venus/v1.13.0"), "venus-1.13.0");
+        assert_eq!(parsed("lotus-1.24.0-rc1+calibnet"), "lotus-1.24.0-rc1");
+        assert_eq!(parsed("boost"), "boost");
+    }
+
+    #[test]
+    fn check_rules() {
+        let rules = [
+            AgentRule {
+                client: "lotus".into(),
+                min_version: "1.23.3".into(),
+                action: AgentAction::Refuse,
+            },
+            AgentRule {
+                client: "lotus".into(),
+                min_version: "1.24.0".into(),
+                action: AgentAction::Deprioritize,
+            },
+        ];
+        let check = |agent| AgentVersion::parse(agent).check(&rules);
+        assert_eq!(check("lotus-1.23.2+mainnet"), Some(AgentAction::Refuse));
+        assert_eq!(
+            check("lotus-1.23.3+mainnet"),
+            Some(AgentAction::Deprioritize)
+        );
+        // Pre-releases precede their release.
+        assert_eq!(
+            check("lotus-1.24.0-rc1+mainnet"),
+            Some(AgentAction::Deprioritize)
+        );
+        assert_eq!(check("lotus-1.24.0+mainnet"), None);
+        assert_eq!(check("forest-0.1.0"), None);
+        assert_eq!(check("lotus"), None);
+    }
+
+    #[test]
+    fn distribution() {
+        let distribution = version_distribution([
+            "lotus-1.23.3+mainnet+git.1",
+            "forest-0.12.1",
+            "lotus-1.23.3+mainnet+git.2",
+        ]);
+        let distribution: Vec<_> = distribution
+            .iter()
+            .map(|(agent, peers)| (agent.to_string(), *peers))
+            .collect();
+        assert_eq!(
+            distribution,
+            [("lotus-1.23.3".into(), 2), ("forest-0.12.1".into(), 1)]
+        );
+    }
+}
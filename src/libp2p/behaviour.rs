@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 use crate::libp2p_bitswap::BitswapBehaviour;
+use crate::networks::ChainConfig;
 use crate::utils::{encoding::blake2b_256, version::FOREST_VERSION_STRING};
 use ahash::{HashMap, HashSet};
 use libp2p::{
@@ -25,6 +26,7 @@ use crate::libp2p::{
     config::Libp2pConfig,
     discovery::{DiscoveryBehaviour, DiscoveryConfig},
     gossip_params::{build_peer_score_params, build_peer_score_threshold},
+    graphsync::{graphsync_behaviour, GraphSyncBehaviour},
     hello::HelloBehaviour,
 };
 
@@ -42,6 +44,7 @@ pub(in crate::libp2p) struct ForestBehaviour {
     pub(super) hello: HelloBehaviour,
     pub(super) chain_exchange: ChainExchangeBehaviour,
     pub(super) bitswap: BitswapBehaviour,
+    pub(super) graphsync: GraphSyncBehaviour,
 }
 
 impl Recorder<ForestBehaviourEvent> for Metrics {
@@ -59,6 +62,7 @@ impl ForestBehaviour {
     pub fn new(
         local_key: &Keypair,
         config: &Libp2pConfig,
+        chain_config: &ChainConfig,
         network_name: &str,
     ) -> anyhow::Result<Self> {
         let mut gs_config_builder = gossipsub::ConfigBuilder::default();
@@ -78,7 +82,7 @@ impl ForestBehaviour {
 
         gossipsub
             .with_peer_score(
-                build_peer_score_params(network_name),
+                build_peer_score_params(chain_config, network_name),
                 build_peer_score_threshold(),
             )
             .unwrap();
@@ -91,7 +95,8 @@ impl ForestBehaviour {
                 "/chain/ipfs/bitswap",
             ],
             Default::default(),
-        );
+        )
+        .with_server_limits(config.bitswap_server_limits);
         if let Err(err) = crate::libp2p_bitswap::register_metrics(prometheus::default_registry()) {
             warn!("Fail to register prometheus metrics for libp2p_bitswap: {err}");
         }
@@ -127,6 +132,7 @@ impl ForestBehaviour {
             bitswap,
             hello: HelloBehaviour::default(),
             chain_exchange: ChainExchangeBehaviour::default(),
+            graphsync: graphsync_behaviour(&config.graphsync_server_limits),
         })
     }
 
@@ -149,6 +155,11 @@ impl ForestBehaviour {
         self.gossipsub.subscribe(topic)
     }
 
+    /// Returns the number of peers in the mesh of a gossip topic.
+    pub fn mesh_peer_count(&self, topic: &Topic) -> usize {
+        self.gossipsub.mesh_peers(&topic.hash()).count()
+    }
+
     /// Returns a set of peer ids
     pub fn peers(&self) -> &HashSet<PeerId> {
         self.discovery.peers()
@@ -14,6 +14,9 @@ use serde_tuple::{self, Deserialize_tuple, Serialize_tuple};
 pub const HEADERS: u64 = 0b01;
 /// `ChainExchange` Filecoin messages set bit.
 pub const MESSAGES: u64 = 0b10;
+/// Maximum number of tipsets that can be requested at once. Matches the fork
+/// length threshold of Lotus.
+pub const MAX_REQUEST_LENGTH: u64 = 900;
 
 /// The payload that gets sent to another node to request for blocks and
 /// messages.
@@ -38,6 +41,21 @@ impl ChainExchangeRequest {
     pub fn include_messages(&self) -> bool {
         self.options & MESSAGES > 0
     }
+
+    /// Checks that the request asks for headers, messages or both, and for a
+    /// supported number of tipsets.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.options == 0 || self.options & !(HEADERS | MESSAGES) != 0 {
+            return Err(format!("Invalid request options: {:#b}", self.options));
+        }
+        if self.request_len == 0 || self.request_len > MAX_REQUEST_LENGTH {
+            return Err(format!("Invalid request length: {}", self.request_len));
+        }
+        if self.start.is_empty() {
+            return Err("Request has no start tipset".into());
+        }
+        Ok(())
+    }
 }
 
 /// Status codes of a `chain_exchange` response.
@@ -258,6 +276,22 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn request_validation() {
+        let request = |options, request_len| ChainExchangeRequest {
+            start: vec![Cid::default()],
+            request_len,
+            options,
+        };
+        assert!(request(HEADERS, 1).validate().is_ok());
+        assert!(request(MESSAGES, MAX_REQUEST_LENGTH).validate().is_ok());
+        assert!(request(HEADERS | MESSAGES, 10).validate().is_ok());
+        assert!(request(0, 10).validate().is_err());
+        assert!(request(0b100, 10).validate().is_err());
+        assert!(request(HEADERS, 0).validate().is_err());
+        assert!(request(HEADERS, MAX_REQUEST_LENGTH + 1).validate().is_err());
+    }
+
     #[quickcheck]
     fn chain_exchange_response_status_roundtrip(status: ChainExchangeResponseStatus) {
         let serialized = serde_json::to_string(&status).unwrap();
@@ -21,6 +21,15 @@ pub fn make_chain_exchange_response<DB>(
 where
     DB: Blockstore + Send + Sync + 'static,
 {
+    if let Err(message) = request.validate() {
+        debug!("Invalid chain exchange request: {message}");
+        return ChainExchangeResponse {
+            chain: vec![],
+            status: ChainExchangeResponseStatus::BadRequest,
+            message,
+        };
+    }
+
     let mut response_chain: Vec<TipsetBundle> = Vec::with_capacity(request.request_len as usize);
 
     let mut curr_tipset_cids = request.start.clone();
@@ -238,4 +247,61 @@ mod tests {
         assert_eq!(ts_38_msgs.secp_msg_includes[1].len(), 1);
         assert_eq!(ts_38_msgs.bls_msg_includes[1].len(), 11);
     }
+
+    fn chain_store(db: Arc<MemoryDB>) -> ChainStore<MemoryDB> {
+        let gen_block = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .build()
+            .unwrap();
+        ChainStore::new(db.clone(), db, Arc::new(ChainConfig::default()), gen_block).unwrap()
+    }
+
+    #[tokio::test]
+    async fn headers_only_and_messages_only() {
+        let (cids, db) = populate_db().await;
+        let cs = chain_store(db);
+
+        let headers = make_chain_exchange_response(
+            &cs,
+            &ChainExchangeRequest {
+                start: cids.clone(),
+                request_len: 2,
+                options: HEADERS,
+            },
+        );
+        assert_eq!(headers.status, ChainExchangeResponseStatus::Success);
+        assert!(headers
+            .chain
+            .iter()
+            .all(|b| !b.blocks.is_empty() && b.messages.is_none()));
+
+        let messages = make_chain_exchange_response(
+            &cs,
+            &ChainExchangeRequest {
+                start: cids,
+                request_len: 2,
+                options: MESSAGES,
+            },
+        );
+        assert_eq!(messages.status, ChainExchangeResponseStatus::Success);
+        assert!(messages
+            .chain
+            .iter()
+            .all(|b| b.blocks.is_empty() && b.messages.is_some()));
+    }
+
+    #[tokio::test]
+    async fn bad_request() {
+        let (cids, db) = populate_db().await;
+        let response = make_chain_exchange_response(
+            &chain_store(db),
+            &ChainExchangeRequest {
+                start: cids,
+                request_len: 2,
+                options: 0,
+            },
+        );
+        assert_eq!(response.status, ChainExchangeResponseStatus::BadRequest);
+        assert!(response.chain.is_empty());
+    }
 }
@@ -1,6 +1,9 @@
 // Copyright 2019-2023 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+use crate::libp2p::agent_policy::AgentRule;
+use crate::libp2p::graphsync::GraphSyncServerLimits;
+use crate::libp2p_bitswap::BitswapServerLimits;
 use libp2p::Multiaddr;
 use serde::{Deserialize, Serialize};
 #[cfg(test)]
@@ -33,6 +36,18 @@ pub struct Libp2pConfig {
     pub kademlia: bool,
     /// Target peer count.
     pub target_peer_count: u32,
+    /// Limits of the blocks served to peers over `bitswap`.
+    pub bitswap_server_limits: BitswapServerLimits,
+    /// Limits of the subgraphs served to peers over `GraphSync`.
+    pub graphsync_server_limits: GraphSyncServerLimits,
+    /// Number of recent messages kept, decoded, for each gossip topic, for
+    /// inspection with `forest-cli net pubsub-stats`. Zero disables sampling.
+    #[cfg_attr(test, arbitrary(gen(|g| u32::arbitrary(g) as _)))]
+    pub gossip_samples: usize,
+    /// Minimum client versions of the peers, from the agent versions they
+    /// announce. Peers below a minimum are deprioritized for requests or
+    /// refused.
+    pub agent_rules: Vec<AgentRule>,
 }
 
 impl Default for Libp2pConfig {
@@ -43,6 +58,10 @@ impl Default for Libp2pConfig {
             mdns: false,
             kademlia: true,
             target_peer_count: 75,
+            bitswap_server_limits: Default::default(),
+            graphsync_server_limits: Default::default(),
+            gossip_samples: 0,
+            agent_rules: vec![],
         }
     }
 }
@@ -7,7 +7,8 @@ use libp2p::gossipsub::{
     score_parameter_decay, IdentTopic, PeerScoreParams, PeerScoreThresholds, TopicScoreParams,
 };
 
-use crate::libp2p::{PUBSUB_BLOCK_STR, PUBSUB_MSG_STR};
+use crate::libp2p::{message_topics, PUBSUB_BLOCK_STR};
+use crate::networks::ChainConfig;
 
 // All these parameters are copied from what Lotus has set for their Topic
 // scores. They are currently unused because enabling them causes GossipSub
@@ -78,13 +79,17 @@ fn build_block_topic_config() -> TopicScoreParams {
     }
 }
 
-pub(in crate::libp2p) fn build_peer_score_params(network_name: &str) -> PeerScoreParams {
+pub(in crate::libp2p) fn build_peer_score_params(
+    chain_config: &ChainConfig,
+    network_name: &str,
+) -> PeerScoreParams {
     #[allow(clippy::disallowed_types)]
     let mut psp_topics = std::collections::HashMap::new();
 
-    // msg topic
-    let msg_topic = IdentTopic::new(format!("{PUBSUB_MSG_STR}/{network_name}"));
-    psp_topics.insert(msg_topic.hash(), build_msg_topic_config());
+    // msg topics
+    for msg_topic in message_topics(chain_config, network_name) {
+        psp_topics.insert(msg_topic.hash(), build_msg_topic_config());
+    }
     // block topic
     let block_topic = IdentTopic::new(format!("{PUBSUB_BLOCK_STR}/{network_name}"));
     psp_topics.insert(block_topic.hash(), build_block_topic_config());
@@ -0,0 +1,174 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Per topic statistics of the `GossipSub` messages seen by the node. Totals
+//! are kept in the [`metrics`](super::metrics), this only tracks the recent
+//! arrivals and, in debug mode, samples of the recent messages.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use super::metrics::{values, GOSSIP_MESSAGE_TOTAL, GOSSIP_PUBLISHED_TOTAL, GOSSIP_REJECTED_TOTAL};
+use super::{PeerId, PubsubMessage};
+use crate::lotus_json::LotusJson;
+use crate::rpc_api::net_api::{PubsubSample, PubsubTopicStats};
+use ahash::HashMap;
+
+/// Window of the reported message rates.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default)]
+struct TopicStats {
+    arrivals: VecDeque<Instant>,
+    samples: VecDeque<PubsubSample>,
+}
+
+impl TopicStats {
+    fn prune(&mut self, now: Instant) {
+        while let Some(arrival) = self.arrivals.front() {
+            if now.duration_since(*arrival) <= RATE_WINDOW {
+                break;
+            }
+            self.arrivals.pop_front();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(in crate::libp2p) struct GossipStats {
+    /// Number of recent messages kept for each topic, zero disables sampling.
+    sample_size: usize,
+    topics: HashMap<&'static str, TopicStats>,
+}
+
+impl GossipStats {
+    pub fn new(sample_size: usize) -> Self {
+        Self {
+            sample_size,
+            topics: Default::default(),
+        }
+    }
+
+    /// Records a message received on `topic`, which is one of the topics
+    /// without the network name suffix. `message` is `None` for messages that
+    /// could not be decoded.
+    pub fn record_received(
+        &mut self,
+        topic: &'static str,
+        source: PeerId,
+        size: usize,
+        message: Option<&PubsubMessage>,
+        now: Instant,
+    ) {
+        GOSSIP_MESSAGE_TOTAL.with_label_values(&[topic]).inc();
+        if message.is_none() {
+            GOSSIP_REJECTED_TOTAL
+                .with_label_values(&[topic, values::UNDECODABLE])
+                .inc();
+        }
+
+        let stats = self.topics.entry(topic).or_default();
+        stats.prune(now);
+        stats.arrivals.push_back(now);
+        if self.sample_size == 0 {
+            return;
+        }
+        if stats.samples.len() == self.sample_size {
+            stats.samples.pop_front();
+        }
+        let message = match message {
+            Some(PubsubMessage::Block(block)) => serde_json::to_value(LotusJson(block.clone())),
+            Some(PubsubMessage::Message(message)) => {
+                serde_json::to_value(LotusJson(message.clone()))
+            }
+            None => Ok(serde_json::Value::Null),
+        };
+        stats.samples.push_back(PubsubSample {
+            source: source.to_string(),
+            received: chrono::Utc::now(),
+            size,
+            message: message.unwrap_or_default(),
+        });
+    }
+
+    pub fn record_published(&self, topic: &'static str) {
+        GOSSIP_PUBLISHED_TOTAL.with_label_values(&[topic]).inc();
+    }
+
+    pub fn topic_stats(
+        &mut self,
+        topic: &'static str,
+        mesh_peers: usize,
+        now: Instant,
+    ) -> PubsubTopicStats {
+        let stats = self.topics.entry(topic).or_default();
+        stats.prune(now);
+        PubsubTopicStats {
+            topic: topic.into(),
+            mesh_peers,
+            received: GOSSIP_MESSAGE_TOTAL.with_label_values(&[topic]).get(),
+            published: GOSSIP_PUBLISHED_TOTAL.with_label_values(&[topic]).get(),
+            rejected: values::GOSSIP_REJECT_REASONS
+                .iter()
+                .map(|reason| {
+                    let count = GOSSIP_REJECTED_TOTAL
+                        .with_label_values(&[topic, reason])
+                        .get();
+                    (reason.to_string(), count)
+                })
+                .collect(),
+            received_last_minute: stats.arrivals.len() as u64,
+            samples: stats.samples.iter().cloned().collect(),
+        }
+    }
+}
+
+/// Records a gossip message that was decoded but rejected by the chain or the
+/// message pool.
+pub fn record_gossip_rejected(topic: &'static str) {
+    GOSSIP_REJECTED_TOTAL
+        .with_label_values(&[topic, values::INVALID])
+        .inc();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::SignedMessage;
+    use crate::shim::{crypto::Signature, message::Message};
+
+    #[test]
+    fn topic_rates_and_samples() {
+        // The metrics are global, so the test uses a topic of its own.
+        const TOPIC: &str = "/test/gossip_stats";
+        let mut stats = GossipStats::new(2);
+        let start = Instant::now();
+        let source = PeerId::random();
+        let message = PubsubMessage::Message(SignedMessage {
+            message: Message::default(),
+            signature: Signature::new_secp256k1(vec![0; 65]),
+        });
+        for offset in [0, 10, 70] {
+            stats.record_received(
+                TOPIC,
+                source,
+                100,
+                Some(&message),
+                start + Duration::from_secs(offset),
+            );
+        }
+        stats.record_received(TOPIC, source, 5, None, start + Duration::from_secs(75));
+
+        let topic_stats = stats.topic_stats(TOPIC, 3, start + Duration::from_secs(80));
+        assert_eq!(topic_stats.mesh_peers, 3);
+        assert_eq!(topic_stats.received, 4);
+        assert_eq!(topic_stats.rejected[values::UNDECODABLE], 1);
+        assert_eq!(topic_stats.rejected[values::INVALID], 0);
+        // The first two messages are older than the rate window.
+        assert_eq!(topic_stats.received_last_minute, 2);
+        // Only the last two messages are sampled.
+        assert_eq!(topic_stats.samples.len(), 2);
+        assert!(topic_stats.samples[0].message.is_object());
+        assert!(topic_stats.samples[1].message.is_null());
+    }
+}
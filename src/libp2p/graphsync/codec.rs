@@ -0,0 +1,72 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::io;
+
+use async_trait::async_trait;
+use futures::prelude::*;
+use libp2p::{core::upgrade, request_response};
+
+use super::*;
+
+/// Maximum size of a message, as in `go-graphsync`.
+const MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+/// Every `GraphSync` message is sent on a new stream, which is closed without
+/// a response, as in `bitswap`.
+#[derive(Debug, Clone, Default)]
+pub struct GraphSyncCodec;
+
+#[async_trait]
+impl request_response::Codec for GraphSyncCodec {
+    type Protocol = &'static str;
+    type Request = GraphSyncMessage;
+    type Response = ();
+
+    async fn read_request<T>(&mut self, _: &Self::Protocol, io: &mut T) -> io::Result<Self::Request>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let data = upgrade::read_length_prefixed(io, MAX_MESSAGE_SIZE).await?;
+        let root: GraphSyncMessageRoot = fvm_ipld_encoding::from_slice(&data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(root.gs2)
+    }
+
+    async fn read_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        _: &mut T,
+    ) -> io::Result<Self::Response>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        Ok(())
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        message: Self::Request,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let data = fvm_ipld_encoding::to_vec(&GraphSyncMessageRoot { gs2: message })
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        upgrade::write_length_prefixed(io, data).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        _: &mut T,
+        _: Self::Response,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        Ok(())
+    }
+}
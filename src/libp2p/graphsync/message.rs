@@ -0,0 +1,128 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::collections::BTreeMap;
+
+use cid::Cid;
+use fvm_ipld_encoding::strict_bytes;
+use serde::{Deserialize, Serialize};
+use serde_tuple::{Deserialize_tuple, Serialize_tuple};
+use unsigned_varint::encode as varint_encode;
+
+use crate::ipld::Ipld;
+
+/// `GraphSync` v2 message, see the
+/// [schema](https://github.com/ipfs/go-graphsync/blob/main/message/ipldbind/schema.ipldsch).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GraphSyncMessage {
+    #[serde(rename = "req", default, skip_serializing_if = "Vec::is_empty")]
+    pub requests: Vec<GraphSyncRequest>,
+    #[serde(rename = "rsp", default, skip_serializing_if = "Vec::is_empty")]
+    pub responses: Vec<GraphSyncResponse>,
+    #[serde(rename = "blk", default, skip_serializing_if = "Vec::is_empty")]
+    pub blocks: Vec<GraphSyncBlock>,
+}
+
+/// Root of the encoded [`GraphSyncMessage`], which is versioned by its single
+/// key.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(in crate::libp2p::graphsync) struct GraphSyncMessageRoot {
+    pub gs2: GraphSyncMessage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GraphSyncRequestType {
+    #[serde(rename = "n")]
+    New,
+    #[serde(rename = "c")]
+    Cancel,
+    #[serde(rename = "u")]
+    Update,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphSyncRequest {
+    /// UUID of the request, chosen by the requester.
+    #[serde(with = "strict_bytes")]
+    pub id: Vec<u8>,
+    #[serde(rename = "type")]
+    pub ty: GraphSyncRequestType,
+    #[serde(rename = "pri", default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<Cid>,
+    /// IPLD selector, kept undecoded so that requests with unsupported
+    /// selectors can be rejected rather than dropped.
+    #[serde(rename = "sel", default, skip_serializing_if = "Option::is_none")]
+    pub selector: Option<Ipld>,
+    #[serde(rename = "ext", default, skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<BTreeMap<String, Ipld>>,
+}
+
+/// Status codes of the [`GraphSyncResponse`]s.
+#[cfg(any(test, feature = "graphsync-server"))]
+pub mod status {
+    pub const PARTIAL_RESPONSE: i32 = 14;
+    pub const REQUEST_COMPLETED_FULL: i32 = 20;
+    pub const REQUEST_COMPLETED_PARTIAL: i32 = 21;
+    pub const REQUEST_REJECTED: i32 = 30;
+    pub const REQUEST_FAILED_BUSY: i32 = 31;
+    pub const REQUEST_FAILED_UNKNOWN: i32 = 32;
+    pub const REQUEST_FAILED_CONTENT_NOT_FOUND: i32 = 34;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphSyncResponse {
+    #[serde(rename = "reqid", with = "strict_bytes")]
+    pub id: Vec<u8>,
+    #[serde(rename = "stat")]
+    pub status: i32,
+    #[serde(rename = "meta", default, skip_serializing_if = "Vec::is_empty")]
+    pub metadata: Vec<GraphSyncLinkMetadata>,
+    #[serde(rename = "ext", default, skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<BTreeMap<String, Ipld>>,
+}
+
+/// What the responder did with a link of the traversal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GraphSyncLinkAction {
+    /// The block is in the message, or in an earlier one.
+    #[serde(rename = "p")]
+    Present,
+    /// The block was already sent for this request.
+    #[serde(rename = "d")]
+    DuplicateNotSent,
+    /// The responder doesn't have the block.
+    #[serde(rename = "m")]
+    Missing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct GraphSyncLinkMetadata {
+    pub link: Cid,
+    pub action: GraphSyncLinkAction,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct GraphSyncBlock {
+    /// Version, codec and multihash type and length of the CID of the block.
+    #[serde(with = "strict_bytes")]
+    pub prefix: Vec<u8>,
+    #[serde(with = "strict_bytes")]
+    pub data: Vec<u8>,
+}
+
+impl GraphSyncBlock {
+    pub fn new(cid: &Cid, data: Vec<u8>) -> Self {
+        let mut prefix = vec![];
+        for n in [
+            u64::from(cid.version()),
+            cid.codec(),
+            cid.hash().code(),
+            cid.hash().size().into(),
+        ] {
+            prefix.extend_from_slice(varint_encode::u64(n, &mut varint_encode::u64_buffer()));
+        }
+        Self { prefix, data }
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! `GraphSync` ([SPEC](https://github.com/ipfs/go-graphsync/blob/main/docs/architecture.md))
+//! server, for peers that pull IPLD subgraphs, e.g. state subtrees, with
+//! selectors rather than block by block over `bitswap`. The server is opt-in:
+//! it is only built with the `graphsync-server` feature, and only serves
+//! requests if enabled in the configuration.
+
+mod codec;
+mod message;
+#[cfg(any(test, feature = "graphsync-server"))]
+mod server;
+
+use libp2p::request_response::{self, ProtocolSupport};
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(any(test, feature = "graphsync-server")))]
+pub use self::disabled::*;
+#[cfg(any(test, feature = "graphsync-server"))]
+pub use self::server::*;
+pub use self::{codec::*, message::*};
+
+/// Libp2p protocol name for `GraphSync`.
+pub const GRAPHSYNC_PROTOCOL_NAME: &str = "/ipfs/graphsync/2.0.0";
+
+pub type GraphSyncBehaviour = request_response::Behaviour<GraphSyncCodec>;
+
+/// Creates the `GraphSync` behaviour. Inbound streams are only accepted if
+/// the server is built and enabled.
+pub fn graphsync_behaviour(limits: &GraphSyncServerLimits) -> GraphSyncBehaviour {
+    let support = match limits.enabled && cfg!(any(test, feature = "graphsync-server")) {
+        true => ProtocolSupport::Full,
+        false => ProtocolSupport::Outbound,
+    };
+    request_response::Behaviour::new([(GRAPHSYNC_PROTOCOL_NAME, support)], Default::default())
+}
+
+/// Limits of the `GraphSync` server. A limit of zero disables it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+#[cfg_attr(test, derive(derive_quickcheck_arbitrary::Arbitrary))]
+pub struct GraphSyncServerLimits {
+    /// Serve the requests of peers. Requests are still sent if disabled. The
+    /// server is only available if Forest is built with the
+    /// `graphsync-server` feature.
+    pub enabled: bool,
+    /// Requests served concurrently, across all peers.
+    pub max_concurrent_requests: u32,
+    /// Requests of a single peer that are served concurrently.
+    pub max_requests_per_peer: u32,
+    /// Blocks sent in response to a single request.
+    pub max_blocks_per_request: u32,
+    /// Block data sent in response to a single request, in bytes.
+    #[cfg_attr(test, arbitrary(gen(|g| u32::arbitrary(g) as _)))]
+    pub max_bytes_per_request: u64,
+    /// Block data sent to a single peer per minute, in bytes.
+    #[cfg_attr(test, arbitrary(gen(|g| u32::arbitrary(g) as _)))]
+    pub max_bytes_per_peer_per_minute: u64,
+}
+
+impl Default for GraphSyncServerLimits {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_concurrent_requests: 16,
+            max_requests_per_peer: 4,
+            max_blocks_per_request: 100_000,
+            max_bytes_per_request: 256 * 1024 * 1024,
+            max_bytes_per_peer_per_minute: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+/// Stand-in for the server when it isn't built. No requests are received, as
+/// inbound streams aren't accepted.
+#[cfg(not(any(test, feature = "graphsync-server")))]
+mod disabled {
+    use std::sync::Arc;
+
+    use fvm_ipld_blockstore::Blockstore;
+    use libp2p::PeerId;
+    use tracing::warn;
+
+    use super::{GraphSyncMessage, GraphSyncServerLimits};
+    use crate::chain::ChainStore;
+
+    pub struct GraphSyncServer;
+
+    impl GraphSyncServer {
+        pub fn new(limits: GraphSyncServerLimits) -> Self {
+            if limits.enabled {
+                warn!("The GraphSync server is enabled, but Forest is built without the `graphsync-server` feature");
+            }
+            Self
+        }
+
+        pub fn handle_message<DB>(
+            self: &Arc<Self>,
+            _: &Arc<ChainStore<DB>>,
+            _: PeerId,
+            _: GraphSyncMessage,
+            _: &flume::Sender<(PeerId, GraphSyncMessage)>,
+        ) where
+            DB: Blockstore + Send + Sync + 'static,
+        {
+        }
+
+        pub fn on_peer_disconnected(&self, _: &PeerId) {}
+    }
+}
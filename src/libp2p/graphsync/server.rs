@@ -0,0 +1,607 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Serving `GraphSync` requests out of the local block store.
+//!
+//! The selector of a request is applied to its root, depth first, and every
+//! block that the traversal loads is sent to the requester, once, along with
+//! metadata telling which links were sent, already sent or missing. Blocks are
+//! sent in batches of up to [`MAX_BLOCK_BYTES_PER_MESSAGE`] bytes.
+//!
+//! Requests are served within budgets: a request is completed partially once
+//! it has been sent [`GraphSyncServerLimits::max_blocks_per_request`] blocks
+//! or [`GraphSyncServerLimits::max_bytes_per_request`] bytes, or once its
+//! peer has been sent [`GraphSyncServerLimits::max_bytes_per_peer_per_minute`]
+//! bytes in the current minute. Traversals also stop once they have visited
+//! as many blocks as they may send, duplicates included. Peers with too many
+//! requests in progress, or requests beyond
+//! [`GraphSyncServerLimits::max_concurrent_requests`] across all peers, are
+//! told the server is busy. Response messages are sent on a bounded channel,
+//! so that traversals wait for them to be sent to the network.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use ahash::HashMap;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use libp2p::PeerId;
+use parking_lot::Mutex;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::debug;
+
+use super::*;
+use crate::chain::ChainStore;
+use crate::ipld::{from_ipld, selector::Selector, CidHashSet, Ipld};
+use crate::libp2p::metrics::{self, GRAPHSYNC_REQUEST_TOTAL, GRAPHSYNC_SERVED_BYTES_TOTAL};
+use crate::utils::encoding::from_slice_with_fallback;
+
+/// Block data sent in a single message. Blocks are up to 1 MiB, so messages
+/// stay below the maximum message size of 4 MiB.
+const MAX_BLOCK_BYTES_PER_MESSAGE: usize = 1024 * 1024;
+
+const PEER_BUDGET_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default)]
+struct PeerState {
+    /// Cancellation flags of the requests in progress, by request ID.
+    requests: HashMap<Vec<u8>, Arc<AtomicBool>>,
+    window_start: Option<Instant>,
+    window_bytes: u64,
+}
+
+/// `GraphSync` server, see the [module documentation](self).
+#[derive(Debug)]
+pub struct GraphSyncServer {
+    limits: GraphSyncServerLimits,
+    peers: Mutex<HashMap<PeerId, PeerState>>,
+    /// Permits of the requests served concurrently, across all peers.
+    traversals: Arc<Semaphore>,
+}
+
+impl Default for GraphSyncServer {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+/// Remaining budget of a request.
+struct Budget {
+    blocks: u32,
+    bytes: u64,
+    /// Blocks that may still be visited, including those already sent.
+    visits: u64,
+}
+
+enum Visit {
+    Node(Ipld),
+    Missing,
+    OverBudget,
+}
+
+/// Response messages of a request, batched.
+struct Responder<'a, F> {
+    id: &'a [u8],
+    send: F,
+    metadata: Vec<GraphSyncLinkMetadata>,
+    blocks: Vec<GraphSyncBlock>,
+    block_bytes: usize,
+}
+
+impl<F: FnMut(GraphSyncMessage)> Responder<'_, F> {
+    fn push(&mut self, link: Cid, action: GraphSyncLinkAction, block: Option<GraphSyncBlock>) {
+        self.metadata.push(GraphSyncLinkMetadata { link, action });
+        if let Some(block) = block {
+            self.block_bytes += block.data.len();
+            self.blocks.push(block);
+        }
+        if self.block_bytes >= MAX_BLOCK_BYTES_PER_MESSAGE {
+            self.send(status::PARTIAL_RESPONSE);
+        }
+    }
+
+    fn send(&mut self, status: i32) {
+        GRAPHSYNC_SERVED_BYTES_TOTAL.inc_by(self.block_bytes as u64);
+        self.block_bytes = 0;
+        (self.send)(GraphSyncMessage {
+            responses: vec![GraphSyncResponse {
+                id: self.id.to_vec(),
+                status,
+                metadata: std::mem::take(&mut self.metadata),
+                extensions: None,
+            }],
+            blocks: std::mem::take(&mut self.blocks),
+            ..Default::default()
+        });
+    }
+}
+
+impl GraphSyncServer {
+    pub fn new(limits: GraphSyncServerLimits) -> Self {
+        let traversals = match limits.max_concurrent_requests {
+            0 => Semaphore::MAX_PERMITS,
+            max => max as usize,
+        };
+        Self {
+            limits,
+            peers: Default::default(),
+            traversals: Arc::new(Semaphore::new(traversals)),
+        }
+    }
+
+    /// Handles the requests in a message of `peer`. New requests are served
+    /// on the blocking thread pool, and their response messages are sent to
+    /// `responses`, which should be bounded. Busy responses are dropped if it
+    /// is full, as this isn't called from a blocking thread.
+    pub fn handle_message<DB>(
+        self: &Arc<Self>,
+        chain_store: &Arc<ChainStore<DB>>,
+        peer: PeerId,
+        message: GraphSyncMessage,
+        responses: &flume::Sender<(PeerId, GraphSyncMessage)>,
+    ) where
+        DB: Blockstore + Send + Sync + 'static,
+    {
+        for request in message.requests {
+            match request.ty {
+                GraphSyncRequestType::New => {
+                    let Some((cancelled, permit)) = self.begin(peer, &request.id) else {
+                        record_status(status::REQUEST_FAILED_BUSY);
+                        let busy = status_message(&request.id, status::REQUEST_FAILED_BUSY);
+                        let _ = responses.try_send((peer, busy));
+                        continue;
+                    };
+                    let (server, chain_store, responses) =
+                        (self.clone(), chain_store.clone(), responses.clone());
+                    tokio::task::spawn_blocking(move || {
+                        server.serve(chain_store.blockstore(), &peer, &request, &cancelled, |m| {
+                            let _ = responses.send((peer, m));
+                        });
+                        server.end(&peer, &request.id);
+                        drop(permit);
+                    });
+                }
+                GraphSyncRequestType::Cancel => self.cancel(&peer, &request.id),
+                // Pausing and extensions aren't supported, so there is nothing
+                // to update.
+                GraphSyncRequestType::Update => {}
+            }
+        }
+        if !message.responses.is_empty() {
+            debug!("Ignoring GraphSync responses of {peer}, no requests were sent");
+        }
+    }
+
+    /// Stops serving the requests of a disconnected peer.
+    pub fn on_peer_disconnected(&self, peer: &PeerId) {
+        if let Some(state) = self.peers.lock().remove(peer) {
+            for cancelled in state.requests.values() {
+                cancelled.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Returns the cancellation flag of a new request and its permit to be
+    /// served, or `None` if the peer has too many requests in progress or
+    /// reuses a request ID, or if the server is serving too many requests.
+    fn begin(&self, peer: PeerId, id: &[u8]) -> Option<(Arc<AtomicBool>, OwnedSemaphorePermit)> {
+        let mut peers = self.peers.lock();
+        let state = peers.entry(peer).or_default();
+        let max_requests = self.limits.max_requests_per_peer as usize;
+        if (max_requests > 0 && state.requests.len() >= max_requests)
+            || state.requests.contains_key(id)
+        {
+            return None;
+        }
+        let permit = self.traversals.clone().try_acquire_owned().ok()?;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        state.requests.insert(id.to_vec(), cancelled.clone());
+        Some((cancelled, permit))
+    }
+
+    fn end(&self, peer: &PeerId, id: &[u8]) {
+        if let Some(state) = self.peers.lock().get_mut(peer) {
+            state.requests.remove(id);
+        }
+    }
+
+    fn cancel(&self, peer: &PeerId, id: &[u8]) {
+        if let Some(cancelled) = self
+            .peers
+            .lock()
+            .get(peer)
+            .and_then(|state| state.requests.get(id))
+        {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Draws `bytes` from the budget of `peer` for the current minute.
+    fn take_peer_bytes(&self, peer: &PeerId, bytes: u64) -> bool {
+        let max_bytes = self.limits.max_bytes_per_peer_per_minute;
+        if max_bytes == 0 {
+            return true;
+        }
+        let now = Instant::now();
+        let mut peers = self.peers.lock();
+        // Disconnected peers have no budget left.
+        let Some(state) = peers.get_mut(peer) else {
+            return false;
+        };
+        if state.window_start.map_or(true, |start| {
+            now.duration_since(start) >= PEER_BUDGET_WINDOW
+        }) {
+            state.window_start = Some(now);
+            state.window_bytes = 0;
+        }
+        if state.window_bytes + bytes > max_bytes {
+            return false;
+        }
+        state.window_bytes += bytes;
+        true
+    }
+
+    /// Serves a new request, calling `send` with each response message. No
+    /// final response is sent if the request is cancelled.
+    fn serve(
+        &self,
+        db: &impl Blockstore,
+        peer: &PeerId,
+        request: &GraphSyncRequest,
+        cancelled: &AtomicBool,
+        send: impl FnMut(GraphSyncMessage),
+    ) {
+        let mut responder = Responder {
+            id: &request.id,
+            send,
+            metadata: vec![],
+            blocks: vec![],
+            block_bytes: 0,
+        };
+        let status = match (request.root, &request.selector) {
+            (Some(root), Some(selector)) => match from_ipld::<Selector>(selector.clone()) {
+                Ok(selector) => {
+                    match self.traverse(db, peer, root, selector, cancelled, &mut responder) {
+                        Ok(Some(status)) => status,
+                        Ok(None) => return,
+                        Err(e) => {
+                            debug!("Failed to serve GraphSync request of {peer}: {e}");
+                            status::REQUEST_FAILED_UNKNOWN
+                        }
+                    }
+                }
+                Err(_) => status::REQUEST_REJECTED,
+            },
+            _ => status::REQUEST_REJECTED,
+        };
+        record_status(status);
+        responder.send(status);
+    }
+
+    /// Walks the selected blocks, depth first. Returns the final status of the
+    /// request, or `None` if it was cancelled.
+    fn traverse<F: FnMut(GraphSyncMessage)>(
+        &self,
+        db: &impl Blockstore,
+        peer: &PeerId,
+        root: Cid,
+        selector: Selector,
+        cancelled: &AtomicBool,
+        responder: &mut Responder<F>,
+    ) -> anyhow::Result<Option<i32>> {
+        let blocks = match self.limits.max_blocks_per_request {
+            0 => u32::MAX,
+            max => max,
+        };
+        let mut budget = Budget {
+            blocks,
+            bytes: match self.limits.max_bytes_per_request {
+                0 => u64::MAX,
+                max => max,
+            },
+            // Duplicates are visited again, so that their links are reported
+            // on every path, but subgraphs shared by many paths can't make the
+            // traversal exponential.
+            visits: blocks as u64 * 2,
+        };
+        let mut sent = CidHashSet::default();
+        let mut complete = true;
+        let mut stack = match self.visit(db, peer, &root, &mut sent, &mut budget, responder)? {
+            Visit::Node(node) => vec![(node, selector)],
+            Visit::Missing => return Ok(Some(status::REQUEST_FAILED_CONTENT_NOT_FOUND)),
+            Visit::OverBudget => return Ok(Some(status::REQUEST_COMPLETED_PARTIAL)),
+        };
+        while let Some((node, selector)) = stack.pop() {
+            if cancelled.load(Ordering::Relaxed) {
+                return Ok(None);
+            }
+            let node = match node {
+                Ipld::Link(cid) => {
+                    match self.visit(db, peer, &cid, &mut sent, &mut budget, responder)? {
+                        Visit::Node(node) => node,
+                        Visit::Missing => {
+                            complete = false;
+                            continue;
+                        }
+                        Visit::OverBudget => return Ok(Some(status::REQUEST_COMPLETED_PARTIAL)),
+                    }
+                }
+                node => node,
+            };
+            let children: Vec<(String, &Ipld)> = match &node {
+                Ipld::Map(map) => map.iter().map(|(k, v)| (k.clone(), v)).collect(),
+                Ipld::List(list) => list
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| (i.to_string(), v))
+                    .collect(),
+                _ => vec![],
+            };
+            // Pushed in reverse, so that children are visited in order.
+            for (segment, child) in children.into_iter().rev() {
+                if let Some(next) = selector.clone().explore(&node, &segment) {
+                    stack.push((child.clone(), next));
+                }
+            }
+        }
+        Ok(Some(match complete {
+            true => status::REQUEST_COMPLETED_FULL,
+            false => status::REQUEST_COMPLETED_PARTIAL,
+        }))
+    }
+
+    /// Loads a block of the traversal and sends it, unless it was already
+    /// sent.
+    fn visit<F: FnMut(GraphSyncMessage)>(
+        &self,
+        db: &impl Blockstore,
+        peer: &PeerId,
+        cid: &Cid,
+        sent: &mut CidHashSet,
+        budget: &mut Budget,
+        responder: &mut Responder<F>,
+    ) -> anyhow::Result<Visit> {
+        let Some(data) = db.get(cid)? else {
+            responder.push(*cid, GraphSyncLinkAction::Missing, None);
+            return Ok(Visit::Missing);
+        };
+        if budget.visits == 0 {
+            return Ok(Visit::OverBudget);
+        }
+        budget.visits -= 1;
+        if sent.contains(cid) {
+            responder.push(*cid, GraphSyncLinkAction::DuplicateNotSent, None);
+        } else {
+            let len = data.len() as u64;
+            if budget.blocks == 0 || budget.bytes < len || !self.take_peer_bytes(peer, len) {
+                return Ok(Visit::OverBudget);
+            }
+            budget.blocks -= 1;
+            budget.bytes -= len;
+            sent.insert(*cid);
+            responder.push(
+                *cid,
+                GraphSyncLinkAction::Present,
+                Some(GraphSyncBlock::new(cid, data.clone())),
+            );
+        }
+        Ok(Visit::Node(match cid.codec() {
+            fvm_ipld_encoding::DAG_CBOR => from_slice_with_fallback(&data)?,
+            crate::shim::crypto::IPLD_RAW => Ipld::Bytes(data),
+            // Blocks of other codecs are leaves.
+            _ => Ipld::Null,
+        }))
+    }
+}
+
+fn status_message(id: &[u8], status: i32) -> GraphSyncMessage {
+    GraphSyncMessage {
+        responses: vec![GraphSyncResponse {
+            id: id.to_vec(),
+            status,
+            metadata: vec![],
+            extensions: None,
+        }],
+        ..Default::default()
+    }
+}
+
+fn record_status(status: i32) {
+    let label = match status {
+        status::REQUEST_COMPLETED_FULL => metrics::values::COMPLETED_FULL,
+        status::REQUEST_COMPLETED_PARTIAL => metrics::values::COMPLETED_PARTIAL,
+        status::REQUEST_REJECTED => metrics::values::REJECTED,
+        status::REQUEST_FAILED_BUSY => metrics::values::BUSY,
+        status::REQUEST_FAILED_CONTENT_NOT_FOUND => metrics::values::NOT_FOUND,
+        _ => metrics::values::FAILED,
+    };
+    GRAPHSYNC_REQUEST_TOTAL.with_label_values(&[label]).inc();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MemoryDB;
+    use crate::utils::db::CborStoreExt;
+    use libipld_macro::ipld;
+
+    fn request(root: Cid, selector: Ipld) -> GraphSyncRequest {
+        GraphSyncRequest {
+            id: vec![1; 16],
+            ty: GraphSyncRequestType::New,
+            priority: None,
+            root: Some(root),
+            selector: Some(selector),
+            extensions: None,
+        }
+    }
+
+    fn explore_all() -> Ipld {
+        ipld!({"R": {"l": {"none": {}}, ":>": {"a": {">": {"@": {}}}}}})
+    }
+
+    fn serve(
+        server: &GraphSyncServer,
+        db: &MemoryDB,
+        request: &GraphSyncRequest,
+    ) -> GraphSyncResponse {
+        let mut messages = vec![];
+        let peer = PeerId::random();
+        let _permit = server.begin(peer, &request.id).unwrap();
+        server.serve(db, &peer, request, &AtomicBool::new(false), |m| {
+            messages.push(m)
+        });
+        let mut response = messages.pop().unwrap().responses.pop().unwrap();
+        for message in messages {
+            assert_eq!(message.responses[0].status, status::PARTIAL_RESPONSE);
+            response
+                .metadata
+                .splice(0..0, message.responses[0].metadata.clone());
+        }
+        response
+    }
+
+    #[test]
+    fn serves_selected_blocks() {
+        use GraphSyncLinkAction::*;
+        let db = MemoryDB::default();
+        let leaf = db.put_cbor_default(&"leaf").unwrap();
+        let missing = Cid::default();
+        let left = db.put_cbor_default(&(leaf, 1)).unwrap();
+        let right = db.put_cbor_default(&(leaf, missing)).unwrap();
+        let root = db.put_cbor_default(&(left, right)).unwrap();
+        let server = GraphSyncServer::default();
+
+        let response = serve(&server, &db, &request(root, explore_all()));
+        assert_eq!(response.status, status::REQUEST_COMPLETED_PARTIAL);
+        let actions: Vec<_> = response
+            .metadata
+            .iter()
+            .map(|m| (m.link, m.action))
+            .collect();
+        assert_eq!(
+            actions,
+            [
+                (root, Present),
+                (left, Present),
+                (leaf, Present),
+                (right, Present),
+                (leaf, DuplicateNotSent),
+                (missing, Missing)
+            ]
+        );
+
+        // Only the first element of the root.
+        let selector = ipld!({"i": {"i": 0, ">": {".": {}}}});
+        let response = serve(&server, &db, &request(root, selector));
+        assert_eq!(response.status, status::REQUEST_COMPLETED_FULL);
+        assert_eq!(response.metadata.len(), 2);
+
+        let response = serve(&server, &db, &request(missing, explore_all()));
+        assert_eq!(response.status, status::REQUEST_FAILED_CONTENT_NOT_FOUND);
+        let response = serve(&server, &db, &request(root, ipld!({"?": {}})));
+        assert_eq!(response.status, status::REQUEST_REJECTED);
+    }
+
+    #[test]
+    fn budgets() {
+        let db = MemoryDB::default();
+        let leaf = db.put_cbor_default(&"leaf").unwrap();
+        let root = db.put_cbor_default(&(leaf, leaf)).unwrap();
+        let server = GraphSyncServer::new(GraphSyncServerLimits {
+            max_requests_per_peer: 1,
+            max_blocks_per_request: 1,
+            ..Default::default()
+        });
+        let response = serve(&server, &db, &request(root, explore_all()));
+        assert_eq!(response.status, status::REQUEST_COMPLETED_PARTIAL);
+        assert_eq!(response.metadata.len(), 1);
+
+        let peer = PeerId::random();
+        assert!(server.begin(peer, &[1]).is_some());
+        assert!(server.begin(peer, &[2]).is_none());
+        assert!(server.begin(PeerId::random(), &[2]).is_some());
+        server.end(&peer, &[1]);
+        assert!(server.begin(peer, &[2]).is_some());
+
+        let server = GraphSyncServer::new(GraphSyncServerLimits {
+            max_bytes_per_peer_per_minute: 10,
+            ..Default::default()
+        });
+        let other = PeerId::random();
+        let _requests = (
+            server.begin(peer, &[1]).unwrap(),
+            server.begin(other, &[1]).unwrap(),
+        );
+        assert!(server.take_peer_bytes(&peer, 8));
+        assert!(!server.take_peer_bytes(&peer, 8));
+        assert!(server.take_peer_bytes(&other, 8));
+        // The state of disconnected peers isn't re-created.
+        server.on_peer_disconnected(&other);
+        assert!(!server.take_peer_bytes(&other, 8));
+        assert!(server.peers.lock().get(&other).is_none());
+
+        // Requests beyond the global cap are turned down.
+        let server = GraphSyncServer::new(GraphSyncServerLimits {
+            max_concurrent_requests: 1,
+            ..Default::default()
+        });
+        let permit = server.begin(peer, &[1]).unwrap();
+        assert!(server.begin(other, &[1]).is_none());
+        server.end(&peer, &[1]);
+        drop(permit);
+        assert!(server.begin(other, &[1]).is_some());
+    }
+
+    #[test]
+    fn shared_subgraphs_are_visited_within_budget() {
+        // Each level links twice to the level below, so there are 2^32 paths.
+        let db = MemoryDB::default();
+        let mut root = db.put_cbor_default(&"leaf").unwrap();
+        for _ in 0..32 {
+            root = db.put_cbor_default(&(root, root)).unwrap();
+        }
+        let server = GraphSyncServer::new(GraphSyncServerLimits {
+            max_blocks_per_request: 100,
+            ..Default::default()
+        });
+        let response = serve(&server, &db, &request(root, explore_all()));
+        assert_eq!(response.status, status::REQUEST_COMPLETED_PARTIAL);
+        assert_eq!(response.metadata.len(), 200);
+    }
+
+    #[test]
+    fn message_round_trip() {
+        let db = MemoryDB::default();
+        let root = db.put_cbor_default(&"root").unwrap();
+        let message = GraphSyncMessage {
+            requests: vec![request(root, explore_all())],
+            responses: vec![GraphSyncResponse {
+                id: vec![2; 16],
+                status: status::REQUEST_COMPLETED_FULL,
+                metadata: vec![GraphSyncLinkMetadata {
+                    link: root,
+                    action: GraphSyncLinkAction::Present,
+                }],
+                extensions: None,
+            }],
+            blocks: vec![GraphSyncBlock::new(&root, db.get(&root).unwrap().unwrap())],
+        };
+        let bytes = fvm_ipld_encoding::to_vec(&GraphSyncMessageRoot {
+            gs2: message.clone(),
+        })
+        .unwrap();
+        let decoded: GraphSyncMessageRoot = fvm_ipld_encoding::from_slice(&bytes).unwrap();
+        assert_eq!(decoded.gs2, message);
+        // Version 1, DAG-CBOR, Blake2b-256 and 32 bytes.
+        assert_eq!(
+            message.blocks[0].prefix,
+            [0x01, 0x71, 0xa0, 0xe4, 0x02, 0x20]
+        );
+    }
+}
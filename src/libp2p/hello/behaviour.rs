@@ -66,7 +66,10 @@ impl Default for HelloBehaviour {
     fn default() -> Self {
         Self {
             inner: InnerBehaviour::new(
-                [(HELLO_PROTOCOL_NAME, ProtocolSupport::Full)],
+                [
+                    (HELLO_V2_PROTOCOL_NAME, ProtocolSupport::Full),
+                    (HELLO_PROTOCOL_NAME, ProtocolSupport::Full),
+                ],
                 Default::default(),
             ),
             response_channels: Default::default(),
@@ -1,11 +1,87 @@
 // Copyright 2019-2023 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+use std::io;
+
+use async_trait::async_trait;
+use futures::prelude::*;
+use libp2p::request_response;
+
 use super::*;
 use crate::libp2p::rpc::CborRequestResponse;
 
 /// Libp2p Hello protocol name.
 pub const HELLO_PROTOCOL_NAME: &str = "/fil/hello/1.0.0";
 
-/// Hello protocol codec to be used within the RPC service.
-pub type HelloCodec = CborRequestResponse<&'static str, HelloRequest, HelloResponse>;
+/// Libp2p Hello protocol name of the version that includes the
+/// [`ChainIdentity`] of the sender. Peers that don't support it, e.g. Lotus,
+/// negotiate [`HELLO_PROTOCOL_NAME`] instead.
+pub const HELLO_V2_PROTOCOL_NAME: &str = "/fil/hello/2.0.0";
+
+/// Hello protocol codec to be used within the RPC service. Only hello v2
+/// requests carry a [`ChainIdentity`].
+#[derive(Clone, Default)]
+pub struct HelloCodec {
+    inner: CborRequestResponse<&'static str, HelloRequest, HelloResponse>,
+}
+
+#[async_trait]
+impl request_response::Codec for HelloCodec {
+    type Protocol = &'static str;
+    type Request = HelloRequest;
+    type Response = HelloResponse;
+
+    async fn read_request<T>(
+        &mut self,
+        protocol: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Request>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let mut request = self.inner.read_request(protocol, io).await?;
+        if *protocol != HELLO_V2_PROTOCOL_NAME {
+            request.identity = None;
+        }
+        Ok(request)
+    }
+
+    async fn read_response<T>(
+        &mut self,
+        protocol: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Response>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        self.inner.read_response(protocol, io).await
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        protocol: &Self::Protocol,
+        io: &mut T,
+        mut req: Self::Request,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        // Hello v1 peers reject messages with extra fields.
+        if *protocol != HELLO_V2_PROTOCOL_NAME {
+            req.identity = None;
+        }
+        self.inner.write_request(protocol, io, req).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        protocol: &Self::Protocol,
+        io: &mut T,
+        res: Self::Response,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        self.inner.write_response(protocol, io, res).await
+    }
+}
@@ -1,18 +1,182 @@
 // Copyright 2019-2023 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+use std::fmt;
+
+use crate::networks::ChainConfig;
 use crate::shim::bigint::BigInt;
 use crate::shim::clock::ChainEpoch;
 use cid::Cid;
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    ser::SerializeTuple,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 use serde_tuple::{self, Deserialize_tuple, Serialize_tuple};
 
 /// Hello message <https://filecoin-project.github.io/specs/#hello-spec>
-#[derive(Clone, Debug, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+///
+/// Over hello v1 the message is the 4-tuple of the spec. Hello v2 appends the
+/// [`ChainIdentity`] of the sender.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct HelloRequest {
     pub heaviest_tip_set: Vec<Cid>,
     pub heaviest_tipset_height: ChainEpoch,
     pub heaviest_tipset_weight: BigInt,
     pub genesis_cid: Cid,
+    /// Only sent and received over hello v2.
+    pub identity: Option<ChainIdentity>,
+}
+
+/// Identity of the chain a node follows, in addition to its genesis.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct ChainIdentity {
+    /// Network name, as stored in the genesis state.
+    pub network_name: String,
+    /// Network version at the heaviest tipset. This identifies the fork the
+    /// node is on.
+    pub network_version: u32,
+}
+
+impl ChainIdentity {
+    /// Identity of a node on `network_name` whose heaviest tipset is at
+    /// `epoch`.
+    pub fn new(network_name: &str, chain_config: &ChainConfig, epoch: ChainEpoch) -> Self {
+        ChainIdentity {
+            network_name: network_name.into(),
+            network_version: u32::from(*chain_config.network_version(epoch)),
+        }
+    }
+}
+
+/// Reason for disconnecting a peer after its hello request.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum HelloMismatch {
+    #[error("Genesis hash mismatch: {received} received, {expected} expected")]
+    Genesis { expected: Cid, received: Cid },
+    #[error("Network name mismatch: {received} received, {expected} expected")]
+    NetworkName { expected: String, received: String },
+    #[error("Network version mismatch at epoch {epoch}: {received} received, {expected} expected")]
+    NetworkVersion {
+        epoch: ChainEpoch,
+        expected: u32,
+        received: u32,
+    },
+}
+
+impl HelloMismatch {
+    /// Value of the `reason` label of the `hello_mismatch_total` metric.
+    pub fn label(&self) -> &'static str {
+        match self {
+            HelloMismatch::Genesis { .. } => "genesis",
+            HelloMismatch::NetworkName { .. } => "network_name",
+            HelloMismatch::NetworkVersion { .. } => "network_version",
+        }
+    }
+}
+
+impl HelloRequest {
+    /// Checks that the sender follows the same chain as we do. The network
+    /// name and version can only be checked for hello v2 peers. The network
+    /// version is compared with the one our upgrade schedule has at the
+    /// sender's head, which catches peers that missed or forked off at an
+    /// upgrade.
+    #[allow(clippy::result_large_err)]
+    pub fn verify(
+        &self,
+        genesis_cid: &Cid,
+        network_name: &str,
+        chain_config: &ChainConfig,
+    ) -> Result<(), HelloMismatch> {
+        if &self.genesis_cid != genesis_cid {
+            return Err(HelloMismatch::Genesis {
+                expected: *genesis_cid,
+                received: self.genesis_cid,
+            });
+        }
+        let Some(identity) = &self.identity else {
+            return Ok(());
+        };
+        if identity.network_name != network_name {
+            return Err(HelloMismatch::NetworkName {
+                expected: network_name.into(),
+                received: identity.network_name.clone(),
+            });
+        }
+        let expected = ChainIdentity::new(network_name, chain_config, self.heaviest_tipset_height)
+            .network_version;
+        if identity.network_version != expected {
+            return Err(HelloMismatch::NetworkVersion {
+                epoch: self.heaviest_tipset_height,
+                expected,
+                received: identity.network_version,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for HelloRequest {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let len = if self.identity.is_some() { 5 } else { 4 };
+        let mut tuple = serializer.serialize_tuple(len)?;
+        tuple.serialize_element(&self.heaviest_tip_set)?;
+        tuple.serialize_element(&self.heaviest_tipset_height)?;
+        tuple.serialize_element(&self.heaviest_tipset_weight)?;
+        tuple.serialize_element(&self.genesis_cid)?;
+        if let Some(identity) = &self.identity {
+            tuple.serialize_element(identity)?;
+        }
+        tuple.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for HelloRequest {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct HelloRequestVisitor;
+
+        impl<'de> Visitor<'de> for HelloRequestVisitor {
+            type Value = HelloRequest;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a hello request tuple of 4 or 5 elements")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let heaviest_tip_set = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let heaviest_tipset_height = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                let heaviest_tipset_weight = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+                let genesis_cid = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(3, &self))?;
+                let identity = seq.next_element()?;
+                Ok(HelloRequest {
+                    heaviest_tip_set,
+                    heaviest_tipset_height,
+                    heaviest_tipset_weight,
+                    genesis_cid,
+                    identity,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(HelloRequestVisitor)
+    }
 }
 
 /// Response to a Hello message. This just handles latency of the peer.
@@ -33,16 +197,98 @@ mod tests {
 
     use super::*;
 
+    fn genesis_cid() -> Cid {
+        Cid::new_v1(DAG_CBOR, Identity.digest(&[]))
+    }
+
     #[test]
     fn hello_default_ser() {
         let orig_msg = HelloRequest {
-            genesis_cid: Cid::new_v1(DAG_CBOR, Identity.digest(&[])),
+            genesis_cid: genesis_cid(),
             heaviest_tipset_weight: Default::default(),
             heaviest_tipset_height: Default::default(),
             heaviest_tip_set: Default::default(),
+            identity: None,
         };
         let bz = to_vec(&orig_msg).unwrap();
         let msg: HelloRequest = from_slice_with_fallback(&bz).unwrap();
         assert_eq!(msg, orig_msg);
     }
+
+    #[test]
+    fn hello_v2_ser() {
+        let orig_msg = HelloRequest {
+            genesis_cid: genesis_cid(),
+            heaviest_tipset_weight: Default::default(),
+            heaviest_tipset_height: 10,
+            heaviest_tip_set: Default::default(),
+            identity: Some(ChainIdentity {
+                network_name: "calibrationnet".into(),
+                network_version: 20,
+            }),
+        };
+        let bz = to_vec(&orig_msg).unwrap();
+        let msg: HelloRequest = from_slice_with_fallback(&bz).unwrap();
+        assert_eq!(msg, orig_msg);
+
+        // The first four elements are the hello v1 message.
+        let v1 = HelloRequest {
+            identity: None,
+            ..orig_msg
+        };
+        let v1_bz = to_vec(&v1).unwrap();
+        assert_eq!(v1_bz[0], 0x84);
+        assert_eq!(bz[0], 0x85);
+        assert_eq!(bz[1..v1_bz.len()], v1_bz[1..]);
+    }
+
+    #[test]
+    fn hello_mismatch() {
+        let chain_config = ChainConfig::default();
+        let identity = ChainIdentity::new("testnetnet", &chain_config, 10);
+        let request = HelloRequest {
+            genesis_cid: genesis_cid(),
+            heaviest_tipset_weight: Default::default(),
+            heaviest_tipset_height: 10,
+            heaviest_tip_set: Default::default(),
+            identity: Some(identity.clone()),
+        };
+        assert_eq!(
+            request.verify(&genesis_cid(), "testnetnet", &chain_config),
+            Ok(())
+        );
+
+        let other_genesis = Cid::new_v1(DAG_CBOR, Identity.digest(&[1]));
+        let err = request
+            .verify(&other_genesis, "testnetnet", &chain_config)
+            .unwrap_err();
+        assert_eq!(err.label(), "genesis");
+
+        let err = request
+            .verify(&genesis_cid(), "calibrationnet", &chain_config)
+            .unwrap_err();
+        assert_eq!(err.label(), "network_name");
+
+        let forked = HelloRequest {
+            identity: Some(ChainIdentity {
+                network_version: identity.network_version + 1,
+                ..identity
+            }),
+            ..request.clone()
+        };
+        let err = forked
+            .verify(&genesis_cid(), "testnetnet", &chain_config)
+            .unwrap_err();
+        assert_eq!(err.label(), "network_version");
+
+        // Hello v1 peers can only be checked for their genesis.
+        let v1 = HelloRequest {
+            identity: None,
+            ..request
+        };
+        assert_eq!(
+            v1.verify(&genesis_cid(), "calibrationnet", &chain_config),
+            Ok(())
+        );
+    }
 }
@@ -2,7 +2,9 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 use lazy_static::lazy_static;
-use prometheus::core::{AtomicU64, GenericCounter, GenericGauge};
+use prometheus::core::{
+    AtomicU64, GenericCounter, GenericCounterVec, GenericGauge, GenericGaugeVec, Opts,
+};
 
 lazy_static! {
     pub static ref PEER_FAILURE_TOTAL: Box<GenericCounter<AtomicU64>> = {
@@ -46,4 +48,175 @@ lazy_static! {
             .expect("Registering the bad_peers metric with the metrics registry must succeed");
         bad_peers
     };
+    pub static ref HELLO_MISMATCH_TOTAL: Box<GenericCounterVec<AtomicU64>> = {
+        let hello_mismatch_total = Box::new(
+            GenericCounterVec::<AtomicU64>::new(
+                Opts::new(
+                    "hello_mismatch_total",
+                    "Total number of peers disconnected for following a different chain",
+                ),
+                &[labels::REASON],
+            )
+            .expect("Defining the hello_mismatch_total metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(hello_mismatch_total.clone())
+            .expect(
+                "Registering the hello_mismatch_total metric with the metrics registry must succeed",
+            );
+        hello_mismatch_total
+    };
+    pub static ref PEER_PENALTY_TOTAL: Box<GenericCounterVec<AtomicU64>> = {
+        let peer_penalty_total = Box::new(
+            GenericCounterVec::<AtomicU64>::new(
+                Opts::new(
+                    "peer_penalty_total",
+                    "Total number of penalties given to misbehaving peers",
+                ),
+                &[labels::REASON],
+            )
+            .expect("Defining the peer_penalty_total metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(peer_penalty_total.clone())
+            .expect(
+                "Registering the peer_penalty_total metric with the metrics registry must succeed",
+            );
+        peer_penalty_total
+    };
+    pub static ref GOSSIP_MESSAGE_TOTAL: Box<GenericCounterVec<AtomicU64>> = {
+        let gossip_message_total = Box::new(
+            GenericCounterVec::<AtomicU64>::new(
+                Opts::new(
+                    "gossip_message_total",
+                    "Total number of messages received on each gossip topic",
+                ),
+                &[labels::TOPIC],
+            )
+            .expect("Defining the gossip_message_total metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(gossip_message_total.clone())
+            .expect(
+                "Registering the gossip_message_total metric with the metrics registry must succeed",
+            );
+        gossip_message_total
+    };
+    pub static ref GOSSIP_PUBLISHED_TOTAL: Box<GenericCounterVec<AtomicU64>> = {
+        let gossip_published_total = Box::new(
+            GenericCounterVec::<AtomicU64>::new(
+                Opts::new(
+                    "gossip_published_total",
+                    "Total number of messages published on each gossip topic",
+                ),
+                &[labels::TOPIC],
+            )
+            .expect("Defining the gossip_published_total metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(gossip_published_total.clone())
+            .expect(
+                "Registering the gossip_published_total metric with the metrics registry must succeed",
+            );
+        gossip_published_total
+    };
+    pub static ref GOSSIP_REJECTED_TOTAL: Box<GenericCounterVec<AtomicU64>> = {
+        let gossip_rejected_total = Box::new(
+            GenericCounterVec::<AtomicU64>::new(
+                Opts::new(
+                    "gossip_rejected_total",
+                    "Total number of gossip messages rejected, by topic and validation outcome",
+                ),
+                &[labels::TOPIC, labels::REASON],
+            )
+            .expect("Defining the gossip_rejected_total metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(gossip_rejected_total.clone())
+            .expect(
+                "Registering the gossip_rejected_total metric with the metrics registry must succeed",
+            );
+        gossip_rejected_total
+    };
+    pub static ref GOSSIP_MESH_PEERS: Box<GenericGaugeVec<AtomicU64>> = {
+        let gossip_mesh_peers = Box::new(
+            GenericGaugeVec::<AtomicU64>::new(
+                Opts::new(
+                    "gossip_mesh_peers",
+                    "Number of peers in the mesh of each gossip topic",
+                ),
+                &[labels::TOPIC],
+            )
+            .expect("Defining the gossip_mesh_peers metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(gossip_mesh_peers.clone())
+            .expect(
+                "Registering the gossip_mesh_peers metric with the metrics registry must succeed",
+            );
+        gossip_mesh_peers
+    };
+    pub static ref GRAPHSYNC_REQUEST_TOTAL: Box<GenericCounterVec<AtomicU64>> = {
+        let graphsync_request_total = Box::new(
+            GenericCounterVec::<AtomicU64>::new(
+                Opts::new(
+                    "graphsync_request_total",
+                    "Total number of GraphSync requests of peers, by final status",
+                ),
+                &[labels::STATUS],
+            )
+            .expect("Defining the graphsync_request_total metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(graphsync_request_total.clone())
+            .expect(
+                "Registering the graphsync_request_total metric with the metrics registry must succeed",
+            );
+        graphsync_request_total
+    };
+    pub static ref GRAPHSYNC_SERVED_BYTES_TOTAL: Box<GenericCounter<AtomicU64>> = {
+        let graphsync_served_bytes_total = Box::new(
+            GenericCounter::<AtomicU64>::new(
+                "graphsync_served_bytes_total",
+                "Total number of block bytes sent to peers over GraphSync",
+            )
+            .expect("Defining the graphsync_served_bytes_total metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(graphsync_served_bytes_total.clone())
+            .expect(
+                "Registering the graphsync_served_bytes_total metric with the metrics registry must succeed",
+            );
+        graphsync_served_bytes_total
+    };
+}
+
+pub mod labels {
+    pub const REASON: &str = "reason";
+    pub const TOPIC: &str = "topic";
+    pub const STATUS: &str = "status";
+}
+
+pub mod values {
+    /// Gossip messages that could not be deserialized.
+    pub const UNDECODABLE: &str = "undecodable";
+    /// Gossip messages that failed the validation of the chain or of the
+    /// message pool.
+    pub const INVALID: &str = "invalid";
+
+    pub const GOSSIP_REJECT_REASONS: [&str; 2] = [UNDECODABLE, INVALID];
+
+    // Final statuses of `GraphSync` requests.
+    #[cfg(any(test, feature = "graphsync-server"))]
+    pub const COMPLETED_FULL: &str = "completed_full";
+    #[cfg(any(test, feature = "graphsync-server"))]
+    pub const COMPLETED_PARTIAL: &str = "completed_partial";
+    #[cfg(any(test, feature = "graphsync-server"))]
+    pub const REJECTED: &str = "rejected";
+    #[cfg(any(test, feature = "graphsync-server"))]
+    pub const BUSY: &str = "busy";
+    #[cfg(any(test, feature = "graphsync-server"))]
+    pub const NOT_FOUND: &str = "not_found";
+    #[cfg(any(test, feature = "graphsync-server"))]
+    pub const FAILED: &str = "failed";
 }
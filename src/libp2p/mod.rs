@@ -1,15 +1,19 @@
 // Copyright 2019-2023 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+pub mod agent_policy;
 mod behaviour;
 pub mod chain_exchange;
 mod config;
 mod discovery;
 mod gossip_params;
+mod gossip_stats;
+pub mod graphsync;
 pub mod hello;
 pub mod keypair;
-mod metrics;
+pub mod metrics;
 mod peer_manager;
+mod protocol_stats;
 pub mod rpc;
 mod service;
 
@@ -21,7 +25,10 @@ pub use libp2p::{
 };
 
 pub(in crate::libp2p) use self::behaviour::*;
-pub use self::{config::*, peer_manager::*, service::*};
+pub use self::{
+    config::*, gossip_stats::record_gossip_rejected, peer_manager::*,
+    protocol_stats::RequestOutcome, service::*,
+};
 #[cfg(test)]
 mod tests {
     mod decode_test;
@@ -3,18 +3,30 @@
 
 use std::{
     cmp::Ordering,
+    path::PathBuf,
     sync::Arc,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use crate::blocks::Tipset;
+use crate::blocks::{Tipset, TipsetKeys};
+use crate::db::{
+    setting_keys::{BANNED_PEERS_KEY, PEER_HEADS_KEY},
+    SettingsStore, SettingsStoreExt,
+};
+use crate::libp2p::agent_policy::{AgentAction, AgentRule, AgentVersion};
+use crate::shim::clock::ChainEpoch;
 use ahash::{HashMap, HashSet};
 use flume::{Receiver, Sender};
+use num::BigInt;
 use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 use tracing::{debug, trace, warn};
 
 use crate::libp2p::*;
+use crate::rpc_api::net_api::{DailyProtocolStats, RequestOutcomes, RequestProtocol};
+
+use super::protocol_stats::{ProtocolStats, RequestOutcome};
 
 /// New peer multiplier slightly less than 1 to incentivize choosing new peers.
 const NEW_PEER_MUL: f64 = 0.9;
@@ -27,6 +39,104 @@ const LOCAL_INV_ALPHA: u32 = 5;
 /// Global duration multiplier, affects duration delta change.
 const GLOBAL_INV_ALPHA: u32 = 20;
 
+/// Score peers can build up with successful requests, to absorb occasional
+/// penalties.
+const MAX_PEER_SCORE: i64 = 20;
+/// Peers are disconnected and banned once their score drops to this value.
+const BAN_PEER_SCORE: i64 = -100;
+/// Duration of the bans of low-scoring peers.
+const SCORE_BAN_DURATION: Duration = Duration::from_secs(60 * 60);
+/// Scores move one point towards zero every interval, so that occasional
+/// penalties are forgiven over time.
+const SCORE_DECAY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Misbehavior of a peer that lowers its score, see
+/// [`PeerManager::penalize`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerPenalty {
+    /// Served or gossiped a tipset that failed validation.
+    InvalidTipset,
+    /// Failed to respond to a request in time.
+    Timeout,
+    /// Sent a malformed response.
+    ProtocolViolation,
+}
+
+impl PeerPenalty {
+    fn score(self) -> i64 {
+        match self {
+            PeerPenalty::InvalidTipset => 50,
+            PeerPenalty::ProtocolViolation => 25,
+            PeerPenalty::Timeout => 10,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            PeerPenalty::InvalidTipset => "invalid_tipset",
+            PeerPenalty::Timeout => "timeout",
+            PeerPenalty::ProtocolViolation => "protocol_violation",
+        }
+    }
+}
+
+/// Score of a peer, decaying towards zero by one point every
+/// [`SCORE_DECAY_INTERVAL`].
+#[derive(Debug, Clone, Copy)]
+struct PeerScore {
+    value: i64,
+    updated: Instant,
+}
+
+impl PeerScore {
+    fn new(now: Instant) -> Self {
+        Self {
+            value: 0,
+            updated: now,
+        }
+    }
+
+    /// Returns the decayed score at `now`.
+    fn value(&self, now: Instant) -> i64 {
+        let decay = (now.saturating_duration_since(self.updated).as_secs()
+            / SCORE_DECAY_INTERVAL.as_secs()) as i64;
+        if self.value > 0 {
+            (self.value - decay).max(0)
+        } else {
+            (self.value + decay).min(0)
+        }
+    }
+
+    /// Adds `delta` to the decayed score, up to [`MAX_PEER_SCORE`], and
+    /// returns the new score.
+    fn add(&mut self, delta: i64, now: Instant) -> i64 {
+        self.value = (self.value(now) + delta).min(MAX_PEER_SCORE);
+        self.updated = now;
+        self.value
+    }
+}
+
+/// Entry of the persisted ban list.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct PersistedBan {
+    peer: String,
+    /// Expiration in seconds since the Unix epoch, bans without expiration
+    /// are permanent.
+    expiration: Option<u64>,
+}
+
+/// Entry of the persisted peer heads.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct PersistedPeerHead {
+    peer: String,
+    tipset_keys: TipsetKeys,
+    epoch: ChainEpoch,
+    #[serde(with = "crate::lotus_json")]
+    weight: BigInt,
+    /// Last advertisement in seconds since the Unix epoch.
+    updated: u64,
+}
+
 #[derive(Debug, Default)]
 /// Contains info about the peer's head [Tipset], as well as the request stats.
 struct PeerInfo {
@@ -51,6 +161,115 @@ impl PeerInfo {
     }
 }
 
+/// Head advertised by a peer, in a hello request or by gossiping a block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerHead {
+    pub tipset_keys: TipsetKeys,
+    pub epoch: ChainEpoch,
+    pub weight: BigInt,
+    /// When the head was last advertised.
+    pub updated: SystemTime,
+}
+
+/// Connection to a connected peer, see [`PeerManager::peer_details`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerConnection {
+    /// Remote address of the first established connection.
+    pub address: Multiaddr,
+    /// Whether the peer dialed the node.
+    pub inbound: bool,
+    pub connected_since: SystemTime,
+    /// Round-trip time of the latest ping.
+    pub latency: Option<Duration>,
+    /// Protocols announced by the peer through `identify`.
+    pub protocols: Vec<String>,
+    /// Size of the gossip messages received from the peer.
+    pub gossip_bytes_received: u64,
+}
+
+impl PeerConnection {
+    /// Transport of the connection, e.g. `tcp` or `quic-v1`.
+    pub fn transport(&self) -> &'static str {
+        let mut transport = "unknown";
+        for protocol in self.address.iter() {
+            transport = match protocol {
+                Protocol::Tcp(_) => "tcp",
+                Protocol::Udp(_) => "udp",
+                Protocol::Quic => "quic",
+                Protocol::QuicV1 => "quic-v1",
+                Protocol::Ws(_) => "ws",
+                Protocol::Wss(_) => "wss",
+                _ => continue,
+            };
+        }
+        transport
+    }
+}
+
+/// Connection, client and score of a connected peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerDetails {
+    pub peer: PeerId,
+    pub connection: PeerConnection,
+    pub agent: Option<String>,
+    pub deprioritized: bool,
+    pub score: i64,
+}
+
+/// Estimate of the head of the network, based on the heads advertised by
+/// peers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkHead {
+    pub tipset_keys: TipsetKeys,
+    pub epoch: ChainEpoch,
+    pub weight: BigInt,
+    /// Number of peers that advertised this head.
+    pub peers: usize,
+    /// Number of peers that advertised any head recently.
+    pub total_peers: usize,
+}
+
+impl NetworkHead {
+    /// Whether more than half of the peers agree on this head.
+    pub fn is_majority(&self) -> bool {
+        self.peers * 2 > self.total_peers
+    }
+}
+
+/// Returns the head advertised by the most peers, ignoring heads older than
+/// `max_age`. Ties are broken by weight.
+pub fn estimate_network_head<'a>(
+    heads: impl IntoIterator<Item = &'a PeerHead>,
+    now: SystemTime,
+    max_age: Duration,
+) -> Option<NetworkHead> {
+    let mut votes: HashMap<&TipsetKeys, (usize, &PeerHead)> = HashMap::default();
+    let mut total_peers = 0;
+    for head in heads {
+        let age = now.duration_since(head.updated).unwrap_or_default();
+        if age > max_age {
+            continue;
+        }
+        total_peers += 1;
+        votes.entry(&head.tipset_keys).or_insert((0, head)).0 += 1;
+    }
+    votes
+        .into_values()
+        .max_by(|(a_peers, a), (b_peers, b)| {
+            a_peers
+                .cmp(b_peers)
+                .then_with(|| a.weight.cmp(&b.weight))
+                .then_with(|| a.epoch.cmp(&b.epoch))
+        })
+        .map(|(peers, head)| NetworkHead {
+            tipset_keys: head.tipset_keys.clone(),
+            epoch: head.epoch,
+            weight: head.weight.clone(),
+            peers,
+            total_peers,
+        })
+}
+
 /// Peer tracking sets, these are handled together to avoid race conditions or
 /// deadlocks when updating state.
 #[derive(Default)]
@@ -60,6 +279,19 @@ struct PeerSets {
     /// Set of peers to ignore for being incompatible/ failing to accept
     /// connections.
     bad_peers: HashSet<PeerId>,
+    /// Latest head advertised by each connected peer.
+    heads: HashMap<PeerId, PeerHead>,
+    /// Agent versions announced by the connected peers.
+    agents: HashMap<PeerId, String>,
+    /// Connections to the connected peers.
+    connections: HashMap<PeerId, PeerConnection>,
+    /// Connected peers that run a client version below the minimum of
+    /// [`AgentAction::Deprioritize`] rules.
+    deprioritized: HashSet<PeerId>,
+    /// Scores of the peers that were penalized or served requests, peers
+    /// start at zero. Scores outlive connections, so that peers can't reset
+    /// them by reconnecting, and are pruned once they decayed back to zero.
+    scores: HashMap<PeerId, PeerScore>,
 }
 
 /// Thread safe peer manager which handles peer management for the
@@ -74,7 +306,17 @@ pub struct PeerManager {
     /// Peer operation receiver
     peer_ops_rx: Receiver<PeerOperation>,
     /// Peer ban list, key is peer id, value is expiration time
-    peer_ban_list: RwLock<HashMap<PeerId, Option<Instant>>>,
+    peer_ban_list: RwLock<HashMap<PeerId, Option<SystemTime>>>,
+    /// Store the ban list is persisted to, if any.
+    ban_list_store: Option<Arc<dyn SettingsStore + Sync + Send>>,
+    /// Minimum client versions of the peers.
+    agent_rules: Vec<AgentRule>,
+    /// Outcomes of the requests sent to peers, by protocol and by day.
+    protocol_stats: RwLock<ProtocolStats>,
+    /// File the protocol statistics are persisted to, if any.
+    protocol_stats_path: Option<PathBuf>,
+    /// Store the peer heads are persisted to, if any.
+    peer_heads_store: Option<Arc<dyn SettingsStore + Sync + Send>>,
 }
 
 impl Default for PeerManager {
@@ -86,16 +328,179 @@ impl Default for PeerManager {
             peer_ops_tx,
             peer_ops_rx,
             peer_ban_list: Default::default(),
+            ban_list_store: None,
+            agent_rules: vec![],
+            protocol_stats: Default::default(),
+            protocol_stats_path: None,
+            peer_heads_store: None,
         }
     }
 }
 
 impl PeerManager {
+    /// Creates a peer manager that persists its ban list to the settings
+    /// store. The bans from a previous run that didn't expire yet are
+    /// restored.
+    pub fn with_ban_list(store: Arc<dyn SettingsStore + Sync + Send>) -> Self {
+        let bans = match load_ban_list(store.as_ref()) {
+            Ok(bans) => bans,
+            Err(e) => {
+                warn!("Failed to load the peer ban list: {e}");
+                Default::default()
+            }
+        };
+        let (peer_ops_tx, peer_ops_rx) = flume::unbounded();
+        for &peer in bans.keys() {
+            // The receiver is owned by the manager, so sending can't fail.
+            let _ = peer_ops_tx.send(PeerOperation::Ban(peer, "banned in a previous run".into()));
+        }
+        PeerManager {
+            peers: Default::default(),
+            avg_global_time: Default::default(),
+            peer_ops_tx,
+            peer_ops_rx,
+            peer_ban_list: RwLock::new(bans),
+            ban_list_store: Some(store),
+            agent_rules: vec![],
+            protocol_stats: Default::default(),
+            protocol_stats_path: None,
+            peer_heads_store: None,
+        }
+    }
+
+    /// Persists the protocol statistics to `path`, see
+    /// [`PeerManager::save_protocol_stats`]. The statistics of a previous run
+    /// are restored.
+    pub fn with_protocol_stats(mut self, path: PathBuf) -> Self {
+        match ProtocolStats::load(&path) {
+            Ok(stats) => self.protocol_stats = RwLock::new(stats),
+            Err(e) => warn!(
+                "Failed to load the protocol statistics from {}: {e}",
+                path.display()
+            ),
+        }
+        self.protocol_stats_path = Some(path);
+        self
+    }
+
+    /// Persists the peer heads to the settings store, see
+    /// [`PeerManager::save_peer_heads`]. The heads of a previous run are
+    /// restored, so that the network head can be estimated before the peers
+    /// advertise their heads again.
+    pub fn with_peer_heads(mut self, store: Arc<dyn SettingsStore + Sync + Send>) -> Self {
+        match load_peer_heads(store.as_ref()) {
+            Ok(heads) => self.peers.get_mut().heads = heads,
+            Err(e) => warn!("Failed to load the peer heads: {e}"),
+        }
+        self.peer_heads_store = Some(store);
+        self
+    }
+
+    /// Sets the minimum client versions of the peers, see
+    /// [`PeerManager::update_peer_agent`].
+    pub fn with_agent_rules(mut self, rules: Vec<AgentRule>) -> Self {
+        self.agent_rules = rules;
+        self
+    }
+
+    /// Records the agent version announced by a peer and returns the action
+    /// of the rule it violates, if any. Deprioritized peers are sent requests
+    /// after the others; refusing peers is up to the caller.
+    pub async fn update_peer_agent(&self, peer_id: PeerId, agent: &str) -> Option<AgentAction> {
+        let action = AgentVersion::parse(agent).check(&self.agent_rules);
+        let mut peers = self.peers.write().await;
+        peers.agents.insert(peer_id, agent.to_owned());
+        if action == Some(AgentAction::Deprioritize) {
+            peers.deprioritized.insert(peer_id);
+        } else {
+            peers.deprioritized.remove(&peer_id);
+        }
+        action
+    }
+
+    /// Returns the agent versions of the connected peers, and whether they
+    /// are deprioritized.
+    pub async fn peer_agents(&self) -> Vec<(String, bool)> {
+        let peers = self.peers.read().await;
+        peers
+            .agents
+            .iter()
+            .map(|(peer, agent)| (agent.clone(), peers.deprioritized.contains(peer)))
+            .collect()
+    }
+
+    /// Records the first connection established to a peer.
+    pub async fn record_connection(&self, peer_id: PeerId, address: Multiaddr, inbound: bool) {
+        self.peers.write().await.connections.insert(
+            peer_id,
+            PeerConnection {
+                address,
+                inbound,
+                connected_since: SystemTime::now(),
+                latency: None,
+                protocols: vec![],
+                gossip_bytes_received: 0,
+            },
+        );
+    }
+
+    /// Forgets the connection to a peer once its last connection is closed.
+    pub async fn remove_connection(&self, peer_id: &PeerId) {
+        self.peers.write().await.connections.remove(peer_id);
+    }
+
+    pub async fn update_peer_latency(&self, peer_id: &PeerId, rtt: Duration) {
+        if let Some(connection) = self.peers.write().await.connections.get_mut(peer_id) {
+            connection.latency = Some(rtt);
+        }
+    }
+
+    pub async fn update_peer_protocols(&self, peer_id: &PeerId, protocols: Vec<String>) {
+        if let Some(connection) = self.peers.write().await.connections.get_mut(peer_id) {
+            connection.protocols = protocols;
+        }
+    }
+
+    pub async fn log_gossip_received(&self, peer_id: &PeerId, size: usize) {
+        if let Some(connection) = self.peers.write().await.connections.get_mut(peer_id) {
+            connection.gossip_bytes_received += size as u64;
+        }
+    }
+
+    /// Returns the connection, agent version and score of each connected
+    /// peer.
+    pub async fn peer_details(&self) -> Vec<PeerDetails> {
+        let peers = self.peers.read().await;
+        peers
+            .connections
+            .iter()
+            .map(|(peer, connection)| PeerDetails {
+                peer: *peer,
+                connection: connection.clone(),
+                agent: peers.agents.get(peer).cloned(),
+                deprioritized: peers.deprioritized.contains(peer),
+                score: peers
+                    .scores
+                    .get(peer)
+                    .map_or(0, |score| score.value(Instant::now())),
+            })
+            .collect()
+    }
+
     /// Updates peer's heaviest tipset. If the peer does not exist in the set, a
     /// new `PeerInfo` will be generated.
     pub async fn update_peer_head(&self, peer_id: PeerId, ts: Arc<Tipset>) {
         let mut peers = self.peers.write().await;
         trace!("Updating head for PeerId {}", &peer_id);
+        peers.heads.insert(
+            peer_id,
+            PeerHead {
+                tipset_keys: ts.key().clone(),
+                epoch: ts.epoch(),
+                weight: ts.weight().clone(),
+                updated: SystemTime::now(),
+            },
+        );
         if let Some(pi) = peers.full_peers.get_mut(&peer_id) {
             pi.head = Some(ts);
         } else {
@@ -104,6 +509,38 @@ impl PeerManager {
         }
     }
 
+    /// Records the head a peer advertised without sending the headers, e.g.
+    /// in a hello request.
+    pub async fn update_peer_head_hint(
+        &self,
+        peer_id: PeerId,
+        tipset_keys: TipsetKeys,
+        epoch: ChainEpoch,
+        weight: BigInt,
+    ) {
+        trace!("Updating head hint for PeerId {}", &peer_id);
+        self.peers.write().await.heads.insert(
+            peer_id,
+            PeerHead {
+                tipset_keys,
+                epoch,
+                weight,
+                updated: SystemTime::now(),
+            },
+        );
+    }
+
+    /// Returns the latest head advertised by each connected peer, and the
+    /// heads restored from the settings store until those peers reconnect.
+    pub async fn peer_heads(&self) -> Vec<(PeerId, PeerHead)> {
+        let peers = self.peers.read().await;
+        peers
+            .heads
+            .iter()
+            .map(|(peer, head)| (*peer, head.clone()))
+            .collect()
+    }
+
     /// Returns true if peer is not marked as bad or not already in set.
     pub async fn is_peer_new(&self, peer_id: &PeerId) -> bool {
         let peers = self.peers.read().await;
@@ -119,6 +556,7 @@ impl PeerManager {
             .full_peers
             .iter()
             .map(|(p, info)| {
+                let deprioritized = peer_lk.deprioritized.contains(p);
                 let cost = if (info.successes + info.failures) > 0 {
                     // Calculate cost based on fail rate and latency
                     let fail_rate = f64::from(info.failures) / f64::from(info.successes);
@@ -127,13 +565,16 @@ impl PeerManager {
                     // There have been no failures or successes
                     average_time.as_secs_f64() * NEW_PEER_MUL
                 };
-                (p, cost)
+                (p, deprioritized, cost)
             })
             .collect();
 
         // Unstable sort because hashmap iter order doesn't need to be preserved.
-        peers.sort_unstable_by(|(_, v1), (_, v2)| v1.partial_cmp(v2).unwrap_or(Ordering::Equal));
-        peers.into_iter().map(|(p, _)| p).cloned().collect()
+        peers.sort_unstable_by(|(_, d1, v1), (_, d2, v2)| {
+            d1.cmp(d2)
+                .then_with(|| v1.partial_cmp(v2).unwrap_or(Ordering::Equal))
+        });
+        peers.into_iter().map(|(p, _, _)| p).cloned().collect()
     }
 
     /// Return shuffled slice of ordered peers from the peer manager. Ordering
@@ -184,6 +625,12 @@ impl PeerManager {
         let peer_stats = peers.full_peers.entry(peer).or_default();
         peer_stats.successes += 1;
         log_time(peer_stats, dur);
+        let now = Instant::now();
+        peers
+            .scores
+            .entry(peer)
+            .or_insert_with(|| PeerScore::new(now))
+            .add(1, now);
     }
 
     /// Logs a failure for the given peer, and updates the average request
@@ -202,6 +649,124 @@ impl PeerManager {
         }
     }
 
+    /// Records the outcome of a request sent to a peer, in the statistics of
+    /// the current day.
+    pub async fn record_request(
+        &self,
+        peer: PeerId,
+        protocol: RequestProtocol,
+        outcome: RequestOutcome,
+    ) {
+        let mut outcomes = RequestOutcomes::default();
+        outcomes.record(outcome);
+        self.record_requests(peer, protocol, &outcomes).await;
+    }
+
+    /// Records the outcomes of several requests sent to a peer, in the
+    /// statistics of the current day.
+    pub async fn record_requests(
+        &self,
+        peer: PeerId,
+        protocol: RequestProtocol,
+        outcomes: &RequestOutcomes,
+    ) {
+        self.protocol_stats.write().await.record(
+            chrono::Utc::now().date_naive(),
+            peer,
+            protocol,
+            outcomes,
+        );
+    }
+
+    /// Returns the outcomes of the requests sent to each peer, by protocol and
+    /// by day.
+    pub async fn protocol_stats(&self) -> Vec<DailyProtocolStats> {
+        self.protocol_stats.read().await.summaries()
+    }
+
+    /// Writes the protocol statistics to their file, if any.
+    pub async fn save_protocol_stats(&self) {
+        self.save_protocol_stats_with(&*self.protocol_stats.read().await);
+    }
+
+    /// Writes the protocol statistics to their file without waiting, for when
+    /// the node stops. Nothing is written if they are being updated.
+    pub fn save_protocol_stats_now(&self) {
+        if let Ok(stats) = self.protocol_stats.try_read() {
+            self.save_protocol_stats_with(&stats);
+        }
+    }
+
+    fn save_protocol_stats_with(&self, stats: &ProtocolStats) {
+        if let Some(path) = &self.protocol_stats_path {
+            if let Err(e) = stats.save(path) {
+                warn!(
+                    "Failed to save the protocol statistics to {}: {e}",
+                    path.display()
+                );
+            }
+        }
+    }
+
+    /// Writes the peer heads to the settings store, if any.
+    pub async fn save_peer_heads(&self) {
+        if let Some(store) = &self.peer_heads_store {
+            let heads: Vec<_> = self
+                .peers
+                .read()
+                .await
+                .heads
+                .iter()
+                .map(|(peer, head)| PersistedPeerHead {
+                    peer: peer.to_string(),
+                    tipset_keys: head.tipset_keys.clone(),
+                    epoch: head.epoch,
+                    weight: head.weight.clone(),
+                    updated: head
+                        .updated
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs(),
+                })
+                .collect();
+            if let Err(e) = store.write_obj(PEER_HEADS_KEY, &heads) {
+                warn!("Failed to save the peer heads: {e}");
+            }
+        }
+    }
+
+    /// Lowers the score of a peer for misbehaving. Peers whose score drops to
+    /// [`BAN_PEER_SCORE`] are disconnected and banned for
+    /// [`SCORE_BAN_DURATION`].
+    pub async fn penalize(&self, peer: PeerId, penalty: PeerPenalty) {
+        metrics::PEER_PENALTY_TOTAL
+            .with_label_values(&[penalty.label()])
+            .inc();
+        let score = {
+            let mut peers = self.peers.write().await;
+            let now = Instant::now();
+            let score = peers
+                .scores
+                .entry(peer)
+                .or_insert_with(|| PeerScore::new(now))
+                .add(-penalty.score(), now);
+            if score <= BAN_PEER_SCORE {
+                peers.scores.remove(&peer);
+            }
+            score
+        };
+        debug!("penalized peer {peer} for {penalty:?}, score: {score}");
+        if score <= BAN_PEER_SCORE {
+            self.mark_peer_bad(peer).await;
+            self.ban_peer(
+                peer,
+                format!("score of {score} after {}", penalty.label()),
+                Some(SCORE_BAN_DURATION),
+            )
+            .await;
+        }
+    }
+
     /// Removes a peer from the set and returns true if the value was present
     /// previously
     pub async fn mark_peer_bad(&self, peer_id: PeerId) -> bool {
@@ -244,7 +809,12 @@ impl PeerManager {
         duration: Option<Duration>,
     ) {
         let mut locked = self.peer_ban_list.write().await;
-        locked.insert(peer, duration.and_then(|d| Instant::now().checked_add(d)));
+        locked.insert(
+            peer,
+            duration.and_then(|d| SystemTime::now().checked_add(d)),
+        );
+        self.save_ban_list(&locked);
+        drop(locked);
         if let Err(e) = self
             .peer_ops_tx
             .send_async(PeerOperation::Ban(peer, reason.into()))
@@ -260,6 +830,13 @@ impl PeerManager {
             unban_list.clear();
 
             let now = Instant::now();
+            self.peers
+                .write()
+                .await
+                .scores
+                .retain(|_, score| score.value(now) != 0);
+
+            let now = SystemTime::now();
             for (peer, expiration) in self.peer_ban_list.read().await.iter() {
                 if let Some(expiration) = expiration {
                     if &now > expiration {
@@ -273,6 +850,7 @@ impl PeerManager {
                     for peer in unban_list.iter() {
                         locked.remove(peer);
                     }
+                    self.save_ban_list(&locked);
                 }
                 for &peer in unban_list.iter() {
                     if let Err(e) = self
@@ -287,6 +865,63 @@ impl PeerManager {
             tokio::time::sleep(Duration::from_secs(60)).await;
         }
     }
+
+    fn save_ban_list(&self, bans: &HashMap<PeerId, Option<SystemTime>>) {
+        if let Some(store) = &self.ban_list_store {
+            let bans: Vec<_> = bans
+                .iter()
+                .map(|(peer, expiration)| PersistedBan {
+                    peer: peer.to_string(),
+                    expiration: expiration.map(|expiration| {
+                        expiration
+                            .duration_since(UNIX_EPOCH)
+                            .unwrap_or_default()
+                            .as_secs()
+                    }),
+                })
+                .collect();
+            if let Err(e) = store.write_obj(BANNED_PEERS_KEY, &bans) {
+                warn!("Failed to save the peer ban list: {e}");
+            }
+        }
+    }
+}
+
+/// Reads the unexpired bans of a ban list persisted by
+/// [`PeerManager::save_ban_list`]. A missing key is an empty list.
+fn load_ban_list(store: &dyn SettingsStore) -> anyhow::Result<HashMap<PeerId, Option<SystemTime>>> {
+    let bans: Vec<PersistedBan> = store.read_obj(BANNED_PEERS_KEY)?.unwrap_or_default();
+    let now = SystemTime::now();
+    let mut ban_list = HashMap::default();
+    for ban in bans {
+        let expiration = ban
+            .expiration
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+        if expiration.map_or(true, |expiration| expiration > now) {
+            ban_list.insert(ban.peer.parse()?, expiration);
+        }
+    }
+    Ok(ban_list)
+}
+
+/// Reads the heads persisted by [`PeerManager::save_peer_heads`]. A missing
+/// key is an empty map.
+fn load_peer_heads(store: &dyn SettingsStore) -> anyhow::Result<HashMap<PeerId, PeerHead>> {
+    let heads: Vec<PersistedPeerHead> = store.read_obj(PEER_HEADS_KEY)?.unwrap_or_default();
+    heads
+        .into_iter()
+        .map(|head| {
+            Ok((
+                head.peer.parse()?,
+                PeerHead {
+                    tipset_keys: head.tipset_keys,
+                    epoch: head.epoch,
+                    weight: head.weight,
+                    updated: UNIX_EPOCH + Duration::from_secs(head.updated),
+                },
+            ))
+        })
+        .collect()
 }
 
 fn remove_peer(peers: &mut PeerSets, peer_id: &PeerId) -> bool {
@@ -296,6 +931,9 @@ fn remove_peer(peers: &mut PeerSets, peer_id: &PeerId) -> bool {
         peers.full_peers.len()
     );
 
+    peers.heads.remove(peer_id);
+    peers.agents.remove(peer_id);
+    peers.deprioritized.remove(peer_id);
     peers.full_peers.remove(peer_id).is_some()
 }
 
@@ -315,3 +953,211 @@ pub enum PeerOperation {
     Ban(PeerId, String),
     Unban(PeerId),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cid::multihash::MultihashDigest;
+    use cid::Cid;
+
+    fn head(key: u64, epoch: ChainEpoch, age: Duration) -> PeerHead {
+        let cid = Cid::new_v1(
+            fvm_ipld_encoding::DAG_CBOR,
+            cid::multihash::Code::Identity.digest(&key.to_be_bytes()),
+        );
+        PeerHead {
+            tipset_keys: TipsetKeys::from(vec![cid]),
+            epoch,
+            weight: BigInt::from(epoch),
+            updated: SystemTime::UNIX_EPOCH + Duration::from_secs(3600) - age,
+        }
+    }
+
+    #[test]
+    fn network_head_is_most_advertised() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(3600);
+        let fresh = Duration::from_secs(10);
+        let heads = [
+            head(1, 100, fresh),
+            head(1, 100, fresh),
+            head(2, 101, fresh),
+            // Stale heads are ignored
+            head(3, 90, Duration::from_secs(600)),
+            head(3, 90, Duration::from_secs(600)),
+            head(3, 90, Duration::from_secs(600)),
+        ];
+        let network_head = estimate_network_head(&heads, now, Duration::from_secs(60)).unwrap();
+        assert_eq!(network_head.tipset_keys, heads[0].tipset_keys);
+        assert_eq!(network_head.peers, 2);
+        assert_eq!(network_head.total_peers, 3);
+        assert!(network_head.is_majority());
+    }
+
+    #[tokio::test]
+    async fn peer_heads_are_persisted() {
+        let store = Arc::new(crate::db::MemoryDB::default());
+        let manager = PeerManager::default().with_peer_heads(store.clone());
+        let peer = PeerId::random();
+        let head = head(1, 100, Duration::ZERO);
+        manager
+            .update_peer_head_hint(
+                peer,
+                head.tipset_keys.clone(),
+                head.epoch,
+                head.weight.clone(),
+            )
+            .await;
+        manager.save_peer_heads().await;
+
+        let restored = PeerManager::default().with_peer_heads(store);
+        let heads = restored.peer_heads().await;
+        assert_eq!(heads.len(), 1);
+        assert_eq!(heads[0].0, peer);
+        assert_eq!(heads[0].1.tipset_keys, head.tipset_keys);
+        assert_eq!(heads[0].1.weight, head.weight);
+    }
+
+    #[tokio::test]
+    async fn low_scoring_peers_are_banned() {
+        let manager = PeerManager::default();
+        let peer = PeerId::random();
+        manager.log_success(peer, Duration::from_secs(1)).await;
+        manager.penalize(peer, PeerPenalty::InvalidTipset).await;
+        manager.penalize(peer, PeerPenalty::InvalidTipset).await;
+        assert!(manager.peer_ban_list.read().await.is_empty());
+        assert_eq!(
+            manager.peers.read().await.scores[&peer].value(Instant::now()),
+            -99
+        );
+
+        manager.penalize(peer, PeerPenalty::Timeout).await;
+        assert!(manager.peer_ban_list.read().await.contains_key(&peer));
+        assert!(!manager.is_peer_new(&peer).await);
+        assert!(matches!(
+            manager.peer_ops_rx().try_recv(),
+            Ok(PeerOperation::Ban(banned, _)) if banned == peer
+        ));
+    }
+
+    #[test]
+    fn scores_decay_towards_zero() {
+        let now = Instant::now();
+        let mut score = PeerScore::new(now);
+        assert_eq!(score.add(-50, now), -50);
+        assert_eq!(score.value(now + SCORE_DECAY_INTERVAL * 10), -40);
+        assert_eq!(score.value(now + SCORE_DECAY_INTERVAL * 100), 0);
+        // Decayed points are forgotten on the next update.
+        let later = now + SCORE_DECAY_INTERVAL * 45;
+        assert_eq!(score.add(MAX_PEER_SCORE * 2, later), MAX_PEER_SCORE);
+        assert_eq!(score.value(later + SCORE_DECAY_INTERVAL * 5), 15);
+    }
+
+    #[tokio::test]
+    async fn ban_list_is_persisted() {
+        let store = Arc::new(crate::db::MemoryDB::default());
+        let (banned, permanent, expired) = (PeerId::random(), PeerId::random(), PeerId::random());
+        {
+            let manager = PeerManager::with_ban_list(store.clone());
+            manager
+                .ban_peer(banned, "test", Some(Duration::from_secs(3600)))
+                .await;
+            manager.ban_peer(permanent, "test", None).await;
+            manager
+                .ban_peer(expired, "test", Some(Duration::ZERO))
+                .await;
+        }
+
+        let manager = PeerManager::with_ban_list(store);
+        let bans = manager.peer_ban_list.read().await;
+        assert_eq!(bans.len(), 2);
+        assert!(bans[&banned].is_some());
+        assert!(bans[&permanent].is_none());
+        // Restored bans are applied to the swarm.
+        assert_eq!(manager.peer_ops_rx().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn old_agents_are_deprioritized() {
+        let manager = PeerManager::default().with_agent_rules(vec![AgentRule {
+            client: "lotus".into(),
+            min_version: "1.23.3".into(),
+            action: AgentAction::Deprioritize,
+        }]);
+        let (fast_old, slow_new) = (PeerId::random(), PeerId::random());
+        manager.log_success(fast_old, Duration::from_secs(1)).await;
+        manager.log_success(slow_new, Duration::from_secs(5)).await;
+        assert_eq!(manager.sorted_peers().await, [fast_old, slow_new]);
+
+        assert_eq!(
+            manager
+                .update_peer_agent(fast_old, "lotus-1.23.2+mainnet")
+                .await,
+            Some(AgentAction::Deprioritize)
+        );
+        assert_eq!(
+            manager
+                .update_peer_agent(slow_new, "lotus-1.23.3+mainnet")
+                .await,
+            None
+        );
+        assert_eq!(manager.sorted_peers().await, [slow_new, fast_old]);
+        assert_eq!(manager.peer_agents().await.len(), 2);
+
+        manager.remove_peer(&fast_old).await;
+        assert_eq!(
+            manager.peer_agents().await,
+            [("lotus-1.23.3+mainnet".to_owned(), false)]
+        );
+    }
+
+    #[tokio::test]
+    async fn connected_peers_are_detailed() {
+        let manager = PeerManager::default();
+        let (peer, other) = (PeerId::random(), PeerId::random());
+        manager
+            .record_connection(peer, "/ip4/1.2.3.4/udp/1234/quic-v1".parse().unwrap(), true)
+            .await;
+        // Peers that aren't connected are ignored.
+        manager
+            .update_peer_latency(&other, Duration::from_millis(5))
+            .await;
+        manager
+            .update_peer_latency(&peer, Duration::from_millis(42))
+            .await;
+        manager
+            .update_peer_protocols(&peer, vec!["/ipfs/ping/1.0.0".into()])
+            .await;
+        manager.log_gossip_received(&peer, 100).await;
+        manager.log_gossip_received(&peer, 28).await;
+        manager.update_peer_agent(peer, "lotus-1.23.3").await;
+        manager.penalize(peer, PeerPenalty::Timeout).await;
+
+        let details = manager.peer_details().await;
+        assert_eq!(details.len(), 1);
+        let details = &details[0];
+        assert_eq!(details.peer, peer);
+        assert!(details.connection.inbound);
+        assert_eq!(details.connection.transport(), "quic-v1");
+        assert_eq!(details.connection.latency, Some(Duration::from_millis(42)));
+        assert_eq!(details.connection.protocols, ["/ipfs/ping/1.0.0"]);
+        assert_eq!(details.connection.gossip_bytes_received, 128);
+        assert_eq!(details.agent.as_deref(), Some("lotus-1.23.3"));
+        assert_eq!(details.score, -10);
+
+        manager.remove_connection(&peer).await;
+        assert!(manager.peer_details().await.is_empty());
+    }
+
+    #[test]
+    fn network_head_ties_are_broken_by_weight() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(3600);
+        let heads = [head(1, 100, Duration::ZERO), head(2, 101, Duration::ZERO)];
+        let network_head = estimate_network_head(&heads, now, Duration::from_secs(60)).unwrap();
+        assert_eq!(network_head.epoch, 101);
+        assert!(!network_head.is_majority());
+        assert_eq!(
+            estimate_network_head(&[], now, Duration::from_secs(60)),
+            None
+        );
+    }
+}
@@ -0,0 +1,196 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Outcomes of the requests sent to each peer, by protocol and by day. Unlike
+//! the peer scores, they are persisted, so that the peers that keep failing
+//! requests over days can be told apart from the ones that had a bad moment.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+use super::PeerId;
+use crate::rpc_api::net_api::{DailyProtocolStats, RequestOutcomes, RequestProtocol};
+use ahash::HashMap;
+use chrono::NaiveDate;
+
+/// Number of days of statistics kept.
+const PROTOCOL_STATS_DAYS: usize = 30;
+
+/// Maximum number of peer and protocol pairs kept per day. Past it, the pair
+/// with the fewest requests is forgotten.
+const MAX_PROTOCOL_STATS_ENTRIES: usize = 4096;
+
+/// Outcome of a request sent to a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestOutcome {
+    Success,
+    /// The peer didn't respond in time.
+    Timeout,
+    /// The peer sent a malformed or invalid response.
+    Invalid,
+    /// The peer responded with an error status.
+    Error,
+}
+
+impl RequestOutcomes {
+    pub fn record(&mut self, outcome: RequestOutcome) {
+        match outcome {
+            RequestOutcome::Success => self.success += 1,
+            RequestOutcome::Timeout => self.timeout += 1,
+            RequestOutcome::Invalid => self.invalid += 1,
+            RequestOutcome::Error => self.error += 1,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(in crate::libp2p) struct ProtocolStats {
+    days: BTreeMap<NaiveDate, HashMap<(PeerId, RequestProtocol), RequestOutcomes>>,
+}
+
+impl ProtocolStats {
+    /// Adds `outcomes` to the statistics of `peer` on `day`, and forgets the
+    /// days older than [`PROTOCOL_STATS_DAYS`].
+    pub fn record(
+        &mut self,
+        day: NaiveDate,
+        peer: PeerId,
+        protocol: RequestProtocol,
+        outcomes: &RequestOutcomes,
+    ) {
+        let stats = self.days.entry(day).or_default();
+        if stats.len() >= MAX_PROTOCOL_STATS_ENTRIES && !stats.contains_key(&(peer, protocol)) {
+            if let Some(fewest) = stats
+                .iter()
+                .min_by_key(|(_, outcomes)| outcomes.total())
+                .map(|(key, _)| *key)
+            {
+                stats.remove(&fewest);
+            }
+        }
+        stats.entry((peer, protocol)).or_default().add(outcomes);
+        while self.days.len() > PROTOCOL_STATS_DAYS {
+            self.days.pop_first();
+        }
+    }
+
+    /// Returns the statistics of each day, oldest first.
+    pub fn summaries(&self) -> Vec<DailyProtocolStats> {
+        self.days
+            .iter()
+            .flat_map(|(day, stats)| {
+                stats
+                    .iter()
+                    .map(|((peer, protocol), outcomes)| DailyProtocolStats {
+                        day: *day,
+                        peer: peer.to_string(),
+                        protocol: *protocol,
+                        outcomes: *outcomes,
+                    })
+            })
+            .collect()
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let mut stats = Self::default();
+        if !path.exists() {
+            return Ok(stats);
+        }
+        let summaries: Vec<DailyProtocolStats> = serde_json::from_slice(&std::fs::read(path)?)?;
+        for summary in summaries {
+            stats.record(
+                summary.day,
+                summary.peer.parse()?,
+                summary.protocol,
+                &summary.outcomes,
+            );
+        }
+        Ok(stats)
+    }
+
+    /// Writes the statistics to a temporary file renamed to `path`, so that a
+    /// crash doesn't leave a truncated file.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut file =
+            tempfile::NamedTempFile::new_in(path.parent().unwrap_or_else(|| Path::new(".")))?;
+        file.write_all(&serde_json::to_vec(&self.summaries())?)?;
+        file.as_file().sync_all()?;
+        file.persist(path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn days_are_aggregated_and_pruned() {
+        let peer = PeerId::random();
+        let day = |n| NaiveDate::from_ymd_opt(2023, 1, 1).unwrap() + chrono::Days::new(n);
+        let mut outcomes = RequestOutcomes::default();
+        outcomes.record(RequestOutcome::Success);
+        outcomes.record(RequestOutcome::Timeout);
+
+        let mut stats = ProtocolStats::default();
+        stats.record(day(0), peer, RequestProtocol::Bitswap, &outcomes);
+        stats.record(day(0), peer, RequestProtocol::Bitswap, &outcomes);
+        stats.record(day(0), peer, RequestProtocol::ChainExchange, &outcomes);
+        let summaries = stats.summaries();
+        assert_eq!(summaries.len(), 2);
+        let bitswap = summaries
+            .iter()
+            .find(|summary| summary.protocol == RequestProtocol::Bitswap)
+            .unwrap();
+        assert_eq!((bitswap.outcomes.success, bitswap.outcomes.timeout), (2, 2));
+
+        for n in 1..=PROTOCOL_STATS_DAYS as u64 {
+            stats.record(day(n), peer, RequestProtocol::Bitswap, &outcomes);
+        }
+        let summaries = stats.summaries();
+        assert_eq!(summaries.len(), PROTOCOL_STATS_DAYS);
+        assert_eq!(summaries[0].day, day(1));
+    }
+
+    #[test]
+    fn peers_with_fewest_requests_are_forgotten() {
+        let day = NaiveDate::from_ymd_opt(2023, 1, 1).unwrap();
+        let mut outcomes = RequestOutcomes::default();
+        outcomes.record(RequestOutcome::Success);
+
+        let mut stats = ProtocolStats::default();
+        let busy = PeerId::random();
+        stats.record(day, busy, RequestProtocol::Bitswap, &outcomes);
+        stats.record(day, busy, RequestProtocol::Bitswap, &outcomes);
+        for _ in 0..MAX_PROTOCOL_STATS_ENTRIES {
+            stats.record(day, PeerId::random(), RequestProtocol::Bitswap, &outcomes);
+        }
+        let summaries = stats.summaries();
+        assert_eq!(summaries.len(), MAX_PROTOCOL_STATS_ENTRIES);
+        assert!(summaries
+            .iter()
+            .any(|summary| summary.peer == busy.to_string()));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("protocol_stats.json");
+        let mut outcomes = RequestOutcomes::default();
+        outcomes.record(RequestOutcome::Invalid);
+        outcomes.record(RequestOutcome::Error);
+        let mut stats = ProtocolStats::default();
+        stats.record(
+            NaiveDate::from_ymd_opt(2023, 1, 1).unwrap(),
+            PeerId::random(),
+            RequestProtocol::ChainExchange,
+            &outcomes,
+        );
+        stats.save(&path).unwrap();
+        assert_eq!(
+            ProtocolStats::load(&path).unwrap().summaries(),
+            stats.summaries()
+        );
+    }
+}
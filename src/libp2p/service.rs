@@ -3,14 +3,19 @@
 
 use std::{
     sync::Arc,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use crate::libp2p_bitswap::{
     request_manager::BitswapRequestManager, BitswapStoreRead, BitswapStoreReadWrite,
 };
-use crate::message::SignedMessage;
-use crate::{blocks::GossipBlock, rpc_api::net_api::NetInfoResult};
+use crate::message::{Message as _, SignedMessage};
+use crate::networks::ChainConfig;
+use crate::shim::address::{Address, Protocol as AddressProtocol};
+use crate::{
+    blocks::GossipBlock,
+    rpc_api::net_api::{NetInfoResult, PubsubTopicStats, RequestOutcomes, RequestProtocol},
+};
 use crate::{chain::ChainStore, utils::encoding::from_slice_with_fallback};
 use ahash::{HashMap, HashSet};
 use anyhow::Context;
@@ -24,7 +29,7 @@ pub use libp2p::gossipsub::{IdentTopic, Topic};
 use libp2p::swarm::DialError;
 use libp2p::{
     core::{self, muxing::StreamMuxerBox, transport::Boxed, Multiaddr},
-    gossipsub,
+    gossipsub, identify,
     identity::Keypair,
     metrics::{Metrics, Recorder},
     multiaddr::Protocol,
@@ -38,12 +43,16 @@ use tracing::{debug, error, info, trace, warn};
 
 use super::{
     chain_exchange::{make_chain_exchange_response, ChainExchangeRequest, ChainExchangeResponse},
+    gossip_stats::GossipStats,
+    metrics::GOSSIP_MESH_PEERS,
     ForestBehaviour, ForestBehaviourEvent, Libp2pConfig,
 };
 use crate::libp2p::{
+    agent_policy::AgentAction,
     chain_exchange::ChainExchangeBehaviour,
     discovery::DiscoveryEvent,
-    hello::{HelloBehaviour, HelloRequest, HelloResponse},
+    graphsync::{GraphSyncBehaviour, GraphSyncMessage, GraphSyncServer},
+    hello::{ChainIdentity, HelloBehaviour, HelloRequest, HelloResponse},
     rpc::RequestResponseError,
     PeerManager, PeerOperation,
 };
@@ -87,10 +96,44 @@ pub const PUBSUB_MSG_STR: &str = "/fil/msgs";
 
 const PUBSUB_TOPICS: [&str; 2] = [PUBSUB_BLOCK_STR, PUBSUB_MSG_STR];
 
+/// `Gossipsub` topics of the messages, one per shard, see
+/// [`ChainConfig::message_topic_shards`].
+pub fn message_topics(chain_config: &ChainConfig, network_name: &str) -> Vec<IdentTopic> {
+    match chain_config.message_topic_shards {
+        0 | 1 => vec![Topic::new(format!("{PUBSUB_MSG_STR}/{network_name}"))],
+        shards => (0..shards)
+            .map(|shard| Topic::new(format!("{PUBSUB_MSG_STR}/{network_name}/{shard}")))
+            .collect(),
+    }
+}
+
+/// `Gossipsub` topic the messages sent by `from` are published on. `from` must
+/// be the key address of the sender, so that all of its messages land on the
+/// same shard whatever address they use.
+pub fn message_topic(chain_config: &ChainConfig, network_name: &str, from: &Address) -> IdentTopic {
+    let mut topics = message_topics(chain_config, network_name);
+    topics.swap_remove(chain_config.message_topic_shard(from) as usize)
+}
+
+/// `Gossipsub` topics of `base`, one of [`PUBSUB_TOPICS`].
+fn gossip_topics(base: &str, chain_config: &ChainConfig, network_name: &str) -> Vec<IdentTopic> {
+    match base {
+        PUBSUB_MSG_STR => message_topics(chain_config, network_name),
+        _ => vec![Topic::new(format!("{base}/{network_name}"))],
+    }
+}
+
 pub const BITSWAP_TIMEOUT: Duration = Duration::from_secs(10);
 
 const BAN_PEER_DURATION: Duration = Duration::from_secs(60 * 60); //1h
 
+/// `GraphSync` response messages waiting to be sent, of up to about 1 MiB
+/// each. The traversals serving the requests wait when it is full.
+const GRAPHSYNC_RESPONSE_BUFFER: usize = 16;
+
+/// Interval between the writes of the protocol statistics of the peers.
+const PROTOCOL_STATS_SAVE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
 /// Events emitted by this Service.
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
@@ -173,6 +216,7 @@ pub enum NetRPCMethods {
     Info(OneShotSender<NetInfoResult>),
     Connect(OneShotSender<bool>, PeerId, HashSet<Multiaddr>),
     Disconnect(OneShotSender<()>, PeerId),
+    PubsubStats(OneShotSender<Vec<PubsubTopicStats>>),
 }
 
 /// The `Libp2pService` listens to events from the libp2p swarm.
@@ -180,6 +224,7 @@ pub struct Libp2pService<DB> {
     config: Libp2pConfig,
     swarm: Swarm<ForestBehaviour>,
     cs: Arc<ChainStore<DB>>,
+    chain_config: Arc<ChainConfig>,
     peer_manager: Arc<PeerManager>,
     network_receiver_in: flume::Receiver<NetworkMessage>,
     network_sender_in: Sender<NetworkMessage>,
@@ -196,6 +241,7 @@ where
     pub fn new(
         config: Libp2pConfig,
         cs: Arc<ChainStore<DB>>,
+        chain_config: Arc<ChainConfig>,
         peer_manager: Arc<PeerManager>,
         net_keypair: Keypair,
         network_name: &str,
@@ -208,7 +254,7 @@ where
 
         let mut swarm = SwarmBuilder::with_tokio_executor(
             transport,
-            ForestBehaviour::new(&net_keypair, &config, network_name)?,
+            ForestBehaviour::new(&net_keypair, &config, &chain_config, network_name)?,
             peer_id,
         )
         .notify_handler_buffer_size(std::num::NonZeroUsize::new(20).expect("Not zero"))
@@ -216,9 +262,10 @@ where
         .build();
 
         // Subscribe to gossipsub topics with the network name suffix
-        for topic in PUBSUB_TOPICS.iter() {
-            let t = Topic::new(format!("{topic}/{network_name}"));
-            swarm.behaviour_mut().subscribe(&t).unwrap();
+        for base in PUBSUB_TOPICS {
+            for topic in gossip_topics(base, &chain_config, network_name) {
+                swarm.behaviour_mut().subscribe(&topic).unwrap();
+            }
         }
 
         let (network_sender_in, network_receiver_in) = flume::unbounded();
@@ -228,6 +275,7 @@ where
             config,
             swarm,
             cs,
+            chain_config,
             peer_manager,
             network_receiver_in,
             network_sender_in,
@@ -254,21 +302,30 @@ where
         }
 
         let bitswap_request_manager = self.swarm.behaviour().bitswap.request_manager();
+        let graphsync_server = Arc::new(GraphSyncServer::new(self.config.graphsync_server_limits));
         let mut swarm_stream = self.swarm.fuse();
         let mut network_stream = self.network_receiver_in.stream().fuse();
         let mut interval =
             IntervalStream::new(tokio::time::interval(Duration::from_secs(15))).fuse();
+        let mut protocol_stats_interval =
+            IntervalStream::new(tokio::time::interval(PROTOCOL_STATS_SAVE_INTERVAL)).fuse();
         let pubsub_block_str = format!("{}/{}", PUBSUB_BLOCK_STR, self.network_name);
-        let pubsub_msg_str = format!("{}/{}", PUBSUB_MSG_STR, self.network_name);
+        let pubsub_msg_topics: Vec<_> = message_topics(&self.chain_config, &self.network_name)
+            .into_iter()
+            .map(|topic| topic.to_string())
+            .collect();
 
         let (cx_response_tx, cx_response_rx) = flume::unbounded();
 
         let mut cx_response_rx_stream = cx_response_rx.stream().fuse();
+        let (graphsync_tx, graphsync_rx) = flume::bounded(GRAPHSYNC_RESPONSE_BUFFER);
+        let mut graphsync_rx_stream = graphsync_rx.stream().fuse();
         let mut bitswap_outbound_request_rx_stream = bitswap_request_manager
             .outbound_request_rx()
             .stream()
             .fuse();
         let mut peer_ops_rx_stream = self.peer_manager.peer_ops_rx().stream().fuse();
+        let mut gossip_stats = GossipStats::new(self.config.gossip_samples);
         let mut libp2p_registry = Default::default();
         let metrics = Metrics::new(&mut libp2p_registry);
         crate::metrics::add_metrics_registry("libp2p".into(), libp2p_registry).await;
@@ -281,14 +338,34 @@ where
                         handle_forest_behaviour_event(
                             swarm_stream.get_mut(),
                             &bitswap_request_manager,
+                            &graphsync_server,
+                            &graphsync_tx,
                             &self.peer_manager,
                             event,
                             &self.cs,
                             &self.genesis_cid,
+                            &self.network_name,
+                            &self.chain_config,
                             &self.network_sender_out,
                             cx_response_tx.clone(),
                             &pubsub_block_str,
-                            &pubsub_msg_str,).await;
+                            &pubsub_msg_topics,
+                            &mut gossip_stats,).await;
+                    },
+                    Some(SwarmEvent::ConnectionEstablished { peer_id, endpoint, num_established, .. }) => {
+                        if num_established.get() == 1 {
+                            self.peer_manager
+                                .record_connection(
+                                    peer_id,
+                                    endpoint.get_remote_address().clone(),
+                                    endpoint.is_listener(),
+                                )
+                                .await;
+                        }
+                    },
+                    Some(SwarmEvent::ConnectionClosed { peer_id, num_established: 0, .. }) => {
+                        graphsync_server.on_peer_disconnected(&peer_id);
+                        self.peer_manager.remove_connection(&peer_id).await;
                     },
                     None => { break; },
                     _ => { },
@@ -301,13 +378,38 @@ where
                             self.cs.clone(),
                             bitswap_request_manager.clone(),
                             message,
-                            &self.network_sender_out).await;
+                            &self.network_name,
+                            &self.chain_config,
+                            &self.network_sender_out,
+                            &mut gossip_stats).await;
                     }
                     None => { break; }
                 },
                 interval_event = interval.next() => if interval_event.is_some() {
                     // Print peer count on an interval.
                     debug!("Peers connected: {}", swarm_stream.get_mut().behaviour_mut().peers().len());
+                    for base in PUBSUB_TOPICS {
+                        let mesh_peers: usize = gossip_topics(base, &self.chain_config, &self.network_name)
+                            .iter()
+                            .map(|topic| swarm_stream.get_mut().behaviour().mesh_peer_count(topic))
+                            .sum();
+                        GOSSIP_MESH_PEERS.with_label_values(&[base]).set(mesh_peers as u64);
+                    }
+                    for (peer, stats) in bitswap_request_manager.take_peer_stats() {
+                        let outcomes = RequestOutcomes {
+                            success: stats.blocks,
+                            timeout: stats.timeouts,
+                            invalid: stats.invalid_blocks,
+                            error: 0,
+                        };
+                        self.peer_manager
+                            .record_requests(peer, RequestProtocol::Bitswap, &outcomes)
+                            .await;
+                    }
+                },
+                protocol_stats_event = protocol_stats_interval.next() => if protocol_stats_event.is_some() {
+                    self.peer_manager.save_protocol_stats().await;
+                    self.peer_manager.save_peer_heads().await;
                 },
                 cs_pair_opt = cx_response_rx_stream.next() => {
                     if let Some((_request_id, channel, cx_response)) = cs_pair_opt {
@@ -317,6 +419,12 @@ where
                         }
                     }
                 },
+                graphsync_message_opt = graphsync_rx_stream.next() => {
+                    if let Some((peer, message)) = graphsync_message_opt {
+                        let graphsync = &mut swarm_stream.get_mut().behaviour_mut().graphsync;
+                        graphsync.send_request(&peer, message);
+                    }
+                },
                 bitswap_outbound_request_opt = bitswap_outbound_request_rx_stream.next() => {
                     if let Some((peer, request)) = bitswap_outbound_request_opt {
                         let bitswap = &mut swarm_stream.get_mut().behaviour_mut().bitswap;
@@ -358,24 +466,43 @@ fn handle_peer_ops(swarm: &mut Swarm<ForestBehaviour>, peer_ops: PeerOperation)
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_network_message(
     swarm: &mut Swarm<ForestBehaviour>,
     store: Arc<impl BitswapStoreReadWrite>,
     bitswap_request_manager: Arc<BitswapRequestManager>,
     message: NetworkMessage,
+    network_name: &str,
+    chain_config: &ChainConfig,
     network_sender_out: &Sender<NetworkEvent>,
+    gossip_stats: &mut GossipStats,
 ) {
     match message {
         NetworkMessage::PubsubMessage { topic, message } => {
-            if let Err(e) = swarm.behaviour_mut().publish(topic, message) {
-                warn!("Failed to send gossipsub message: {:?}", e);
+            let base_topic = PUBSUB_TOPICS.into_iter().find(|base| {
+                gossip_topics(base, chain_config, network_name)
+                    .iter()
+                    .any(|t| t.hash() == topic.hash())
+            });
+            match swarm.behaviour_mut().publish(topic, message) {
+                Ok(_) => {
+                    if let Some(base_topic) = base_topic {
+                        gossip_stats.record_published(base_topic);
+                    }
+                }
+                Err(e) => warn!("Failed to send gossipsub message: {:?}", e),
             }
         }
         NetworkMessage::HelloRequest {
             peer_id,
-            request,
+            mut request,
             response_channel,
         } => {
+            request.identity = Some(ChainIdentity::new(
+                network_name,
+                chain_config,
+                request.heaviest_tipset_height,
+            ));
             let request_id =
                 swarm
                     .behaviour_mut()
@@ -470,6 +597,22 @@ async fn handle_network_message(
                         warn!("Failed to disconnect from a peer");
                     }
                 }
+                NetRPCMethods::PubsubStats(response_channel) => {
+                    let now = Instant::now();
+                    let stats = PUBSUB_TOPICS
+                        .into_iter()
+                        .map(|base| {
+                            let mesh_peers = gossip_topics(base, chain_config, network_name)
+                                .iter()
+                                .map(|topic| swarm.behaviour().mesh_peer_count(topic))
+                                .sum();
+                            gossip_stats.topic_stats(base, mesh_peers, now)
+                        })
+                        .collect();
+                    if response_channel.send(stats).is_err() {
+                        warn!("Failed to get gossipsub stats");
+                    }
+                }
             }
         }
     }
@@ -491,11 +634,16 @@ async fn handle_discovery_event(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_gossip_event(
     e: gossipsub::Event,
+    peer_manager: &PeerManager,
     network_sender_out: &Sender<NetworkEvent>,
+    chain_config: &ChainConfig,
+    network_name: &str,
     pubsub_block_str: &str,
-    pubsub_msg_str: &str,
+    pubsub_msg_topics: &[String],
+    gossip_stats: &mut GossipStats,
 ) {
     if let gossipsub::Event::Message {
         propagation_source: source,
@@ -505,36 +653,82 @@ async fn handle_gossip_event(
     {
         let topic = message.topic.as_str();
         let message = message.data;
+        let size = message.len();
         trace!("Got a Gossip Message from {:?}", source);
+        peer_manager.log_gossip_received(&source, size).await;
         if topic == pubsub_block_str {
             match from_slice_with_fallback::<GossipBlock>(&message) {
                 Ok(b) => {
+                    let message = PubsubMessage::Block(b);
+                    gossip_stats.record_received(
+                        PUBSUB_BLOCK_STR,
+                        source,
+                        size,
+                        Some(&message),
+                        Instant::now(),
+                    );
                     emit_event(
                         network_sender_out,
-                        NetworkEvent::PubsubMessage {
-                            source,
-                            message: PubsubMessage::Block(b),
-                        },
+                        NetworkEvent::PubsubMessage { source, message },
                     )
                     .await;
                 }
                 Err(e) => {
+                    gossip_stats.record_received(
+                        PUBSUB_BLOCK_STR,
+                        source,
+                        size,
+                        None,
+                        Instant::now(),
+                    );
                     warn!("Gossip Block from peer {source:?} could not be deserialized: {e}",);
                 }
             }
-        } else if topic == pubsub_msg_str {
+        } else if pubsub_msg_topics.iter().any(|t| t == topic) {
             match from_slice_with_fallback::<SignedMessage>(&message) {
+                // Messages must be gossiped on the shard of their sender. The
+                // ID addresses can't be resolved here, so only the messages
+                // sent from key addresses are checked.
+                Ok(m)
+                    if m.from().protocol() != AddressProtocol::ID
+                        && message_topic(chain_config, network_name, &m.from()).to_string()
+                            != topic =>
+                {
+                    gossip_stats.record_received(
+                        PUBSUB_MSG_STR,
+                        source,
+                        size,
+                        None,
+                        Instant::now(),
+                    );
+                    warn!(
+                        "Gossip Message {} from peer {source:?} was sent on the wrong topic: {topic}",
+                        m.cid().map(|c| c.to_string()).unwrap_or_default()
+                    );
+                }
                 Ok(m) => {
+                    let message = PubsubMessage::Message(m);
+                    gossip_stats.record_received(
+                        PUBSUB_MSG_STR,
+                        source,
+                        size,
+                        Some(&message),
+                        Instant::now(),
+                    );
                     emit_event(
                         network_sender_out,
-                        NetworkEvent::PubsubMessage {
-                            source,
-                            message: PubsubMessage::Message(m),
-                        },
+                        NetworkEvent::PubsubMessage { source, message },
                     )
                     .await;
                 }
                 Err(e) => {
+                    gossip_stats.record_received(
+                        PUBSUB_MSG_STR,
+                        source,
+                        size,
+                        None,
+                        Instant::now(),
+                    );
                     warn!("Gossip Message from peer {source:?} could not be deserialized: {e}");
                 }
             }
@@ -549,6 +743,8 @@ async fn handle_hello_event(
     event: request_response::Event<HelloRequest, HelloResponse, HelloResponse>,
     peer_manager: &Arc<PeerManager>,
     genesis_cid: &Cid,
+    network_name: &str,
+    chain_config: &ChainConfig,
     network_sender_out: &Sender<NetworkEvent>,
 ) {
     match event {
@@ -575,16 +771,12 @@ async fn handle_hello_event(
                     .expect("System time since unix epoch should not exceed u64");
 
                 trace!("Received hello request: {:?}", request);
-                if &request.genesis_cid != genesis_cid {
+                if let Err(mismatch) = request.verify(genesis_cid, network_name, chain_config) {
+                    crate::libp2p::metrics::HELLO_MISMATCH_TOTAL
+                        .with_label_values(&[mismatch.label()])
+                        .inc();
                     peer_manager
-                        .ban_peer(
-                            peer,
-                            format!(
-                                "Genesis hash mismatch: {} received, {genesis_cid} expected",
-                                request.genesis_cid
-                            ),
-                            Some(BAN_PEER_DURATION),
-                        )
+                        .ban_peer(peer, mismatch.to_string(), Some(BAN_PEER_DURATION))
                         .await;
                 } else {
                     let sent = SystemTime::now()
@@ -649,6 +841,9 @@ async fn handle_ping_event(ping_event: ping::Event, peer_manager: &Arc<PeerManag
                 ping_event.peer.to_base58(),
                 rtt.as_millis()
             );
+            peer_manager
+                .update_peer_latency(&ping_event.peer, rtt)
+                .await;
         }
         Err(ping::Failure::Unsupported) => {
             peer_manager
@@ -748,14 +943,50 @@ async fn handle_chain_exchange_event<DB>(
     }
 }
 
+fn handle_graphsync_event<DB>(
+    graphsync: &mut GraphSyncBehaviour,
+    graphsync_server: &Arc<GraphSyncServer>,
+    event: request_response::Event<GraphSyncMessage, ()>,
+    db: &Arc<ChainStore<DB>>,
+    graphsync_tx: &Sender<(PeerId, GraphSyncMessage)>,
+) where
+    DB: Blockstore + Sync + Send + 'static,
+{
+    match event {
+        request_response::Event::Message {
+            peer,
+            message:
+                request_response::Message::Request {
+                    request, channel, ..
+                },
+        } => {
+            // Close the inbound stream right away, responses are sent on new
+            // streams.
+            let _ = graphsync.send_response(channel, ());
+            graphsync_server.handle_message(db, peer, request, graphsync_tx);
+        }
+        request_response::Event::OutboundFailure { peer, error, .. } => {
+            debug!("GraphSync outbound error (peer: {peer:?}): {error:?}");
+        }
+        request_response::Event::InboundFailure { peer, error, .. } => {
+            debug!("GraphSync inbound error (peer: {peer:?}): {error:?}");
+        }
+        _ => {}
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 async fn handle_forest_behaviour_event<DB>(
     swarm: &mut Swarm<ForestBehaviour>,
     bitswap_request_manager: &Arc<BitswapRequestManager>,
+    graphsync_server: &Arc<GraphSyncServer>,
+    graphsync_tx: &Sender<(PeerId, GraphSyncMessage)>,
     peer_manager: &Arc<PeerManager>,
     event: ForestBehaviourEvent,
     db: &Arc<ChainStore<DB>>,
     genesis_cid: &Cid,
+    network_name: &str,
+    chain_config: &ChainConfig,
     network_sender_out: &Sender<NetworkEvent>,
     cx_response_tx: Sender<(
         RequestId,
@@ -763,7 +994,8 @@ async fn handle_forest_behaviour_event<DB>(
         ChainExchangeResponse,
     )>,
     pubsub_block_str: &str,
-    pubsub_msg_str: &str,
+    pubsub_msg_topics: &[String],
+    gossip_stats: &mut GossipStats,
 ) where
     DB: Blockstore + BitswapStoreRead + Sync + Send + 'static,
 {
@@ -772,7 +1004,17 @@ async fn handle_forest_behaviour_event<DB>(
             handle_discovery_event(discovery_out, network_sender_out).await
         }
         ForestBehaviourEvent::Gossipsub(e) => {
-            handle_gossip_event(e, network_sender_out, pubsub_block_str, pubsub_msg_str).await
+            handle_gossip_event(
+                e,
+                peer_manager,
+                network_sender_out,
+                chain_config,
+                network_name,
+                pubsub_block_str,
+                pubsub_msg_topics,
+                gossip_stats,
+            )
+            .await
         }
         ForestBehaviourEvent::Hello(rr_event) => {
             handle_hello_event(
@@ -780,6 +1022,8 @@ async fn handle_forest_behaviour_event<DB>(
                 rr_event,
                 peer_manager,
                 genesis_cid,
+                network_name,
+                chain_config,
                 network_sender_out,
             )
             .await
@@ -793,7 +1037,34 @@ async fn handle_forest_behaviour_event<DB>(
                 warn!("bitswap: {e}");
             }
         }
+        ForestBehaviourEvent::Graphsync(event) => handle_graphsync_event(
+            &mut swarm.behaviour_mut().graphsync,
+            graphsync_server,
+            event,
+            db,
+            graphsync_tx,
+        ),
         ForestBehaviourEvent::Ping(ping_event) => handle_ping_event(ping_event, peer_manager).await,
+        ForestBehaviourEvent::Identify(identify::Event::Received { peer_id, info }) => {
+            peer_manager
+                .update_peer_protocols(
+                    &peer_id,
+                    info.protocols.iter().map(|p| p.to_string()).collect(),
+                )
+                .await;
+            let action = peer_manager
+                .update_peer_agent(peer_id, &info.agent_version)
+                .await;
+            if action == Some(AgentAction::Refuse) {
+                peer_manager
+                    .ban_peer(
+                        peer_id,
+                        format!("refused client version {}", info.agent_version),
+                        Some(BAN_PEER_DURATION),
+                    )
+                    .await;
+            }
+        }
         ForestBehaviourEvent::Identify(_) => {}
         ForestBehaviourEvent::KeepAlive(_) => {}
         ForestBehaviourEvent::ConnectionLimits(_) => {}
@@ -838,3 +1109,35 @@ pub fn build_transport(local_key: Keypair) -> anyhow::Result<Boxed<(PeerId, Stre
         .timeout(Duration::from_secs(20))
         .boxed())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_gossiped_on_the_shard_of_their_sender() {
+        let mut chain_config = ChainConfig::default();
+        let sender = Address::new_id(1234);
+        assert_eq!(
+            message_topic(&chain_config, "testnetnet", &sender).to_string(),
+            "/fil/msgs/testnetnet"
+        );
+
+        chain_config.message_topic_shards = 4;
+        let topics: Vec<_> = message_topics(&chain_config, "testnetnet")
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(topics.len(), 4);
+        assert_eq!(topics[3], "/fil/msgs/testnetnet/3");
+        let shards: HashSet<_> = (0..100)
+            .map(|id| message_topic(&chain_config, "testnetnet", &Address::new_id(id)).to_string())
+            .inspect(|topic| assert!(topics.contains(topic)))
+            .collect();
+        assert_eq!(shards.len(), 4);
+        assert_eq!(
+            message_topic(&chain_config, "testnetnet", &sender).to_string(),
+            message_topic(&chain_config, "testnetnet", &sender).to_string()
+        );
+    }
+}
@@ -36,6 +36,13 @@ impl BitswapBehaviour {
         }
     }
 
+    /// Limits the rate at which inbound requests are served, see
+    /// [`BitswapRateLimiter`].
+    pub fn with_server_limits(mut self, limits: BitswapServerLimits) -> Self {
+        self.request_manager = Arc::new(BitswapRequestManager::new(limits));
+        self
+    }
+
     /// Gets mutable borrow of the inner [`request_response::Behaviour`]
     pub fn inner_mut(&mut self) -> &mut request_response::Behaviour<BitswapRequestResponseCodec> {
         &mut self.inner
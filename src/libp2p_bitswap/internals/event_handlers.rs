@@ -33,8 +33,28 @@ pub fn handle_event_impl<S: BitswapStoreRead>(
                 for message in request {
                     match message {
                         BitswapMessage::Request(request) => {
+                            let rate_limiter = request_manager.rate_limiter();
+                            if !request.cancel && !rate_limiter.allow_request(&peer) {
+                                metrics::message_counter_inbound_request_denied_peer_rate().inc();
+                                continue;
+                            }
                             if let Some(response) = handle_inbound_request(store, &request) {
-                                bitswap.send_response(&peer, (request.cid, response));
+                                let allowed = match &response {
+                                    BitswapResponse::Block(data) => {
+                                        rate_limiter.allow_block(&peer, data.len())
+                                    }
+                                    BitswapResponse::Have(_) => {
+                                        rate_limiter.on_served(&peer);
+                                        true
+                                    }
+                                };
+                                if allowed {
+                                    metrics::message_counter_inbound_request_served().inc();
+                                    bitswap.send_response(&peer, (request.cid, response));
+                                } else {
+                                    metrics::message_counter_inbound_request_denied_bandwidth()
+                                        .inc();
+                                }
                             }
                         }
                         BitswapMessage::Response(cid, response) => {
@@ -135,6 +135,21 @@ pub(in crate::libp2p_bitswap) fn message_counter_outbound_response_block(
     MESSAGE_COUNTER.with_label_values(&["outbound_response_block"])
 }
 
+pub(in crate::libp2p_bitswap) fn message_counter_inbound_request_served(
+) -> GenericCounter<AtomicU64> {
+    MESSAGE_COUNTER.with_label_values(&["inbound_request_served"])
+}
+
+pub(in crate::libp2p_bitswap) fn message_counter_inbound_request_denied_peer_rate(
+) -> GenericCounter<AtomicU64> {
+    MESSAGE_COUNTER.with_label_values(&["inbound_request_denied_peer_rate"])
+}
+
+pub(in crate::libp2p_bitswap) fn message_counter_inbound_request_denied_bandwidth(
+) -> GenericCounter<AtomicU64> {
+    MESSAGE_COUNTER.with_label_values(&["inbound_request_denied_bandwidth"])
+}
+
 pub(in crate::libp2p_bitswap) fn peer_container_capacity() -> GenericGauge<AtomicU64> {
     CONTAINER_CAPACITIES.with_label_values(&["peer_container_capacity"])
 }
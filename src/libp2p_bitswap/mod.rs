@@ -39,6 +39,9 @@ pub use message::*;
 mod metrics;
 pub use metrics::register_metrics;
 
+mod rate_limiter;
+pub use rate_limiter::*;
+
 pub mod request_manager;
 
 mod store;
@@ -0,0 +1,295 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Rate limiting of the inbound requests that are served from the local
+//! block store.
+//!
+//! Every peer has a token bucket of requests, and the block data sent to all
+//! peers is drawn from a shared token bucket of bytes. Requests that exceed
+//! either limit are dropped, as if the block was missing. The bucket of a peer
+//! is kept for [`PEER_STATE_EXPIRY`] after it disconnects, so that
+//! reconnecting doesn't refill it.
+
+use std::time::{Duration, Instant};
+
+use ahash::HashMap;
+use libp2p::PeerId;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+/// Limits of the `bitswap` server. A rate of zero disables the limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+#[cfg_attr(test, derive(derive_quickcheck_arbitrary::Arbitrary))]
+pub struct BitswapServerLimits {
+    /// Sustained number of requests per second served to a single peer.
+    pub peer_requests_per_sec: u32,
+    /// Number of requests of a single peer that are served in a burst.
+    pub peer_request_burst: u32,
+    /// Block data served to all peers, in bytes per second.
+    #[cfg_attr(test, arbitrary(gen(|g| u32::arbitrary(g) as _)))]
+    pub max_bytes_per_sec: u64,
+}
+
+impl Default for BitswapServerLimits {
+    fn default() -> Self {
+        Self {
+            peer_requests_per_sec: 100,
+            peer_request_burst: 1000,
+            max_bytes_per_sec: 0,
+        }
+    }
+}
+
+/// How long the state of a disconnected peer is kept, at least. It is also
+/// kept until its bucket would be refilled.
+pub const PEER_STATE_EXPIRY: Duration = Duration::from_secs(10 * 60);
+
+/// Requests of a single peer over its recent connections.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PeerUsage {
+    pub served_requests: u64,
+    pub served_bytes: u64,
+    pub denied_requests: u64,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    rate: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64, capacity: f64, now: Instant) -> Self {
+        Self {
+            capacity,
+            rate,
+            tokens: capacity,
+            refilled: now,
+        }
+    }
+
+    /// Amounts larger than the capacity are taken from a full bucket, which
+    /// then stays in debt until it is refilled.
+    fn try_take(&mut self, amount: f64, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.refilled = now;
+        if self.tokens >= amount.min(self.capacity) {
+            self.tokens -= amount;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PeerState {
+    requests: Option<TokenBucket>,
+    usage: PeerUsage,
+    /// When the peer disconnected, unless it is connected.
+    disconnected: Option<Instant>,
+}
+
+/// Per-peer accounting and rate limiting of inbound requests, see the
+/// [module documentation](self).
+#[derive(Debug)]
+pub struct BitswapRateLimiter {
+    limits: BitswapServerLimits,
+    peers: Mutex<HashMap<PeerId, PeerState>>,
+    bandwidth: Mutex<Option<TokenBucket>>,
+}
+
+impl Default for BitswapRateLimiter {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl BitswapRateLimiter {
+    pub fn new(limits: BitswapServerLimits) -> Self {
+        let bandwidth = (limits.max_bytes_per_sec > 0).then(|| {
+            let rate = limits.max_bytes_per_sec as f64;
+            TokenBucket::new(rate, rate, Instant::now())
+        });
+        Self {
+            limits,
+            peers: Default::default(),
+            bandwidth: Mutex::new(bandwidth),
+        }
+    }
+
+    pub fn limits(&self) -> &BitswapServerLimits {
+        &self.limits
+    }
+
+    /// Returns the usage of a connected or recently disconnected peer.
+    pub fn peer_usage(&self, peer: &PeerId) -> Option<PeerUsage> {
+        self.peers.lock().get(peer).map(|state| state.usage)
+    }
+
+    /// Checks whether a request of `peer` may be processed.
+    pub(in crate::libp2p_bitswap) fn allow_request(&self, peer: &PeerId) -> bool {
+        self.allow_request_at(peer, Instant::now())
+    }
+
+    /// Checks whether a block of `bytes` may be sent to `peer`, and accounts
+    /// for the request as served if so.
+    pub(in crate::libp2p_bitswap) fn allow_block(&self, peer: &PeerId, bytes: usize) -> bool {
+        self.allow_block_at(peer, bytes, Instant::now())
+    }
+
+    /// Accounts for a served request without block data.
+    pub(in crate::libp2p_bitswap) fn on_served(&self, peer: &PeerId) {
+        if let Some(state) = self.peers.lock().get_mut(peer) {
+            state.usage.served_requests += 1;
+        }
+    }
+
+    pub(in crate::libp2p_bitswap) fn on_peer_disconnected(&self, peer: &PeerId) {
+        self.on_peer_disconnected_at(peer, Instant::now())
+    }
+
+    /// Marks `peer` as disconnected, and forgets the peers disconnected for
+    /// longer than their expiry.
+    fn on_peer_disconnected_at(&self, peer: &PeerId, now: Instant) {
+        let refill = match self.limits.peer_requests_per_sec {
+            0 => Duration::ZERO,
+            rate => {
+                Duration::from_secs_f64(self.limits.peer_request_burst.max(1) as f64 / rate as f64)
+            }
+        };
+        let expiry = PEER_STATE_EXPIRY.max(refill);
+        let mut peers = self.peers.lock();
+        if let Some(state) = peers.get_mut(peer) {
+            state.disconnected = Some(now);
+        }
+        peers.retain(|_, state| {
+            state.disconnected.map_or(true, |disconnected| {
+                now.saturating_duration_since(disconnected) < expiry
+            })
+        });
+    }
+
+    fn allow_request_at(&self, peer: &PeerId, now: Instant) -> bool {
+        let limits = &self.limits;
+        let mut peers = self.peers.lock();
+        let state = peers.entry(*peer).or_insert_with(|| PeerState {
+            requests: (limits.peer_requests_per_sec > 0).then(|| {
+                TokenBucket::new(
+                    limits.peer_requests_per_sec as f64,
+                    limits.peer_request_burst.max(1) as f64,
+                    now,
+                )
+            }),
+            usage: Default::default(),
+            disconnected: None,
+        });
+        state.disconnected = None;
+        let allowed = match &mut state.requests {
+            Some(bucket) => bucket.try_take(1.0, now),
+            None => true,
+        };
+        if !allowed {
+            state.usage.denied_requests += 1;
+        }
+        allowed
+    }
+
+    fn allow_block_at(&self, peer: &PeerId, bytes: usize, now: Instant) -> bool {
+        let allowed = match &mut *self.bandwidth.lock() {
+            Some(bucket) => bucket.try_take(bytes as f64, now),
+            None => true,
+        };
+        if let Some(state) = self.peers.lock().get_mut(peer) {
+            if allowed {
+                state.usage.served_requests += 1;
+                state.usage.served_bytes += bytes as u64;
+            } else {
+                state.usage.denied_requests += 1;
+            }
+        }
+        allowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peer_request_rate() {
+        let limiter = BitswapRateLimiter::new(BitswapServerLimits {
+            peer_requests_per_sec: 10,
+            peer_request_burst: 2,
+            max_bytes_per_sec: 0,
+        });
+        let (alice, bob) = (PeerId::random(), PeerId::random());
+        let now = Instant::now();
+
+        assert!(limiter.allow_request_at(&alice, now));
+        assert!(limiter.allow_request_at(&alice, now));
+        assert!(!limiter.allow_request_at(&alice, now));
+        // Peers have separate buckets.
+        assert!(limiter.allow_request_at(&bob, now));
+        // One request is refilled every 100ms.
+        assert!(limiter.allow_request_at(&alice, now + Duration::from_millis(100)));
+        assert!(!limiter.allow_request_at(&alice, now + Duration::from_millis(100)));
+
+        assert_eq!(limiter.peer_usage(&alice).unwrap().denied_requests, 2);
+    }
+
+    #[test]
+    fn reconnections_keep_the_bucket() {
+        let limiter = BitswapRateLimiter::new(BitswapServerLimits {
+            peer_requests_per_sec: 10,
+            peer_request_burst: 1,
+            max_bytes_per_sec: 0,
+        });
+        let (alice, bob) = (PeerId::random(), PeerId::random());
+        let now = Instant::now();
+
+        assert!(limiter.allow_request_at(&alice, now));
+        limiter.on_peer_disconnected_at(&alice, now);
+        assert!(!limiter.allow_request_at(&alice, now));
+        assert_eq!(limiter.peer_usage(&alice).unwrap().denied_requests, 1);
+
+        // Disconnected peers are forgotten once expired.
+        limiter.on_peer_disconnected_at(&alice, now);
+        assert!(limiter.allow_request_at(&bob, now));
+        limiter.on_peer_disconnected_at(&bob, now + PEER_STATE_EXPIRY);
+        assert!(limiter.peer_usage(&alice).is_none());
+        assert!(limiter.peer_usage(&bob).is_some());
+    }
+
+    #[test]
+    fn global_bandwidth() {
+        let limiter = BitswapRateLimiter::new(BitswapServerLimits {
+            peer_requests_per_sec: 0,
+            peer_request_burst: 0,
+            max_bytes_per_sec: 1000,
+        });
+        let peer = PeerId::random();
+        let now = Instant::now();
+        assert!(limiter.allow_request_at(&peer, now));
+
+        assert!(limiter.allow_block_at(&peer, 600, now));
+        assert!(!limiter.allow_block_at(&peer, 600, now));
+        // Blocks larger than the capacity are served from a full bucket.
+        let later = now + Duration::from_secs(1);
+        assert!(limiter.allow_block_at(&peer, 1500, later));
+        assert!(!limiter.allow_block_at(&peer, 1, later + Duration::from_millis(400)));
+
+        assert_eq!(
+            limiter.peer_usage(&peer).unwrap(),
+            PeerUsage {
+                served_requests: 2,
+                served_bytes: 2100,
+                denied_requests: 2,
+            }
+        );
+    }
+}
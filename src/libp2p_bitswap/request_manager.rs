@@ -10,11 +10,11 @@ use std::{
 };
 
 use crate::ipld::CidHashMap;
-use ahash::{HashSet, HashSetExt};
+use ahash::{HashMap, HashSet, HashSetExt};
 use flume::TryRecvError;
 use libipld::{Block, Cid};
 use libp2p::PeerId;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 
 use crate::libp2p_bitswap::{event_handlers::*, *};
 
@@ -23,7 +23,19 @@ const BITSWAP_BLOCK_REQUEST_INTERVAL: Duration = Duration::from_millis(500);
 #[derive(Debug, Clone)]
 struct ResponseChannels {
     block_have: flume::Sender<PeerId>,
-    block_received: flume::Sender<Option<Vec<u8>>>,
+    block_received: flume::Sender<(PeerId, Option<Vec<u8>>)>,
+}
+
+/// Outcomes of the block requests sent to a peer, see
+/// [`BitswapRequestManager::take_peer_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BitswapPeerStats {
+    /// Blocks received from the peer.
+    pub blocks: u64,
+    /// Blocks announced by the peer and not received in time.
+    pub timeouts: u64,
+    /// Blocks received from the peer that don't match their CID.
+    pub invalid_blocks: u64,
 }
 
 /// Request manager implementation that is optimized for Filecoin network
@@ -34,6 +46,8 @@ pub struct BitswapRequestManager {
     outbound_request_rx: flume::Receiver<(PeerId, BitswapRequest)>,
     peers: RwLock<HashSet<PeerId>>,
     response_channels: RwLock<CidHashMap<ResponseChannels>>,
+    rate_limiter: BitswapRateLimiter,
+    peer_stats: Mutex<HashMap<PeerId, BitswapPeerStats>>,
 }
 
 impl BitswapRequestManager {
@@ -44,16 +58,35 @@ impl BitswapRequestManager {
     pub fn outbound_request_rx(&self) -> &flume::Receiver<(PeerId, BitswapRequest)> {
         &self.outbound_request_rx
     }
+
+    /// Rate limiter of the inbound requests served from the block store.
+    pub fn rate_limiter(&self) -> &BitswapRateLimiter {
+        &self.rate_limiter
+    }
+
+    /// Returns the outcomes of the block requests sent to each peer since the
+    /// previous call.
+    pub fn take_peer_stats(&self) -> HashMap<PeerId, BitswapPeerStats> {
+        std::mem::take(&mut *self.peer_stats.lock())
+    }
 }
 
 impl Default for BitswapRequestManager {
     fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl BitswapRequestManager {
+    pub fn new(server_limits: BitswapServerLimits) -> Self {
         let (outbound_request_tx, outbound_request_rx) = flume::unbounded();
         Self {
             outbound_request_tx,
             outbound_request_rx,
             peers: RwLock::new(HashSet::new()),
             response_channels: RwLock::new(CidHashMap::new()),
+            rate_limiter: BitswapRateLimiter::new(server_limits),
+            peer_stats: Default::default(),
         }
     }
 }
@@ -145,10 +178,14 @@ impl BitswapRequestManager {
 
         let mut success = false;
         let mut block_data = None;
+        // Peers the block was requested from, and the one it was received from.
+        let mut requested = HashSet::new();
+        let mut sender = None;
         let block_request = BitswapRequest::new_block(cid).send_dont_have(false);
         while !success && Instant::now() < deadline {
             match block_have_rx.try_recv() {
                 Ok(peer) => {
+                    requested.insert(peer);
                     _ = self.outbound_request_tx.send((peer, block_request.clone()));
                 }
                 Err(TryRecvError::Empty) => {}
@@ -157,19 +194,22 @@ impl BitswapRequestManager {
                 }
             }
 
-            if let Ok(data) = block_saved_rx.recv_timeout(BITSWAP_BLOCK_REQUEST_INTERVAL) {
+            if let Ok((peer, data)) = block_saved_rx.recv_timeout(BITSWAP_BLOCK_REQUEST_INTERVAL) {
                 success = true;
+                sender = Some(peer);
                 block_data = data;
             }
         }
 
         if !success {
-            if let Ok(data) = block_saved_rx.recv_deadline(deadline) {
+            if let Ok((peer, data)) = block_saved_rx.recv_deadline(deadline) {
                 success = true;
+                sender = Some(peer);
                 block_data = data;
             }
         }
 
+        let mut valid = true;
         if let Some(data) = block_data {
             success = match Block::new(cid, data) {
                 Ok(block) => match store.insert(&block) {
@@ -188,11 +228,25 @@ impl BitswapRequestManager {
                 },
                 Err(e) => {
                     warn!("Failed to construct block: {e}, cid: {cid}");
+                    valid = false;
                     false
                 }
             };
         }
 
+        {
+            let mut peer_stats = self.peer_stats.lock();
+            match sender {
+                Some(peer) if valid => peer_stats.entry(peer).or_default().blocks += 1,
+                Some(peer) => peer_stats.entry(peer).or_default().invalid_blocks += 1,
+                None => {
+                    for peer in requested {
+                        peer_stats.entry(peer).or_default().timeouts += 1;
+                    }
+                }
+            }
+        }
+
         // Cleanup
         {
             let mut response_channels = self.response_channels.write();
@@ -216,15 +270,15 @@ impl BitswapRequestManager {
                     _ = chans.block_have.send(peer);
                 }
             }
-            DataBlock(_peer, cid, data) => {
+            DataBlock(peer, cid, data) => {
                 if let Some(chans) = self.response_channels.read().get(cid) {
                     if let Ok(true) = store.contains(&cid) {
                         // Avoid duplicate writes, still notify the receiver
                         metrics::message_counter_inbound_response_block_already_exists_in_db()
                             .inc();
-                        _ = chans.block_received.send(None);
+                        _ = chans.block_received.send((peer, None));
                     } else {
-                        _ = chans.block_received.send(Some(data));
+                        _ = chans.block_received.send((peer, Some(data)));
                     }
 
                     // <https://github.com/ipfs/go-libipfs/tree/main/bitswap#background>
@@ -257,6 +311,7 @@ impl BitswapRequestManager {
     }
 
     pub(in crate::libp2p_bitswap) fn on_peer_disconnected(&self, peer: &PeerId) -> bool {
+        self.rate_limiter.on_peer_disconnected(peer);
         let mut peers = self.peers.write();
         let success = peers.remove(peer);
         if success {
@@ -34,7 +34,8 @@ impl HasLotusJson for SignedMessage {
                     "Value": "0",
                     "Version": 0
                 },
-                "Signature": {"Type": "bls", "Data": "aGVsbG8gd29ybGQh"}
+                "Signature": {"Type": "bls", "Data": "aGVsbG8gd29ybGQh"},
+                "CID": {"/": "bafy2bzaced3xdk2uf6azekyxgcttujvy3fzyeqmibtpjf2fxcpfdx2zcx4s3g"}
             }),
             SignedMessage {
                 message: crate::shim::message::Message::default(),
@@ -46,12 +47,14 @@ impl HasLotusJson for SignedMessage {
         )]
     }
 
+    /// The CID depends on the signature type, see [`SignedMessage::cid`].
     fn into_lotus_json(self) -> Self::LotusJson {
+        let cid = self.cid().ok();
         let Self { message, signature } = self;
         Self::LotusJson {
             message: message.into(),
             signature: signature.into(),
-            cid: None.into(), // BUG?(aatifsyed)
+            cid: cid.into(),
         }
     }
 
@@ -59,7 +62,7 @@ impl HasLotusJson for SignedMessage {
         let Self::LotusJson {
             message,
             signature,
-            cid: _ignored, // BUG?(aatifsyed)
+            cid: _ignored,
         } = lotus_json;
         Self {
             message: message.into_inner(),
@@ -0,0 +1,291 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Messages sent from delegated (`f410`) addresses are signed by Ethereum
+//! wallets. The signature covers the RLP encoding of the `EIP-1559`
+//! transaction equivalent to the message, not the message CID.
+//!
+//! See `EthTxArgsFromUnsignedEthMessage` in Lotus.
+
+use crate::shim::{
+    address::Address,
+    crypto::{Signature, SignatureType},
+    econ::TokenAmount,
+    message::Message,
+};
+use anyhow::{bail, ensure};
+use fvm_ipld_encoding::BytesDe;
+use sha3::{Digest, Keccak256};
+
+/// Namespace of the Ethereum Address Manager in delegated addresses.
+pub const EAM_NAMESPACE: u64 = 10;
+/// `CreateExternal` method of the Ethereum Address Manager actor.
+pub const EAM_METHOD_CREATE_EXTERNAL: u64 = 4;
+/// `InvokeContract` method of the EVM actor.
+pub const EVM_METHOD_INVOKE_CONTRACT: u64 = 3844450837;
+
+/// Type prefix of `EIP-1559` transactions.
+const EIP_1559_TX_TYPE: u8 = 0x02;
+
+pub type EthAddress = [u8; 20];
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+/// Returns the Ethereum address of `addr`. ID addresses are masked, i.e.
+/// `0xff`, followed by zeros and the big-endian ID.
+pub fn eth_address(addr: &Address) -> anyhow::Result<EthAddress> {
+    use fvm_shared3::address::Payload;
+    let mut eth_addr = [0; 20];
+    match addr.payload() {
+        Payload::ID(id) => {
+            eth_addr[0] = 0xff;
+            eth_addr[12..].copy_from_slice(&id.to_be_bytes());
+        }
+        Payload::Delegated(delegated)
+            if delegated.namespace() == EAM_NAMESPACE && delegated.subaddress().len() == 20 =>
+        {
+            eth_addr.copy_from_slice(delegated.subaddress());
+        }
+        _ => bail!("{addr} has no Ethereum address"),
+    }
+    Ok(eth_addr)
+}
+
+/// Returns the bytes signed by the sender of a delegated message, i.e. the
+/// unsigned `EIP-1559` transaction `0x02 || rlp([chain_id, nonce,
+/// max_priority_fee_per_gas, max_fee_per_gas, gas_limit, to, value, input,
+/// access_list])`.
+pub fn eth_tx_signing_bytes(message: &Message, eth_chain_id: u64) -> anyhow::Result<Vec<u8>> {
+    let fields = eth_tx_fields(message, eth_chain_id)?;
+    let mut tx = vec![EIP_1559_TX_TYPE];
+    rlp::append_list(&mut tx, &fields);
+    Ok(tx)
+}
+
+/// Returns the Ethereum transaction hash of a delegated message, i.e. the
+/// `keccak256` of the signed transaction, which appends the `y_parity`, `r`
+/// and `s` values of the signature to the fields of the unsigned one.
+pub fn eth_tx_hash(
+    message: &Message,
+    signature: &Signature,
+    eth_chain_id: u64,
+) -> anyhow::Result<[u8; 32]> {
+    ensure!(
+        signature.sig_type == SignatureType::Delegated,
+        "expected a delegated signature, got {:?}",
+        signature.sig_type
+    );
+    let [rs @ .., y_parity]: [u8; 65] = signature.bytes.as_slice().try_into().map_err(|_| {
+        anyhow::anyhow!(
+            "invalid delegated signature length {}",
+            signature.bytes.len()
+        )
+    })?;
+    let (r, s) = rs.split_at(32);
+
+    let mut fields = eth_tx_fields(message, eth_chain_id)?;
+    rlp::append_uint(&mut fields, y_parity as u64);
+    rlp::append_bytes(&mut fields, rlp::trim_leading_zeros(r));
+    rlp::append_bytes(&mut fields, rlp::trim_leading_zeros(s));
+    let mut tx = vec![EIP_1559_TX_TYPE];
+    rlp::append_list(&mut tx, &fields);
+    Ok(keccak256(&tx))
+}
+
+/// RLP encoding of the fields of the unsigned transaction.
+fn eth_tx_fields(message: &Message, eth_chain_id: u64) -> anyhow::Result<Vec<u8>> {
+    ensure!(
+        message.version == 0,
+        "unsupported message version {}",
+        message.version
+    );
+    eth_address(&message.from)?;
+    let to = if message.to == Address::ETHEREUM_ACCOUNT_MANAGER_ACTOR {
+        ensure!(
+            message.method_num == EAM_METHOD_CREATE_EXTERNAL,
+            "unsupported EAM method {}",
+            message.method_num
+        );
+        vec![]
+    } else {
+        ensure!(
+            message.method_num == EVM_METHOD_INVOKE_CONTRACT,
+            "invalid method {}, only InvokeContract ({EVM_METHOD_INVOKE_CONTRACT}) is allowed",
+            message.method_num
+        );
+        eth_address(&message.to)?.to_vec()
+    };
+    let input = if message.params.is_empty() {
+        vec![]
+    } else {
+        let BytesDe(input) = fvm_ipld_encoding::from_slice(&message.params)?;
+        input
+    };
+
+    let mut fields = vec![];
+    rlp::append_uint(&mut fields, eth_chain_id);
+    rlp::append_uint(&mut fields, message.sequence);
+    rlp::append_bytes(&mut fields, &token_amount_bytes(&message.gas_premium)?);
+    rlp::append_bytes(&mut fields, &token_amount_bytes(&message.gas_fee_cap)?);
+    rlp::append_uint(&mut fields, message.gas_limit);
+    rlp::append_bytes(&mut fields, &to);
+    rlp::append_bytes(&mut fields, &token_amount_bytes(&message.value)?);
+    rlp::append_bytes(&mut fields, &input);
+    // Empty access list
+    rlp::append_list(&mut fields, &[]);
+    Ok(fields)
+}
+
+/// Minimal big-endian bytes of a non-negative amount.
+fn token_amount_bytes(amount: &TokenAmount) -> anyhow::Result<Vec<u8>> {
+    let (sign, bytes) = amount.atto().to_bytes_be();
+    ensure!(sign != num_bigint::Sign::Minus, "negative amount {amount}");
+    Ok(rlp::trim_leading_zeros(&bytes).to_vec())
+}
+
+/// The parts of the RLP encoding needed for transactions.
+mod rlp {
+    pub fn trim_leading_zeros(bytes: &[u8]) -> &[u8] {
+        let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+        &bytes[start..]
+    }
+
+    fn append_length(out: &mut Vec<u8>, len: usize, short_offset: u8) {
+        if len <= 55 {
+            out.push(short_offset + len as u8);
+        } else {
+            let len_bytes = len.to_be_bytes();
+            let len_bytes = trim_leading_zeros(&len_bytes);
+            // Long forms start 55 after the short forms.
+            out.push(short_offset + 55 + len_bytes.len() as u8);
+            out.extend_from_slice(len_bytes);
+        }
+    }
+
+    pub fn append_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+        if let [byte] = bytes {
+            if *byte < 0x80 {
+                out.push(*byte);
+                return;
+            }
+        }
+        append_length(out, bytes.len(), 0x80);
+        out.extend_from_slice(bytes);
+    }
+
+    pub fn append_uint(out: &mut Vec<u8>, value: u64) {
+        append_bytes(out, trim_leading_zeros(&value.to_be_bytes()));
+    }
+
+    /// Appends a list of already encoded items.
+    pub fn append_list(out: &mut Vec<u8>, encoded_items: &[u8]) {
+        append_length(out, encoded_items.len(), 0xc0);
+        out.extend_from_slice(encoded_items);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fvm_ipld_encoding::{BytesSer, RawBytes};
+
+    #[test]
+    fn rlp_encoding() {
+        let encode = |f: &dyn Fn(&mut Vec<u8>)| {
+            let mut out = vec![];
+            f(&mut out);
+            out
+        };
+        assert_eq!(encode(&|out| rlp::append_bytes(out, b"dog")), b"\x83dog");
+        assert_eq!(encode(&|out| rlp::append_bytes(out, b"")), [0x80]);
+        assert_eq!(encode(&|out| rlp::append_bytes(out, &[0x0f])), [0x0f]);
+        assert_eq!(encode(&|out| rlp::append_uint(out, 0)), [0x80]);
+        assert_eq!(
+            encode(&|out| rlp::append_uint(out, 1024)),
+            [0x82, 0x04, 0x00]
+        );
+        assert_eq!(encode(&|out| rlp::append_list(out, &[])), [0xc0]);
+        assert_eq!(
+            encode(&|out| rlp::append_list(out, b"\x83cat\x83dog")),
+            b"\xc8\x83cat\x83dog"
+        );
+        let long = [b'a'; 56];
+        assert_eq!(
+            encode(&|out| rlp::append_bytes(out, &long))[..2],
+            [0xb8, 56]
+        );
+    }
+
+    #[test]
+    fn eth_addresses() {
+        assert_eq!(
+            eth_address(&Address::new_id(0x1234)).unwrap(),
+            hex::decode("ff00000000000000000000000000000000001234")
+                .unwrap()
+                .as_slice()
+        );
+        let eth_addr = [7; 20];
+        let f410 = Address::new_delegated(EAM_NAMESPACE, &eth_addr).unwrap();
+        assert_eq!(eth_address(&f410).unwrap(), eth_addr);
+        assert!(eth_address(&Address::new_actor(b"actor")).is_err());
+    }
+
+    #[test]
+    fn verify_delegated_signature() {
+        let secret_key = libsecp256k1::SecretKey::parse(&[1; 32]).unwrap();
+        let public_key = libsecp256k1::PublicKey::from_secret_key(&secret_key);
+        let eth_addr = &keccak256(&public_key.serialize()[1..])[12..];
+        let from = Address::new_delegated(EAM_NAMESPACE, eth_addr).unwrap();
+
+        let message = Message {
+            from,
+            to: Address::new_id(1000),
+            sequence: 3,
+            value: TokenAmount::from_atto(10),
+            method_num: EVM_METHOD_INVOKE_CONTRACT,
+            params: RawBytes::serialize(BytesSer(&[1, 2, 3])).unwrap(),
+            gas_limit: 1_000_000,
+            gas_fee_cap: TokenAmount::from_atto(200),
+            gas_premium: TokenAmount::from_atto(100),
+            ..Default::default()
+        };
+        let signing_bytes = eth_tx_signing_bytes(&message, 314).unwrap();
+        assert_eq!(signing_bytes[0], EIP_1559_TX_TYPE);
+
+        let (sig, recovery_id) = libsecp256k1::sign(
+            &libsecp256k1::Message::parse(&keccak256(&signing_bytes)),
+            &secret_key,
+        );
+        let mut bytes = sig.serialize().to_vec();
+        bytes.push(recovery_id.serialize());
+        let signature = Signature::new_delegated(bytes);
+
+        assert!(signature.verify(&signing_bytes, &from).is_ok());
+        // Signed for another chain
+        assert!(signature
+            .verify(&eth_tx_signing_bytes(&message, 1).unwrap(), &from)
+            .is_err());
+        // Signed by another sender
+        let other = Address::new_delegated(EAM_NAMESPACE, &[7; 20]).unwrap();
+        assert!(signature.verify(&signing_bytes, &other).is_err());
+
+        // The hash covers the signature, unlike the signing bytes.
+        let tx_hash = eth_tx_hash(&message, &signature, 314).unwrap();
+        assert_ne!(tx_hash, keccak256(&signing_bytes));
+        let mut other_signature = signature.clone();
+        other_signature.bytes[64] ^= 1;
+        assert_ne!(
+            eth_tx_hash(&message, &other_signature, 314).unwrap(),
+            tx_hash
+        );
+        assert!(eth_tx_hash(&message, &Signature::new_bls(vec![0; 96]), 314).is_err());
+
+        let send = Message {
+            method_num: 0,
+            ..message
+        };
+        assert!(eth_tx_signing_bytes(&send, 314).is_err());
+    }
+}
@@ -0,0 +1,236 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Decoding and inspection of standalone messages, e.g. to find out why the
+//! CID of a message built by a client differs from the one seen by the chain.
+//!
+//! Messages are accepted as `Lotus` JSON or as CBOR, either raw or hex
+//! encoded, and either signed or unsigned.
+
+use std::fmt;
+
+use crate::lotus_json::LotusJson;
+use crate::message::{delegated, field_violations, ChainMessage, SignedMessage};
+use crate::shim::{crypto::SignatureType, message::Message, version::NetworkVersion};
+use anyhow::Context as _;
+use cid::Cid;
+
+/// A decoded message, see [`inspect`].
+#[derive(Debug, Clone)]
+pub struct MessageInspection {
+    pub message: ChainMessage,
+    /// Canonical CID of the message. BLS messages are identified by the CID
+    /// of the unsigned message.
+    pub cid: Cid,
+    /// CID given in the JSON input, if any.
+    pub declared_cid: Option<Cid>,
+    /// Ethereum transaction hash of signed delegated messages.
+    pub eth_tx_hash: Option<[u8; 32]>,
+    /// Fields that are out of bounds, and other reasons the chain would reject
+    /// the message.
+    pub violations: Vec<String>,
+}
+
+/// Decodes a message from JSON or CBOR `input`. Also returns the CID of the
+/// JSON message, if present.
+pub fn decode(input: &[u8]) -> anyhow::Result<(ChainMessage, Option<Cid>)> {
+    let text = std::str::from_utf8(input).map(str::trim);
+    match text {
+        Ok(text) if text.starts_with('{') => decode_json(text),
+        Ok(text) if !text.is_empty() && text.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            let bytes = hex::decode(text).context("invalid hex encoded CBOR")?;
+            Ok((decode_cbor(&bytes)?, None))
+        }
+        _ => Ok((decode_cbor(input)?, None)),
+    }
+}
+
+fn decode_json(text: &str) -> anyhow::Result<(ChainMessage, Option<Cid>)> {
+    let value: serde_json::Value = serde_json::from_str(text).context("invalid JSON")?;
+    let declared_cid = match value.get("CID") {
+        Some(cid) => serde_json::from_value::<LotusJson<Option<Cid>>>(cid.clone())
+            .context("invalid CID")?
+            .into_inner(),
+        None => None,
+    };
+    let message = if value.get("Signature").is_some() {
+        let LotusJson(message) = serde_json::from_value::<LotusJson<SignedMessage>>(value)
+            .context("invalid signed message")?;
+        ChainMessage::Signed(message)
+    } else {
+        let LotusJson(message) =
+            serde_json::from_value::<LotusJson<Message>>(value).context("invalid message")?;
+        ChainMessage::Unsigned(message)
+    };
+    Ok((message, declared_cid))
+}
+
+fn decode_cbor(bytes: &[u8]) -> anyhow::Result<ChainMessage> {
+    if let Ok(message) = fvm_ipld_encoding::from_slice::<SignedMessage>(bytes) {
+        return Ok(ChainMessage::Signed(message));
+    }
+    let message = fvm_ipld_encoding::from_slice::<Message>(bytes)
+        .context("the input is neither a signed nor an unsigned CBOR message")?;
+    Ok(ChainMessage::Unsigned(message))
+}
+
+/// Computes the identifiers of `message` and checks its fields against the
+/// bounds of the latest network version. The Ethereum transaction hash of
+/// delegated messages depends on the `eth_chain_id` of the network.
+pub fn inspect(
+    message: ChainMessage,
+    declared_cid: Option<Cid>,
+    eth_chain_id: u64,
+) -> anyhow::Result<MessageInspection> {
+    let cid = message.cid()?;
+    let mut violations = field_violations(message.message(), NetworkVersion::V20);
+    if declared_cid.is_some_and(|declared| declared != cid) {
+        violations.push(format!("the declared CID differs from the computed {cid}"));
+    }
+    let mut eth_tx_hash = None;
+    if let ChainMessage::Signed(signed) = &message {
+        if signed.signature.sig_type == SignatureType::Delegated {
+            match delegated::eth_tx_hash(&signed.message, &signed.signature, eth_chain_id) {
+                Ok(hash) => eth_tx_hash = Some(hash),
+                Err(e) => violations.push(format!("invalid Ethereum transaction: {e}")),
+            }
+        }
+    }
+    Ok(MessageInspection {
+        message,
+        cid,
+        declared_cid,
+        eth_tx_hash,
+        violations,
+    })
+}
+
+impl fmt::Display for MessageInspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = self.message.message();
+        writeln!(f, "CID:          {}", self.cid)?;
+        if let Some(declared_cid) = self.declared_cid {
+            writeln!(f, "Declared CID: {declared_cid}")?;
+        }
+        if let Some(hash) = self.eth_tx_hash {
+            writeln!(f, "Eth tx hash:  0x{}", hex::encode(hash))?;
+        }
+        writeln!(f, "Version:      {}", message.version)?;
+        writeln!(f, "From:         {}", message.from)?;
+        writeln!(f, "To:           {}", message.to)?;
+        writeln!(f, "Nonce:        {}", message.sequence)?;
+        writeln!(f, "Value:        {} FIL", message.value)?;
+        writeln!(f, "Method:       {}", message.method_num)?;
+        writeln!(
+            f,
+            "Params:       0x{} ({} bytes)",
+            hex::encode(message.params.bytes()),
+            message.params.len()
+        )?;
+        writeln!(f, "Gas limit:    {}", message.gas_limit)?;
+        writeln!(f, "Gas fee cap:  {} attoFIL", message.gas_fee_cap.atto())?;
+        writeln!(f, "Gas premium:  {} attoFIL", message.gas_premium.atto())?;
+        match &self.message {
+            ChainMessage::Signed(signed) => writeln!(
+                f,
+                "Signature:    {:?} 0x{}",
+                signed.signature.sig_type,
+                hex::encode(&signed.signature.bytes)
+            )?,
+            ChainMessage::Unsigned(_) => writeln!(f, "Signature:    none")?,
+        }
+        if self.violations.is_empty() {
+            write!(f, "No problems found")
+        } else {
+            write!(f, "Problems:")?;
+            for violation in &self.violations {
+                write!(f, "\n  - {violation}")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shim::{address::Address, crypto::Signature, econ::TokenAmount};
+
+    fn message() -> Message {
+        Message {
+            from: Address::new_id(100),
+            to: Address::new_id(200),
+            value: TokenAmount::from_atto(10),
+            gas_limit: 1_000_000,
+            gas_fee_cap: TokenAmount::from_atto(200),
+            gas_premium: TokenAmount::from_atto(100),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn decode_formats() {
+        let unsigned = message();
+        let signed = SignedMessage {
+            message: unsigned.clone(),
+            signature: Signature::new_secp256k1(vec![0; 65]),
+        };
+        let cbor = fvm_ipld_encoding::to_vec(&signed).unwrap();
+        assert_eq!(
+            decode(&cbor).unwrap().0,
+            ChainMessage::Signed(signed.clone())
+        );
+        assert_eq!(
+            decode(hex::encode(&cbor).as_bytes()).unwrap().0,
+            ChainMessage::Signed(signed.clone())
+        );
+        assert_eq!(
+            decode(&fvm_ipld_encoding::to_vec(&unsigned).unwrap())
+                .unwrap()
+                .0,
+            ChainMessage::Unsigned(unsigned.clone())
+        );
+
+        let json = serde_json::to_vec(&LotusJson(signed.clone())).unwrap();
+        assert_eq!(
+            decode(&json).unwrap(),
+            (
+                ChainMessage::Signed(signed.clone()),
+                Some(signed.cid().unwrap())
+            )
+        );
+        let json = serde_json::to_vec(&LotusJson(unsigned.clone())).unwrap();
+        assert_eq!(decode(&json).unwrap().0, ChainMessage::Unsigned(unsigned));
+        assert!(decode(b"{}").is_err());
+        assert!(decode(b"garbage").is_err());
+    }
+
+    #[test]
+    fn inspect_violations() {
+        let valid = inspect(ChainMessage::Unsigned(message()), None, 314).unwrap();
+        assert!(valid.violations.is_empty());
+        assert_eq!(valid.cid, message().cid().unwrap());
+
+        let invalid = Message {
+            gas_premium: TokenAmount::from_atto(300),
+            gas_limit: u64::MAX,
+            ..message()
+        };
+        let inspection = inspect(
+            ChainMessage::Unsigned(invalid),
+            Some(message().cid().unwrap()),
+            314,
+        )
+        .unwrap();
+        assert_eq!(inspection.violations.len(), 3);
+
+        // Delegated signatures must be valid Ethereum transactions.
+        let signed = SignedMessage {
+            message: message(),
+            signature: Signature::new_delegated(vec![0; 65]),
+        };
+        let inspection = inspect(ChainMessage::Signed(signed), None, 314).unwrap();
+        assert!(inspection.eth_tx_hash.is_none());
+        assert_eq!(inspection.violations.len(), 1);
+    }
+}
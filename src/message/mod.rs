@@ -2,6 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 pub mod chain_message;
+pub mod delegated;
+pub mod inspect;
 pub mod signed_message;
 
 use crate::shim::message::MethodNum;
@@ -97,45 +99,54 @@ pub fn valid_for_block_inclusion(
     min_gas: Gas,
     version: NetworkVersion,
 ) -> Result<(), anyhow::Error> {
+    if let Some(violation) = field_violations(msg, version).into_iter().next() {
+        anyhow::bail!(violation);
+    }
+    if Gas::new(msg.gas_limit) < min_gas {
+        anyhow::bail!(
+            "gas_limit {} cannot be less than cost {} of storing a message on chain",
+            msg.gas_limit,
+            min_gas
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns the fields of the message that are out of bounds at network
+/// `version`, regardless of the state of the chain.
+pub fn field_violations(msg: &ShimMessage, version: NetworkVersion) -> Vec<String> {
     use crate::shim::address::ZERO_ADDRESS;
     use crate::shim::econ::{BLOCK_GAS_LIMIT, TOTAL_FILECOIN};
+    let mut violations = vec![];
     if msg.version != 0 {
-        anyhow::bail!("Message version: {} not supported", msg.version);
+        violations.push(format!("Message version: {} not supported", msg.version));
     }
     if msg.to == *ZERO_ADDRESS && version >= NetworkVersion::V7 {
-        anyhow::bail!("invalid 'to' address");
+        violations.push("invalid 'to' address".into());
     }
     if msg.value.is_negative() {
-        anyhow::bail!("message value cannot be negative");
+        violations.push("message value cannot be negative".into());
     }
     if msg.value > *TOTAL_FILECOIN {
-        anyhow::bail!("message value cannot be greater than total FIL supply");
+        violations.push("message value cannot be greater than total FIL supply".into());
     }
     if msg.gas_fee_cap.is_negative() {
-        anyhow::bail!("gas_fee_cap cannot be negative");
+        violations.push("gas_fee_cap cannot be negative".into());
     }
     if msg.gas_premium.is_negative() {
-        anyhow::bail!("gas_premium cannot be negative");
+        violations.push("gas_premium cannot be negative".into());
     }
     if msg.gas_premium > msg.gas_fee_cap {
-        anyhow::bail!("gas_fee_cap less than gas_premium");
+        violations.push("gas_fee_cap less than gas_premium".into());
     }
     if msg.gas_limit > BLOCK_GAS_LIMIT {
-        anyhow::bail!(
+        violations.push(format!(
             "gas_limit {} cannot be greater than block gas limit",
             msg.gas_limit
-        );
-    }
-
-    if Gas::new(msg.gas_limit) < min_gas {
-        anyhow::bail!(
-            "gas_limit {} cannot be less than cost {} of storing a message on chain",
-            msg.gas_limit,
-            min_gas
-        );
+        ));
     }
-
-    Ok(())
+    violations
 }
 
 #[cfg(test)]
@@ -67,10 +67,33 @@ impl SignedMessage {
         self.signature.signature_type() == SignatureType::Delegated
     }
 
+    /// Returns the bytes the signature was computed over. Delegated messages
+    /// are signed as Ethereum transactions of the chain `eth_chain_id`.
+    pub fn signing_bytes(&self, eth_chain_id: u64) -> anyhow::Result<Vec<u8>> {
+        Self::message_signing_bytes(&self.message, self.signature.signature_type(), eth_chain_id)
+    }
+
+    /// Returns the bytes to sign `message` with a key of type `sig_type`, i.e.
+    /// the bytes of the unsigned message CID for BLS and `secp256k1` keys.
+    pub fn message_signing_bytes(
+        message: &Message,
+        sig_type: SignatureType,
+        eth_chain_id: u64,
+    ) -> anyhow::Result<Vec<u8>> {
+        match sig_type {
+            SignatureType::Delegated => {
+                super::delegated::eth_tx_signing_bytes(message, eth_chain_id)
+            }
+            SignatureType::Bls | SignatureType::Secp256k1 => Ok(message.cid()?.to_bytes()),
+        }
+    }
+
     /// Verifies that the from address of the message generated the signature.
-    pub fn verify(&self) -> Result<(), String> {
-        self.signature
-            .verify(&self.message.cid().unwrap().to_bytes(), &self.from())
+    pub fn verify(&self, eth_chain_id: u64) -> Result<(), String> {
+        let data = self
+            .signing_bytes(eth_chain_id)
+            .map_err(|e| e.to_string())?;
+        self.signature.verify(&data, &self.from())
     }
 
     // Important note: `msg.cid()` is different from
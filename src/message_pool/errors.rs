@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 use crate::chain::Error as ChainError;
+use crate::shim::crypto::SignatureType;
 use fvm_ipld_encoding::Error as EncodeError;
 use thiserror::Error;
 
@@ -26,8 +27,12 @@ pub enum Error {
     InvalidToAddr,
     #[error("Invalid from address")]
     InvalidFromAddr,
+    #[error("Signature type {0:?} can't be used by sender {1}")]
+    InvalidSignatureType(SignatureType, String),
     #[error("Message with sequence already in mempool")]
     DuplicateSequence,
+    #[error("Message sequence {1} of {0} is reserved by another client")]
+    SequenceReserved(String, u64),
     #[error("Validation Error: {0}")]
     SoftValidationFailure(String),
     #[error("Too many pending messages from actor {0} (trusted: {1})")]
@@ -11,7 +11,12 @@ pub use self::{
     config::*,
     errors::*,
     msgpool::{
+        auto_resubmit::{
+            AutoResubmit, AutoResubmitConfig, AutoResubmitStatus, ResubmitAction, ResubmitRecord,
+            TrackedMessage,
+        },
         msg_pool::MessagePool,
+        nonce_reservations::{NonceReservation, DEFAULT_NONCE_LEASE},
         provider::{MpoolRpcProvider, Provider},
         *,
     },
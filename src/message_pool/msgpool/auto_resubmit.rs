@@ -0,0 +1,661 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Automatic replacement of the underpriced local messages.
+//!
+//! The pending messages of the local addresses whose keys are in the keystore
+//! or a remote signer are tracked until they leave the message pool, across
+//! restarts. On every head change, a tracked message whose gas fee cap
+//! is below the base fee of the next tipset is replaced by a copy with a higher
+//! gas premium and fee cap, signed again with the key of its sender. The fee
+//! cap of a replacement covers the highest base fee of the recent tipsets, see
+//! [`ChainStore::base_fee_history`], increased by the step of the
+//! [`AutoResubmitConfig::bump_schedule`] for the replacement, so that a base
+//! fee that keeps rising doesn't underprice it again right away.
+//!
+//! Replacements never exceed [`AutoResubmitConfig::max_fee_cap`], and messages
+//! are abandoned, i.e. left as they are, [`AutoResubmitConfig::abandon_after`]
+//! epochs after they were first seen. Every automated action is recorded in an
+//! audit log kept in the settings store. The engine can be paused, e.g. with
+//! `forest-cli mpool resubmit-pause`, in which case messages are tracked but
+//! neither replaced nor abandoned.
+
+use std::sync::Arc;
+
+use super::{msg_pool::MessagePool, provider::Provider, RBF_DENOM, RBF_NUM};
+use crate::blocks::Tipset;
+use crate::chain::{ChainEpochDelta, ChainStore, HeadChange};
+use crate::db::{
+    setting_keys::{AUTO_RESUBMIT_LOG_KEY, AUTO_RESUBMIT_PAUSED_KEY, AUTO_RESUBMIT_TRACKED_KEY},
+    SettingsStore, SettingsStoreExt,
+};
+use crate::key_management::{KeyStore, RemoteSigners};
+use crate::message::{Message as MessageTrait, SignedMessage};
+use crate::shim::{address::Address, clock::ChainEpoch, econ::TokenAmount, message::Message};
+use ahash::{HashMap, HashSet};
+use chrono::{DateTime, Utc};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast::error::RecvError, RwLock};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+#[cfg_attr(test, derive(derive_quickcheck_arbitrary::Arbitrary))]
+pub struct AutoResubmitConfig {
+    /// Replaces the underpriced local messages automatically.
+    pub enabled: bool,
+    /// Highest gas fee cap of a replacement.
+    #[serde(with = "crate::lotus_json")]
+    pub max_fee_cap: TokenAmount,
+    /// Increase of the gas premium and fee cap of the successive replacements
+    /// of a message, in percent. The last step applies to any further
+    /// replacement.
+    pub bump_schedule: Vec<u32>,
+    /// Messages are abandoned this many epochs after they were first seen.
+    pub abandon_after: ChainEpochDelta,
+    /// Number of records kept in the audit log.
+    #[cfg_attr(test, arbitrary(gen(|g| u32::arbitrary(g) as _)))]
+    pub log_size: usize,
+}
+
+impl Default for AutoResubmitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_fee_cap: TokenAmount::from_nano(10),
+            bump_schedule: vec![25, 50, 100],
+            abandon_after: 240,
+            log_size: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResubmitAction {
+    Replaced,
+    /// The message is underpriced, but can't be replaced without exceeding
+    /// the maximum gas fee cap.
+    FeeCapReached,
+    Abandoned,
+    /// The replacement couldn't be signed or pushed, and the message was
+    /// abandoned.
+    Failed,
+    Paused,
+    Resumed,
+}
+
+/// Record of the audit log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ResubmitRecord {
+    pub time: DateTime<Utc>,
+    /// Epoch of the head when the action was taken.
+    pub epoch: ChainEpoch,
+    pub action: ResubmitAction,
+    /// Sender of the message, missing for pauses and resumptions.
+    #[serde(with = "crate::lotus_json")]
+    pub from: Option<Address>,
+    pub nonce: Option<u64>,
+    #[serde(with = "crate::lotus_json")]
+    pub message: Option<Cid>,
+    #[serde(with = "crate::lotus_json")]
+    pub replacement: Option<Cid>,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TrackedMessage {
+    #[serde(with = "crate::lotus_json")]
+    pub from: Address,
+    pub nonce: u64,
+    /// Latest version of the message.
+    #[serde(with = "crate::lotus_json")]
+    pub message: Cid,
+    pub first_epoch: ChainEpoch,
+    pub replacements: u64,
+    pub abandoned: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AutoResubmitStatus {
+    pub enabled: bool,
+    pub paused: bool,
+    #[serde(with = "crate::lotus_json")]
+    pub max_fee_cap: TokenAmount,
+    pub bump_schedule: Vec<u32>,
+    pub abandon_after: ChainEpochDelta,
+    pub tracked: Vec<TrackedMessage>,
+}
+
+#[derive(Debug, Clone)]
+struct Tracked {
+    message: Cid,
+    first_epoch: ChainEpoch,
+    replacements: u64,
+    abandoned: bool,
+    /// Whether reaching the maximum fee cap was recorded since the last
+    /// replacement.
+    fee_cap_reached: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum Decision {
+    Keep,
+    Replace(Box<Message>),
+    FeeCapReached,
+    Abandon,
+}
+
+/// Automatic resubmission engine, see the [module documentation](self).
+#[derive(Default)]
+pub struct AutoResubmit {
+    config: AutoResubmitConfig,
+    tracked: Mutex<HashMap<(Address, u64), Tracked>>,
+}
+
+impl AutoResubmit {
+    pub fn new(config: AutoResubmitConfig) -> Self {
+        Self {
+            config,
+            tracked: Default::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn is_paused(&self, settings: &dyn SettingsStore) -> anyhow::Result<bool> {
+        Ok(settings
+            .read_obj(AUTO_RESUBMIT_PAUSED_KEY)?
+            .unwrap_or_default())
+    }
+
+    /// Pauses or resumes the engine. Returns whether its state changed.
+    pub fn set_paused(
+        &self,
+        settings: &dyn SettingsStore,
+        paused: bool,
+        epoch: ChainEpoch,
+    ) -> anyhow::Result<bool> {
+        anyhow::ensure!(
+            self.config.enabled,
+            "Automatic resubmission is disabled, see the `auto_resubmit` section of the configuration"
+        );
+        if self.is_paused(settings)? == paused {
+            return Ok(false);
+        }
+        settings.write_obj(AUTO_RESUBMIT_PAUSED_KEY, &paused)?;
+        self.record(
+            settings,
+            ResubmitRecord {
+                time: Utc::now(),
+                epoch,
+                action: match paused {
+                    true => ResubmitAction::Paused,
+                    false => ResubmitAction::Resumed,
+                },
+                from: None,
+                nonce: None,
+                message: None,
+                replacement: None,
+                detail: String::new(),
+            },
+        )?;
+        Ok(true)
+    }
+
+    pub fn status(&self, settings: &dyn SettingsStore) -> anyhow::Result<AutoResubmitStatus> {
+        Ok(AutoResubmitStatus {
+            enabled: self.config.enabled,
+            paused: self.is_paused(settings)?,
+            max_fee_cap: self.config.max_fee_cap.clone(),
+            bump_schedule: self.config.bump_schedule.clone(),
+            abandon_after: self.config.abandon_after,
+            tracked: self.tracked_messages(),
+        })
+    }
+
+    fn tracked_messages(&self) -> Vec<TrackedMessage> {
+        let mut tracked: Vec<_> = self
+            .tracked
+            .lock()
+            .iter()
+            .map(|((from, nonce), tracked)| TrackedMessage {
+                from: *from,
+                nonce: *nonce,
+                message: tracked.message,
+                first_epoch: tracked.first_epoch,
+                replacements: tracked.replacements,
+                abandoned: tracked.abandoned,
+            })
+            .collect();
+        tracked.sort_by_key(|tracked| (tracked.from.to_string(), tracked.nonce));
+        tracked
+    }
+
+    /// Restores the messages tracked before a restart, so that they are still
+    /// abandoned [`AutoResubmitConfig::abandon_after`] epochs after they were
+    /// first seen.
+    fn restore(&self, settings: &dyn SettingsStore) -> anyhow::Result<()> {
+        let restored: Vec<TrackedMessage> = settings
+            .read_obj(AUTO_RESUBMIT_TRACKED_KEY)?
+            .unwrap_or_default();
+        self.tracked
+            .lock()
+            .extend(restored.into_iter().map(|tracked| {
+                (
+                    (tracked.from, tracked.nonce),
+                    Tracked {
+                        message: tracked.message,
+                        first_epoch: tracked.first_epoch,
+                        replacements: tracked.replacements,
+                        abandoned: tracked.abandoned,
+                        fee_cap_reached: false,
+                    },
+                )
+            }));
+        Ok(())
+    }
+
+    fn save(&self, settings: &dyn SettingsStore) -> anyhow::Result<()> {
+        settings.write_obj(AUTO_RESUBMIT_TRACKED_KEY, &self.tracked_messages())
+    }
+
+    /// Returns the `limit` most recent records of the audit log, oldest first.
+    pub fn log(
+        &self,
+        settings: &dyn SettingsStore,
+        limit: usize,
+    ) -> anyhow::Result<Vec<ResubmitRecord>> {
+        let mut log: Vec<ResubmitRecord> = settings
+            .read_obj(AUTO_RESUBMIT_LOG_KEY)?
+            .unwrap_or_default();
+        log.drain(..log.len().saturating_sub(limit));
+        Ok(log)
+    }
+
+    fn record(&self, settings: &dyn SettingsStore, record: ResubmitRecord) -> anyhow::Result<()> {
+        info!(
+            "Automatic resubmission at epoch {}: {:?} {} {}",
+            record.epoch,
+            record.action,
+            record
+                .message
+                .map(|cid| cid.to_string())
+                .unwrap_or_default(),
+            record.detail
+        );
+        let mut log = self.log(settings, self.config.log_size)?;
+        log.push(record);
+        log.drain(..log.len().saturating_sub(self.config.log_size));
+        settings.write_obj(AUTO_RESUBMIT_LOG_KEY, &log)
+    }
+
+    /// Decides what to do with a tracked message at the head of `epoch`.
+    /// `max_base_fee` is the highest recent base fee.
+    fn decide(
+        &self,
+        tracked: &mut Tracked,
+        message: &Message,
+        epoch: ChainEpoch,
+        base_fee: &TokenAmount,
+        max_base_fee: &TokenAmount,
+    ) -> Decision {
+        if tracked.abandoned {
+            return Decision::Keep;
+        }
+        if epoch - tracked.first_epoch >= self.config.abandon_after {
+            tracked.abandoned = true;
+            return Decision::Abandon;
+        }
+        if message.gas_fee_cap >= *base_fee {
+            return Decision::Keep;
+        }
+
+        let bump = self
+            .config
+            .bump_schedule
+            .get(tracked.replacements as usize)
+            .or(self.config.bump_schedule.last())
+            .copied()
+            .unwrap_or_default();
+        let premium = &message.gas_premium;
+        // The message pool only accepts replacements with a premium above
+        // this one.
+        let min_premium =
+            premium + (premium * RBF_NUM).div_floor(RBF_DENOM) + TokenAmount::from_atto(1);
+        let gas_premium = bumped(premium, bump).max(min_premium + TokenAmount::from_atto(1));
+        let gas_fee_cap = (bumped(max_base_fee, bump) + &gas_premium)
+            .max(message.gas_fee_cap.clone())
+            .min(self.config.max_fee_cap.clone());
+        if gas_fee_cap < *base_fee || gas_fee_cap < gas_premium {
+            return match std::mem::replace(&mut tracked.fee_cap_reached, true) {
+                true => Decision::Keep,
+                false => Decision::FeeCapReached,
+            };
+        }
+        tracked.fee_cap_reached = false;
+        Decision::Replace(Box::new(Message {
+            gas_fee_cap,
+            gas_premium,
+            ..message.clone()
+        }))
+    }
+
+    /// Replaces the underpriced local messages on every head change, forever.
+    pub async fn run<T, DB>(
+        self: Arc<Self>,
+        mpool: Arc<MessagePool<T>>,
+        chain_store: Arc<ChainStore<DB>>,
+        keystore: Arc<RwLock<KeyStore>>,
+        remote_signers: Arc<RemoteSigners>,
+    ) -> anyhow::Result<()>
+    where
+        T: Provider + Send + Sync + 'static,
+        DB: Blockstore + Send + Sync + 'static,
+    {
+        info!("Automatic resubmission of the underpriced local messages enabled");
+        if let Err(e) = self.restore(chain_store.settings().as_ref()) {
+            warn!("Failed to restore the messages tracked for resubmission: {e:#}");
+        }
+        let mut head_changes = chain_store.publisher().subscribe();
+        loop {
+            let head = match head_changes.recv().await {
+                Ok(HeadChange::Apply(tipset)) => tipset,
+                Err(RecvError::Lagged(_)) => chain_store.heaviest_tipset(),
+                Err(RecvError::Closed) => return Ok(()),
+            };
+            if let Err(e) = self
+                .resubmit(&mpool, &chain_store, &keystore, &remote_signers, &head)
+                .await
+            {
+                warn!("Failed to resubmit the underpriced local messages: {e:#}");
+            }
+        }
+    }
+
+    async fn resubmit<T, DB>(
+        &self,
+        mpool: &MessagePool<T>,
+        chain_store: &ChainStore<DB>,
+        keystore: &RwLock<KeyStore>,
+        remote_signers: &RemoteSigners,
+        head: &Tipset,
+    ) -> anyhow::Result<()>
+    where
+        T: Provider,
+        DB: Blockstore,
+    {
+        let settings = chain_store.settings();
+        let epoch = head.epoch();
+        let base_fee = mpool.api.chain_compute_base_fee(head)?;
+        let max_base_fee = chain_store
+            .base_fee_history()
+            .into_iter()
+            .fold(base_fee.clone(), TokenAmount::max);
+        let paused = self.is_paused(settings.as_ref())?;
+
+        let record = |action, smsg: &SignedMessage, replacement, detail| {
+            Ok::<_, anyhow::Error>(ResubmitRecord {
+                time: Utc::now(),
+                epoch,
+                action,
+                from: Some(smsg.from()),
+                nonce: Some(smsg.sequence()),
+                message: Some(smsg.cid()?),
+                replacement,
+                detail,
+            })
+        };
+        // Messages signed elsewhere and pushed by clients can't be replaced.
+        let mut pending = mpool.local_pending();
+        {
+            let mut keystore = keystore.write().await;
+            pending.retain(|smsg| remote_signers.can_sign(&smsg.from(), &mut keystore));
+        }
+        let mut records = vec![];
+        let mut replacements = vec![];
+        {
+            let mut tracked = self.tracked.lock();
+            let keys: HashSet<_> = pending
+                .iter()
+                .map(|smsg| (smsg.from(), smsg.sequence()))
+                .collect();
+            tracked.retain(|key, _| keys.contains(key));
+            for smsg in pending {
+                let cid = smsg.cid()?;
+                let entry = tracked
+                    .entry((smsg.from(), smsg.sequence()))
+                    .or_insert_with(|| Tracked {
+                        message: cid,
+                        first_epoch: epoch,
+                        replacements: 0,
+                        abandoned: false,
+                        fee_cap_reached: false,
+                    });
+                entry.message = cid;
+                if paused {
+                    continue;
+                }
+                match self.decide(entry, smsg.message(), epoch, &base_fee, &max_base_fee) {
+                    Decision::Keep => {}
+                    Decision::Abandon => records.push(record(
+                        ResubmitAction::Abandoned,
+                        &smsg,
+                        None,
+                        format!(
+                            "not included {} epochs after it was first seen",
+                            self.config.abandon_after
+                        ),
+                    )?),
+                    Decision::FeeCapReached => records.push(record(
+                        ResubmitAction::FeeCapReached,
+                        &smsg,
+                        None,
+                        format!(
+                            "base fee {base_fee} is out of reach of the maximum gas fee cap {}",
+                            self.config.max_fee_cap
+                        ),
+                    )?),
+                    Decision::Replace(message) => replacements.push((smsg, *message)),
+                }
+            }
+        }
+        self.save(settings.as_ref())?;
+        for record in records {
+            self.record(settings.as_ref(), record)?;
+        }
+
+        let eth_chain_id = mpool.chain_config.eth_chain_id;
+        for (smsg, message) in replacements {
+            let detail = format!(
+                "gas fee cap {} -> {}, gas premium {} -> {}, base fee {base_fee}",
+                smsg.message().gas_fee_cap,
+                message.gas_fee_cap,
+                smsg.message().gas_premium,
+                message.gas_premium
+            );
+            let replaced = async {
+                let replacement = sign(keystore, remote_signers, message, eth_chain_id).await?;
+                let cid = replacement.cid()?;
+                mpool.push(replacement).await?;
+                Ok::<_, anyhow::Error>(cid)
+            }
+            .await;
+            let mut tracked = self.tracked.lock();
+            let entry = tracked.get_mut(&(smsg.from(), smsg.sequence()));
+            let record = match replaced {
+                Ok(cid) => {
+                    if let Some(entry) = entry {
+                        entry.message = cid;
+                        entry.replacements += 1;
+                    }
+                    record(ResubmitAction::Replaced, &smsg, Some(cid), detail)?
+                }
+                Err(e) => {
+                    if let Some(entry) = entry {
+                        entry.abandoned = true;
+                    }
+                    record(ResubmitAction::Failed, &smsg, None, format!("{e:#}"))?
+                }
+            };
+            drop(tracked);
+            self.record(settings.as_ref(), record)?;
+        }
+        self.save(settings.as_ref())
+    }
+}
+
+/// Signs `message` with the key of its sender.
+async fn sign(
+    keystore: &RwLock<KeyStore>,
+    remote_signers: &RemoteSigners,
+    message: Message,
+    eth_chain_id: u64,
+) -> anyhow::Result<SignedMessage> {
+    let signer = remote_signers.find(&message.from, &mut *keystore.write().await)?;
+    let signing_bytes =
+        SignedMessage::message_signing_bytes(&message, signer.sig_type(), eth_chain_id)?;
+    let signature = signer.sign(&signing_bytes).await?;
+    let smsg = SignedMessage::new_unchecked(message, signature);
+    smsg.verify(eth_chain_id).map_err(anyhow::Error::msg)?;
+    Ok(smsg)
+}
+
+/// Returns `amount` increased by `percent`.
+fn bumped(amount: &TokenAmount, percent: u32) -> TokenAmount {
+    (amount * (100 + u64::from(percent))).div_floor(100)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MemoryDB;
+
+    #[test]
+    fn replace_underpriced_messages() {
+        let engine = AutoResubmit::new(AutoResubmitConfig {
+            enabled: true,
+            max_fee_cap: TokenAmount::from_atto(1000),
+            bump_schedule: vec![25, 100],
+            abandon_after: 10,
+            log_size: 10,
+        });
+        let mut tracked = Tracked {
+            message: Cid::default(),
+            first_epoch: 100,
+            replacements: 0,
+            abandoned: false,
+            fee_cap_reached: false,
+        };
+        let message = Message {
+            gas_fee_cap: TokenAmount::from_atto(100),
+            gas_premium: TokenAmount::from_atto(10),
+            ..Default::default()
+        };
+        let atto = TokenAmount::from_atto;
+        let decide = |tracked: &mut Tracked, epoch, base_fee, max_base_fee| {
+            engine.decide(
+                tracked,
+                &message,
+                epoch,
+                &atto(base_fee),
+                &atto(max_base_fee),
+            )
+        };
+
+        assert_eq!(decide(&mut tracked, 101, 90, 200), Decision::Keep);
+        // The premium is raised to the replace-by-fee minimum, and the fee cap
+        // covers the highest recent base fee.
+        let Decision::Replace(replacement) = decide(&mut tracked, 102, 150, 200) else {
+            panic!("the message is underpriced");
+        };
+        assert_eq!(replacement.gas_premium, atto(14));
+        assert_eq!(replacement.gas_fee_cap, atto(264));
+        tracked.replacements = 1;
+        let Decision::Replace(replacement) = decide(&mut tracked, 103, 150, 200) else {
+            panic!("the message is underpriced");
+        };
+        assert_eq!(replacement.gas_premium, atto(20));
+        assert_eq!(replacement.gas_fee_cap, atto(420));
+        // Replacements are capped to the maximum fee cap, and a base fee out of
+        // its reach is recorded once.
+        assert_eq!(
+            decide(&mut tracked, 104, 600, 600),
+            Decision::Replace(Box::new(Message {
+                gas_fee_cap: atto(1000),
+                gas_premium: atto(20),
+                ..message.clone()
+            }))
+        );
+        assert_eq!(
+            decide(&mut tracked, 105, 2000, 2000),
+            Decision::FeeCapReached
+        );
+        assert_eq!(decide(&mut tracked, 106, 2000, 2000), Decision::Keep);
+
+        assert_eq!(decide(&mut tracked, 110, 150, 200), Decision::Abandon);
+        assert_eq!(decide(&mut tracked, 111, 150, 200), Decision::Keep);
+    }
+
+    #[test]
+    fn tracked_messages_survive_restarts() {
+        let settings = MemoryDB::default();
+        let engine = AutoResubmit::default();
+        engine.tracked.lock().insert(
+            (Address::new_id(1000), 7),
+            Tracked {
+                message: Cid::default(),
+                first_epoch: 100,
+                replacements: 2,
+                abandoned: false,
+                fee_cap_reached: true,
+            },
+        );
+        engine.save(&settings).unwrap();
+
+        let restarted = AutoResubmit::default();
+        restarted.restore(&settings).unwrap();
+        assert_eq!(restarted.tracked_messages(), engine.tracked_messages());
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        let settings = MemoryDB::default();
+        assert!(AutoResubmit::default()
+            .set_paused(&settings, true, 1)
+            .is_err());
+
+        let engine = AutoResubmit::new(AutoResubmitConfig {
+            enabled: true,
+            log_size: 2,
+            ..Default::default()
+        });
+        assert!(!engine.is_paused(&settings).unwrap());
+        assert!(engine.set_paused(&settings, true, 1).unwrap());
+        assert!(!engine.set_paused(&settings, true, 2).unwrap());
+        assert!(engine.is_paused(&settings).unwrap());
+        assert!(engine.set_paused(&settings, false, 3).unwrap());
+        assert!(engine.set_paused(&settings, true, 4).unwrap());
+
+        let actions = |limit| {
+            engine
+                .log(&settings, limit)
+                .unwrap()
+                .iter()
+                .map(|record| (record.epoch, record.action))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            actions(10),
+            [(3, ResubmitAction::Resumed), (4, ResubmitAction::Paused)]
+        );
+        assert_eq!(actions(1), [(4, ResubmitAction::Paused)]);
+    }
+}
@@ -1,8 +1,10 @@
 // Copyright 2019-2023 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+pub(in crate::message_pool) mod auto_resubmit;
 pub(in crate::message_pool) mod metrics;
 pub(in crate::message_pool) mod msg_pool;
+pub(in crate::message_pool) mod nonce_reservations;
 pub(in crate::message_pool) mod provider;
 #[cfg(test)]
 mod selection;
@@ -13,7 +15,7 @@ pub(in crate::message_pool) mod utils;
 use std::{borrow::BorrowMut, cmp::Ordering, sync::Arc};
 
 use crate::blocks::Tipset;
-use crate::libp2p::{NetworkMessage, Topic, PUBSUB_MSG_STR};
+use crate::libp2p::{message_topic, NetworkMessage};
 use crate::message::{Message as MessageTrait, SignedMessage};
 use crate::networks::ChainConfig;
 use crate::shim::{address::Address, crypto::Signature};
@@ -90,9 +92,10 @@ where
 
     for m in msgs.iter() {
         let mb = to_vec(m)?;
+        let key_addr = api.resolve_to_key(&m.from(), &ts)?;
         network_sender
             .send_async(NetworkMessage::PubsubMessage {
-                topic: Topic::new(format!("{PUBSUB_MSG_STR}/{network_name}")),
+                topic: message_topic(chain_config, network_name, &key_addr),
                 message: mb,
             })
             .await
@@ -326,7 +329,7 @@ pub mod tests {
     use crate::message::SignedMessage;
     use crate::networks::ChainConfig;
     use crate::shim::{
-        address::Address,
+        address::{Address, Protocol},
         crypto::SignatureType,
         econ::TokenAmount,
         message::{Message, Message_v3},
@@ -644,6 +647,121 @@ pub mod tests {
         assert_eq!(cur_ts.as_ref(), &tipset);
     }
 
+    #[tokio::test]
+    async fn test_nonce_reservations() {
+        let keystore = KeyStore::new(KeyStoreConfig::Memory).unwrap();
+        let mut wallet = Wallet::new(keystore);
+        let sender = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+        let target = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+
+        let tma = TestApi::default();
+        tma.set_state_sequence(&sender, 0);
+        let (tx, _rx) = flume::bounded(50);
+        let mut services = JoinSet::new();
+        let mpool = MessagePool::new(
+            tma,
+            "mptest".to_string(),
+            tx,
+            Default::default(),
+            Arc::default(),
+            &mut services,
+        )
+        .unwrap();
+
+        let lease = Duration::from_secs(60);
+        let first = mpool.reserve_nonce(&sender, lease).unwrap();
+        let second = mpool.reserve_nonce(&sender, lease).unwrap();
+        assert_eq!((first.nonce, second.nonce), (0, 1));
+        assert_eq!(mpool.next_nonce(&sender).unwrap(), 2);
+
+        // Only the holder of a reservation can push a message using its nonce,
+        // which redeems the reservation.
+        let smsg = create_smsg(&target, &sender, wallet.borrow_mut(), 1, 1000000, 1);
+        assert!(matches!(
+            mpool.push(smsg.clone()).await,
+            Err(Error::SequenceReserved(_, 1))
+        ));
+        assert!(mpool
+            .push_reserved(smsg.clone(), Some(first.lease))
+            .await
+            .is_err());
+        mpool.push_reserved(smsg, Some(second.lease)).await.unwrap();
+        assert!(!mpool.release_nonce(&sender, second.nonce, second.lease));
+
+        // Released and expired nonces are handed out again.
+        assert!(!mpool.release_nonce(&sender, first.nonce, second.lease));
+        assert!(mpool.release_nonce(&sender, first.nonce, first.lease));
+        assert_eq!(mpool.next_nonce(&sender).unwrap(), 0);
+        mpool.reserve_nonce(&sender, Duration::ZERO).unwrap();
+        assert_eq!(mpool.next_nonce(&sender).unwrap(), 0);
+        mpool.reserve_nonce(&sender, lease).unwrap();
+        assert_eq!(mpool.next_nonce(&sender).unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_delegated_sender() {
+        use crate::message::delegated::EVM_METHOD_INVOKE_CONTRACT;
+
+        let keystore = KeyStore::new(KeyStoreConfig::Memory).unwrap();
+        let mut wallet = Wallet::new(keystore);
+        let sender = wallet.generate_addr(SignatureType::Delegated).unwrap();
+        let secp_sender = wallet.generate_addr(SignatureType::Secp256k1).unwrap();
+        assert_eq!(sender.protocol(), Protocol::Delegated);
+
+        let tma = TestApi::default();
+        tma.set_state_sequence(&sender, 0);
+        let (tx, _rx) = flume::bounded(50);
+        let mut services = JoinSet::new();
+        let mpool = MessagePool::new(
+            tma,
+            "mptest".to_string(),
+            tx,
+            Default::default(),
+            Arc::default(),
+            &mut services,
+        )
+        .unwrap();
+        let eth_chain_id = mpool.chain_config.eth_chain_id;
+
+        let umsg: Message = Message_v3 {
+            to: Address::new_id(1000).into(),
+            from: sender.into(),
+            method_num: EVM_METHOD_INVOKE_CONTRACT,
+            gas_limit: 1000000,
+            gas_fee_cap: TokenAmount::from_atto(101).into(),
+            gas_premium: TokenAmount::from_atto(1).into(),
+            ..Message_v3::default()
+        }
+        .into();
+        let signing_bytes =
+            SignedMessage::message_signing_bytes(&umsg, SignatureType::Delegated, eth_chain_id)
+                .unwrap();
+        let sig = wallet.sign(&sender, &signing_bytes).unwrap();
+        let smsg = SignedMessage::new_unchecked(umsg.clone(), sig);
+        mpool.push(smsg).await.unwrap();
+        assert_eq!(mpool.get_sequence(&sender).unwrap(), 1);
+
+        // A signature over the message CID is not valid for a delegated sender.
+        let sig = wallet
+            .sign(&sender, &umsg.cid().unwrap().to_bytes())
+            .unwrap();
+        let smsg = SignedMessage::new_unchecked(umsg.clone(), sig);
+        assert!(mpool.add(smsg).is_err());
+
+        // Neither are signatures of other types.
+        let sig = wallet
+            .sign(&secp_sender, &umsg.cid().unwrap().to_bytes())
+            .unwrap();
+        let smsg = SignedMessage::new_unchecked(umsg, sig);
+        assert_eq!(
+            mpool.add(smsg),
+            Err(Error::InvalidSignatureType(
+                SignatureType::Secp256k1,
+                sender.to_string()
+            ))
+        );
+    }
+
     #[tokio::test]
     async fn test_msg_chains() {
         let keystore = KeyStore::new(KeyStoreConfig::Memory).unwrap();
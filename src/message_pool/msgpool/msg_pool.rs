@@ -6,17 +6,21 @@
 // inclusion in the chain. Messages are added either directly for locally
 // published messages or through pubsub propagation.
 
-use std::{num::NonZeroUsize, sync::Arc, time::Duration};
+use std::{
+    num::NonZeroUsize,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use crate::blocks::{BlockHeader, Tipset};
 use crate::chain::{HeadChange, MINIMUM_BASE_FEE};
 #[cfg(test)]
 use crate::db::SettingsStore;
-use crate::libp2p::{NetworkMessage, Topic, PUBSUB_MSG_STR};
+use crate::libp2p::{message_topic, NetworkMessage};
 use crate::message::{valid_for_block_inclusion, ChainMessage, Message, SignedMessage};
-use crate::networks::{ChainConfig, NEWEST_NETWORK_VERSION};
+use crate::networks::{proof_policy, ChainConfig, NEWEST_NETWORK_VERSION};
 use crate::shim::{
-    address::Address,
+    address::{Address, Protocol},
     crypto::{Signature, SignatureType},
     econ::TokenAmount,
     gas::{price_list_by_network_version, Gas},
@@ -39,6 +43,7 @@ use crate::message_pool::{
     errors::Error,
     head_change, metrics,
     msgpool::{
+        nonce_reservations::{NonceReservation, NonceReservations},
         recover_sig, republish_pending_messages, select_messages_for_block,
         BASE_FEE_LOWER_BOUND_FACTOR_CONSERVATIVE, RBF_DENOM, RBF_NUM,
     },
@@ -197,6 +202,8 @@ pub struct MessagePool<T> {
     pub config: MpoolConfig,
     /// Chain configuration
     pub chain_config: Arc<ChainConfig>,
+    /// Nonces reserved by the clients signing messages from a shared address
+    nonce_reservations: Mutex<NonceReservations>,
 }
 
 impl<T> MessagePool<T>
@@ -213,16 +220,40 @@ where
     /// Push a signed message to the `MessagePool`. Additionally performs basic
     /// checks on the validity of a message.
     pub async fn push(&self, msg: SignedMessage) -> Result<Cid, Error> {
+        self.push_reserved(msg, None).await
+    }
+
+    /// Push a signed message to the `MessagePool`, redeeming the reservation
+    /// `lease` of its nonce, see [`MessagePool::reserve_nonce`]. Messages using
+    /// a nonce reserved by another lease are rejected.
+    pub async fn push_reserved(
+        &self,
+        msg: SignedMessage,
+        lease: Option<u64>,
+    ) -> Result<Cid, Error> {
         self.check_message(&msg)?;
         let cid = msg.cid().map_err(|err| Error::Other(err.to_string()))?;
         let cur_ts = self.cur_tipset.lock().clone();
-        let publish = self.add_tipset(msg.clone(), &cur_ts, true)?;
+        let from = msg.from();
+        let sequence = msg.sequence();
+        let publish = {
+            // Held while the message is added, so that the nonce can't be
+            // reserved in between.
+            let mut reservations = self.nonce_reservations.lock();
+            if !reservations.may_use(&from, sequence, lease) {
+                return Err(Error::SequenceReserved(from.to_string(), sequence));
+            }
+            let publish = self.add_tipset(msg.clone(), &cur_ts, true)?;
+            reservations.redeem(&from, sequence);
+            publish
+        };
         let msg_ser = to_vec(&msg)?;
         self.add_local(msg)?;
         if publish {
+            let key_addr = self.api.resolve_to_key(&from, &cur_ts)?;
             self.network_sender
                 .send_async(NetworkMessage::PubsubMessage {
-                    topic: Topic::new(format!("{}/{}", PUBSUB_MSG_STR, self.network_name)),
+                    topic: message_topic(&self.chain_config, &self.network_name, &key_addr),
                     message: msg_ser,
                 })
                 .await
@@ -242,6 +273,7 @@ where
         if msg.gas_fee_cap().atto() < &MINIMUM_BASE_FEE.into() {
             return Err(Error::GasFeeCapTooLow);
         }
+        check_signature_type(msg)?;
         self.verify_msg_sig(msg)
     }
 
@@ -266,7 +298,8 @@ where
             return Ok(());
         }
 
-        msg.verify().map_err(Error::Other)?;
+        msg.verify(self.chain_config.eth_chain_id)
+            .map_err(Error::Other)?;
 
         self.sig_val_cache.lock().put(cid, ());
 
@@ -292,6 +325,18 @@ where
                 "Sender actor is not a valid top-level sender".to_owned(),
             ));
         }
+        if proof_policy::carries_proofs(msg.message()) {
+            // Messages to actors that don't exist yet are left to the VM.
+            if let Ok(recipient) = self.api.get_actor_after(&msg.to(), cur_ts) {
+                proof_policy::check_message_proofs(
+                    &self.chain_config.network,
+                    msg.message(),
+                    &recipient.code,
+                    nv,
+                )
+                .map_err(|e| Error::Other(e.to_string()))?;
+            }
+        }
 
         let publish = verify_msg_before_add(&msg, cur_ts, local, &self.chain_config)?;
 
@@ -323,6 +368,7 @@ where
 
     /// Get the sequence for a given address, return Error if there is a failure
     /// to retrieve the respective sequence.
+    #[cfg(test)]
     pub fn get_sequence(&self, addr: &Address) -> Result<u64, Error> {
         let cur_ts = self.cur_tipset.lock().clone();
 
@@ -342,6 +388,52 @@ where
         }
     }
 
+    /// Returns the lowest nonce of `addr` that is neither used by a pending
+    /// message nor reserved, see [`MessagePool::reserve_nonce`]. It fills the
+    /// gaps left by the reservations that expired.
+    pub fn next_nonce(&self, addr: &Address) -> Result<u64, Error> {
+        let mut reservations = self.nonce_reservations.lock();
+        self.next_unreserved_nonce(addr, &mut reservations)
+    }
+
+    fn next_unreserved_nonce(
+        &self,
+        addr: &Address,
+        reservations: &mut NonceReservations,
+    ) -> Result<u64, Error> {
+        reservations.prune(Instant::now());
+        let cur_ts = self.cur_tipset.lock().clone();
+        let mut nonce = self.get_state_sequence(addr, &cur_ts)?;
+        let pending = self.pending.read();
+        let mset = pending.get(addr);
+        while mset.is_some_and(|mset| mset.msgs.contains_key(&nonce))
+            || reservations.is_reserved(addr, nonce)
+        {
+            nonce += 1;
+        }
+        Ok(nonce)
+    }
+
+    /// Reserves the next nonce of `addr` for `duration`, so that the clients
+    /// signing messages from the same address don't race on nonces. The
+    /// reservation is redeemed when a message using the nonce is pushed with
+    /// [`MessagePool::push_reserved`], and otherwise expires.
+    pub fn reserve_nonce(
+        &self,
+        addr: &Address,
+        duration: Duration,
+    ) -> Result<NonceReservation, Error> {
+        let mut reservations = self.nonce_reservations.lock();
+        let nonce = self.next_unreserved_nonce(addr, &mut reservations)?;
+        Ok(reservations.reserve(*addr, nonce, duration))
+    }
+
+    /// Releases the reservation `lease` of `nonce` of `addr`, returns whether
+    /// it was still held.
+    pub fn release_nonce(&self, addr: &Address, nonce: u64, lease: u64) -> bool {
+        self.nonce_reservations.lock().release(addr, nonce, lease)
+    }
+
     /// Get the state of the sequence for a given address in `cur_ts`.
     fn get_state_sequence(&self, addr: &Address, cur_ts: &Tipset) -> Result<u64, Error> {
         let actor = self.api.get_actor_after(addr, cur_ts)?;
@@ -391,6 +483,18 @@ where
         Some(msg_vec)
     }
 
+    /// Returns the pending messages of the addresses that pushed messages to
+    /// this node.
+    pub fn local_pending(&self) -> Vec<SignedMessage> {
+        let local_addrs: HashSet<Address> = self.local_addrs.read().iter().copied().collect();
+        let pending = self.pending.read();
+        local_addrs
+            .iter()
+            .filter_map(|addr| pending.get(addr))
+            .flat_map(|mset| mset.msgs.values().cloned())
+            .collect()
+    }
+
     /// Return Vector of signed messages given a block header for self.
     pub fn messages_for_blocks(&self, blks: &[BlockHeader]) -> Result<Vec<SignedMessage>, Error> {
         let mut msg_vec: Vec<SignedMessage> = Vec::new();
@@ -502,6 +606,7 @@ where
             network_sender,
             repub_trigger,
             chain_config: Arc::clone(&chain_config),
+            nonce_reservations: Default::default(),
         };
 
         mp.load_local()?;
@@ -634,6 +739,25 @@ where
     Ok(())
 }
 
+/// Checks that the signature type matches the protocol of the sender, before
+/// the signature is verified. Delegated (`f410`) senders are Ethereum
+/// accounts, which sign with delegated signatures, and only them. Signatures
+/// from other senders are verified against their address as is.
+fn check_signature_type(msg: &SignedMessage) -> Result<(), Error> {
+    let from = msg.from();
+    let sig_type = msg.signature().signature_type();
+    let valid = match from.protocol() {
+        Protocol::Delegated => sig_type == SignatureType::Delegated,
+        Protocol::BLS => sig_type == SignatureType::Bls,
+        Protocol::Secp256k1 => sig_type == SignatureType::Secp256k1,
+        Protocol::ID | Protocol::Actor => sig_type != SignatureType::Delegated,
+    };
+    if !valid {
+        return Err(Error::InvalidSignatureType(sig_type, from.to_string()));
+    }
+    Ok(())
+}
+
 fn verify_msg_before_add(
     m: &SignedMessage,
     cur_ts: &Tipset,
@@ -0,0 +1,123 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Reservations of nonces by the clients that sign messages from a shared
+//! address. A client reserves a nonce, signs its message with it and pushes
+//! it with the lease, while the nonces handed to the other clients skip the
+//! reserved one, and their pushes using it are rejected. Reservations are
+//! leases: the ones that are neither redeemed by a push nor released expire,
+//! and their nonces are handed out again.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::shim::address::Address;
+use ahash::HashMap;
+
+/// Duration of the reservations that don't specify one.
+pub const DEFAULT_NONCE_LEASE: Duration = Duration::from_secs(5 * 60);
+/// Longest duration of a reservation.
+pub const MAX_NONCE_LEASE: Duration = Duration::from_secs(60 * 60);
+
+/// Nonce reserved by a client, see [`MessagePool::reserve_nonce`](super::msg_pool::MessagePool::reserve_nonce).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NonceReservation {
+    pub address: Address,
+    pub nonce: u64,
+    /// Identifier of the reservation, required to push a message using the
+    /// nonce or to release it.
+    pub lease: u64,
+    pub expiration: SystemTime,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Lease {
+    id: u64,
+    expiration: Instant,
+}
+
+#[derive(Debug, Default)]
+pub(in crate::message_pool) struct NonceReservations {
+    leases: HashMap<Address, BTreeMap<u64, Lease>>,
+    next_lease: u64,
+}
+
+impl NonceReservations {
+    /// Forgets the expired reservations.
+    pub fn prune(&mut self, now: Instant) {
+        self.leases.retain(|_, leases| {
+            leases.retain(|_, lease| lease.expiration > now);
+            !leases.is_empty()
+        });
+    }
+
+    pub fn is_reserved(&self, address: &Address, nonce: u64) -> bool {
+        self.leases
+            .get(address)
+            .is_some_and(|leases| leases.contains_key(&nonce))
+    }
+
+    /// Reserves `nonce` of `address` for `duration`, capped to
+    /// [`MAX_NONCE_LEASE`].
+    pub fn reserve(
+        &mut self,
+        address: Address,
+        nonce: u64,
+        duration: Duration,
+    ) -> NonceReservation {
+        let duration = duration.min(MAX_NONCE_LEASE);
+        self.next_lease += 1;
+        let lease = Lease {
+            id: self.next_lease,
+            expiration: Instant::now() + duration,
+        };
+        self.leases.entry(address).or_default().insert(nonce, lease);
+        NonceReservation {
+            address,
+            nonce,
+            lease: lease.id,
+            expiration: SystemTime::now() + duration,
+        }
+    }
+
+    /// Releases the reservation of `nonce` of `address`, if it is the lease
+    /// `id`. Returns whether the reservation was released.
+    pub fn release(&mut self, address: &Address, nonce: u64, id: u64) -> bool {
+        let Some(leases) = self.leases.get_mut(address) else {
+            return false;
+        };
+        if leases.get(&nonce).map(|lease| lease.id) != Some(id) {
+            return false;
+        }
+        leases.remove(&nonce);
+        if leases.is_empty() {
+            self.leases.remove(address);
+        }
+        true
+    }
+
+    /// Whether a message pushed with the lease `id`, if any, may use `nonce` of
+    /// `address`, i.e. whether the nonce isn't reserved by another lease.
+    pub fn may_use(&mut self, address: &Address, nonce: u64, id: Option<u64>) -> bool {
+        self.prune(Instant::now());
+        match self
+            .leases
+            .get(address)
+            .and_then(|leases| leases.get(&nonce))
+        {
+            Some(lease) => Some(lease.id) == id,
+            None => true,
+        }
+    }
+
+    /// Redeems the reservation of `nonce` of `address`, if any, once a message
+    /// using it was pushed.
+    pub fn redeem(&mut self, address: &Address, nonce: u64) {
+        if let Some(leases) = self.leases.get_mut(address) {
+            leases.remove(&nonce);
+            if leases.is_empty() {
+                self.leases.remove(address);
+            }
+        }
+    }
+}
@@ -5,6 +5,7 @@ use std::sync::Arc;
 
 use crate::blocks::{BlockHeader, Tipset, TipsetKeys};
 use crate::chain::HeadChange;
+use crate::interpreter::resolve_to_key_addr;
 use crate::message::{ChainMessage, SignedMessage};
 use crate::message_pool::msg_pool::{
     MAX_ACTOR_PENDING_MESSAGES, MAX_UNTRUSTED_ACTOR_PENDING_MESSAGES,
@@ -41,6 +42,9 @@ pub trait Provider {
     /// `StateTree` will be rooted at. Return `ActorState` or Error
     /// depending on whether or not `ActorState` is found
     fn get_actor_after(&self, addr: &Address, ts: &Tipset) -> Result<ActorState, Error>;
+    /// Return the key address of the account `addr` in the state of the given
+    /// tipset
+    fn resolve_to_key(&self, addr: &Address, ts: &Tipset) -> Result<Address, Error>;
     /// Return the signed messages for given block header
     fn messages_for_block(
         &self,
@@ -110,6 +114,13 @@ where
         actor.ok_or_else(|| Error::Other("No actor state".to_owned()))
     }
 
+    fn resolve_to_key(&self, addr: &Address, ts: &Tipset) -> Result<Address, Error> {
+        let state = StateTree::new_from_root(self.sm.blockstore_owned(), ts.parent_state())
+            .map_err(|e| Error::Other(e.to_string()))?;
+        resolve_to_key_addr(&state, self.sm.blockstore(), addr)
+            .map_err(|e| Error::Other(e.to_string()))
+    }
+
     fn messages_for_block(
         &self,
         h: &BlockHeader,
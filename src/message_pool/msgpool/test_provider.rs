@@ -170,6 +170,10 @@ impl Provider for TestApi {
         Ok(actor)
     }
 
+    fn resolve_to_key(&self, addr: &Address, _ts: &Tipset) -> Result<Address, Error> {
+        Ok(*addr)
+    }
+
     fn messages_for_block(
         &self,
         h: &BlockHeader,
@@ -4,10 +4,14 @@
 pub mod db;
 
 use crate::db::DBStatistics;
+use crate::utils::tls::{serve, TlsServer};
 use ahash::{HashMap, HashMapExt};
 use axum::{http::StatusCode, response::IntoResponse, routing::get, Router};
 use lazy_static::lazy_static;
-use prometheus::core::{AtomicU64, GenericCounterVec, Opts};
+use prometheus::core::{
+    AtomicU64, GenericCounter, GenericCounterVec, GenericGauge, GenericGaugeVec, Opts,
+};
+use prometheus::GaugeVec;
 use prometheus::{Encoder, TextEncoder};
 use std::sync::Arc;
 use std::{net::TcpListener, path::PathBuf};
@@ -24,6 +28,7 @@ pub async fn add_metrics_registry(name: String, registry: prometheus_client::reg
 
 pub async fn init_prometheus<DB>(
     prometheus_listener: TcpListener,
+    tls: Option<Arc<TlsServer>>,
     db_directory: PathBuf,
     db: Arc<DB>,
 ) -> anyhow::Result<()>
@@ -41,10 +46,9 @@ where
         .route("/metrics", get(collect_prometheus_metrics))
         .route("/stats/db", get(collect_db_metrics::<DB>))
         .with_state(db);
-    let server = axum::Server::from_tcp(prometheus_listener)?.serve(app.into_make_service());
 
     // Wait for server to exit
-    Ok(server.await?)
+    serve(prometheus_listener, tls.as_ref(), app).await
 }
 
 async fn collect_prometheus_metrics() -> impl IntoResponse {
@@ -119,6 +123,120 @@ lazy_static! {
             .expect("Registering the lru_cache_miss metric with the metrics registry must succeed");
         lru_cache_miss
     };
+    pub static ref SNAPSHOT_EXPORT_EPOCH: Box<GenericGauge<AtomicU64>> = {
+        let snapshot_export_epoch = Box::new(
+            GenericGauge::<AtomicU64>::new(
+                "snapshot_export_epoch",
+                "Epoch of the most recent scheduled snapshot",
+            )
+            .expect("Defining the snapshot_export_epoch metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(snapshot_export_epoch.clone())
+            .expect(
+                "Registering the snapshot_export_epoch metric with the metrics registry must succeed",
+            );
+        snapshot_export_epoch
+    };
+    pub static ref SNAPSHOT_EXPORT_BYTES: Box<GenericGauge<AtomicU64>> = {
+        let snapshot_export_bytes = Box::new(
+            GenericGauge::<AtomicU64>::new(
+                "snapshot_export_bytes",
+                "Bytes written by the current or most recent scheduled snapshot export",
+            )
+            .expect("Defining the snapshot_export_bytes metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(snapshot_export_bytes.clone())
+            .expect(
+                "Registering the snapshot_export_bytes metric with the metrics registry must succeed",
+            );
+        snapshot_export_bytes
+    };
+    pub static ref SNAPSHOT_EXPORT_FAILURE_TOTAL: Box<GenericCounter<AtomicU64>> = {
+        let snapshot_export_failure_total = Box::new(
+            GenericCounter::<AtomicU64>::new(
+                "snapshot_export_failure_total",
+                "Total number of failed scheduled snapshot exports",
+            )
+            .expect("Defining the snapshot_export_failure_total metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(snapshot_export_failure_total.clone())
+            .expect(
+                "Registering the snapshot_export_failure_total metric with the metrics registry must succeed",
+            );
+        snapshot_export_failure_total
+    };
+    pub static ref POST_WATCHDOG_ALERT_TOTAL: Box<GenericCounterVec<AtomicU64>> = {
+        let post_watchdog_alert_total = Box::new(
+            GenericCounterVec::<AtomicU64>::new(
+                Opts::new(
+                    "post_watchdog_alert_total",
+                    "Total number of invalid or missing window PoSt submissions of the watched miners",
+                ),
+                &[labels::KIND],
+            )
+            .expect("Defining the post_watchdog_alert_total metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(post_watchdog_alert_total.clone())
+            .expect(
+                "Registering the post_watchdog_alert_total metric with the metrics registry must succeed",
+            );
+        post_watchdog_alert_total
+    };
+    pub static ref RESOURCE_GOVERNOR_LEVEL: Box<GenericGauge<AtomicU64>> = {
+        let resource_governor_level = Box::new(
+            GenericGauge::<AtomicU64>::new(
+                "resource_governor_level",
+                "Number of times the verification parallelism is halved because of resource pressure",
+            )
+            .expect("Defining the resource_governor_level metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(resource_governor_level.clone())
+            .expect(
+                "Registering the resource_governor_level metric with the metrics registry must succeed",
+            );
+        resource_governor_level
+    };
+    pub static ref RESOURCE_GOVERNOR_PARALLELISM: Box<GenericGaugeVec<AtomicU64>> = {
+        let resource_governor_parallelism = Box::new(
+            GenericGaugeVec::<AtomicU64>::new(
+                Opts::new(
+                    "resource_governor_parallelism",
+                    "Number of verification or validation tasks currently allowed to run in parallel",
+                ),
+                &[labels::KIND],
+            )
+            .expect("Defining the resource_governor_parallelism metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(resource_governor_parallelism.clone())
+            .expect(
+                "Registering the resource_governor_parallelism metric with the metrics registry must succeed",
+            );
+        resource_governor_parallelism
+    };
+    pub static ref RESOURCE_PRESSURE: Box<GaugeVec> = {
+        let resource_pressure = Box::new(
+            GaugeVec::new(
+                Opts::new(
+                    "resource_pressure",
+                    "CPU load per CPU and share of the memory in use, in percent",
+                ),
+                &[labels::KIND],
+            )
+            .expect("Defining the resource_pressure metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(resource_pressure.clone())
+            .expect(
+                "Registering the resource_pressure metric with the metrics registry must succeed",
+            );
+        resource_pressure
+    };
 }
 
 pub mod labels {
@@ -130,4 +248,18 @@ pub mod values {
     pub const TIPSET: &str = "tipset";
     /// tipset cache in state manager
     pub const STATE_MANAGER_TIPSET: &str = "sm_tipset";
+    /// Cache of the state tree blocks read by the VM externs.
+    pub const STATE_BLOCK: &str = "state_block";
+    /// Window `PoSt` submission that failed local verification.
+    pub const INVALID_POST: &str = "invalid";
+    /// Partitions without a window `PoSt` submission.
+    pub const MISSING_POST: &str = "missing";
+    /// Verification of proofs and signatures.
+    pub const VERIFICATION: &str = "verification";
+    /// Validation of blocks.
+    pub const VALIDATION: &str = "validation";
+    /// Load average over the last minute, per CPU.
+    pub const CPU_LOAD: &str = "cpu_load";
+    /// Share of the memory in use.
+    pub const MEMORY: &str = "memory";
 }
@@ -0,0 +1,224 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Wasm actor bundles of the networks.
+//!
+//! A network requires the bundles of its upgrades, see [`HeightInfo`](super::HeightInfo). The
+//! missing ones are loaded from `<manifest>.car` files in a local directory,
+//! or downloaded from the URLs of [`ACTOR_BUNDLES`] and saved in that
+//! directory. A bundle is stored in the blockstore only if its root is the
+//! expected manifest and all of its blocks match their CID.
+
+use std::io::Cursor;
+use std::path::Path;
+
+use super::ChainConfig;
+use crate::build::ACTOR_BUNDLES;
+use crate::db::MemoryDB;
+use crate::shim::{machine::Manifest, version::NetworkVersion};
+use crate::utils::db::car_stream::CarStream;
+use crate::utils::net::global_http_client;
+use anyhow::Context as _;
+use cid::Cid;
+use futures::TryStreamExt;
+use fvm_ipld_blockstore::Blockstore;
+use tracing::info;
+
+/// Returns the manifests of the bundles required by the network, in the order
+/// of the upgrades.
+pub fn required_manifests(chain_config: &ChainConfig) -> Vec<Cid> {
+    let mut manifests = vec![];
+    for info in super::sort_by_epoch(&chain_config.height_infos) {
+        if let Some(bundle) = info.bundle {
+            if !manifests.contains(&bundle) {
+                manifests.push(bundle);
+            }
+        }
+    }
+    manifests
+}
+
+/// Returns the manifest of the bundle that runs at `network_version`, which
+/// is the one of the last upgrade with a bundle.
+pub fn manifest_cid(chain_config: &ChainConfig, network_version: NetworkVersion) -> Option<Cid> {
+    super::sort_by_epoch(&chain_config.height_infos)
+        .into_iter()
+        .filter(|info| NetworkVersion::from(info.height) <= network_version)
+        .filter_map(|info| info.bundle)
+        .last()
+}
+
+/// Returns `true` if the manifest and the code of all its actors are stored.
+pub fn is_bundle_stored(db: &impl Blockstore, manifest: &Cid) -> anyhow::Result<bool> {
+    if !db.has(manifest)? {
+        return Ok(false);
+    }
+    let manifest = Manifest::load(db, manifest)?;
+    for (_, code) in manifest.builtin_actors() {
+        if !db.has(code)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Stores the bundles required by the network that are missing from `db`.
+/// Bundles are read from and downloaded to `bundle_dir`.
+pub async fn ensure_actor_bundles(
+    db: &impl Blockstore,
+    chain_config: &ChainConfig,
+    bundle_dir: &Path,
+) -> anyhow::Result<()> {
+    for manifest in required_manifests(chain_config) {
+        if is_bundle_stored(db, &manifest)? {
+            continue;
+        }
+        let path = bundle_dir.join(format!("{manifest}.car"));
+        if path.is_file() {
+            info!("Loading actor bundle {manifest} from {}", path.display());
+            let car = tokio::fs::read(&path).await?;
+            load_bundle(db, &manifest, car)
+                .await
+                .with_context(|| format!("invalid actor bundle {}", path.display()))?;
+        } else {
+            let car = download_bundle(&manifest).await?;
+            load_bundle(db, &manifest, car.clone()).await?;
+            tokio::fs::create_dir_all(bundle_dir).await?;
+            tokio::fs::write(&path, car).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn download_bundle(manifest: &Cid) -> anyhow::Result<Vec<u8>> {
+    let info = ACTOR_BUNDLES
+        .iter()
+        .find(|info| info.manifest == *manifest)
+        .with_context(|| format!("no known source for actor bundle {manifest}"))?;
+    info!("Downloading actor bundle {manifest} from {}", info.url);
+    let response = global_http_client()
+        .get(info.url.clone())
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(response.bytes().await?.to_vec())
+}
+
+/// Verifies the bundle in `car`, which may be compressed, before copying it to
+/// `db`.
+async fn load_bundle(db: &impl Blockstore, manifest: &Cid, car: Vec<u8>) -> anyhow::Result<()> {
+    let stream = CarStream::new(Cursor::new(car)).await?;
+    anyhow::ensure!(
+        stream.header.roots == [*manifest],
+        "expected the root {manifest}, found {:?}",
+        stream.header.roots
+    );
+    let bundle = MemoryDB::default();
+    let blocks: Vec<_> = stream.try_collect().await?;
+    for block in &blocks {
+        anyhow::ensure!(block.valid(), "block {} doesn't match its CID", block.cid);
+        bundle.put_keyed(&block.cid, &block.data)?;
+    }
+    anyhow::ensure!(
+        is_bundle_stored(&bundle, manifest)?,
+        "the code of some actors is missing"
+    );
+    db.put_many_keyed(blocks.into_iter().map(|block| (block.cid, block.data)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::networks::Height;
+    use crate::utils::db::car_stream::{Block, CarHeader};
+    use crate::utils::db::CborStoreExt;
+    use cid::multihash::{Code, MultihashDigest};
+    use integer_encoding::VarInt;
+
+    fn raw_block(data: &[u8]) -> Block {
+        Block {
+            cid: Cid::new_v1(fvm_ipld_encoding::IPLD_RAW, Code::Blake2b256.digest(data)),
+            data: data.to_vec(),
+        }
+    }
+
+    fn car(root: Cid, blocks: &[Block]) -> Vec<u8> {
+        let header = fvm_ipld_encoding::to_vec(&CarHeader {
+            roots: vec![root],
+            version: 1,
+        })
+        .unwrap();
+        let mut car = header.len().encode_var_vec();
+        car.extend(header);
+        for block in blocks {
+            block.write(&mut car).unwrap();
+        }
+        car
+    }
+
+    /// Returns the manifest and the blocks of a bundle with two actors.
+    fn bundle() -> (Cid, Vec<Block>) {
+        let db = MemoryDB::default();
+        let system = raw_block(b"system wasm");
+        let init = raw_block(b"init wasm");
+        let actors = db
+            .put_cbor_default(&vec![("system", system.cid), ("init", init.cid)])
+            .unwrap();
+        let manifest = db.put_cbor_default(&(1, actors)).unwrap();
+        let mut blocks = vec![system, init];
+        for cid in [actors, manifest] {
+            blocks.push(Block {
+                cid,
+                data: db.get(&cid).unwrap().unwrap(),
+            });
+        }
+        (manifest, blocks)
+    }
+
+    #[tokio::test]
+    async fn verify_bundles() {
+        let (manifest, blocks) = bundle();
+        let db = MemoryDB::default();
+
+        // Wrong root.
+        assert!(load_bundle(&db, &manifest, car(blocks[0].cid, &blocks))
+            .await
+            .is_err());
+        // Missing actor code.
+        assert!(load_bundle(&db, &manifest, car(manifest, &blocks[1..]))
+            .await
+            .is_err());
+        // Tampered block.
+        let mut tampered = blocks.clone();
+        tampered[0].data = b"evil wasm".to_vec();
+        assert!(load_bundle(&db, &manifest, car(manifest, &tampered))
+            .await
+            .is_err());
+        assert!(!is_bundle_stored(&db, &manifest).unwrap());
+
+        load_bundle(&db, &manifest, car(manifest, &blocks))
+            .await
+            .unwrap();
+        assert!(is_bundle_stored(&db, &manifest).unwrap());
+    }
+
+    #[test]
+    fn manifests_of_network_versions() {
+        let config = ChainConfig::calibnet();
+        let manifests = required_manifests(&config);
+        assert_eq!(manifests.len(), 3);
+        // Every required bundle can be downloaded.
+        for manifest in &manifests {
+            assert!(ACTOR_BUNDLES.iter().any(|info| info.manifest == *manifest));
+        }
+        assert_eq!(manifest_cid(&config, NetworkVersion::V16), None);
+        assert_eq!(
+            manifest_cid(&config, Height::Hygge.into()),
+            Some(manifests[1])
+        );
+        assert_eq!(
+            manifest_cid(&config, Height::Thunder.into()),
+            manifests.last().copied()
+        );
+    }
+}
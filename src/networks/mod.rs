@@ -1,12 +1,14 @@
 // Copyright 2019-2023 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-use std::{fmt::Display, str::FromStr};
+use std::{fmt::Display, str::FromStr, sync::Arc};
 
-use crate::beacon::{BeaconPoint, BeaconSchedule, DrandBeacon, DrandConfig};
+use crate::beacon::{BeaconPoint, BeaconSchedule, DrandBeacon, DrandConfig, DrandNetwork};
+use crate::db::SettingsStore;
+use crate::shim::address::Address;
 use crate::shim::clock::{ChainEpoch, EPOCH_DURATION_SECONDS};
-use crate::shim::sector::{RegisteredPoStProofV3, RegisteredSealProofV3};
 use crate::shim::version::NetworkVersion;
+use crate::utils::encoding::blake2b_256;
 use anyhow::Error;
 use cid::Cid;
 use fil_actors_shared::v10::runtime::Policy;
@@ -16,9 +18,11 @@ use strum_macros::Display;
 
 mod drand;
 
+pub mod actor_bundle;
 pub mod calibnet;
 pub mod devnet;
 pub mod mainnet;
+pub mod proof_policy;
 
 /// Newest network version for all networks
 pub const NEWEST_NETWORK_VERSION: NetworkVersion = NetworkVersion::V17;
@@ -146,6 +150,31 @@ struct DrandPoint<'a> {
     pub config: &'a DrandConfig<'a>,
 }
 
+/// How to handle a root declared by a block header that doesn't match the
+/// locally computed one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Strictness {
+    /// Reject the block.
+    #[default]
+    Reject,
+    /// Log a warning and accept the block.
+    Warn,
+    /// Skip the check.
+    Ignore,
+}
+
+/// Strictness of the receipt related checks of block validation. Only devnets
+/// may relax them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReceiptValidation {
+    /// Receipt root declared by the block header.
+    pub receipts: Strictness,
+    /// Events roots of the computed receipts, see FIP-0049.
+    pub events: Strictness,
+}
+
 /// Defines all network configuration parameters.
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 #[serde(default)]
@@ -163,6 +192,14 @@ pub struct ChainConfig {
     /// the exported snapshot.
     pub recent_state_roots: i64,
     pub request_window: usize,
+    pub receipt_validation: ReceiptValidation,
+    /// Servers of the `drand` mainnet beacon, in order of preference. The
+    /// public endpoints are used if empty.
+    pub drand_servers: Vec<String>,
+    /// Number of shards of the gossip topic of the messages. The messages of
+    /// a sender are always gossiped on the same shard, so that they keep
+    /// propagating in nonce order. A single shard is the unsharded topic.
+    pub message_topic_shards: u32,
 }
 
 impl ChainConfig {
@@ -179,6 +216,9 @@ impl ChainConfig {
             eth_chain_id: ETH_CHAIN_ID,
             recent_state_roots: DEFAULT_RECENT_STATE_ROOTS,
             request_window: DEFAULT_REQUEST_WINDOW,
+            receipt_validation: ReceiptValidation::default(),
+            drand_servers: vec![],
+            message_topic_shards: 1,
         }
     }
 
@@ -195,6 +235,9 @@ impl ChainConfig {
             eth_chain_id: ETH_CHAIN_ID,
             recent_state_roots: DEFAULT_RECENT_STATE_ROOTS,
             request_window: DEFAULT_REQUEST_WINDOW,
+            receipt_validation: ReceiptValidation::default(),
+            drand_servers: vec![],
+            message_topic_shards: 1,
         }
     }
 
@@ -205,21 +248,14 @@ impl ChainConfig {
         policy.minimum_verified_allocation_size = 256.into();
         policy.pre_commit_challenge_delay = 10;
 
-        #[allow(clippy::disallowed_types)]
-        let allowed_proof_types = std::collections::HashSet::from_iter(vec![
-            RegisteredSealProofV3::StackedDRG2KiBV1,
-            RegisteredSealProofV3::StackedDRG8MiBV1,
-        ]);
-        policy.valid_pre_commit_proof_type = allowed_proof_types;
-        #[allow(clippy::disallowed_types)]
-        let allowed_proof_types = std::collections::HashSet::from_iter(vec![
-            RegisteredPoStProofV3::StackedDRGWindow2KiBV1,
-            RegisteredPoStProofV3::StackedDRGWindow8MiBV1,
-        ]);
-        policy.valid_post_proof_type = allowed_proof_types;
+        let network = NetworkChain::Devnet("devnet".to_string());
+        policy.valid_pre_commit_proof_type =
+            proof_policy::valid_seal_proofs(&network, NEWEST_NETWORK_VERSION).collect();
+        policy.valid_post_proof_type =
+            proof_policy::valid_window_post_proofs(&network, NEWEST_NETWORK_VERSION).collect();
 
         Self {
-            network: NetworkChain::Devnet("devnet".to_string()),
+            network,
             genesis_cid: None,
             bootstrap_peers: Vec::new(),
             block_delay_secs: 4,
@@ -229,6 +265,9 @@ impl ChainConfig {
             eth_chain_id: ETH_CHAIN_ID,
             recent_state_roots: DEFAULT_RECENT_STATE_ROOTS,
             request_window: DEFAULT_REQUEST_WINDOW,
+            receipt_validation: ReceiptValidation::default(),
+            drand_servers: vec![],
+            message_topic_shards: 1,
         }
     }
 
@@ -254,22 +293,67 @@ impl ChainConfig {
         From::from(height)
     }
 
+    fn drand_schedule(&self) -> &'static [DrandPoint<'static>] {
+        match self.network {
+            NetworkChain::Mainnet => &mainnet::DRAND_SCHEDULE,
+            NetworkChain::Calibnet => &calibnet::DRAND_SCHEDULE,
+            NetworkChain::Devnet(_) => &devnet::DRAND_SCHEDULE,
+        }
+    }
+
+    /// Hash of the drand chain whose entries are in the headers at `epoch`.
+    pub fn drand_chain_hash(&self, epoch: ChainEpoch) -> &'static str {
+        self.drand_schedule()
+            .iter()
+            .rev()
+            .find(|point| epoch >= point.height)
+            .or_else(|| self.drand_schedule().first())
+            .map_or("", |point| point.config.chain_info.hash.as_ref())
+    }
+
+    /// URLs of the drand servers used by the beacon schedule of the chain.
+    pub fn drand_servers(&self) -> Vec<String> {
+        let mut servers: Vec<_> = self
+            .drand_schedule()
+            .iter()
+            .flat_map(|dc| self.drand_servers_of(dc.config))
+            .collect();
+        servers.dedup();
+        servers
+    }
+
+    fn drand_servers_of(&self, config: &DrandConfig) -> Vec<String> {
+        if config.network_type == DrandNetwork::Mainnet && !self.drand_servers.is_empty() {
+            self.drand_servers.clone()
+        } else {
+            config.servers.iter().map(|s| s.to_string()).collect()
+        }
+    }
+
     pub fn get_beacon_schedule(&self, genesis_ts: u64) -> BeaconSchedule {
-        let ds_iter = match self.network {
-            NetworkChain::Mainnet => mainnet::DRAND_SCHEDULE.iter(),
-            NetworkChain::Calibnet => calibnet::DRAND_SCHEDULE.iter(),
-            NetworkChain::Devnet(_) => devnet::DRAND_SCHEDULE.iter(),
-        };
+        self.beacon_schedule_with_store(genesis_ts, None)
+    }
 
+    /// Same as [`ChainConfig::get_beacon_schedule`], but the verified beacon
+    /// entries are kept in `store`.
+    pub fn beacon_schedule_with_store(
+        &self,
+        genesis_ts: u64,
+        store: Option<Arc<dyn SettingsStore + Sync + Send>>,
+    ) -> BeaconSchedule {
         BeaconSchedule(
-            ds_iter
-                .map(|dc| BeaconPoint {
-                    height: dc.height,
-                    beacon: Box::new(DrandBeacon::new(
-                        genesis_ts,
-                        self.block_delay_secs,
-                        dc.config,
-                    )),
+            self.drand_schedule()
+                .iter()
+                .map(|dc| {
+                    let mut beacon = DrandBeacon::new(genesis_ts, self.block_delay_secs, dc.config)
+                        .with_servers(self.drand_servers_of(dc.config));
+                    if let Some(store) = &store {
+                        beacon = beacon.with_store(store.clone());
+                    }
+                    BeaconPoint {
+                        height: dc.height,
+                        beacon: Box::new(beacon),
+                    }
                 })
                 .collect(),
         )
@@ -294,6 +378,16 @@ impl ChainConfig {
     pub fn is_testnet(&self) -> bool {
         !matches!(self.network, NetworkChain::Mainnet)
     }
+
+    /// Shard of the gossip topic of the messages sent by `from`, in
+    /// `0..message_topic_shards`. `from` is expected to be a key address, as
+    /// the ID and key addresses of a sender map to different shards.
+    pub fn message_topic_shard(&self, from: &Address) -> u32 {
+        let shards = self.message_topic_shards.max(1);
+        let hash = blake2b_256(&from.to_bytes());
+        let prefix = u64::from_be_bytes(hash[..8].try_into().expect("hash is 32 bytes long"));
+        (prefix % u64::from(shards)) as u32
+    }
 }
 
 impl Default for ChainConfig {
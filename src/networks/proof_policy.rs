@@ -0,0 +1,370 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Proof types accepted by each network, by network version.
+//!
+//! The `V1_1` seal proofs replaced the `V1` ones for new sectors in network
+//! version 7, with both accepted during the transition, and the `V1_1` window
+//! `PoSt` proofs were added in network version 19. The sector sizes depend on
+//! the network: mainnet and calibnet use 32 and 64 GiB sectors, devnets use 2
+//! KiB and 8 MiB ones.
+//!
+//! The table is advisory: the message pool and the `PoSt` watchdog use it to
+//! catch proofs the miner actor would refuse early, and to pick the proof
+//! types of new sectors. Block validation doesn't consult it, the proof types
+//! of a block are left to the proof verifiers and the actors, so that a devnet
+//! configured with other sector sizes still syncs.
+
+use super::NetworkChain;
+use crate::shim::{
+    message::Message,
+    sector::{
+        RegisteredPoStProof, RegisteredPoStProofV3, RegisteredSealProof, RegisteredSealProofV3,
+        SectorSize,
+    },
+    version::NetworkVersion,
+};
+use anyhow::Context as _;
+use cid::Cid;
+use fil_actor_interface::miner;
+use fil_actor_miner_state::v11::{
+    Method as MinerMethod, PreCommitSectorBatchParams, PreCommitSectorBatchParams2,
+    PreCommitSectorParams, SubmitWindowedPoStParams,
+};
+
+/// Proof type and the network versions that accept it.
+struct Rule<P> {
+    proof: P,
+    since: NetworkVersion,
+    /// First network version that refuses the proof type, if any.
+    until: Option<NetworkVersion>,
+}
+
+impl<P: Copy> Rule<P> {
+    const fn new(proof: P, since: NetworkVersion, until: Option<NetworkVersion>) -> Self {
+        Self {
+            proof,
+            since,
+            until,
+        }
+    }
+
+    fn accepts(&self, network_version: NetworkVersion) -> bool {
+        network_version >= self.since && self.until.map_or(true, |until| network_version < until)
+    }
+}
+
+/// Seal proofs of new sectors, oldest first.
+const SEAL_PROOFS: &[Rule<RegisteredSealProofV3>] = {
+    use RegisteredSealProofV3::*;
+    const V1_UNTIL: Option<NetworkVersion> = Some(NetworkVersion::V8);
+    &[
+        Rule::new(StackedDRG2KiBV1, NetworkVersion::V0, V1_UNTIL),
+        Rule::new(StackedDRG8MiBV1, NetworkVersion::V0, V1_UNTIL),
+        Rule::new(StackedDRG512MiBV1, NetworkVersion::V0, V1_UNTIL),
+        Rule::new(StackedDRG32GiBV1, NetworkVersion::V0, V1_UNTIL),
+        Rule::new(StackedDRG64GiBV1, NetworkVersion::V0, V1_UNTIL),
+        Rule::new(StackedDRG2KiBV1P1, NetworkVersion::V7, None),
+        Rule::new(StackedDRG8MiBV1P1, NetworkVersion::V7, None),
+        Rule::new(StackedDRG512MiBV1P1, NetworkVersion::V7, None),
+        Rule::new(StackedDRG32GiBV1P1, NetworkVersion::V7, None),
+        Rule::new(StackedDRG64GiBV1P1, NetworkVersion::V7, None),
+    ]
+};
+
+const WINNING_POST_PROOFS: &[Rule<RegisteredPoStProofV3>] = {
+    use RegisteredPoStProofV3::*;
+    &[
+        Rule::new(StackedDRGWinning2KiBV1, NetworkVersion::V0, None),
+        Rule::new(StackedDRGWinning8MiBV1, NetworkVersion::V0, None),
+        Rule::new(StackedDRGWinning512MiBV1, NetworkVersion::V0, None),
+        Rule::new(StackedDRGWinning32GiBV1, NetworkVersion::V0, None),
+        Rule::new(StackedDRGWinning64GiBV1, NetworkVersion::V0, None),
+    ]
+};
+
+/// Window `PoSt` proofs, oldest first.
+const WINDOW_POST_PROOFS: &[Rule<RegisteredPoStProofV3>] = {
+    use RegisteredPoStProofV3::*;
+    &[
+        Rule::new(StackedDRGWindow2KiBV1, NetworkVersion::V0, None),
+        Rule::new(StackedDRGWindow8MiBV1, NetworkVersion::V0, None),
+        Rule::new(StackedDRGWindow512MiBV1, NetworkVersion::V0, None),
+        Rule::new(StackedDRGWindow32GiBV1, NetworkVersion::V0, None),
+        Rule::new(StackedDRGWindow64GiBV1, NetworkVersion::V0, None),
+        Rule::new(StackedDRGWindow2KiBV1P1, NetworkVersion::V19, None),
+        Rule::new(StackedDRGWindow8MiBV1P1, NetworkVersion::V19, None),
+        Rule::new(StackedDRGWindow512MiBV1P1, NetworkVersion::V19, None),
+        Rule::new(StackedDRGWindow32GiBV1P1, NetworkVersion::V19, None),
+        Rule::new(StackedDRGWindow64GiBV1P1, NetworkVersion::V19, None),
+    ]
+};
+
+/// Sector sizes supported by `network`.
+pub fn sector_sizes(network: &NetworkChain) -> &'static [SectorSize] {
+    match network {
+        NetworkChain::Mainnet | NetworkChain::Calibnet => &[SectorSize::_32GiB, SectorSize::_64GiB],
+        NetworkChain::Devnet(_) => &[SectorSize::_2KiB, SectorSize::_8MiB],
+    }
+}
+
+fn seal_proof_size(proof: RegisteredSealProofV3) -> Option<SectorSize> {
+    proof.sector_size().ok().map(SectorSize::from)
+}
+
+fn post_proof_size(proof: RegisteredPoStProofV3) -> Option<SectorSize> {
+    proof.sector_size().ok().map(SectorSize::from)
+}
+
+/// Returns the seal proof of the new sectors of `size`.
+pub fn seal_proof(
+    size: SectorSize,
+    network_version: NetworkVersion,
+) -> anyhow::Result<RegisteredSealProof> {
+    SEAL_PROOFS
+        .iter()
+        .rev()
+        .find(|rule| rule.accepts(network_version) && seal_proof_size(rule.proof) == Some(size))
+        .map(|rule| rule.proof.into())
+        .with_context(|| format!("no seal proof for {size:?} sectors in {network_version:?}"))
+}
+
+/// Returns the winning `PoSt` proof of the sectors sealed with `seal_proof`.
+pub fn winning_post_proof(
+    seal_proof: RegisteredSealProof,
+    network_version: NetworkVersion,
+) -> anyhow::Result<RegisteredPoStProof> {
+    let size = seal_proof_size(*seal_proof);
+    WINNING_POST_PROOFS
+        .iter()
+        .rev()
+        .find(|rule| rule.accepts(network_version) && post_proof_size(rule.proof) == size)
+        .map(|rule| rule.proof.into())
+        .with_context(|| {
+            format!("no winning PoSt proof for {seal_proof:?} sectors in {network_version:?}")
+        })
+}
+
+/// Returns whether new sectors of `network` can be sealed with `proof`.
+pub fn is_valid_seal_proof(
+    network: &NetworkChain,
+    proof: RegisteredSealProofV3,
+    network_version: NetworkVersion,
+) -> bool {
+    seal_proof_size(proof).is_some_and(|size| sector_sizes(network).contains(&size))
+        && SEAL_PROOFS
+            .iter()
+            .any(|rule| rule.proof == proof && rule.accepts(network_version))
+}
+
+/// Returns whether `proof` is a winning or window `PoSt` proof type accepted by
+/// `network`.
+pub fn is_valid_post_proof(
+    network: &NetworkChain,
+    proof: RegisteredPoStProofV3,
+    network_version: NetworkVersion,
+) -> bool {
+    post_proof_size(proof).is_some_and(|size| sector_sizes(network).contains(&size))
+        && WINNING_POST_PROOFS
+            .iter()
+            .chain(WINDOW_POST_PROOFS)
+            .any(|rule| rule.proof == proof && rule.accepts(network_version))
+}
+
+/// Seal proofs of the new sectors of `network`.
+pub fn valid_seal_proofs(
+    network: &NetworkChain,
+    network_version: NetworkVersion,
+) -> impl Iterator<Item = RegisteredSealProofV3> + '_ {
+    SEAL_PROOFS
+        .iter()
+        .map(|rule| rule.proof)
+        .filter(move |proof| is_valid_seal_proof(network, *proof, network_version))
+}
+
+/// Window `PoSt` proofs accepted by `network`.
+pub fn valid_window_post_proofs(
+    network: &NetworkChain,
+    network_version: NetworkVersion,
+) -> impl Iterator<Item = RegisteredPoStProofV3> + '_ {
+    WINDOW_POST_PROOFS
+        .iter()
+        .map(|rule| rule.proof)
+        .filter(move |proof| is_valid_post_proof(network, *proof, network_version))
+}
+
+/// Returns whether the parameters of `message` hold proof types, if it is sent
+/// to a miner actor.
+pub fn carries_proofs(message: &Message) -> bool {
+    [
+        MinerMethod::SubmitWindowedPoSt,
+        MinerMethod::PreCommitSector,
+        MinerMethod::PreCommitSectorBatch,
+        MinerMethod::PreCommitSectorBatch2,
+    ]
+    .into_iter()
+    .any(|method| message.method_num == method as u64)
+}
+
+/// Checks the proof types in the parameters of `message`, sent to an actor
+/// with the `recipient` code, so that messages a miner actor would refuse are
+/// caught early. Parameters that can't be decoded are left to the actor.
+pub fn check_message_proofs(
+    network: &NetworkChain,
+    message: &Message,
+    recipient: &Cid,
+    network_version: NetworkVersion,
+) -> anyhow::Result<()> {
+    let is_miner = miner::is_v8_miner_cid(recipient)
+        || miner::is_v9_miner_cid(recipient)
+        || miner::is_v10_miner_cid(recipient)
+        || miner::is_v11_miner_cid(recipient);
+    if !is_miner || !carries_proofs(message) {
+        return Ok(());
+    }
+
+    let params = message.params.bytes();
+    let (seal_proofs, post_proofs) = match message.method_num {
+        m if m == MinerMethod::SubmitWindowedPoSt as u64 => {
+            match fvm_ipld_encoding::from_slice::<SubmitWindowedPoStParams>(params) {
+                Ok(params) => (vec![], params.proofs.iter().map(|p| p.post_proof).collect()),
+                Err(_) => return Ok(()),
+            }
+        }
+        m if m == MinerMethod::PreCommitSector as u64 => {
+            match fvm_ipld_encoding::from_slice::<PreCommitSectorParams>(params) {
+                Ok(params) => (vec![params.seal_proof], vec![]),
+                Err(_) => return Ok(()),
+            }
+        }
+        m if m == MinerMethod::PreCommitSectorBatch as u64 => {
+            match fvm_ipld_encoding::from_slice::<PreCommitSectorBatchParams>(params) {
+                Ok(params) => (
+                    params.sectors.iter().map(|s| s.seal_proof).collect(),
+                    vec![],
+                ),
+                Err(_) => return Ok(()),
+            }
+        }
+        _ => match fvm_ipld_encoding::from_slice::<PreCommitSectorBatchParams2>(params) {
+            Ok(params) => (
+                params.sectors.iter().map(|s| s.seal_proof).collect(),
+                vec![],
+            ),
+            Err(_) => return Ok(()),
+        },
+    };
+    if let Some(proof) = seal_proofs
+        .into_iter()
+        .find(|proof| !is_valid_seal_proof(network, *proof, network_version))
+    {
+        anyhow::bail!("seal proof {proof:?} isn't accepted by {network} in {network_version:?}");
+    }
+    if let Some(proof) = post_proofs
+        .into_iter()
+        .find(|proof| !is_valid_post_proof(network, *proof, network_version))
+    {
+        anyhow::bail!("PoSt proof {proof:?} isn't accepted by {network} in {network_version:?}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use RegisteredPoStProofV3::*;
+    use RegisteredSealProofV3::*;
+
+    #[test]
+    fn seal_proofs_follow_the_network_version() {
+        let seal_proof = |size, nv| *seal_proof(size, nv).unwrap();
+        assert_eq!(
+            seal_proof(SectorSize::_32GiB, NetworkVersion::V6),
+            StackedDRG32GiBV1
+        );
+        assert_eq!(
+            seal_proof(SectorSize::_32GiB, NetworkVersion::V7),
+            StackedDRG32GiBV1P1
+        );
+        assert_eq!(
+            seal_proof(SectorSize::_2KiB, NetworkVersion::V18),
+            StackedDRG2KiBV1P1
+        );
+        assert_eq!(
+            *winning_post_proof(StackedDRG64GiBV1P1.into(), NetworkVersion::V18).unwrap(),
+            StackedDRGWinning64GiBV1
+        );
+
+        let mainnet = NetworkChain::Mainnet;
+        // Both versions are accepted during the transition.
+        assert!(is_valid_seal_proof(
+            &mainnet,
+            StackedDRG32GiBV1,
+            NetworkVersion::V7
+        ));
+        assert!(!is_valid_seal_proof(
+            &mainnet,
+            StackedDRG32GiBV1,
+            NetworkVersion::V8
+        ));
+        assert!(!is_valid_seal_proof(
+            &mainnet,
+            StackedDRG32GiBV1P1,
+            NetworkVersion::V6
+        ));
+        assert!(is_valid_seal_proof(
+            &mainnet,
+            StackedDRG64GiBV1P1,
+            NetworkVersion::V18
+        ));
+        assert!(!is_valid_seal_proof(
+            &mainnet,
+            RegisteredSealProofV3::Invalid(42),
+            NetworkVersion::V18
+        ));
+    }
+
+    #[test]
+    fn proofs_follow_the_network() {
+        let (mainnet, devnet) = (NetworkChain::Mainnet, NetworkChain::Devnet("devnet".into()));
+        assert!(!is_valid_seal_proof(
+            &mainnet,
+            StackedDRG2KiBV1P1,
+            NetworkVersion::V18
+        ));
+        assert!(is_valid_seal_proof(
+            &devnet,
+            StackedDRG2KiBV1P1,
+            NetworkVersion::V18
+        ));
+        assert!(is_valid_post_proof(
+            &mainnet,
+            StackedDRGWinning32GiBV1,
+            NetworkVersion::V18
+        ));
+        assert!(!is_valid_post_proof(
+            &devnet,
+            StackedDRGWinning32GiBV1,
+            NetworkVersion::V18
+        ));
+        assert!(!is_valid_post_proof(
+            &mainnet,
+            StackedDRGWindow32GiBV1P1,
+            NetworkVersion::V18
+        ));
+        assert!(is_valid_post_proof(
+            &mainnet,
+            StackedDRGWindow32GiBV1P1,
+            NetworkVersion::V19
+        ));
+
+        assert_eq!(
+            valid_seal_proofs(&devnet, NetworkVersion::V17).collect::<Vec<_>>(),
+            [StackedDRG2KiBV1P1, StackedDRG8MiBV1P1]
+        );
+        assert_eq!(
+            valid_window_post_proofs(&devnet, NetworkVersion::V17).collect::<Vec<_>>(),
+            [StackedDRGWindow2KiBV1, StackedDRGWindow8MiBV1]
+        );
+    }
+}
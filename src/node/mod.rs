@@ -0,0 +1,215 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Facade for embedding a Forest node in another Rust application.
+//!
+//! The [`Builder`] configures and starts the same set of services as the
+//! `forest` daemon, but in-process. The returned [`Node`] gives access to head
+//! changes and chain state without going through JSON-RPC.
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use forest_filecoin::node::{Builder, HeadChange, NetworkChain};
+//!
+//! let node = Builder::new()
+//!     .chain(NetworkChain::Calibnet)
+//!     .data_dir("/var/lib/forest")
+//!     .rpc(false)
+//!     .auto_download_snapshot(true)
+//!     .start()
+//!     .await?;
+//!
+//! let mut head_changes = node.subscribe_head_changes();
+//! while let Ok(HeadChange::Apply(tipset)) = head_changes.recv().await {
+//!     println!("new head at epoch {}", tipset.epoch());
+//! }
+//! node.shutdown().await
+//! # }
+//! ```
+//!
+//! The node does not install a logger; embedders are expected to set up their
+//! own `tracing` subscriber.
+
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+
+use crate::chain::ChainStore;
+use crate::cli_shared::cli::CliOpts;
+use crate::daemon;
+use crate::networks::ChainConfig;
+use crate::state_manager::StateManager;
+use anyhow::Context as _;
+use tokio::{
+    sync::{broadcast, mpsc, oneshot},
+    task::JoinHandle,
+};
+
+pub use crate::blocks::{Tipset, TipsetKeys};
+pub use crate::chain::inclusion_proof::{verify_inclusion, InclusionProof, ProofBlock};
+pub use crate::chain::HeadChange;
+pub use crate::cli_shared::cli::Config;
+pub use crate::daemon::DaemonDb as NodeDb;
+pub use crate::networks::NetworkChain;
+pub use crate::shim::{address::Address, state_tree::ActorState};
+
+/// Configures an in-process Forest node. Unset options fall back to the same
+/// defaults as the `forest` daemon, except that the keystore is not encrypted
+/// (there is no terminal to prompt for a passphrase).
+pub struct Builder {
+    config: Config,
+    opts: CliOpts,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        let mut config = Config::default();
+        config.client.encrypt_keystore = false;
+        Self::from_config(config)
+    }
+
+    /// Starts from an existing configuration, e.g. one parsed from a
+    /// `config.toml` file.
+    pub fn from_config(config: Config) -> Self {
+        Builder {
+            config,
+            opts: CliOpts::default(),
+        }
+    }
+
+    /// Selects the network to join.
+    pub fn chain(mut self, chain: NetworkChain) -> Self {
+        self.config.chain = Arc::new(ChainConfig::from_chain(&chain));
+        self
+    }
+
+    /// Directory holding the database, keystore and `libp2p` identity.
+    pub fn data_dir(mut self, data_dir: impl Into<PathBuf>) -> Self {
+        self.config.client.data_dir = data_dir.into();
+        self
+    }
+
+    /// Enables or disables the JSON-RPC server.
+    pub fn rpc(mut self, enabled: bool) -> Self {
+        self.config.client.enable_rpc = enabled;
+        self
+    }
+
+    pub fn rpc_address(mut self, address: SocketAddr) -> Self {
+        self.config.client.rpc_address = address;
+        self
+    }
+
+    pub fn metrics_address(mut self, address: SocketAddr) -> Self {
+        self.config.client.metrics_address = address;
+        self
+    }
+
+    /// Encrypts the keystore. The passphrase is read from the
+    /// [`crate::FOREST_KEYSTORE_PHRASE_ENV`] environment variable.
+    pub fn encrypt_keystore(mut self, encrypt: bool) -> Self {
+        self.config.client.encrypt_keystore = encrypt;
+        self
+    }
+
+    /// Imports a snapshot from a local file or URL before syncing.
+    pub fn import_snapshot(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.client.snapshot_path = Some(path.into());
+        self.config.client.snapshot = true;
+        self
+    }
+
+    /// Downloads a snapshot if the database is empty, instead of failing.
+    pub fn auto_download_snapshot(mut self, enabled: bool) -> Self {
+        self.opts.auto_download_snapshot = enabled;
+        self
+    }
+
+    /// Disables the automatic database garbage collection.
+    pub fn no_gc(mut self, disabled: bool) -> Self {
+        self.opts.no_gc = disabled;
+        self
+    }
+
+    /// Starts the node on the current `tokio` runtime. Returns once the chain
+    /// has been loaded and the node is joining the network.
+    pub async fn start(self) -> anyhow::Result<Node> {
+        let Builder { config, opts } = self;
+        let (shutdown_send, mut shutdown_recv) = mpsc::channel(1);
+        let (ready_send, ready_recv) = oneshot::channel();
+
+        let daemon = tokio::spawn({
+            let shutdown_send = shutdown_send.clone();
+            async move {
+                tokio::select! {
+                    ret = daemon::start(opts, config, shutdown_send, Some(ready_send)) => ret,
+                    _ = shutdown_recv.recv() => Ok(()),
+                }
+            }
+        });
+
+        match ready_recv.await {
+            Ok(state_manager) => Ok(Node {
+                state_manager,
+                shutdown_send,
+                daemon,
+            }),
+            // The daemon exited before becoming ready, surface its error.
+            Err(_) => {
+                daemon.await.context("Forest node panicked")??;
+                anyhow::bail!("Forest node stopped before it was ready")
+            }
+        }
+    }
+}
+
+/// Handle to a running in-process Forest node.
+pub struct Node {
+    state_manager: Arc<StateManager<NodeDb>>,
+    shutdown_send: mpsc::Sender<()>,
+    daemon: JoinHandle<anyhow::Result<()>>,
+}
+
+impl Node {
+    pub fn state_manager(&self) -> &Arc<StateManager<NodeDb>> {
+        &self.state_manager
+    }
+
+    pub fn chain_store(&self) -> &Arc<ChainStore<NodeDb>> {
+        self.state_manager.chain_store()
+    }
+
+    /// Returns the current head of the chain.
+    pub fn heaviest_tipset(&self) -> Arc<Tipset> {
+        self.chain_store().heaviest_tipset()
+    }
+
+    /// Subscribes to head changes. Slow receivers may observe
+    /// [`broadcast::error::RecvError::Lagged`].
+    pub fn subscribe_head_changes(&self) -> broadcast::Receiver<HeadChange> {
+        self.chain_store().publisher().subscribe()
+    }
+
+    /// Looks up an actor in the parent state of the current head.
+    pub fn get_actor(&self, address: &Address) -> anyhow::Result<Option<ActorState>> {
+        let head = self.heaviest_tipset();
+        self.state_manager.get_actor(address, *head.parent_state())
+    }
+
+    /// Requests a shutdown and waits for all services to stop.
+    pub async fn shutdown(self) -> anyhow::Result<()> {
+        // The daemon may already have stopped on its own
+        let _ = self.shutdown_send.send(()).await;
+        self.wait().await
+    }
+
+    /// Waits until the node stops, either because a service failed or because
+    /// a shutdown was requested over JSON-RPC.
+    pub async fn wait(self) -> anyhow::Result<()> {
+        self.daemon.await.context("Forest node panicked")?
+    }
+}
@@ -5,9 +5,10 @@ use crate::rpc_api::{beacon_api::*, data_types::RPCState};
 use fvm_ipld_blockstore::Blockstore;
 use jsonrpc_v2::{Data, Error as JsonRpcError, Params};
 
-/// `BeaconGetEntry` returns the beacon entry for the given Filecoin epoch. If
-/// the entry has not yet been produced, the call will block until the entry
-/// becomes available
+/// `BeaconGetEntry` returns the beacon entry for the given Filecoin epoch.
+/// Entries found in the headers of the chain or verified before are served
+/// from the settings store, other entries are fetched from the beacon. If the entry has not yet been
+/// produced, the call will block until the entry becomes available
 pub(in crate::rpc) async fn beacon_get_entry<DB>(
     data: Data<RPCState<DB>>,
     Params(params): Params<BeaconGetEntryParams>,
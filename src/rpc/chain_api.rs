@@ -5,10 +5,14 @@
 use std::sync::Arc;
 
 use crate::blocks::{BlockHeader, Tipset};
-use crate::chain::index::ResolveNullTipset;
+use crate::chain::{
+    export_progress::RPC_EXPORT_PROGRESS, inclusion_proof::prove_inclusion,
+    index::ResolveNullTipset, MissingData,
+};
 use crate::ipld::CidHashSet;
 use crate::json::cid::CidJson;
 use crate::lotus_json::LotusJson;
+use crate::rpc::rpc_util::{chain_error, load_tipset, missing_data_error};
 use crate::rpc_api::{
     chain_api::*,
     data_types::{BlockMessages, RPCState},
@@ -35,7 +39,7 @@ where
         .state_manager
         .blockstore()
         .get_cbor(&msg_cid)?
-        .ok_or("can't find message with that cid")?;
+        .ok_or_else(|| missing_data_error(MissingData::unknown(format!("message {msg_cid}"))))?;
     Ok(LotusJson(ret))
 }
 
@@ -45,9 +49,12 @@ pub(in crate::rpc) async fn chain_export<DB>(
         epoch,
         recent_roots,
         output_path,
-        tipset_keys: tsk,
+        tipset,
         skip_checksum,
         dry_run,
+        canonical,
+        diff,
+        diff_depth,
     }): Params<ChainExportParams>,
 ) -> Result<ChainExportResult, JsonRpcError>
 where
@@ -72,20 +79,46 @@ where
         ))?;
     }
 
-    let head = data.chain_store.tipset_from_keys(&tsk)?;
+    let head = load_tipset(&data, &tipset)?;
     let start_ts =
         data.chain_store
             .chain_index
             .tipset_by_height(epoch, head, ResolveNullTipset::TakeOlder)?;
 
+    let seen = match diff {
+        Some(diff) => {
+            if diff >= start_ts.epoch() {
+                Err(&format!(
+                    "diff epoch must be smaller than the exported epoch {}",
+                    start_ts.epoch()
+                ))?;
+            }
+            let base = data.chain_store.chain_index.tipset_by_height(
+                diff,
+                start_ts.clone(),
+                ResolveNullTipset::TakeOlder,
+            )?;
+            let diff_depth = diff_depth.unwrap_or(chain_finality);
+            let db = Arc::clone(&data.chain_store.db);
+            let tipset = start_ts.clone();
+            tokio::task::spawn_blocking(move || {
+                crate::chain::diff_seen(&db, &base, Some(diff_depth), &tipset)
+            })
+            .await?
+        }
+        None => CidHashSet::default(),
+    };
+
     match if dry_run {
         crate::chain::export::<Sha256>(
             Arc::clone(&data.chain_store.db),
             &start_ts,
             recent_roots,
             VoidAsyncWriter,
-            CidHashSet::default(),
+            seen,
             skip_checksum,
+            canonical,
+            Arc::clone(&RPC_EXPORT_PROGRESS),
         )
         .await
     } else {
@@ -95,8 +128,10 @@ where
             &start_ts,
             recent_roots,
             file,
-            CidHashSet::default(),
+            seen,
             skip_checksum,
+            canonical,
+            Arc::clone(&RPC_EXPORT_PROGRESS),
         )
         .await
     } {
@@ -105,6 +140,11 @@ where
     }
 }
 
+pub(in crate::rpc) async fn chain_export_progress(
+) -> Result<ChainExportProgressResult, JsonRpcError> {
+    Ok(RPC_EXPORT_PROGRESS.progress())
+}
+
 pub(in crate::rpc) async fn chain_read_obj<DB>(
     data: Data<RPCState<DB>>,
     Params(params): Params<ChainReadObjParams>,
@@ -144,15 +184,17 @@ where
         .state_manager
         .blockstore()
         .get_cbor(&blk_cid)?
-        .ok_or("can't find block with that cid")?;
+        .ok_or_else(|| missing_data_error(MissingData::unknown(format!("block {blk_cid}"))))?;
     let blk_msgs = blk.messages();
     let (unsigned_cids, signed_cids) =
-        crate::chain::read_msg_cids(data.state_manager.blockstore(), blk_msgs)?;
+        crate::chain::read_msg_cids(data.state_manager.blockstore(), blk_msgs)
+            .map_err(chain_error)?;
     let (bls_msg, secp_msg) = crate::chain::block_messages_from_cids(
         data.state_manager.blockstore(),
         &unsigned_cids,
         &signed_cids,
-    )?;
+    )
+    .map_err(chain_error)?;
     let cids = unsigned_cids
         .into_iter()
         .chain(signed_cids)
@@ -173,13 +215,14 @@ pub(in crate::rpc) async fn chain_get_tipset_by_height<DB>(
 where
     DB: Blockstore,
 {
-    let (height, tsk) = params;
-    let ts = data.state_manager.chain_store().tipset_from_keys(&tsk)?;
+    let (height, LotusJson(selector)) = params;
+    let ts = load_tipset(&data, &selector)?;
     let tss = data
         .state_manager
         .chain_store()
         .chain_index
-        .tipset_by_height(height, ts, ResolveNullTipset::TakeOlder)?;
+        .tipset_by_height(height, ts, ResolveNullTipset::TakeOlder)
+        .map_err(chain_error)?;
     Ok((*tss).clone().into())
 }
 
@@ -221,15 +264,76 @@ where
 
 pub(in crate::rpc) async fn chain_get_tipset<DB>(
     data: Data<RPCState<DB>>,
-    Params((LotusJson(tsk),)): Params<ChainGetTipSetParams>,
+    Params((LotusJson(selector),)): Params<ChainGetTipSetParams>,
 ) -> Result<ChainGetTipSetResult, JsonRpcError>
 where
     DB: Blockstore,
 {
-    let ts = data.state_manager.chain_store().tipset_from_keys(&tsk)?;
+    let ts = load_tipset(&data, &selector)?;
     Ok((*ts).clone().into())
 }
 
+pub(in crate::rpc) async fn chain_get_path<DB>(
+    data: Data<RPCState<DB>>,
+    Params((LotusJson(from), LotusJson(to))): Params<ChainGetPathParams>,
+) -> Result<ChainGetPathResult, JsonRpcError>
+where
+    DB: Blockstore,
+{
+    let from = load_tipset(&data, &from)?;
+    let to = load_tipset(&data, &to)?;
+    let path = data
+        .state_manager
+        .chain_store()
+        .chain_get_path(from.key(), to.key())?;
+    Ok(path.into_iter().map(Into::into).collect())
+}
+
+pub(in crate::rpc) async fn chain_get_message_inclusion_proof<DB>(
+    data: Data<RPCState<DB>>,
+    Params((CidJson(message), LotusJson(selector))): Params<ChainGetMessageInclusionProofParams>,
+) -> Result<ChainGetMessageInclusionProofResult, JsonRpcError>
+where
+    DB: Blockstore,
+{
+    let chain_store = data.state_manager.chain_store();
+    let tipset = load_tipset(&data, &selector)?;
+    Ok(prove_inclusion(
+        chain_store.blockstore(),
+        &tipset,
+        &message,
+    )?)
+}
+
+pub(in crate::rpc) async fn chain_import_finality_certificate<DB>(
+    data: Data<RPCState<DB>>,
+    Params((certificate,)): Params<ChainImportFinalityCertificateParams>,
+) -> Result<ChainImportFinalityCertificateResult, JsonRpcError>
+where
+    DB: Blockstore,
+{
+    let (tx, rx) = flume::bounded(1);
+    data.finality_tx.send_async((certificate, tx)).await?;
+    rx.recv_async().await??;
+    Ok(())
+}
+
+pub(in crate::rpc) async fn chain_validate<DB>(
+    data: Data<RPCState<DB>>,
+    Params((from, to, restart)): Params<ChainValidateParams>,
+) -> Result<ChainValidateResult, JsonRpcError>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    match from {
+        Some(from) => Ok(Some(
+            data.state_manager
+                .start_chain_validation(from, to, restart)?,
+        )),
+        None => Ok(data.state_manager.chain_validation_progress()?),
+    }
+}
+
 pub(in crate::rpc) async fn chain_get_name<DB>(
     data: Data<RPCState<DB>>,
 ) -> Result<ChainGetNameResult, JsonRpcError>
@@ -248,8 +352,8 @@ pub(in crate::rpc) async fn chain_set_head<DB>(
 where
     DB: Blockstore,
 {
-    let (params,) = params;
-    let new_head = data.state_manager.chain_store().tipset_from_keys(&params)?;
+    let (LotusJson(selector),) = params;
+    let new_head = load_tipset(&data, &selector)?;
     let mut current = data.state_manager.chain_store().heaviest_tipset();
     while current.epoch() >= new_head.epoch() {
         for cid in &current.key().cids {
@@ -266,6 +370,42 @@ where
         .map_err(Into::into)
 }
 
+pub(in crate::rpc) async fn chain_pin<DB>(
+    data: Data<RPCState<DB>>,
+    Params((target,)): Params<ChainPinParams>,
+) -> Result<ChainPinResult, JsonRpcError>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    let state_root = match target {
+        ChainPinTarget::StateRoot(state_root) => state_root,
+        ChainPinTarget::Tipset(selector) => *load_tipset(&data, &selector)?.parent_state(),
+    };
+    // Walks the whole state tree.
+    let chain_store = Arc::clone(data.state_manager.chain_store());
+    tokio::task::spawn_blocking(move || chain_store.pin_state(state_root)).await??;
+    Ok(LotusJson(state_root))
+}
+
+pub(in crate::rpc) async fn chain_unpin<DB>(
+    data: Data<RPCState<DB>>,
+    Params((LotusJson(state_root),)): Params<ChainUnpinParams>,
+) -> Result<ChainUnpinResult, JsonRpcError>
+where
+    DB: Blockstore,
+{
+    Ok(data.state_manager.chain_store().unpin_state(&state_root)?)
+}
+
+pub(in crate::rpc) async fn chain_pins<DB>(
+    data: Data<RPCState<DB>>,
+) -> Result<ChainPinsResult, JsonRpcError>
+where
+    DB: Blockstore,
+{
+    Ok(LotusJson(data.state_manager.chain_store().pinned_states()?))
+}
+
 pub(crate) async fn chain_get_min_base_fee<DB>(
     data: Data<RPCState<DB>>,
     Params(params): Params<ChainGetMinBaseFeeParams>,
@@ -286,3 +426,19 @@ where
 
     Ok(min_base_fee.atto().to_string())
 }
+
+/// Returns the gas market history of the heaviest chain, see
+/// [`crate::chain::gas_history`].
+pub(crate) async fn chain_gas_history<DB>(
+    data: Data<RPCState<DB>>,
+    Params(params): Params<ChainGasHistoryParams>,
+) -> Result<ChainGasHistoryResult, JsonRpcError>
+where
+    DB: Blockstore,
+{
+    let (first, last, resolution) = params;
+    Ok(data
+        .state_manager
+        .chain_store()
+        .gas_history(first, last, resolution)?)
+}
@@ -0,0 +1,160 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use crate::key_management::{eth_signature, personal_message, TypedData};
+use crate::message::delegated::{
+    eth_address, eth_tx_hash, EAM_METHOD_CREATE_EXTERNAL, EVM_METHOD_INVOKE_CONTRACT,
+};
+use crate::rpc::{mpool_api::sign_and_push, rpc_util::load_tipset};
+use crate::rpc_api::{
+    data_types::RPCState,
+    eth_api::*,
+    eth_types::{EthAddress, EthBytes, EthWord},
+};
+use crate::shim::{
+    address::{Address, Protocol},
+    crypto::{Signature, SignatureType},
+    message::Message,
+};
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::{BytesSer, RawBytes};
+use jsonrpc_v2::{Data, Error as JsonRpcError, Params};
+
+/// Returns the bytecode of a contract at a block. Addresses that aren't
+/// contracts have no bytecode.
+pub(in crate::rpc) async fn eth_get_code<DB: Blockstore + Send + Sync + 'static>(
+    data: Data<RPCState<DB>>,
+    Params((address, block)): Params<EthGetCodeParams>,
+) -> Result<EthGetCodeResult, JsonRpcError> {
+    let tipset = load_tipset(&data, &block.0)?;
+    let bytecode = data
+        .state_manager
+        .evm_bytecode(&address.to_filecoin_address()?, &tipset)?;
+    Ok(EthBytes(bytecode.to_vec()))
+}
+
+/// Returns the value at a position of the storage of a contract at a block.
+pub(in crate::rpc) async fn eth_get_storage_at<DB: Blockstore + Send + Sync + 'static>(
+    data: Data<RPCState<DB>>,
+    Params((address, position, block)): Params<EthGetStorageAtParams>,
+) -> Result<EthGetStorageAtResult, JsonRpcError> {
+    let tipset = load_tipset(&data, &block.0)?;
+    let address = address.to_filecoin_address()?;
+    let state_manager = data.state_manager.clone();
+    let value = tokio::task::spawn_blocking(move || {
+        state_manager.evm_storage_at(&address, position.0, tipset)
+    })
+    .await??;
+    Ok(EthWord(value))
+}
+
+/// Returns the Ethereum addresses of the delegated keys of the wallet.
+pub(in crate::rpc) async fn eth_accounts<DB: Blockstore>(
+    data: Data<RPCState<DB>>,
+) -> Result<EthAccountsResult, JsonRpcError> {
+    let keystore = data.keystore.read().await;
+    Ok(crate::key_management::list_addrs(&keystore)?
+        .iter()
+        .filter(|addr| addr.protocol() == Protocol::Delegated)
+        .filter_map(|addr| eth_address(addr).ok())
+        .map(EthAddress)
+        .collect())
+}
+
+/// Returns the `f410` address of an Ethereum account of the wallet.
+fn delegated_address(address: EthAddress) -> Result<Address, JsonRpcError> {
+    let addr = address.to_filecoin_address()?;
+    if addr.protocol() != Protocol::Delegated {
+        return Err(format!("{address} isn't the address of a delegated key").into());
+    }
+    Ok(addr)
+}
+
+/// Signs `bytes` with the delegated key of `address`.
+async fn sign_delegated<DB: Blockstore>(
+    data: &RPCState<DB>,
+    address: EthAddress,
+    bytes: &[u8],
+) -> Result<Signature, JsonRpcError> {
+    let addr = delegated_address(address)?;
+    let signer = data
+        .remote_signers
+        .find(&addr, &mut *data.keystore.write().await)?;
+    if signer.sig_type() != SignatureType::Delegated {
+        return Err(format!("{address} isn't the address of a delegated key").into());
+    }
+    let sig = signer.sign(bytes).await?;
+    sig.verify(bytes, &addr)?;
+    Ok(sig)
+}
+
+/// Signs a message prefixed as in `EIP-191`, with the delegated key of an
+/// address. Returns the signature in the Ethereum format.
+pub(in crate::rpc) async fn personal_sign<DB: Blockstore>(
+    data: Data<RPCState<DB>>,
+    Params((message, address)): Params<PersonalSignParams>,
+) -> Result<PersonalSignResult, JsonRpcError> {
+    let sig = sign_delegated(&data, address, &personal_message(&message.0)).await?;
+    Ok(EthBytes(eth_signature(&sig)?.to_vec()))
+}
+
+/// Signs `EIP-712` typed data with the delegated key of an address. Returns
+/// the signature in the Ethereum format.
+pub(in crate::rpc) async fn eth_sign_typed_data<DB: Blockstore>(
+    data: Data<RPCState<DB>>,
+    Params((address, typed_data)): Params<EthSignTypedDataParams>,
+) -> Result<EthSignTypedDataResult, JsonRpcError> {
+    let typed_data: TypedData = match typed_data {
+        serde_json::Value::String(json) => serde_json::from_str(&json)?,
+        value => serde_json::from_value(value)?,
+    };
+    let sig = sign_delegated(&data, address, &typed_data.signing_bytes()?).await?;
+    Ok(EthBytes(eth_signature(&sig)?.to_vec()))
+}
+
+/// Converts a transaction to a message from the delegated key of its sender,
+/// then signs it and adds it to the message pool like
+/// `Filecoin.MpoolPushMessage`. Returns the transaction hash.
+pub(in crate::rpc) async fn eth_send_transaction<DB: Blockstore + Send + Sync + 'static>(
+    data: Data<RPCState<DB>>,
+    Params((tx,)): Params<EthSendTransactionParams>,
+) -> Result<EthSendTransactionResult, JsonRpcError> {
+    let input = tx.input.map(|input| input.0).unwrap_or_default();
+    let (to, method_num, params) = match tx.to {
+        Some(to) => (
+            to.to_filecoin_address()?,
+            EVM_METHOD_INVOKE_CONTRACT,
+            if input.is_empty() {
+                RawBytes::default()
+            } else {
+                RawBytes::serialize(BytesSer(&input))?
+            },
+        ),
+        None => (
+            Address::ETHEREUM_ACCOUNT_MANAGER_ACTOR,
+            EAM_METHOD_CREATE_EXTERNAL,
+            RawBytes::serialize(BytesSer(&input))?,
+        ),
+    };
+    let message = Message {
+        from: delegated_address(tx.from)?,
+        to,
+        method_num,
+        params,
+        value: tx.value.map(|value| value.0).unwrap_or_default(),
+        gas_limit: tx.gas.map(|gas| gas.0).unwrap_or_default(),
+        gas_fee_cap: tx.max_fee_per_gas.map(|fee| fee.0).unwrap_or_default(),
+        gas_premium: tx
+            .max_priority_fee_per_gas
+            .map(|fee| fee.0)
+            .unwrap_or_default(),
+        ..Default::default()
+    };
+    let smsg = sign_and_push(&data, message, None).await?;
+    let eth_chain_id = data.state_manager.chain_config().eth_chain_id;
+    Ok(EthWord(eth_tx_hash(
+        &smsg.message,
+        &smsg.signature,
+        eth_chain_id,
+    )?))
+}
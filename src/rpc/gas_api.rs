@@ -21,6 +21,10 @@ use rand_distr::{Distribution, Normal};
 
 const MIN_GAS_PREMIUM: f64 = 100000.0;
 
+/// Default maximum fee of an estimated message, in nanoFIL. Same as the
+/// `DefaultMaxFee` of Lotus, which is 0.07 FIL.
+const DEFAULT_MAX_FEE_NANO: u64 = 70_000_000;
+
 /// Estimate the fee cap
 pub(in crate::rpc) async fn gas_estimate_fee_cap<DB>(
     data: Data<RPCState<DB>>,
@@ -43,13 +47,24 @@ fn estimate_fee_cap<DB>(
 where
     DB: Blockstore,
 {
-    let ts = data.state_manager.chain_store().heaviest_tipset();
-
-    let parent_base_fee = ts.blocks()[0].parent_base_fee();
+    // The highest recent base fee smooths out the oscillations of the base fee
+    // when blocks alternate between full and empty.
+    let chain_store = data.state_manager.chain_store();
+    let base_fee = chain_store
+        .base_fee_history()
+        .into_iter()
+        .max()
+        .unwrap_or_else(|| {
+            chain_store
+                .heaviest_tipset()
+                .min_ticket_block()
+                .parent_base_fee()
+                .clone()
+        });
     let increase_factor =
         (1.0 + (BASE_FEE_MAX_CHANGE_DENOM as f64).recip()).powf(max_queue_blks as f64);
 
-    let fee_in_future = parent_base_fee
+    let fee_in_future = base_fee
         * BigInt::from_f64(increase_factor * (1 << 8) as f64)
             .ok_or("failed to convert fee_in_future f64 to bigint")?;
     let mut out: crate::shim::econ::TokenAmount = fee_in_future.div_floor(1 << 8);
@@ -101,7 +116,11 @@ where
             .chain_store()
             .tipset_from_keys(ts.parents())?;
         blocks += pts.blocks().len();
-        let msgs = crate::chain::messages_for_tipset(data.state_manager.blockstore_owned(), &pts)?;
+        let msgs = crate::chain::messages_for_tipset(
+            data.state_manager.blockstore_owned(),
+            &pts,
+            &data.state_manager.chain_config(),
+        )?;
 
         prices.append(
             &mut msgs
@@ -227,7 +246,7 @@ where
 pub(in crate::rpc) async fn estimate_message_gas<DB>(
     data: &Data<RPCState<DB>>,
     msg: Message,
-    _spec: Option<MessageSendSpec>,
+    spec: Option<MessageSendSpec>,
     tsk: TipsetKeys,
 ) -> Result<Message, JsonRpcError>
 where
@@ -246,6 +265,48 @@ where
         let gfp = estimate_fee_cap(data, msg.clone(), 20, tsk)?;
         msg.set_gas_fee_cap(gfp);
     }
-    // TODO: Cap Gas Fee https://github.com/ChainSafe/forest/issues/901
+    cap_gas_fee(&mut msg, spec.map(|spec| spec.max_fee).unwrap_or_default());
     Ok(msg)
 }
+
+/// Lowers the fee cap of `msg` so that it never pays more than `max_fee`, or
+/// the default maximum fee if `max_fee` is zero. The premium can't exceed the
+/// fee cap.
+fn cap_gas_fee(msg: &mut Message, max_fee: TokenAmount) {
+    let max_fee = match max_fee.is_zero() {
+        true => TokenAmount::from_nano(DEFAULT_MAX_FEE_NANO),
+        false => max_fee,
+    };
+    let gas_limit = msg.gas_limit();
+    if gas_limit == 0 {
+        return;
+    }
+    if msg.gas_fee_cap() * gas_limit > max_fee {
+        msg.set_gas_fee_cap(max_fee.div_floor(gas_limit));
+    }
+    if msg.gas_premium() > msg.gas_fee_cap() {
+        msg.set_gas_premium(msg.gas_fee_cap());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gas_fee_is_capped() {
+        let mut msg = Message {
+            gas_limit: 1_000,
+            gas_fee_cap: TokenAmount::from_atto(1_000),
+            gas_premium: TokenAmount::from_atto(800),
+            ..Default::default()
+        };
+        cap_gas_fee(&mut msg, TokenAmount::from_atto(500_000));
+        assert_eq!(msg.gas_fee_cap, TokenAmount::from_atto(500));
+        assert_eq!(msg.gas_premium, TokenAmount::from_atto(500));
+
+        // The default maximum fee is far above the fee of this message.
+        cap_gas_fee(&mut msg, TokenAmount::zero());
+        assert_eq!(msg.gas_fee_cap, TokenAmount::from_atto(500));
+    }
+}
@@ -6,6 +6,7 @@ mod beacon_api;
 mod chain_api;
 mod common_api;
 mod db_api;
+mod eth_api;
 mod gas_api;
 mod mpool_api;
 mod net_api;
@@ -22,9 +23,10 @@ use std::{net::TcpListener, sync::Arc};
 
 use crate::rpc_api::{
     auth_api::*, beacon_api::*, chain_api::*, common_api::*, data_types::RPCState, db_api::*,
-    gas_api::*, mpool_api::*, net_api::*, node_api::NODE_STATUS, progress_api::GET_PROGRESS,
+    eth_api::*, gas_api::*, mpool_api::*, net_api::*, node_api::*, progress_api::GET_PROGRESS,
     state_api::*, sync_api::*, wallet_api::*,
 };
+use crate::utils::tls::{serve, TlsServer};
 use axum::routing::{get, post};
 use fvm_ipld_blockstore::Blockstore;
 use jsonrpc_v2::{Data, Error as JSONRPCError, Params, Server};
@@ -35,7 +37,7 @@ use crate::rpc::{
     beacon_api::beacon_get_entry,
     common_api::{shutdown, start_time, version},
     rpc_http_handler::rpc_http_handler,
-    rpc_ws_handler::rpc_ws_handler,
+    rpc_ws_handler::{rpc_ws_handler, RpcRouterState},
     state_api::*,
 };
 
@@ -44,6 +46,7 @@ pub type RpcResult<T> = Result<T, JSONRPCError>;
 pub async fn start_rpc<DB>(
     state: Arc<RPCState<DB>>,
     rpc_endpoint: TcpListener,
+    tls: Option<Arc<TlsServer>>,
     forest_version: &'static str,
     shutdown_send: Sender<()>,
 ) -> Result<(), JSONRPCError>
@@ -52,12 +55,16 @@ where
 {
     use auth_api::*;
     use chain_api::*;
+    use eth_api::*;
     use gas_api::*;
     use mpool_api::*;
     use sync_api::*;
     use wallet_api::*;
 
     let block_delay = state.state_manager.chain_config().block_delay_secs;
+    let head_changes = state.chain_store.publisher().clone();
+    let watch_events = state.watch_list.publisher().clone();
+    let actor_changes = state.state_manager.actor_change_publisher().clone();
     let rpc_server = Arc::new(
         Server::new()
             .with_data(Data(state))
@@ -69,12 +76,23 @@ where
             // Chain API
             .with_method(CHAIN_GET_MESSAGE, chain_api::chain_get_message::<DB>)
             .with_method(CHAIN_EXPORT, chain_api::chain_export::<DB>)
+            .with_method(CHAIN_EXPORT_PROGRESS, chain_api::chain_export_progress)
             .with_method(CHAIN_READ_OBJ, chain_read_obj::<DB>)
             .with_method(CHAIN_HAS_OBJ, chain_has_obj::<DB>)
             .with_method(CHAIN_GET_BLOCK_MESSAGES, chain_get_block_messages::<DB>)
             .with_method(CHAIN_GET_TIPSET_BY_HEIGHT, chain_get_tipset_by_height::<DB>)
             .with_method(CHAIN_GET_GENESIS, chain_get_genesis::<DB>)
             .with_method(CHAIN_GET_TIPSET, chain_get_tipset::<DB>)
+            .with_method(CHAIN_GET_PATH, chain_get_path::<DB>)
+            .with_method(
+                CHAIN_GET_MESSAGE_INCLUSION_PROOF,
+                chain_get_message_inclusion_proof::<DB>,
+            )
+            .with_method(
+                CHAIN_IMPORT_FINALITY_CERTIFICATE,
+                chain_import_finality_certificate::<DB>,
+            )
+            .with_method(CHAIN_VALIDATE, chain_validate::<DB>)
             .with_method(CHAIN_HEAD, chain_head::<DB>)
             .with_method(CHAIN_GET_BLOCK, chain_api::chain_get_block::<DB>)
             .with_method(CHAIN_GET_NAME, chain_api::chain_get_name::<DB>)
@@ -83,14 +101,29 @@ where
                 CHAIN_GET_MIN_BASE_FEE,
                 chain_api::chain_get_min_base_fee::<DB>,
             )
+            .with_method(CHAIN_GAS_HISTORY, chain_api::chain_gas_history::<DB>)
+            .with_method(CHAIN_PIN, chain_api::chain_pin::<DB>)
+            .with_method(CHAIN_UNPIN, chain_api::chain_unpin::<DB>)
+            .with_method(CHAIN_PINS, chain_api::chain_pins::<DB>)
             // Message Pool API
             .with_method(MPOOL_PENDING, mpool_pending::<DB>)
             .with_method(MPOOL_PUSH, mpool_push::<DB>)
+            .with_method(MPOOL_EXPORT, mpool_export::<DB>)
+            .with_method(MPOOL_IMPORT, mpool_import::<DB>)
+            .with_method(MPOOL_GET_NONCE, mpool_get_nonce::<DB>)
+            .with_method(MPOOL_RESERVE_NONCE, mpool_reserve_nonce::<DB>)
+            .with_method(MPOOL_RELEASE_NONCE, mpool_release_nonce::<DB>)
+            .with_method(MPOOL_PUSH_RESERVED, mpool_push_reserved::<DB>)
+            .with_method(MPOOL_RESUBMIT_STATUS, mpool_resubmit_status::<DB>)
+            .with_method(MPOOL_RESUBMIT_PAUSE, mpool_resubmit_pause::<DB>)
+            .with_method(MPOOL_RESUBMIT_RESUME, mpool_resubmit_resume::<DB>)
+            .with_method(MPOOL_RESUBMIT_LOG, mpool_resubmit_log::<DB>)
             .with_method(MPOOL_PUSH_MESSAGE, mpool_push_message::<DB>)
             // Sync API
             .with_method(SYNC_CHECK_BAD, sync_check_bad::<DB>)
             .with_method(SYNC_MARK_BAD, sync_mark_bad::<DB>)
             .with_method(SYNC_STATE, sync_state::<DB>)
+            .with_method(SYNC_SUBMIT_BLOCK, sync_submit_block::<DB>)
             // Wallet API
             .with_method(WALLET_BALANCE, wallet_balance::<DB>)
             .with_method(WALLET_DEFAULT_ADDRESS, wallet_default_address::<DB>)
@@ -101,6 +134,7 @@ where
             .with_method(WALLET_NEW, wallet_new::<DB>)
             .with_method(WALLET_SET_DEFAULT, wallet_set_default::<DB>)
             .with_method(WALLET_SIGN, wallet_sign::<DB>)
+            .with_method(WALLET_SIGN_MESSAGE, wallet_sign_message::<DB>)
             .with_method(WALLET_VERIFY, wallet_verify::<DB>)
             // State API
             .with_method(STATE_CALL, state_call::<DB>)
@@ -113,11 +147,36 @@ where
             .with_method(STATE_GET_RECEIPT, state_get_receipt::<DB>)
             .with_method(STATE_WAIT_MSG, state_wait_msg::<DB>)
             .with_method(STATE_FETCH_ROOT, state_fetch_root::<DB>)
+            .with_method(STATE_DIFF, state_diff::<DB>)
+            .with_method(STATE_MISMATCH_REPORTS, state_mismatch_reports::<DB>)
+            .with_method(STATE_MINER_REWARDS, state_miner_rewards::<DB>)
+            .with_method(STATE_ACTOR_EVENTS, state_actor_events::<DB>)
+            .with_method(STATE_LIST_NEW_ACTORS, state_list_new_actors::<DB>)
+            .with_method(
+                STATE_GET_DEAL_IDS_FOR_MESSAGE,
+                state_get_deal_ids_for_message::<DB>,
+            )
+            .with_method(WATCH_LIST_HISTORY, watch_list_history::<DB>)
+            .with_method(STATE_ACTOR_TRACE, state_actor_trace::<DB>)
+            .with_method(STATE_LIST_ACTORS, state_list_actors::<DB>)
+            .with_method(STATE_READ_STATE, state_read_state::<DB>)
             // Gas API
             .with_method(GAS_ESTIMATE_FEE_CAP, gas_estimate_fee_cap::<DB>)
             .with_method(GAS_ESTIMATE_GAS_LIMIT, gas_estimate_gas_limit::<DB>)
             .with_method(GAS_ESTIMATE_GAS_PREMIUM, gas_estimate_gas_premium::<DB>)
             .with_method(GAS_ESTIMATE_MESSAGE_GAS, gas_estimate_message_gas::<DB>)
+            // Eth API
+            .with_method(ETH_GET_CODE, eth_get_code::<DB>)
+            .with_method(ETH_GET_CODE_ALIAS, eth_get_code::<DB>)
+            .with_method(ETH_GET_STORAGE_AT, eth_get_storage_at::<DB>)
+            .with_method(ETH_GET_STORAGE_AT_ALIAS, eth_get_storage_at::<DB>)
+            .with_method(ETH_ACCOUNTS, eth_accounts::<DB>)
+            .with_method(ETH_ACCOUNTS_ALIAS, eth_accounts::<DB>)
+            .with_method(PERSONAL_SIGN, personal_sign::<DB>)
+            .with_method(ETH_SIGN_TYPED_DATA, eth_sign_typed_data::<DB>)
+            .with_method(ETH_SIGN_TYPED_DATA_V4, eth_sign_typed_data::<DB>)
+            .with_method(ETH_SEND_TRANSACTION, eth_send_transaction::<DB>)
+            .with_method(ETH_SEND_TRANSACTION_ALIAS, eth_send_transaction::<DB>)
             // Common API
             .with_method(VERSION, move || version(block_delay, forest_version))
             .with_method(SHUTDOWN, move || shutdown(shutdown_send.clone()))
@@ -128,23 +187,33 @@ where
             .with_method(NET_INFO, net_api::net_info::<DB>)
             .with_method(NET_CONNECT, net_api::net_connect::<DB>)
             .with_method(NET_DISCONNECT, net_api::net_disconnect::<DB>)
+            .with_method(NET_PEER_HEADS, net_api::net_peer_heads::<DB>)
+            .with_method(NET_AGENT_VERSIONS, net_api::net_agent_versions::<DB>)
+            .with_method(NET_PUBSUB_STATS, net_api::net_pubsub_stats::<DB>)
+            .with_method(NET_PEER_DETAILS, net_api::net_peer_details::<DB>)
+            .with_method(NET_PROTOCOL_STATS, net_api::net_protocol_stats::<DB>)
             // DB API
             .with_method(DB_GC, db_api::db_gc::<DB>)
             // Progress API
             .with_method(GET_PROGRESS, progress_api::get_progress)
             // Node API
             .with_method(NODE_STATUS, node_api::node_status::<DB>)
+            .with_method(GET_CONFIG, node_api::get_config::<DB>)
             .finish_unwrapped(),
     );
 
     let app = axum::Router::new()
         .route("/rpc/v0", get(rpc_ws_handler))
         .route("/rpc/v0", post(rpc_http_handler))
-        .with_state(rpc_server);
+        .with_state(RpcRouterState {
+            rpc_server,
+            head_changes,
+            watch_events,
+            actor_changes,
+        });
 
     info!("Ready for RPC connections");
-    let server = axum::Server::from_tcp(rpc_endpoint)?.serve(app.into_make_service());
-    server.await?;
+    serve(rpc_endpoint, tls.as_ref(), app).await?;
 
     info!("Stopped accepting RPC connections");
 
@@ -2,14 +2,21 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 #![allow(clippy::unused_async)]
 
-use std::convert::TryFrom;
+use std::time::Duration;
 
 use crate::blocks::TipsetKeys;
 use crate::json::cid::{vec::CidJsonVec, CidJson};
 use crate::lotus_json::LotusJson;
 use crate::message::SignedMessage;
-use crate::rpc_api::{data_types::RPCState, mpool_api::*};
-use crate::shim::address::Protocol;
+use crate::message_pool::DEFAULT_NONCE_LEASE;
+use crate::rpc_api::{
+    data_types::{MessageSendSpec, RPCState},
+    mpool_api::*,
+};
+use crate::shim::{
+    address::{Address, Protocol},
+    message::Message,
+};
 use ahash::{HashSet, HashSetExt};
 use fvm_ipld_blockstore::Blockstore;
 use jsonrpc_v2::{Data, Error as JsonRpcError, Params};
@@ -89,6 +96,42 @@ where
     Ok(CidJson(cid))
 }
 
+/// Return the messages in `mpool`, to be imported in another node with
+/// `Filecoin.MpoolImport`
+pub(in crate::rpc) async fn mpool_export<DB>(
+    data: Data<RPCState<DB>>,
+) -> Result<MpoolExportResult, JsonRpcError>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    let (pending, _) = data.mpool.pending()?;
+    Ok(pending.into())
+}
+
+/// Add exported `SignedMessage`s to `mpool`, in the order of their nonces.
+/// Messages are checked and published as if pushed one by one.
+pub(in crate::rpc) async fn mpool_import<DB>(
+    data: Data<RPCState<DB>>,
+    Params((LotusJson(mut messages),)): Params<MpoolImportParams>,
+) -> Result<MpoolImportResult, JsonRpcError>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    messages.sort_by_key(|msg| (msg.message.from, msg.message.sequence));
+    let mut summary = MpoolImportSummary::default();
+    for msg in messages {
+        let cid = msg.cid()?;
+        match data.mpool.as_ref().push(msg).await {
+            Ok(_) => summary.imported.push(cid),
+            Err(e) => summary.rejected.push(MpoolImportRejection {
+                cid,
+                error: e.to_string(),
+            }),
+        }
+    }
+    Ok(summary)
+}
+
 /// Sign given `UnsignedMessage` and add it to `mpool`, return `SignedMessage`
 pub(in crate::rpc) async fn mpool_push_message<DB>(
     data: Data<RPCState<DB>>,
@@ -98,10 +141,21 @@ where
     DB: Blockstore + Send + Sync + 'static,
 {
     let (LotusJson(umsg), spec) = params;
+    Ok(sign_and_push(&data, umsg, spec).await?.into())
+}
 
+/// Estimates the gas of `umsg`, sets its nonce, signs it with the key of its
+/// sender and adds it to `mpool`.
+pub(in crate::rpc) async fn sign_and_push<DB>(
+    data: &Data<RPCState<DB>>,
+    umsg: Message,
+    spec: Option<MessageSendSpec>,
+) -> Result<SignedMessage, JsonRpcError>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
     let from = umsg.from;
 
-    let mut keystore = data.keystore.as_ref().write().await;
     let heaviest_tipset = data.state_manager.chain_store().heaviest_tipset();
     let key_addr = data
         .state_manager
@@ -113,7 +167,7 @@ where
             "Expected nonce for MpoolPushMessage is 0, and will be calculated for you.".into(),
         );
     }
-    let mut umsg = estimate_message_gas::<DB>(&data, umsg, spec, Default::default()).await?;
+    let mut umsg = estimate_message_gas::<DB>(data, umsg, spec, Default::default()).await?;
     if umsg.gas_premium > umsg.gas_fee_cap {
         return Err("After estimation, gas premium is greater than gas fee cap".into());
     }
@@ -121,21 +175,180 @@ where
     if from.protocol() == Protocol::ID {
         umsg.from = key_addr;
     }
-    let nonce = data.mpool.get_sequence(&from)?;
-    umsg.sequence = nonce;
-    let key = crate::key_management::Key::try_from(crate::key_management::try_find(
-        &key_addr,
-        &mut keystore,
-    )?)?;
-    let sig = crate::key_management::sign(
-        *key.key_info.key_type(),
-        key.key_info.private_key(),
-        umsg.cid().unwrap().to_bytes().as_slice(),
-    )?;
-
-    let smsg = SignedMessage::new_from_parts(umsg, sig)?;
-
-    data.mpool.as_ref().push(smsg.clone()).await?;
-
-    Ok(smsg.into())
+    // The keystore isn't locked while a remote signer answers, the nonce is
+    // reserved instead so that concurrent pushes don't share it.
+    let signer = data
+        .remote_signers
+        .find(&key_addr, &mut *data.keystore.write().await)?;
+    // Pending messages are keyed by the address they are sent from.
+    let reservation = data.mpool.reserve_nonce(&umsg.from, DEFAULT_NONCE_LEASE)?;
+    umsg.sequence = reservation.nonce;
+    let eth_chain_id = data.state_manager.chain_config().eth_chain_id;
+    let signing_bytes =
+        SignedMessage::message_signing_bytes(&umsg, signer.sig_type(), eth_chain_id)?;
+    let sig = match signer.sign(&signing_bytes).await {
+        Ok(sig) => sig,
+        Err(e) => {
+            data.mpool
+                .release_nonce(&umsg.from, reservation.nonce, reservation.lease);
+            return Err(e.into());
+        }
+    };
+
+    let smsg = SignedMessage::new_unchecked(umsg, sig);
+    smsg.verify(eth_chain_id)?;
+
+    if let Err(e) = data
+        .mpool
+        .push_reserved(smsg.clone(), Some(reservation.lease))
+        .await
+    {
+        data.mpool
+            .release_nonce(&smsg.message().from, reservation.nonce, reservation.lease);
+        return Err(e.into());
+    }
+
+    Ok(smsg)
+}
+
+/// Returns the address the pending messages of `address` are keyed by, i.e.
+/// its key address if it is an ID address.
+async fn pending_address<DB>(
+    data: &Data<RPCState<DB>>,
+    address: Address,
+) -> Result<Address, JsonRpcError>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    if address.protocol() != Protocol::ID {
+        return Ok(address);
+    }
+    let heaviest_tipset = data.state_manager.chain_store().heaviest_tipset();
+    Ok(data
+        .state_manager
+        .resolve_to_key_addr(&address, &heaviest_tipset)
+        .await?)
+}
+
+/// Return the next nonce of an address, skipping the pending and reserved ones
+pub(in crate::rpc) async fn mpool_get_nonce<DB>(
+    data: Data<RPCState<DB>>,
+    Params(params): Params<MpoolGetNonceParams>,
+) -> Result<MpoolGetNonceResult, JsonRpcError>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    let (LotusJson(address),) = params;
+    let address = pending_address(&data, address).await?;
+    Ok(data.mpool.next_nonce(&address)?)
+}
+
+/// Reserve the next nonce of an address, for clients signing messages from a
+/// shared address
+pub(in crate::rpc) async fn mpool_reserve_nonce<DB>(
+    data: Data<RPCState<DB>>,
+    Params(params): Params<MpoolReserveNonceParams>,
+) -> Result<MpoolReserveNonceResult, JsonRpcError>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    let (LotusJson(address), duration) = params;
+    let address = pending_address(&data, address).await?;
+    let duration = duration.map_or(DEFAULT_NONCE_LEASE, Duration::from_secs);
+    let reservation = data.mpool.reserve_nonce(&address, duration)?;
+    Ok(MpoolNonceReservation {
+        address: reservation.address,
+        nonce: reservation.nonce,
+        lease: reservation.lease,
+        expiration: reservation.expiration.into(),
+    })
+}
+
+/// Release a nonce reservation that won't be used
+pub(in crate::rpc) async fn mpool_release_nonce<DB>(
+    data: Data<RPCState<DB>>,
+    Params(params): Params<MpoolReleaseNonceParams>,
+) -> Result<MpoolReleaseNonceResult, JsonRpcError>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    let (LotusJson(address), nonce, lease) = params;
+    let address = pending_address(&data, address).await?;
+    if !data.mpool.release_nonce(&address, nonce, lease) {
+        return Err(format!("No reservation {lease} of nonce {nonce} of {address}").into());
+    }
+    Ok(())
+}
+
+/// Add a signed message using a reserved nonce to `mpool`, redeeming the
+/// reservation
+pub(in crate::rpc) async fn mpool_push_reserved<DB>(
+    data: Data<RPCState<DB>>,
+    Params((LotusJson(signed_message), lease)): Params<MpoolPushReservedParams>,
+) -> Result<MpoolPushReservedResult, JsonRpcError>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    let cid = data
+        .mpool
+        .push_reserved(signed_message, Some(lease))
+        .await?;
+    Ok(CidJson(cid))
+}
+
+/// Return the status of the automatic resubmission of the underpriced local
+/// messages
+pub(in crate::rpc) async fn mpool_resubmit_status<DB>(
+    data: Data<RPCState<DB>>,
+) -> Result<MpoolResubmitStatusResult, JsonRpcError>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    Ok(data
+        .auto_resubmit
+        .status(data.chain_store.settings().as_ref())?)
+}
+
+/// Pause the automatic resubmission of the underpriced local messages
+pub(in crate::rpc) async fn mpool_resubmit_pause<DB>(
+    data: Data<RPCState<DB>>,
+) -> Result<MpoolResubmitPauseResult, JsonRpcError>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    set_resubmit_paused(&data, true)
+}
+
+/// Resume the automatic resubmission of the underpriced local messages
+pub(in crate::rpc) async fn mpool_resubmit_resume<DB>(
+    data: Data<RPCState<DB>>,
+) -> Result<MpoolResubmitResumeResult, JsonRpcError>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    set_resubmit_paused(&data, false)
+}
+
+fn set_resubmit_paused<DB>(data: &Data<RPCState<DB>>, paused: bool) -> Result<bool, JsonRpcError>
+where
+    DB: Blockstore,
+{
+    let epoch = data.chain_store.heaviest_tipset().epoch();
+    Ok(data
+        .auto_resubmit
+        .set_paused(data.chain_store.settings().as_ref(), paused, epoch)?)
+}
+
+/// Return the most recent records of the audit log of the automatic
+/// resubmission
+pub(in crate::rpc) async fn mpool_resubmit_log<DB>(
+    data: Data<RPCState<DB>>,
+    Params((limit,)): Params<MpoolResubmitLogParams>,
+) -> Result<MpoolResubmitLogResult, JsonRpcError>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    Ok(data
+        .auto_resubmit
+        .log(data.chain_store.settings().as_ref(), limit)?)
 }
@@ -2,8 +2,13 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 
-use crate::libp2p::{NetRPCMethods, NetworkMessage, PeerId};
+use crate::chain::index::ResolveNullTipset;
+use crate::libp2p::{
+    agent_policy::{version_distribution, AgentVersion},
+    estimate_network_head, NetRPCMethods, NetworkMessage, PeerId,
+};
 use crate::rpc_api::{
     data_types::{AddrInfo, RPCState},
     net_api::*,
@@ -106,3 +111,125 @@ pub(in crate::rpc) async fn net_disconnect<DB: Blockstore>(
 
     Ok(())
 }
+
+pub(in crate::rpc) async fn net_pubsub_stats<DB: Blockstore>(
+    data: Data<RPCState<DB>>,
+) -> Result<NetPubsubStatsResult, JsonRpcError> {
+    let (tx, rx) = oneshot::channel();
+    let req = NetworkMessage::JSONRPCRequest {
+        method: NetRPCMethods::PubsubStats(tx),
+    };
+
+    data.network_send.send_async(req).await?;
+    Ok(rx.await?)
+}
+
+/// Peer heads older than this are not taken into account for the network head
+/// estimate.
+const PEER_HEAD_MAX_AGE: Duration = Duration::from_secs(5 * 60);
+
+pub(in crate::rpc) async fn net_peer_heads<DB: Blockstore>(
+    data: Data<RPCState<DB>>,
+) -> Result<NetPeerHeadsResult, JsonRpcError> {
+    let heads = data.peer_manager.peer_heads().await;
+    let network_head = estimate_network_head(
+        heads.iter().map(|(_, head)| head),
+        SystemTime::now(),
+        PEER_HEAD_MAX_AGE,
+    );
+
+    // The local node is on a minority fork if most peers agree on a head that
+    // the local chain has moved past without including it.
+    let local_head = data.chain_store.heaviest_tipset();
+    let minority_fork = match &network_head {
+        Some(head) if head.is_majority() && head.epoch <= local_head.epoch() => {
+            let local = data.chain_store.chain_index.tipset_by_height(
+                head.epoch,
+                local_head,
+                ResolveNullTipset::TakeOlder,
+            )?;
+            local.key() != &head.tipset_keys
+        }
+        _ => false,
+    };
+
+    Ok(NetPeerHeadsResult {
+        peers: heads
+            .into_iter()
+            .map(|(peer, head)| PeerHeadInfo {
+                peer: peer.to_string(),
+                tipset_keys: head.tipset_keys,
+                epoch: head.epoch,
+                weight: head.weight,
+                updated: head.updated.into(),
+            })
+            .collect(),
+        network_head: network_head.map(|head| NetworkHeadInfo {
+            tipset_keys: head.tipset_keys,
+            epoch: head.epoch,
+            weight: head.weight,
+            peers: head.peers,
+            total_peers: head.total_peers,
+        }),
+        minority_fork,
+    })
+}
+
+pub(in crate::rpc) async fn net_agent_versions<DB: Blockstore>(
+    data: Data<RPCState<DB>>,
+) -> Result<NetAgentVersionsResult, JsonRpcError> {
+    let agents = data.peer_manager.peer_agents().await;
+    let deprioritized: Vec<_> = agents
+        .iter()
+        .filter(|(_, deprioritized)| *deprioritized)
+        .map(|(agent, _)| AgentVersion::parse(agent))
+        .collect();
+    Ok(
+        version_distribution(agents.iter().map(|(agent, _)| agent.as_str()))
+            .into_iter()
+            .map(|(agent, peers)| AgentVersionCount {
+                deprioritized: deprioritized.iter().filter(|d| **d == agent).count(),
+                client: agent.client,
+                version: agent.version.map(|version| version.to_string()),
+                peers,
+            })
+            .collect(),
+    )
+}
+
+pub(in crate::rpc) async fn net_peer_details<DB: Blockstore>(
+    data: Data<RPCState<DB>>,
+) -> Result<NetPeerDetailsResult, JsonRpcError> {
+    Ok(data
+        .peer_manager
+        .peer_details()
+        .await
+        .into_iter()
+        .map(|details| PeerDetailsInfo {
+            peer: details.peer.to_string(),
+            address: details.connection.address.to_string(),
+            direction: match details.connection.inbound {
+                true => "inbound",
+                false => "outbound",
+            }
+            .into(),
+            transport: details.connection.transport().into(),
+            protocols: details.connection.protocols,
+            agent: details.agent,
+            deprioritized: details.deprioritized,
+            score: details.score,
+            latency_ms: details
+                .connection
+                .latency
+                .map(|latency| latency.as_millis() as u64),
+            gossip_bytes_received: details.connection.gossip_bytes_received,
+            connected_since: details.connection.connected_since.into(),
+        })
+        .collect())
+}
+
+pub(in crate::rpc) async fn net_protocol_stats<DB: Blockstore>(
+    data: Data<RPCState<DB>>,
+) -> Result<NetProtocolStatsResult, JsonRpcError> {
+    Ok(data.peer_manager.protocol_stats().await)
+}
@@ -1,7 +1,20 @@
 // Copyright 2019-2023 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-use crate::rpc_api::{auth_api::*, check_access, data_types::JsonRpcServerState, ACCESS_MAP};
+use std::sync::Arc;
+
+use crate::blocks::Tipset;
+use crate::chain::{MissingData, MissingReason};
+use crate::rpc_api::{
+    auth_api::*,
+    chain_api::CHAIN_NOTIFY,
+    check_access,
+    data_types::{JsonRpcServerState, RPCState},
+    error_codes,
+    tipset_selector::TipsetSelector,
+    ACCESS_MAP,
+};
+use fvm_ipld_blockstore::Blockstore;
 use http::{HeaderMap, HeaderValue, StatusCode};
 use serde::de::DeserializeOwned;
 use tracing::{debug, error};
@@ -18,6 +31,43 @@ pub fn get_error_obj(code: i64, message: String) -> jsonrpc_v2::Error {
     }
 }
 
+/// Surfaces missing chain data with a dedicated error code, so that clients
+/// can tell pruned data apart from data that never existed.
+pub fn missing_data_error(missing: MissingData) -> jsonrpc_v2::Error {
+    let code = match missing.reason {
+        MissingReason::Unknown => error_codes::NOT_FOUND,
+        MissingReason::Pruned => error_codes::PRUNED,
+        MissingReason::NotYetSynced => error_codes::NOT_YET_SYNCED,
+        MissingReason::NotComputed => error_codes::NOT_COMPUTED,
+    };
+    get_error_obj(code, missing.to_string())
+}
+
+pub fn chain_error(e: crate::chain::Error) -> jsonrpc_v2::Error {
+    match e {
+        crate::chain::Error::Missing(missing) => missing_data_error(missing),
+        other => other.into(),
+    }
+}
+
+pub fn state_error(e: crate::state_manager::Error) -> jsonrpc_v2::Error {
+    match e {
+        crate::state_manager::Error::Missing(missing) => missing_data_error(missing),
+        other => other.into(),
+    }
+}
+
+/// Loads the tipset selected by an RPC parameter.
+pub fn load_tipset<DB: Blockstore>(
+    data: &RPCState<DB>,
+    selector: &TipsetSelector,
+) -> Result<Arc<Tipset>, jsonrpc_v2::Error> {
+    let chain_finality = data.state_manager.chain_config().policy.chain_finality;
+    selector
+        .resolve(&data.chain_store, chain_finality)
+        .map_err(chain_error)
+}
+
 pub fn get_error_res(code: i64, message: String) -> jsonrpc_v2::ResponseObject {
     jsonrpc_v2::ResponseObject::Error {
         jsonrpc: jsonrpc_v2::V2,
@@ -33,7 +83,7 @@ pub fn get_error_str(code: i64, message: String) -> String {
     }
 }
 
-const STREAMING_METHODS: [&str; 0] = [];
+const STREAMING_METHODS: [&str; 1] = [CHAIN_NOTIFY];
 
 pub fn is_streaming_method(method_name: &str) -> bool {
     STREAMING_METHODS.contains(&method_name)
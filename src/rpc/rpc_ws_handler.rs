@@ -2,22 +2,62 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 use std::sync::Arc;
+use std::time::Duration;
 
-use crate::rpc_api::data_types::JsonRpcServerState;
+use crate::blocks::Tipset;
+use crate::chain::{
+    export_progress::RPC_EXPORT_PROGRESS, headchange_json::HeadChangeJson, HeadChange,
+};
+use crate::lotus_json::LotusJson;
+use crate::rpc_api::{
+    chain_api::{
+        ChainNotifyResult, ChainNotifyValue, CHAIN_EXPORT_PROGRESS_NOTIFY, CHAIN_HEAD,
+        CHAIN_NOTIFY, CHANNEL_CLOSE, CHANNEL_VALUE,
+    },
+    data_types::JsonRpcServerState,
+    state_api::{STATE_ACTOR_CHANGES_NOTIFY, WATCH_LIST_NOTIFY},
+};
+use crate::state_manager::{actor_changes::ActorChange, watch_list::WatchEvent};
 use axum::{
     extract::{
         ws::{Message, WebSocket},
-        WebSocketUpgrade,
+        FromRef, WebSocketUpgrade,
     },
     response::IntoResponse,
 };
 use crossbeam::atomic::AtomicCell;
 use futures::{stream::SplitSink, SinkExt, StreamExt};
 use http::{HeaderMap, HeaderValue};
-use tokio::sync::RwLock;
+use tokio::sync::{
+    broadcast::{error::RecvError, Sender as Publisher},
+    RwLock,
+};
 use tracing::{debug, error, info, warn};
 
-use crate::rpc::rpc_util::{call_rpc_str, check_permissions, get_auth_header, get_error_str};
+use crate::rpc::rpc_util::{
+    call_rpc, call_rpc_str, check_permissions, get_auth_header, get_error_str,
+};
+
+/// Interval between two checks of the export progress for the subscribers of
+/// [`CHAIN_EXPORT_PROGRESS_NOTIFY`].
+const EXPORT_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// State of the RPC router. Besides the RPC methods, websockets serve the
+/// subscriptions to the head changes of the chain, to the changes of the
+/// watch-list and to the actors created and deleted.
+#[derive(Clone)]
+pub struct RpcRouterState {
+    pub rpc_server: JsonRpcServerState,
+    pub head_changes: Publisher<HeadChange>,
+    pub watch_events: Publisher<WatchEvent>,
+    pub actor_changes: Publisher<ActorChange>,
+}
+
+impl FromRef<RpcRouterState> for JsonRpcServerState {
+    fn from_ref(state: &RpcRouterState) -> Self {
+        state.rpc_server.clone()
+    }
+}
 
 async fn rpc_ws_task(
     authorization_header: Option<HeaderValue>,
@@ -44,26 +84,263 @@ async fn rpc_ws_task(
     Ok(())
 }
 
+/// What to push to a [`CHAIN_NOTIFY`] subscriber after an event of the head
+/// change channel.
+#[derive(Debug, PartialEq)]
+enum Notification {
+    Apply(Arc<Tipset>),
+    /// The subscriber fell behind and missed some changes. It is sent the
+    /// current head instead.
+    Resync,
+    Close,
+}
+
+impl From<Result<HeadChange, RecvError>> for Notification {
+    fn from(event: Result<HeadChange, RecvError>) -> Self {
+        match event {
+            Ok(HeadChange::Apply(tipset)) => Notification::Apply(tipset),
+            Err(RecvError::Lagged(missed)) => {
+                warn!("ChainNotify subscriber missed {missed} head changes, resynchronizing");
+                Notification::Resync
+            }
+            Err(RecvError::Closed) => Notification::Close,
+        }
+    }
+}
+
+fn channel_value(
+    channel_id: ChainNotifyResult,
+    value: &impl serde::Serialize,
+) -> anyhow::Result<String> {
+    Ok(serde_json::to_string(&serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": CHANNEL_VALUE,
+        "params": [channel_id, value],
+    }))?)
+}
+
+async fn current_head(rpc_server: JsonRpcServerState) -> anyhow::Result<ChainNotifyValue> {
+    let (_, head) = call_rpc::<LotusJson<Tipset>>(
+        rpc_server,
+        jsonrpc_v2::RequestObject::request()
+            .with_method(CHAIN_HEAD)
+            .finish(),
+    )
+    .await?;
+    Ok(vec![HeadChangeJson::Current(head)])
+}
+
+async fn chain_notify_task(
+    authorization_header: Option<HeaderValue>,
+    rpc_call: jsonrpc_v2::RequestObject,
+    rpc_server: JsonRpcServerState,
+    head_changes: Publisher<HeadChange>,
+    channel_id: ChainNotifyResult,
+    is_socket_active: Arc<AtomicCell<bool>>,
+    ws_sender: Arc<RwLock<SplitSink<WebSocket, Message>>>,
+) -> anyhow::Result<()> {
+    check_permissions(rpc_server.clone(), CHAIN_NOTIFY, authorization_header)
+        .await
+        .map_err(|(_, e)| anyhow::Error::msg(e))?;
+
+    // Subscribing before fetching the current head ensures that no change is
+    // missed in-between.
+    let mut receiver = head_changes.subscribe();
+    let response = serde_json::json!({
+        "jsonrpc": "2.0",
+        "result": channel_id,
+        "id": rpc_call.id_ref(),
+    });
+    let send = |text: String| {
+        let ws_sender = ws_sender.clone();
+        async move { ws_sender.write().await.send(Message::Text(text)).await }
+    };
+    send(response.to_string()).await?;
+    send(channel_value(
+        channel_id,
+        &current_head(rpc_server.clone()).await?,
+    )?)
+    .await?;
+
+    loop {
+        let value = match Notification::from(receiver.recv().await) {
+            Notification::Apply(tipset) => vec![HeadChangeJson::from(HeadChange::Apply(tipset))],
+            Notification::Resync => current_head(rpc_server.clone()).await?,
+            Notification::Close => break,
+        };
+        if !is_socket_active.load() {
+            return Ok(());
+        }
+        // Sending waits for slow clients. The head change channel is bounded,
+        // so clients that fall too far behind are resynchronized instead of
+        // buffering changes without limit.
+        send(channel_value(channel_id, &value)?).await?;
+    }
+    let close = serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": CHANNEL_CLOSE,
+        "params": [channel_id],
+    });
+    send(close.to_string()).await?;
+    Ok(())
+}
+
+/// Pushes the events of `publisher` to a subscriber of `method`, e.g.
+/// [`WATCH_LIST_NOTIFY`]. Subscribers that fall behind miss events.
+#[allow(clippy::too_many_arguments)]
+async fn broadcast_notify_task<T: Clone + serde::Serialize>(
+    method: &str,
+    authorization_header: Option<HeaderValue>,
+    rpc_call: jsonrpc_v2::RequestObject,
+    rpc_server: JsonRpcServerState,
+    publisher: Publisher<T>,
+    channel_id: u64,
+    is_socket_active: Arc<AtomicCell<bool>>,
+    ws_sender: Arc<RwLock<SplitSink<WebSocket, Message>>>,
+) -> anyhow::Result<()> {
+    check_permissions(rpc_server, method, authorization_header)
+        .await
+        .map_err(|(_, e)| anyhow::Error::msg(e))?;
+
+    let mut receiver = publisher.subscribe();
+    let response = serde_json::json!({
+        "jsonrpc": "2.0",
+        "result": channel_id,
+        "id": rpc_call.id_ref(),
+    });
+    let send = |text: String| {
+        let ws_sender = ws_sender.clone();
+        async move { ws_sender.write().await.send(Message::Text(text)).await }
+    };
+    send(response.to_string()).await?;
+
+    loop {
+        let event = match receiver.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(missed)) => {
+                warn!("{method} subscriber missed {missed} events");
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+        if !is_socket_active.load() {
+            return Ok(());
+        }
+        send(channel_value(channel_id, &event)?).await?;
+    }
+    let close = serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": CHANNEL_CLOSE,
+        "params": [channel_id],
+    });
+    send(close.to_string()).await?;
+    Ok(())
+}
+
+async fn export_progress_task(
+    authorization_header: Option<HeaderValue>,
+    rpc_call: jsonrpc_v2::RequestObject,
+    rpc_server: JsonRpcServerState,
+    channel_id: u64,
+    is_socket_active: Arc<AtomicCell<bool>>,
+    ws_sender: Arc<RwLock<SplitSink<WebSocket, Message>>>,
+) -> anyhow::Result<()> {
+    check_permissions(
+        rpc_server,
+        CHAIN_EXPORT_PROGRESS_NOTIFY,
+        authorization_header,
+    )
+    .await
+    .map_err(|(_, e)| anyhow::Error::msg(e))?;
+
+    let response = serde_json::json!({
+        "jsonrpc": "2.0",
+        "result": channel_id,
+        "id": rpc_call.id_ref(),
+    });
+    let send = |text: String| {
+        let ws_sender = ws_sender.clone();
+        async move { ws_sender.write().await.send(Message::Text(text)).await }
+    };
+    send(response.to_string()).await?;
+
+    // The progress of a running export changes at every check, the one of a
+    // finished export is sent once.
+    let mut interval = tokio::time::interval(EXPORT_PROGRESS_INTERVAL);
+    let mut sent = None;
+    loop {
+        interval.tick().await;
+        if !is_socket_active.load() {
+            return Ok(());
+        }
+        let progress = RPC_EXPORT_PROGRESS.progress();
+        if sent.as_ref() != Some(&progress) {
+            send(channel_value(channel_id, &progress)?).await?;
+            sent = Some(progress);
+        }
+    }
+}
+
+/// Runs [`broadcast_notify_task`], sending its error to the subscriber.
+#[allow(clippy::too_many_arguments)]
+async fn broadcast_notify<T: Clone + serde::Serialize>(
+    method: &'static str,
+    publisher: Publisher<T>,
+    authorization_header: Option<HeaderValue>,
+    rpc_call: jsonrpc_v2::RequestObject,
+    rpc_server: JsonRpcServerState,
+    channel_id: u64,
+    is_socket_active: Arc<AtomicCell<bool>>,
+    ws_sender: Arc<RwLock<SplitSink<WebSocket, Message>>>,
+) {
+    if let Err(e) = broadcast_notify_task(
+        method,
+        authorization_header,
+        rpc_call,
+        rpc_server,
+        publisher,
+        channel_id,
+        is_socket_active,
+        ws_sender.clone(),
+    )
+    .await
+    {
+        let msg = format!("WS {method} subscription error: {e}");
+        debug!("{}", msg);
+        let _ = ws_sender
+            .write()
+            .await
+            .send(Message::Text(get_error_str(3, msg)))
+            .await;
+    }
+}
+
 pub async fn rpc_ws_handler(
     headers: HeaderMap,
-    axum::extract::State(rpc_server): axum::extract::State<JsonRpcServerState>,
+    axum::extract::State(state): axum::extract::State<RpcRouterState>,
     ws: WebSocketUpgrade,
 ) -> impl IntoResponse {
     let authorization_header = get_auth_header(headers);
     ws.on_upgrade(move |socket| async {
-        rpc_ws_handler_inner(socket, authorization_header, rpc_server).await
+        rpc_ws_handler_inner(socket, authorization_header, state).await
     })
 }
 
 async fn rpc_ws_handler_inner(
     socket: WebSocket,
     authorization_header: Option<HeaderValue>,
-    rpc_server: JsonRpcServerState,
+    RpcRouterState {
+        rpc_server,
+        head_changes,
+        watch_events,
+        actor_changes,
+    }: RpcRouterState,
 ) {
     info!("Accepted WS connection!");
     let (sender, mut receiver) = socket.split();
     let ws_sender = Arc::new(RwLock::new(sender));
     let socket_active = Arc::new(AtomicCell::new(true));
+    let mut channel_id = 0;
     while let Some(Ok(message)) = receiver.next().await {
         debug!("Received new WS RPC message: {:?}", message);
         if let Message::Text(request_text) = message {
@@ -77,6 +354,81 @@ async fn rpc_ws_handler_inner(
                 match serde_json::from_str(&request_text)
                     as Result<jsonrpc_v2::RequestObject, serde_json::Error>
                 {
+                    Ok(rpc_call) if rpc_call.method_ref() == CHAIN_NOTIFY => {
+                        channel_id += 1;
+                        let head_changes = head_changes.clone();
+                        tokio::task::spawn(async move {
+                            if let Err(e) = chain_notify_task(
+                                authorization_header,
+                                rpc_call,
+                                task_rpc_server,
+                                head_changes,
+                                channel_id,
+                                task_socket_active,
+                                task_ws_sender.clone(),
+                            )
+                            .await
+                            {
+                                let msg = format!("WS ChainNotify subscription error: {e}");
+                                debug!("{}", msg);
+                                let _ = task_ws_sender
+                                    .write()
+                                    .await
+                                    .send(Message::Text(get_error_str(3, msg)))
+                                    .await;
+                            }
+                        });
+                    }
+                    Ok(rpc_call) if rpc_call.method_ref() == WATCH_LIST_NOTIFY => {
+                        channel_id += 1;
+                        tokio::task::spawn(broadcast_notify(
+                            WATCH_LIST_NOTIFY,
+                            watch_events.clone(),
+                            authorization_header,
+                            rpc_call,
+                            task_rpc_server,
+                            channel_id,
+                            task_socket_active,
+                            task_ws_sender,
+                        ));
+                    }
+                    Ok(rpc_call) if rpc_call.method_ref() == STATE_ACTOR_CHANGES_NOTIFY => {
+                        channel_id += 1;
+                        tokio::task::spawn(broadcast_notify(
+                            STATE_ACTOR_CHANGES_NOTIFY,
+                            actor_changes.clone(),
+                            authorization_header,
+                            rpc_call,
+                            task_rpc_server,
+                            channel_id,
+                            task_socket_active,
+                            task_ws_sender,
+                        ));
+                    }
+                    Ok(rpc_call) if rpc_call.method_ref() == CHAIN_EXPORT_PROGRESS_NOTIFY => {
+                        channel_id += 1;
+                        tokio::task::spawn(async move {
+                            if let Err(e) = export_progress_task(
+                                authorization_header,
+                                rpc_call,
+                                task_rpc_server,
+                                channel_id,
+                                task_socket_active,
+                                task_ws_sender.clone(),
+                            )
+                            .await
+                            {
+                                let msg =
+                                    format!("WS ChainExportProgressNotify subscription error: {e}");
+                                debug!("{}", msg);
+                                let _ = task_ws_sender
+                                    .write()
+                                    .await
+                                    .send(Message::Text(get_error_str(3, msg)))
+                                    .await;
+                            }
+                        });
+                    }
                     Ok(rpc_call) => {
                         tokio::task::spawn(async move {
                             match rpc_ws_task(
@@ -122,3 +474,64 @@ async fn rpc_ws_handler_inner(
     }
     socket_active.store(false);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::BlockHeader;
+    use crate::shim::address::Address;
+    use tokio::sync::broadcast;
+
+    #[tokio::test]
+    async fn lagging_subscribers_are_resynchronized() {
+        let tipset = Arc::new(Tipset::from(
+            BlockHeader::builder()
+                .miner_address(Address::new_id(0))
+                .build()
+                .unwrap(),
+        ));
+        let (publisher, mut receiver) = broadcast::channel(2);
+        publisher.send(HeadChange::Apply(tipset.clone())).unwrap();
+        assert_eq!(
+            Notification::from(receiver.recv().await),
+            Notification::Apply(tipset.clone())
+        );
+
+        // Changes dropped by the bounded channel are replaced by a resync.
+        for _ in 0..3 {
+            publisher.send(HeadChange::Apply(tipset.clone())).unwrap();
+        }
+        assert_eq!(
+            Notification::from(receiver.recv().await),
+            Notification::Resync
+        );
+        assert_eq!(
+            Notification::from(receiver.recv().await),
+            Notification::Apply(tipset.clone())
+        );
+
+        drop(publisher);
+        receiver.recv().await.unwrap();
+        assert_eq!(
+            Notification::from(receiver.recv().await),
+            Notification::Close
+        );
+    }
+
+    #[test]
+    fn channel_value_format() {
+        let tipset = Tipset::from(
+            BlockHeader::builder()
+                .miner_address(Address::new_id(0))
+                .build()
+                .unwrap(),
+        );
+        let value = serde_json::from_str::<serde_json::Value>(
+            &channel_value(7, &vec![HeadChangeJson::Current(tipset.into())]).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(value["method"], CHANNEL_VALUE);
+        assert_eq!(value["params"][0], 7);
+        assert_eq!(value["params"][1][0]["type"], "current");
+    }
+}
@@ -3,17 +3,22 @@
 #![allow(clippy::unused_async)]
 
 use crate::ipld::json::IpldJson;
+use crate::ipld::render::{render_ipld, RenderOptions};
 use crate::ipld::CidHashSet;
 use crate::json::address::json::AddressJson;
 use crate::json::cid::CidJson;
 use crate::libp2p::NetworkMessage;
 use crate::lotus_json::LotusJson;
+use crate::rpc::rpc_util::{load_tipset, state_error};
 use crate::rpc_api::{
-    data_types::{MarketDeal, MessageLookup, RPCState},
+    data_types::{ActorReadState, MarketDeal, MessageLookup, RPCState},
     state_api::*,
 };
-use crate::shim::address::Address;
-use crate::state_manager::InvocResult;
+use crate::shim::{address::Address, econ::TokenAmount, state_tree::StateTree};
+use crate::state_manager::{
+    actor_changes::ActorChangeKind, execution_trace::ExecutionTrace, InvocResult,
+};
+use crate::statediff::decode_actor_state;
 use ahash::{HashMap, HashMapExt};
 use anyhow::Context;
 use cid::Cid;
@@ -37,9 +42,9 @@ pub(in crate::rpc) async fn state_call<DB: Blockstore + Send + Sync + 'static>(
     Params(params): Params<StateCallParams>,
 ) -> Result<StateCallResult, JsonRpcError> {
     let state_manager = &data.state_manager;
-    let (message_json, LotusJson(key)) = params;
+    let (message_json, LotusJson(selector)) = params;
     let mut message = message_json.into_inner();
-    let tipset = data.state_manager.chain_store().tipset_from_keys(&key)?;
+    let tipset = load_tipset(&data, &selector)?;
     Ok(state_manager.call(&mut message, Some(tipset))?)
 }
 
@@ -50,15 +55,16 @@ pub(in crate::rpc) async fn state_replay<DB: Blockstore + Send + Sync + 'static>
     Params(params): Params<StateReplayParams>,
 ) -> Result<StateReplayResult, JsonRpcError> {
     let state_manager = &data.state_manager;
-    let (cidjson, LotusJson(key)) = params;
+    let (cidjson, LotusJson(selector)) = params;
     let cid = cidjson.into();
-    let tipset = data.state_manager.chain_store().tipset_from_keys(&key)?;
+    let tipset = load_tipset(&data, &selector)?;
     let (msg, ret) = state_manager.replay(&tipset, cid).await?;
 
     Ok(InvocResult {
         msg,
         msg_rct: Some(ret.msg_receipt()),
         error: ret.failure_info(),
+        execution_trace: ExecutionTrace::from_events(&ret.exec_trace())?,
     })
 }
 
@@ -78,8 +84,8 @@ pub(in crate::rpc) async fn state_get_network_version<DB: Blockstore>(
     data: Data<RPCState<DB>>,
     Params(params): Params<StateNetworkVersionParams>,
 ) -> Result<StateNetworkVersionResult, JsonRpcError> {
-    let (LotusJson(tsk),) = params;
-    let ts = data.chain_store.tipset_from_keys(&tsk)?;
+    let (LotusJson(selector),) = params;
+    let ts = load_tipset(&data, &selector)?;
     Ok(data.state_manager.get_network_version(ts.epoch()))
 }
 
@@ -87,21 +93,60 @@ pub(crate) async fn state_get_actor<DB: Blockstore>(
     data: Data<RPCState<DB>>,
     Params(params): Params<StateGetActorParams>,
 ) -> Result<StateGetActorResult, JsonRpcError> {
-    let (AddressJson(addr), LotusJson(tsk)) = params;
-    let ts = data.chain_store.tipset_from_keys(&tsk)?;
+    let (AddressJson(addr), LotusJson(selector)) = params;
+    let ts = load_tipset(&data, &selector)?;
     let state = data.state_manager.get_actor(&addr, *ts.parent_state());
     state.map(Into::into).map_err(|e| e.into())
 }
 
+/// Returns the addresses of all the actors in the parent state of a tipset.
+pub(in crate::rpc) async fn state_list_actors<DB: Blockstore>(
+    data: Data<RPCState<DB>>,
+    Params(params): Params<StateListActorsParams>,
+) -> Result<StateListActorsResult, JsonRpcError> {
+    let (LotusJson(selector),) = params;
+    let ts = load_tipset(&data, &selector)?;
+    let state_tree = StateTree::new_from_root(data.chain_store.db.clone(), ts.parent_state())?;
+    let mut actors = vec![];
+    state_tree.for_each(|address, _| {
+        actors.push(address);
+        Ok(())
+    })?;
+    Ok(LotusJson(actors))
+}
+
+/// Returns the state of an actor in the parent state of a tipset, decoded if
+/// it is a builtin actor.
+pub(in crate::rpc) async fn state_read_state<DB: Blockstore>(
+    data: Data<RPCState<DB>>,
+    Params(params): Params<StateReadStateParams>,
+) -> Result<StateReadStateResult, JsonRpcError> {
+    let (AddressJson(addr), LotusJson(selector)) = params;
+    let ts = load_tipset(&data, &selector)?;
+    let actor = data
+        .state_manager
+        .get_actor(&addr, *ts.parent_state())?
+        .with_context(|| format!("actor {addr} not found"))?;
+    let db = data.chain_store.blockstore();
+    let decoded = decode_actor_state(db, &actor);
+    Ok(ActorReadState {
+        balance: TokenAmount::from(&actor.balance),
+        code: actor.code,
+        state: render_ipld(db, &Ipld::Link(actor.state), &RenderOptions::default()),
+        name: decoded.as_ref().map(|(name, _)| name.to_string()),
+        decoded: decoded.map(|(_, decoded)| decoded),
+    })
+}
+
 /// looks up the Escrow and Locked balances of the given address in the Storage
 /// Market
 pub(in crate::rpc) async fn state_market_balance<DB: Blockstore + Send + Sync + 'static>(
     data: Data<RPCState<DB>>,
     Params(params): Params<StateMarketBalanceParams>,
 ) -> Result<StateMarketBalanceResult, JsonRpcError> {
-    let (address, LotusJson(key)) = params;
+    let (address, LotusJson(selector)) = params;
     let address = address.into();
-    let tipset = data.state_manager.chain_store().tipset_from_keys(&key)?;
+    let tipset = load_tipset(&data, &selector)?;
     data.state_manager
         .market_balance(&address, &tipset)
         .map_err(|e| e.into())
@@ -111,8 +156,8 @@ pub(in crate::rpc) async fn state_market_deals<DB: Blockstore>(
     data: Data<RPCState<DB>>,
     Params(params): Params<StateMarketDealsParams>,
 ) -> Result<StateMarketDealsResult, JsonRpcError> {
-    let (LotusJson(tsk),) = params;
-    let ts = data.chain_store.tipset_from_keys(&tsk)?;
+    let (LotusJson(selector),) = params;
+    let ts = load_tipset(&data, &selector)?;
     let actor = data
         .state_manager
         .get_actor(&Address::MARKET_ACTOR, *ts.parent_state())?
@@ -147,14 +192,15 @@ pub(in crate::rpc) async fn state_get_receipt<DB: Blockstore + Send + Sync + 'st
     data: Data<RPCState<DB>>,
     Params(params): Params<StateGetReceiptParams>,
 ) -> Result<StateGetReceiptResult, JsonRpcError> {
-    let (cidjson, LotusJson(key)) = params;
+    let (cidjson, LotusJson(selector)) = params;
     let state_manager = &data.state_manager;
     let cid = cidjson.into();
-    let tipset = data.state_manager.chain_store().tipset_from_keys(&key)?;
+    let tipset = load_tipset(&data, &selector)?;
     state_manager
         .get_receipt(tipset, cid)
+        .await
         .map(|s| s.into())
-        .map_err(|e| e.into())
+        .map_err(state_error)
 }
 /// looks back in the chain for a message. If not found, it blocks until the
 /// message arrives on chain, and gets to the indicated confidence depth.
@@ -165,7 +211,10 @@ pub(in crate::rpc) async fn state_wait_msg<DB: Blockstore + Send + Sync + 'stati
     let (cidjson, confidence) = params;
     let state_manager = &data.state_manager;
     let cid: Cid = cidjson.into();
-    let (tipset, receipt) = state_manager.wait_for_message(cid, confidence).await?;
+    let (tipset, receipt) = state_manager
+        .wait_for_message(cid, confidence)
+        .await
+        .map_err(state_error)?;
     let tipset = tipset.ok_or("wait for msg returned empty tuple")?;
     let receipt = receipt.ok_or("wait for msg returned empty receipt")?;
     let ipld: Ipld = if receipt.return_data().bytes().is_empty() {
@@ -351,3 +400,98 @@ pub(in crate::rpc) async fn state_fetch_root<DB: Blockstore + Sync + Send + 'sta
 fn lock_pop<T>(mutex: &Mutex<Vec<T>>) -> Option<T> {
     mutex.lock().pop()
 }
+
+/// Returns the actors that differ between the parent states of two tipsets.
+pub(in crate::rpc) async fn state_diff<DB: Blockstore + Send + Sync + 'static>(
+    data: Data<RPCState<DB>>,
+    Params((LotusJson(from), LotusJson(to))): Params<StateDiffParams>,
+) -> Result<StateDiffResult, JsonRpcError> {
+    let from = load_tipset(&data, &from)?;
+    let to = load_tipset(&data, &to)?;
+    Ok(data.state_manager.state_diff(&from, &to)?)
+}
+
+/// Lists the forensic bundles written for state root mismatches.
+pub(in crate::rpc) async fn state_mismatch_reports<DB: Blockstore + Send + Sync + 'static>(
+    data: Data<RPCState<DB>>,
+) -> Result<StateMismatchReportsResult, JsonRpcError> {
+    Ok(data.state_manager.state_mismatch_reports()?)
+}
+
+/// Returns the block rewards of a miner, from the rewards index.
+pub(in crate::rpc) async fn state_miner_rewards<DB: Blockstore + Send + Sync + 'static>(
+    data: Data<RPCState<DB>>,
+    Params((AddressJson(miner), from, to)): Params<StateMinerRewardsParams>,
+) -> Result<StateMinerRewardsResult, JsonRpcError> {
+    let head = data.state_manager.chain_store().heaviest_tipset();
+    let miner = data
+        .state_manager
+        .lookup_id(&miner, &head)?
+        .with_context(|| format!("Failed to resolve the address of {miner}"))?;
+    Ok(data.state_manager.miner_rewards(&miner, from..=to)?)
+}
+
+/// Returns the events emitted by an actor, from the event index.
+pub(in crate::rpc) async fn state_actor_events<DB: Blockstore + Send + Sync + 'static>(
+    data: Data<RPCState<DB>>,
+    Params((AddressJson(emitter), from, to, LotusJson(topic))): Params<StateActorEventsParams>,
+) -> Result<StateActorEventsResult, JsonRpcError> {
+    let head = data.state_manager.chain_store().heaviest_tipset();
+    let emitter = data
+        .state_manager
+        .lookup_id(&emitter, &head)?
+        .with_context(|| format!("Failed to resolve the address of {emitter}"))?;
+    Ok(data
+        .state_manager
+        .actor_events(emitter.id()?, from..=to, topic.as_deref())?)
+}
+
+/// Returns the actors created in a range of epochs, from the actor change
+/// index.
+pub(in crate::rpc) async fn state_list_new_actors<DB: Blockstore + Send + Sync + 'static>(
+    data: Data<RPCState<DB>>,
+    Params((from, to)): Params<StateListNewActorsParams>,
+) -> Result<StateListNewActorsResult, JsonRpcError> {
+    Ok(data
+        .state_manager
+        .actor_changes(from..=to)?
+        .into_iter()
+        .filter(|change| change.kind == ActorChangeKind::Created)
+        .collect())
+}
+
+/// Returns the deal IDs allocated by a `PublishStorageDeals` message, from
+/// the deal index.
+pub(in crate::rpc) async fn state_get_deal_ids_for_message<
+    DB: Blockstore + Send + Sync + 'static,
+>(
+    data: Data<RPCState<DB>>,
+    Params((CidJson(message),)): Params<StateGetDealIdsForMessageParams>,
+) -> Result<StateGetDealIdsForMessageResult, JsonRpcError> {
+    Ok(data
+        .state_manager
+        .deal_ids_for_message(&message)?
+        .with_context(|| format!("No deals are indexed for message {message}"))?)
+}
+
+/// Returns the recent calls of a traced actor.
+pub(in crate::rpc) async fn state_actor_trace<DB: Blockstore + Send + Sync + 'static>(
+    data: Data<RPCState<DB>>,
+    Params((AddressJson(address),)): Params<StateActorTraceParams>,
+) -> Result<StateActorTraceResult, JsonRpcError> {
+    let traced = data
+        .actor_tracer
+        .traced_address(&address)
+        .with_context(|| format!("{address} isn't traced"))?;
+    Ok(data.state_manager.actor_trace(&traced)?)
+}
+
+/// Returns the recent changes of an address of the watch-list.
+pub(in crate::rpc) async fn watch_list_history<DB: Blockstore + Send + Sync + 'static>(
+    data: Data<RPCState<DB>>,
+    Params((AddressJson(address),)): Params<WatchListHistoryParams>,
+) -> Result<WatchListHistoryResult, JsonRpcError> {
+    Ok(data
+        .watch_list
+        .history(data.chain_store.settings().as_ref(), &address)?)
+}
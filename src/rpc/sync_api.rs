@@ -4,10 +4,12 @@
 
 use crate::chain_sync::SyncState;
 use crate::json::cid::CidJson;
+use crate::lotus_json::LotusJson;
 use crate::rpc_api::{
     data_types::{RPCState, RPCSyncState},
     sync_api::*,
 };
+use anyhow::Context as _;
 use fvm_ipld_blockstore::Blockstore;
 use jsonrpc_v2::{Data, Error as JsonRpcError, Params};
 use parking_lot::RwLock;
@@ -53,6 +55,24 @@ where
     Ok(RPCSyncState { active_syncs })
 }
 
+/// Validates a block produced by a miner, publishes it to the network and
+/// syncs it.
+pub(in crate::rpc) async fn sync_submit_block<DB>(
+    data: Data<RPCState<DB>>,
+    Params(params): Params<SyncSubmitBlockParams>,
+) -> Result<SyncSubmitBlockResult, JsonRpcError>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    let (LotusJson(block),) = params;
+    let publisher = data
+        .block_publisher
+        .as_ref()
+        .context("Blocks can't be submitted to a node without chain sync")?;
+    publisher.publish(block).await?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
@@ -108,15 +128,15 @@ mod tests {
             let ts = Tipset::from(header);
             let db = cs_for_test.blockstore();
             let tsk = ts.key();
-            cs_for_test
-                .set_heaviest_tipset(Arc::new(ts.clone()))
-                .unwrap();
-
             for i in tsk.cids.into_iter() {
                 let bz2 = bz.clone();
                 db.put_keyed(&i, &bz2).unwrap();
             }
 
+            cs_for_test
+                .set_heaviest_tipset(Arc::new(ts.clone()))
+                .unwrap();
+
             let provider =
                 MpoolRpcProvider::new(cs_arc.publisher().clone(), state_manager_for_thread.clone());
             MessagePool::new(
@@ -132,6 +152,7 @@ mod tests {
         let (new_mined_block_tx, _) = flume::bounded(5);
         let start_time = chrono::Utc::now();
         let (gc_event_tx, _) = flume::unbounded();
+        let (finality_tx, _) = flume::unbounded();
 
         let state = Arc::new(RPCState {
             state_manager,
@@ -139,13 +160,22 @@ mod tests {
             mpool: Arc::new(pool),
             bad_blocks: Default::default(),
             sync_state: Arc::new(parking_lot::RwLock::new(Default::default())),
+            clock_skew: Default::default(),
             network_send,
             network_name: TEST_NET_NAME.to_owned(),
             start_time,
             chain_store: cs_for_chain.clone(),
             beacon,
             new_mined_block_tx,
+            block_publisher: None,
             gc_event_tx,
+            finality_tx,
+            remote_signers: Default::default(),
+            peer_manager: Default::default(),
+            watch_list: Default::default(),
+            actor_tracer: Default::default(),
+            auto_resubmit: Default::default(),
+            config: Default::default(),
         });
         (state, network_rx)
     }
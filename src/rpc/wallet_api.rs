@@ -6,6 +6,7 @@ use std::{convert::TryFrom, str::FromStr};
 use crate::json::address::json::AddressJson;
 use crate::key_management::{Error, Key};
 use crate::lotus_json::LotusJson;
+use crate::message::SignedMessage;
 use crate::rpc_api::{data_types::RPCState, wallet_api::*};
 use crate::shim::{address::Address, econ::TokenAmount, state_tree::StateTree};
 use base64::{prelude::BASE64_STANDARD, Engine};
@@ -190,22 +191,41 @@ where
     let key_addr = state_manager
         .resolve_to_key_addr(&address, &heaviest_tipset)
         .await?;
-    let keystore = &mut *data.keystore.write().await;
-    let key = match crate::key_management::find_key(&key_addr, keystore) {
-        Ok(key) => key,
-        Err(_) => {
-            let key_info = crate::key_management::try_find(&key_addr, keystore)?;
-            Key::try_from(key_info)?
-        }
-    };
+    let signer = data
+        .remote_signers
+        .find(&key_addr, &mut *data.keystore.write().await)?;
+    let sig = signer.sign(&BASE64_STANDARD.decode(msg_string)?).await?;
+
+    Ok(sig.into())
+}
 
-    let sig = crate::key_management::sign(
-        *key.key_info.key_type(),
-        key.key_info.private_key(),
-        &BASE64_STANDARD.decode(msg_string)?,
+/// Sign an unsigned message with the key of the given address. The signed
+/// bytes depend on the key type, see [`SignedMessage::message_signing_bytes`].
+pub(in crate::rpc) async fn wallet_sign_message<DB>(
+    data: Data<RPCState<DB>>,
+    Params((AddressJson(address), LotusJson(message))): Params<WalletSignMessageParams>,
+) -> Result<WalletSignMessageResult, JsonRpcError>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    let state_manager = &data.state_manager;
+    let heaviest_tipset = state_manager.chain_store().heaviest_tipset();
+    let key_addr = state_manager
+        .resolve_to_key_addr(&address, &heaviest_tipset)
+        .await?;
+    let signer = data
+        .remote_signers
+        .find(&key_addr, &mut *data.keystore.write().await)?;
+
+    let signing_bytes = SignedMessage::message_signing_bytes(
+        &message,
+        signer.sig_type(),
+        state_manager.chain_config().eth_chain_id,
     )?;
+    let sig = signer.sign(&signing_bytes).await?;
+    sig.verify(&signing_bytes, &key_addr)?;
 
-    Ok(sig.into())
+    Ok(LotusJson(SignedMessage::new_unchecked(message, sig)))
 }
 
 /// Verify a Signature, true if verified, false otherwise
@@ -5,18 +5,19 @@ use std::sync::Arc;
 
 use crate::beacon::BeaconSchedule;
 use crate::blocks::{Tipset, TipsetKeys};
-use crate::chain::ChainStore;
-use crate::chain_sync::{BadBlockCache, SyncState};
+use crate::chain::{finality::CertificateImport, ChainStore};
+use crate::chain_sync::{BadBlockCache, BlockPublisher, ClockSkew, SyncState};
+use crate::cli_shared::cli::Config;
 use crate::ipld::json::IpldJson;
 use crate::json::{cid::CidJson, token_amount::json};
-use crate::key_management::KeyStore;
+use crate::key_management::{KeyStore, RemoteSigners};
 pub use crate::libp2p::{Multiaddr, Protocol};
-use crate::libp2p::{Multihash, NetworkMessage};
+use crate::libp2p::{Multihash, NetworkMessage, PeerManager};
 use crate::message::signed_message::SignedMessage;
-use crate::message_pool::{MessagePool, MpoolRpcProvider};
+use crate::message_pool::{AutoResubmit, MessagePool, MpoolRpcProvider};
 use crate::shim::executor::Receipt;
 use crate::shim::{econ::TokenAmount, message::Message};
-use crate::state_manager::StateManager;
+use crate::state_manager::{actor_trace::ActorTracer, watch_list::WatchList, StateManager};
 use ahash::HashSet;
 use chrono::Utc;
 use cid::Cid;
@@ -39,12 +40,25 @@ where
     pub mpool: Arc<MessagePool<MpoolRpcProvider<DB>>>,
     pub bad_blocks: Arc<BadBlockCache>,
     pub sync_state: Arc<SyncRwLock<SyncState>>,
+    pub clock_skew: Arc<ClockSkew>,
     pub network_send: flume::Sender<NetworkMessage>,
     pub network_name: String,
     pub start_time: chrono::DateTime<Utc>,
     pub new_mined_block_tx: flume::Sender<Arc<Tipset>>,
+    /// Publisher of the blocks submitted by miners. Missing if the node runs
+    /// without chain sync.
+    pub block_publisher: Option<BlockPublisher<DB>>,
     pub beacon: Arc<BeaconSchedule>,
     pub gc_event_tx: flume::Sender<flume::Sender<anyhow::Result<()>>>,
+    /// Queue of the finality certificates imported over RPC.
+    pub finality_tx: flume::Sender<CertificateImport>,
+    pub remote_signers: Arc<RemoteSigners>,
+    pub peer_manager: Arc<PeerManager>,
+    pub watch_list: Arc<WatchList>,
+    pub actor_tracer: Arc<ActorTracer>,
+    pub auto_resubmit: Arc<AutoResubmit>,
+    /// Configuration the node was started with.
+    pub config: Arc<Config>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -70,8 +84,9 @@ pub struct BlockMessages {
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct MessageSendSpec {
+    /// Maximum fee paid for the message, zero for the default maximum fee.
     #[serde(with = "json")]
-    max_fee: TokenAmount,
+    pub max_fee: TokenAmount,
 }
 
 #[derive(Serialize)]
@@ -93,6 +108,23 @@ pub struct MessageLookup {
     pub return_dec: IpldJson,
 }
 
+/// State of an actor for inspection. Lotus only returns the `Balance`, `Code`
+/// and `State` fields.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ActorReadState {
+    #[serde(with = "crate::lotus_json")]
+    pub balance: TokenAmount,
+    #[serde(with = "crate::lotus_json")]
+    pub code: Cid,
+    /// See [`render_ipld`](crate::ipld::render::render_ipld).
+    pub state: serde_json::Value,
+    /// Name of the builtin actor, if its state could be decoded.
+    pub name: Option<String>,
+    /// Debug representation of the decoded state.
+    pub decoded: Option<String>,
+}
+
 // Net API
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
@@ -0,0 +1,310 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Parameters and results of the Ethereum JSON-RPC methods, which use the hex
+//! encodings of the Ethereum JSON-RPC specification instead of Lotus JSON.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::message::delegated::EAM_NAMESPACE;
+use crate::rpc_api::tipset_selector::{TipsetSelector, TipsetTag};
+use crate::shim::address::Address;
+use crate::shim::clock::ChainEpoch;
+use crate::shim::econ::TokenAmount;
+use anyhow::Context as _;
+use num_bigint::BigInt;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Prefix of the Ethereum addresses masking actor IDs.
+const ID_MASK_PREFIX: [u8; 12] = [0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+fn decode_hex(s: &str) -> anyhow::Result<Vec<u8>> {
+    let digits = s
+        .strip_prefix("0x")
+        .with_context(|| format!("{s} isn't 0x-prefixed"))?;
+    match digits.len() % 2 {
+        0 => Ok(hex::decode(digits)?),
+        _ => Ok(hex::decode(format!("0{digits}"))?),
+    }
+}
+
+/// Ethereum address. Masked ID addresses, i.e. `0xff` followed by zeros and
+/// the big-endian ID, designate actors by ID. Other addresses are the
+/// subaddresses of `f410` addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EthAddress(pub [u8; 20]);
+
+impl EthAddress {
+    pub fn to_filecoin_address(self) -> anyhow::Result<Address> {
+        match self.0.strip_prefix(&ID_MASK_PREFIX) {
+            Some(id) => Ok(Address::new_id(u64::from_be_bytes(id.try_into()?))),
+            None => Ok(Address::new_delegated(EAM_NAMESPACE, &self.0)?),
+        }
+    }
+}
+
+impl FromStr for EthAddress {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = decode_hex(s)?;
+        Ok(Self(bytes.try_into().map_err(|_| {
+            anyhow::anyhow!("{s} isn't a 20 bytes Ethereum address")
+        })?))
+    }
+}
+
+impl fmt::Display for EthAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{}", hex::encode(self.0))
+    }
+}
+
+/// 32 bytes word, i.e. a storage position or value. Shorter values are
+/// left-padded with zeros.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct EthWord(pub [u8; 32]);
+
+impl EthWord {
+    /// Pads `bytes` to 32 bytes.
+    pub fn from_be_slice(bytes: &[u8]) -> anyhow::Result<Self> {
+        anyhow::ensure!(bytes.len() <= 32, "word longer than 32 bytes");
+        let mut word = [0; 32];
+        word[32 - bytes.len()..].copy_from_slice(bytes);
+        Ok(Self(word))
+    }
+}
+
+impl FromStr for EthWord {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_be_slice(&decode_hex(s)?)
+    }
+}
+
+impl fmt::Display for EthWord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{}", hex::encode(self.0))
+    }
+}
+
+macro_rules! string_serde {
+    ($ty:ty) => {
+        impl Serialize for $ty {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                String::deserialize(deserializer)?
+                    .parse()
+                    .map_err(D::Error::custom)
+            }
+        }
+    };
+}
+
+/// Quantity, e.g. a gas limit, encoded in hex without leading zeros.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EthUint64(pub u64);
+
+impl FromStr for EthUint64 {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s
+            .strip_prefix("0x")
+            .with_context(|| format!("{s} isn't 0x-prefixed"))?;
+        Ok(Self(u64::from_str_radix(digits, 16)?))
+    }
+}
+
+impl fmt::Display for EthUint64 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}", self.0)
+    }
+}
+
+/// Amount of `attoFIL`, i.e. `wei`, encoded as a quantity.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EthTokenAmount(pub TokenAmount);
+
+impl FromStr for EthTokenAmount {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s
+            .strip_prefix("0x")
+            .with_context(|| format!("{s} isn't 0x-prefixed"))?;
+        let atto = BigInt::parse_bytes(digits.as_bytes(), 16)
+            .with_context(|| format!("invalid quantity {s}"))?;
+        Ok(Self(TokenAmount::from_atto(atto)))
+    }
+}
+
+impl fmt::Display for EthTokenAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{}", self.0.atto().to_str_radix(16))
+    }
+}
+
+string_serde!(EthAddress);
+string_serde!(EthWord);
+string_serde!(EthUint64);
+string_serde!(EthTokenAmount);
+
+/// Bytes, e.g. the bytecode of a contract.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EthBytes(pub Vec<u8>);
+
+impl Serialize for EthBytes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("0x{}", hex::encode(&self.0)))
+    }
+}
+
+impl<'de> Deserialize<'de> for EthBytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        decode_hex(&String::deserialize(deserializer)?)
+            .map(Self)
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Transaction of `eth_sendTransaction`, to be signed by the node. The
+/// transactions without recipient deploy the contract of their input. Omitted
+/// gas parameters are estimated.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EthTransactionArgs {
+    pub from: EthAddress,
+    #[serde(default)]
+    pub to: Option<EthAddress>,
+    #[serde(default)]
+    pub gas: Option<EthUint64>,
+    #[serde(default)]
+    pub max_fee_per_gas: Option<EthTokenAmount>,
+    #[serde(default)]
+    pub max_priority_fee_per_gas: Option<EthTokenAmount>,
+    #[serde(default)]
+    pub value: Option<EthTokenAmount>,
+    /// Input of the transaction, also accepted as `data` by most clients.
+    #[serde(default, alias = "data")]
+    pub input: Option<EthBytes>,
+}
+
+/// Block parameter of the Ethereum methods: a tag, a block number, or an
+/// `EIP-1898` object with a block number. Pending blocks are the head, and
+/// block hashes aren't supported.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EthBlock(pub TipsetSelector);
+
+impl FromStr for EthBlock {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let selector = match s {
+            "latest" | "pending" => TipsetSelector::Tag(TipsetTag::Latest),
+            "safe" => TipsetSelector::Tag(TipsetTag::Safe),
+            "finalized" => TipsetSelector::Tag(TipsetTag::Finalized),
+            "earliest" => TipsetSelector::height(0),
+            number => {
+                let digits = number
+                    .strip_prefix("0x")
+                    .with_context(|| format!("invalid block parameter {number}"))?;
+                TipsetSelector::height(ChainEpoch::from_str_radix(digits, 16)?)
+            }
+        };
+        Ok(Self(selector))
+    }
+}
+
+impl<'de> Deserialize<'de> for EthBlock {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Param {
+            Str(String),
+            #[serde(rename_all = "camelCase")]
+            Number {
+                block_number: String,
+            },
+        }
+
+        match Param::deserialize(deserializer).map_err(|_| {
+            D::Error::custom("expected a block number or tag, block hashes aren't supported")
+        })? {
+            Param::Str(s) | Param::Number { block_number: s } => {
+                s.parse().map_err(D::Error::custom)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn addresses() {
+        let masked: EthAddress = "0xff00000000000000000000000000000000000400"
+            .parse()
+            .unwrap();
+        assert_eq!(masked.to_filecoin_address().unwrap(), Address::new_id(1024));
+        let contract: EthAddress = "0xd4c5fb16488aa48081296299d54b0c648c9333da"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            contract.to_filecoin_address().unwrap(),
+            Address::new_delegated(10, &contract.0).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(contract).unwrap(),
+            json!("0xd4c5fb16488aa48081296299d54b0c648c9333da")
+        );
+        assert!("0xd4c5".parse::<EthAddress>().is_err());
+    }
+
+    #[test]
+    fn words_are_padded() {
+        let word: EthWord = serde_json::from_value(json!("0x1")).unwrap();
+        assert_eq!(word.0[31], 1);
+        assert_eq!(
+            serde_json::to_value(word).unwrap(),
+            json!(format!("0x{:064x}", 1))
+        );
+        assert!(format!("0x{}", "00".repeat(33)).parse::<EthWord>().is_err());
+        let amount: EthTokenAmount = serde_json::from_value(json!("0xde0b6b3a7640000")).unwrap();
+        assert_eq!(amount.0, TokenAmount::from_whole(1));
+        assert_eq!(
+            serde_json::to_value(EthUint64(21000)).unwrap(),
+            json!("0x5208")
+        );
+        assert_eq!(
+            serde_json::to_value(EthBytes(vec![0x60, 0x80])).unwrap(),
+            json!("0x6080")
+        );
+    }
+
+    #[test]
+    fn blocks() {
+        let block = |value| serde_json::from_value::<EthBlock>(value).unwrap().0;
+        assert_eq!(block(json!("latest")), TipsetSelector::default());
+        assert_eq!(
+            block(json!("finalized")),
+            TipsetSelector::Tag(TipsetTag::Finalized)
+        );
+        assert_eq!(block(json!("0x1a")), TipsetSelector::height(26));
+        assert_eq!(
+            block(json!({"blockNumber": "earliest"})),
+            TipsetSelector::height(0)
+        );
+        assert!(serde_json::from_value::<EthBlock>(json!({"blockHash": "0x00"})).is_err());
+        assert!(serde_json::from_value::<EthBlock>(json!("26")).is_err());
+    }
+}
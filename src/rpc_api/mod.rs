@@ -13,6 +13,22 @@ use ahash::{HashMap, HashMapExt};
 use once_cell::sync::Lazy;
 
 pub mod data_types;
+pub mod eth_types;
+pub mod tipset_selector;
+
+/// Error codes for chain data that is not available locally. They sit in the
+/// range that the JSON-RPC specification reserves for server errors.
+pub mod error_codes {
+    /// The data is not known to the node.
+    pub const NOT_FOUND: i64 = -32001;
+    /// The data was part of the chain but is no longer stored.
+    pub const PRUNED: i64 = -32002;
+    /// The data is ahead of the current head of the node.
+    pub const NOT_YET_SYNCED: i64 = -32003;
+    /// The data results from the execution of a tipset that the node never
+    /// executed.
+    pub const NOT_COMPUTED: i64 = -32004;
+}
 
 /// Access levels to be checked against JWT claims
 pub enum Access {
@@ -37,27 +53,49 @@ pub static ACCESS_MAP: Lazy<HashMap<&str, Access>> = Lazy::new(|| {
     // Chain API
     access.insert(chain_api::CHAIN_GET_MESSAGE, Access::Read);
     access.insert(chain_api::CHAIN_EXPORT, Access::Read);
+    access.insert(chain_api::CHAIN_EXPORT_PROGRESS, Access::Read);
+    access.insert(chain_api::CHAIN_EXPORT_PROGRESS_NOTIFY, Access::Read);
     access.insert(chain_api::CHAIN_READ_OBJ, Access::Read);
     access.insert(chain_api::CHAIN_HAS_OBJ, Access::Read);
     access.insert(chain_api::CHAIN_GET_BLOCK_MESSAGES, Access::Read);
     access.insert(chain_api::CHAIN_GET_TIPSET_BY_HEIGHT, Access::Read);
     access.insert(chain_api::CHAIN_GET_GENESIS, Access::Read);
     access.insert(chain_api::CHAIN_HEAD, Access::Read);
+    access.insert(chain_api::CHAIN_NOTIFY, Access::Read);
     access.insert(chain_api::CHAIN_GET_BLOCK, Access::Read);
     access.insert(chain_api::CHAIN_GET_TIPSET, Access::Read);
     access.insert(chain_api::CHAIN_GET_NAME, Access::Read);
+    access.insert(chain_api::CHAIN_GET_PATH, Access::Read);
+    access.insert(chain_api::CHAIN_GET_MESSAGE_INCLUSION_PROOF, Access::Read);
     access.insert(chain_api::CHAIN_SET_HEAD, Access::Admin);
+    access.insert(chain_api::CHAIN_IMPORT_FINALITY_CERTIFICATE, Access::Admin);
+    access.insert(chain_api::CHAIN_VALIDATE, Access::Admin);
     access.insert(chain_api::CHAIN_GET_MIN_BASE_FEE, Access::Admin);
+    access.insert(chain_api::CHAIN_GAS_HISTORY, Access::Read);
+    access.insert(chain_api::CHAIN_PIN, Access::Admin);
+    access.insert(chain_api::CHAIN_UNPIN, Access::Admin);
+    access.insert(chain_api::CHAIN_PINS, Access::Read);
 
     // Message Pool API
     access.insert(mpool_api::MPOOL_PENDING, Access::Read);
     access.insert(mpool_api::MPOOL_PUSH, Access::Write);
     access.insert(mpool_api::MPOOL_PUSH_MESSAGE, Access::Sign);
+    access.insert(mpool_api::MPOOL_EXPORT, Access::Read);
+    access.insert(mpool_api::MPOOL_IMPORT, Access::Write);
+    access.insert(mpool_api::MPOOL_GET_NONCE, Access::Read);
+    access.insert(mpool_api::MPOOL_RESERVE_NONCE, Access::Write);
+    access.insert(mpool_api::MPOOL_RELEASE_NONCE, Access::Write);
+    access.insert(mpool_api::MPOOL_PUSH_RESERVED, Access::Write);
+    access.insert(mpool_api::MPOOL_RESUBMIT_STATUS, Access::Read);
+    access.insert(mpool_api::MPOOL_RESUBMIT_PAUSE, Access::Write);
+    access.insert(mpool_api::MPOOL_RESUBMIT_RESUME, Access::Write);
+    access.insert(mpool_api::MPOOL_RESUBMIT_LOG, Access::Read);
 
     // Sync API
     access.insert(sync_api::SYNC_CHECK_BAD, Access::Read);
     access.insert(sync_api::SYNC_MARK_BAD, Access::Admin);
     access.insert(sync_api::SYNC_STATE, Access::Read);
+    access.insert(sync_api::SYNC_SUBMIT_BLOCK, Access::Write);
 
     // Wallet API
     access.insert(wallet_api::WALLET_BALANCE, Access::Write);
@@ -70,6 +108,7 @@ pub static ACCESS_MAP: Lazy<HashMap<&str, Access>> = Lazy::new(|| {
     access.insert(wallet_api::WALLET_NEW, Access::Write);
     access.insert(wallet_api::WALLET_SET_DEFAULT, Access::Write);
     access.insert(wallet_api::WALLET_SIGN, Access::Sign);
+    access.insert(wallet_api::WALLET_SIGN_MESSAGE, Access::Sign);
     access.insert(wallet_api::WALLET_VERIFY, Access::Read);
 
     // State API
@@ -83,6 +122,18 @@ pub static ACCESS_MAP: Lazy<HashMap<&str, Access>> = Lazy::new(|| {
     access.insert(state_api::STATE_NETWORK_NAME, Access::Read);
     access.insert(state_api::STATE_NETWORK_VERSION, Access::Read);
     access.insert(state_api::STATE_FETCH_ROOT, Access::Read);
+    access.insert(state_api::STATE_DIFF, Access::Read);
+    access.insert(state_api::STATE_MISMATCH_REPORTS, Access::Read);
+    access.insert(state_api::STATE_MINER_REWARDS, Access::Read);
+    access.insert(state_api::STATE_ACTOR_EVENTS, Access::Read);
+    access.insert(state_api::STATE_LIST_ACTORS, Access::Read);
+    access.insert(state_api::STATE_READ_STATE, Access::Read);
+    access.insert(state_api::STATE_GET_DEAL_IDS_FOR_MESSAGE, Access::Read);
+    access.insert(state_api::WATCH_LIST_HISTORY, Access::Read);
+    access.insert(state_api::STATE_ACTOR_TRACE, Access::Read);
+    access.insert(state_api::WATCH_LIST_NOTIFY, Access::Read);
+    access.insert(state_api::STATE_LIST_NEW_ACTORS, Access::Read);
+    access.insert(state_api::STATE_ACTOR_CHANGES_NOTIFY, Access::Read);
 
     // Gas API
     access.insert(gas_api::GAS_ESTIMATE_GAS_LIMIT, Access::Read);
@@ -90,6 +141,19 @@ pub static ACCESS_MAP: Lazy<HashMap<&str, Access>> = Lazy::new(|| {
     access.insert(gas_api::GAS_ESTIMATE_FEE_CAP, Access::Read);
     access.insert(gas_api::GAS_ESTIMATE_MESSAGE_GAS, Access::Read);
 
+    // Eth API
+    access.insert(eth_api::ETH_GET_CODE, Access::Read);
+    access.insert(eth_api::ETH_GET_CODE_ALIAS, Access::Read);
+    access.insert(eth_api::ETH_GET_STORAGE_AT, Access::Read);
+    access.insert(eth_api::ETH_GET_STORAGE_AT_ALIAS, Access::Read);
+    access.insert(eth_api::ETH_ACCOUNTS, Access::Write);
+    access.insert(eth_api::ETH_ACCOUNTS_ALIAS, Access::Write);
+    access.insert(eth_api::PERSONAL_SIGN, Access::Sign);
+    access.insert(eth_api::ETH_SIGN_TYPED_DATA, Access::Sign);
+    access.insert(eth_api::ETH_SIGN_TYPED_DATA_V4, Access::Sign);
+    access.insert(eth_api::ETH_SEND_TRANSACTION, Access::Sign);
+    access.insert(eth_api::ETH_SEND_TRANSACTION_ALIAS, Access::Sign);
+
     // Common API
     access.insert(common_api::VERSION, Access::Read);
     access.insert(common_api::SHUTDOWN, Access::Admin);
@@ -101,6 +165,11 @@ pub static ACCESS_MAP: Lazy<HashMap<&str, Access>> = Lazy::new(|| {
     access.insert(net_api::NET_INFO, Access::Read);
     access.insert(net_api::NET_CONNECT, Access::Write);
     access.insert(net_api::NET_DISCONNECT, Access::Write);
+    access.insert(net_api::NET_PEER_HEADS, Access::Read);
+    access.insert(net_api::NET_AGENT_VERSIONS, Access::Read);
+    access.insert(net_api::NET_PUBSUB_STATS, Access::Read);
+    access.insert(net_api::NET_PEER_DETAILS, Access::Read);
+    access.insert(net_api::NET_PROTOCOL_STATS, Access::Read);
 
     // DB API
     access.insert(db_api::DB_GC, Access::Write);
@@ -109,6 +178,7 @@ pub static ACCESS_MAP: Lazy<HashMap<&str, Access>> = Lazy::new(|| {
     access.insert(progress_api::GET_PROGRESS, Access::Read);
     // Node API
     access.insert(node_api::NODE_STATUS, Access::Read);
+    access.insert(node_api::GET_CONFIG, Access::Admin);
 
     access
 });
@@ -161,14 +231,21 @@ pub mod beacon_api {
 pub mod chain_api {
     use std::path::PathBuf;
 
-    use crate::blocks::{BlockHeader, Tipset, TipsetKeys};
+    use crate::blocks::{BlockHeader, Tipset};
     use crate::json::cid::CidJson;
     use crate::lotus_json::LotusJson;
     use crate::shim::clock::ChainEpoch;
     use crate::shim::message::Message;
+    use cid::Cid;
     use serde::{Deserialize, Serialize};
 
-    use crate::rpc_api::data_types::BlockMessages;
+    use crate::chain::{
+        export_progress::ExportProgress, finality::FinalityCertificate,
+        gas_history::GasHistoryPoint, headchange_json::HeadChangeJson,
+        inclusion_proof::InclusionProof, ChainEpochDelta,
+    };
+    use crate::rpc_api::{data_types::BlockMessages, tipset_selector::TipsetSelector};
+    use crate::state_manager::chain_validation::{ChainValidationProgress, ValidateFrom};
 
     pub const CHAIN_GET_MESSAGE: &str = "Filecoin.ChainGetMessage";
     pub type ChainGetMessageParams = (CidJson,);
@@ -181,14 +258,35 @@ pub mod chain_api {
         pub epoch: ChainEpoch,
         pub recent_roots: i64,
         pub output_path: PathBuf,
+        /// Tipset in the chain of which `epoch` is exported.
         #[serde(with = "crate::lotus_json")]
-        pub tipset_keys: TipsetKeys,
+        pub tipset: TipsetSelector,
         pub skip_checksum: bool,
         pub dry_run: bool,
+        /// Write blocks in a canonical order, see
+        /// [`crate::ipld::ChainStream::canonical`].
+        #[serde(default)]
+        pub canonical: bool,
+        /// Epoch of the root of a previous snapshot. Only the blocks that are
+        /// not in that snapshot are exported, see [`crate::chain::diff_seen`].
+        pub diff: Option<ChainEpoch>,
+        /// Number of state-roots of the previous snapshot. The chain finality
+        /// if unset.
+        pub diff_depth: Option<ChainEpochDelta>,
     }
 
     pub type ChainExportResult = Option<String>;
 
+    /// Returns the progress of the running export, or of the last one.
+    pub const CHAIN_EXPORT_PROGRESS: &str = "Filecoin.ChainExportProgress";
+    pub type ChainExportProgressResult = Option<ExportProgress>;
+
+    /// Subscribes to the progress of the exports. Only available over
+    /// websockets, like [`CHAIN_NOTIFY`]. The current progress is pushed when
+    /// subscribing, then every time it changes, at most once per second.
+    pub const CHAIN_EXPORT_PROGRESS_NOTIFY: &str = "Filecoin.ChainExportProgressNotify";
+    pub type ChainExportProgressNotifyValue = Option<ExportProgress>;
+
     pub const CHAIN_READ_OBJ: &str = "Filecoin.ChainReadObj";
     pub type ChainReadObjParams = (CidJson,);
     pub type ChainReadObjResult = String;
@@ -202,7 +300,8 @@ pub mod chain_api {
     pub type ChainGetBlockMessagesResult = BlockMessages;
 
     pub const CHAIN_GET_TIPSET_BY_HEIGHT: &str = "Filecoin.ChainGetTipsetByHeight";
-    pub type ChainGetTipsetByHeightParams = (ChainEpoch, TipsetKeys);
+    /// Height, and tipset in the chain of which the height is looked up.
+    pub type ChainGetTipsetByHeightParams = (ChainEpoch, LotusJson<TipsetSelector>);
     pub type ChainGetTipsetByHeightResult = LotusJson<Tipset>;
 
     pub const CHAIN_GET_GENESIS: &str = "Filecoin.ChainGetGenesis";
@@ -220,31 +319,102 @@ pub mod chain_api {
     pub type ChainGetBlockResult = LotusJson<BlockHeader>;
 
     pub const CHAIN_GET_TIPSET: &str = "Filecoin.ChainGetTipSet";
-    pub type ChainGetTipSetParams = (LotusJson<TipsetKeys>,);
+    pub type ChainGetTipSetParams = (LotusJson<TipsetSelector>,);
     pub type ChainGetTipSetResult = LotusJson<Tipset>;
 
     pub const CHAIN_GET_NAME: &str = "Filecoin.ChainGetName";
     pub type ChainGetNameParams = ();
     pub type ChainGetNameResult = String;
 
+    pub const CHAIN_GET_PATH: &str = "Filecoin.ChainGetPath";
+    pub type ChainGetPathParams = (LotusJson<TipsetSelector>, LotusJson<TipsetSelector>);
+    pub type ChainGetPathResult = Vec<HeadChangeJson>;
+
+    /// Proof that a message is included in a tipset, to be checked with
+    /// [`verify_inclusion`](crate::chain::inclusion_proof::verify_inclusion).
+    pub const CHAIN_GET_MESSAGE_INCLUSION_PROOF: &str = "Filecoin.ChainGetMessageInclusionProof";
+    pub type ChainGetMessageInclusionProofParams = (CidJson, LotusJson<TipsetSelector>);
+    pub type ChainGetMessageInclusionProofResult = InclusionProof;
+
+    /// Verifies a finality certificate and marks its tipset as finalized, see
+    /// [`finality`](crate::chain::finality).
+    pub const CHAIN_IMPORT_FINALITY_CERTIFICATE: &str = "Forest.ChainImportFinalityCertificate";
+    pub type ChainImportFinalityCertificateParams = (FinalityCertificate,);
+    pub type ChainImportFinalityCertificateResult = ();
+
+    /// Subscribes to the head changes of the chain. Only available over
+    /// websockets: the result is the ID of the channel on which the changes
+    /// are pushed with [`CHANNEL_VALUE`] notifications. The first notification
+    /// holds the current head; subscribers that fall behind are sent the
+    /// current head again instead of the changes they missed.
+    pub const CHAIN_NOTIFY: &str = "Filecoin.ChainNotify";
+    pub type ChainNotifyParams = ();
+    pub type ChainNotifyResult = u64;
+    pub type ChainNotifyValue = Vec<HeadChangeJson>;
+
+    /// Method of the notifications carrying the values of a subscription
+    /// channel, with the channel ID and the value as parameters.
+    pub const CHANNEL_VALUE: &str = "xrpc.ch.val";
+    /// Method of the notification closing a subscription channel, with the
+    /// channel ID as parameter.
+    pub const CHANNEL_CLOSE: &str = "xrpc.ch.close";
+
     pub const CHAIN_SET_HEAD: &str = "Filecoin.ChainSetHead";
-    pub type ChainSetHeadParams = (TipsetKeys,);
+    pub type ChainSetHeadParams = (LotusJson<TipsetSelector>,);
     pub type ChainSetHeadResult = ();
 
+    pub const CHAIN_VALIDATE: &str = "Filecoin.ChainValidate";
+    /// Starts or resumes validating the chain if a starting point is given,
+    /// up to an epoch or the head, and whether to discard the progress of a
+    /// previous validation.
+    pub type ChainValidateParams = (Option<ValidateFrom>, Option<ChainEpoch>, bool);
+    pub type ChainValidateResult = Option<ChainValidationProgress>;
+
     pub const CHAIN_GET_MIN_BASE_FEE: &str = "Filecoin.ChainGetMinBaseFee";
     pub type ChainGetMinBaseFeeParams = (u32,);
     pub type ChainGetMinBaseFeeResult = String;
+
+    pub const CHAIN_GAS_HISTORY: &str = "Filecoin.ChainGasHistory";
+    /// First and last epoch, and number of epochs per point.
+    pub type ChainGasHistoryParams = (ChainEpoch, ChainEpoch, ChainEpoch);
+    pub type ChainGasHistoryResult = Vec<GasHistoryPoint>;
+
+    /// Pins a state so that it is kept by the garbage collectors, and returns
+    /// its root, see [`ChainStore::pin_state`](crate::chain::ChainStore::pin_state).
+    pub const CHAIN_PIN: &str = "Filecoin.ChainPin";
+    pub type ChainPinParams = (ChainPinTarget,);
+    pub type ChainPinResult = LotusJson<Cid>;
+
+    /// Unpins a state root, returns whether it was pinned.
+    pub const CHAIN_UNPIN: &str = "Filecoin.ChainUnpin";
+    pub type ChainUnpinParams = (LotusJson<Cid>,);
+    pub type ChainUnpinResult = bool;
+
+    pub const CHAIN_PINS: &str = "Filecoin.ChainPins";
+    pub type ChainPinsResult = LotusJson<Vec<Cid>>;
+
+    /// State to pin: a state root, or the parent state of a tipset, i.e. the
+    /// state its messages are applied to.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub enum ChainPinTarget {
+        StateRoot(#[serde(with = "crate::lotus_json")] Cid),
+        Tipset(#[serde(with = "crate::lotus_json")] TipsetSelector),
+    }
 }
 
 /// Message Pool API
 pub mod mpool_api {
+    use crate::message_pool::{AutoResubmitStatus, ResubmitRecord};
     use crate::rpc_api::data_types::MessageSendSpec;
-    use crate::shim::message::Message;
+    use crate::shim::{address::Address, message::Message};
     use crate::{
         json::cid::{vec::CidJsonVec, CidJson},
         lotus_json::LotusJson,
         message::SignedMessage,
     };
+    use cid::Cid;
+    use serde::{Deserialize, Serialize};
 
     pub const MPOOL_PENDING: &str = "Filecoin.MpoolPending";
     pub type MpoolPendingParams = (CidJsonVec,);
@@ -257,11 +427,90 @@ pub mod mpool_api {
     pub const MPOOL_PUSH_MESSAGE: &str = "Filecoin.MpoolPushMessage";
     pub type MpoolPushMessageParams = (LotusJson<Message>, Option<MessageSendSpec>);
     pub type MpoolPushMessageResult = LotusJson<SignedMessage>;
+
+    pub const MPOOL_EXPORT: &str = "Filecoin.MpoolExport";
+    pub type MpoolExportParams = ();
+    pub type MpoolExportResult = LotusJson<Vec<SignedMessage>>;
+
+    pub const MPOOL_IMPORT: &str = "Filecoin.MpoolImport";
+    pub type MpoolImportParams = (LotusJson<Vec<SignedMessage>>,);
+    pub type MpoolImportResult = MpoolImportSummary;
+
+    /// Outcome of the import of a bundle of signed messages. A rejected message
+    /// doesn't prevent the others from being imported.
+    #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct MpoolImportSummary {
+        #[serde(with = "crate::lotus_json")]
+        pub imported: Vec<Cid>,
+        pub rejected: Vec<MpoolImportRejection>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct MpoolImportRejection {
+        #[serde(with = "crate::lotus_json")]
+        pub cid: Cid,
+        pub error: String,
+    }
+
+    pub const MPOOL_GET_NONCE: &str = "Filecoin.MpoolGetNonce";
+    pub type MpoolGetNonceParams = (LotusJson<Address>,);
+    pub type MpoolGetNonceResult = u64;
+
+    /// Reserves the next nonce of an address, for an optional number of
+    /// seconds, capped to an hour.
+    pub const MPOOL_RESERVE_NONCE: &str = "Filecoin.MpoolReserveNonce";
+    pub type MpoolReserveNonceParams = (LotusJson<Address>, Option<u64>);
+    pub type MpoolReserveNonceResult = MpoolNonceReservation;
+
+    pub const MPOOL_RELEASE_NONCE: &str = "Filecoin.MpoolReleaseNonce";
+    pub type MpoolReleaseNonceParams = (LotusJson<Address>, u64, u64);
+    pub type MpoolReleaseNonceResult = ();
+
+    /// Pushes a signed message using a nonce reserved with
+    /// `Filecoin.MpoolReserveNonce`, with the lease of the reservation.
+    pub const MPOOL_PUSH_RESERVED: &str = "Filecoin.MpoolPushReserved";
+    pub type MpoolPushReservedParams = (LotusJson<SignedMessage>, u64);
+    pub type MpoolPushReservedResult = CidJson;
+
+    /// Nonce reserved until it is used by a pushed message, released, or
+    /// expires.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct MpoolNonceReservation {
+        #[serde(with = "crate::lotus_json")]
+        pub address: Address,
+        pub nonce: u64,
+        /// Identifier of the reservation, required to release it.
+        pub lease: u64,
+        pub expiration: chrono::DateTime<chrono::Utc>,
+    }
+
+    /// Status of the automatic resubmission of the underpriced local messages.
+    pub const MPOOL_RESUBMIT_STATUS: &str = "Filecoin.MpoolResubmitStatus";
+    pub type MpoolResubmitStatusResult = AutoResubmitStatus;
+
+    /// Pauses the automatic resubmission. Returns whether it was running.
+    pub const MPOOL_RESUBMIT_PAUSE: &str = "Filecoin.MpoolResubmitPause";
+    pub type MpoolResubmitPauseResult = bool;
+
+    /// Resumes the automatic resubmission. Returns whether it was paused.
+    pub const MPOOL_RESUBMIT_RESUME: &str = "Filecoin.MpoolResubmitResume";
+    pub type MpoolResubmitResumeResult = bool;
+
+    /// Returns the given number of most recent records of the audit log of the
+    /// automatic resubmission, oldest first.
+    pub const MPOOL_RESUBMIT_LOG: &str = "Filecoin.MpoolResubmitLog";
+    pub type MpoolResubmitLogParams = (usize,);
+    pub type MpoolResubmitLogResult = Vec<ResubmitRecord>;
 }
 
 /// Sync API
 pub mod sync_api {
+    use crate::blocks::GossipBlock;
     use crate::json::cid::CidJson;
+    use crate::lotus_json::LotusJson;
 
     use crate::rpc_api::data_types::RPCSyncState;
 
@@ -276,6 +525,10 @@ pub mod sync_api {
     pub const SYNC_STATE: &str = "Filecoin.SyncState";
     pub type SyncStateParams = ();
     pub type SyncStateResult = RPCSyncState;
+
+    pub const SYNC_SUBMIT_BLOCK: &str = "Filecoin.SyncSubmitBlock";
+    pub type SyncSubmitBlockParams = (LotusJson<GossipBlock>,);
+    pub type SyncSubmitBlockResult = ();
 }
 
 /// Wallet API
@@ -283,7 +536,9 @@ pub mod wallet_api {
     use crate::json::address::json::AddressJson;
     use crate::key_management::KeyInfo;
     use crate::lotus_json::LotusJson;
+    use crate::message::SignedMessage;
     use crate::shim::crypto::{Signature, SignatureType};
+    use crate::shim::message::Message;
 
     pub const WALLET_BALANCE: &str = "Filecoin.WalletBalance";
     pub type WalletBalanceParams = (String,);
@@ -321,6 +576,10 @@ pub mod wallet_api {
     pub type WalletSignParams = (AddressJson, Vec<u8>);
     pub type WalletSignResult = LotusJson<Signature>;
 
+    pub const WALLET_SIGN_MESSAGE: &str = "Filecoin.WalletSignMessage";
+    pub type WalletSignMessageParams = (AddressJson, LotusJson<Message>);
+    pub type WalletSignMessageResult = LotusJson<SignedMessage>;
+
     pub const WALLET_VERIFY: &str = "Filecoin.WalletVerify";
     pub type WalletVerifyParams = (AddressJson, Vec<u8>, LotusJson<Signature>);
     pub type WalletVerifyResult = bool;
@@ -330,23 +589,32 @@ pub mod wallet_api {
 pub mod state_api {
     use std::path::PathBuf;
 
-    use crate::blocks::TipsetKeys;
     use crate::json::{address::json::AddressJson, cid::CidJson};
     use crate::lotus_json::LotusJson;
     use crate::shim::executor::Receipt;
     use crate::shim::message::Message;
-    use crate::shim::{state_tree::ActorState, version::NetworkVersion};
-    use crate::state_manager::{InvocResult, MarketBalance};
+    use crate::shim::{
+        address::Address, clock::ChainEpoch, deal::DealID, state_tree::ActorState,
+        version::NetworkVersion,
+    };
+    use crate::state_manager::{
+        actor_changes::ActorChange, actor_trace::TracedTipset, diff::StateDiff,
+        event_index::IndexedEvent, forensics::StateMismatchReport, rewards::MinerReward,
+        watch_list::WatchEvent, InvocResult, MarketBalance,
+    };
     use ahash::HashMap;
 
-    use crate::rpc_api::data_types::{MarketDeal, MessageLookup};
+    use crate::rpc_api::{
+        data_types::{ActorReadState, MarketDeal, MessageLookup},
+        tipset_selector::TipsetSelector,
+    };
 
     pub const STATE_CALL: &str = "Filecoin.StateCall";
-    pub type StateCallParams = (LotusJson<Message>, LotusJson<TipsetKeys>);
+    pub type StateCallParams = (LotusJson<Message>, LotusJson<TipsetSelector>);
     pub type StateCallResult = InvocResult;
 
     pub const STATE_REPLAY: &str = "Filecoin.StateReplay";
-    pub type StateReplayParams = (CidJson, LotusJson<TipsetKeys>);
+    pub type StateReplayParams = (CidJson, LotusJson<TipsetSelector>);
     pub type StateReplayResult = InvocResult;
 
     pub const STATE_NETWORK_NAME: &str = "Filecoin.StateNetworkName";
@@ -355,23 +623,23 @@ pub mod state_api {
     pub type StateNetworkNameResult = String;
 
     pub const STATE_NETWORK_VERSION: &str = "Filecoin.StateNetworkVersion";
-    pub type StateNetworkVersionParams = (LotusJson<TipsetKeys>,);
+    pub type StateNetworkVersionParams = (LotusJson<TipsetSelector>,);
     pub type StateNetworkVersionResult = NetworkVersion;
 
     pub const STATE_GET_ACTOR: &str = "Filecoin.StateGetActor";
-    pub type StateGetActorParams = (AddressJson, LotusJson<TipsetKeys>);
+    pub type StateGetActorParams = (AddressJson, LotusJson<TipsetSelector>);
     pub type StateGetActorResult = LotusJson<Option<ActorState>>;
 
     pub const STATE_MARKET_BALANCE: &str = "Filecoin.StateMarketBalance";
-    pub type StateMarketBalanceParams = (AddressJson, LotusJson<TipsetKeys>);
+    pub type StateMarketBalanceParams = (AddressJson, LotusJson<TipsetSelector>);
     pub type StateMarketBalanceResult = MarketBalance;
 
     pub const STATE_MARKET_DEALS: &str = "Filecoin.StateMarketDeals";
-    pub type StateMarketDealsParams = (LotusJson<TipsetKeys>,);
+    pub type StateMarketDealsParams = (LotusJson<TipsetSelector>,);
     pub type StateMarketDealsResult = HashMap<String, MarketDeal>;
 
     pub const STATE_GET_RECEIPT: &str = "Filecoin.StateGetReceipt";
-    pub type StateGetReceiptParams = (CidJson, LotusJson<TipsetKeys>);
+    pub type StateGetReceiptParams = (CidJson, LotusJson<TipsetSelector>);
     pub type StateGetReceiptResult = LotusJson<Receipt>;
 
     pub const STATE_WAIT_MSG: &str = "Filecoin.StateWaitMsg";
@@ -381,6 +649,77 @@ pub mod state_api {
     pub const STATE_FETCH_ROOT: &str = "Filecoin.StateFetchRoot";
     pub type StateFetchRootParams = (CidJson, Option<PathBuf>);
     pub type StateFetchRootResult = String;
+
+    pub const STATE_DIFF: &str = "Filecoin.StateDiff";
+    pub type StateDiffParams = (LotusJson<TipsetSelector>, LotusJson<TipsetSelector>);
+    pub type StateDiffResult = StateDiff;
+
+    pub const STATE_MISMATCH_REPORTS: &str = "Filecoin.StateMismatchReports";
+    pub type StateMismatchReportsParams = ();
+    pub type StateMismatchReportsResult = Vec<StateMismatchReport>;
+
+    pub const STATE_MINER_REWARDS: &str = "Filecoin.StateMinerRewards";
+    /// Miner, first and last epoch.
+    pub type StateMinerRewardsParams = (AddressJson, ChainEpoch, ChainEpoch);
+    pub type StateMinerRewardsResult = Vec<MinerReward>;
+
+    pub const STATE_ACTOR_EVENTS: &str = "Filecoin.StateActorEvents";
+    /// Emitter, first and last epoch, and optional topic.
+    pub type StateActorEventsParams = (
+        AddressJson,
+        ChainEpoch,
+        ChainEpoch,
+        LotusJson<Option<Vec<u8>>>,
+    );
+    pub type StateActorEventsResult = Vec<IndexedEvent>;
+
+    pub const STATE_LIST_ACTORS: &str = "Filecoin.StateListActors";
+    pub type StateListActorsParams = (LotusJson<TipsetSelector>,);
+    pub type StateListActorsResult = LotusJson<Vec<Address>>;
+
+    /// State of an actor, rendered and decoded. Unlike Lotus'
+    /// `Filecoin.StateReadState`, the state is returned as an IPLD rendering
+    /// along with its decoding, hence the `Forest.` namespace.
+    pub const STATE_READ_STATE: &str = "Forest.StateReadState";
+    pub type StateReadStateParams = (AddressJson, LotusJson<TipsetSelector>);
+    pub type StateReadStateResult = ActorReadState;
+
+    /// Deal IDs allocated by a `PublishStorageDeals` message, from the deal
+    /// index.
+    pub const STATE_GET_DEAL_IDS_FOR_MESSAGE: &str = "Filecoin.StateGetDealIdsForMessage";
+    pub type StateGetDealIdsForMessageParams = (CidJson,);
+    pub type StateGetDealIdsForMessageResult = Vec<DealID>;
+
+    /// Recent calls of a traced actor, oldest first.
+    pub const STATE_ACTOR_TRACE: &str = "Filecoin.StateActorTrace";
+    pub type StateActorTraceParams = (AddressJson,);
+    pub type StateActorTraceResult = Vec<TracedTipset>;
+
+    /// Recent changes of an address of the watch-list, oldest first.
+    pub const WATCH_LIST_HISTORY: &str = "Filecoin.WatchListHistory";
+    pub type WatchListHistoryParams = (AddressJson,);
+    pub type WatchListHistoryResult = Vec<WatchEvent>;
+
+    /// Subscribes to the changes of the addresses of the watch-list. Only
+    /// available over websockets, like
+    /// [`CHAIN_NOTIFY`](super::chain_api::CHAIN_NOTIFY). Subscribers that
+    /// fall behind miss changes, which they can find with
+    /// [`WATCH_LIST_HISTORY`].
+    pub const WATCH_LIST_NOTIFY: &str = "Filecoin.WatchListNotify";
+    pub type WatchListNotifyValue = WatchEvent;
+
+    /// Actors created in the canonical tipsets of a range of epochs, from the
+    /// actor change index.
+    pub const STATE_LIST_NEW_ACTORS: &str = "Filecoin.StateListNewActors";
+    /// First and last epoch.
+    pub type StateListNewActorsParams = (ChainEpoch, ChainEpoch);
+    pub type StateListNewActorsResult = Vec<ActorChange>;
+
+    /// Subscribes to the actors created and deleted by the tipsets executed
+    /// from now on. Only available over websockets, like
+    /// [`WATCH_LIST_NOTIFY`].
+    pub const STATE_ACTOR_CHANGES_NOTIFY: &str = "Filecoin.StateActorChangesNotify";
+    pub type StateActorChangesNotifyValue = ActorChange;
 }
 
 /// Gas API
@@ -413,6 +752,46 @@ pub mod gas_api {
     pub type GasEstimateMessageGasResult = LotusJson<Message>;
 }
 
+/// Eth API. Methods are also served under their names in the Ethereum
+/// JSON-RPC specification, e.g. `eth_getCode`.
+pub mod eth_api {
+    use crate::rpc_api::eth_types::{EthAddress, EthBlock, EthBytes, EthTransactionArgs, EthWord};
+
+    pub const ETH_GET_CODE: &str = "Filecoin.EthGetCode";
+    pub const ETH_GET_CODE_ALIAS: &str = "eth_getCode";
+    pub type EthGetCodeParams = (EthAddress, EthBlock);
+    pub type EthGetCodeResult = EthBytes;
+
+    pub const ETH_GET_STORAGE_AT: &str = "Filecoin.EthGetStorageAt";
+    pub const ETH_GET_STORAGE_AT_ALIAS: &str = "eth_getStorageAt";
+    /// Contract, storage position and block.
+    pub type EthGetStorageAtParams = (EthAddress, EthWord, EthBlock);
+    pub type EthGetStorageAtResult = EthWord;
+
+    pub const ETH_ACCOUNTS: &str = "Filecoin.EthAccounts";
+    pub const ETH_ACCOUNTS_ALIAS: &str = "eth_accounts";
+    pub type EthAccountsResult = Vec<EthAddress>;
+
+    /// Signs `EIP-191` personal messages, with Ethereum signatures.
+    pub const PERSONAL_SIGN: &str = "personal_sign";
+    /// Message and signer.
+    pub type PersonalSignParams = (EthBytes, EthAddress);
+    pub type PersonalSignResult = EthBytes;
+
+    /// Signs `EIP-712` typed data, given as an object or as its JSON string.
+    pub const ETH_SIGN_TYPED_DATA: &str = "eth_signTypedData";
+    pub const ETH_SIGN_TYPED_DATA_V4: &str = "eth_signTypedData_v4";
+    /// Signer and typed data.
+    pub type EthSignTypedDataParams = (EthAddress, serde_json::Value);
+    pub type EthSignTypedDataResult = EthBytes;
+
+    pub const ETH_SEND_TRANSACTION: &str = "Filecoin.EthSendTransaction";
+    pub const ETH_SEND_TRANSACTION_ALIAS: &str = "eth_sendTransaction";
+    pub type EthSendTransactionParams = (EthTransactionArgs,);
+    /// Transaction hash.
+    pub type EthSendTransactionResult = EthWord;
+}
+
 /// Common API
 pub mod common_api {
     use chrono::Utc;
@@ -435,9 +814,14 @@ pub mod common_api {
 
 /// Net API
 pub mod net_api {
+    use std::collections::BTreeMap;
+
     use serde::{Deserialize, Serialize};
 
+    use crate::blocks::TipsetKeys;
     use crate::rpc_api::data_types::AddrInfo;
+    use crate::shim::clock::ChainEpoch;
+    use num::BigInt;
 
     pub const NET_ADDRS_LISTEN: &str = "Filecoin.NetAddrsListen";
     pub type NetAddrsListenParams = ();
@@ -481,6 +865,167 @@ pub mod net_api {
     pub const NET_DISCONNECT: &str = "Filecoin.NetDisconnect";
     pub type NetDisconnectParams = (String,);
     pub type NetDisconnectResult = ();
+
+    pub const NET_PEER_HEADS: &str = "Filecoin.NetPeerHeads";
+    pub type NetPeerHeadsParams = ();
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct PeerHeadInfo {
+        pub peer: String,
+        #[serde(with = "crate::lotus_json")]
+        pub tipset_keys: TipsetKeys,
+        pub epoch: ChainEpoch,
+        #[serde(with = "crate::lotus_json")]
+        pub weight: BigInt,
+        pub updated: chrono::DateTime<chrono::Utc>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct NetworkHeadInfo {
+        #[serde(with = "crate::lotus_json")]
+        pub tipset_keys: TipsetKeys,
+        pub epoch: ChainEpoch,
+        #[serde(with = "crate::lotus_json")]
+        pub weight: BigInt,
+        /// Number of peers advertising this head.
+        pub peers: usize,
+        /// Number of peers that advertised a head recently.
+        pub total_peers: usize,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct NetPeerHeadsResult {
+        pub peers: Vec<PeerHeadInfo>,
+        /// The head most recently advertised by the most peers.
+        pub network_head: Option<NetworkHeadInfo>,
+        /// Whether a majority of peers agree on a head that is not part of the
+        /// local chain.
+        pub minority_fork: bool,
+    }
+
+    /// Returns the number of connected peers running each client version.
+    pub const NET_AGENT_VERSIONS: &str = "Filecoin.NetAgentVersions";
+    pub type NetAgentVersionsParams = ();
+    pub type NetAgentVersionsResult = Vec<AgentVersionCount>;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct AgentVersionCount {
+        pub client: String,
+        /// Version without the build metadata, if it could be parsed.
+        pub version: Option<String>,
+        pub peers: usize,
+        /// Number of peers that are deprioritized by the agent rules of the
+        /// node.
+        pub deprioritized: usize,
+    }
+
+    /// Returns the connection, client and score of each connected peer.
+    pub const NET_PEER_DETAILS: &str = "Filecoin.NetPeerDetails";
+    pub type NetPeerDetailsParams = ();
+    pub type NetPeerDetailsResult = Vec<PeerDetailsInfo>;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct PeerDetailsInfo {
+        pub peer: String,
+        pub address: String,
+        /// `inbound` if the peer dialed the node, `outbound` otherwise.
+        pub direction: String,
+        /// Transport of the connection, e.g. `tcp` or `quic-v1`.
+        pub transport: String,
+        pub protocols: Vec<String>,
+        pub agent: Option<String>,
+        pub deprioritized: bool,
+        pub score: i64,
+        /// Round-trip time of the latest ping, in milliseconds.
+        pub latency_ms: Option<u64>,
+        pub gossip_bytes_received: u64,
+        pub connected_since: chrono::DateTime<chrono::Utc>,
+    }
+
+    /// Returns the outcomes of the requests sent to each peer, by protocol
+    /// and by day, for the days kept by the node.
+    pub const NET_PROTOCOL_STATS: &str = "Filecoin.NetProtocolStats";
+    pub type NetProtocolStatsParams = ();
+    pub type NetProtocolStatsResult = Vec<DailyProtocolStats>;
+
+    /// Protocol of the requests sent to peers.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum RequestProtocol {
+        ChainExchange,
+        Bitswap,
+    }
+
+    impl std::fmt::Display for RequestProtocol {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(match self {
+                RequestProtocol::ChainExchange => "chain_exchange",
+                RequestProtocol::Bitswap => "bitswap",
+            })
+        }
+    }
+
+    /// Number of requests by outcome.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct RequestOutcomes {
+        pub success: u64,
+        pub timeout: u64,
+        pub invalid: u64,
+        /// Responses with an error status.
+        #[serde(default)]
+        pub error: u64,
+    }
+
+    impl RequestOutcomes {
+        pub fn total(&self) -> u64 {
+            self.success + self.timeout + self.invalid + self.error
+        }
+
+        pub fn add(&mut self, other: &RequestOutcomes) {
+            self.success += other.success;
+            self.timeout += other.timeout;
+            self.invalid += other.invalid;
+            self.error += other.error;
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct DailyProtocolStats {
+        /// Day in UTC.
+        pub day: chrono::NaiveDate,
+        pub peer: String,
+        pub protocol: RequestProtocol,
+        #[serde(flatten)]
+        pub outcomes: RequestOutcomes,
+    }
+
+    pub const NET_PUBSUB_STATS: &str = "Filecoin.NetPubsubStats";
+    pub type NetPubsubStatsParams = ();
+    pub type NetPubsubStatsResult = Vec<PubsubTopicStats>;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct PubsubTopicStats {
+        pub topic: String,
+        pub mesh_peers: usize,
+        pub received: u64,
+        pub published: u64,
+        /// Number of rejected messages by validation outcome.
+        pub rejected: BTreeMap<String, u64>,
+        pub received_last_minute: u64,
+        /// Recently received messages, only sampled when
+        /// `network.gossip_samples` is set in the configuration.
+        pub samples: Vec<PubsubSample>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    pub struct PubsubSample {
+        pub source: String,
+        pub received: chrono::DateTime<chrono::Utc>,
+        pub size: usize,
+        /// The decoded message in `Lotus` JSON, `null` if it could not be
+        /// decoded.
+        pub message: serde_json::Value,
+    }
 }
 
 /// DB API
@@ -510,6 +1055,13 @@ pub mod node_api {
     pub type NodeStatusParams = ();
     pub type NodeStatusResult = NodeStatus;
 
+    pub const GET_CONFIG: &str = "Forest.GetConfig";
+    pub type GetConfigResult = NodeConfig;
+
+    use std::collections::BTreeMap;
+
+    use crate::networks::Height;
+    use cid::Cid;
     use serde::{Deserialize, Serialize};
 
     #[derive(Debug, Serialize, Deserialize, Default)]
@@ -530,10 +1082,66 @@ pub mod node_api {
         pub blocks_per_tipset_last_finality: f64,
     }
 
+    #[derive(Debug, Serialize, Deserialize, Default)]
+    pub struct NodeClockStatus {
+        /// Estimated offset of the local clock in seconds, positive if it is
+        /// ahead of the network. Missing until enough blocks were received.
+        pub offset_secs: Option<i64>,
+        /// Whether the offset is large enough for blocks to be rejected.
+        pub skewed: bool,
+    }
+
     #[derive(Debug, Deserialize, Default, Serialize)]
     pub struct NodeStatus {
         pub sync_status: NodeSyncStatus,
         pub peer_status: NodePeerStatus,
         pub chain_status: NodeChainStatus,
+        #[serde(default)]
+        pub clock_status: NodeClockStatus,
+    }
+
+    /// Effective configuration of a running node.
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct NodeConfig {
+        /// Configuration, as in the configuration file, with secrets
+        /// redacted.
+        pub config: serde_json::Value,
+        pub network: NodeNetwork,
+        /// Cargo features the node was built with.
+        pub features: Vec<String>,
+        /// Experimental modes and overrides set through environment
+        /// variables, with their values.
+        pub environment: BTreeMap<String, String>,
+        pub versions: NodeVersions,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct NodeNetwork {
+        /// Network profile, e.g. `mainnet`.
+        pub chain: String,
+        /// Name of the network in its genesis block.
+        pub name: String,
+        #[serde(with = "crate::lotus_json")]
+        pub genesis: Cid,
+        /// Network version at the head of the chain.
+        pub network_version: u32,
+        /// Newest network version supported by the node.
+        pub newest_network_version: u32,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct NodeVersions {
+        pub forest: String,
+        pub fvm: Vec<String>,
+        /// Actor bundles of the network upgrades.
+        pub actor_bundles: Vec<ActorBundleVersion>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct ActorBundleVersion {
+        pub height: Height,
+        pub network_version: u32,
+        #[serde(with = "crate::lotus_json")]
+        pub manifest: Cid,
     }
 }
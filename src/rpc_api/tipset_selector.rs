@@ -0,0 +1,311 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Selection of the tipset an RPC method operates on.
+//!
+//! Every state and chain method taking a tipset accepts a [`TipsetSelector`],
+//! which is one of:
+//! - a tipset key, e.g. `[{"/": "bafy..."}]`. The empty key (`null` or `[]`)
+//!   selects the head, as in Lotus.
+//! - a tag: `"latest"`, `"finalized"` or `"safe"`, see [`TipsetTag`].
+//! - a height, e.g. `{"Height": 1000, "Anchor": "finalized"}`. The tipset is
+//!   looked up in the chain of the anchor, itself a selector, or of the head
+//!   if it's omitted. Null rounds select the tipset before them.
+
+use std::sync::Arc;
+
+use crate::blocks::{Tipset, TipsetKeys};
+use crate::chain::{index::ResolveNullTipset, ChainStore, Error};
+use crate::lotus_json::{HasLotusJson, LotusJson};
+use crate::shim::clock::ChainEpoch;
+use fvm_ipld_blockstore::Blockstore;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
+
+/// Number of epochs after which a tipset is considered safe from reorgs. Same
+/// as the `SafeEpochDelay` of Lotus.
+pub const SAFE_HEIGHT_DISTANCE: ChainEpoch = 30;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TipsetSelector {
+    Key(TipsetKeys),
+    Height {
+        height: ChainEpoch,
+        anchor: Box<TipsetSelector>,
+    },
+    Tag(TipsetTag),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(test, derive(derive_quickcheck_arbitrary::Arbitrary))]
+pub enum TipsetTag {
+    /// The head of the node.
+    Latest,
+    /// The last tipset finalized by a finality certificate, or the tipset at
+    /// the chain finality behind the head, whichever is the most recent.
+    Finalized,
+    /// Like [`TipsetTag::Finalized`], at [`SAFE_HEIGHT_DISTANCE`] behind the
+    /// head.
+    Safe,
+}
+
+impl Default for TipsetSelector {
+    fn default() -> Self {
+        Self::Tag(TipsetTag::Latest)
+    }
+}
+
+#[cfg(test)]
+impl quickcheck::Arbitrary for TipsetSelector {
+    fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+        // Anchors aren't heights, so that selectors stay shallow.
+        let anchor = |g: &mut quickcheck::Gen| match bool::arbitrary(g) {
+            true => Self::Key(TipsetKeys::arbitrary(g)),
+            false => Self::Tag(TipsetTag::arbitrary(g)),
+        };
+        match g.choose(&[0, 1, 2]) {
+            Some(0) => Self::Height {
+                height: ChainEpoch::arbitrary(g),
+                anchor: Box::new(anchor(g)),
+            },
+            _ => anchor(g),
+        }
+    }
+}
+
+impl From<TipsetKeys> for TipsetSelector {
+    fn from(key: TipsetKeys) -> Self {
+        Self::Key(key)
+    }
+}
+
+impl TipsetSelector {
+    /// The tipset at `height` in the chain of the head.
+    pub fn height(height: ChainEpoch) -> Self {
+        Self::Height {
+            height,
+            anchor: Box::default(),
+        }
+    }
+
+    /// Loads the selected tipset.
+    pub fn resolve<DB: Blockstore>(
+        &self,
+        chain_store: &ChainStore<DB>,
+        chain_finality: ChainEpoch,
+    ) -> Result<Arc<Tipset>, Error> {
+        match self {
+            Self::Key(key) => chain_store.tipset_from_keys(key),
+            Self::Height { height, anchor } => {
+                if *height < 0 {
+                    return Err(Error::Other(format!("Invalid tipset height {height}")));
+                }
+                let anchor = anchor.resolve(chain_store, chain_finality)?;
+                chain_store.chain_index.tipset_by_height(
+                    *height,
+                    anchor,
+                    ResolveNullTipset::TakeOlder,
+                )
+            }
+            Self::Tag(TipsetTag::Latest) => Ok(chain_store.heaviest_tipset()),
+            Self::Tag(TipsetTag::Finalized) => behind_head(chain_store, chain_finality),
+            Self::Tag(TipsetTag::Safe) => behind_head(chain_store, SAFE_HEIGHT_DISTANCE),
+        }
+    }
+}
+
+/// The tipset `distance` epochs behind the head, or the finalized tipset if
+/// it's more recent.
+fn behind_head<DB: Blockstore>(
+    chain_store: &ChainStore<DB>,
+    distance: ChainEpoch,
+) -> Result<Arc<Tipset>, Error> {
+    let head = chain_store.heaviest_tipset();
+    let height = (head.epoch() - distance).max(0);
+    match chain_store.finalized_tipset()? {
+        Some(finalized) if finalized.epoch() >= height => Ok(finalized),
+        _ => chain_store
+            .chain_index
+            .tipset_by_height(height, head, ResolveNullTipset::TakeOlder),
+    }
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum TipsetSelectorLotusJson {
+    Key(LotusJson<TipsetKeys>),
+    Height(HeightLotusJson),
+    Tag(TipsetTag),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase", deny_unknown_fields)]
+pub struct HeightLotusJson {
+    height: ChainEpoch,
+    #[serde(skip_serializing_if = "is_latest", default = "latest")]
+    anchor: Box<TipsetSelectorLotusJson>,
+}
+
+fn latest() -> Box<TipsetSelectorLotusJson> {
+    Box::new(TipsetSelectorLotusJson::Tag(TipsetTag::Latest))
+}
+
+fn is_latest(anchor: &TipsetSelectorLotusJson) -> bool {
+    matches!(anchor, TipsetSelectorLotusJson::Tag(TipsetTag::Latest))
+}
+
+impl<'de> Deserialize<'de> for TipsetSelectorLotusJson {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        use serde_json::Value;
+
+        let value = Value::deserialize(deserializer)?;
+        match &value {
+            Value::Null | Value::Array(_) => serde_json::from_value(value).map(Self::Key),
+            Value::Object(_) => serde_json::from_value(value).map(Self::Height),
+            Value::String(_) => serde_json::from_value(value).map(Self::Tag),
+            _ => {
+                return Err(D::Error::custom(
+                    "expected a tipset key, a height or a tag (latest, finalized or safe)",
+                ))
+            }
+        }
+        .map_err(D::Error::custom)
+    }
+}
+
+impl HasLotusJson for TipsetSelector {
+    type LotusJson = TipsetSelectorLotusJson;
+
+    fn snapshots() -> Vec<(serde_json::Value, Self)> {
+        use serde_json::json;
+
+        vec![
+            (json!(null), Self::Key(TipsetKeys::default())),
+            (
+                json!([{"/": "baeaaaaa"}]),
+                Self::Key(TipsetKeys::from(vec![cid::Cid::default()])),
+            ),
+            (json!("finalized"), Self::Tag(TipsetTag::Finalized)),
+            (json!({"Height": 10}), Self::height(10)),
+            (
+                json!({"Height": 10, "Anchor": "safe"}),
+                Self::Height {
+                    height: 10,
+                    anchor: Box::new(Self::Tag(TipsetTag::Safe)),
+                },
+            ),
+        ]
+    }
+
+    fn into_lotus_json(self) -> Self::LotusJson {
+        match self {
+            Self::Key(key) => TipsetSelectorLotusJson::Key(LotusJson(key)),
+            Self::Height { height, anchor } => TipsetSelectorLotusJson::Height(HeightLotusJson {
+                height,
+                anchor: Box::new(anchor.into_lotus_json()),
+            }),
+            Self::Tag(tag) => TipsetSelectorLotusJson::Tag(tag),
+        }
+    }
+
+    fn from_lotus_json(lotus_json: Self::LotusJson) -> Self {
+        match lotus_json {
+            TipsetSelectorLotusJson::Key(LotusJson(key)) => Self::Key(key),
+            TipsetSelectorLotusJson::Height(HeightLotusJson { height, anchor }) => Self::Height {
+                height,
+                anchor: Box::new(Self::from_lotus_json(*anchor)),
+            },
+            TipsetSelectorLotusJson::Tag(tag) => Self::Tag(tag),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::BlockHeader;
+    use crate::db::MemoryDB;
+    use crate::ipld::FrozenCids;
+    use crate::lotus_json::{assert_all_snapshots, assert_unchanged_via_json};
+    use crate::networks::ChainConfig;
+    use crate::shim::address::Address;
+    use crate::utils::db::CborStoreExt;
+    use quickcheck_macros::quickcheck;
+
+    #[test]
+    fn snapshots() {
+        assert_all_snapshots::<TipsetSelector>();
+        assert!(
+            serde_json::from_value::<LotusJson<TipsetSelector>>(serde_json::json!("earliest"))
+                .is_err()
+        );
+        assert!(
+            serde_json::from_value::<LotusJson<TipsetSelector>>(serde_json::json!(
+                {"Height": 10, "Tag": "latest"}
+            ))
+            .is_err()
+        );
+    }
+
+    #[quickcheck]
+    fn selectors_are_unchanged_via_json(selector: TipsetSelector) {
+        assert_unchanged_via_json(selector)
+    }
+
+    #[test]
+    fn selectors_resolve_in_the_chain_of_the_head() {
+        let db = Arc::new(MemoryDB::default());
+        let genesis = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .build()
+            .unwrap();
+        db.put_cbor_default(&genesis).unwrap();
+        let chain_store = ChainStore::new(
+            db.clone(),
+            db.clone(),
+            Arc::new(ChainConfig::default()),
+            genesis.clone(),
+        )
+        .unwrap();
+        // Epoch 3 is a null round.
+        let mut chain = vec![Arc::new(Tipset::from(genesis))];
+        for epoch in [1, 2, 4, 5, 6, 7] {
+            let header = BlockHeader::builder()
+                .miner_address(Address::new_id(0))
+                .parents(TipsetKeys::new(FrozenCids::from_iter(
+                    chain.last().unwrap().cids(),
+                )))
+                .epoch(epoch)
+                .build()
+                .unwrap();
+            db.put_cbor_default(&header).unwrap();
+            chain.push(Arc::new(Tipset::from(header)));
+        }
+        let head = chain.last().unwrap().clone();
+        chain_store.set_heaviest_tipset(head.clone()).unwrap();
+        let epoch = |selector: TipsetSelector| selector.resolve(&chain_store, 4).unwrap().epoch();
+
+        assert_eq!(epoch(TipsetSelector::Key(TipsetKeys::default())), 7);
+        assert_eq!(epoch(chain[2].key().clone().into()), 2);
+        assert_eq!(epoch(TipsetSelector::height(3)), 2);
+        assert_eq!(
+            epoch(TipsetSelector::Height {
+                height: 1,
+                anchor: Box::new(chain[3].key().clone().into()),
+            }),
+            1
+        );
+        assert!(TipsetSelector::height(8).resolve(&chain_store, 4).is_err());
+        assert_eq!(epoch(TipsetSelector::Tag(TipsetTag::Latest)), 7);
+        assert_eq!(epoch(TipsetSelector::Tag(TipsetTag::Finalized)), 2);
+        assert_eq!(epoch(TipsetSelector::Tag(TipsetTag::Safe)), 0);
+
+        // Finality certificates finalize tipsets before the chain finality.
+        chain_store.mark_finalized(chain[4].clone()).unwrap();
+        assert_eq!(epoch(TipsetSelector::Tag(TipsetTag::Finalized)), 5);
+        assert_eq!(epoch(TipsetSelector::Tag(TipsetTag::Safe)), 5);
+    }
+}
@@ -2,9 +2,10 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 use crate::rpc_api::chain_api::*;
+use futures::Stream;
 use jsonrpc_v2::Error;
 
-use crate::rpc_client::call;
+use crate::rpc_client::{call, subscribe};
 
 pub async fn chain_get_block(
     cid: ChainGetBlockParams,
@@ -20,6 +21,20 @@ pub async fn chain_export(
     call(CHAIN_EXPORT, params, auth_token).await
 }
 
+pub async fn chain_export_progress(
+    auth_token: &Option<String>,
+) -> Result<ChainExportProgressResult, Error> {
+    call(CHAIN_EXPORT_PROGRESS, (), auth_token).await
+}
+
+/// Streams the progress of the exports of the node, starting with the current
+/// progress.
+pub async fn chain_export_progress_notify(
+    auth_token: &Option<String>,
+) -> anyhow::Result<impl Stream<Item = anyhow::Result<ChainExportProgressNotifyValue>>> {
+    subscribe(CHAIN_EXPORT_PROGRESS_NOTIFY, (), auth_token).await
+}
+
 pub async fn chain_get_tipset_by_height(
     params: ChainGetTipsetByHeightParams,
     auth_token: &Option<String>,
@@ -37,6 +52,14 @@ pub async fn chain_head(auth_token: &Option<String>) -> Result<ChainHeadResult,
     call(CHAIN_HEAD, (), auth_token).await
 }
 
+/// Streams the head changes of the node, starting with its current head.
+pub async fn chain_notify(
+    params: ChainNotifyParams,
+    auth_token: &Option<String>,
+) -> anyhow::Result<impl Stream<Item = anyhow::Result<ChainNotifyValue>>> {
+    subscribe(CHAIN_NOTIFY, params, auth_token).await
+}
+
 pub async fn chain_get_message(
     cid: ChainGetMessageParams,
     auth_token: &Option<String>,
@@ -58,6 +81,27 @@ pub async fn chain_get_name(
     call(CHAIN_GET_NAME, params, auth_token).await
 }
 
+pub async fn chain_get_path(
+    params: ChainGetPathParams,
+    auth_token: &Option<String>,
+) -> Result<ChainGetPathResult, Error> {
+    call(CHAIN_GET_PATH, params, auth_token).await
+}
+
+pub async fn chain_get_message_inclusion_proof(
+    params: ChainGetMessageInclusionProofParams,
+    auth_token: &Option<String>,
+) -> Result<ChainGetMessageInclusionProofResult, Error> {
+    call(CHAIN_GET_MESSAGE_INCLUSION_PROOF, params, auth_token).await
+}
+
+pub async fn chain_validate(
+    params: ChainValidateParams,
+    auth_token: &Option<String>,
+) -> Result<ChainValidateResult, Error> {
+    call(CHAIN_VALIDATE, params, auth_token).await
+}
+
 pub async fn chain_set_head(
     params: ChainSetHeadParams,
     auth_token: &Option<String>,
@@ -71,3 +115,21 @@ pub async fn chain_get_min_base_fee(
 ) -> Result<ChainGetMinBaseFeeResult, Error> {
     call(CHAIN_GET_MIN_BASE_FEE, params, auth_token).await
 }
+
+pub async fn chain_pin(
+    params: ChainPinParams,
+    auth_token: &Option<String>,
+) -> Result<ChainPinResult, Error> {
+    call(CHAIN_PIN, params, auth_token).await
+}
+
+pub async fn chain_unpin(
+    params: ChainUnpinParams,
+    auth_token: &Option<String>,
+) -> Result<ChainUnpinResult, Error> {
+    call(CHAIN_UNPIN, params, auth_token).await
+}
+
+pub async fn chain_pins(auth_token: &Option<String>) -> Result<ChainPinsResult, Error> {
+    call(CHAIN_PINS, (), auth_token).await
+}
@@ -13,16 +13,32 @@ pub mod state_ops;
 pub mod sync_ops;
 pub mod wallet_ops;
 
-use std::env;
+use std::{env, path::PathBuf, sync::Arc};
 
 use crate::libp2p::{Multiaddr, Protocol};
-use crate::utils::net::global_http_client;
+use crate::rpc_api::chain_api::{CHANNEL_CLOSE, CHANNEL_VALUE};
+use crate::utils::{net::global_http_client, tls};
+use anyhow::Context as _;
+use futures::{SinkExt, Stream, StreamExt};
 use jsonrpc_v2::{Error, Id, RequestObject, V2};
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio_rustls::rustls::ClientConfig;
+use tokio_tungstenite::{
+    tungstenite::{client::IntoClientRequest, Message},
+    Connector,
+};
 use tracing::debug;
 
 pub const API_INFO_KEY: &str = "FULLNODE_API_INFO";
+/// PEM file of the certificates of the authorities trusted to serve the API
+/// over TLS, instead of the public ones, e.g. for self-signed certificates.
+pub const API_CA_KEY: &str = "FULLNODE_API_CA";
+/// PEM file of the certificate chain presented to a node that requires client
+/// certificates, along with the private key in [`API_CLIENT_KEY_KEY`].
+pub const API_CLIENT_CERT_KEY: &str = "FULLNODE_API_CLIENT_CERT";
+/// PEM file of the private key of the certificate in [`API_CLIENT_CERT_KEY`].
+pub const API_CLIENT_KEY_KEY: &str = "FULLNODE_API_CLIENT_KEY";
 pub const DEFAULT_HOST: &str = "127.0.0.1";
 pub const DEFAULT_MULTIADDRESS: &str = "/ip4/127.0.0.1/tcp/2345/http";
 pub const DEFAULT_PORT: u16 = 2345;
@@ -57,6 +73,48 @@ pub static API_INFO: Lazy<ApiInfo> = Lazy::new(|| {
     ApiInfo { multiaddr, token }
 });
 
+/// Returns the TLS configuration of the connections to the node if it is
+/// customized by [`API_CA_KEY`], [`API_CLIENT_CERT_KEY`] and
+/// [`API_CLIENT_KEY_KEY`].
+fn api_tls() -> anyhow::Result<Option<Arc<ClientConfig>>> {
+    static TLS: OnceCell<Option<Arc<ClientConfig>>> = OnceCell::new();
+    TLS.get_or_try_init(|| {
+        let var = |key| env::var_os(key).map(PathBuf::from);
+        let ca = var(API_CA_KEY);
+        let identity = match (var(API_CLIENT_CERT_KEY), var(API_CLIENT_KEY_KEY)) {
+            (Some(certificate), Some(private_key)) => Some((certificate, private_key)),
+            (None, None) => None,
+            _ => {
+                anyhow::bail!("{API_CLIENT_CERT_KEY} and {API_CLIENT_KEY_KEY} must be set together")
+            }
+        };
+        if ca.is_none() && identity.is_none() {
+            return Ok(None);
+        }
+        let config = tls::client_config(
+            ca.as_deref(),
+            identity
+                .as_ref()
+                .map(|(certificate, key)| (certificate.as_path(), key.as_path())),
+        )?;
+        Ok(Some(Arc::new(config)))
+    })
+    .cloned()
+}
+
+/// Returns the HTTP client of the API, see [`api_tls`].
+fn api_http_client() -> anyhow::Result<reqwest::Client> {
+    static CLIENT: OnceCell<reqwest::Client> = OnceCell::new();
+    CLIENT
+        .get_or_try_init(|| match api_tls()? {
+            Some(tls) => Ok(reqwest::Client::builder()
+                .use_preconfigured_tls(ClientConfig::clone(&tls))
+                .build()?),
+            None => Ok(global_http_client()),
+        })
+        .cloned()
+}
+
 /// Error object in a response
 #[derive(Deserialize)]
 pub struct JsonRpcError {
@@ -153,7 +211,8 @@ where
 
     debug!("Using JSON-RPC v2 HTTP URL: {}", api_url);
 
-    let request = global_http_client().post(api_url).json(&rpc_req);
+    let client = api_http_client().map_err(|e| Error::internal(format!("{e:#}")))?;
+    let request = client.post(api_url).json(&rpc_req);
     let request = match (API_INFO.token.as_ref(), token) {
         (Some(token), _) | (_, Some(token)) => request.header(http::header::AUTHORIZATION, token),
         _ => request,
@@ -170,3 +229,92 @@ where
         }),
     }
 }
+
+/// Notification pushed by the node on a subscription channel.
+#[derive(Deserialize)]
+struct ChannelNotification {
+    method: Option<String>,
+    #[serde(default)]
+    params: serde_json::Value,
+    error: Option<JsonRpcError>,
+}
+
+/// Utility method for subscribing to a streaming RPC method over websockets.
+/// The stream ends when the node closes the subscription channel or the
+/// connection.
+async fn subscribe<P, T>(
+    method_name: &str,
+    params: P,
+    token: &Option<String>,
+) -> anyhow::Result<impl Stream<Item = anyhow::Result<T>>>
+where
+    P: Serialize,
+    T: DeserializeOwned,
+{
+    let rpc_req = RequestObject::request()
+        .with_method(method_name)
+        .with_params(serde_json::to_value(params)?)
+        .finish();
+
+    // `https` becomes `wss`
+    let api_url = multiaddress_to_url(API_INFO.multiaddr.to_owned()).replacen("http", "ws", 1);
+
+    debug!("Using JSON-RPC v2 WS URL: {}", api_url);
+
+    let mut request = api_url.into_client_request()?;
+    if let Some(token) = API_INFO.token.as_ref().or(token.as_ref()) {
+        request
+            .headers_mut()
+            .insert(http::header::AUTHORIZATION, token.parse()?);
+    }
+    let connector = api_tls()?.map(Connector::Rustls);
+    let (mut ws, _) =
+        tokio_tungstenite::connect_async_tls_with_config(request, None, false, connector).await?;
+    ws.send(Message::Text(serde_json::to_string(&rpc_req)?))
+        .await?;
+
+    let channel_id = loop {
+        let message = ws
+            .next()
+            .await
+            .context("connection closed before the subscription started")??;
+        if let Message::Text(text) = message {
+            match serde_json::from_str::<JsonRpcResponse<u64>>(&text)? {
+                JsonRpcResponse::Result { result, .. } => break result,
+                JsonRpcResponse::Error { error, .. } => {
+                    anyhow::bail!("{} (code {})", error.message, error.code)
+                }
+            }
+        }
+    };
+
+    Ok(futures::stream::unfold(ws, move |mut ws| async move {
+        loop {
+            let text = match ws.next().await? {
+                Ok(Message::Text(text)) => text,
+                Ok(Message::Close(_)) => return None,
+                Ok(_) => continue,
+                Err(e) => return Some((Err(e.into()), ws)),
+            };
+            let notification = match serde_json::from_str::<ChannelNotification>(&text) {
+                Ok(notification) => notification,
+                Err(e) => return Some((Err(e.into()), ws)),
+            };
+            if let Some(error) = notification.error {
+                let error = anyhow::anyhow!("{} (code {})", error.message, error.code);
+                return Some((Err(error), ws));
+            }
+            match notification.method.as_deref() {
+                Some(CHANNEL_VALUE) => {
+                    match serde_json::from_value::<(u64, T)>(notification.params) {
+                        Ok((id, value)) if id == channel_id => return Some((Ok(value), ws)),
+                        Ok(_) => continue,
+                        Err(e) => return Some((Err(e.into()), ws)),
+                    }
+                }
+                Some(CHANNEL_CLOSE) => return None,
+                _ => continue,
+            }
+        }
+    }))
+}
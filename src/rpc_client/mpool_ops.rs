@@ -6,6 +6,13 @@ use jsonrpc_v2::Error;
 
 use crate::rpc_client::call;
 
+pub async fn mpool_push(
+    params: MpoolPushParams,
+    auth_token: &Option<String>,
+) -> Result<MpoolPushResult, Error> {
+    call(MPOOL_PUSH, params, auth_token).await
+}
+
 pub async fn mpool_push_message(
     params: MpoolPushMessageParams,
     auth_token: &Option<String>,
@@ -19,3 +26,42 @@ pub async fn mpool_pending(
 ) -> Result<MpoolPendingResult, Error> {
     call(MPOOL_PENDING, params, auth_token).await
 }
+
+pub async fn mpool_export(
+    params: MpoolExportParams,
+    auth_token: &Option<String>,
+) -> Result<MpoolExportResult, Error> {
+    call(MPOOL_EXPORT, params, auth_token).await
+}
+
+pub async fn mpool_import(
+    params: MpoolImportParams,
+    auth_token: &Option<String>,
+) -> Result<MpoolImportResult, Error> {
+    call(MPOOL_IMPORT, params, auth_token).await
+}
+
+pub async fn mpool_resubmit_status(
+    auth_token: &Option<String>,
+) -> Result<MpoolResubmitStatusResult, Error> {
+    call(MPOOL_RESUBMIT_STATUS, (), auth_token).await
+}
+
+pub async fn mpool_resubmit_pause(
+    auth_token: &Option<String>,
+) -> Result<MpoolResubmitPauseResult, Error> {
+    call(MPOOL_RESUBMIT_PAUSE, (), auth_token).await
+}
+
+pub async fn mpool_resubmit_resume(
+    auth_token: &Option<String>,
+) -> Result<MpoolResubmitResumeResult, Error> {
+    call(MPOOL_RESUBMIT_RESUME, (), auth_token).await
+}
+
+pub async fn mpool_resubmit_log(
+    params: MpoolResubmitLogParams,
+    auth_token: &Option<String>,
+) -> Result<MpoolResubmitLogResult, Error> {
+    call(MPOOL_RESUBMIT_LOG, params, auth_token).await
+}
@@ -40,3 +40,38 @@ pub async fn net_disconnect(
 ) -> Result<NetDisconnectResult, Error> {
     call(NET_DISCONNECT, params, auth_token).await
 }
+
+pub async fn net_peer_heads(
+    params: NetPeerHeadsParams,
+    auth_token: &Option<String>,
+) -> Result<NetPeerHeadsResult, Error> {
+    call(NET_PEER_HEADS, params, auth_token).await
+}
+
+pub async fn net_pubsub_stats(
+    params: NetPubsubStatsParams,
+    auth_token: &Option<String>,
+) -> Result<NetPubsubStatsResult, Error> {
+    call(NET_PUBSUB_STATS, params, auth_token).await
+}
+
+pub async fn net_agent_versions(
+    params: NetAgentVersionsParams,
+    auth_token: &Option<String>,
+) -> Result<NetAgentVersionsResult, Error> {
+    call(NET_AGENT_VERSIONS, params, auth_token).await
+}
+
+pub async fn net_peer_details(
+    params: NetPeerDetailsParams,
+    auth_token: &Option<String>,
+) -> Result<NetPeerDetailsResult, Error> {
+    call(NET_PEER_DETAILS, params, auth_token).await
+}
+
+pub async fn net_protocol_stats(
+    params: NetProtocolStatsParams,
+    auth_token: &Option<String>,
+) -> Result<NetProtocolStatsResult, Error> {
+    call(NET_PROTOCOL_STATS, params, auth_token).await
+}
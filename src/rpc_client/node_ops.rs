@@ -1,7 +1,9 @@
 // Copyright 2019-2023 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-use crate::rpc_api::node_api::{NodeStatusParams, NodeStatusResult, NODE_STATUS};
+use crate::rpc_api::node_api::{
+    GetConfigResult, NodeStatusParams, NodeStatusResult, GET_CONFIG, NODE_STATUS,
+};
 use jsonrpc_v2::Error;
 
 use crate::rpc_client::call;
@@ -12,3 +14,7 @@ pub async fn node_status(
 ) -> Result<NodeStatusResult, Error> {
     call(NODE_STATUS, params, auth_token).await
 }
+
+pub async fn get_config(auth_token: &Option<String>) -> Result<GetConfigResult, Error> {
+    call(GET_CONFIG, (), auth_token).await
+}
@@ -2,9 +2,10 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 use crate::rpc_api::state_api::*;
+use futures::Stream;
 use jsonrpc_v2::Error;
 
-use crate::rpc_client::call;
+use crate::rpc_client::{call, subscribe};
 
 pub async fn state_get_actor(
     params: StateGetActorParams,
@@ -19,3 +20,73 @@ pub async fn state_fetch_root(
 ) -> Result<StateFetchRootResult, Error> {
     call(STATE_FETCH_ROOT, params, auth_token).await
 }
+
+pub async fn state_diff(
+    params: StateDiffParams,
+    auth_token: &Option<String>,
+) -> Result<StateDiffResult, Error> {
+    call(STATE_DIFF, params, auth_token).await
+}
+
+pub async fn state_mismatch_reports(
+    params: StateMismatchReportsParams,
+    auth_token: &Option<String>,
+) -> Result<StateMismatchReportsResult, Error> {
+    call(STATE_MISMATCH_REPORTS, params, auth_token).await
+}
+
+pub async fn state_miner_rewards(
+    params: StateMinerRewardsParams,
+    auth_token: &Option<String>,
+) -> Result<StateMinerRewardsResult, Error> {
+    call(STATE_MINER_REWARDS, params, auth_token).await
+}
+
+pub async fn state_actor_events(
+    params: StateActorEventsParams,
+    auth_token: &Option<String>,
+) -> Result<StateActorEventsResult, Error> {
+    call(STATE_ACTOR_EVENTS, params, auth_token).await
+}
+
+pub async fn state_list_actors(
+    params: StateListActorsParams,
+    auth_token: &Option<String>,
+) -> Result<StateListActorsResult, Error> {
+    call(STATE_LIST_ACTORS, params, auth_token).await
+}
+
+pub async fn state_read_state(
+    params: StateReadStateParams,
+    auth_token: &Option<String>,
+) -> Result<StateReadStateResult, Error> {
+    call(STATE_READ_STATE, params, auth_token).await
+}
+
+pub async fn watch_list_history(
+    params: WatchListHistoryParams,
+    auth_token: &Option<String>,
+) -> Result<WatchListHistoryResult, Error> {
+    call(WATCH_LIST_HISTORY, params, auth_token).await
+}
+
+/// Streams the changes of the addresses of the watch-list of the node.
+pub async fn watch_list_notify(
+    auth_token: &Option<String>,
+) -> anyhow::Result<impl Stream<Item = anyhow::Result<WatchListNotifyValue>>> {
+    subscribe(WATCH_LIST_NOTIFY, (), auth_token).await
+}
+
+pub async fn state_list_new_actors(
+    params: StateListNewActorsParams,
+    auth_token: &Option<String>,
+) -> Result<StateListNewActorsResult, Error> {
+    call(STATE_LIST_NEW_ACTORS, params, auth_token).await
+}
+
+/// Streams the actors created and deleted by the tipsets executed by the node.
+pub async fn state_actor_changes_notify(
+    auth_token: &Option<String>,
+) -> anyhow::Result<impl Stream<Item = anyhow::Result<StateActorChangesNotifyValue>>> {
+    subscribe(STATE_ACTOR_CHANGES_NOTIFY, (), auth_token).await
+}
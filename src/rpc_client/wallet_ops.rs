@@ -69,6 +69,13 @@ pub async fn wallet_sign(
     call(WALLET_SIGN, message, auth_token).await
 }
 
+pub async fn wallet_sign_message(
+    params: WalletSignMessageParams,
+    auth_token: &Option<String>,
+) -> Result<WalletSignMessageResult, Error> {
+    call(WALLET_SIGN_MESSAGE, params, auth_token).await
+}
+
 pub async fn wallet_verify(
     message: WalletVerifyParams,
     auth_token: &Option<String>,
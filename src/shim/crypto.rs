@@ -82,17 +82,27 @@ impl Signature {
         }
     }
 
+    /// Creates a delegated Signature given the raw bytes.
+    pub fn new_delegated(bytes: Vec<u8>) -> Self {
+        Self {
+            sig_type: SignatureType::Delegated,
+            bytes,
+        }
+    }
+
     pub fn signature_type(&self) -> SignatureType {
         self.sig_type
     }
 
-    /// Checks if a signature is valid given data and address.
+    /// Checks if a signature is valid given data and address. Delegated
+    /// signatures sign Ethereum transactions, see
+    /// [`crate::message::delegated::eth_tx_signing_bytes`].
     pub fn verify(&self, data: &[u8], addr: &crate::shim::address::Address) -> Result<(), String> {
         use fvm_shared3::crypto::signature::ops::{verify_bls_sig, verify_secp256k1_sig};
         match self.sig_type {
             SignatureType::Bls => verify_bls_sig(&self.bytes, data, addr),
             SignatureType::Secp256k1 => verify_secp256k1_sig(&self.bytes, data, addr),
-            SignatureType::Delegated => Ok(()),
+            SignatureType::Delegated => verify_delegated_sig(&self.bytes, data, addr),
         }
     }
 
@@ -118,6 +128,46 @@ impl TryFrom<&Signature> for BlsSignature {
     }
 }
 
+/// Checks that the `keccak256` hash of `data` was signed by the Ethereum
+/// account of the delegated address `addr`. Signatures are `r || s || v`,
+/// with a recovery id `v` of 0 or 1.
+fn verify_delegated_sig(
+    signature: &[u8],
+    data: &[u8],
+    addr: &crate::shim::address::Address,
+) -> Result<(), String> {
+    use crate::message::delegated::{keccak256, EAM_NAMESPACE};
+    use crate::shim::address::Address;
+
+    let [rs @ .., v] = signature else {
+        return Err("Empty delegated signature".to_string());
+    };
+    if rs.len() != 64 {
+        return Err(format!(
+            "Invalid delegated signature length {}, expected 65",
+            signature.len()
+        ));
+    }
+    let sig = libsecp256k1::Signature::parse_standard_slice(rs).map_err(|e| e.to_string())?;
+    let recovery_id = libsecp256k1::RecoveryId::parse(*v).map_err(|e| e.to_string())?;
+    let public_key = libsecp256k1::recover(
+        &libsecp256k1::Message::parse(&keccak256(data)),
+        &sig,
+        &recovery_id,
+    )
+    .map_err(|e| e.to_string())?;
+    // The Ethereum address is the tail of the hash of the uncompressed key,
+    // without its `0x04` prefix.
+    let eth_addr = &keccak256(&public_key.serialize()[1..])[12..];
+    let signer = Address::new_delegated(EAM_NAMESPACE, eth_addr).map_err(|e| e.to_string())?;
+    if &signer != addr {
+        return Err(format!(
+            "Delegated signature was signed by {signer}, expected {addr}"
+        ));
+    }
+    Ok(())
+}
+
 // Forest's version of the `verify_bls_aggregate` function is semantically different
 // from the version in FVM.
 /// Aggregates and verifies BLS signatures collectively.
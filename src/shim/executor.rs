@@ -5,11 +5,12 @@ use fvm2::executor::ApplyRet as ApplyRet_v2;
 use fvm3::executor::ApplyRet as ApplyRet_v3;
 use fvm_ipld_encoding::RawBytes;
 use fvm_shared2::receipt::Receipt as Receipt_v2;
-use fvm_shared3::error::ExitCode;
+use fvm_shared3::error::{ErrorNumber, ExitCode};
+pub use fvm_shared3::event::StampedEvent;
 pub use fvm_shared3::receipt::Receipt as Receipt_v3;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::shim::econ::TokenAmount;
+use crate::shim::{address::Address, econ::TokenAmount, gas::GasCharge, state_tree::ActorID};
 
 #[derive(Clone, Debug)]
 pub enum ApplyRet {
@@ -57,6 +58,203 @@ impl ApplyRet {
             ApplyRet::V3(v3) => Receipt::V3(v3.msg_receipt.clone()),
         }
     }
+
+    /// Actor events were introduced with `FVM3`.
+    pub fn events(&self) -> Vec<StampedEvent> {
+        match self {
+            ApplyRet::V2(_) => vec![],
+            ApplyRet::V3(v3) => v3.events.clone(),
+        }
+    }
+
+    /// Execution trace of the message. It is empty unless the VM was created
+    /// with [`VMTrace::Traced`](crate::interpreter::VMTrace::Traced).
+    pub fn exec_trace(&self) -> Vec<TraceEvent> {
+        use fvm2::trace::ExecutionEvent as EV2;
+        use fvm3::trace::ExecutionEvent as EV3;
+        match self {
+            ApplyRet::V2(v2) => v2
+                .exec_trace
+                .iter()
+                .filter_map(|event| match event {
+                    EV2::GasCharge(charge) => Some(TraceEvent::GasCharge {
+                        name: charge.name.to_string(),
+                        gas: GasCharge::from(charge.clone()).total().round_up(),
+                        compute_gas: charge.compute_gas.round_up() as u64,
+                        storage_gas: charge.storage_gas.round_up() as u64,
+                    }),
+                    EV2::Call {
+                        from,
+                        to,
+                        method,
+                        params,
+                        value,
+                    } => Some(TraceEvent::Call {
+                        from: *from,
+                        to: to.into(),
+                        method: *method,
+                        value: value.into(),
+                        params_codec: cbor_codec(params),
+                        params: params.to_vec(),
+                    }),
+                    EV2::CallReturn(data) => Some(TraceEvent::CallReturn {
+                        exit_code: ExitCode::OK,
+                        return_codec: cbor_codec(data),
+                        return_data: data.to_vec(),
+                    }),
+                    EV2::CallAbort(exit_code) => Some(TraceEvent::CallReturn {
+                        exit_code: ExitCode::new(exit_code.value()),
+                        return_data: vec![],
+                        return_codec: 0,
+                    }),
+                    EV2::CallError(error) => Some(TraceEvent::CallError(CallError {
+                        message: error.0.clone(),
+                        number: error.1 as u32,
+                    })),
+                    _ => None,
+                })
+                .collect(),
+            ApplyRet::V3(v3) => v3
+                .exec_trace
+                .iter()
+                .filter_map(|event| match event {
+                    EV3::GasCharge(charge) => Some(TraceEvent::GasCharge {
+                        name: charge.name.to_string(),
+                        gas: charge.total().round_up(),
+                        compute_gas: charge.compute_gas.round_up(),
+                        storage_gas: charge.other_gas.round_up(),
+                    }),
+                    EV3::Call {
+                        from,
+                        to,
+                        method,
+                        params,
+                        value,
+                    } => Some(TraceEvent::Call {
+                        from: *from,
+                        to: to.into(),
+                        method: *method,
+                        value: value.into(),
+                        params: params
+                            .as_ref()
+                            .map(|block| block.data.clone())
+                            .unwrap_or_default(),
+                        params_codec: params.as_ref().map_or(0, |block| block.codec),
+                    }),
+                    EV3::CallReturn(exit_code, data) => Some(TraceEvent::CallReturn {
+                        exit_code: *exit_code,
+                        return_data: data
+                            .as_ref()
+                            .map(|block| block.data.clone())
+                            .unwrap_or_default(),
+                        return_codec: data.as_ref().map_or(0, |block| block.codec),
+                    }),
+                    EV3::CallError(error) => Some(TraceEvent::CallError(CallError {
+                        message: error.0.clone(),
+                        number: error.1 as u32,
+                    })),
+                    // Actor invocations are implied by the calls.
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Codec of `FVM2` parameters and return values, which are `CBOR` unless
+/// empty.
+fn cbor_codec(bytes: &RawBytes) -> u64 {
+    if bytes.is_empty() {
+        0
+    } else {
+        fvm_ipld_encoding::DAG_CBOR
+    }
+}
+
+/// Event of the execution trace of a message, independent of the FVM
+/// version. Calls are followed by the events of the callee, and end with a
+/// [`TraceEvent::CallReturn`] or a [`TraceEvent::CallError`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub enum TraceEvent {
+    /// Gas charge, with its compute and storage parts. Traces recorded before
+    /// the parts were kept have only the total.
+    #[serde(rename_all = "PascalCase")]
+    GasCharge {
+        name: String,
+        gas: u64,
+        #[serde(default)]
+        compute_gas: u64,
+        #[serde(default)]
+        storage_gas: u64,
+    },
+    /// Call, with its parameters and their IPLD codec, or no parameters and
+    /// a codec of `0`.
+    #[serde(rename_all = "PascalCase")]
+    Call {
+        from: ActorID,
+        #[serde(with = "crate::lotus_json")]
+        to: Address,
+        method: u64,
+        #[serde(with = "crate::lotus_json")]
+        value: TokenAmount,
+        #[serde(with = "crate::lotus_json", default)]
+        params: Vec<u8>,
+        #[serde(default)]
+        params_codec: u64,
+    },
+    #[serde(rename_all = "PascalCase")]
+    CallReturn {
+        exit_code: ExitCode,
+        #[serde(with = "crate::lotus_json", default)]
+        return_data: Vec<u8>,
+        #[serde(default)]
+        return_codec: u64,
+    },
+    /// The call failed before the callee could return, e.g. because it does
+    /// not exist.
+    CallError(CallError),
+}
+
+/// Syscall error that failed a call, see [`TraceEvent::CallError`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase", from = "CallErrorJson")]
+pub struct CallError {
+    pub message: String,
+    /// Value of the [`ErrorNumber`] of the error, which is the same in all
+    /// FVM versions.
+    pub number: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CallErrorJson {
+    #[serde(rename_all = "PascalCase")]
+    Error { message: String, number: u32 },
+    /// `Debug` text of the error, e.g. `SyscallError("...", NotFound)`, in the
+    /// traces recorded before the error number was kept.
+    Debug(String),
+}
+
+impl From<CallErrorJson> for CallError {
+    fn from(json: CallErrorJson) -> Self {
+        match json {
+            CallErrorJson::Error { message, number } => Self { message, number },
+            CallErrorJson::Debug(message) => {
+                let number = [
+                    (", InsufficientFunds)", ErrorNumber::InsufficientFunds),
+                    (", NotFound)", ErrorNumber::NotFound),
+                ]
+                .into_iter()
+                .find(|(suffix, _)| message.ends_with(suffix))
+                .map_or(ErrorNumber::AssertionFailed, |(_, number)| number);
+                Self {
+                    message,
+                    number: number as u32,
+                }
+            }
+        }
+    }
 }
 
 #[derive(PartialEq, Clone, Debug)]
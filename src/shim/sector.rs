@@ -1,7 +1,6 @@
 // Copyright 2019-2023 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-use crate::shim::version::NetworkVersion;
 use fvm_ipld_encoding::repr::{Deserialize_repr, Serialize_repr};
 use fvm_shared2::sector::{
     RegisteredPoStProof as RegisteredPoStProofV2, RegisteredSealProof as RegisteredSealProofV2,
@@ -49,15 +48,6 @@ pub type SectorNumber = fvm_shared3::sector::SectorNumber;
 )]
 pub struct RegisteredSealProof(RegisteredSealProofV3);
 
-impl RegisteredSealProof {
-    pub fn from_sector_size(size: SectorSize, network_version: NetworkVersion) -> Self {
-        RegisteredSealProof(RegisteredSealProofV3::from_sector_size(
-            size.into(),
-            network_version.into(),
-        ))
-    }
-}
-
 impl Deref for RegisteredSealProof {
     type Target = RegisteredSealProofV3;
     fn deref(&self) -> &Self::Target {
@@ -0,0 +1,403 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Index of the actors created and deleted when executing tipsets, so that
+//! explorers can track deployments without diffing the state trees of every
+//! tipset.
+//!
+//! The created and deleted actors are found by diffing the actors of the
+//! parent state and of the resulting state, see [`diff_state_trees`], which
+//! only visits the parts of the state trees that changed. Created actors are
+//! attributed to the message that created them: `Exec` messages to the init
+//! actor, `Create` messages to the Ethereum address manager, or messages to
+//! new addresses. Deleted actors are attributed to the message sent to them.
+//! Actors created or deleted by internal calls, e.g. the EVM `CREATE` opcode,
+//! are found without their message.
+//!
+//! The changes are stored in the [index store](super::index_store) keyed by
+//! epoch, and published to subscribers as tipsets are executed. Like the
+//! [event index](super::event_index), only tipsets executed by this node are
+//! indexed, as long as the state roots of their epochs, and queries are
+//! restricted to canonical tipsets.
+
+use std::ops::RangeInclusive;
+
+use crate::blocks::{Tipset, TipsetKeys};
+use crate::chain::index::ResolveNullTipset;
+use crate::message::ChainMessage;
+use crate::shim::{
+    address::{Address, Protocol},
+    clock::ChainEpoch,
+    executor::ApplyRet,
+    state_tree::{ActorID, StateTree},
+};
+use crate::state_manager::diff::{diff_state_trees, ActorChange as StateChange};
+use crate::state_manager::index_store::{self, IndexBatch};
+use crate::state_manager::StateManager;
+use ahash::HashMap;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use serde::{Deserialize, Serialize};
+
+/// Number of changes buffered for the subscribers.
+pub(super) const ACTOR_CHANGES_CAP: usize = 256;
+
+/// `Exec` and `Exec4` methods of the init actor.
+const INIT_EXEC_METHODS: [u64; 2] = [2, 3];
+/// `Create`, `Create2` and `CreateExternal` methods of the Ethereum address
+/// manager.
+const EAM_CREATE_METHODS: [u64; 3] = [2, 3, 4];
+
+fn actor_changes_key(epoch: ChainEpoch) -> String {
+    index_store::index_key(format_args!("actor_changes/{epoch}"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActorChangeKind {
+    Created,
+    Deleted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ActorChange {
+    pub epoch: ChainEpoch,
+    #[serde(with = "crate::lotus_json")]
+    pub tipset: TipsetKeys,
+    pub kind: ActorChangeKind,
+    pub actor: ActorID,
+    #[serde(with = "crate::lotus_json")]
+    pub code: Cid,
+    /// Key address of the actor, if known.
+    #[serde(with = "crate::lotus_json")]
+    pub address: Option<Address>,
+    /// Message that created or deleted the actor, if known.
+    #[serde(with = "crate::lotus_json")]
+    pub message: Option<Cid>,
+}
+
+/// Collects the actors created and the receivers of the messages of a tipset
+/// as they are applied.
+#[derive(Debug, Default)]
+pub(super) struct ActorChangeCollector {
+    /// Actors whose ID and key address are returned by a message.
+    created: Vec<(Cid, ActorID, Option<Address>)>,
+    /// Receivers of the successful messages.
+    receivers: Vec<(Cid, Address)>,
+}
+
+impl ActorChangeCollector {
+    pub fn observe(&mut self, cid: &Cid, message: &ChainMessage, apply_ret: &ApplyRet) {
+        let message = message.message();
+        let receipt = apply_ret.msg_receipt();
+        if !receipt.exit_code().is_success() {
+            return;
+        }
+        self.receivers.push((*cid, message.to));
+        // The return values have the same layout in every actors version.
+        let return_data = receipt.return_data();
+        let created = if message.to == Address::INIT_ACTOR
+            && INIT_EXEC_METHODS.contains(&message.method_num)
+        {
+            fvm_ipld_encoding::from_slice::<(Address, Address)>(&return_data)
+                .map_err(anyhow::Error::from)
+                .and_then(|(id, robust)| Ok((id.id()?, Some(robust))))
+        } else if message.to == Address::ETHEREUM_ACCOUNT_MANAGER_ACTOR
+            && EAM_CREATE_METHODS.contains(&message.method_num)
+        {
+            fvm_ipld_encoding::from_slice::<(ActorID, Option<Address>, serde::de::IgnoredAny)>(
+                &return_data,
+            )
+            .map(|(id, robust, _)| (id, robust))
+            .map_err(anyhow::Error::from)
+        } else {
+            return;
+        };
+        match created {
+            Ok((id, robust)) => self.created.push((*cid, id, robust)),
+            Err(e) => tracing::warn!("Failed to decode the actor created by message {cid}: {e}"),
+        }
+    }
+}
+
+impl<DB> StateManager<DB>
+where
+    DB: Blockstore,
+{
+    pub(super) fn index_actor_changes(
+        &self,
+        tipset: &Tipset,
+        collector: ActorChangeCollector,
+        state_root: Cid,
+    ) -> anyhow::Result<()> {
+        let parent = StateTree::new_from_root(self.blockstore_owned(), tipset.parent_state())?;
+        let state = StateTree::new_from_root(self.blockstore_owned(), &state_root)?;
+        let mut created = vec![];
+        let mut deleted = vec![];
+        for actor in diff_state_trees(&self.blockstore_owned(), tipset.parent_state(), &state_root)?
+        {
+            match (actor.change, actor.from, actor.to) {
+                (StateChange::Created, _, Some(to)) => created.push((actor.address.id()?, to)),
+                (StateChange::Deleted, Some(from), _) => deleted.push((actor.address.id()?, from)),
+                _ => {}
+            }
+        }
+        created.sort_by_key(|(id, _)| *id);
+        deleted.sort_by_key(|(id, _)| *id);
+
+        let mut origins: HashMap<ActorID, (Cid, Option<Address>)> = collector
+            .created
+            .into_iter()
+            .map(|(message, id, robust)| (id, (message, robust)))
+            .collect();
+        let mut deletions: HashMap<ActorID, (Cid, Address)> = HashMap::default();
+        for (message, to) in &collector.receivers {
+            if let Some(id) = state.lookup_id(to)? {
+                if created.iter().any(|(created, _)| *created == id) {
+                    let robust = (to.protocol() != Protocol::ID).then_some(*to);
+                    origins.entry(id).or_insert((*message, robust));
+                }
+            }
+            if let Some(id) = parent.lookup_id(to)? {
+                deletions.entry(id).or_insert((*message, *to));
+            }
+        }
+
+        let change = |kind, actor, code, address, message| ActorChange {
+            epoch: tipset.epoch(),
+            tipset: tipset.key().clone(),
+            kind,
+            actor,
+            code,
+            address,
+            message,
+        };
+        let mut changes = vec![];
+        for (id, actor) in created {
+            let (message, robust) = match origins.remove(&id) {
+                Some((message, robust)) => (Some(message), robust),
+                None => (None, None),
+            };
+            let address = robust.or(actor.delegated_address.map(Address::from));
+            changes.push(change(
+                ActorChangeKind::Created,
+                id,
+                actor.code,
+                address,
+                message,
+            ));
+        }
+        for (id, actor) in deleted {
+            let (message, address) = match deletions.remove(&id) {
+                Some((message, to)) if to.protocol() != Protocol::ID => (Some(message), Some(to)),
+                Some((message, _)) => (Some(message), actor.delegated_address.map(Address::from)),
+                None => (None, actor.delegated_address.map(Address::from)),
+            };
+            changes.push(change(
+                ActorChangeKind::Deleted,
+                id,
+                actor.code,
+                address,
+                message,
+            ));
+        }
+
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let mut batch = IndexBatch::default();
+        batch.push(actor_changes_key(tipset.epoch()), tipset, &changes)?;
+        self.indices.write(batch);
+        for change in changes {
+            // There may be no subscriber.
+            let _ = self.actor_changes.send(change);
+        }
+        Ok(())
+    }
+
+    /// Returns the indexed actor changes of the canonical tipsets of `epochs`,
+    /// at most [`index_store::MAX_INDEX_QUERY_EPOCHS`].
+    pub fn actor_changes(
+        &self,
+        epochs: RangeInclusive<ChainEpoch>,
+    ) -> anyhow::Result<Vec<ActorChange>> {
+        index_store::ensure_query_range(&epochs)?;
+        let chain_index = &self.chain_store().chain_index;
+        let heaviest = self.chain_store().heaviest_tipset();
+        let end = chain_index.tipset_by_height(
+            (*epochs.end()).min(heaviest.epoch()),
+            heaviest,
+            ResolveNullTipset::TakeOlder,
+        )?;
+        let settings = self.chain_store().settings();
+        let mut changes = vec![];
+        for tipset in chain_index
+            .chain(end)
+            .take_while(|tipset| tipset.epoch() >= *epochs.start())
+        {
+            let entries = index_store::read_entries::<Vec<ActorChange>>(
+                settings.as_ref(),
+                &actor_changes_key(tipset.epoch()),
+            )?;
+            changes.extend(
+                entries
+                    .into_iter()
+                    .filter(|entry| &entry.tipset == tipset.key())
+                    .flat_map(|entry| entry.value)
+                    .rev(),
+            );
+        }
+        changes.reverse();
+        Ok(changes)
+    }
+
+    /// Publisher of the actor changes of the executed tipsets.
+    pub fn actor_change_publisher(&self) -> &tokio::sync::broadcast::Sender<ActorChange> {
+        &self.actor_changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::blocks::BlockHeader;
+    use crate::chain::ChainStore;
+    use crate::db::MemoryDB;
+    use crate::networks::ChainConfig;
+    use crate::shim::{
+        message::Message,
+        state_tree::{ActorState, StateTreeVersion},
+    };
+    use crate::utils::db::CborStoreExt;
+    use fil_actor_interface::KNOWN_CIDS;
+    use fvm3::executor::ApplyRet as ApplyRet_v3;
+    use fvm_shared3::receipt::Receipt;
+
+    fn apply_ret(return_data: Vec<u8>) -> ApplyRet {
+        ApplyRet_v3 {
+            msg_receipt: Receipt {
+                exit_code: fvm_shared3::error::ExitCode::OK,
+                return_data: return_data.into(),
+                gas_used: 0,
+                events_root: None,
+            },
+            penalty: Default::default(),
+            miner_tip: Default::default(),
+            base_fee_burn: Default::default(),
+            over_estimation_burn: Default::default(),
+            refund: Default::default(),
+            gas_refund: 0,
+            gas_burned: 0,
+            failure_info: None,
+            exec_trace: vec![],
+            events: vec![],
+        }
+        .into()
+    }
+
+    /// Returns the root of a state tree holding `actors` and an init actor
+    /// allocating `next_id`.
+    fn state(db: &Arc<MemoryDB>, next_id: ActorID, actors: &[ActorID]) -> Cid {
+        let mut init = fil_actor_init_state::v11::State::new(db.as_ref(), "test".into()).unwrap();
+        init.next_id = next_id;
+        let mut tree = StateTree::new(Arc::clone(db), StateTreeVersion::V5).unwrap();
+        tree.set_actor(
+            &Address::INIT_ACTOR,
+            ActorState::new_empty(KNOWN_CIDS.actor.init.v11.mainnet, None),
+        )
+        .unwrap();
+        let mut init_actor = tree.get_actor(&Address::INIT_ACTOR).unwrap().unwrap();
+        init_actor.state = db.put_cbor_default(&init).unwrap();
+        tree.set_actor(&Address::INIT_ACTOR, init_actor).unwrap();
+        for id in actors {
+            tree.set_actor(
+                &Address::new_id(*id),
+                ActorState::new_empty(Cid::default(), None),
+            )
+            .unwrap();
+        }
+        tree.flush().unwrap()
+    }
+
+    #[test]
+    fn created_and_deleted_actors_are_indexed() {
+        let db = Arc::new(MemoryDB::default());
+        let genesis = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .timestamp(7777)
+            .state_root(state(&db, 100, &[50, 60, 70]))
+            .build()
+            .unwrap();
+        crate::chain::persist_objects(&db, &[&genesis]).unwrap();
+        let chain_config = Arc::new(ChainConfig::default());
+        let chain_store = Arc::new(
+            ChainStore::new(
+                db.clone(),
+                db.clone(),
+                chain_config.clone(),
+                genesis.clone(),
+            )
+            .unwrap(),
+        );
+        let state_manager = StateManager::new(chain_store, chain_config).unwrap();
+        let mut changes = state_manager.actor_change_publisher().subscribe();
+        let genesis = Tipset::from(genesis);
+
+        let message = |to: Address, method_num| {
+            let message = ChainMessage::Unsigned(Message {
+                from: Address::new_id(60),
+                to,
+                method_num,
+                ..Default::default()
+            });
+            (message.cid().unwrap(), message)
+        };
+        let robust = Address::new_actor(b"robust");
+        let (exec, exec_message) = message(Address::INIT_ACTOR, 2);
+        let (create, create_message) = message(Address::ETHEREUM_ACCOUNT_MANAGER_ACTOR, 4);
+        let (delete, delete_message) = message(Address::new_id(50), 0);
+        let mut collector = ActorChangeCollector::default();
+        collector.observe(
+            &exec,
+            &exec_message,
+            &apply_ret(fvm_ipld_encoding::to_vec(&(Address::new_id(100), robust)).unwrap()),
+        );
+        let eth_address = fvm_ipld_encoding::RawBytes::new(vec![0; 20]);
+        collector.observe(
+            &create,
+            &create_message,
+            &apply_ret(fvm_ipld_encoding::to_vec(&(101, Some(robust), eth_address)).unwrap()),
+        );
+        collector.observe(&delete, &delete_message, &apply_ret(vec![]));
+        // Actor 102 is created, and actor 70 deleted, by internal calls.
+        let state_root = state(&db, 103, &[60, 100, 101, 102]);
+        state_manager
+            .index_actor_changes(&genesis, collector, state_root)
+            .unwrap();
+        state_manager.flush_indices();
+
+        let change = |kind, actor, address, message| ActorChange {
+            epoch: 0,
+            tipset: genesis.key().clone(),
+            kind,
+            actor,
+            code: Cid::default(),
+            address,
+            message,
+        };
+        let expected = vec![
+            change(ActorChangeKind::Created, 100, Some(robust), Some(exec)),
+            change(ActorChangeKind::Created, 101, Some(robust), Some(create)),
+            change(ActorChangeKind::Created, 102, None, None),
+            change(ActorChangeKind::Deleted, 50, None, Some(delete)),
+            change(ActorChangeKind::Deleted, 70, None, None),
+        ];
+        assert_eq!(state_manager.actor_changes(0..=0).unwrap(), expected);
+        assert!(state_manager.actor_changes(1..=2).unwrap().is_empty());
+        for change in expected {
+            assert_eq!(changes.try_recv().unwrap(), change);
+        }
+    }
+}
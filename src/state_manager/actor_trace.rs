@@ -0,0 +1,411 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Tracing of the calls to actors, so that developers can watch their
+//! contracts live instead of replaying tipsets.
+//!
+//! With actors to trace configured, tipsets are executed with tracing enabled,
+//! see [`ExecutionCallback::traced`]. Every message that calls a traced actor,
+//! directly or through an internal send, is recorded with its decoded
+//! parameters and return value and the sends it made. The change of the state
+//! of the actor is recorded once per tipset, as the state between messages
+//! isn't kept. The calls of the last tipsets are kept for every actor in the
+//! settings store.
+//!
+//! Actors are matched by their configured address and by their ID address,
+//! once it's resolved: at startup, or after the first tipset executed after
+//! the creation of the actor.
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::blocks::{Tipset, TipsetKeys};
+use crate::db::SettingsStoreExt;
+use crate::ipld::{json::IpldJson, Ipld};
+use crate::message::{ChainMessage, Message as MessageTrait};
+use crate::shim::{
+    address::{Address, StrictAddress},
+    clock::ChainEpoch,
+    econ::TokenAmount,
+    executor::{ApplyRet, TraceEvent},
+};
+use crate::state_manager::{
+    callbacks::{ExecutionCallback, TipsetExecution},
+    diff::ActorDiff,
+    StateManager,
+};
+use ahash::HashMap;
+use anyhow::Context as _;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+fn actor_trace_key(address: &Address) -> String {
+    format!("/actor_trace/{address}")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+#[cfg_attr(test, derive(derive_quickcheck_arbitrary::Arbitrary))]
+pub struct ActorTraceConfig {
+    /// Traced actors. No actors disables the tracing.
+    pub actors: Vec<String>,
+    /// Number of tipsets whose calls are kept for every actor.
+    #[cfg_attr(test, arbitrary(gen(|g| u32::arbitrary(g) as _)))]
+    pub history_size: usize,
+}
+
+impl Default for ActorTraceConfig {
+    fn default() -> Self {
+        Self {
+            actors: vec![],
+            history_size: 100,
+        }
+    }
+}
+
+/// Call of a traced actor by a message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TracedCall {
+    #[serde(with = "crate::lotus_json")]
+    pub message: Cid,
+    #[serde(with = "crate::lotus_json")]
+    pub from: Address,
+    #[serde(with = "crate::lotus_json")]
+    pub to: Address,
+    pub method: u64,
+    #[serde(with = "crate::lotus_json")]
+    pub value: TokenAmount,
+    /// Parameters of the message, `null` if they aren't CBOR.
+    pub params: Option<IpldJson>,
+    pub exit_code: u32,
+    /// Return value of the message, `null` if it isn't CBOR.
+    #[serde(rename = "Return")]
+    pub return_value: Option<IpldJson>,
+    pub gas_used: u64,
+    /// Calls and returns of the message, internal sends included.
+    pub sends: Vec<TraceEvent>,
+}
+
+/// Calls of a traced actor in a tipset.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TracedTipset {
+    pub epoch: ChainEpoch,
+    #[serde(with = "crate::lotus_json")]
+    pub tipset: TipsetKeys,
+    pub calls: Vec<TracedCall>,
+    /// Change of the actor over the execution of the tipset.
+    pub state_change: Option<ActorDiff>,
+}
+
+/// Traces the calls of the configured actors.
+#[derive(Default)]
+pub struct ActorTracer {
+    actors: Vec<Address>,
+    history_size: usize,
+    /// ID addresses of the traced actors.
+    ids: Arc<Mutex<HashMap<Address, Address>>>,
+}
+
+impl ActorTracer {
+    pub fn new(config: &ActorTraceConfig) -> anyhow::Result<Self> {
+        let actors = config
+            .actors
+            .iter()
+            .map(|address| {
+                StrictAddress::from_str(address)
+                    .map(Address::from)
+                    .with_context(|| format!("Invalid traced address {address}"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self {
+            actors,
+            history_size: config.history_size,
+            ids: Default::default(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actors.is_empty()
+    }
+
+    /// Returns the configured address of a traced actor, given its configured
+    /// or ID address.
+    pub fn traced_address(&self, address: &Address) -> Option<Address> {
+        let ids = self.ids.lock();
+        self.actors
+            .iter()
+            .find(|actor| *actor == address || ids.get(actor) == Some(address))
+            .copied()
+    }
+
+    /// Resolves the ID addresses of the traced actors at the head of the
+    /// chain.
+    pub fn resolve_ids<DB>(&self, state_manager: &StateManager<DB>)
+    where
+        DB: Blockstore + Send + Sync + 'static,
+    {
+        resolve_ids(
+            &self.actors,
+            &self.ids,
+            state_manager,
+            &state_manager.chain_store().heaviest_tipset(),
+        );
+    }
+}
+
+fn resolve_ids<DB>(
+    actors: &[Address],
+    ids: &Mutex<HashMap<Address, Address>>,
+    state_manager: &StateManager<DB>,
+    tipset: &Tipset,
+) where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    let mut ids = ids.lock();
+    for actor in actors {
+        if ids.contains_key(actor) {
+            continue;
+        }
+        if let Ok(Some(id)) = state_manager.lookup_id(actor, tipset) {
+            ids.insert(*actor, id);
+        }
+    }
+}
+
+impl<DB> ExecutionCallback<DB> for ActorTracer
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    fn name(&self) -> &str {
+        "actor trace"
+    }
+
+    fn traced(&self) -> bool {
+        true
+    }
+
+    fn begin(&self, _tipset: &Tipset) -> Box<dyn TipsetExecution<DB>> {
+        let ids = self.ids.lock();
+        Box::new(TipsetTrace {
+            actors: self
+                .actors
+                .iter()
+                .map(|actor| (*actor, ids.get(actor).copied()))
+                .collect(),
+            history_size: self.history_size,
+            ids: self.ids.clone(),
+            calls: vec![],
+        })
+    }
+}
+
+/// Calls of the traced actors in the execution of a tipset.
+struct TipsetTrace {
+    /// Traced actors, and their ID addresses if they are resolved.
+    actors: Vec<(Address, Option<Address>)>,
+    history_size: usize,
+    ids: Arc<Mutex<HashMap<Address, Address>>>,
+    /// Calls, by traced actor.
+    calls: Vec<(Address, TracedCall)>,
+}
+
+fn decode(bytes: &[u8]) -> Option<IpldJson> {
+    match bytes.is_empty() {
+        true => None,
+        false => fvm_ipld_encoding::from_slice::<Ipld>(bytes)
+            .ok()
+            .map(IpldJson),
+    }
+}
+
+impl<DB> TipsetExecution<DB> for TipsetTrace
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    fn observe(&mut self, cid: &Cid, message: &ChainMessage, apply_ret: &ApplyRet) {
+        let unsigned = message.message();
+        let sends = apply_ret.exec_trace();
+        let called = |address: &Address| {
+            unsigned.to() == *address
+                || sends
+                    .iter()
+                    .any(|send| matches!(send, TraceEvent::Call { to, .. } if to == address))
+        };
+        let receipt = apply_ret.msg_receipt();
+        for actor in &self.actors {
+            if !called(&actor.0) && !actor.1.as_ref().is_some_and(called) {
+                continue;
+            }
+            self.calls.push((
+                actor.0,
+                TracedCall {
+                    message: *cid,
+                    from: unsigned.from(),
+                    to: unsigned.to(),
+                    method: unsigned.method_num(),
+                    value: unsigned.value(),
+                    params: decode(unsigned.params().bytes()),
+                    exit_code: receipt.exit_code().value(),
+                    return_value: decode(receipt.return_data().bytes()),
+                    gas_used: receipt.gas_used(),
+                    sends: sends
+                        .iter()
+                        .filter(|send| !matches!(send, TraceEvent::GasCharge { .. }))
+                        .cloned()
+                        .collect(),
+                },
+            ));
+        }
+    }
+
+    fn finish(
+        self: Box<Self>,
+        state_manager: &StateManager<DB>,
+        tipset: &Tipset,
+        state_root: Cid,
+    ) -> anyhow::Result<()> {
+        let actors: Vec<Address> = self.actors.iter().map(|actor| actor.0).collect();
+        resolve_ids(&actors, &self.ids, state_manager, tipset);
+
+        let settings = state_manager.chain_store().settings();
+        for actor in &self.actors {
+            let calls: Vec<TracedCall> = self
+                .calls
+                .iter()
+                .filter(|(traced, _)| *traced == actor.0)
+                .map(|(_, call)| call.clone())
+                .collect();
+            if calls.is_empty() {
+                continue;
+            }
+            let state_change = ActorDiff::new(
+                actor.0,
+                state_manager.get_actor(&actor.0, *tipset.parent_state())?,
+                state_manager.get_actor(&actor.0, state_root)?,
+            );
+            let key = actor_trace_key(&actor.0);
+            let mut history: Vec<TracedTipset> = settings.read_obj(&key)?.unwrap_or_default();
+            // Tipsets are executed again after restarts.
+            history.retain(|traced| &traced.tipset != tipset.key());
+            history.push(TracedTipset {
+                epoch: tipset.epoch(),
+                tipset: tipset.key().clone(),
+                calls,
+                state_change,
+            });
+            let excess = history.len().saturating_sub(self.history_size);
+            history.drain(..excess);
+            settings.write_obj(&key, &history)?;
+        }
+        Ok(())
+    }
+}
+
+impl<DB> StateManager<DB>
+where
+    DB: Blockstore,
+{
+    /// Returns the recorded calls of a traced actor, oldest first.
+    pub fn actor_trace(&self, address: &Address) -> anyhow::Result<Vec<TracedTipset>> {
+        Ok(self
+            .chain_store()
+            .settings()
+            .read_obj(&actor_trace_key(address))?
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MemoryDB;
+    use crate::shim::message::Message;
+    use fvm3::executor::ApplyRet as ApplyRet_v3;
+    use fvm_ipld_encoding::RawBytes;
+    use fvm_shared3::receipt::Receipt;
+
+    fn apply_ret(sends: &[Address]) -> ApplyRet {
+        ApplyRet_v3 {
+            msg_receipt: Receipt {
+                exit_code: fvm_shared3::error::ExitCode::OK,
+                return_data: Default::default(),
+                gas_used: 10,
+                events_root: None,
+            },
+            penalty: Default::default(),
+            miner_tip: Default::default(),
+            base_fee_burn: Default::default(),
+            over_estimation_burn: Default::default(),
+            refund: Default::default(),
+            gas_refund: 0,
+            gas_burned: 0,
+            failure_info: None,
+            exec_trace: sends
+                .iter()
+                .map(|to| fvm3::trace::ExecutionEvent::Call {
+                    from: 100,
+                    to: (*to).into(),
+                    method: 2,
+                    params: None,
+                    value: Default::default(),
+                })
+                .collect(),
+            events: vec![],
+        }
+        .into()
+    }
+
+    #[test]
+    fn direct_and_internal_calls_are_traced() {
+        let robust = Address::new_actor(b"contract");
+        let id = Address::new_id(1000);
+        let mut trace = TipsetTrace {
+            actors: vec![(robust, Some(id))],
+            history_size: 10,
+            ids: Default::default(),
+            calls: vec![],
+        };
+        let message = |to: Address, sequence: u64| {
+            ChainMessage::Unsigned(Message {
+                from: Address::new_id(100),
+                to,
+                sequence,
+                method_num: 2,
+                params: RawBytes::serialize(("hello", 42)).unwrap(),
+                ..Default::default()
+            })
+        };
+        let observe = |trace: &mut TipsetTrace, message: ChainMessage, sends: &[Address]| {
+            TipsetExecution::<MemoryDB>::observe(
+                trace,
+                &message.cid().unwrap(),
+                &message,
+                &apply_ret(sends),
+            );
+        };
+        // Direct call to the robust address.
+        observe(&mut trace, message(robust, 0), &[robust]);
+        // Internal send to the ID address.
+        observe(&mut trace, message(Address::new_id(101), 1), &[id]);
+        // Unrelated message.
+        observe(&mut trace, message(Address::new_id(102), 2), &[]);
+
+        assert_eq!(trace.calls.len(), 2);
+        assert!(trace.calls.iter().all(|(actor, _)| *actor == robust));
+        let call = &trace.calls[0].1;
+        assert_eq!(call.to, robust);
+        assert_eq!(call.gas_used, 10);
+        assert_eq!(call.sends.len(), 1);
+        assert_eq!(
+            call.params,
+            Some(IpldJson(Ipld::List(vec![
+                Ipld::String("hello".into()),
+                Ipld::Integer(42)
+            ])))
+        );
+    }
+}
@@ -0,0 +1,320 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Verifiable log of the states computed by the node.
+//!
+//! Validating a tipset checks the state root and receipts root declared by
+//! its blocks against the state computed for its parent. Each validated tipset
+//! appends an [`AuditRecord`] of that computation to the log, one JSON object
+//! per line. Records are hash-chained, each holding the hash of the previous
+//! one, so that edited, removed or reordered records break the chain. Every
+//! [`SIGNATURE_INTERVAL`] records, the hash of the record is signed with the
+//! libp2p key of the node, which ties the log to its peer ID. [`verify`]
+//! checks a log, see `forest-tool audit-log verify`.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, Write},
+    path::Path,
+};
+
+use crate::blocks::{BlockHeader, TipsetKeys};
+use crate::libp2p::{Keypair, PeerId};
+use crate::shim::clock::ChainEpoch;
+use crate::state_manager::StateManager;
+use crate::utils::encoding::blake2b_256;
+use crate::utils::version::FOREST_VERSION_STRING;
+use anyhow::{ensure, Context as _};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use libp2p::identity::PublicKey;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Number of records between two signatures.
+pub const SIGNATURE_INTERVAL: u64 = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AuditRecord {
+    /// Position of the record in the log, from `0`.
+    pub seq: u64,
+    /// Epoch of the executed tipset.
+    pub epoch: ChainEpoch,
+    #[serde(with = "crate::lotus_json")]
+    pub tipset: TipsetKeys,
+    #[serde(with = "crate::lotus_json")]
+    pub state_root: Cid,
+    #[serde(with = "crate::lotus_json")]
+    pub receipts_root: Cid,
+    /// Version of the node that computed the state.
+    pub version: String,
+    /// Hex-encoded hash of the previous record, empty for the first one.
+    pub prev: String,
+    pub signature: Option<RecordSignature>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RecordSignature {
+    /// Hex-encoded protobuf encoding of the libp2p public key of the node.
+    pub public_key: String,
+    /// Hex-encoded signature of the hash of the record.
+    pub signature: String,
+}
+
+impl AuditRecord {
+    /// Hash of the record, excluding its signature.
+    pub fn hash(&self) -> [u8; 32] {
+        blake2b_256(
+            &fvm_ipld_encoding::to_vec(&(
+                self.seq,
+                self.epoch,
+                &self.tipset,
+                self.state_root,
+                self.receipts_root,
+                &self.version,
+                &self.prev,
+            ))
+            .expect("infallible"),
+        )
+    }
+}
+
+/// Appends records to the audit log file.
+pub struct AuditLog {
+    file: Mutex<AuditLogFile>,
+    keypair: Keypair,
+}
+
+struct AuditLogFile {
+    file: File,
+    next_seq: u64,
+    prev: String,
+}
+
+impl AuditLog {
+    /// Opens the log at `path`, creating it if needed, to append records
+    /// signed with `keypair`. A record torn by a crash at the end of the log
+    /// is dropped.
+    pub fn open(path: &Path, keypair: Keypair) -> anyhow::Result<Self> {
+        let last = match std::fs::read(path) {
+            Ok(log) => {
+                // Records are written with their line feed at once.
+                let complete = log.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+                if complete < log.len() {
+                    warn!(
+                        "Dropping the torn last record of the audit log {}",
+                        path.display()
+                    );
+                    OpenOptions::new()
+                        .write(true)
+                        .open(path)?
+                        .set_len(complete as u64)?;
+                }
+                read_records(&log[..complete])
+                    .last()
+                    .transpose()
+                    .with_context(|| format!("invalid audit log {}", path.display()))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(AuditLogFile {
+                file,
+                next_seq: last.as_ref().map_or(0, |record| record.seq + 1),
+                prev: last
+                    .map(|record| hex::encode(record.hash()))
+                    .unwrap_or_default(),
+            }),
+            keypair,
+        })
+    }
+
+    pub fn append(
+        &self,
+        epoch: ChainEpoch,
+        tipset: &TipsetKeys,
+        state_root: Cid,
+        receipts_root: Cid,
+    ) -> anyhow::Result<()> {
+        let mut log = self.file.lock();
+        let mut record = AuditRecord {
+            seq: log.next_seq,
+            epoch,
+            tipset: tipset.clone(),
+            state_root,
+            receipts_root,
+            version: FOREST_VERSION_STRING.clone(),
+            prev: log.prev.clone(),
+            signature: None,
+        };
+        let hash = record.hash();
+        if (record.seq + 1) % SIGNATURE_INTERVAL == 0 {
+            record.signature = Some(RecordSignature {
+                public_key: hex::encode(self.keypair.public().encode_protobuf()),
+                signature: hex::encode(self.keypair.sign(&hash)?),
+            });
+        }
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        log.file.write_all(&line)?;
+        log.file.flush()?;
+        log.next_seq += 1;
+        log.prev = hex::encode(hash);
+        Ok(())
+    }
+}
+
+fn read_records(reader: impl BufRead) -> impl Iterator<Item = anyhow::Result<AuditRecord>> {
+    reader
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+}
+
+/// Outcome of the verification of an audit log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditSummary {
+    pub records: u64,
+    /// Number of records covered by a signature.
+    pub signed_records: u64,
+    /// Nodes that signed the log.
+    pub signers: Vec<PeerId>,
+    /// Epochs of the first and last records.
+    pub epochs: Option<(ChainEpoch, ChainEpoch)>,
+}
+
+/// Checks that the records of a log are chained and that its signatures are
+/// valid.
+pub fn verify(reader: impl BufRead) -> anyhow::Result<AuditSummary> {
+    let mut summary = AuditSummary {
+        records: 0,
+        signed_records: 0,
+        signers: vec![],
+        epochs: None,
+    };
+    let mut prev = String::new();
+    for record in read_records(reader) {
+        let record = record.with_context(|| format!("record {}", summary.records))?;
+        ensure!(
+            record.seq == summary.records,
+            "record {} has sequence number {}",
+            summary.records,
+            record.seq
+        );
+        ensure!(
+            record.prev == prev,
+            "record {} doesn't chain to the previous record",
+            record.seq
+        );
+        let hash = record.hash();
+        if let Some(signature) = &record.signature {
+            let public_key = PublicKey::try_decode_protobuf(&hex::decode(&signature.public_key)?)?;
+            ensure!(
+                public_key.verify(&hash, &hex::decode(&signature.signature)?),
+                "invalid signature of record {}",
+                record.seq
+            );
+            let signer = PeerId::from(public_key);
+            if !summary.signers.contains(&signer) {
+                summary.signers.push(signer);
+            }
+            summary.signed_records = record.seq + 1;
+        }
+        summary.epochs = Some(match summary.epochs {
+            Some((first, _)) => (first, record.epoch),
+            None => (record.epoch, record.epoch),
+        });
+        summary.records += 1;
+        prev = hex::encode(hash);
+    }
+    Ok(summary)
+}
+
+impl<DB> StateManager<DB>
+where
+    DB: Blockstore,
+{
+    /// Log of the states computed while validating tipsets. Without it, no
+    /// audit records are written.
+    pub fn with_audit_log(mut self, audit_log: AuditLog) -> Self {
+        self.audit_log = Some(audit_log);
+        self
+    }
+
+    /// Records the state computed for the parent tipset of `header`, which
+    /// has been checked against the headers of its tipset by its validation.
+    pub fn audit_validated_tipset(&self, header: &BlockHeader) -> anyhow::Result<()> {
+        let Some(audit_log) = &self.audit_log else {
+            return Ok(());
+        };
+        let parent = self.chain_store().tipset_from_keys(header.parents())?;
+        audit_log.append(
+            parent.epoch(),
+            parent.key(),
+            *header.state_root(),
+            *header.message_receipts(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipld::FrozenCids;
+
+    fn append_records(log: &AuditLog, epochs: std::ops::Range<ChainEpoch>) {
+        for epoch in epochs {
+            let cid = Cid::default();
+            log.append(
+                epoch,
+                &TipsetKeys::new(FrozenCids::from_iter([cid])),
+                cid,
+                cid,
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn tampered_logs_fail_verification() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let keypair = Keypair::generate_ed25519();
+        append_records(&AuditLog::open(&path, keypair.clone()).unwrap(), 0..150);
+        // The chain resumes after a restart.
+        append_records(&AuditLog::open(&path, keypair.clone()).unwrap(), 150..250);
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        let summary = verify(log.as_bytes()).unwrap();
+        assert_eq!(summary.records, 250);
+        assert_eq!(summary.signed_records, 200);
+        assert_eq!(summary.signers, [PeerId::from(keypair.public())]);
+        assert_eq!(summary.epochs, Some((0, 249)));
+
+        let mut lines: Vec<_> = log.lines().collect();
+        lines.remove(10);
+        verify(lines.join("\n").as_bytes()).unwrap_err();
+
+        let edited = log.replacen("\"Epoch\":10,", "\"Epoch\":11,", 1);
+        verify(edited.as_bytes()).unwrap_err();
+    }
+
+    #[test]
+    fn torn_records_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.log");
+        let keypair = Keypair::generate_ed25519();
+        append_records(&AuditLog::open(&path, keypair.clone()).unwrap(), 0..10);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"Seq\":10,\"Epo").unwrap();
+        append_records(&AuditLog::open(&path, keypair).unwrap(), 10..20);
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(verify(log.as_bytes()).unwrap().records, 20);
+    }
+}
@@ -0,0 +1,334 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Consumers of the results of the execution of tipsets.
+//!
+//! Every message applied while [`StateManager`] executes a tipset, implicit
+//! messages included, is passed to the registered [`ExecutionCallback`]s, so
+//! that indices and other consumers don't need changes in the interpreter.
+//! Each consumer observes the messages of a tipset through its own
+//! [`TipsetExecution`], which is finished once the tipset is executed. The
+//! [event](super::event_index), [deal](super::deal_index),
+//! [message](super::message_index) and [actor change](super::actor_changes)
+//! indices are registered by default.
+//!
+//! Consumers observe executions, they can't fail them: errors are logged.
+//! Tipsets executed again, e.g. after a restart, are observed again.
+
+use std::sync::Arc;
+
+use crate::blocks::Tipset;
+use crate::message::ChainMessage;
+use crate::shim::executor::ApplyRet;
+use crate::state_manager::StateManager;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use tracing::warn;
+
+use super::{
+    actor_changes::ActorChangeCollector, deal_index::DealCollector, event_index::EventCollector,
+    message_index::MessageCollector,
+};
+
+pub trait ExecutionCallback<DB>: Send + Sync {
+    /// Name of the consumer, for logs.
+    fn name(&self) -> &str;
+
+    /// Whether the consumer needs the execution traces of the messages, see
+    /// [`ApplyRet::exec_trace`]. Tracing slows the execution down.
+    fn traced(&self) -> bool {
+        false
+    }
+
+    /// Starts observing the execution of `tipset`.
+    fn begin(&self, tipset: &Tipset) -> Box<dyn TipsetExecution<DB>>;
+}
+
+/// Observer of the execution of a tipset.
+pub trait TipsetExecution<DB>: Send {
+    fn observe(&mut self, cid: &Cid, message: &ChainMessage, apply_ret: &ApplyRet);
+
+    /// Called once every message of `tipset` is applied, with the resulting
+    /// state root.
+    fn finish(
+        self: Box<Self>,
+        state_manager: &StateManager<DB>,
+        tipset: &Tipset,
+        state_root: Cid,
+    ) -> anyhow::Result<()>;
+}
+
+/// Observers of the execution of a tipset by every registered consumer.
+pub(super) struct Observers<DB> {
+    /// Names of the consumers, and their observers.
+    observers: Vec<(String, Box<dyn TipsetExecution<DB>>)>,
+}
+
+impl<DB: Blockstore> Observers<DB> {
+    pub fn begin(callbacks: &[Arc<dyn ExecutionCallback<DB>>], tipset: &Tipset) -> Self {
+        Self {
+            observers: callbacks
+                .iter()
+                .map(|callback| (callback.name().to_string(), callback.begin(tipset)))
+                .collect(),
+        }
+    }
+
+    pub fn observe(&mut self, cid: &Cid, message: &ChainMessage, apply_ret: &ApplyRet) {
+        for (_, observer) in &mut self.observers {
+            observer.observe(cid, message, apply_ret);
+        }
+    }
+
+    pub fn finish(self, state_manager: &StateManager<DB>, tipset: &Tipset, state_root: Cid) {
+        for (name, observer) in self.observers {
+            if let Err(e) = observer.finish(state_manager, tipset, state_root) {
+                warn!(
+                    "Execution callback {name} failed on epoch {}: {e}",
+                    tipset.epoch()
+                );
+            }
+        }
+    }
+}
+
+impl<DB: Blockstore> TipsetExecution<DB> for EventCollector {
+    fn observe(&mut self, cid: &Cid, message: &ChainMessage, apply_ret: &ApplyRet) {
+        EventCollector::observe(self, cid, message, apply_ret)
+    }
+
+    fn finish(
+        self: Box<Self>,
+        state_manager: &StateManager<DB>,
+        tipset: &Tipset,
+        _state_root: Cid,
+    ) -> anyhow::Result<()> {
+        state_manager.index_events(tipset, *self)
+    }
+}
+
+impl<DB: Blockstore> TipsetExecution<DB> for DealCollector {
+    fn observe(&mut self, cid: &Cid, message: &ChainMessage, apply_ret: &ApplyRet) {
+        DealCollector::observe(self, cid, message, apply_ret)
+    }
+
+    fn finish(
+        self: Box<Self>,
+        state_manager: &StateManager<DB>,
+        tipset: &Tipset,
+        _state_root: Cid,
+    ) -> anyhow::Result<()> {
+        state_manager.index_deals(tipset, *self)
+    }
+}
+
+impl<DB: Blockstore> TipsetExecution<DB> for MessageCollector {
+    fn observe(&mut self, cid: &Cid, message: &ChainMessage, _apply_ret: &ApplyRet) {
+        MessageCollector::observe(self, cid, message)
+    }
+
+    fn finish(
+        self: Box<Self>,
+        state_manager: &StateManager<DB>,
+        tipset: &Tipset,
+        _state_root: Cid,
+    ) -> anyhow::Result<()> {
+        state_manager.index_messages(tipset, &self.messages)
+    }
+}
+
+impl<DB: Blockstore> TipsetExecution<DB> for ActorChangeCollector {
+    fn observe(&mut self, cid: &Cid, message: &ChainMessage, apply_ret: &ApplyRet) {
+        ActorChangeCollector::observe(self, cid, message, apply_ret)
+    }
+
+    fn finish(
+        self: Box<Self>,
+        state_manager: &StateManager<DB>,
+        tipset: &Tipset,
+        state_root: Cid,
+    ) -> anyhow::Result<()> {
+        state_manager.index_actor_changes(tipset, *self, state_root)
+    }
+}
+
+/// Indexes the events emitted by actors, see [`event_index`](super::event_index).
+pub struct EventIndexer;
+
+impl<DB: Blockstore> ExecutionCallback<DB> for EventIndexer {
+    fn name(&self) -> &str {
+        "event index"
+    }
+
+    fn begin(&self, _tipset: &Tipset) -> Box<dyn TipsetExecution<DB>> {
+        Box::<EventCollector>::default()
+    }
+}
+
+/// Indexes the deal IDs of published deals, see
+/// [`deal_index`](super::deal_index).
+pub struct DealIndexer;
+
+impl<DB: Blockstore> ExecutionCallback<DB> for DealIndexer {
+    fn name(&self) -> &str {
+        "deal index"
+    }
+
+    fn begin(&self, _tipset: &Tipset) -> Box<dyn TipsetExecution<DB>> {
+        Box::<DealCollector>::default()
+    }
+}
+
+/// Indexes the tipsets including messages, see
+/// [`message_index`](super::message_index).
+pub struct MessageIndexer;
+
+impl<DB: Blockstore> ExecutionCallback<DB> for MessageIndexer {
+    fn name(&self) -> &str {
+        "message index"
+    }
+
+    fn begin(&self, _tipset: &Tipset) -> Box<dyn TipsetExecution<DB>> {
+        Box::<MessageCollector>::default()
+    }
+}
+
+/// Indexes the actors created and deleted, see
+/// [`actor_changes`](super::actor_changes).
+pub struct ActorChangeIndexer;
+
+impl<DB: Blockstore> ExecutionCallback<DB> for ActorChangeIndexer {
+    fn name(&self) -> &str {
+        "actor change index"
+    }
+
+    fn begin(&self, _tipset: &Tipset) -> Box<dyn TipsetExecution<DB>> {
+        Box::<ActorChangeCollector>::default()
+    }
+}
+
+pub(super) fn default_callbacks<DB: Blockstore>() -> Vec<Arc<dyn ExecutionCallback<DB>>> {
+    vec![
+        Arc::new(EventIndexer),
+        Arc::new(DealIndexer),
+        Arc::new(MessageIndexer),
+        Arc::new(ActorChangeIndexer),
+    ]
+}
+
+impl<DB: Blockstore> StateManager<DB> {
+    /// Registers a consumer of the results of the execution of the tipsets
+    /// executed from now on.
+    pub fn register_execution_callback(&self, callback: Arc<dyn ExecutionCallback<DB>>) {
+        self.execution_callbacks.write().push(callback);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::BlockHeader;
+    use crate::chain::ChainStore;
+    use crate::db::MemoryDB;
+    use crate::networks::ChainConfig;
+    use crate::shim::{address::Address, message::Message};
+    use fvm3::executor::ApplyRet as ApplyRet_v3;
+    use fvm_shared3::receipt::Receipt;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// Accounts for the gas used by the messages of the executed tipsets.
+    #[derive(Default)]
+    struct GasAccounting(Arc<AtomicU64>);
+
+    struct TipsetGas(Arc<AtomicU64>, u64);
+
+    impl<DB> ExecutionCallback<DB> for GasAccounting {
+        fn name(&self) -> &str {
+            "gas accounting"
+        }
+
+        fn begin(&self, _tipset: &Tipset) -> Box<dyn TipsetExecution<DB>> {
+            Box::new(TipsetGas(self.0.clone(), 0))
+        }
+    }
+
+    impl<DB> TipsetExecution<DB> for TipsetGas {
+        fn observe(&mut self, _cid: &Cid, _message: &ChainMessage, apply_ret: &ApplyRet) {
+            self.1 += apply_ret.msg_receipt().gas_used();
+        }
+
+        fn finish(
+            self: Box<Self>,
+            _state_manager: &StateManager<DB>,
+            _tipset: &Tipset,
+            _state_root: Cid,
+        ) -> anyhow::Result<()> {
+            self.0.fetch_add(self.1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    fn apply_ret(gas_used: u64) -> ApplyRet {
+        ApplyRet_v3 {
+            msg_receipt: Receipt {
+                exit_code: fvm_shared3::error::ExitCode::OK,
+                return_data: Default::default(),
+                gas_used,
+                events_root: None,
+            },
+            penalty: Default::default(),
+            miner_tip: Default::default(),
+            base_fee_burn: Default::default(),
+            over_estimation_burn: Default::default(),
+            refund: Default::default(),
+            gas_refund: 0,
+            gas_burned: 0,
+            failure_info: None,
+            exec_trace: vec![],
+            events: vec![],
+        }
+        .into()
+    }
+
+    #[test]
+    fn registered_callbacks_observe_executions_alongside_the_indices() {
+        let db = Arc::new(MemoryDB::default());
+        let genesis = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .timestamp(7777)
+            .build()
+            .unwrap();
+        crate::chain::persist_objects(&db, &[&genesis]).unwrap();
+        let chain_config = Arc::new(ChainConfig::default());
+        let chain_store = Arc::new(
+            ChainStore::new(db.clone(), db, chain_config.clone(), genesis.clone()).unwrap(),
+        );
+        let state_manager = StateManager::new(chain_store, chain_config).unwrap();
+        let genesis = Tipset::from(genesis);
+        let gas = GasAccounting::default();
+        let gas_used = gas.0.clone();
+        state_manager.register_execution_callback(Arc::new(gas));
+
+        let message = ChainMessage::Unsigned(Message {
+            from: Address::new_id(100),
+            ..Default::default()
+        });
+        let cid = message.cid().unwrap();
+        let callbacks = state_manager.execution_callbacks.read().clone();
+        let mut observers = Observers::begin(&callbacks, &genesis);
+        observers.observe(&cid, &message, &apply_ret(10));
+        observers.observe(&cid, &message, &apply_ret(32));
+        observers.finish(&state_manager, &genesis, *genesis.parent_state());
+        state_manager.flush_indices();
+
+        assert_eq!(gas_used.load(Ordering::Relaxed), 42);
+        assert_eq!(
+            state_manager
+                .message_inclusion(&cid)
+                .unwrap()
+                .unwrap()
+                .key(),
+            genesis.key()
+        );
+    }
+}
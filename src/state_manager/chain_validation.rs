@@ -0,0 +1,363 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Full chain validation: every tipset from a starting epoch up to the head,
+//! or up to a given epoch, is executed again, and the resulting state and
+//! receipt roots are compared with the ones declared by its children.
+//!
+//! Validation runs in batches of [`BATCH_EPOCHS`] epochs, whose tipsets are
+//! executed in parallel. The computed states are written to an
+//! [`OverlayBlockstore`] that is discarded after every batch, so validation
+//! leaves the database untouched. The progress is persisted in the settings
+//! store after every batch, so an interrupted validation resumes from the last
+//! validated batch. Validation stops at the first divergence.
+
+use std::{ops::RangeInclusive, str::FromStr, sync::Arc, time::Instant};
+
+use crate::blocks::TipsetKeys;
+use crate::chain::index::ResolveNullTipset;
+use crate::db::{setting_keys::CHAIN_VALIDATION_KEY, SettingsStoreExt};
+use crate::shim::clock::ChainEpoch;
+use crate::state_manager::{check_tipset_state, metrics, StateManager};
+use crate::utils::db::overlay::OverlayBlockstore;
+use crate::utils::io::scheduler::IO_SCHEDULER;
+use anyhow::{bail, Context as _};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use itertools::Itertools as _;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+/// Number of epochs validated between two progress checkpoints.
+pub const BATCH_EPOCHS: ChainEpoch = 100;
+
+lazy_static::lazy_static! {
+    static ref LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+}
+
+/// Where a chain validation starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ValidateFrom {
+    Genesis,
+    /// The oldest tipset whose parent state is available, e.g. the first
+    /// tipset of the imported snapshot.
+    Snapshot,
+    Epoch(ChainEpoch),
+}
+
+impl FromStr for ValidateFrom {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "genesis" => Ok(ValidateFrom::Genesis),
+            "snapshot" => Ok(ValidateFrom::Snapshot),
+            epoch => Ok(ValidateFrom::Epoch(
+                epoch
+                    .parse()
+                    .context("expected `genesis`, `snapshot` or an epoch")?,
+            )),
+        }
+    }
+}
+
+/// Tipset whose execution doesn't produce the state and receipt roots declared
+/// by its children.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
+#[serde(rename_all = "PascalCase")]
+#[error(
+    "state mismatch at epoch {epoch}: computed state root {computed_state_root} and receipt \
+     root {computed_receipt_root}, expected {expected_state_root} and {expected_receipt_root}"
+)]
+pub struct StateDivergence {
+    /// Epoch of the executed tipset.
+    pub epoch: ChainEpoch,
+    #[serde(with = "crate::lotus_json")]
+    pub tipset: TipsetKeys,
+    #[serde(with = "crate::lotus_json")]
+    pub expected_state_root: Cid,
+    #[serde(with = "crate::lotus_json")]
+    pub computed_state_root: Cid,
+    #[serde(with = "crate::lotus_json")]
+    pub expected_receipt_root: Cid,
+    #[serde(with = "crate::lotus_json")]
+    pub computed_receipt_root: Cid,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ChainValidationProgress {
+    pub from: ChainEpoch,
+    pub to: ChainEpoch,
+    /// All tipsets below this epoch have been validated.
+    pub validated: ChainEpoch,
+    /// Time spent validating, over all runs.
+    pub elapsed_secs: f64,
+    /// Whether a validation is in progress. Not persisted.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub running: bool,
+    /// Error that stopped the last run.
+    pub error: Option<String>,
+    /// First divergence, which stopped the last run.
+    #[serde(default)]
+    pub divergence: Option<StateDivergence>,
+}
+
+impl ChainValidationProgress {
+    pub fn new(from: ChainEpoch, to: ChainEpoch) -> Self {
+        ChainValidationProgress {
+            from,
+            to,
+            validated: from,
+            elapsed_secs: 0.0,
+            running: false,
+            error: None,
+            divergence: None,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.validated >= self.to
+    }
+
+    /// A validation stopped by an error or a divergence is resumed from the
+    /// last validated epoch, i.e. a divergence is checked again.
+    fn is_resumable(&self, from: ChainEpoch, to: ChainEpoch) -> bool {
+        self.from == from && self.to == to && !self.is_finished()
+    }
+
+    pub fn epochs_per_sec(&self) -> f64 {
+        if self.elapsed_secs > 0.0 {
+            (self.validated - self.from) as f64 / self.elapsed_secs
+        } else {
+            0.0
+        }
+    }
+}
+
+impl<DB> StateManager<DB>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    /// Returns the progress of the current or last chain validation.
+    pub fn chain_validation_progress(&self) -> anyhow::Result<Option<ChainValidationProgress>> {
+        let progress: Option<ChainValidationProgress> = self
+            .chain_store()
+            .settings()
+            .read_obj(CHAIN_VALIDATION_KEY)?;
+        Ok(progress.map(|progress| ChainValidationProgress {
+            running: LOCK.try_lock().is_err(),
+            ..progress
+        }))
+    }
+
+    /// Starts validating the chain in the background, up to `to` or the head.
+    /// An unfinished validation of the same range is resumed, unless `restart`
+    /// is set.
+    pub fn start_chain_validation(
+        self: &Arc<Self>,
+        from: ValidateFrom,
+        to: Option<ChainEpoch>,
+        restart: bool,
+    ) -> anyhow::Result<ChainValidationProgress> {
+        let Ok(guard) = LOCK.try_lock() else {
+            bail!("Another chain validation is in progress");
+        };
+        let from = self.validation_start(from)?;
+        let head = self.chain_store().heaviest_tipset().epoch();
+        let to = match to {
+            Some(to) if to > head => bail!("Epoch {to} is after the head at epoch {head}"),
+            Some(to) if to <= from => bail!("Epoch {to} is not after epoch {from}"),
+            Some(to) => to,
+            None => head,
+        };
+        let previous = self.chain_validation_progress()?;
+        let mut progress = match previous {
+            Some(progress) if !restart && progress.is_resumable(from, to) => {
+                info!("Resuming chain validation at epoch {}", progress.validated);
+                progress
+            }
+            _ => ChainValidationProgress::new(from, to),
+        };
+        progress.error = None;
+        progress.divergence = None;
+        self.chain_store()
+            .settings()
+            .write_obj(CHAIN_VALIDATION_KEY, &progress)?;
+
+        let this = Arc::clone(self);
+        let mut task_progress = progress.clone();
+        tokio::task::spawn_blocking(move || {
+            let _guard = guard;
+            if let Err(e) = this.run_chain_validation(&mut task_progress) {
+                error!("Chain validation failed: {e:#}");
+                task_progress.error = Some(format!("{e:#}"));
+                if let Err(e) = this
+                    .chain_store()
+                    .settings()
+                    .write_obj(CHAIN_VALIDATION_KEY, &task_progress)
+                {
+                    error!("Failed to persist the chain validation progress: {e}");
+                }
+            }
+        });
+        progress.running = true;
+        Ok(progress)
+    }
+
+    fn validation_start(&self, from: ValidateFrom) -> anyhow::Result<ChainEpoch> {
+        Ok(match from {
+            // Genesis itself is not executed.
+            ValidateFrom::Genesis => 1,
+            ValidateFrom::Epoch(epoch) => epoch,
+            ValidateFrom::Snapshot => {
+                let heaviest = self.chain_store().heaviest_tipset();
+                self.chain_store()
+                    .chain_index
+                    .chain(heaviest)
+                    .take_while(|tipset| {
+                        self.blockstore()
+                            .has(tipset.parent_state())
+                            .unwrap_or_default()
+                    })
+                    .last()
+                    .context("The state of the heaviest tipset is missing")?
+                    .epoch()
+            }
+        })
+    }
+
+    fn run_chain_validation(
+        self: &Arc<Self>,
+        progress: &mut ChainValidationProgress,
+    ) -> anyhow::Result<()> {
+        info!(
+            "Validating the chain from epoch {} to {}",
+            progress.validated, progress.to
+        );
+        while !progress.is_finished() {
+            let end = (progress.validated + BATCH_EPOCHS).min(progress.to);
+            let started = Instant::now();
+            let divergence = self.validate_batch(progress.validated..=end)?;
+            IO_SCHEDULER.back_off_blocking(started.elapsed());
+            progress.elapsed_secs += started.elapsed().as_secs_f64();
+            if let Some(divergence) = divergence {
+                progress.validated = divergence.epoch;
+                progress.divergence = Some(divergence.clone());
+                return Err(divergence.into());
+            }
+            progress.validated = end;
+            self.chain_store()
+                .settings()
+                .write_obj(CHAIN_VALIDATION_KEY, &progress)?;
+
+            metrics::CHAIN_VALIDATION_EPOCH.set(end as u64);
+            metrics::CHAIN_VALIDATION_THROUGHPUT.set(progress.epochs_per_sec());
+            info!(
+                "Chain validated up to epoch {end} of {} ({:.2} epochs/s)",
+                progress.to,
+                progress.epochs_per_sec()
+            );
+        }
+        info!(
+            "Chain validated from epoch {} to {}",
+            progress.from, progress.to
+        );
+        Ok(())
+    }
+
+    /// Executes the tipsets of `epochs`, except the last one, and returns
+    /// the divergence with the lowest epoch.
+    fn validate_batch(
+        self: &Arc<Self>,
+        epochs: RangeInclusive<ChainEpoch>,
+    ) -> anyhow::Result<Option<StateDivergence>> {
+        use rayon::iter::{ParallelBridge as _, ParallelIterator as _};
+
+        let end = self.chain_store().chain_index.tipset_by_height(
+            *epochs.end(),
+            self.chain_store().heaviest_tipset(),
+            ResolveNullTipset::TakeOlder,
+        )?;
+        let tipsets = self
+            .chain_store()
+            .chain_index
+            .chain(end)
+            .take_while(|tipset| tipset.epoch() >= *epochs.start());
+        let overlay = Arc::new(OverlayBlockstore::new(self.blockstore_owned()));
+        let chain_index = Arc::new(self.chain_store().chain_index.with_db(overlay));
+        let genesis_timestamp = self.chain_store().genesis().timestamp();
+        let divergences = tipsets
+            .tuple_windows()
+            .par_bridge()
+            .map(|(child, parent)| {
+                check_tipset_state(
+                    genesis_timestamp,
+                    chain_index.clone(),
+                    self.chain_config(),
+                    self.beacon_schedule(),
+                    &self.engine,
+                    &child,
+                    parent,
+                )
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(divergences
+            .into_iter()
+            .flatten()
+            .min_by_key(|divergence| divergence.epoch))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_validate_from() {
+        assert_eq!(
+            "genesis".parse::<ValidateFrom>().unwrap(),
+            ValidateFrom::Genesis
+        );
+        assert_eq!(
+            "snapshot".parse::<ValidateFrom>().unwrap(),
+            ValidateFrom::Snapshot
+        );
+        assert_eq!(
+            "1200".parse::<ValidateFrom>().unwrap(),
+            ValidateFrom::Epoch(1200)
+        );
+        assert!("head".parse::<ValidateFrom>().is_err());
+    }
+
+    #[test]
+    fn progress_throughput() {
+        let mut progress = ChainValidationProgress::new(100, 400);
+        assert_eq!(progress.epochs_per_sec(), 0.0);
+        progress.validated = 300;
+        progress.elapsed_secs = 50.0;
+        assert_eq!(progress.epochs_per_sec(), 4.0);
+        assert!(!progress.is_finished());
+        progress.validated = 400;
+        assert!(progress.is_finished());
+    }
+
+    #[test]
+    fn resume_same_range() {
+        let mut progress = ChainValidationProgress::new(100, 400);
+        progress.validated = 250;
+        assert!(progress.is_resumable(100, 400));
+        assert!(!progress.is_resumable(100, 500));
+        assert!(!progress.is_resumable(200, 400));
+        progress.validated = 400;
+        assert!(!progress.is_resumable(100, 400));
+
+        // Progress persisted before divergences were recorded.
+        let mut json = serde_json::to_value(&progress).unwrap();
+        json.as_object_mut().unwrap().remove("Divergence");
+        assert_eq!(
+            serde_json::from_value::<ChainValidationProgress>(json).unwrap(),
+            progress
+        );
+    }
+}
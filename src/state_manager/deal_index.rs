@@ -0,0 +1,143 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Index of the deal IDs allocated by `PublishStorageDeals` messages, so that
+//! deal-making pipelines can map their publish messages to on-chain deals
+//! without decoding receipts.
+//!
+//! The deal IDs returned by every successful `PublishStorageDeals` message of
+//! an executed tipset are stored in the [index store](super::index_store),
+//! keyed by message CID. The IDs depend on the state the message is applied
+//! to, so they are stored per including tipset and only those of the canonical
+//! tipset are returned. Like the other indices, only tipsets executed by this
+//! node are indexed, as long as the state roots of their epochs.
+
+use crate::blocks::Tipset;
+use crate::message::ChainMessage;
+use crate::shim::{address::Address, deal::DealID, executor::ApplyRet};
+use crate::state_manager::index_store::{self, IndexBatch};
+use crate::state_manager::StateManager;
+use cid::Cid;
+use fil_actor_interface::market::Method as MarketMethod;
+use fil_actor_market_state::v11::PublishStorageDealsReturn;
+use fvm_ipld_blockstore::Blockstore;
+
+fn deal_ids_key(message: &Cid) -> String {
+    index_store::index_key(format_args!("deal_ids/{message}"))
+}
+
+/// Collects the deal IDs allocated by the messages of a tipset as they are
+/// applied.
+#[derive(Debug, Default)]
+pub(super) struct DealCollector {
+    deals: Vec<(Cid, Vec<DealID>)>,
+}
+
+impl DealCollector {
+    pub fn observe(&mut self, cid: &Cid, message: &ChainMessage, apply_ret: &ApplyRet) {
+        let message = message.message();
+        if message.to != Address::MARKET_ACTOR
+            || message.method_num != MarketMethod::PublishStorageDeals as u64
+            || !apply_ret.msg_receipt().exit_code().is_success()
+        {
+            return;
+        }
+        // The return value has the same layout in every actors version.
+        match fvm_ipld_encoding::from_slice::<PublishStorageDealsReturn>(
+            &apply_ret.msg_receipt().return_data(),
+        ) {
+            Ok(ret) => self.deals.push((*cid, ret.ids)),
+            Err(e) => tracing::warn!("Failed to decode the deal IDs of message {cid}: {e}"),
+        }
+    }
+}
+
+impl<DB> StateManager<DB>
+where
+    DB: Blockstore,
+{
+    pub(super) fn index_deals(
+        &self,
+        tipset: &Tipset,
+        collector: DealCollector,
+    ) -> anyhow::Result<()> {
+        let mut batch = IndexBatch::default();
+        for (message, ids) in collector.deals {
+            batch.push(deal_ids_key(&message), tipset, &ids)?;
+        }
+        self.indices.write(batch);
+        Ok(())
+    }
+
+    /// Returns the deal IDs allocated by the `PublishStorageDeals` message
+    /// `message` in the canonical tipset that included it, if it was executed
+    /// by this node. The IDs allocated in forks aren't returned.
+    pub fn deal_ids_for_message(&self, message: &Cid) -> anyhow::Result<Option<Vec<DealID>>> {
+        let entries = index_store::read_entries::<Vec<DealID>>(
+            self.chain_store().settings().as_ref(),
+            &deal_ids_key(message),
+        )?;
+        for entry in entries.into_iter().rev() {
+            let Ok(tipset) = self.chain_store().tipset_from_keys(&entry.tipset) else {
+                continue;
+            };
+            if self.is_canonical(&tipset)? {
+                return Ok(Some(entry.value));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::BlockHeader;
+    use crate::chain::ChainStore;
+    use crate::db::MemoryDB;
+    use crate::networks::ChainConfig;
+    use std::sync::Arc;
+
+    #[test]
+    fn only_deal_ids_of_the_canonical_tipset_are_returned() {
+        let db = Arc::new(MemoryDB::default());
+        let genesis = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .timestamp(7777)
+            .build()
+            .unwrap();
+        let fork = BlockHeader::builder()
+            .miner_address(Address::new_id(1))
+            .timestamp(7777)
+            .build()
+            .unwrap();
+        crate::chain::persist_objects(&db, &[&genesis, &fork]).unwrap();
+        let chain_config = Arc::new(ChainConfig::default());
+        let chain_store = Arc::new(
+            ChainStore::new(db.clone(), db, chain_config.clone(), genesis.clone()).unwrap(),
+        );
+        let state_manager = StateManager::new(chain_store, chain_config).unwrap();
+        let (genesis, fork) = (Tipset::from(genesis), Tipset::from(fork));
+
+        let message = Cid::default();
+        assert_eq!(state_manager.deal_ids_for_message(&message).unwrap(), None);
+
+        let collector = |ids: Vec<DealID>| DealCollector {
+            deals: vec![(message, ids)],
+        };
+        state_manager
+            .index_deals(&fork, collector(vec![3]))
+            .unwrap();
+        state_manager.flush_indices();
+        assert_eq!(state_manager.deal_ids_for_message(&message).unwrap(), None);
+
+        state_manager
+            .index_deals(&genesis, collector(vec![1, 2]))
+            .unwrap();
+        state_manager.flush_indices();
+        assert_eq!(
+            state_manager.deal_ids_for_message(&message).unwrap(),
+            Some(vec![1, 2])
+        );
+    }
+}
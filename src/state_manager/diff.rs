@@ -0,0 +1,213 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Actor-level differences between two state trees, e.g. to find where the
+//! states computed by Forest and Lotus diverge.
+//!
+//! Only the parts of the actors HAMTs that differ are visited, see
+//! [`crate::ipld::diff`].
+
+use std::sync::Arc;
+
+use crate::blocks::{Tipset, TipsetKeys};
+use crate::ipld::diff::hamt_diff;
+use crate::shim::{
+    address::Address,
+    econ::TokenAmount,
+    state_tree::{ActorState, StateRoot, StateTree},
+};
+use crate::state_manager::StateManager;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::CborStore;
+use num_traits::Zero;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActorChange {
+    Created,
+    Deleted,
+    Mutated,
+}
+
+/// Change of a single actor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ActorDiff {
+    #[serde(with = "crate::lotus_json")]
+    pub address: Address,
+    pub change: ActorChange,
+    #[serde(with = "crate::lotus_json")]
+    pub from: Option<ActorState>,
+    #[serde(with = "crate::lotus_json")]
+    pub to: Option<ActorState>,
+    /// Missing actors have a balance of zero.
+    #[serde(with = "crate::lotus_json")]
+    pub balance_delta: TokenAmount,
+    /// Missing actors have a nonce of zero.
+    pub nonce_delta: i64,
+    /// Whether the head of the actor state changed.
+    pub state_changed: bool,
+}
+
+impl ActorDiff {
+    pub(super) fn new(
+        address: Address,
+        from: Option<ActorState>,
+        to: Option<ActorState>,
+    ) -> Option<Self> {
+        let change = match (&from, &to) {
+            (None, Some(_)) => ActorChange::Created,
+            (Some(_), None) => ActorChange::Deleted,
+            (Some(from), Some(to)) if from != to => ActorChange::Mutated,
+            _ => return None,
+        };
+        let balance = |actor: &Option<ActorState>| {
+            actor
+                .as_ref()
+                .map(|actor| TokenAmount::from(&actor.balance))
+                .unwrap_or_else(TokenAmount::zero)
+        };
+        let nonce = |actor: &Option<ActorState>| actor.as_ref().map_or(0, |actor| actor.sequence);
+        Some(ActorDiff {
+            address,
+            change,
+            balance_delta: balance(&to) - &balance(&from),
+            nonce_delta: nonce(&to) as i64 - nonce(&from) as i64,
+            state_changed: from.as_ref().map(|actor| actor.state)
+                != to.as_ref().map(|actor| actor.state),
+            from,
+            to,
+        })
+    }
+}
+
+/// Differences between the parent states of two tipsets.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct StateDiff {
+    #[serde(with = "crate::lotus_json")]
+    pub from_tipset: TipsetKeys,
+    #[serde(with = "crate::lotus_json")]
+    pub from_state_root: Cid,
+    #[serde(with = "crate::lotus_json")]
+    pub to_tipset: TipsetKeys,
+    #[serde(with = "crate::lotus_json")]
+    pub to_state_root: Cid,
+    /// Sorted by address bytes.
+    pub actors: Vec<ActorDiff>,
+}
+
+/// Returns the root of the actors HAMT. State trees of actors version 0 have
+/// no [`StateRoot`] wrapper.
+pub fn actors_root(db: &impl Blockstore, state_root: &Cid) -> Cid {
+    match db.get_cbor::<StateRoot>(state_root) {
+        Ok(Some(root)) => root.actors,
+        _ => *state_root,
+    }
+}
+
+/// Returns the actors that differ between two state trees.
+pub fn diff_state_trees<DB: Blockstore>(
+    db: &Arc<DB>,
+    from: &Cid,
+    to: &Cid,
+) -> anyhow::Result<Vec<ActorDiff>> {
+    let diff = hamt_diff(db, &actors_root(db, from), &actors_root(db, to))?;
+    let from_tree = StateTree::new_from_root(Arc::clone(db), from)?;
+    let to_tree = StateTree::new_from_root(Arc::clone(db), to)?;
+    let mut actors = vec![];
+    for key in diff.keys() {
+        let address = Address::from_bytes(key)?;
+        if let Some(actor_diff) = ActorDiff::new(
+            address,
+            from_tree.get_actor(&address)?,
+            to_tree.get_actor(&address)?,
+        ) {
+            actors.push((key, actor_diff));
+        }
+    }
+    actors.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(actors
+        .into_iter()
+        .map(|(_, actor_diff)| actor_diff)
+        .collect())
+}
+
+impl<DB> StateManager<DB>
+where
+    DB: Blockstore,
+{
+    /// Compares the parent states of two tipsets. The parent states are the
+    /// ones the blocks were mined on, so no messages are executed.
+    pub fn state_diff(&self, from: &Tipset, to: &Tipset) -> anyhow::Result<StateDiff> {
+        Ok(StateDiff {
+            from_tipset: from.key().clone(),
+            from_state_root: *from.parent_state(),
+            to_tipset: to.key().clone(),
+            to_state_root: *to.parent_state(),
+            actors: diff_state_trees(
+                &self.blockstore_owned(),
+                from.parent_state(),
+                to.parent_state(),
+            )?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MemoryDB;
+    use crate::shim::state_tree::StateTreeVersion;
+
+    fn actor(balance: u64, sequence: u64) -> ActorState {
+        ActorState::new(
+            Cid::default(),
+            Cid::default(),
+            TokenAmount::from_atto(balance),
+            sequence,
+            None,
+        )
+    }
+
+    #[test]
+    fn created_deleted_mutated() {
+        let db = Arc::new(MemoryDB::default());
+        let state_root = |actors: &[(u64, ActorState)]| {
+            let mut tree = StateTree::new(db.clone(), StateTreeVersion::V5).unwrap();
+            for (id, actor) in actors {
+                tree.set_actor(&Address::new_id(*id), actor.clone())
+                    .unwrap();
+            }
+            tree.flush().unwrap()
+        };
+        let from = state_root(&[
+            (100, actor(10, 1)),
+            (101, actor(10, 1)),
+            (102, actor(10, 1)),
+        ]);
+        let to = state_root(&[(100, actor(4, 3)), (102, actor(10, 1)), (103, actor(7, 0))]);
+
+        assert!(diff_state_trees(&db, &from, &from).unwrap().is_empty());
+
+        let actors = diff_state_trees(&db, &from, &to).unwrap();
+        let changes: Vec<_> = actors
+            .iter()
+            .map(|actor| (actor.address, actor.change))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (Address::new_id(100), ActorChange::Mutated),
+                (Address::new_id(101), ActorChange::Deleted),
+                (Address::new_id(103), ActorChange::Created),
+            ]
+        );
+        assert_eq!(actors[0].balance_delta, TokenAmount::from_atto(-6));
+        assert_eq!(actors[0].nonce_delta, 2);
+        assert!(!actors[0].state_changed);
+        assert_eq!(actors[1].balance_delta, TokenAmount::from_atto(-10));
+        assert_eq!(actors[2].nonce_delta, 0);
+    }
+}
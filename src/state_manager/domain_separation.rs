@@ -0,0 +1,252 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Domain separation tags of the randomness drawn by the node and the actors.
+//!
+//! Randomness is drawn from a base, a ticket or a beacon entry, with
+//! [`draw_randomness`](super::chain_rand::draw_randomness), which hashes the
+//! tag along with the epoch and some entropy, so that the values drawn for
+//! different purposes are unrelated. The tags and the derivation are
+//! consensus critical: [`DOMAIN_SEPARATION_TAGS`] lists every tag with its
+//! source and consumers, and the tests pin the value drawn for each tag and
+//! check the derivation against the tickets and election proofs of blocks
+//! mined by Lotus. Values can be derived manually with
+//! `forest-tool shed rand`.
+
+use std::str::FromStr;
+
+use fil_actors_shared::v11::runtime::DomainSeparationTag;
+
+/// Base the randomness is drawn from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RandomnessSource {
+    /// Tickets of the chain, see
+    /// [`ChainRand::get_chain_randomness`](super::chain_rand::ChainRand::get_chain_randomness).
+    Tickets,
+    /// Beacon entries, see
+    /// [`ChainRand::get_beacon_randomness`](super::chain_rand::ChainRand::get_beacon_randomness).
+    Beacon,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DomainSeparation {
+    pub tag: DomainSeparationTag,
+    /// Name of the tag, as accepted by `forest-tool shed rand`.
+    pub name: &'static str,
+    /// Base of the randomness, if any is still drawn with the tag.
+    pub source: Option<RandomnessSource>,
+    /// Consumers of the randomness.
+    pub drawn_by: &'static str,
+}
+
+impl DomainSeparation {
+    /// Value of the tag, as hashed.
+    pub fn value(&self) -> i64 {
+        self.tag as i64
+    }
+}
+
+/// Every domain separation tag, in the order of their values.
+pub const DOMAIN_SEPARATION_TAGS: [DomainSeparation; 10] = [
+    DomainSeparation {
+        tag: DomainSeparationTag::TicketProduction,
+        name: "ticket-production",
+        source: Some(RandomnessSource::Beacon),
+        drawn_by: "block tickets, at the epoch before the block",
+    },
+    DomainSeparation {
+        tag: DomainSeparationTag::ElectionProofProduction,
+        name: "election-proof-production",
+        source: Some(RandomnessSource::Beacon),
+        drawn_by: "block election proofs",
+    },
+    DomainSeparation {
+        tag: DomainSeparationTag::WinningPoStChallengeSeed,
+        name: "winning-post-challenge-seed",
+        source: Some(RandomnessSource::Beacon),
+        drawn_by: "winning PoSt challenges of blocks",
+    },
+    DomainSeparation {
+        tag: DomainSeparationTag::WindowedPoStChallengeSeed,
+        name: "windowed-post-challenge-seed",
+        source: Some(RandomnessSource::Beacon),
+        drawn_by: "window PoSt challenges, in the miner actor and the PoSt watchdog",
+    },
+    DomainSeparation {
+        tag: DomainSeparationTag::SealRandomness,
+        name: "seal-randomness",
+        source: Some(RandomnessSource::Tickets),
+        drawn_by: "sector pre-commits and proofs of replication, in the miner actor",
+    },
+    DomainSeparation {
+        tag: DomainSeparationTag::InteractiveSealChallengeSeed,
+        name: "interactive-seal-challenge-seed",
+        source: Some(RandomnessSource::Beacon),
+        drawn_by: "proofs of replication, in the miner actor",
+    },
+    DomainSeparation {
+        tag: DomainSeparationTag::WindowPoStDeadlineAssignment,
+        name: "window-post-deadline-assignment",
+        source: None,
+        drawn_by: "nothing, deadlines are assigned deterministically",
+    },
+    DomainSeparation {
+        tag: DomainSeparationTag::MarketDealCronSeed,
+        name: "market-deal-cron-seed",
+        source: None,
+        drawn_by: "nothing, deal updates are scheduled deterministically",
+    },
+    DomainSeparation {
+        tag: DomainSeparationTag::PoStChainCommit,
+        name: "post-chain-commit",
+        source: Some(RandomnessSource::Tickets),
+        drawn_by: "chain commitments of window PoSt submissions, in the miner actor",
+    },
+    DomainSeparation {
+        tag: DomainSeparationTag::EvmPrevRandao,
+        name: "evm-prev-randao",
+        source: Some(RandomnessSource::Beacon),
+        drawn_by: "the PREVRANDAO opcode, in the EVM actor",
+    },
+];
+
+impl FromStr for DomainSeparation {
+    type Err = anyhow::Error;
+
+    /// Parses the name or the value of a tag.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        DOMAIN_SEPARATION_TAGS
+            .into_iter()
+            .find(|dst| dst.name == s || dst.value().to_string() == s)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "unknown domain separation tag {s}, expected one of {}",
+                    DOMAIN_SEPARATION_TAGS
+                        .iter()
+                        .map(|dst| format!("{} ({})", dst.name, dst.value()))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::BlockHeader;
+    use crate::db::car::PlainCar;
+    use crate::shim::address::Address;
+    use crate::shim::crypto::{verify_bls_sig, TICKET_RANDOMNESS_LOOKBACK};
+    use crate::state_manager::chain_rand::draw_randomness;
+    use fvm_ipld_encoding::CborStore;
+    use num_traits::FromPrimitive;
+
+    /// Values drawn from [`base`] at epoch 2880000, with the CBOR encoding of
+    /// `f01000` as entropy, in the order of [`DOMAIN_SEPARATION_TAGS`]. They
+    /// pin the derivation, which [`lotus_blocks`] checks against Lotus.
+    const VECTORS: [&str; 10] = [
+        "4ec76b3f203546237aaeb0851de3543e31d6eb0d92eb795a1c0a4aced06673cc",
+        "66ff50246185c0d814349a0fa9b4289115a4873eda8a7cefd51ab94729501c8b",
+        "483a5cd1da110c003f177b0d1b3a5f7a1841017ba4fe7c4d00d9560d44293fdd",
+        "07edd4d0bb19d63160cb90092b35550347aaba1d6c5b92e89e04e9813ef42ce9",
+        "0129640af3c61392e202aa213958911e10276da6d3326c8acf490031b83c96d5",
+        "c7a2535b5ddbc3fa243cb1ae2634fbe2a2104ea8bb126290780eef760ac689fd",
+        "3de630cbe695eb6eee09961f58021f5ccc0e1caa5e5ab5893d75789881dfa2fd",
+        "dede30abda6d13095fedaee2c78770e29ed9f60a80f3488f29a9febce0d54924",
+        "1660ace952a77471ac6f54ca66a638d55ed8367f5733f44c96a9123cd05a0f2b",
+        "4e4e60f6e9087b2e1bcd95a1ee0b98d621ceef4b4719f7ffda248ae699919ba8",
+    ];
+
+    /// Randomness base of the length of a beacon entry or a ticket.
+    fn base() -> Vec<u8> {
+        (0..96).collect()
+    }
+
+    #[test]
+    fn every_tag_is_listed() {
+        for (i, dst) in DOMAIN_SEPARATION_TAGS.iter().enumerate() {
+            assert_eq!(dst.value(), i as i64 + 1);
+            assert_eq!(DomainSeparationTag::from_i64(dst.value()), Some(dst.tag));
+            assert_eq!(dst.name.parse::<DomainSeparation>().unwrap(), *dst);
+            assert_eq!(
+                dst.value().to_string().parse::<DomainSeparation>().unwrap(),
+                *dst
+            );
+        }
+        assert_eq!(
+            DomainSeparationTag::from_i64(DOMAIN_SEPARATION_TAGS.len() as i64 + 1),
+            None
+        );
+        assert!("ticket".parse::<DomainSeparation>().is_err());
+    }
+
+    #[test]
+    fn golden_vectors() {
+        let entropy = fvm_ipld_encoding::to_vec(&Address::new_id(1000)).unwrap();
+        assert_eq!(entropy, [0x43, 0x00, 0xe8, 0x07]);
+        for (dst, expected) in DOMAIN_SEPARATION_TAGS.iter().zip(VECTORS) {
+            let drawn = draw_randomness(&base(), dst.value(), 2880000, &entropy).unwrap();
+            assert_eq!(hex::encode(drawn), expected, "{}", dst.name);
+        }
+        // Empty entropy and base.
+        assert_eq!(
+            hex::encode(draw_randomness(&base(), 1, 0, &[]).unwrap()),
+            "26d41a77b77f9fef3ced9c255c31f18cae16e10d7a866d899c1f035c45b65e11"
+        );
+        assert_eq!(
+            hex::encode(draw_randomness(&[], 4, 2880000, &entropy).unwrap()),
+            "6fdd88c7934dd1fea1d261bdc3413ed9a6ab326a7a9aa2ff5e37430ca0c8eda1"
+        );
+    }
+
+    /// Checks the derivation against the blocks of `test-snapshots/chain4.car`,
+    /// mined by Lotus on a devnet: their tickets and election proofs are VRF
+    /// signatures of the worker of `t01000` over the randomness drawn with the
+    /// `TicketProduction` and `ElectionProofProduction` tags from the beacon
+    /// entries of the blocks.
+    #[test]
+    fn lotus_blocks() {
+        // The snapshot has no state to look the worker up in. This is the key
+        // of the sender of the `ChangePeerID` message of `t01000` in the
+        // snapshot, which only the worker and control addresses may send.
+        let worker = Address::new_bls(&hex::decode("a965e2fe603444f6dabeeda1b5932d08130c1bff0b1098beaec26bbca708dd230d76a831432db83ea1bb71a0e04d1933").unwrap()).unwrap();
+        let car = PlainCar::new(&include_bytes!("../../test-snapshots/chain4.car")[..]).unwrap();
+        let mut header: BlockHeader = car.get_cbor(&car.roots()[0]).unwrap().unwrap();
+        let mut checked = 0;
+        while header.epoch() > 0 {
+            assert_eq!(header.miner_address(), &Address::new_id(1000));
+            let beacon = header.beacon_entries().last().unwrap();
+            // The devnet is before the Smoke upgrade, so the ticket of the
+            // parent isn't part of the entropy of the ticket.
+            let entropy = fvm_ipld_encoding::to_vec(header.miner_address()).unwrap();
+
+            let election = draw_randomness(
+                beacon.data(),
+                DomainSeparationTag::ElectionProofProduction as i64,
+                header.epoch(),
+                &entropy,
+            )
+            .unwrap();
+            let proof = header.election_proof().as_ref().unwrap();
+            verify_bls_sig(proof.vrfproof.as_bytes(), &election, &worker).unwrap();
+
+            let ticket = draw_randomness(
+                beacon.data(),
+                DomainSeparationTag::TicketProduction as i64,
+                header.epoch() - TICKET_RANDOMNESS_LOOKBACK,
+                &entropy,
+            )
+            .unwrap();
+            let proof = header.ticket().as_ref().unwrap();
+            verify_bls_sig(proof.vrfproof.as_bytes(), &ticket, &worker).unwrap();
+
+            header = car
+                .get_cbor(&header.parents().cids.into_iter().next().unwrap())
+                .unwrap()
+                .unwrap();
+            checked += 1;
+        }
+        assert_eq!(checked, 3);
+    }
+}
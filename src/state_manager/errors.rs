@@ -3,6 +3,7 @@
 
 use std::fmt::Debug;
 
+use crate::chain::{Error as ChainError, MissingData};
 use thiserror::Error;
 use tokio::task::JoinError;
 
@@ -12,11 +13,23 @@ pub enum Error {
     /// Error originating from state
     #[error("{0}")]
     State(String),
+    /// Requested chain data is not available locally
+    #[error(transparent)]
+    Missing(MissingData),
     /// Other state manager error
     #[error("{0}")]
     Other(String),
 }
 
+impl From<ChainError> for Error {
+    fn from(e: ChainError) -> Self {
+        match e {
+            ChainError::Missing(missing) => Error::Missing(missing),
+            other => Error::Other(other.to_string()),
+        }
+    }
+}
+
 impl From<String> for Error {
     fn from(e: String) -> Self {
         Error::Other(e)
@@ -0,0 +1,296 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Index of the actor events emitted when executing tipsets.
+//!
+//! The events of every executed tipset are stored in the
+//! [index store](super::index_store), keyed by epoch and emitter, so that the events of an actor over a range of
+//! epochs are found without scanning other actors. The topic of an event is
+//! the value of its `t1` entry, i.e. the event signature of `FEVM` logs, and
+//! is used to filter the events of an emitter.
+//!
+//! Like the [rewards index](super::rewards), only tipsets executed by this
+//! node are indexed, as long as the state roots of their epochs, and queries
+//! are restricted to canonical tipsets.
+
+use std::ops::RangeInclusive;
+
+use crate::blocks::{Tipset, TipsetKeys};
+use crate::chain::index::ResolveNullTipset;
+use crate::message::ChainMessage;
+use crate::shim::{
+    address::Address,
+    clock::ChainEpoch,
+    executor::{ApplyRet, StampedEvent},
+    state_tree::ActorID,
+};
+use crate::state_manager::index_store::{self, IndexBatch};
+use crate::state_manager::StateManager;
+use ahash::HashMap;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use serde::{Deserialize, Serialize};
+
+/// Key of the entry holding the topic of an event.
+pub const TOPIC_KEY: &str = "t1";
+
+fn events_key(epoch: ChainEpoch, emitter: ActorID) -> String {
+    index_store::index_key(format_args!("events/{epoch}/{emitter}"))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EventEntry {
+    pub flags: u64,
+    pub key: String,
+    pub codec: u64,
+    #[serde(with = "crate::lotus_json")]
+    pub value: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct IndexedEvent {
+    pub epoch: ChainEpoch,
+    #[serde(with = "crate::lotus_json")]
+    pub tipset: TipsetKeys,
+    /// Message that emitted the event.
+    #[serde(with = "crate::lotus_json")]
+    pub message: Cid,
+    /// Execution order of the message in the tipset.
+    pub message_index: u64,
+    /// Order of the event in the events of the message.
+    pub event_index: u64,
+    pub emitter: ActorID,
+    #[serde(with = "crate::lotus_json")]
+    pub topic: Option<Vec<u8>>,
+    pub entries: Vec<EventEntry>,
+}
+
+/// Collects the events of the messages of a tipset as they are applied.
+#[derive(Debug, Default)]
+pub(super) struct EventCollector {
+    message_index: u64,
+    events: Vec<(Cid, u64, Vec<StampedEvent>)>,
+}
+
+impl EventCollector {
+    /// Implicit messages, i.e. rewards and cron, have no receipt in the
+    /// tipset and are skipped.
+    pub fn observe(&mut self, cid: &Cid, message: &ChainMessage, apply_ret: &ApplyRet) {
+        if message.message().from == Address::SYSTEM_ACTOR {
+            return;
+        }
+        let events = apply_ret.events();
+        if !events.is_empty() {
+            self.events.push((*cid, self.message_index, events));
+        }
+        self.message_index += 1;
+    }
+
+    fn into_events(self, tipset: &Tipset) -> Vec<IndexedEvent> {
+        let mut indexed = vec![];
+        for (message, message_index, events) in self.events {
+            for (event_index, stamped) in events.into_iter().enumerate() {
+                let entries: Vec<EventEntry> = stamped
+                    .event
+                    .entries
+                    .into_iter()
+                    .map(|entry| EventEntry {
+                        flags: entry.flags.bits(),
+                        key: entry.key,
+                        codec: entry.codec,
+                        value: entry.value,
+                    })
+                    .collect();
+                indexed.push(IndexedEvent {
+                    epoch: tipset.epoch(),
+                    tipset: tipset.key().clone(),
+                    message,
+                    message_index,
+                    event_index: event_index as u64,
+                    emitter: stamped.emitter,
+                    topic: entries
+                        .iter()
+                        .find(|entry| entry.key == TOPIC_KEY)
+                        .map(|entry| entry.value.clone()),
+                    entries,
+                });
+            }
+        }
+        indexed
+    }
+}
+
+impl<DB> StateManager<DB>
+where
+    DB: Blockstore,
+{
+    pub(super) fn index_events(
+        &self,
+        tipset: &Tipset,
+        collector: EventCollector,
+    ) -> anyhow::Result<()> {
+        let mut by_emitter: HashMap<ActorID, Vec<IndexedEvent>> = HashMap::default();
+        for event in collector.into_events(tipset) {
+            by_emitter.entry(event.emitter).or_default().push(event);
+        }
+        let mut batch = IndexBatch::default();
+        for (emitter, events) in by_emitter {
+            batch.push(events_key(tipset.epoch(), emitter), tipset, &events)?;
+        }
+        self.indices.write(batch);
+        Ok(())
+    }
+
+    /// Returns the indexed events emitted by the actor `emitter` in the
+    /// canonical tipsets of `epochs`, optionally restricted to a `topic`. The
+    /// range spans at most [`index_store::MAX_INDEX_QUERY_EPOCHS`].
+    pub fn actor_events(
+        &self,
+        emitter: ActorID,
+        epochs: RangeInclusive<ChainEpoch>,
+        topic: Option<&[u8]>,
+    ) -> anyhow::Result<Vec<IndexedEvent>> {
+        index_store::ensure_query_range(&epochs)?;
+        let chain_index = &self.chain_store().chain_index;
+        let heaviest = self.chain_store().heaviest_tipset();
+        let end = chain_index.tipset_by_height(
+            (*epochs.end()).min(heaviest.epoch()),
+            heaviest,
+            ResolveNullTipset::TakeOlder,
+        )?;
+        let settings = self.chain_store().settings();
+        let mut events = vec![];
+        for tipset in chain_index
+            .chain(end)
+            .take_while(|tipset| tipset.epoch() >= *epochs.start())
+        {
+            let entries = index_store::read_entries::<Vec<IndexedEvent>>(
+                settings.as_ref(),
+                &events_key(tipset.epoch(), emitter),
+            )?;
+            let mut tipset_events: Vec<IndexedEvent> = entries
+                .into_iter()
+                .filter(|entry| &entry.tipset == tipset.key())
+                .flat_map(|entry| entry.value)
+                .filter(|event| topic.is_none() || event.topic.as_deref() == topic)
+                .collect();
+            tipset_events.sort_by_key(|event| (event.message_index, event.event_index));
+            events.extend(tipset_events.into_iter().rev());
+        }
+        events.reverse();
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::blocks::BlockHeader;
+    use crate::chain::ChainStore;
+    use crate::db::MemoryDB;
+    use crate::networks::ChainConfig;
+    use crate::shim::message::Message;
+    use fvm3::executor::ApplyRet as ApplyRet_v3;
+    use fvm_shared3::event::{ActorEvent, Entry, Flags};
+    use fvm_shared3::receipt::Receipt;
+
+    fn apply_ret(events: Vec<StampedEvent>) -> ApplyRet {
+        ApplyRet_v3 {
+            msg_receipt: Receipt {
+                exit_code: fvm_shared3::error::ExitCode::OK,
+                return_data: Default::default(),
+                gas_used: 0,
+                events_root: None,
+            },
+            penalty: Default::default(),
+            miner_tip: Default::default(),
+            base_fee_burn: Default::default(),
+            over_estimation_burn: Default::default(),
+            refund: Default::default(),
+            gas_refund: 0,
+            gas_burned: 0,
+            failure_info: None,
+            exec_trace: vec![],
+            events,
+        }
+        .into()
+    }
+
+    fn event(emitter: ActorID, topic: &[u8]) -> StampedEvent {
+        let entry = |key: &str, value: &[u8]| Entry {
+            flags: Flags::FLAG_INDEXED_ALL,
+            key: key.into(),
+            codec: fvm_ipld_encoding::IPLD_RAW,
+            value: value.to_vec(),
+        };
+        StampedEvent::new(
+            emitter,
+            ActorEvent::from(vec![entry(TOPIC_KEY, topic), entry("d", b"data")]),
+        )
+    }
+
+    #[test]
+    fn index_and_query() {
+        let db = Arc::new(MemoryDB::default());
+        let genesis = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .timestamp(7777)
+            .build()
+            .unwrap();
+        crate::chain::persist_objects(&db, &[&genesis]).unwrap();
+        let chain_config = Arc::new(ChainConfig::default());
+        let chain_store = Arc::new(
+            ChainStore::new(db.clone(), db, chain_config.clone(), genesis.clone()).unwrap(),
+        );
+        let state_manager = StateManager::new(chain_store, chain_config).unwrap();
+        let genesis = Tipset::from(genesis);
+
+        let message = |sequence| {
+            ChainMessage::Unsigned(Message {
+                from: Address::new_id(100),
+                sequence,
+                ..Default::default()
+            })
+        };
+        let mut collector = EventCollector::default();
+        let (first, second) = (message(0), message(1));
+        collector.observe(
+            &first.cid().unwrap(),
+            &first,
+            &apply_ret(vec![event(1000, b"a"), event(1001, b"a")]),
+        );
+        collector.observe(
+            &second.cid().unwrap(),
+            &second,
+            &apply_ret(vec![event(1000, b"b")]),
+        );
+        state_manager.index_events(&genesis, collector).unwrap();
+        state_manager.flush_indices();
+
+        let events = state_manager.actor_events(1000, 0..=10, None).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| (event.message_index, event.topic.clone()))
+                .collect::<Vec<_>>(),
+            vec![(0, Some(b"a".to_vec())), (1, Some(b"b".to_vec()))]
+        );
+        assert_eq!(events[1].message, second.cid().unwrap());
+        assert_eq!(
+            state_manager
+                .actor_events(1000, 0..=10, Some(b"b"))
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(state_manager
+            .actor_events(1002, 0..=10, None)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,233 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Bytecode and storage of EVM actors, for the `eth_getCode` and
+//! `eth_getStorageAt` methods.
+//!
+//! The bytecode is read from the state of the actor. The storage is a `KAMT`
+//! read by the actor itself, through its `GetStorageAt` method called by the
+//! system actor, as in Lotus. Both are read from the parent state of a tipset,
+//! like the other state methods, and are cached by actor and state root of the
+//! actor, which only changes when the contract does.
+
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+
+use crate::blocks::Tipset;
+use crate::shim::{
+    address::Address,
+    econ::BLOCK_GAS_LIMIT,
+    message::Message,
+    state_tree::{ActorID, StateTree},
+};
+use crate::state_manager::StateManager;
+use anyhow::Context as _;
+use cid::Cid;
+use fil_actor_interface::evm;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::{BytesDe, BytesSer, RawBytes};
+use lru::LruCache;
+use nonzero_ext::nonzero;
+use parking_lot::Mutex;
+
+const EVM_CACHE_SIZE: NonZeroUsize = nonzero!(4096usize);
+
+/// Cache of the bytecode and storage of EVM actors, keyed by actor and state
+/// root of the actor.
+#[allow(clippy::type_complexity)]
+pub(super) struct EvmCache {
+    bytecode: Mutex<LruCache<(ActorID, Cid), Arc<Vec<u8>>>>,
+    storage: Mutex<LruCache<(ActorID, Cid, [u8; 32]), [u8; 32]>>,
+}
+
+impl Default for EvmCache {
+    fn default() -> Self {
+        Self {
+            bytecode: Mutex::new(LruCache::new(EVM_CACHE_SIZE)),
+            storage: Mutex::new(LruCache::new(EVM_CACHE_SIZE)),
+        }
+    }
+}
+
+/// EVM actor found at an address.
+struct EvmActor {
+    id: ActorID,
+    /// State root of the actor.
+    head: Cid,
+    state: evm::State,
+}
+
+impl EvmActor {
+    fn bytecode(&self) -> Cid {
+        match &self.state {
+            evm::State::V10(st) => st.bytecode,
+            evm::State::V11(st) => st.bytecode,
+        }
+    }
+
+    /// Whether the contract self-destructed.
+    fn is_dead(&self) -> bool {
+        match &self.state {
+            evm::State::V10(st) => st.tombstone.is_some(),
+            evm::State::V11(st) => st.tombstone.is_some(),
+        }
+    }
+}
+
+impl<DB> StateManager<DB>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    /// Returns the live EVM actor at `address` in the parent state of
+    /// `tipset`, if any.
+    fn evm_actor(&self, address: &Address, tipset: &Tipset) -> anyhow::Result<Option<EvmActor>> {
+        let state = StateTree::new_from_root(self.blockstore_owned(), tipset.parent_state())?;
+        let (Some(id), Some(actor)) = (state.lookup_id(address)?, state.get_actor(address)?)
+        else {
+            return Ok(None);
+        };
+        if !evm::is_v10_evm_cid(&actor.code) && !evm::is_v11_evm_cid(&actor.code) {
+            return Ok(None);
+        }
+        let actor = EvmActor {
+            id,
+            head: actor.state,
+            state: evm::State::load(self.blockstore(), actor.code, actor.state)?,
+        };
+        Ok((!actor.is_dead()).then_some(actor))
+    }
+
+    /// Returns the bytecode of the contract at `address` in the parent state
+    /// of `tipset`. Addresses that aren't contracts have no bytecode.
+    pub fn evm_bytecode(&self, address: &Address, tipset: &Tipset) -> anyhow::Result<Arc<Vec<u8>>> {
+        let Some(actor) = self.evm_actor(address, tipset)? else {
+            return Ok(Default::default());
+        };
+        let key = (actor.id, actor.head);
+        if let Some(bytecode) = self.evm_cache.bytecode.lock().get(&key) {
+            return Ok(bytecode.clone());
+        }
+        let bytecode = Arc::new(
+            self.blockstore()
+                .get(&actor.bytecode())?
+                .with_context(|| format!("Bytecode of {address} not found"))?,
+        );
+        self.evm_cache.bytecode.lock().put(key, bytecode.clone());
+        Ok(bytecode)
+    }
+
+    /// Returns the value at `position` in the storage of the contract at
+    /// `address`, in the parent state of `tipset`. The storage of addresses
+    /// that aren't contracts is empty.
+    pub fn evm_storage_at(
+        self: &Arc<Self>,
+        address: &Address,
+        position: [u8; 32],
+        tipset: Arc<Tipset>,
+    ) -> anyhow::Result<[u8; 32]> {
+        let Some(actor) = self.evm_actor(address, &tipset)? else {
+            return Ok([0; 32]);
+        };
+        let key = (actor.id, actor.head, position);
+        if let Some(value) = self.evm_cache.storage.lock().get(&key) {
+            return Ok(*value);
+        }
+        // Storage keys and values are big-endian integers, encoded without
+        // their leading zeros.
+        let leading_zeros = position.iter().take_while(|byte| **byte == 0).count();
+        let mut message = Message {
+            from: Address::SYSTEM_ACTOR,
+            to: Address::new_id(actor.id),
+            method_num: evm::Method::GetStorageAt as u64,
+            params: RawBytes::serialize((BytesSer(&position[leading_zeros..]),))?,
+            gas_limit: BLOCK_GAS_LIMIT,
+            ..Default::default()
+        };
+        let result = self.call(&mut message, Some(tipset))?;
+        let receipt = result.msg_rct.context("GetStorageAt has no receipt")?;
+        anyhow::ensure!(
+            receipt.exit_code().is_success(),
+            "GetStorageAt failed: {}",
+            result
+                .error
+                .unwrap_or_else(|| receipt.exit_code().to_string())
+        );
+        let BytesDe(bytes) = fvm_ipld_encoding::from_slice(&receipt.return_data())?;
+        anyhow::ensure!(bytes.len() <= 32, "Invalid storage value");
+        let mut value = [0; 32];
+        value[32 - bytes.len()..].copy_from_slice(&bytes);
+        self.evm_cache.storage.lock().put(key, value);
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::BlockHeader;
+    use crate::chain::ChainStore;
+    use crate::db::MemoryDB;
+    use crate::networks::ChainConfig;
+    use crate::shim::state_tree::{ActorState, StateTreeVersion};
+    use crate::utils::db::CborStoreExt;
+    use cid::multihash::{Code, MultihashDigest};
+    use fil_actor_interface::KNOWN_CIDS;
+
+    #[test]
+    fn bytecode_of_contracts() {
+        let db = Arc::new(MemoryDB::default());
+        let bytecode = vec![0x60, 0x80, 0x60, 0x40];
+        let bytecode_cid = Cid::new_v1(
+            fvm_ipld_encoding::IPLD_RAW,
+            Code::Blake2b256.digest(&bytecode),
+        );
+        db.put_keyed(&bytecode_cid, &bytecode).unwrap();
+        let evm_state = |tombstone: Option<(ActorID, u64)>| {
+            db.put_cbor_default(&(
+                bytecode_cid,
+                BytesSer(&[0; 32]),
+                Cid::default(),
+                0,
+                tombstone,
+            ))
+            .unwrap()
+        };
+        let evm_code = KNOWN_CIDS.actor.evm.v11.mainnet;
+        let (contract, dead, account) = (
+            Address::new_id(1000),
+            Address::new_id(1001),
+            Address::new_id(1002),
+        );
+        let mut tree = StateTree::new(db.clone(), StateTreeVersion::V5).unwrap();
+        let mut actor = ActorState::new_empty(evm_code, None);
+        actor.state = evm_state(None);
+        tree.set_actor(&contract, actor).unwrap();
+        let mut actor = ActorState::new_empty(evm_code, None);
+        actor.state = evm_state(Some((100, 1)));
+        tree.set_actor(&dead, actor).unwrap();
+        tree.set_actor(&account, ActorState::new_empty(Cid::default(), None))
+            .unwrap();
+
+        let genesis = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .timestamp(7777)
+            .state_root(tree.flush().unwrap())
+            .build()
+            .unwrap();
+        let chain_config = Arc::new(ChainConfig::default());
+        let chain_store = Arc::new(
+            ChainStore::new(db.clone(), db, chain_config.clone(), genesis.clone()).unwrap(),
+        );
+        let state_manager = StateManager::new(chain_store, chain_config).unwrap();
+        let genesis = Tipset::from(genesis);
+
+        let code = |address| state_manager.evm_bytecode(&address, &genesis).unwrap();
+        assert_eq!(*code(contract), bytecode);
+        // The second read hits the cache.
+        assert_eq!(*code(contract), bytecode);
+        assert_eq!(state_manager.evm_cache.bytecode.lock().len(), 1);
+        assert!(code(dead).is_empty());
+        assert!(code(account).is_empty());
+        assert!(code(Address::new_id(2000)).is_empty());
+    }
+}
@@ -0,0 +1,312 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Execution traces in the JSON format of the `ExecutionTrace` returned by
+//! the `StateReplay` method of Lotus, so that the traces of both
+//! implementations can be diffed when investigating consensus or gas
+//! discrepancies.
+//!
+//! The trace is built from the [`TraceEvent`]s of the message the same way
+//! `filecoin-ffi` builds it for Lotus: the gas charged before the first call
+//! is attributed to it, and the events following its return are ignored. Gas
+//! charges aren't timed, so their `tt` is always `0`, and empty byte strings
+//! and lists are `null`, like the `nil` slices of Lotus.
+
+use crate::shim::{
+    address::Address,
+    econ::TokenAmount,
+    executor::{CallError, TraceEvent},
+};
+use fvm_shared3::error::{ErrorNumber, ExitCode};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Trace of a call and of its subcalls.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ExecutionTrace {
+    pub msg: MessageTrace,
+    pub msg_rct: ReturnTrace,
+    #[serde(with = "null_if_empty")]
+    pub gas_charges: Vec<GasTrace>,
+    #[serde(with = "null_if_empty")]
+    pub subcalls: Vec<ExecutionTrace>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MessageTrace {
+    #[serde(with = "crate::lotus_json")]
+    pub from: Address,
+    #[serde(with = "crate::lotus_json")]
+    pub to: Address,
+    #[serde(with = "crate::lotus_json")]
+    pub value: TokenAmount,
+    pub method: u64,
+    #[serde(with = "crate::lotus_json")]
+    pub params: Option<Vec<u8>>,
+    pub params_codec: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ReturnTrace {
+    pub exit_code: ExitCode,
+    #[serde(with = "crate::lotus_json")]
+    pub r#return: Option<Vec<u8>>,
+    pub return_codec: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GasTrace {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "tg")]
+    pub total_gas: u64,
+    #[serde(rename = "cg")]
+    pub compute_gas: u64,
+    #[serde(rename = "sg")]
+    pub storage_gas: u64,
+    /// Time taken by the charged operation, in nanoseconds.
+    #[serde(rename = "tt")]
+    pub time_taken: u64,
+}
+
+impl ExecutionTrace {
+    /// Builds the trace of a message from the events of its execution.
+    /// Returns `None` if there is no call, e.g. if the execution wasn't
+    /// traced.
+    pub fn from_events(events: &[TraceEvent]) -> anyhow::Result<Option<Self>> {
+        let mut initial_gas_charges = vec![];
+        // Calls that haven't returned yet, innermost last.
+        let mut calls: Vec<ExecutionTrace> = vec![];
+        for event in events {
+            match event {
+                TraceEvent::GasCharge {
+                    name,
+                    gas,
+                    compute_gas,
+                    storage_gas,
+                } => {
+                    let charge = GasTrace {
+                        name: name.clone(),
+                        total_gas: *gas,
+                        compute_gas: *compute_gas,
+                        storage_gas: *storage_gas,
+                        time_taken: 0,
+                    };
+                    match calls.last_mut() {
+                        Some(call) => call.gas_charges.push(charge),
+                        None => initial_gas_charges.push(charge),
+                    }
+                }
+                TraceEvent::Call {
+                    from,
+                    to,
+                    method,
+                    value,
+                    params,
+                    params_codec,
+                } => {
+                    let gas_charges = match calls.is_empty() {
+                        true => std::mem::take(&mut initial_gas_charges),
+                        false => vec![],
+                    };
+                    calls.push(ExecutionTrace {
+                        msg: MessageTrace {
+                            from: Address::new_id(*from),
+                            to: *to,
+                            value: value.clone(),
+                            method: *method,
+                            params: non_empty(params),
+                            params_codec: *params_codec,
+                        },
+                        msg_rct: ReturnTrace {
+                            exit_code: ExitCode::OK,
+                            r#return: None,
+                            return_codec: 0,
+                        },
+                        gas_charges,
+                        subcalls: vec![],
+                    });
+                }
+                TraceEvent::CallReturn { .. } | TraceEvent::CallError(_) => {
+                    let mut call = calls
+                        .pop()
+                        .ok_or_else(|| anyhow::anyhow!("return without a call"))?;
+                    call.msg_rct = match event {
+                        TraceEvent::CallReturn {
+                            exit_code,
+                            return_data,
+                            return_codec,
+                        } => ReturnTrace {
+                            exit_code: *exit_code,
+                            r#return: non_empty(return_data),
+                            return_codec: *return_codec,
+                        },
+                        _ => ReturnTrace {
+                            exit_code: call_error_exit_code(event),
+                            r#return: None,
+                            return_codec: 0,
+                        },
+                    };
+                    match calls.last_mut() {
+                        Some(parent) => parent.subcalls.push(call),
+                        None => return Ok(Some(call)),
+                    }
+                }
+            }
+        }
+        anyhow::ensure!(calls.is_empty(), "call without a return");
+        Ok(None)
+    }
+}
+
+fn non_empty(bytes: &[u8]) -> Option<Vec<u8>> {
+    (!bytes.is_empty()).then(|| bytes.to_vec())
+}
+
+/// Exit code of a call that failed before the callee could return, as
+/// reported by `filecoin-ffi` for the error number of the syscall error.
+fn call_error_exit_code(event: &TraceEvent) -> ExitCode {
+    match event {
+        TraceEvent::CallError(CallError { number, .. })
+            if *number == ErrorNumber::InsufficientFunds as u32 =>
+        {
+            ExitCode::SYS_INSUFFICIENT_FUNDS
+        }
+        TraceEvent::CallError(CallError { number, .. })
+            if *number == ErrorNumber::NotFound as u32 =>
+        {
+            ExitCode::SYS_INVALID_RECEIVER
+        }
+        _ => ExitCode::SYS_ASSERTION_FAILED,
+    }
+}
+
+/// Empty lists are `null`, like `nil` slices in Go.
+mod null_if_empty {
+    use super::*;
+
+    pub fn serialize<S, T>(value: &Vec<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Serialize,
+    {
+        match value.is_empty() {
+            true => serializer.serialize_none(),
+            false => value.serialize(serializer),
+        }
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de>,
+    {
+        Ok(Option::deserialize(deserializer)?.unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn gas(gas: u64) -> TraceEvent {
+        TraceEvent::GasCharge {
+            name: "OnMethodInvocation".into(),
+            gas,
+            compute_gas: gas,
+            storage_gas: 0,
+        }
+    }
+
+    fn call(from: u64, to: u64, params: Vec<u8>) -> TraceEvent {
+        TraceEvent::Call {
+            from,
+            to: Address::new_id(to),
+            method: 2,
+            value: TokenAmount::from_atto(10),
+            params_codec: if params.is_empty() { 0 } else { 0x51 },
+            params,
+        }
+    }
+
+    #[test]
+    fn lotus_json() {
+        let events = vec![
+            gas(5),
+            call(100, 200, vec![0x80]),
+            gas(10),
+            call(200, 300, vec![]),
+            TraceEvent::CallError(CallError {
+                message: "no such actor".into(),
+                number: ErrorNumber::NotFound as u32,
+            }),
+            TraceEvent::CallReturn {
+                exit_code: ExitCode::USR_FORBIDDEN,
+                return_data: vec![0x80],
+                return_codec: 0x51,
+            },
+            // Ignored, as in Lotus.
+            gas(20),
+        ];
+        let trace = ExecutionTrace::from_events(&events).unwrap().unwrap();
+        let expected = json!({
+            "Msg": {
+                "From": "f0100",
+                "To": "f0200",
+                "Value": "10",
+                "Method": 2,
+                "Params": "gA==",
+                "ParamsCodec": 0x51
+            },
+            "MsgRct": {"ExitCode": 18, "Return": "gA==", "ReturnCodec": 0x51},
+            "GasCharges": [
+                {"Name": "OnMethodInvocation", "tg": 5, "cg": 5, "sg": 0, "tt": 0},
+                {"Name": "OnMethodInvocation", "tg": 10, "cg": 10, "sg": 0, "tt": 0}
+            ],
+            "Subcalls": [{
+                "Msg": {
+                    "From": "f0200",
+                    "To": "f0300",
+                    "Value": "10",
+                    "Method": 2,
+                    "Params": null,
+                    "ParamsCodec": 0
+                },
+                "MsgRct": {"ExitCode": 5, "Return": null, "ReturnCodec": 0},
+                "GasCharges": null,
+                "Subcalls": null
+            }]
+        });
+        assert_eq!(serde_json::to_value(&trace).unwrap(), expected);
+        assert_eq!(
+            serde_json::from_value::<ExecutionTrace>(expected).unwrap(),
+            trace
+        );
+    }
+
+    #[test]
+    fn incomplete_traces() {
+        assert_eq!(ExecutionTrace::from_events(&[gas(5)]).unwrap(), None);
+        assert!(ExecutionTrace::from_events(&[call(100, 200, vec![])]).is_err());
+    }
+
+    #[test]
+    fn call_errors_recorded_before_their_number() {
+        let event: TraceEvent = serde_json::from_value(json!({
+            "CallError": "SyscallError(\"insufficient funds\", InsufficientFunds)"
+        }))
+        .unwrap();
+        assert_eq!(
+            call_error_exit_code(&event),
+            ExitCode::SYS_INSUFFICIENT_FUNDS
+        );
+        let reserialized = serde_json::to_value(&event).unwrap();
+        assert_eq!(
+            serde_json::from_value::<TraceEvent>(reserialized).unwrap(),
+            event
+        );
+    }
+}
@@ -0,0 +1,467 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Forensic bundles of state root mismatches.
+//!
+//! When the state computed for a tipset doesn't match the state root declared
+//! by a block built on it, the evidence is dumped into a directory named
+//! `<epoch>-<block cid>`:
+//! - `traces.json`: the executed messages with their receipts and execution
+//!   traces,
+//! - `actors.json`: the actors that differ between both state roots,
+//! - `report.json`: a [`StateMismatchReport`]. It is written last, so only
+//!   complete bundles are listed.
+//!
+//! Bundles are dumped in the background, one at a time, and only the latest
+//! [`MAX_BUNDLES`] are kept.
+//!
+//! A single suspect tipset can also be executed again and compared against
+//! the results declared on chain, see [`StateManager::reexecute_tipset`].
+
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+};
+
+use crate::blocks::{BlockHeader, Tipset, TipsetKeys};
+use crate::interpreter::VMTrace;
+use crate::message::ChainMessage;
+use crate::shim::{
+    address::Address,
+    clock::ChainEpoch,
+    executor::{ApplyRet, Receipt, TraceEvent},
+    message::Message,
+};
+use crate::state_manager::{
+    diff::diff_state_trees, execution_trace::ExecutionTrace, CidPair, StateManager,
+};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+const REPORT_FILE: &str = "report.json";
+const TRACES_FILE: &str = "traces.json";
+const ACTORS_FILE: &str = "actors.json";
+/// Maximum number of bundles in the forensics directory, the oldest ones are
+/// removed first.
+pub const MAX_BUNDLES: usize = 16;
+/// Maximum size of a file of a bundle. Execution traces that don't fit are
+/// left out, other files are skipped.
+const MAX_FILE_BYTES: usize = 64 << 20;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct StateMismatchReport {
+    /// Epoch of the block that declared the state root.
+    pub epoch: ChainEpoch,
+    #[serde(with = "crate::lotus_json")]
+    pub block: Cid,
+    #[serde(with = "crate::lotus_json")]
+    pub parent_tipset: TipsetKeys,
+    #[serde(with = "crate::lotus_json")]
+    pub expected_state_root: Cid,
+    #[serde(with = "crate::lotus_json")]
+    pub computed_state_root: Cid,
+    #[serde(with = "crate::lotus_json")]
+    pub expected_receipt_root: Cid,
+    #[serde(with = "crate::lotus_json")]
+    pub computed_receipt_root: Cid,
+    pub created: chrono::DateTime<chrono::Utc>,
+    /// Directory of the bundle.
+    pub path: PathBuf,
+    /// Parts of the bundle that could not be collected.
+    pub errors: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct MessageTrace {
+    #[serde(with = "crate::lotus_json")]
+    cid: Cid,
+    #[serde(with = "crate::lotus_json")]
+    message: Message,
+    #[serde(with = "crate::lotus_json")]
+    receipt: Receipt,
+    failure_info: Option<String>,
+    execution_trace: Option<ExecutionTrace>,
+}
+
+/// Tipset executed again, and the results declared by a block built on it.
+#[derive(Debug, Clone)]
+pub struct Reexecution {
+    pub epoch: ChainEpoch,
+    pub expected_state_root: Cid,
+    pub computed_state_root: Cid,
+    pub expected_receipt_root: Cid,
+    pub computed_receipt_root: Cid,
+    /// Explicit messages of the tipset, in execution order.
+    pub messages: Vec<ReexecutedMessage>,
+}
+
+impl Reexecution {
+    pub fn matches(&self) -> bool {
+        self.expected_state_root == self.computed_state_root
+            && self.expected_receipt_root == self.computed_receipt_root
+    }
+
+    /// Index of the first message whose receipt differs from the declared one.
+    pub fn first_divergence(&self) -> Option<usize> {
+        self.messages.iter().position(ReexecutedMessage::diverges)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReexecutedMessage {
+    pub cid: Cid,
+    pub receipt: Receipt,
+    /// Receipt declared on chain, `None` if it is missing.
+    pub expected_receipt: Option<Receipt>,
+    pub failure_info: Option<String>,
+    /// Empty unless the tipset is executed with traces.
+    pub trace: Vec<TraceEvent>,
+}
+
+impl ReexecutedMessage {
+    pub fn diverges(&self) -> bool {
+        // Receipts are decoded as `V2` and computed as `V3`, so compare the
+        // fields.
+        let fields = |receipt: &Receipt| {
+            (
+                receipt.exit_code(),
+                receipt.return_data(),
+                receipt.gas_used(),
+            )
+        };
+        self.expected_receipt
+            .as_ref()
+            .map_or(true, |expected| fields(expected) != fields(&self.receipt))
+    }
+}
+
+fn write_json(path: &Path, value: &impl Serialize) -> anyhow::Result<()> {
+    serde_json::to_writer_pretty(File::create(path)?, value)?;
+    Ok(())
+}
+
+/// Writes `value` unless it is larger than [`MAX_FILE_BYTES`]. Returns whether
+/// the file was written.
+fn write_capped_json(path: &Path, value: &impl Serialize) -> anyhow::Result<bool> {
+    let json = serde_json::to_vec_pretty(value)?;
+    if json.len() > MAX_FILE_BYTES {
+        return Ok(false);
+    }
+    std::fs::write(path, json)?;
+    Ok(true)
+}
+
+/// Removes the bundles of `dir` with the lowest epochs until at most `max` are
+/// left.
+fn rotate_bundles(dir: &Path, max: usize) -> anyhow::Result<()> {
+    let mut bundles = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let epoch = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.split_once('-')?.0.parse::<ChainEpoch>().ok());
+        if let (Some(epoch), true) = (epoch, entry.metadata()?.is_dir()) {
+            bundles.push((epoch, entry.path()));
+        }
+    }
+    bundles.sort();
+    for (_, path) in bundles.iter().take(bundles.len().saturating_sub(max)) {
+        std::fs::remove_dir_all(path)?;
+        info!("Removed state mismatch report {}", path.display());
+    }
+    Ok(())
+}
+
+impl<DB> StateManager<DB>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    /// Directory forensic bundles are written to. Without it, state root
+    /// mismatches are only reported as validation errors.
+    pub fn with_forensics_dir(mut self, dir: PathBuf) -> Self {
+        self.forensics_dir = Some(dir);
+        self
+    }
+
+    /// Dumps a forensic bundle in a blocking task, see
+    /// [`StateManager::dump_state_mismatch`]. Mismatches found while another
+    /// bundle is dumped are skipped.
+    pub fn spawn_state_mismatch_dump(
+        self: &Arc<Self>,
+        parent: Arc<Tipset>,
+        block: BlockHeader,
+        computed: CidPair,
+    ) {
+        if self.forensics_dir.is_none() || self.forensics_running.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        // Also cleared if the dump panics.
+        let state_manager = scopeguard::guard(Arc::clone(self), |state_manager| {
+            state_manager
+                .forensics_running
+                .store(false, Ordering::Release)
+        });
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = state_manager.dump_state_mismatch(parent, &block, computed) {
+                warn!("Failed to write state mismatch report: {e}");
+            }
+        });
+    }
+
+    /// Dumps a forensic bundle for `block`, whose state root differs from
+    /// the `computed` state of `parent`. The messages of `parent` are executed
+    /// again to record their receipts and execution traces. Returns `None` if
+    /// no forensics directory is set or the bundle already exists.
+    pub fn dump_state_mismatch(
+        self: &Arc<Self>,
+        parent: Arc<Tipset>,
+        block: &BlockHeader,
+        (state_root, receipt_root): CidPair,
+    ) -> anyhow::Result<Option<StateMismatchReport>> {
+        let Some(dir) = &self.forensics_dir else {
+            return Ok(None);
+        };
+        let path = dir.join(format!("{}-{}", block.epoch(), block.cid()));
+        if path.join(REPORT_FILE).exists() {
+            return Ok(None);
+        }
+        std::fs::create_dir_all(&path)?;
+        let mut errors = vec![];
+
+        let (trace_tx, trace_rx) = std::sync::mpsc::channel();
+        let callback = move |cid: &Cid, message: &ChainMessage, apply_ret: &ApplyRet| {
+            trace_tx.send(MessageTrace {
+                cid: *cid,
+                message: message.message().clone(),
+                receipt: apply_ret.msg_receipt(),
+                failure_info: apply_ret.failure_info(),
+                execution_trace: ExecutionTrace::from_events(&apply_ret.exec_trace())?,
+            })?;
+            Ok(())
+        };
+        if let Err(e) =
+            self.compute_tipset_state_blocking(Arc::clone(&parent), Some(callback), VMTrace::Traced)
+        {
+            errors.push(format!("Failed to execute the parent tipset: {e}"));
+        }
+        let mut traces = trace_rx.try_iter().collect::<Vec<_>>();
+        if !write_capped_json(&path.join(TRACES_FILE), &traces)? {
+            errors.push(format!(
+                "Execution traces left out, they exceed {MAX_FILE_BYTES} bytes"
+            ));
+            for trace in &mut traces {
+                trace.execution_trace = None;
+            }
+            if !write_capped_json(&path.join(TRACES_FILE), &traces)? {
+                errors.push(format!(
+                    "Receipts left out, they exceed {MAX_FILE_BYTES} bytes"
+                ));
+            }
+        }
+
+        // The expected state is usually missing locally, it can be fetched
+        // with `forest-cli state fetch`.
+        match diff_state_trees(&self.blockstore_owned(), block.state_root(), &state_root) {
+            Ok(actors) => {
+                if !write_capped_json(&path.join(ACTORS_FILE), &actors)? {
+                    errors.push(format!(
+                        "Actor diff left out, it exceeds {MAX_FILE_BYTES} bytes"
+                    ));
+                }
+            }
+            Err(e) => errors.push(format!("Failed to diff the state roots: {e}")),
+        }
+
+        let report = StateMismatchReport {
+            epoch: block.epoch(),
+            block: *block.cid(),
+            parent_tipset: parent.key().clone(),
+            expected_state_root: *block.state_root(),
+            computed_state_root: state_root,
+            expected_receipt_root: *block.message_receipts(),
+            computed_receipt_root: receipt_root,
+            created: chrono::Utc::now(),
+            path: path.clone(),
+            errors,
+        };
+        write_json(&path.join(REPORT_FILE), &report)?;
+        info!("State mismatch report written to {}", path.display());
+        rotate_bundles(dir, MAX_BUNDLES)?;
+        Ok(Some(report))
+    }
+
+    /// Executes `tipset` again and compares the results against those declared
+    /// by `child`, a block built on it. The receipts of the messages are
+    /// compared one by one, to find the first diverging message.
+    pub fn reexecute_tipset(
+        self: &Arc<Self>,
+        tipset: Arc<Tipset>,
+        child: &BlockHeader,
+        trace: VMTrace,
+    ) -> anyhow::Result<Reexecution> {
+        anyhow::ensure!(
+            child.parents() == tipset.key(),
+            "block {} isn't built on tipset {}",
+            child.cid(),
+            tipset.key()
+        );
+        let (message_tx, message_rx) = std::sync::mpsc::channel();
+        let callback = move |cid: &Cid, message: &ChainMessage, apply_ret: &ApplyRet| {
+            // Implicit messages have no receipts.
+            if message.message().from != Address::SYSTEM_ACTOR {
+                message_tx.send((*cid, apply_ret.clone()))?;
+            }
+            Ok(())
+        };
+        let (state_root, receipt_root) =
+            self.compute_tipset_state_blocking(Arc::clone(&tipset), Some(callback), trace)?;
+        let messages = message_rx
+            .try_iter()
+            .enumerate()
+            .map(|(index, (cid, apply_ret))| ReexecutedMessage {
+                cid,
+                receipt: apply_ret.msg_receipt(),
+                expected_receipt: crate::chain::get_parent_reciept(self.blockstore(), child, index)
+                    .ok()
+                    .flatten(),
+                failure_info: apply_ret.failure_info(),
+                trace: apply_ret.exec_trace(),
+            })
+            .collect();
+        Ok(Reexecution {
+            epoch: tipset.epoch(),
+            expected_state_root: *child.state_root(),
+            computed_state_root: state_root,
+            expected_receipt_root: *child.message_receipts(),
+            computed_receipt_root: receipt_root,
+            messages,
+        })
+    }
+
+    /// Lists the forensic bundles in the forensics directory, by epoch.
+    pub fn state_mismatch_reports(&self) -> anyhow::Result<Vec<StateMismatchReport>> {
+        let Some(dir) = &self.forensics_dir else {
+            return Ok(vec![]);
+        };
+        if !dir.is_dir() {
+            return Ok(vec![]);
+        }
+        let mut reports = vec![];
+        for entry in std::fs::read_dir(dir)? {
+            let report_path = entry?.path().join(REPORT_FILE);
+            let Ok(file) = File::open(&report_path) else {
+                continue;
+            };
+            match serde_json::from_reader(BufReader::new(file)) {
+                Ok(report) => reports.push(report),
+                Err(e) => warn!("Skipping {}: {e}", report_path.display()),
+            }
+        }
+        reports.sort_by_key(|report: &StateMismatchReport| report.epoch);
+        Ok(reports)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::ChainStore;
+    use crate::db::MemoryDB;
+    use crate::networks::ChainConfig;
+    use crate::shim::address::Address;
+
+    #[test]
+    fn lists_complete_bundles() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(MemoryDB::default());
+        let genesis = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .timestamp(7777)
+            .build()
+            .unwrap();
+        let chain_config = Arc::new(ChainConfig::default());
+        let chain_store = Arc::new(
+            ChainStore::new(db.clone(), db, chain_config.clone(), genesis.clone()).unwrap(),
+        );
+        let state_manager = StateManager::new(chain_store, chain_config)
+            .unwrap()
+            .with_forensics_dir(dir.path().into());
+        assert!(state_manager.state_mismatch_reports().unwrap().is_empty());
+
+        let report = StateMismatchReport {
+            epoch: 10,
+            block: *genesis.cid(),
+            parent_tipset: TipsetKeys::from(vec![*genesis.cid()]),
+            expected_state_root: Cid::default(),
+            computed_state_root: Cid::default(),
+            expected_receipt_root: Cid::default(),
+            computed_receipt_root: Cid::default(),
+            created: chrono::Utc::now(),
+            path: dir.path().join("10-genesis"),
+            errors: vec!["Failed to diff the state roots".into()],
+        };
+        std::fs::create_dir(&report.path).unwrap();
+        write_json(&report.path.join(REPORT_FILE), &report).unwrap();
+        // Bundles without a report are incomplete.
+        std::fs::create_dir(dir.path().join("11-incomplete")).unwrap();
+
+        assert_eq!(state_manager.state_mismatch_reports().unwrap(), [report]);
+    }
+
+    #[test]
+    fn oldest_bundles_are_rotated() {
+        let dir = tempfile::tempdir().unwrap();
+        for epoch in [4, 10, 3, 2, 1] {
+            std::fs::create_dir(dir.path().join(format!("{epoch}-bundle"))).unwrap();
+        }
+        rotate_bundles(dir.path(), 3).unwrap();
+        let mut left: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["10-bundle", "3-bundle", "4-bundle"]);
+    }
+
+    #[test]
+    fn first_diverging_message() {
+        use fvm_shared3::{error::ExitCode, receipt::Receipt as Receipt_v3};
+
+        let receipt = |gas_used| {
+            Receipt::from(Receipt_v3 {
+                exit_code: ExitCode::OK,
+                return_data: Default::default(),
+                gas_used,
+                events_root: None,
+            })
+        };
+        let message = |expected_receipt| ReexecutedMessage {
+            cid: Cid::default(),
+            receipt: receipt(10),
+            expected_receipt,
+            failure_info: None,
+            trace: vec![],
+        };
+        let mut reexecution = Reexecution {
+            epoch: 10,
+            expected_state_root: Cid::default(),
+            computed_state_root: Cid::default(),
+            expected_receipt_root: Cid::default(),
+            computed_receipt_root: Cid::default(),
+            messages: vec![message(Some(receipt(10))), message(Some(receipt(10)))],
+        };
+        assert!(reexecution.matches());
+        assert_eq!(reexecution.first_divergence(), None);
+
+        reexecution.messages.push(message(Some(receipt(11))));
+        reexecution.messages.push(message(None));
+        assert_eq!(reexecution.first_divergence(), Some(2));
+    }
+}
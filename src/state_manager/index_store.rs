@@ -0,0 +1,168 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Storage shared by the indices built when executing tipsets: the
+//! [rewards](super::rewards), [event](super::event_index),
+//! [message](super::message_index), [deal](super::deal_index) and
+//! [actor change](super::actor_changes) indices.
+//!
+//! Records are stored in the settings store under [`INDEX_KEY_PREFIX`], as
+//! arrays of [`IndexEntry`] values. A tipset executed again replaces its
+//! entries, and the garbage collector drops the records with the state roots
+//! of their epochs, so the indices cover the same epochs as the retained
+//! states.
+//!
+//! Entries are merged into the records by a dedicated thread rather than on
+//! the execution path, so queries may briefly lag behind the executed
+//! tipsets.
+
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use crate::blocks::Tipset;
+use crate::db::setting_keys::INDEX_KEY_PREFIX;
+use crate::db::{BatchStore, DbBatch, IndexEntry, SettingsStore, SettingsStoreExt};
+use crate::shim::clock::ChainEpoch;
+use serde::Serialize;
+use tracing::warn;
+
+/// Maximum number of epochs covered by a range query of an index, one day of
+/// the main network.
+pub const MAX_INDEX_QUERY_EPOCHS: ChainEpoch = 2880;
+
+/// Number of batches queued before executing tipsets waits for the writer.
+const INDEX_QUEUE_CAP: usize = 256;
+
+/// Returns the key of the index record `name`.
+pub fn index_key(name: impl std::fmt::Display) -> String {
+    format!("{INDEX_KEY_PREFIX}{name}")
+}
+
+/// Fails if `epochs` is wider than [`MAX_INDEX_QUERY_EPOCHS`].
+pub fn ensure_query_range(epochs: &RangeInclusive<ChainEpoch>) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        epochs.end().saturating_sub(*epochs.start()) < MAX_INDEX_QUERY_EPOCHS,
+        "epoch range {epochs:?} exceeds the maximum of {MAX_INDEX_QUERY_EPOCHS} epochs"
+    );
+    Ok(())
+}
+
+/// Reads the entries of the index record `key`.
+pub fn read_entries<T: serde::de::DeserializeOwned>(
+    settings: &dyn SettingsStore,
+    key: &str,
+) -> anyhow::Result<Vec<IndexEntry<T>>> {
+    Ok(settings.read_obj(key)?.unwrap_or_default())
+}
+
+/// Entries of a tipset, by index record key.
+#[derive(Default)]
+pub struct IndexBatch {
+    entries: Vec<(String, IndexEntry<serde_json::Value>)>,
+}
+
+impl IndexBatch {
+    /// Queues the entry of `tipset` in the record `key`.
+    pub fn push<T: Serialize>(
+        &mut self,
+        key: String,
+        tipset: &Tipset,
+        value: &T,
+    ) -> anyhow::Result<()> {
+        self.entries.push((
+            key,
+            IndexEntry {
+                epoch: tipset.epoch(),
+                tipset: tipset.key().clone(),
+                value: serde_json::to_value(value)?,
+            },
+        ));
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+enum Work {
+    Write(IndexBatch),
+    #[cfg(test)]
+    Flush(flume::Sender<()>),
+}
+
+/// Handle of the thread writing the index batches.
+pub struct IndexWriter {
+    work: flume::Sender<Work>,
+}
+
+impl IndexWriter {
+    /// Spawns the writer thread, which exits once the handle is dropped.
+    pub fn spawn(
+        settings: Arc<dyn SettingsStore + Sync + Send>,
+        batches: Arc<dyn BatchStore + Sync + Send>,
+    ) -> anyhow::Result<Self> {
+        let (work, queue) = flume::bounded(INDEX_QUEUE_CAP);
+        std::thread::Builder::new()
+            .name("index-writer".into())
+            .spawn(move || {
+                for work in queue.iter() {
+                    match work {
+                        Work::Write(batch) => {
+                            if let Err(e) = merge(settings.as_ref(), batches.as_ref(), batch) {
+                                warn!("Failed to write index entries: {e}");
+                            }
+                        }
+                        #[cfg(test)]
+                        Work::Flush(done) => {
+                            let _ = done.send(());
+                        }
+                    }
+                }
+            })?;
+        Ok(Self { work })
+    }
+
+    /// Queues `batch`, waiting if the writer is behind.
+    pub fn write(&self, batch: IndexBatch) {
+        if !batch.is_empty() && self.work.send(Work::Write(batch)).is_err() {
+            warn!("Index writer stopped, dropping index entries");
+        }
+    }
+
+    /// Waits until the batches queued so far are written.
+    #[cfg(test)]
+    pub fn flush(&self) {
+        let (done, wait) = flume::bounded(1);
+        if self.work.send(Work::Flush(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+}
+
+/// Merges `batch` into the stored records, replacing the previous entries of
+/// the same tipsets, and commits them at once.
+fn merge(
+    settings: &dyn SettingsStore,
+    batches: &dyn BatchStore,
+    batch: IndexBatch,
+) -> anyhow::Result<()> {
+    let mut records: ahash::HashMap<String, Vec<IndexEntry<serde_json::Value>>> =
+        Default::default();
+    for (key, entry) in batch.entries {
+        let record = match records.entry(key) {
+            std::collections::hash_map::Entry::Occupied(record) => record.into_mut(),
+            std::collections::hash_map::Entry::Vacant(record) => {
+                let mut entries = read_entries(settings, record.key())?;
+                entries.retain(|stored| stored.tipset != entry.tipset);
+                record.insert(entries)
+            }
+        };
+        record.push(entry);
+    }
+    let mut db_batch = DbBatch::default();
+    for (key, entries) in records {
+        db_batch.write_obj(&key, &entries)?;
+    }
+    batches.commit_batch(db_batch)
+}
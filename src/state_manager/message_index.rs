@@ -0,0 +1,197 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Index of the tipsets that included a message, used to replay messages.
+//!
+//! The messages of every executed tipset are stored in the
+//! [index store](super::index_store), keyed by message CID. Like the
+//! [rewards index](super::rewards), only tipsets executed by this node are
+//! indexed, as long as the state roots of their epochs. Messages that are missing from
+//! the index are searched backwards from the head of the chain instead.
+
+use std::sync::Arc;
+
+use crate::blocks::{Tipset, TipsetKeys};
+use crate::chain::index::ResolveNullTipset;
+use crate::message::{ChainMessage, Message as MessageTrait};
+use crate::shim::{
+    address::Address, clock::ChainEpoch, executor::Receipt, executor::TraceEvent, message::Message,
+};
+use crate::state_manager::index_store::{self, IndexBatch};
+use crate::state_manager::StateManager;
+use anyhow::Context as _;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use serde::{Deserialize, Serialize};
+
+fn inclusion_key(message: &Cid) -> String {
+    index_store::index_key(format_args!("message_inclusions/{message}"))
+}
+
+/// Result of [`StateManager::replay_message`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MessageReplay {
+    /// Epoch of the tipset that included the message.
+    pub epoch: ChainEpoch,
+    #[serde(with = "crate::lotus_json")]
+    pub tipset: TipsetKeys,
+    #[serde(with = "crate::lotus_json")]
+    pub message: Message,
+    #[serde(with = "crate::lotus_json")]
+    pub receipt: Receipt,
+    pub failure_info: Option<String>,
+    pub trace: Vec<TraceEvent>,
+}
+
+/// Collects the messages of a tipset as they are applied.
+#[derive(Debug, Default)]
+pub(super) struct MessageCollector {
+    pub messages: Vec<Cid>,
+}
+
+impl MessageCollector {
+    /// Implicit messages, i.e. rewards and cron, aren't included in the
+    /// tipset and are skipped.
+    pub fn observe(&mut self, cid: &Cid, message: &ChainMessage) {
+        if message.message().from != Address::SYSTEM_ACTOR {
+            self.messages.push(*cid);
+        }
+    }
+}
+
+impl<DB> StateManager<DB>
+where
+    DB: Blockstore,
+{
+    pub(super) fn index_messages(&self, tipset: &Tipset, messages: &[Cid]) -> anyhow::Result<()> {
+        let mut batch = IndexBatch::default();
+        for message in messages {
+            batch.push(inclusion_key(message), tipset, &())?;
+        }
+        self.indices.write(batch);
+        Ok(())
+    }
+
+    /// Returns whether `tipset` is on the chain of the heaviest tipset.
+    pub(super) fn is_canonical(&self, tipset: &Arc<Tipset>) -> anyhow::Result<bool> {
+        let heaviest = self.chain_store().heaviest_tipset();
+        if tipset.epoch() > heaviest.epoch() {
+            return Ok(false);
+        }
+        let canonical = self.chain_store().chain_index.tipset_by_height(
+            tipset.epoch(),
+            heaviest,
+            ResolveNullTipset::TakeOlder,
+        )?;
+        Ok(canonical.key() == tipset.key())
+    }
+}
+
+impl<DB> StateManager<DB>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    /// Returns the indexed tipset that included `message`. A message can be
+    /// included in several forks, the canonical tipset is preferred.
+    pub fn message_inclusion(&self, message: &Cid) -> anyhow::Result<Option<Arc<Tipset>>> {
+        let entries = index_store::read_entries::<()>(
+            self.chain_store().settings().as_ref(),
+            &inclusion_key(message),
+        )?;
+        let mut fork = None;
+        for entry in entries.iter().rev() {
+            let Ok(tipset) = self.chain_store().tipset_from_keys(&entry.tipset) else {
+                continue;
+            };
+            if self.is_canonical(&tipset)? {
+                return Ok(Some(tipset));
+            }
+            fork.get_or_insert(tipset);
+        }
+        Ok(fork)
+    }
+
+    /// Executes the tipset that included `message` again, up to the message,
+    /// and returns the execution trace of the message.
+    pub async fn replay_message(self: &Arc<Self>, message: Cid) -> anyhow::Result<MessageReplay> {
+        let tipset = match self.message_inclusion(&message)? {
+            Some(tipset) => tipset,
+            None => self
+                .search_message_inclusion(message)?
+                .with_context(|| format!("message {message} was not found on chain"))?,
+        };
+        let (unsigned, apply_ret) = self.replay(&tipset, message).await?;
+        Ok(MessageReplay {
+            epoch: tipset.epoch(),
+            tipset: tipset.key().clone(),
+            message: unsigned,
+            receipt: apply_ret.msg_receipt(),
+            failure_info: apply_ret.failure_info(),
+            trace: apply_ret.exec_trace(),
+        })
+    }
+
+    /// Searches the chain backwards from the head. The receipt of a message
+    /// is in the child of the tipset that included it.
+    fn search_message_inclusion(self: &Arc<Self>, cid: Cid) -> anyhow::Result<Option<Arc<Tipset>>> {
+        let message = crate::chain::get_chain_message(self.blockstore(), &cid)?;
+        let found = self.search_back_for_message(
+            self.chain_store().heaviest_tipset(),
+            (&message.from(), &cid, &message.sequence()),
+        )?;
+        match found {
+            Some((child, _)) => Ok(Some(self.chain_store().tipset_from_keys(child.parents())?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::BlockHeader;
+    use crate::chain::ChainStore;
+    use crate::db::MemoryDB;
+    use crate::networks::ChainConfig;
+    use crate::shim::address::Address;
+
+    #[test]
+    fn index_and_lookup() {
+        let db = Arc::new(MemoryDB::default());
+        let genesis = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .timestamp(7777)
+            .build()
+            .unwrap();
+        crate::chain::persist_objects(&db, &[&genesis]).unwrap();
+        let chain_config = Arc::new(ChainConfig::default());
+        let chain_store = Arc::new(
+            ChainStore::new(db.clone(), db, chain_config.clone(), genesis.clone()).unwrap(),
+        );
+        let state_manager = StateManager::new(chain_store, chain_config).unwrap();
+        let genesis = Tipset::from(genesis);
+
+        let message = Cid::default();
+        assert!(state_manager.message_inclusion(&message).unwrap().is_none());
+
+        state_manager.index_messages(&genesis, &[message]).unwrap();
+        // Indexing the same tipset twice is a no-op.
+        state_manager.index_messages(&genesis, &[message]).unwrap();
+        state_manager.flush_indices();
+        let entries = index_store::read_entries::<()>(
+            state_manager.chain_store().settings().as_ref(),
+            &inclusion_key(&message),
+        )
+        .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            state_manager
+                .message_inclusion(&message)
+                .unwrap()
+                .unwrap()
+                .key(),
+            genesis.key()
+        );
+    }
+}
@@ -2,7 +2,10 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 use lazy_static::lazy_static;
-use prometheus::{core::Opts, Histogram, HistogramOpts};
+use prometheus::{
+    core::{AtomicU64, GenericGauge, Opts},
+    Gauge, Histogram, HistogramOpts,
+};
 
 lazy_static! {
     pub static ref APPLY_BLOCKS_TIME: Box<Histogram> =
@@ -22,4 +25,34 @@ lazy_static! {
         );
             apply_blocks_time
         };
+    pub static ref CHAIN_VALIDATION_EPOCH: Box<GenericGauge<AtomicU64>> = {
+        let chain_validation_epoch = Box::new(
+            GenericGauge::<AtomicU64>::new(
+                "chain_validation_epoch",
+                "Epoch up to which the full chain validation has validated the chain",
+            )
+            .expect("Defining the chain_validation_epoch metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(chain_validation_epoch.clone())
+            .expect(
+                "Registering the chain_validation_epoch metric with the metrics registry must succeed",
+            );
+        chain_validation_epoch
+    };
+    pub static ref CHAIN_VALIDATION_THROUGHPUT: Box<Gauge> = {
+        let chain_validation_throughput = Box::new(
+            Gauge::new(
+                "chain_validation_throughput",
+                "Epochs validated per second by the full chain validation",
+            )
+            .expect("Defining the chain_validation_throughput metric must succeed"),
+        );
+        prometheus::default_registry()
+            .register(chain_validation_throughput.clone())
+            .expect(
+                "Registering the chain_validation_throughput metric with the metrics registry must succeed",
+            );
+        chain_validation_throughput
+    };
 }
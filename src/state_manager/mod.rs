@@ -1,24 +1,47 @@
 // Copyright 2019-2023 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+pub mod actor_changes;
+pub mod actor_trace;
+pub mod audit_log;
+pub mod callbacks;
 pub mod chain_rand;
+pub mod chain_validation;
+pub mod deal_index;
+pub mod diff;
+pub mod domain_separation;
 mod errors;
+pub mod event_index;
+mod evm;
+pub mod execution_trace;
+pub mod forensics;
+pub mod index_store;
+pub mod message_index;
 mod metrics;
+pub mod rewards;
+pub mod scheduler;
+pub mod state_size;
 mod utils;
+pub mod watch_list;
 use crate::state_migration::run_state_migrations;
-use anyhow::{bail, Context as _};
+use anyhow::Context as _;
 use rayon::prelude::ParallelBridge;
 pub use utils::is_valid_for_sending;
 mod vm_circ_supply;
+use self::callbacks::{ExecutionCallback, Observers};
+use self::chain_validation::StateDivergence;
 pub use self::errors::*;
+use self::execution_trace::ExecutionTrace;
 use crate::beacon::BeaconSchedule;
 use crate::blocks::{Tipset, TipsetKeys};
 use crate::chain::{
     index::{ChainIndex, ResolveNullTipset},
-    ChainStore, HeadChange,
+    ChainStore, HeadChange, MissingReason,
 };
-use crate::interpreter::BlockMessages;
-use crate::interpreter::{resolve_to_key_addr, ExecutionContext, VM};
+use crate::db::setting_keys::RECEIPTS_PRUNED_EPOCH_KEY;
+use crate::db::SettingsStoreExt;
+use crate::interpreter::{cron::CronOutcome, resolve_to_key_addr, ExecutionContext, VMTrace, VM};
+use crate::interpreter::{BlockMessages, BlockReward};
 use crate::message::{ChainMessage, Message as MessageTrait};
 use crate::networks::ChainConfig;
 use crate::shim::clock::ChainEpoch;
@@ -30,6 +53,7 @@ use crate::shim::{
     state_tree::{ActorState, StateTree},
     version::NetworkVersion,
 };
+use crate::utils::db::overlay::OverlayBlockstore;
 use ahash::{HashMap, HashMapExt};
 use chain_rand::ChainRand;
 use cid::Cid;
@@ -43,15 +67,20 @@ use lru::LruCache;
 use nonzero_ext::nonzero;
 use num::BigInt;
 use num_traits::identities::Zero;
-use parking_lot::Mutex as SyncMutex;
+use parking_lot::{Mutex as SyncMutex, RwLock as SyncRwLock};
+use prometheus::Histogram;
 use serde::{Deserialize, Serialize};
 use std::ops::RangeInclusive;
-use std::{num::NonZeroUsize, sync::Arc};
-use tokio::sync::{broadcast::error::RecvError, Mutex as TokioMutex, RwLock};
+use std::{num::NonZeroUsize, path::PathBuf, sync::Arc};
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    Mutex as TokioMutex, RwLock,
+};
 use tracing::{debug, error, info, instrument, trace, warn};
 use vm_circ_supply::GenesisInfo;
 
 const DEFAULT_TIPSET_CACHE_SIZE: NonZeroUsize = nonzero!(1024usize);
+const DEFAULT_KEY_ADDR_CACHE_SIZE: NonZeroUsize = nonzero!(8192usize);
 
 /// Intermediary for retrieving state objects and updating actor states.
 type CidPair = (Cid, Cid);
@@ -175,6 +204,9 @@ pub struct InvocResult {
     #[serde(with = "crate::lotus_json")]
     pub msg_rct: Option<Receipt>,
     pub error: Option<String>,
+    /// Only set for replayed messages, whose execution is traced.
+    #[serde(default)]
+    pub execution_trace: Option<ExecutionTrace>,
 }
 
 /// An alias Result that represents an `InvocResult` and an Error.
@@ -198,11 +230,29 @@ pub struct StateManager<DB> {
 
     /// This is a cache which indexes tipsets to their calculated state.
     cache: TipsetStateCache,
+    /// Key addresses of ID addresses, by the state root they were resolved in.
+    key_addr_cache: SyncMutex<LruCache<(Cid, Address), Address>>,
     // Beacon can be cheaply crated from the `chain_config`. The only reason we
     // store it here is because it has a look-up cache.
     beacon: Arc<crate::beacon::BeaconSchedule>,
     chain_config: Arc<ChainConfig>,
     engine: crate::shim::machine::MultiEngine,
+    /// See [`forensics`].
+    forensics_dir: Option<PathBuf>,
+    /// Whether a forensic bundle is being dumped.
+    forensics_running: std::sync::atomic::AtomicBool,
+    /// See [`audit_log`].
+    audit_log: Option<audit_log::AuditLog>,
+    /// See [`callbacks`].
+    execution_callbacks: SyncRwLock<Vec<Arc<dyn ExecutionCallback<DB>>>>,
+    /// See [`actor_changes`].
+    actor_changes: broadcast::Sender<actor_changes::ActorChange>,
+    /// See [`evm`].
+    evm_cache: evm::EvmCache,
+    /// See [`scheduler`].
+    scheduler: scheduler::Scheduler<DB>,
+    /// See [`index_store`].
+    indices: index_store::IndexWriter,
 }
 
 #[allow(clippy::type_complexity)]
@@ -217,17 +267,36 @@ where
         chain_config: Arc<ChainConfig>,
     ) -> Result<Self, anyhow::Error> {
         let genesis = cs.genesis();
-        let beacon = Arc::new(chain_config.get_beacon_schedule(genesis.timestamp()));
+        let beacon = Arc::new(
+            chain_config.beacon_schedule_with_store(genesis.timestamp(), Some(cs.settings())),
+        );
 
+        let scheduler = scheduler::Scheduler::load(cs.settings().as_ref())?;
+        let indices = index_store::IndexWriter::spawn(cs.settings(), cs.batches())?;
         Ok(Self {
             cs,
             cache: TipsetStateCache::new(),
+            key_addr_cache: SyncMutex::new(LruCache::new(DEFAULT_KEY_ADDR_CACHE_SIZE)),
             beacon,
             chain_config,
             engine: crate::shim::machine::MultiEngine::default(),
+            forensics_dir: None,
+            forensics_running: Default::default(),
+            audit_log: None,
+            execution_callbacks: SyncRwLock::new(callbacks::default_callbacks()),
+            actor_changes: broadcast::channel(actor_changes::ACTOR_CHANGES_CAP).0,
+            evm_cache: evm::EvmCache::default(),
+            scheduler,
+            indices,
         })
     }
 
+    /// Waits until the queued index entries are written, see [`index_store`].
+    #[cfg(test)]
+    pub(super) fn flush_indices(&self) {
+        self.indices.flush()
+    }
+
     pub fn beacon_schedule(&self) -> Arc<BeaconSchedule> {
         Arc::clone(&self.beacon)
     }
@@ -294,20 +363,7 @@ where
         state_cid: Cid,
         addr: &Address,
     ) -> anyhow::Result<Address, Error> {
-        let state = StateTree::new_from_root(self.blockstore_owned(), &state_cid)
-            .map_err(|e| Error::Other(e.to_string()))?;
-
-        let act = state
-            .get_actor(addr)
-            .map_err(|e| Error::State(e.to_string()))?
-            .ok_or_else(|| Error::State("Miner actor not found".to_string()))?;
-
-        let ms = miner::State::load(self.blockstore(), act.code, act.state)?;
-
-        let info = ms.info(self.blockstore()).map_err(|e| e.to_string())?;
-
-        let addr = resolve_to_key_addr(&state, self.blockstore(), &info.worker().into())?;
-        Ok(addr)
+        miner_work_addr(self.blockstore(), state_cid, addr)
     }
 
     /// Returns specified actor's claimed power and total network power as a
@@ -353,12 +409,40 @@ where
     /// state for a given tipset is guaranteed not to be computed twice.
     #[instrument(skip(self))]
     pub async fn tipset_state(self: &Arc<Self>, tipset: &Arc<Tipset>) -> anyhow::Result<CidPair> {
+        self.tipset_state_observed(tipset, None).await
+    }
+
+    /// Same as [`StateManager::tipset_state`], but records the duration of
+    /// successful state computations in `histogram`. Cache hits are not
+    /// recorded.
+    pub async fn tipset_state_timed(
+        self: &Arc<Self>,
+        tipset: &Arc<Tipset>,
+        histogram: &Histogram,
+    ) -> anyhow::Result<CidPair> {
+        self.tipset_state_observed(tipset, Some(histogram)).await
+    }
+
+    async fn tipset_state_observed(
+        self: &Arc<Self>,
+        tipset: &Arc<Tipset>,
+        histogram: Option<&Histogram>,
+    ) -> anyhow::Result<CidPair> {
         let key = tipset.key();
         self.cache
             .get_or_else(key, || async move {
-                let ts_state = self
-                    .compute_tipset_state(Arc::clone(tipset), NO_CALLBACK)
-                    .await?;
+                let timer = histogram.map(Histogram::start_timer);
+                let result = self
+                    .compute_tipset_state(Arc::clone(tipset), NO_CALLBACK, VMTrace::NotTraced)
+                    .await;
+                match timer {
+                    Some(timer) if result.is_ok() => timer.observe_duration(),
+                    Some(timer) => {
+                        timer.stop_and_discard();
+                    }
+                    None => {}
+                }
+                let ts_state = result?;
                 debug!("Completed tipset state calculation {:?}", tipset.cids());
                 Ok(ts_state)
             })
@@ -375,6 +459,7 @@ where
         let bstate = tipset.parent_state();
         let bheight = tipset.epoch();
         let genesis_info = GenesisInfo::from_chain_config(&self.chain_config());
+        let overlay = Arc::new(OverlayBlockstore::new(self.blockstore_owned()));
         let mut vm = VM::new(
             ExecutionContext {
                 heaviest_tipset: Arc::clone(tipset),
@@ -388,10 +473,11 @@ where
                     bstate,
                 )?,
                 chain_config: self.chain_config(),
-                chain_index: Arc::clone(&self.chain_store().chain_index),
+                chain_index: Arc::new(self.cs.chain_index.with_db(overlay)),
                 timestamp: tipset.min_timestamp(),
             },
             &self.engine,
+            VMTrace::NotTraced,
         )?;
 
         if msg.gas_limit == 0 {
@@ -417,11 +503,13 @@ where
             msg: msg.clone(),
             msg_rct: Some(apply_ret.msg_receipt()),
             error: apply_ret.failure_info(),
+            execution_trace: None,
         })
     }
 
     /// runs the given message and returns its result without any persisted
-    /// changes.
+    /// changes. The VM writes into an [`OverlayBlockstore`] that is discarded
+    /// afterwards.
     pub fn call(
         self: &Arc<Self>,
         message: &mut Message,
@@ -433,7 +521,8 @@ where
     }
 
     /// Computes message on the given [Tipset] state, after applying other
-    /// messages and returns the values computed in the VM.
+    /// messages and returns the values computed in the VM. Like [`Self::call`],
+    /// execution happens on a discarded [`OverlayBlockstore`].
     pub async fn call_with_gas(
         self: &Arc<Self>,
         message: &mut ChainMessage,
@@ -451,6 +540,7 @@ where
         // "next" tipset
         let epoch = ts.epoch() + 1;
         let genesis_info = GenesisInfo::from_chain_config(&self.chain_config());
+        let overlay = Arc::new(OverlayBlockstore::new(self.blockstore_owned()));
         let mut vm = VM::new(
             ExecutionContext {
                 heaviest_tipset: Arc::clone(&ts),
@@ -464,10 +554,11 @@ where
                     &st,
                 )?,
                 chain_config: self.chain_config(),
-                chain_index: Arc::clone(&self.chain_store().chain_index),
+                chain_index: Arc::new(self.cs.chain_index.with_db(overlay)),
                 timestamp: ts.min_timestamp(),
             },
             &self.engine,
+            VMTrace::NotTraced,
         )?;
 
         for msg in prior_messages {
@@ -485,11 +576,13 @@ where
             msg: message.message().clone(),
             msg_rct: Some(ret.msg_receipt()),
             error: ret.failure_info(),
+            execution_trace: None,
         })
     }
 
     /// Replays the given message and returns the result of executing the
     /// indicated message, assuming it was executed in the indicated tipset.
+    /// The execution is traced, see [`ApplyRet::exec_trace`].
     pub async fn replay(
         self: &Arc<Self>,
         ts: &Arc<Tipset>,
@@ -511,7 +604,7 @@ where
             Ok(())
         };
         let result = self
-            .compute_tipset_state(Arc::clone(ts), Some(callback))
+            .compute_tipset_state(Arc::clone(ts), Some(callback), VMTrace::Traced)
             .await;
 
         if let Err(error_message) = result {
@@ -611,13 +704,16 @@ where
         self: &Arc<Self>,
         tipset: Arc<Tipset>,
         callback: Option<CB>,
+        trace: VMTrace,
     ) -> Result<CidPair, Error>
     where
         CB: FnMut(&Cid, &ChainMessage, &ApplyRet) -> Result<(), anyhow::Error> + Send,
     {
         let this = Arc::clone(self);
-        tokio::task::spawn_blocking(move || this.compute_tipset_state_blocking(tipset, callback))
-            .await?
+        tokio::task::spawn_blocking(move || {
+            this.compute_tipset_state_blocking(tipset, callback, trace)
+        })
+        .await?
     }
 
     /// Blocking version of `compute_tipset_state`
@@ -625,26 +721,98 @@ where
     pub fn compute_tipset_state_blocking<CB: 'static>(
         self: &Arc<Self>,
         tipset: Arc<Tipset>,
-        callback: Option<CB>,
+        mut callback: Option<CB>,
+        trace: VMTrace,
     ) -> Result<CidPair, Error>
     where
         CB: FnMut(&Cid, &ChainMessage, &ApplyRet) -> Result<(), anyhow::Error> + Send,
     {
-        Ok(apply_block_messages(
+        let callbacks = self.execution_callbacks.read().clone();
+        let trace = match callbacks.iter().any(|callback| callback.traced()) {
+            true => VMTrace::Traced,
+            false => trace,
+        };
+        let mut observers = Observers::begin(&callbacks, &tipset);
+        let (roots, rewards) = apply_block_messages_with_rewards(
             self.chain_store().genesis().timestamp(),
             Arc::clone(&self.chain_store().chain_index),
             Arc::clone(&self.chain_config),
             self.beacon_schedule(),
             &self.engine,
-            tipset,
-            callback,
-        )?)
+            Arc::clone(&tipset),
+            Some(|cid: &Cid, message: &ChainMessage, apply_ret: &ApplyRet| {
+                observers.observe(cid, message, apply_ret);
+                match &mut callback {
+                    Some(callback) => callback(cid, message, apply_ret),
+                    None => Ok(()),
+                }
+            }),
+            trace,
+        )?;
+        if let Err(e) = self.index_rewards(&tipset, rewards) {
+            warn!(
+                "Failed to index the rewards of epoch {}: {e}",
+                tipset.epoch()
+            );
+        }
+        observers.finish(self, &tipset, roots.0);
+        Ok(roots)
+    }
+
+    /// Returns the receipt of the message at `index` in the messages of the
+    /// parent of `tipset`. Receipts pruned by the garbage collector are
+    /// recomputed by executing the parent tipset again, which requires its
+    /// parent state. Receipts of tipsets that this node never executed are
+    /// reported as such.
+    fn parent_receipt(
+        self: &Arc<Self>,
+        tipset: &Tipset,
+        index: usize,
+    ) -> Result<Option<Receipt>, Error> {
+        let header = tipset.min_ticket_block();
+        match crate::chain::get_parent_reciept(self.blockstore(), header, index) {
+            Err(crate::chain::Error::Missing(missing))
+                if missing.reason == MissingReason::Pruned
+                    || tipset.epoch() <= self.receipts_pruned_epoch()? =>
+            {
+                let parent = self.cs.tipset_from_keys(tipset.parents())?;
+                info!(
+                    "Recomputing the pruned receipts of epoch {}",
+                    parent.epoch()
+                );
+                let (_, receipt_root) =
+                    self.compute_tipset_state_blocking(parent, NO_CALLBACK, VMTrace::NotTraced)?;
+                if receipt_root != *header.message_receipts() {
+                    return Err(Error::Other(format!(
+                        "recomputed receipts root {receipt_root} doesn't match {} of block {}",
+                        header.message_receipts(),
+                        header.cid()
+                    )));
+                }
+                Ok(crate::chain::get_parent_reciept(
+                    self.blockstore(),
+                    header,
+                    index,
+                )?)
+            }
+            result => Ok(result?),
+        }
+    }
+
+    /// Latest epoch whose receipts have been pruned by the garbage collector,
+    /// or `-1` if none have been.
+    fn receipts_pruned_epoch(&self) -> Result<ChainEpoch, Error> {
+        Ok(self
+            .cs
+            .settings()
+            .read_obj(RECEIPTS_PRUNED_EPOCH_KEY)?
+            .unwrap_or(-1))
     }
 
     /// Check if tipset had executed the message, by loading the receipt based
     /// on the index of the message in the block.
     fn tipset_executed_message(
-        &self,
+        self: &Arc<Self>,
         tipset: &Tipset,
         msg_cid: Cid,
         (message_from_address, message_sequence): (&Address, &u64),
@@ -675,17 +843,7 @@ where
                         s == msg_cid
                     ).unwrap_or_default() {
                         // When message Cid has been found, get receipt at index.
-                        let rct = crate::chain::get_parent_reciept(
-                            self.blockstore(),
-                            tipset.blocks().first().unwrap(),
-                            index,
-                        )
-                            .map_err(|err| {
-                                Error::Other(err.to_string())
-                            });
-                        return Some(
-                           rct
-                        );
+                        return Some(self.parent_receipt(tipset, index));
                     }
                     let error_msg = format!("found message with equal nonce as the one we are looking for (F:{:} n {:}, TS: `Error Converting message to Cid` n{:})", msg_cid, message_sequence, s.sequence());
                     return Some(Err(Error::Other(error_msg)))
@@ -701,7 +859,7 @@ where
     }
 
     fn check_search(
-        &self,
+        self: &Arc<Self>,
         mut current: Arc<Tipset>,
         (message_from_address, message_cid, message_sequence): (&Address, &Cid, &u64),
     ) -> Result<Option<(Arc<Tipset>, Receipt)>, Error> {
@@ -740,16 +898,29 @@ where
     }
 
     fn search_back_for_message(
-        &self,
+        self: &Arc<Self>,
         current: Arc<Tipset>,
         params: (&Address, &Cid, &u64),
     ) -> Result<Option<(Arc<Tipset>, Receipt)>, Error> {
         self.check_search(current, params)
     }
-    /// Returns a message receipt from a given tipset and message CID.
-    pub fn get_receipt(&self, tipset: Arc<Tipset>, msg: Cid) -> Result<Receipt, Error> {
-        let m = crate::chain::get_chain_message(self.blockstore(), &msg)
-            .map_err(|e| Error::Other(e.to_string()))?;
+    /// Returns a message receipt from a given tipset and message CID. Pruned
+    /// receipts are recomputed on a blocking thread.
+    pub async fn get_receipt(
+        self: &Arc<Self>,
+        tipset: Arc<Tipset>,
+        msg: Cid,
+    ) -> Result<Receipt, Error> {
+        let this = Arc::clone(self);
+        tokio::task::spawn_blocking(move || this.get_receipt_blocking(tipset, msg)).await?
+    }
+
+    fn get_receipt_blocking(
+        self: &Arc<Self>,
+        tipset: Arc<Tipset>,
+        msg: Cid,
+    ) -> Result<Receipt, Error> {
+        let m = crate::chain::get_chain_message(self.blockstore(), &msg)?;
         let message_var = (&m.from(), &m.sequence());
         let message_receipt = self.tipset_executed_message(&tipset, msg, message_var)?;
 
@@ -780,8 +951,7 @@ where
     ) -> Result<(Option<Arc<Tipset>>, Option<Receipt>), Error> {
         let mut subscriber = self.cs.publisher().subscribe();
         let (sender, mut receiver) = oneshot::channel::<()>();
-        let message = crate::chain::get_chain_message(self.blockstore(), &msg_cid)
-            .map_err(|err| Error::Other(format!("failed to load message {err:}")))?;
+        let message = crate::chain::get_chain_message(self.blockstore(), &msg_cid)?;
 
         let message_var = (&message.from(), &message.sequence());
         let current_tipset = self.cs.heaviest_tipset();
@@ -803,7 +973,7 @@ where
         let address_for_task = message.from();
         let sequence_for_task = message.sequence();
         let height_of_head = current_tipset.epoch();
-        let task = tokio::task::spawn(async move {
+        let task = tokio::task::spawn_blocking(move || {
             let back_tuple = sm_cloned.search_back_for_message(
                 current_tipset,
                 (&address_for_task, &cid_for_task, &sequence_for_task),
@@ -987,16 +1157,26 @@ where
 
         // First try to resolve the actor in the parent state, so we don't have to
         // compute anything.
-        let state = StateTree::new_from_root(self.blockstore_owned(), ts.parent_state())?;
-        if let Ok(addr) = resolve_to_key_addr(&state, self.blockstore(), addr) {
-            return Ok(addr);
+        if let Ok(key_addr) = self.resolve_to_key_addr_at(addr, ts.parent_state()) {
+            return Ok(key_addr);
         }
 
         // If that fails, compute the tip-set and try again.
         let (st, _) = self.tipset_state(ts).await?;
-        let state = StateTree::new_from_root(self.blockstore_owned(), &st)?;
+        self.resolve_to_key_addr_at(addr, &st)
+    }
 
-        resolve_to_key_addr(&state, self.blockstore(), addr)
+    /// Resolves `addr` in the state tree `state_root`. Results are cached by
+    /// state root, so they hold on every fork and at every height.
+    fn resolve_to_key_addr_at(&self, addr: &Address, state_root: &Cid) -> anyhow::Result<Address> {
+        let key = (*state_root, *addr);
+        if let Some(key_addr) = self.key_addr_cache.lock().get(&key) {
+            return Ok(*key_addr);
+        }
+        let state = StateTree::new_from_root(self.blockstore_owned(), state_root)?;
+        let key_addr = resolve_to_key_addr(&state, self.blockstore(), addr)?;
+        self.key_addr_cache.lock().put(key, key_addr);
+        Ok(key_addr)
     }
 
     /// Checks power actor state for if miner meets consensus minimum
@@ -1078,7 +1258,7 @@ where
         )
     }
 
-    fn chain_rand(&self, tipset: Arc<Tipset>) -> ChainRand<DB> {
+    pub fn chain_rand(&self, tipset: Arc<Tipset>) -> ChainRand<DB> {
         ChainRand::new(
             self.chain_config.clone(),
             tipset,
@@ -1088,6 +1268,29 @@ where
     }
 }
 
+/// Returns the key address of the worker of the miner `addr` in the state
+/// `state_cid`, see [`StateManager::get_miner_work_addr`].
+pub fn miner_work_addr<DB: Blockstore>(
+    db: &DB,
+    state_cid: Cid,
+    addr: &Address,
+) -> anyhow::Result<Address, Error> {
+    let state = StateTree::new_from_root(Arc::new(db), &state_cid)
+        .map_err(|e| Error::Other(e.to_string()))?;
+
+    let act = state
+        .get_actor(addr)
+        .map_err(|e| Error::State(e.to_string()))?
+        .ok_or_else(|| Error::State("Miner actor not found".to_string()))?;
+
+    let ms = miner::State::load(db, act.code, act.state)?;
+
+    let info = ms.info(db).map_err(|e| e.to_string())?;
+
+    let addr = resolve_to_key_addr(&state, db, &info.worker().into())?;
+    Ok(addr)
+}
+
 pub fn validate_tipsets<DB, T>(
     genesis_timestamp: u64,
     chain_index: Arc<ChainIndex<Arc<DB>>>,
@@ -1105,36 +1308,71 @@ where
         .tuple_windows()
         .par_bridge()
         .try_for_each(|(child, parent)| {
-            info!(height = parent.epoch(), "compute parent state");
-            let (actual_state, actual_receipt) = apply_block_messages(
+            let divergence = check_tipset_state(
                 genesis_timestamp,
                 chain_index.clone(),
                 chain_config.clone(),
                 beacon.clone(),
                 engine,
+                &child,
                 parent,
-                NO_CALLBACK,
-            )
-            .context("couldn't compute tipset state")?;
-            let expected_receipt = child.min_ticket_block().message_receipts();
-            let expected_state = child.parent_state();
-            match (expected_state, expected_receipt) == (&actual_state, &actual_receipt) {
-                true => Ok(()),
-                false => {
-                    error!(
-                        height = child.epoch(),
-                        ?expected_state,
-                        ?expected_receipt,
-                        ?actual_state,
-                        ?actual_receipt,
-                        "state mismatch"
-                    );
-                    bail!("state mismatch");
-                }
+            )?;
+            match divergence {
+                None => Ok(()),
+                Some(divergence) => Err(divergence.into()),
             }
         })
 }
 
+/// Executes `parent` and compares the resulting state and receipt roots with
+/// the ones declared by its `child`.
+fn check_tipset_state<DB>(
+    genesis_timestamp: u64,
+    chain_index: Arc<ChainIndex<Arc<DB>>>,
+    chain_config: Arc<ChainConfig>,
+    beacon: Arc<BeaconSchedule>,
+    engine: &crate::shim::machine::MultiEngine,
+    child: &Tipset,
+    parent: Arc<Tipset>,
+) -> anyhow::Result<Option<StateDivergence>>
+where
+    DB: Blockstore + Send + Sync + 'static,
+{
+    info!(height = parent.epoch(), "compute parent state");
+    let (epoch, tipset) = (parent.epoch(), parent.key().clone());
+    let (actual_state, actual_receipts) = apply_block_messages(
+        genesis_timestamp,
+        chain_index,
+        chain_config,
+        beacon,
+        engine,
+        parent,
+        NO_CALLBACK,
+    )
+    .context("couldn't compute tipset state")?;
+    let expected_receipts = *child.min_ticket_block().message_receipts();
+    let expected_state = *child.parent_state();
+    if (expected_state, expected_receipts) == (actual_state, actual_receipts) {
+        return Ok(None);
+    }
+    error!(
+        height = child.epoch(),
+        ?expected_state,
+        ?expected_receipts,
+        ?actual_state,
+        ?actual_receipts,
+        "state mismatch"
+    );
+    Ok(Some(StateDivergence {
+        epoch,
+        tipset,
+        expected_state_root: expected_state,
+        computed_state_root: actual_state,
+        expected_receipt_root: expected_receipts,
+        computed_receipt_root: actual_receipts,
+    }))
+}
+
 /// Messages are transactions that produce new states. The state (usually
 /// referred to as the 'state-tree') is a mapping from actor addresses to actor
 /// states. Each block contains the hash of the state-tree that should be used
@@ -1218,8 +1456,39 @@ pub fn apply_block_messages<DB, CB>(
     beacon: Arc<BeaconSchedule>,
     engine: &crate::shim::machine::MultiEngine,
     tipset: Arc<Tipset>,
-    mut callback: Option<CB>,
+    callback: Option<CB>,
 ) -> Result<CidPair, anyhow::Error>
+where
+    DB: Blockstore + Send + Sync + 'static,
+    CB: FnMut(&Cid, &ChainMessage, &ApplyRet) -> Result<(), anyhow::Error>,
+{
+    apply_block_messages_with_rewards(
+        genesis_timestamp,
+        chain_index,
+        chain_config,
+        beacon,
+        engine,
+        tipset,
+        callback,
+        VMTrace::NotTraced,
+    )
+    .map(|(roots, _)| roots)
+}
+
+/// Same as [`apply_block_messages`], but also returns the rewards of the
+/// miners of the tipset, and records the execution traces if `trace` is
+/// [`VMTrace::Traced`].
+#[allow(clippy::too_many_arguments)]
+pub fn apply_block_messages_with_rewards<DB, CB>(
+    genesis_timestamp: u64,
+    chain_index: Arc<ChainIndex<Arc<DB>>>,
+    chain_config: Arc<ChainConfig>,
+    beacon: Arc<BeaconSchedule>,
+    engine: &crate::shim::machine::MultiEngine,
+    tipset: Arc<Tipset>,
+    mut callback: Option<CB>,
+    trace: VMTrace,
+) -> Result<(CidPair, Vec<BlockReward>), anyhow::Error>
 where
     DB: Blockstore + Send + Sync + 'static,
     CB: FnMut(&Cid, &ChainMessage, &ApplyRet) -> Result<(), anyhow::Error>,
@@ -1238,7 +1507,7 @@ where
         // magical genesis miner, this won't work properly, so we short circuit here
         // This avoids the question of 'who gets paid the genesis block reward'
         let message_receipts = tipset.min_ticket_block().message_receipts();
-        return Ok((*tipset.parent_state(), *message_receipts));
+        return Ok(((*tipset.parent_state(), *message_receipts), vec![]));
     }
 
     let _timer = metrics::APPLY_BLOCKS_TIME.start_timer();
@@ -1267,6 +1536,7 @@ where
                 timestamp,
             },
             engine,
+            trace,
         )
     };
 
@@ -1281,8 +1551,8 @@ where
             let timestamp = genesis_timestamp + ((EPOCH_DURATION_SECONDS * epoch_i) as u64);
             let mut vm = create_vm(parent_state, epoch_i, timestamp)?;
             // run cron for null rounds if any
-            if let Err(e) = vm.run_cron(epoch_i, callback.as_mut()) {
-                error!("Beginning of epoch cron failed to run: {}", e);
+            if let CronOutcome::Failed { reason, .. } = vm.run_cron(epoch_i, callback.as_mut())? {
+                error!("Beginning of epoch cron failed: {reason}");
             }
 
             parent_state = vm.flush()?;
@@ -1296,17 +1566,17 @@ where
         }
     }
 
-    let block_messages = BlockMessages::for_tipset(&chain_index.db, &tipset)
+    let block_messages = BlockMessages::for_tipset(&chain_index.db, &tipset, &chain_config)
         .map_err(|e| Error::Other(e.to_string()))?;
 
     let mut vm = create_vm(parent_state, epoch, tipset.min_timestamp())?;
 
     // step 4: apply tipset messages
-    let receipts = vm.apply_block_messages(&block_messages, epoch, callback)?;
+    let (receipts, rewards) = vm.apply_block_messages(&block_messages, epoch, callback)?;
 
     // step 5: construct receipt root from receipts and flush the state-tree
     let receipt_root = Amt::new_from_iter(&chain_index.db, receipts)?;
     let state_root = vm.flush()?;
 
-    Ok((state_root, receipt_root))
+    Ok(((state_root, receipt_root), rewards))
 }
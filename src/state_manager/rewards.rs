@@ -0,0 +1,180 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Index of the block rewards and penalties applied when executing tipsets,
+//! so that miner income can be queried without executing blocks again.
+//!
+//! The rewards of every executed tipset are stored by epoch in the
+//! [index store](super::index_store), and kept as long as the state roots of
+//! their epochs. Tipsets that were executed before the index existed, or only
+//! imported from a snapshot, are missing from it.
+
+use std::ops::RangeInclusive;
+
+use crate::blocks::Tipset;
+use crate::chain::index::ResolveNullTipset;
+use crate::interpreter::BlockReward;
+use crate::shim::{address::Address, clock::ChainEpoch, econ::TokenAmount};
+use crate::state_manager::index_store::{self, IndexBatch};
+use crate::state_manager::StateManager;
+use fvm_ipld_blockstore::Blockstore;
+use serde::{Deserialize, Serialize};
+
+fn rewards_key(epoch: ChainEpoch) -> String {
+    index_store::index_key(format_args!("rewards/{epoch}"))
+}
+
+/// Rewards of a miner for a block, see [`BlockReward`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct MinerReward {
+    pub epoch: ChainEpoch,
+    pub win_count: i64,
+    #[serde(with = "crate::lotus_json")]
+    pub reward: TokenAmount,
+    #[serde(with = "crate::lotus_json")]
+    pub gas_reward: TokenAmount,
+    #[serde(with = "crate::lotus_json")]
+    pub penalty: TokenAmount,
+}
+
+impl MinerReward {
+    pub const CSV_HEADER: &'static str = "epoch,win_count,reward,gas_reward,penalty";
+
+    /// Amounts are in `attoFIL`.
+    pub fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{}",
+            self.epoch,
+            self.win_count,
+            self.reward.atto(),
+            self.gas_reward.atto(),
+            self.penalty.atto()
+        )
+    }
+}
+
+impl<DB> StateManager<DB>
+where
+    DB: Blockstore,
+{
+    pub(super) fn index_rewards(
+        &self,
+        tipset: &Tipset,
+        blocks: Vec<BlockReward>,
+    ) -> anyhow::Result<()> {
+        if blocks.is_empty() {
+            return Ok(());
+        }
+        let mut batch = IndexBatch::default();
+        batch.push(rewards_key(tipset.epoch()), tipset, &blocks)?;
+        self.indices.write(batch);
+        Ok(())
+    }
+
+    /// Returns the indexed rewards of the ID address `miner` for the canonical
+    /// tipsets of `epochs`, at most [`index_store::MAX_INDEX_QUERY_EPOCHS`].
+    pub fn miner_rewards(
+        &self,
+        miner: &Address,
+        epochs: RangeInclusive<ChainEpoch>,
+    ) -> anyhow::Result<Vec<MinerReward>> {
+        index_store::ensure_query_range(&epochs)?;
+        let chain_index = &self.chain_store().chain_index;
+        let heaviest = self.chain_store().heaviest_tipset();
+        let end = chain_index.tipset_by_height(
+            (*epochs.end()).min(heaviest.epoch()),
+            heaviest,
+            ResolveNullTipset::TakeOlder,
+        )?;
+        let settings = self.chain_store().settings();
+        let mut rewards = vec![];
+        for tipset in chain_index
+            .chain(end)
+            .take_while(|tipset| tipset.epoch() >= *epochs.start())
+        {
+            let entries = index_store::read_entries::<Vec<BlockReward>>(
+                settings.as_ref(),
+                &rewards_key(tipset.epoch()),
+            )?;
+            let entry = entries
+                .into_iter()
+                .find(|entry| &entry.tipset == tipset.key());
+            let Some(entry) = entry else {
+                continue;
+            };
+            rewards.extend(
+                entry
+                    .value
+                    .into_iter()
+                    .filter(|block| &block.miner == miner)
+                    .map(|block| MinerReward {
+                        epoch: tipset.epoch(),
+                        win_count: block.win_count,
+                        reward: block.reward,
+                        gas_reward: block.gas_reward,
+                        penalty: block.penalty,
+                    }),
+            );
+        }
+        rewards.reverse();
+        Ok(rewards)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::blocks::BlockHeader;
+    use crate::chain::ChainStore;
+    use crate::db::MemoryDB;
+    use crate::networks::ChainConfig;
+
+    #[test]
+    fn index_and_query() {
+        let db = Arc::new(MemoryDB::default());
+        let genesis = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .timestamp(7777)
+            .build()
+            .unwrap();
+        crate::chain::persist_objects(&db, &[&genesis]).unwrap();
+        let chain_config = Arc::new(ChainConfig::default());
+        let chain_store = Arc::new(
+            ChainStore::new(db.clone(), db, chain_config.clone(), genesis.clone()).unwrap(),
+        );
+        let state_manager = StateManager::new(chain_store, chain_config).unwrap();
+        let genesis = Tipset::from(genesis);
+
+        let reward = |miner: u64, atto: u64| BlockReward {
+            miner: Address::new_id(miner),
+            win_count: 1,
+            reward: TokenAmount::from_atto(atto),
+            gas_reward: TokenAmount::from_atto(1),
+            penalty: TokenAmount::default(),
+        };
+        state_manager
+            .index_rewards(&genesis, vec![reward(1000, 10), reward(1001, 20)])
+            .unwrap();
+        state_manager.flush_indices();
+
+        let rewards = state_manager
+            .miner_rewards(&Address::new_id(1001), 0..=10)
+            .unwrap();
+        assert_eq!(rewards.len(), 1);
+        assert_eq!(rewards[0].reward, TokenAmount::from_atto(20));
+        assert_eq!(rewards[0].to_csv_row(), "0,1,20,1,0");
+        assert!(state_manager
+            .miner_rewards(&Address::new_id(1002), 0..=10)
+            .unwrap()
+            .is_empty());
+        assert!(state_manager
+            .miner_rewards(
+                &Address::new_id(1001),
+                0..=index_store::MAX_INDEX_QUERY_EPOCHS
+            )
+            .is_err());
+    }
+}
@@ -0,0 +1,396 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Tasks run when the chain reaches an epoch, or when a condition on an actor
+//! holds, e.g. to automate network upgrades or miner deadlines.
+//!
+//! Tasks name the [`ScheduledHandler`] running them rather than holding
+//! closures, so that pending tasks are kept in the settings store and survive
+//! restarts: components register their handlers on startup, and tasks whose
+//! handler isn't registered stay pending. Triggers are checked against every
+//! tipset applied to the head of the chain. The state of a tipset is the one
+//! after the execution of its parent, as for the
+//! [watch-list](super::watch_list).
+//!
+//! A task runs once, and isn't run again if its tipset is reverted. A task
+//! is removed once its handler returns, so a task interrupted by a restart
+//! runs again. Handlers may schedule tasks, e.g. to retry.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::blocks::Tipset;
+use crate::chain::HeadChange;
+use crate::db::{setting_keys::SCHEDULED_TASKS_KEY, SettingsStore, SettingsStoreExt};
+use crate::shim::{address::Address, clock::ChainEpoch, econ::TokenAmount, state_tree::StateTree};
+use crate::state_manager::StateManager;
+use ahash::HashMap;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+/// Runs the tasks scheduled for it.
+pub trait ScheduledHandler<DB>: Send + Sync {
+    /// Name of the handler, referred to by its tasks.
+    fn name(&self) -> &str;
+
+    /// Runs `task`, whose trigger holds at `tipset`.
+    fn run(
+        &self,
+        state_manager: &StateManager<DB>,
+        tipset: &Tipset,
+        task: &ScheduledTask,
+    ) -> anyhow::Result<()>;
+}
+
+/// Condition on an actor, in the state of the head of the chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActorCondition {
+    Exists,
+    Missing,
+    MinBalance(#[serde(with = "crate::lotus_json")] TokenAmount),
+    MinNonce(u64),
+    /// The state of the actor differs from the given one, or the actor
+    /// doesn't exist.
+    StateChanged(#[serde(with = "crate::lotus_json")] Cid),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Trigger {
+    /// The head of the chain reaches the epoch, or a later one if the epoch
+    /// is null.
+    Epoch(ChainEpoch),
+    Actor {
+        #[serde(with = "crate::lotus_json")]
+        address: Address,
+        condition: ActorCondition,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduledTask {
+    pub id: u64,
+    /// Name of the [`ScheduledHandler`] running the task.
+    pub handler: String,
+    pub trigger: Trigger,
+    /// Arguments of the handler, kept along the task.
+    pub payload: serde_json::Value,
+}
+
+/// Pending tasks, as kept in the settings store.
+#[derive(Default, Serialize, Deserialize)]
+struct Tasks {
+    /// IDs aren't reused.
+    next_id: u64,
+    pending: BTreeMap<u64, ScheduledTask>,
+}
+
+/// Registered handlers and pending tasks.
+pub(super) struct Scheduler<DB> {
+    handlers: RwLock<HashMap<String, Arc<dyn ScheduledHandler<DB>>>>,
+    tasks: Mutex<Tasks>,
+}
+
+impl<DB: Blockstore> Scheduler<DB> {
+    /// Loads the pending tasks from the settings store.
+    pub fn load(settings: &dyn SettingsStore) -> anyhow::Result<Self> {
+        Ok(Self {
+            handlers: Default::default(),
+            tasks: Mutex::new(settings.read_obj(SCHEDULED_TASKS_KEY)?.unwrap_or_default()),
+        })
+    }
+
+    /// Runs the tasks whose trigger holds at `tipset`.
+    fn observe(&self, state_manager: &StateManager<DB>, tipset: &Tipset) -> anyhow::Result<()> {
+        let pending = self
+            .tasks
+            .lock()
+            .pending
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut tree = None;
+        let mut due = vec![];
+        for task in pending {
+            let Some(handler) = self.handlers.read().get(&task.handler).cloned() else {
+                continue;
+            };
+            let triggered = match &task.trigger {
+                Trigger::Epoch(epoch) => tipset.epoch() >= *epoch,
+                Trigger::Actor { address, condition } => {
+                    let tree = match &mut tree {
+                        Some(tree) => tree,
+                        None => tree.insert(StateTree::new_from_root(
+                            state_manager.blockstore_owned(),
+                            tipset.parent_state(),
+                        )?),
+                    };
+                    let actor = tree.get_actor(address)?;
+                    match (condition, actor) {
+                        (ActorCondition::Exists, actor) => actor.is_some(),
+                        (ActorCondition::Missing, actor) => actor.is_none(),
+                        (ActorCondition::MinBalance(balance), Some(actor)) => {
+                            TokenAmount::from(&actor.balance) >= *balance
+                        }
+                        (ActorCondition::MinNonce(nonce), Some(actor)) => actor.sequence >= *nonce,
+                        (ActorCondition::StateChanged(state), actor) => {
+                            actor.map(|actor| actor.state) != Some(*state)
+                        }
+                        (ActorCondition::MinBalance(_) | ActorCondition::MinNonce(_), None) => {
+                            false
+                        }
+                    }
+                }
+            };
+            if triggered {
+                due.push((handler, task));
+            }
+        }
+
+        // The lock isn't held while the handlers run, so that they can
+        // schedule tasks.
+        for (handler, task) in due {
+            info!(
+                "Running scheduled task {} of {} at epoch {}",
+                task.id,
+                task.handler,
+                tipset.epoch()
+            );
+            if let Err(e) = handler.run(state_manager, tipset, &task) {
+                warn!(
+                    "Scheduled task {} of {} failed: {e:#}",
+                    task.id, task.handler
+                );
+            }
+            let mut tasks = self.tasks.lock();
+            tasks.pending.remove(&task.id);
+            state_manager
+                .chain_store()
+                .settings()
+                .write_obj(SCHEDULED_TASKS_KEY, &*tasks)?;
+        }
+        Ok(())
+    }
+}
+
+impl<DB: Blockstore> StateManager<DB> {
+    /// Registers the handler of the tasks naming it, replacing the handler of
+    /// the same name if any.
+    pub fn register_scheduled_handler(&self, handler: Arc<dyn ScheduledHandler<DB>>) {
+        self.scheduler
+            .handlers
+            .write()
+            .insert(handler.name().to_string(), handler);
+    }
+
+    /// Schedules a task for a registered handler, and returns its ID.
+    pub fn schedule_task(
+        &self,
+        handler: &str,
+        trigger: Trigger,
+        payload: serde_json::Value,
+    ) -> anyhow::Result<u64> {
+        anyhow::ensure!(
+            self.scheduler.handlers.read().contains_key(handler),
+            "No scheduled task handler named {handler}"
+        );
+        let mut tasks = self.scheduler.tasks.lock();
+        let id = tasks.next_id;
+        tasks.next_id += 1;
+        tasks.pending.insert(
+            id,
+            ScheduledTask {
+                id,
+                handler: handler.to_string(),
+                trigger,
+                payload,
+            },
+        );
+        self.chain_store()
+            .settings()
+            .write_obj(SCHEDULED_TASKS_KEY, &*tasks)?;
+        Ok(id)
+    }
+
+    /// Cancels a pending task, and returns whether it was pending.
+    pub fn cancel_scheduled_task(&self, id: u64) -> anyhow::Result<bool> {
+        let mut tasks = self.scheduler.tasks.lock();
+        if tasks.pending.remove(&id).is_none() {
+            return Ok(false);
+        }
+        self.chain_store()
+            .settings()
+            .write_obj(SCHEDULED_TASKS_KEY, &*tasks)?;
+        Ok(true)
+    }
+
+    /// Returns the pending tasks, in the order they were scheduled.
+    pub fn scheduled_tasks(&self) -> Vec<ScheduledTask> {
+        self.scheduler
+            .tasks
+            .lock()
+            .pending
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Runs the scheduled tasks on every head change, forever. The states are
+    /// loaded and the handlers run on the blocking thread pool.
+    pub async fn run_scheduled_tasks(self: Arc<Self>) -> anyhow::Result<()>
+    where
+        DB: Send + Sync + 'static,
+    {
+        let chain_store = self.chain_store();
+        let mut head_changes = chain_store.publisher().subscribe();
+        let mut head = chain_store.heaviest_tipset();
+        loop {
+            let state_manager = Arc::clone(&self);
+            if let Err(e) = tokio::task::spawn_blocking(move || {
+                state_manager.scheduler.observe(&state_manager, &head)
+            })
+            .await?
+            {
+                warn!("Failed to run the scheduled tasks: {e:#}");
+            }
+            head = match head_changes.recv().await {
+                Ok(HeadChange::Apply(tipset)) => tipset,
+                // Epoch triggers are reached by later epochs too, and actor
+                // conditions are checked on the current head.
+                Err(RecvError::Lagged(_)) => chain_store.heaviest_tipset(),
+                Err(RecvError::Closed) => return Ok(()),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::BlockHeader;
+    use crate::chain::ChainStore;
+    use crate::db::MemoryDB;
+    use crate::networks::ChainConfig;
+    use crate::shim::state_tree::{ActorState, StateTreeVersion};
+
+    /// Records the payloads of the tasks it runs.
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(ChainEpoch, serde_json::Value)>>);
+
+    impl<DB> ScheduledHandler<DB> for Arc<Recorder> {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        fn run(
+            &self,
+            _state_manager: &StateManager<DB>,
+            tipset: &Tipset,
+            task: &ScheduledTask,
+        ) -> anyhow::Result<()> {
+            self.0.lock().push((tipset.epoch(), task.payload.clone()));
+            Ok(())
+        }
+    }
+
+    fn state_manager(db: &Arc<MemoryDB>) -> StateManager<MemoryDB> {
+        let genesis = BlockHeader::builder()
+            .miner_address(Address::new_id(0))
+            .timestamp(7777)
+            .build()
+            .unwrap();
+        let chain_config = Arc::new(ChainConfig::default());
+        let chain_store = Arc::new(
+            ChainStore::new(db.clone(), db.clone(), chain_config.clone(), genesis).unwrap(),
+        );
+        StateManager::new(chain_store, chain_config).unwrap()
+    }
+
+    /// Returns a tipset whose parent state holds `actors`.
+    fn tipset(db: &Arc<MemoryDB>, epoch: ChainEpoch, actors: &[(Address, u64)]) -> Tipset {
+        let mut tree = StateTree::new(Arc::clone(db), StateTreeVersion::V5).unwrap();
+        for (address, balance) in actors {
+            let actor = ActorState::new(
+                Cid::default(),
+                Cid::default(),
+                TokenAmount::from_atto(*balance),
+                0,
+                None,
+            );
+            tree.set_actor(address, actor).unwrap();
+        }
+        Tipset::from(
+            BlockHeader::builder()
+                .miner_address(Address::new_id(0))
+                .epoch(epoch)
+                .state_root(tree.flush().unwrap())
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn tasks_run_once_triggered_and_survive_restarts() {
+        let db = Arc::new(MemoryDB::default());
+        let address = Address::new_id(100);
+        let recorder = Arc::new(Recorder::default());
+        let state_manager = state_manager(&db);
+        assert!(state_manager
+            .schedule_task("recorder", Trigger::Epoch(5), "upgrade".into())
+            .is_err());
+        state_manager.register_scheduled_handler(Arc::new(recorder.clone()));
+        state_manager
+            .schedule_task("recorder", Trigger::Epoch(5), "upgrade".into())
+            .unwrap();
+        let cancelled = state_manager
+            .schedule_task("recorder", Trigger::Epoch(5), "cancelled".into())
+            .unwrap();
+        state_manager
+            .schedule_task(
+                "recorder",
+                Trigger::Actor {
+                    address,
+                    condition: ActorCondition::MinBalance(TokenAmount::from_atto(10)),
+                },
+                "funded".into(),
+            )
+            .unwrap();
+        assert!(state_manager.cancel_scheduled_task(cancelled).unwrap());
+        assert!(!state_manager.cancel_scheduled_task(cancelled).unwrap());
+
+        let observe = |state_manager: &StateManager<MemoryDB>, tipset| {
+            state_manager
+                .scheduler
+                .observe(state_manager, &tipset)
+                .unwrap()
+        };
+        observe(&state_manager, tipset(&db, 4, &[(address, 1)]));
+        assert!(recorder.0.lock().is_empty());
+        // The epoch is null.
+        observe(&state_manager, tipset(&db, 6, &[(address, 1)]));
+        assert_eq!(*recorder.0.lock(), [(6, "upgrade".into())]);
+
+        // Pending tasks are kept, and run once their handler is registered.
+        let state_manager = self::state_manager(&db);
+        assert_eq!(state_manager.scheduled_tasks().len(), 1);
+        observe(&state_manager, tipset(&db, 7, &[(address, 10)]));
+        assert_eq!(recorder.0.lock().len(), 1);
+        state_manager.register_scheduled_handler(Arc::new(recorder.clone()));
+        // IDs aren't reused.
+        let later = state_manager
+            .schedule_task("recorder", Trigger::Epoch(100), "later".into())
+            .unwrap();
+        assert_eq!(later, 3);
+        assert!(state_manager.cancel_scheduled_task(later).unwrap());
+        observe(&state_manager, tipset(&db, 8, &[(address, 10)]));
+        observe(&state_manager, tipset(&db, 9, &[(address, 10)]));
+        assert_eq!(
+            *recorder.0.lock(),
+            [(6, "upgrade".into()), (8, "funded".into())]
+        );
+        assert!(self::state_manager(&db).scheduled_tasks().is_empty());
+    }
+}
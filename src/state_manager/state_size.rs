@@ -0,0 +1,235 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Storage used by the actors of a state tree, to find what drives the growth
+//! of the state and to evaluate pruning policies.
+//!
+//! The blocks reachable from the state of an actor are attributed to it.
+//! Blocks shared by several actors, e.g. empty collections, are attributed to
+//! each of them, so the sizes of the actors add up to more than the unique
+//! size of the state. The code of the actors isn't part of their size.
+
+use std::collections::BTreeMap;
+
+use crate::ipld::{CidHashSet, DfsIter, Ipld};
+use crate::shim::{address::Address, crypto::IPLD_RAW, machine::Manifest, state_tree::StateTree};
+use crate::utils::encoding::from_slice_with_fallback;
+use ahash::HashMap;
+use anyhow::Context as _;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::{CborStore, DAG_CBOR};
+use std::sync::Arc;
+
+/// Number and total size of blocks.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Size {
+    pub blocks: u64,
+    pub bytes: u64,
+}
+
+impl Size {
+    fn add(&mut self, other: Size) {
+        self.blocks += other.blocks;
+        self.bytes += other.bytes;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActorSize {
+    pub address: Address,
+    /// Name of the builtin actor, or its code CID if it isn't builtin.
+    pub class: String,
+    pub size: Size,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassSize {
+    pub class: String,
+    pub actors: u64,
+    pub size: Size,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateSize {
+    /// Largest actors first.
+    pub actors: Vec<ActorSize>,
+    /// Largest classes first.
+    pub classes: Vec<ClassSize>,
+    /// Blocks of the state tree itself, e.g. the nodes of the actors HAMT.
+    pub tree: Size,
+    /// Unique blocks of the whole state.
+    pub total: Size,
+}
+
+/// Walks the state tree at `state_root` and attributes the reachable blocks
+/// to the actors.
+pub fn state_size<DB: Blockstore>(db: &Arc<DB>, state_root: &Cid) -> anyhow::Result<StateSize> {
+    let tree = StateTree::new_from_root(Arc::clone(db), state_root)?;
+    let classes = builtin_actor_names(db, &tree).unwrap_or_default();
+
+    // Blocks of all the actors, and their code, which is left out.
+    let mut seen = CidHashSet::default();
+    let mut total = Size::default();
+    let mut actors = vec![];
+    tree.for_each(|address, actor| {
+        seen.insert(actor.code);
+        let mut actor_seen = CidHashSet::default();
+        let size = walk(db.as_ref(), actor.state, &mut actor_seen, |cid, bytes| {
+            if seen.insert(*cid) {
+                total.add(Size { blocks: 1, bytes });
+            }
+        })
+        .with_context(|| format!("failed to walk the state of {address}"))?;
+        let class = match classes.get(&actor.code) {
+            Some(name) => name.clone(),
+            None => actor.code.to_string(),
+        };
+        actors.push(ActorSize {
+            address,
+            class,
+            size,
+        });
+        Ok(())
+    })?;
+    // The remaining blocks are the ones of the tree.
+    let tree = walk(db.as_ref(), *state_root, &mut seen, |_, _| {})?;
+    total.add(tree);
+
+    let mut classes: BTreeMap<&str, ClassSize> = BTreeMap::new();
+    for actor in &actors {
+        let class = classes.entry(&actor.class).or_insert_with(|| ClassSize {
+            class: actor.class.clone(),
+            actors: 0,
+            size: Size::default(),
+        });
+        class.actors += 1;
+        class.size.add(actor.size);
+    }
+    let mut classes: Vec<_> = classes.into_values().collect();
+    classes.sort_by(|a, b| b.size.bytes.cmp(&a.size.bytes));
+    actors.sort_by(|a, b| {
+        b.size
+            .bytes
+            .cmp(&a.size.bytes)
+            .then_with(|| a.address.cmp(&b.address))
+    });
+    Ok(StateSize {
+        actors,
+        classes,
+        tree,
+        total,
+    })
+}
+
+/// Returns the size of the blocks reachable from `root` that are not in
+/// `seen`, and adds them to it. `on_block` is called with each of them.
+fn walk(
+    db: &impl Blockstore,
+    root: Cid,
+    seen: &mut CidHashSet,
+    mut on_block: impl FnMut(&Cid, u64),
+) -> anyhow::Result<Size> {
+    let mut size = Size::default();
+    let mut queue = vec![root];
+    while let Some(cid) = queue.pop() {
+        // Other codecs, e.g. sector commitments, don't refer to blocks.
+        if !matches!(cid.codec(), DAG_CBOR | IPLD_RAW) || !seen.insert(cid) {
+            continue;
+        }
+        let data = db
+            .get(&cid)?
+            .with_context(|| format!("missing block {cid}"))?;
+        let bytes = data.len() as u64;
+        size.add(Size { blocks: 1, bytes });
+        on_block(&cid, bytes);
+        if cid.codec() == DAG_CBOR {
+            let ipld: Ipld = from_slice_with_fallback(&data)?;
+            queue.extend(DfsIter::new(ipld).filter_map(|ipld| match ipld {
+                Ipld::Link(cid) => Some(cid),
+                _ => None,
+            }));
+        }
+    }
+    Ok(size)
+}
+
+/// Returns the names of the builtin actors by code CID, from the manifest in
+/// the state of the system actor.
+fn builtin_actor_names<DB: Blockstore>(
+    db: &Arc<DB>,
+    tree: &StateTree<DB>,
+) -> anyhow::Result<HashMap<Cid, String>> {
+    let system = tree
+        .get_actor(&Address::SYSTEM_ACTOR)?
+        .context("system actor not found")?;
+    let (builtin_actors,): (Cid,) = db
+        .get_cbor(&system.state)?
+        .context("system actor state not found")?;
+    let manifest = Manifest::load_with_actors(db, &builtin_actors, 1)?;
+    Ok(manifest
+        .builtin_actors()
+        .map(|(name, code)| (*code, name.clone()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MemoryDB;
+    use crate::shim::{econ::TokenAmount, state_tree::ActorState, state_tree::StateTreeVersion};
+    use crate::utils::db::CborStoreExt;
+
+    #[test]
+    fn attribute_blocks_to_actors() {
+        let db = Arc::new(MemoryDB::default());
+        let shared = db.put_cbor_default(&"shared collection").unwrap();
+        let small = db.put_cbor_default(&(shared,)).unwrap();
+        let large = db.put_cbor_default(&(shared, vec![0u8; 100])).unwrap();
+        let code = db.put_cbor_default(&"code").unwrap();
+
+        let mut tree = StateTree::new(db.clone(), StateTreeVersion::V5).unwrap();
+        for (id, head) in [(100, small), (101, large), (102, large)] {
+            let actor = ActorState::new(code, head, TokenAmount::from_atto(0), 0, None);
+            tree.set_actor(&Address::new_id(id), actor).unwrap();
+        }
+        let state_root = tree.flush().unwrap();
+
+        let size = state_size(&db, &state_root).unwrap();
+        let block_size = |cid| db.get(&cid).unwrap().unwrap().len() as u64;
+        let large_size = Size {
+            blocks: 2,
+            bytes: block_size(large) + block_size(shared),
+        };
+        let actors: Vec<_> = size
+            .actors
+            .iter()
+            .map(|actor| (actor.address, actor.size))
+            .collect();
+        assert_eq!(
+            actors,
+            [
+                (Address::new_id(101), large_size),
+                (Address::new_id(102), large_size),
+                (
+                    Address::new_id(100),
+                    Size {
+                        blocks: 2,
+                        bytes: block_size(small) + block_size(shared),
+                    }
+                ),
+            ]
+        );
+        // Without a system actor, the classes are the code CIDs.
+        assert_eq!(size.classes.len(), 1);
+        assert_eq!(size.classes[0].class, code.to_string());
+        assert_eq!(size.classes[0].actors, 3);
+
+        // Shared blocks are counted once in the total, and the code isn't.
+        assert!(size.tree.bytes >= block_size(state_root));
+        assert_eq!(
+            size.total.bytes,
+            block_size(small) + block_size(large) + block_size(shared) + size.tree.bytes
+        );
+    }
+}
@@ -1,10 +1,11 @@
 // Copyright 2019-2023 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+use crate::networks::proof_policy;
 use crate::shim::{
     address::{Address, Payload},
     randomness::Randomness,
-    sector::{RegisteredPoStProof, RegisteredSealProof, SectorInfo},
+    sector::{RegisteredPoStProof, SectorInfo},
     state_tree::ActorState,
     version::NetworkVersion,
 };
@@ -72,15 +73,12 @@ where
         }
 
         let info = mas.info(store)?;
-        let spt = RegisteredSealProof::from_sector_size(info.sector_size().into(), nv);
-
-        let wpt = spt
-            .registered_winning_post_proof()
-            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let spt = proof_policy::seal_proof(info.sector_size().into(), nv)?;
+        let wpt = proof_policy::winning_post_proof(spt, nv)?;
 
         let m_id = miner_address.id()?;
 
-        let ids = generate_winning_post_sector_challenge(wpt.into(), m_id, rand, num_prov_sect)?;
+        let ids = generate_winning_post_sector_challenge(wpt, m_id, rand, num_prov_sect)?;
 
         let mut iter = proving_sectors.iter();
 
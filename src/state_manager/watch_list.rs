@@ -0,0 +1,358 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Watch-list of addresses, e.g. the deposit addresses of an exchange.
+//!
+//! Every tipset applied to the head of the chain, the balance, nonce and state
+//! root of the watched actors are compared to the ones seen at the previous
+//! head. The changes are published to the subscribers of the watch-list, and
+//! the recent ones are kept in the settings store, so that a restarted node
+//! reports the changes that happened while it was down.
+//!
+//! The state of a tipset is the one after the execution of its parent, so a
+//! message is seen one tipset after the one that includes it.
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::blocks::{Tipset, TipsetKeys};
+use crate::chain::HeadChange;
+use crate::db::{setting_keys::WATCH_LIST_KEY_PREFIX, SettingsStore, SettingsStoreExt};
+use crate::shim::{
+    address::{Address, StrictAddress},
+    clock::ChainEpoch,
+    econ::TokenAmount,
+    state_tree::StateTree,
+};
+use crate::state_manager::StateManager;
+use ahash::HashMap;
+use anyhow::Context as _;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{info, warn};
+
+/// Capacity of the channel of the watch-list events.
+const EVENT_CAP: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+#[cfg_attr(test, derive(derive_quickcheck_arbitrary::Arbitrary))]
+pub struct WatchListConfig {
+    /// Watched addresses. No addresses disables the watch-list.
+    pub addresses: Vec<String>,
+    /// Number of changes kept for every address.
+    #[cfg_attr(test, arbitrary(gen(|g| u32::arbitrary(g) as _)))]
+    pub history_size: usize,
+}
+
+impl Default for WatchListConfig {
+    fn default() -> Self {
+        Self {
+            addresses: vec![],
+            history_size: 100,
+        }
+    }
+}
+
+/// Watched fields of an actor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct WatchedState {
+    #[serde(with = "crate::lotus_json")]
+    pub balance: TokenAmount,
+    pub nonce: u64,
+    #[serde(with = "crate::lotus_json")]
+    pub state: Cid,
+}
+
+/// Change of a watched actor. The state is missing if the actor doesn't
+/// exist.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct WatchEvent {
+    #[serde(with = "crate::lotus_json")]
+    pub address: Address,
+    /// Tipset of the new state.
+    pub epoch: ChainEpoch,
+    #[serde(with = "crate::lotus_json")]
+    pub tipset: TipsetKeys,
+    pub previous: Option<WatchedState>,
+    pub current: Option<WatchedState>,
+}
+
+pub struct WatchList {
+    addresses: Vec<Address>,
+    history_size: usize,
+    /// Last seen state of the watched actors.
+    last_seen: Mutex<HashMap<Address, Option<WatchedState>>>,
+    publisher: broadcast::Sender<WatchEvent>,
+}
+
+impl Default for WatchList {
+    fn default() -> Self {
+        Self::with_addresses(vec![], WatchListConfig::default().history_size)
+    }
+}
+
+impl WatchList {
+    pub fn new(config: &WatchListConfig) -> anyhow::Result<Self> {
+        let addresses = config
+            .addresses
+            .iter()
+            .map(|address| {
+                StrictAddress::from_str(address)
+                    .map(Address::from)
+                    .with_context(|| format!("Invalid watched address {address}"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self::with_addresses(addresses, config.history_size))
+    }
+
+    fn with_addresses(addresses: Vec<Address>, history_size: usize) -> Self {
+        let (publisher, _) = broadcast::channel(EVENT_CAP);
+        Self {
+            addresses,
+            history_size,
+            last_seen: Default::default(),
+            publisher,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    pub fn publisher(&self) -> &broadcast::Sender<WatchEvent> {
+        &self.publisher
+    }
+
+    /// Returns the recorded changes of a watched address, oldest first.
+    pub fn history(
+        &self,
+        settings: &dyn SettingsStore,
+        address: &Address,
+    ) -> anyhow::Result<Vec<WatchEvent>> {
+        anyhow::ensure!(
+            self.addresses.contains(address),
+            "{address} isn't in the watch-list"
+        );
+        Ok(settings
+            .read_obj(&watch_list_key(address))?
+            .unwrap_or_default())
+    }
+
+    /// Compares the watched actors in the state of `tipset` to the last seen
+    /// ones, and records and publishes the changes.
+    fn observe<DB: Blockstore>(
+        &self,
+        settings: &dyn SettingsStore,
+        db: &Arc<DB>,
+        tipset: &Tipset,
+    ) -> anyhow::Result<()> {
+        let tree = StateTree::new_from_root(Arc::clone(db), tipset.parent_state())?;
+        let mut last_seen = self.last_seen.lock();
+        for address in &self.addresses {
+            let current = tree.get_actor(address)?.map(|actor| WatchedState {
+                balance: TokenAmount::from(&actor.balance),
+                nonce: actor.sequence,
+                state: actor.state,
+            });
+            let mut history = self.history(settings, address)?;
+            // After a restart, the last seen state is the one of the last
+            // recorded change. Addresses without changes start from the
+            // current state.
+            let previous = match last_seen.get(address) {
+                Some(previous) => previous.clone(),
+                None => match history.last() {
+                    Some(event) => event.current.clone(),
+                    None => current.clone(),
+                },
+            };
+            last_seen.insert(*address, current.clone());
+            if previous == current {
+                continue;
+            }
+
+            let event = WatchEvent {
+                address: *address,
+                epoch: tipset.epoch(),
+                tipset: tipset.key().clone(),
+                previous,
+                current,
+            };
+            info!(
+                "Watched actor {address} changed at epoch {}: {}",
+                event.epoch,
+                describe(&event)
+            );
+            history.push(event.clone());
+            let excess = history.len().saturating_sub(self.history_size);
+            history.drain(..excess);
+            settings.write_obj(&watch_list_key(address), &history)?;
+            // There may be no subscribers.
+            let _ = self.publisher.send(event);
+        }
+        Ok(())
+    }
+
+    /// Watches the addresses on every head change, forever.
+    pub async fn run<DB>(
+        self: Arc<Self>,
+        state_manager: Arc<StateManager<DB>>,
+    ) -> anyhow::Result<()>
+    where
+        DB: Blockstore + Send + Sync + 'static,
+    {
+        info!(
+            "Watching the actors {}",
+            self.addresses
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let chain_store = state_manager.chain_store();
+        let db = state_manager.blockstore_owned();
+        let mut head_changes = chain_store.publisher().subscribe();
+        let mut head = chain_store.heaviest_tipset();
+        loop {
+            if let Err(e) = self.observe(chain_store.settings().as_ref(), &db, &head) {
+                warn!("Failed to check the watched actors: {e:#}");
+            }
+            head = match head_changes.recv().await {
+                Ok(HeadChange::Apply(tipset)) => tipset,
+                // The actors are compared to the last seen state, so changes
+                // are combined rather than missed.
+                Err(RecvError::Lagged(_)) => chain_store.heaviest_tipset(),
+                Err(RecvError::Closed) => return Ok(()),
+            };
+        }
+    }
+}
+
+fn watch_list_key(address: &Address) -> String {
+    format!("{WATCH_LIST_KEY_PREFIX}{address}")
+}
+
+fn describe(event: &WatchEvent) -> String {
+    match (&event.previous, &event.current) {
+        (None, Some(_)) => "created".into(),
+        (Some(_), None) => "deleted".into(),
+        (Some(previous), Some(current)) => {
+            let mut changes = vec![];
+            if previous.balance != current.balance {
+                changes.push(format!(
+                    "balance {} -> {}",
+                    previous.balance, current.balance
+                ));
+            }
+            if previous.nonce != current.nonce {
+                changes.push(format!("nonce {} -> {}", previous.nonce, current.nonce));
+            }
+            if previous.state != current.state {
+                changes.push("state".into());
+            }
+            changes.join(", ")
+        }
+        (None, None) => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::BlockHeader;
+    use crate::db::MemoryDB;
+    use crate::shim::state_tree::{ActorState, StateTreeVersion};
+
+    /// Returns a tipset whose parent state holds `actors`.
+    fn tipset(db: &Arc<MemoryDB>, epoch: ChainEpoch, actors: &[(Address, u64)]) -> Tipset {
+        let mut tree = StateTree::new(Arc::clone(db), StateTreeVersion::V5).unwrap();
+        for (address, balance) in actors {
+            let actor = ActorState::new(
+                Cid::default(),
+                Cid::default(),
+                TokenAmount::from_atto(*balance),
+                0,
+                None,
+            );
+            tree.set_actor(address, actor).unwrap();
+        }
+        Tipset::from(
+            BlockHeader::builder()
+                .miner_address(Address::new_id(0))
+                .epoch(epoch)
+                .state_root(tree.flush().unwrap())
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn record_changes() {
+        let db = Arc::new(MemoryDB::default());
+        let (a, b) = (Address::new_id(100), Address::new_id(101));
+        let config = WatchListConfig {
+            addresses: vec![a.to_string(), b.to_string()],
+            history_size: 2,
+        };
+        let watch_list = WatchList::new(&config).unwrap();
+        let mut events = watch_list.publisher().subscribe();
+
+        watch_list
+            .observe(db.as_ref(), &db, &tipset(&db, 1, &[(a, 1)]))
+            .unwrap();
+        assert!(watch_list.history(db.as_ref(), &a).unwrap().is_empty());
+        for (epoch, balance) in [(2, 1), (3, 5), (4, 7), (5, 9)] {
+            watch_list
+                .observe(
+                    db.as_ref(),
+                    &db,
+                    &tipset(&db, epoch, &[(a, balance), (b, 1)]),
+                )
+                .unwrap();
+        }
+
+        let balances = |address| {
+            watch_list
+                .history(db.as_ref(), &address)
+                .unwrap()
+                .iter()
+                .map(|event| {
+                    (
+                        event.epoch,
+                        event.current.as_ref().map(|state| state.balance.clone()),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        // The history is bounded.
+        assert_eq!(
+            balances(a),
+            [
+                (4, Some(TokenAmount::from_atto(7))),
+                (5, Some(TokenAmount::from_atto(9)))
+            ]
+        );
+        assert_eq!(balances(b), [(2, Some(TokenAmount::from_atto(1)))]);
+        assert_eq!(events.try_recv().unwrap().address, b);
+        assert_eq!(events.try_recv().unwrap().epoch, 3);
+
+        // A restarted watch-list resumes from the recorded changes.
+        let watch_list = WatchList::new(&config).unwrap();
+        watch_list
+            .observe(db.as_ref(), &db, &tipset(&db, 6, &[(a, 10)]))
+            .unwrap();
+        assert_eq!(
+            balances(a).last().unwrap(),
+            &(6, Some(TokenAmount::from_atto(10)))
+        );
+        assert_eq!(balances(b).last().unwrap(), &(6, None));
+        assert!(watch_list
+            .history(db.as_ref(), &Address::new_id(102))
+            .is_err());
+    }
+}
@@ -55,6 +55,10 @@ macro_rules! impl_system {
                         new_head,
                     }))
                 }
+
+                fn inputs(&self) -> Vec<Cid> {
+                    vec![self.new_builtin_actors_cid, self.new_code_cid]
+                }
             }
         }
     };
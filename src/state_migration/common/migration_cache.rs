@@ -0,0 +1,129 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use crate::shim::{address::Address, clock::ChainEpoch, econ::TokenAmount};
+use ahash::HashMap;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+/// Code and state head of an actor.
+pub(in crate::state_migration) type CodeAndHead = (Cid, Cid);
+
+/// Everything an actor migration depends on: the address, code, head and
+/// balance of the actor, and the inputs of the migrator, see
+/// [`ActorMigration::inputs`](super::ActorMigration::inputs).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(in crate::state_migration) struct MigrationInputs {
+    pub address: Address,
+    pub prior: CodeAndHead,
+    pub balance: TokenAmount,
+    pub migrator: Vec<Cid>,
+}
+
+/// Outputs of actor migrations, keyed by their inputs.
+type Migrations = HashMap<MigrationInputs, Option<CodeAndHead>>;
+
+/// Outputs of the actor migrations of the last upgrade epoch, keyed by all
+/// their inputs. The state of most actors is the same on the forks of an
+/// upgrade epoch and when a tipset is executed again, so their migration is
+/// done once. Outputs of other networks or upgrades have other inputs, the
+/// code of the migrated actors at least, and are never reused.
+#[derive(Default)]
+pub(in crate::state_migration) struct MigrationCache {
+    /// Prior epoch of the cached migrations, and their outputs. `None` if the
+    /// actor was left unchanged.
+    inner: Mutex<(ChainEpoch, Migrations)>,
+}
+
+impl MigrationCache {
+    /// Cache shared by the migrations of all network upgrades.
+    pub(in crate::state_migration) fn global() -> &'static MigrationCache {
+        static CACHE: Lazy<MigrationCache> = Lazy::new(Default::default);
+        &CACHE
+    }
+
+    /// Returns the cached output of the migration of the actor, if the new
+    /// state is still in `store`.
+    pub(in crate::state_migration) fn get(
+        &self,
+        store: &impl Blockstore,
+        prior_epoch: ChainEpoch,
+        inputs: &MigrationInputs,
+    ) -> Option<Option<CodeAndHead>> {
+        let inner = self.inner.lock();
+        if inner.0 != prior_epoch {
+            return None;
+        }
+        let output = *inner.1.get(inputs)?;
+        match output {
+            Some((_, head)) if !store.has(&head).unwrap_or(false) => None,
+            output => Some(output),
+        }
+    }
+
+    /// Caches the output of the migration of an actor. The cache is cleared
+    /// when the prior epoch changes.
+    pub(in crate::state_migration) fn insert(
+        &self,
+        prior_epoch: ChainEpoch,
+        inputs: MigrationInputs,
+        output: Option<CodeAndHead>,
+    ) {
+        let mut inner = self.inner.lock();
+        if inner.0 != prior_epoch {
+            *inner = (prior_epoch, HashMap::default());
+        }
+        inner.1.insert(inputs, output);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MemoryDB;
+    use crate::utils::db::CborStoreExt;
+
+    #[test]
+    fn cache_outputs_of_an_epoch() {
+        let db = MemoryDB::default();
+        let cache = MigrationCache::default();
+        let stored = db.put_cbor_default(&"new state").unwrap();
+        let missing = Cid::default();
+        let inputs = |id| MigrationInputs {
+            address: Address::new_id(id),
+            prior: (Cid::default(), Cid::default()),
+            balance: TokenAmount::default(),
+            migrator: vec![],
+        };
+        let (a, b, c) = (inputs(1), inputs(2), inputs(3));
+
+        cache.insert(10, a.clone(), Some((Cid::default(), stored)));
+        cache.insert(10, b.clone(), None);
+        cache.insert(10, c.clone(), Some((Cid::default(), missing)));
+        assert_eq!(cache.get(&db, 10, &a), Some(Some((Cid::default(), stored))));
+        assert_eq!(cache.get(&db, 10, &b), Some(None));
+        // The new state was removed from the store.
+        assert_eq!(cache.get(&db, 10, &c), None);
+        assert_eq!(cache.get(&db, 11, &a), None);
+
+        // Migrations with other inputs don't share outputs.
+        let other_balance = MigrationInputs {
+            balance: TokenAmount::from_atto(1),
+            ..a.clone()
+        };
+        assert_eq!(cache.get(&db, 10, &other_balance), None);
+        let other_market = MigrationInputs {
+            migrator: vec![stored],
+            ..a.clone()
+        };
+        assert_eq!(cache.get(&db, 10, &other_market), None);
+
+        // Another epoch clears the cache.
+        cache.insert(11, b.clone(), None);
+        assert_eq!(cache.get(&db, 10, &a), None);
+        assert_eq!(cache.get(&db, 11, &a), None);
+        assert_eq!(cache.get(&db, 11, &b), Some(None));
+    }
+}
@@ -6,7 +6,10 @@ use std::sync::Arc;
 use crate::shim::{address::Address, clock::ChainEpoch, state_tree::ActorState};
 use fvm_ipld_blockstore::Blockstore;
 
-use super::{ActorMigration, ActorMigrationInput};
+use super::{
+    migration_cache::{MigrationCache, MigrationInputs},
+    ActorMigration, ActorMigrationInput,
+};
 
 /// Defines migration result for a single actor migration.
 #[derive(Debug)]
@@ -23,36 +26,52 @@ pub(in crate::state_migration) struct MigrationJob<BS: Blockstore> {
 }
 
 impl<BS: Blockstore> MigrationJob<BS> {
+    /// Runs the migration, or reuses its output from `cache`.
     pub(in crate::state_migration) fn run(
         &self,
         store: &Arc<BS>,
         prior_epoch: ChainEpoch,
+        cache: &MigrationCache,
     ) -> anyhow::Result<Option<MigrationJobOutput>> {
-        if let Some(result) = self
-            .actor_migration
-            .migrate_state(
-                store,
-                ActorMigrationInput {
-                    address: self.address,
-                    balance: self.actor_state.balance.clone().into(),
-                    head: self.actor_state.state,
-                    prior_epoch,
-                },
-            )
-            .map_err(|e| {
-                anyhow::anyhow!(
-                    "state migration failed for {} actor, addr {}:{}",
-                    self.actor_state.code,
-                    self.address,
-                    e
-                )
-            })?
-        {
+        let inputs = MigrationInputs {
+            address: self.address,
+            prior: (self.actor_state.code, self.actor_state.state),
+            balance: self.actor_state.balance.clone().into(),
+            migrator: self.actor_migration.inputs(),
+        };
+        let output = match cache.get(store, prior_epoch, &inputs) {
+            Some(output) => output,
+            None => {
+                let output = self
+                    .actor_migration
+                    .migrate_state(
+                        store,
+                        ActorMigrationInput {
+                            address: self.address,
+                            balance: self.actor_state.balance.clone().into(),
+                            head: self.actor_state.state,
+                            prior_epoch,
+                        },
+                    )
+                    .map_err(|e| {
+                        anyhow::anyhow!(
+                            "state migration failed for {} actor, addr {}:{}",
+                            self.actor_state.code,
+                            self.address,
+                            e
+                        )
+                    })?
+                    .map(|result| (result.new_code_cid, result.new_head));
+                cache.insert(prior_epoch, inputs, output);
+                output
+            }
+        };
+        if let Some((new_code_cid, new_head)) = output {
             Ok(Some(MigrationJobOutput {
                 address: self.address,
                 actor_state: ActorState::new(
-                    result.new_code_cid,
-                    result.new_head,
+                    new_code_cid,
+                    new_head,
                     self.actor_state.balance.clone().into(),
                     self.actor_state.sequence,
                     self.actor_state.delegated_address.map(Address::from),
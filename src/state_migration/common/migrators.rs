@@ -23,6 +23,10 @@ impl<BS: Blockstore> ActorMigration<BS> for NilMigrator {
             new_head: input.head,
         }))
     }
+
+    fn inputs(&self) -> Vec<Cid> {
+        vec![self.0]
+    }
 }
 
 /// Creates a new migrator which preserves the head CID and provides a fixed
@@ -44,4 +48,8 @@ impl<BS: Blockstore> ActorMigration<BS> for DeferredMigrator {
     ) -> anyhow::Result<Option<ActorMigrationOutput>> {
         Ok(None)
     }
+
+    fn inputs(&self) -> Vec<Cid> {
+        vec![]
+    }
 }
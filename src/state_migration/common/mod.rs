@@ -11,6 +11,7 @@ use cid::Cid;
 use fvm_ipld_blockstore::Blockstore;
 
 mod macros;
+mod migration_cache;
 mod migration_job;
 pub(in crate::state_migration) mod migrators;
 mod state_migration;
@@ -46,6 +47,11 @@ pub(in crate::state_migration) trait ActorMigration<BS: Blockstore> {
         store: &BS,
         input: ActorMigrationInput,
     ) -> anyhow::Result<Option<ActorMigrationOutput>>;
+
+    /// Inputs of the migration besides the actor, e.g. the new code or the
+    /// state of another actor that is read. Outputs are only reused from the
+    /// migration cache for the same inputs.
+    fn inputs(&self) -> Vec<Cid>;
 }
 
 /// Trait that defines the interface for actor migration job to be executed after the state migration.
@@ -8,8 +8,11 @@ use crate::shim::{clock::ChainEpoch, state_tree::StateTree};
 use cid::Cid;
 use fvm_ipld_blockstore::Blockstore;
 
-use super::{verifier::MigrationVerifier, Migrator, PostMigratorArc};
+use super::{
+    migration_cache::MigrationCache, verifier::MigrationVerifier, Migrator, PostMigratorArc,
+};
 use crate::state_migration::common::migration_job::{MigrationJob, MigrationJobOutput};
+use crate::utils::io::WithProgressRaw;
 
 /// Handles several cases of migration:
 /// - nil migrations, essentially mapping one Actor to another,
@@ -100,7 +103,7 @@ impl<BS: Blockstore + Send + Sync> StateMigration<BS> {
                             actor_migration: migrator,
                         };
 
-                        let job_output = job.run(store, prior_epoch).unwrap_or_else(|e| {
+                        let job_output = job.run(store, prior_epoch, MigrationCache::global()).unwrap_or_else(|e| {
                             panic!(
                                 "failed executing job for address: {address}, Reason: {e}"
                             )
@@ -114,7 +117,12 @@ impl<BS: Blockstore + Send + Sync> StateMigration<BS> {
                 drop(job_tx);
             });
 
+            #[allow(deprecated)] // Tracking issue: https://github.com/ChainSafe/forest/issues/3157
+            let progress = WithProgressRaw::new("Migrated actors", 0);
+            let mut migrated = 0;
             while let Ok(job_output) = job_rx.recv() {
+                migrated += 1;
+                progress.set(migrated);
                 if let Some(MigrationJobOutput {
                     address,
                     actor_state,
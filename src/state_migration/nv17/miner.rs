@@ -111,6 +111,17 @@ where
             new_head,
         }))
     }
+    fn inputs(&self) -> Vec<Cid> {
+        // The empty deadlines depend on the policy of the network.
+        vec![
+            self.out_code,
+            self.market_proposals,
+            self.empty_deadline_v8_cid,
+            self.empty_deadlines_v8_cid,
+            self.empty_deadline_v9_cid,
+            self.empty_deadlines_v9_cid,
+        ]
+    }
 }
 
 impl MinerMigrator {
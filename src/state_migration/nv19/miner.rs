@@ -43,4 +43,7 @@ impl<BS: Blockstore> ActorMigration<BS> for MinerMigrator {
             new_head,
         }))
     }
+    fn inputs(&self) -> Vec<Cid> {
+        vec![self.0]
+    }
 }
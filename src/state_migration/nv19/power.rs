@@ -85,4 +85,7 @@ impl<BS: Blockstore> ActorMigration<BS> for PowerMigrator {
             new_head,
         }))
     }
+    fn inputs(&self) -> Vec<Cid> {
+        vec![self.0]
+    }
 }
@@ -9,13 +9,19 @@ use std::{
     sync::Arc,
 };
 
+use crate::ipld::diff::hamt_diff;
 use crate::ipld::json::{IpldJson, IpldJsonRef};
 use crate::json::cid::CidJson;
 use crate::shim::{
     address::Address,
+    machine::{
+        ACCOUNT_ACTOR_NAME, CRON_ACTOR_NAME, DATACAP_ACTOR_NAME, EVM_ACTOR_NAME, INIT_ACTOR_NAME,
+        MARKET_ACTOR_NAME, MINER_ACTOR_NAME, MULTISIG_ACTOR_NAME, POWER_ACTOR_NAME,
+        REWARD_ACTOR_NAME, SYSTEM_ACTOR_NAME,
+    },
     state_tree::{ActorState, StateTree},
 };
-use ahash::HashMap;
+use crate::state_manager::diff::actors_root;
 use cid::Cid;
 use colored::*;
 use fil_actor_interface::{
@@ -53,20 +59,6 @@ fn actor_to_resolved(
     }
 }
 
-fn root_to_state_map<BS: Blockstore>(
-    bs: &Arc<BS>,
-    root: &Cid,
-) -> Result<HashMap<Address, ActorState>, anyhow::Error> {
-    let mut actors = HashMap::default();
-    let state_tree = StateTree::new_from_root(bs.clone(), root)?;
-    state_tree.for_each(|addr: Address, actor: &ActorState| {
-        actors.insert(addr, actor.clone());
-        Ok(())
-    })?;
-
-    Ok(actors)
-}
-
 /// Tries to resolve state tree actors, if all data exists in store.
 /// The actors HAMT is hard to parse in a diff, so this attempts to remedy this.
 /// This function will only print the actors that are added, removed, or changed
@@ -77,19 +69,17 @@ fn try_print_actor_states<BS: Blockstore>(
     expected_root: &Cid,
     depth: Option<u64>,
 ) -> Result<(), anyhow::Error> {
-    // For now, resolving to a map, because we need to use go implementation's
-    // inefficient caching this would probably be faster in most cases.
-    let mut e_state = root_to_state_map(bs, expected_root)?;
-
-    // Compare state with expected
+    // Only walk the parts of the actors HAMTs that differ.
+    let diff = hamt_diff(bs, &actors_root(bs, expected_root), &actors_root(bs, root))?;
     let state_tree = StateTree::new_from_root(bs.clone(), root)?;
+    let e_state_tree = StateTree::new_from_root(bs.clone(), expected_root)?;
 
-    state_tree.for_each(|addr: Address, actor| {
-        let calc_pp = pp_actor_state(bs, actor, depth)?;
-
-        if let Some(other) = e_state.remove(&addr) {
-            if &other != actor {
+    for key in diff.keys() {
+        let addr = Address::from_bytes(key)?;
+        match (e_state_tree.get_actor(&addr)?, state_tree.get_actor(&addr)?) {
+            (Some(other), Some(actor)) => {
                 let comma = ",";
+                let calc_pp = pp_actor_state(bs, &actor, depth)?;
                 let expected_pp = pp_actor_state(bs, &other, depth)?;
                 let expected = expected_pp.split(comma).collect::<Vec<&str>>();
                 let calculated = calc_pp.split(comma).collect::<Vec<&str>>();
@@ -99,18 +89,19 @@ fn try_print_actor_states<BS: Blockstore>(
                 writeln!(handle, "Address {addr} changed: ")?;
                 print_diffs(&mut handle, diffs)?;
             }
-        } else {
-            // Added actor, print out the json format actor state.
-            println!("{}", format!("+ Address {addr}:\n{calc_pp}").green());
+            (None, Some(actor)) => {
+                // Added actor, print out the json format actor state.
+                let calc_pp = pp_actor_state(bs, &actor, depth)?;
+                println!("{}", format!("+ Address {addr}:\n{calc_pp}").green());
+            }
+            (Some(state), None) => {
+                // Actor no longer has state
+                let expected_json =
+                    serde_json::to_string_pretty(&actor_to_resolved(bs, &state, depth))?;
+                println!("{}", format!("- Address {addr}:\n{expected_json}").red())
+            }
+            (None, None) => {}
         }
-
-        Ok(())
-    })?;
-
-    // Print all addresses that no longer have actor state
-    for (addr, state) in e_state.into_iter() {
-        let expected_json = serde_json::to_string_pretty(&actor_to_resolved(bs, &state, depth))?;
-        println!("{}", format!("- Address {addr}:\n{expected_json}").red())
     }
 
     Ok(())
@@ -123,48 +114,8 @@ fn pp_actor_state(
 ) -> Result<String, anyhow::Error> {
     let mut buffer = String::new();
     writeln!(&mut buffer, "{actor_state:?}")?;
-    if let Ok(miner_state) = MinerState::load(bs, actor_state.code, actor_state.state) {
-        write!(&mut buffer, "{miner_state:?}")?;
-        return Ok(buffer);
-    }
-    if let Ok(cron_state) = CronState::load(bs, actor_state.code, actor_state.state) {
-        write!(&mut buffer, "{cron_state:?}")?;
-        return Ok(buffer);
-    }
-    if let Ok(account_state) = AccountState::load(bs, actor_state.code, actor_state.state) {
-        write!(&mut buffer, "{account_state:?}")?;
-        return Ok(buffer);
-    }
-    if let Ok(power_state) = PowerState::load(bs, actor_state.code, actor_state.state) {
-        write!(&mut buffer, "{power_state:?}")?;
-        return Ok(buffer);
-    }
-    if let Ok(init_state) = InitState::load(bs, actor_state.code, actor_state.state) {
-        write!(&mut buffer, "{init_state:?}")?;
-        return Ok(buffer);
-    }
-    if let Ok(reward_state) = RewardState::load(bs, actor_state.code, actor_state.state) {
-        write!(&mut buffer, "{reward_state:?}")?;
-        return Ok(buffer);
-    }
-    if let Ok(system_state) = SystemState::load(bs, actor_state.code, actor_state.state) {
-        write!(&mut buffer, "{system_state:?}")?;
-        return Ok(buffer);
-    }
-    if let Ok(multi_sig_state) = MultiSigState::load(bs, actor_state.code, actor_state.state) {
-        write!(&mut buffer, "{multi_sig_state:?}")?;
-        return Ok(buffer);
-    }
-    if let Ok(market_state) = MarketState::load(bs, actor_state.code, actor_state.state) {
-        write!(&mut buffer, "{market_state:?}")?;
-        return Ok(buffer);
-    }
-    if let Ok(datacap_state) = DatacapState::load(bs, actor_state.code, actor_state.state) {
-        write!(&mut buffer, "{datacap_state:?}")?;
-        return Ok(buffer);
-    }
-    if let Ok(evm_state) = EvmState::load(bs, actor_state.code, actor_state.state) {
-        write!(&mut buffer, "{evm_state:?}")?;
+    if let Some((_, decoded)) = decode_actor_state(bs, actor_state) {
+        write!(&mut buffer, "{decoded}")?;
         return Ok(buffer);
     }
 
@@ -173,6 +124,38 @@ fn pp_actor_state(
     Ok(buffer)
 }
 
+/// Decodes the state of a builtin actor, returning the name of the actor and
+/// the debug representation of its state. Returns `None` for the actors that
+/// `fil_actor_interface` can't decode.
+pub fn decode_actor_state(
+    bs: &impl Blockstore,
+    actor_state: &ActorState,
+) -> Option<(&'static str, String)> {
+    macro_rules! decode {
+        ($($state:ty => $name:expr),* $(,)?) => {
+            $(
+                if let Ok(state) = <$state>::load(bs, actor_state.code, actor_state.state) {
+                    return Some(($name, format!("{state:?}")));
+                }
+            )*
+        };
+    }
+    decode!(
+        MinerState => MINER_ACTOR_NAME,
+        CronState => CRON_ACTOR_NAME,
+        AccountState => ACCOUNT_ACTOR_NAME,
+        PowerState => POWER_ACTOR_NAME,
+        InitState => INIT_ACTOR_NAME,
+        RewardState => REWARD_ACTOR_NAME,
+        SystemState => SYSTEM_ACTOR_NAME,
+        MultiSigState => MULTISIG_ACTOR_NAME,
+        MarketState => MARKET_ACTOR_NAME,
+        DatacapState => DATACAP_ACTOR_NAME,
+        EvmState => EVM_ACTOR_NAME,
+    );
+    None
+}
+
 fn print_diffs(handle: &mut impl Write, diffs: TextDiff<str>) -> std::io::Result<()> {
     for op in diffs.ops() {
         for change in diffs.iter_changes(op) {
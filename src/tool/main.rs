@@ -22,6 +22,10 @@ where
             // Run command
             match cmd {
                 Subcommand::Benchmark(benchmark) => benchmark.run().await,
+                Subcommand::Shed(shed) => shed.run().await,
+                Subcommand::ReplayMessage(replay) => replay.run().await,
+                Subcommand::AuditLog(audit_log) => audit_log.run(),
+                Subcommand::StateMigration(state_migration) => state_migration.run().await,
             }
         })
 }
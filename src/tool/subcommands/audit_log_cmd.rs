@@ -0,0 +1,53 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::{fs::File, io::BufReader, path::PathBuf};
+
+use crate::libp2p::PeerId;
+use crate::state_manager::audit_log::{verify, SIGNATURE_INTERVAL};
+
+#[derive(Debug, clap::Subcommand)]
+pub enum AuditLogCommands {
+    /// Check that the records of an audit log are chained and signed
+    Verify {
+        /// Path to the `audit.log` file of the node
+        path: PathBuf,
+        /// Require the signatures to be made by this node
+        #[arg(long)]
+        peer_id: Option<PeerId>,
+    },
+}
+
+impl AuditLogCommands {
+    pub fn run(self) -> anyhow::Result<()> {
+        match self {
+            Self::Verify { path, peer_id } => {
+                let summary = verify(BufReader::new(File::open(path)?))?;
+                if let Some(peer_id) = peer_id {
+                    anyhow::ensure!(
+                        summary.signers.iter().all(|signer| signer == &peer_id),
+                        "the log is signed by {:?}",
+                        summary.signers
+                    );
+                }
+                match summary.epochs {
+                    Some((first, last)) => {
+                        println!("{} records, epochs {first} to {last}", summary.records)
+                    }
+                    None => println!("No records"),
+                }
+                println!(
+                    "{} records are signed, by {:?}",
+                    summary.signed_records, summary.signers
+                );
+                let unsigned = summary.records - summary.signed_records;
+                if unsigned > 0 {
+                    println!(
+                        "The last {unsigned} records are not signed yet, the log is signed every {SIGNATURE_INTERVAL} records"
+                    );
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -3,25 +3,42 @@
 
 use crate::chain::{
     index::{ChainIndex, ResolveNullTipset},
+    store::state_cache::{BlockCache, CachedBlockstore},
     ChainEpochDelta,
 };
+use crate::cli_shared::cli::Config;
+use crate::daemon::bundle::load_actor_bundles;
 use crate::db::car::ManyCar;
+use crate::db::{parity_db::ParityDb, parity_db_config::ParityDbConfig, MemoryDB};
+use crate::interpreter::resolve_to_key_addr;
 use crate::ipld::{stream_chain, stream_graph, DfsIter};
-use crate::shim::clock::ChainEpoch;
+use crate::message::ChainMessage;
+use crate::networks::{calibnet, mainnet, ChainConfig, NetworkChain};
+use crate::shim::{
+    address::Address, clock::ChainEpoch, executor::ApplyRet, machine::MultiEngine,
+    state_tree::StateTree,
+};
+use crate::state_manager::apply_block_messages;
 use crate::utils::db::car_stream::{Block, CarStream};
 use crate::utils::encoding::from_slice_with_fallback;
+use crate::utils::proofs_api::paramfetch::{
+    ensure_params_downloaded, set_proofs_parameter_cache_dir_env,
+};
 use crate::utils::stream::par_buffer;
 use anyhow::{Context as _, Result};
 use cid::Cid;
 use clap::Subcommand;
+use fil_actor_interface::miner;
 use futures::{StreamExt, TryStreamExt};
+use fvm_ipld_blockstore::Blockstore;
 use fvm_ipld_encoding::DAG_CBOR;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{ProgressBar, ProgressIterator, ProgressStyle};
 use itertools::Itertools;
 use libipld_core::ipld::Ipld;
 use std::ops::Deref;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::{
     fs::File,
     io::{AsyncWrite, AsyncWriteExt, BufReader},
@@ -72,6 +89,47 @@ pub enum BenchmarkCommands {
         #[arg(short, long, default_value_t = 2000)]
         depth: ChainEpochDelta,
     },
+    /// Bulk loading the blocks of CAR archives into each database backend
+    DbWrite {
+        /// Snapshot input files (`.car.`, `.car.zst`, `.forest.car.zst`)
+        #[arg(required = true)]
+        snapshot_files: Vec<PathBuf>,
+        /// Database backends to benchmark
+        #[arg(long, value_delimiter = ',', default_value = "memory,parity-db")]
+        backends: Vec<DbBackend>,
+        /// Number of blocks written in a single batch
+        #[arg(long, default_value_t = 10_000)]
+        batch_size: usize,
+    },
+    /// Executing the messages of the most recent tipsets of a snapshot
+    VmExecution {
+        /// Snapshot input files (`.car.`, `.car.zst`, `.forest.car.zst`)
+        #[arg(required = true)]
+        snapshot_files: Vec<PathBuf>,
+        /// Number of tipsets to execute. The snapshot must have the state
+        /// roots of these tipsets, and 900 more for lookbacks.
+        #[arg(long, default_value_t = 10)]
+        epochs: u32,
+    },
+    /// Looking up the worker keys of the miners of the most recent tipsets of
+    /// a snapshot, as the VM externs do, with state caches of several sizes
+    StateCache {
+        /// Snapshot input files (`.car.`, `.car.zst`, `.forest.car.zst`)
+        #[arg(required = true)]
+        snapshot_files: Vec<PathBuf>,
+        /// Number of tipsets whose miners are looked up
+        #[arg(long, default_value_t = 100)]
+        epochs: u32,
+        /// Sizes of the cache in MiB, `0` disables it
+        #[arg(long, value_delimiter = ',', default_value = "0,16,64,256")]
+        sizes: Vec<usize>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DbBackend {
+    Memory,
+    ParityDb,
 }
 
 impl BenchmarkCommands {
@@ -102,6 +160,20 @@ impl BenchmarkCommands {
                 benchmark_exporting(snapshot_files, compression_level, frame_size, epoch, depth)
                     .await
             }
+            Self::DbWrite {
+                snapshot_files,
+                backends,
+                batch_size,
+            } => benchmark_db_write(snapshot_files, backends, batch_size).await,
+            Self::VmExecution {
+                snapshot_files,
+                epochs,
+            } => benchmark_vm_execution(snapshot_files, epochs).await,
+            Self::StateCache {
+                snapshot_files,
+                epochs,
+                sizes,
+            } => benchmark_state_cache(snapshot_files, epochs, sizes),
         }
     }
 }
@@ -233,6 +305,184 @@ async fn benchmark_exporting(
     Ok(())
 }
 
+// Stream the blocks of a set of CAR files into each database backend, in
+// batches, and measure the write throughput.
+async fn benchmark_db_write(
+    input: Vec<PathBuf>,
+    backends: Vec<DbBackend>,
+    batch_size: usize,
+) -> Result<()> {
+    for backend in backends {
+        let started = Instant::now();
+        let (blocks, bytes) = match backend {
+            DbBackend::Memory => {
+                write_blocks(&MemoryDB::default(), input.clone(), batch_size).await?
+            }
+            DbBackend::ParityDb => {
+                let dir = tempfile::tempdir()?;
+                let db = ParityDb::open(dir.path(), &ParityDbConfig::default())?;
+                write_blocks(&db, input.clone(), batch_size).await?
+            }
+        };
+        let elapsed = started.elapsed().as_secs_f64();
+        println!(
+            "{backend:?}: wrote {blocks} blocks ({}) in {elapsed:.1}s, {:.0} blocks/s, {}/s",
+            indicatif::HumanBytes(bytes),
+            blocks as f64 / elapsed,
+            indicatif::HumanBytes((bytes as f64 / elapsed) as u64)
+        );
+    }
+    Ok(())
+}
+
+async fn write_blocks(
+    db: &impl Blockstore,
+    input: Vec<PathBuf>,
+    batch_size: usize,
+) -> Result<(u64, u64)> {
+    let pb = ProgressBar::new_spinner().with_style(
+        ProgressStyle::with_template("{spinner} wrote {human_pos} blocks in {elapsed_precise}")
+            .expect("infallible"),
+    );
+    pb.enable_steady_tick(std::time::Duration::from_secs_f32(0.1));
+
+    let mut s = Box::pin(
+        futures::stream::iter(input)
+            .then(File::open)
+            .map_ok(BufReader::new)
+            .and_then(CarStream::new)
+            .try_flatten(),
+    );
+    let (mut blocks, mut bytes) = (0, 0);
+    let mut batch = Vec::with_capacity(batch_size);
+    while let Some(block) = s.try_next().await? {
+        blocks += 1;
+        bytes += block.data.len() as u64;
+        batch.push((block.cid, block.data));
+        if batch.len() >= batch_size {
+            db.put_many_keyed(std::mem::take(&mut batch))?;
+            pb.set_position(blocks);
+        }
+    }
+    db.put_many_keyed(batch)?;
+    pb.finish_and_clear();
+    Ok((blocks, bytes))
+}
+
+// Execute the most recent tipsets of a snapshot one after the other, and
+// measure how many messages are applied per second. Tipsets are executed
+// sequentially, unlike snapshot validation, so the throughput is the one of a
+// node following the chain.
+async fn benchmark_vm_execution(input: Vec<PathBuf>, epochs: u32) -> Result<()> {
+    let store = Arc::new(open_store(input)?);
+    let heaviest = store.heaviest_tipset()?;
+    let genesis = heaviest.genesis(&store)?;
+    let network = if genesis.cid() == &*calibnet::GENESIS_CID {
+        NetworkChain::Calibnet
+    } else if genesis.cid() == &*mainnet::GENESIS_CID {
+        NetworkChain::Mainnet
+    } else {
+        anyhow::bail!("Unrecognizable genesis block");
+    };
+    let chain_config = Arc::new(ChainConfig::from_chain(&network));
+    let beacon = Arc::new(chain_config.get_beacon_schedule(genesis.timestamp()));
+
+    // Bundles are required when doing state migrations.
+    load_actor_bundles(&store).await?;
+    set_proofs_parameter_cache_dir_env(&Config::default().client.data_dir, None);
+    ensure_params_downloaded().await?;
+
+    let chain_index = Arc::new(ChainIndex::new(Arc::clone(&store)));
+    let last_epoch = heaviest.epoch() - epochs as i64;
+    let mut tipsets = chain_index
+        .chain(Arc::new(heaviest))
+        .take_while(|tipset| tipset.epoch() >= last_epoch)
+        .collect_vec();
+    // Execute from the oldest tipset.
+    tipsets.reverse();
+
+    let pb = ProgressBar::new(tipsets.len() as u64).with_style(
+        ProgressStyle::with_template("{spinner} executed {pos}/{len} tipsets in {elapsed_precise}")
+            .expect("infallible"),
+    );
+    let genesis_timestamp = genesis.timestamp();
+    let started = Instant::now();
+    let (executed, messages) = tokio::task::spawn_blocking(move || {
+        let engine = MultiEngine::default();
+        let (mut executed, mut messages) = (0u64, 0u64);
+        for tipset in tipsets.into_iter().progress_with(pb) {
+            apply_block_messages(
+                genesis_timestamp,
+                Arc::clone(&chain_index),
+                Arc::clone(&chain_config),
+                Arc::clone(&beacon),
+                &engine,
+                tipset,
+                Some(|_: &Cid, message: &ChainMessage, _: &ApplyRet| {
+                    // Rewards and cron are implicit messages.
+                    if message.message().from != Address::SYSTEM_ACTOR {
+                        messages += 1;
+                    }
+                    Ok(())
+                }),
+            )?;
+            executed += 1;
+        }
+        anyhow::Ok((executed, messages))
+    })
+    .await??;
+    let elapsed = started.elapsed().as_secs_f64();
+    println!(
+        "executed {executed} tipsets with {messages} messages in {elapsed:.1}s, {:.1} messages/s",
+        messages as f64 / elapsed
+    );
+    Ok(())
+}
+
+fn benchmark_state_cache(input: Vec<PathBuf>, epochs: u32, sizes: Vec<usize>) -> Result<()> {
+    let store = Arc::new(open_store(input)?);
+    let heaviest = store.heaviest_tipset()?;
+    let last_epoch = heaviest.epoch() - epochs as i64;
+    let chain_index = ChainIndex::new(Arc::clone(&store));
+    let lookups = chain_index
+        .chain(Arc::new(heaviest))
+        .take_while(|tipset| tipset.epoch() >= last_epoch)
+        .flat_map(|tipset| {
+            let state_root = *tipset.parent_state();
+            tipset
+                .blocks()
+                .iter()
+                .map(|block| (state_root, *block.miner_address()))
+                .collect_vec()
+        })
+        .collect_vec();
+
+    for size in sizes {
+        let cache = Arc::new(BlockCache::new(size * 1024 * 1024));
+        let cached = Arc::new(CachedBlockstore::new(
+            Arc::clone(&store),
+            Arc::clone(&cache),
+        ));
+        let started = Instant::now();
+        for (state_root, miner) in &lookups {
+            let state = StateTree::new_from_root(Arc::clone(&cached), state_root)?;
+            let actor = state
+                .get_actor(miner)?
+                .with_context(|| format!("miner {miner} not found"))?;
+            let miner_state = miner::State::load(&cached, actor.code, actor.state)?;
+            resolve_to_key_addr(&state, &cached, &miner_state.info(&cached)?.worker.into())?;
+        }
+        let (hits, misses) = cache.stats();
+        println!(
+            "{size} MiB: {} lookups in {:.2}s, {hits} hits, {misses} misses, {} cached",
+            lookups.len(),
+            started.elapsed().as_secs_f64(),
+            indicatif::HumanBytes(cache.size() as u64),
+        );
+    }
+    Ok(())
+}
+
 // Sink with attached progress indicator
 fn indicatif_sink(task: &'static str) -> impl AsyncWrite {
     let sink = tokio::io::sink();
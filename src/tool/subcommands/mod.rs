@@ -1,7 +1,11 @@
 // Copyright 2019-2023 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+pub mod audit_log_cmd;
 pub mod benchmark_cmd;
+pub mod replay_cmd;
+pub mod shed_cmd;
+pub mod state_migration_cmd;
 
 use crate::cli_shared::cli::HELP_MESSAGE;
 use crate::utils::version::FOREST_VERSION_STRING;
@@ -22,4 +26,20 @@ pub enum Subcommand {
     /// Benchmark various Forest subsystems
     #[command(subcommand)]
     Benchmark(benchmark_cmd::BenchmarkCommands),
+
+    /// Miscellaneous developer tools
+    #[command(subcommand)]
+    Shed(shed_cmd::ShedCommands),
+
+    /// Execute a message again from the database of a stopped node and print
+    /// its execution trace
+    ReplayMessage(replay_cmd::ReplayCommand),
+
+    /// Verify the audit log of the states computed by a node
+    #[command(subcommand)]
+    AuditLog(audit_log_cmd::AuditLogCommands),
+
+    /// Check the state migrations of network upgrades
+    #[command(subcommand)]
+    StateMigration(state_migration_cmd::StateMigrationCommands),
 }
@@ -0,0 +1,255 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Replay of a message from the database of a stopped node, to debug failed
+//! messages. See [`StateManager::replay_message`].
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::blocks::{Tipset, TipsetKeys};
+use crate::chain::ChainStore;
+use crate::cli_shared::cli::Config;
+use crate::daemon::bundle::load_actor_bundles;
+use crate::db::{
+    db_engine::{db_root, open_proxy_db},
+    rolling::RollingDB,
+    setting_keys::HEAD_KEY,
+    SettingsStoreExt,
+};
+use crate::networks::{ChainConfig, NetworkChain};
+use crate::shim::executor::TraceEvent;
+use crate::state_manager::{
+    execution_trace::ExecutionTrace, message_index::MessageReplay, StateManager,
+};
+use crate::utils::proofs_api::paramfetch::{
+    ensure_params_downloaded, set_proofs_parameter_cache_dir_env,
+};
+use anyhow::Context as _;
+use cid::Cid;
+
+#[derive(Debug, clap::Args)]
+pub struct ReplayCommand {
+    /// CID of the message
+    message: Cid,
+    /// Network of the database
+    #[arg(long, default_value = "mainnet")]
+    chain: NetworkChain,
+    /// Data directory of the node, defaults to the one of the default
+    /// configuration
+    #[arg(long)]
+    data_dir: Option<PathBuf>,
+    /// Print every gas charge instead of the gas used by each call
+    #[arg(long)]
+    gas_charges: bool,
+    /// Print the replay as JSON
+    #[arg(long)]
+    json: bool,
+    /// Print the execution trace as JSON, in the format of the trace returned
+    /// by `StateReplay` in Lotus
+    #[arg(long, conflicts_with = "json")]
+    lotus_json: bool,
+}
+
+impl ReplayCommand {
+    pub async fn run(self) -> anyhow::Result<()> {
+        let (state_manager, _) = open_state_manager(&self.chain, self.data_dir).await?;
+        let replay = state_manager.replay_message(self.message).await?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&replay)?);
+        } else if self.lotus_json {
+            let trace = ExecutionTrace::from_events(&replay.trace)?;
+            println!("{}", serde_json::to_string_pretty(&trace)?);
+        } else {
+            for line in format_replay(&replay, self.gas_charges) {
+                println!("{line}");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Opens the database of a stopped node. Returns the database and the head of
+/// the node.
+pub(super) fn open_node_db(
+    chain: &NetworkChain,
+    data_dir: Option<PathBuf>,
+) -> anyhow::Result<(Arc<RollingDB>, Tipset)> {
+    let data_dir = data_dir.unwrap_or_else(|| Config::default().client.data_dir);
+    let db = Arc::new(open_proxy_db(
+        db_root(&data_dir.join(chain.to_string())),
+        Default::default(),
+    )?);
+    let head_key: TipsetKeys = db
+        .read_obj(HEAD_KEY)?
+        .context("the head of the chain is not set")?;
+    let head =
+        Tipset::load(&db, &head_key)?.with_context(|| format!("the head {head_key} is missing"))?;
+    Ok((db, head))
+}
+
+/// Opens the database of a stopped node, for messages to be executed again.
+/// Returns the state manager and the head of the node.
+pub(super) async fn open_state_manager(
+    chain: &NetworkChain,
+    data_dir: Option<PathBuf>,
+) -> anyhow::Result<(Arc<StateManager<RollingDB>>, Arc<Tipset>)> {
+    let data_dir = data_dir.unwrap_or_else(|| Config::default().client.data_dir);
+    let (db, head) = open_node_db(chain, Some(data_dir.clone()))?;
+    let genesis = head.genesis(&db)?;
+    let chain_config = Arc::new(ChainConfig::from_chain(chain));
+
+    // Bundles are required when the tipset runs a state migration, and
+    // parameters when the message verifies proofs.
+    load_actor_bundles(db.as_ref()).await?;
+    set_proofs_parameter_cache_dir_env(&data_dir, None);
+    ensure_params_downloaded().await?;
+
+    let chain_store = Arc::new(ChainStore::new(
+        db.clone(),
+        db,
+        chain_config.clone(),
+        genesis,
+    )?);
+    let state_manager = Arc::new(StateManager::new(chain_store, chain_config)?);
+    Ok((state_manager, Arc::new(head)))
+}
+
+fn format_replay(replay: &MessageReplay, gas_charges: bool) -> Vec<String> {
+    let mut lines = vec![
+        format!("Tipset: {} at epoch {}", replay.tipset, replay.epoch),
+        format!(
+            "Exit code: {}, gas used: {}",
+            replay.receipt.exit_code().value(),
+            replay.receipt.gas_used()
+        ),
+    ];
+    if let Some(failure) = &replay.failure_info {
+        lines.push(format!("Failure: {failure}"));
+    }
+    if replay.trace.is_empty() {
+        lines.push("No execution trace".into());
+    } else {
+        lines.push("Execution trace:".into());
+        lines.extend(format_trace(&replay.trace, gas_charges));
+    }
+    lines
+}
+
+/// Prints the calls as a tree. Without `gas_charges`, the gas charged during
+/// a call, including its subcalls, is printed when it returns.
+pub(super) fn format_trace(trace: &[TraceEvent], gas_charges: bool) -> Vec<String> {
+    let mut lines = vec![];
+    // Gas charged in each open call.
+    let mut calls: Vec<u64> = vec![];
+    let mut charged_outside = 0;
+    for event in trace {
+        let indent = "  ".repeat(calls.len() + 1);
+        match event {
+            TraceEvent::GasCharge { name, gas, .. } => {
+                match calls.last_mut() {
+                    Some(charged) => *charged += gas,
+                    None => charged_outside += gas,
+                }
+                if gas_charges {
+                    lines.push(format!("{indent}gas {name}: {gas}"));
+                }
+            }
+            TraceEvent::Call {
+                from,
+                to,
+                method,
+                value,
+                ..
+            } => {
+                lines.push(format!(
+                    "{indent}call f0{from} -> {to} method {method} value {}",
+                    value.atto()
+                ));
+                calls.push(0);
+            }
+            TraceEvent::CallReturn { exit_code, .. } => close_call(
+                &mut lines,
+                &mut calls,
+                gas_charges,
+                format!("exit code {}", exit_code.value()),
+            ),
+            TraceEvent::CallError(error) => close_call(
+                &mut lines,
+                &mut calls,
+                gas_charges,
+                format!("error {} (number {})", error.message, error.number),
+            ),
+        }
+    }
+    if !gas_charges && charged_outside > 0 {
+        lines.push(format!("  gas outside of calls: {charged_outside}"));
+    }
+    lines
+}
+
+fn close_call(lines: &mut Vec<String>, calls: &mut Vec<u64>, gas_charges: bool, outcome: String) {
+    let charged = calls.pop().unwrap_or_default();
+    if let Some(parent) = calls.last_mut() {
+        *parent += charged;
+    }
+    let indent = "  ".repeat(calls.len() + 1);
+    if gas_charges {
+        lines.push(format!("{indent}return {outcome}"));
+    } else {
+        lines.push(format!("{indent}return {outcome}, gas {charged}"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shim::{address::Address, econ::TokenAmount};
+    use fvm_shared3::error::ExitCode;
+
+    #[test]
+    fn trace_tree() {
+        let gas = |gas| TraceEvent::GasCharge {
+            name: "OnMethodInvocation".into(),
+            gas,
+            compute_gas: gas,
+            storage_gas: 0,
+        };
+        let call = |from, to| TraceEvent::Call {
+            from,
+            to: Address::new_id(to),
+            method: 2,
+            value: TokenAmount::default(),
+            params: vec![],
+            params_codec: 0,
+        };
+        let trace = vec![
+            gas(5),
+            call(100, 200),
+            gas(10),
+            call(200, 300),
+            gas(20),
+            TraceEvent::CallReturn {
+                exit_code: ExitCode::USR_FORBIDDEN,
+                return_data: vec![],
+                return_codec: 0,
+            },
+            TraceEvent::CallReturn {
+                exit_code: ExitCode::OK,
+                return_data: vec![],
+                return_codec: 0,
+            },
+        ];
+        assert_eq!(
+            format_trace(&trace, false),
+            [
+                "  call f0100 -> f0200 method 2 value 0",
+                "    call f0200 -> f0300 method 2 value 0",
+                "    return exit code 18, gas 20",
+                "  return exit code 0, gas 30",
+                "  gas outside of calls: 5",
+            ]
+        );
+        assert_eq!(format_trace(&trace, true).len(), trace.len());
+    }
+}
@@ -0,0 +1,511 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Miscellaneous developer tools.
+//!
+//! The actor schemas are derived by reflection over the `serde` implementations
+//! of the builtin actor states. Actor states are encoded as CBOR tuples, so
+//! fields are identified by their position rather than by their name.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::blocks::{Tipset, TipsetKeys};
+use crate::chain::index::ResolveNullTipset;
+use crate::db::car::ManyCar;
+use crate::interpreter::VMTrace;
+use crate::message::inspect;
+use crate::networks::{ChainConfig, NetworkChain};
+use crate::shim::{address::Address, clock::ChainEpoch, executor::Receipt};
+use crate::state_manager::{
+    chain_rand::draw_randomness,
+    diff::diff_state_trees,
+    domain_separation::DomainSeparation,
+    forensics::Reexecution,
+    state_size::{state_size, StateSize},
+};
+use anyhow::Context as _;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use cid::Cid;
+use clap::Subcommand;
+use indicatif::HumanBytes;
+use itertools::{EitherOrBoth, Itertools as _};
+use serde::Deserialize;
+use serde_json::Value;
+use serde_reflection::{Samples, Tracer, TracerConfig};
+
+use super::replay_cmd::{format_trace, open_state_manager};
+
+#[derive(Debug, Subcommand)]
+pub enum ShedCommands {
+    /// Print the schema of every builtin actor state of an actors version as
+    /// JSON
+    ActorSchema {
+        /// Actors version, e.g. `v11`
+        #[arg(value_parser = parse_actors_version)]
+        version: u64,
+    },
+    /// Print the field-level differences between the builtin actor states of
+    /// two actors versions, e.g. to review a state migration
+    ActorSchemaDiff {
+        /// Actors version to compare from, e.g. `v10`
+        #[arg(value_parser = parse_actors_version)]
+        from: u64,
+        /// Actors version to compare to, e.g. `v11`
+        #[arg(value_parser = parse_actors_version)]
+        to: u64,
+        /// Only compare the state of this actor, e.g. `miner`
+        #[arg(long)]
+        actor: Option<String>,
+    },
+    /// Decode a signed or unsigned message, print its canonical CID, and the
+    /// Ethereum transaction hash of delegated messages, and check its fields
+    MessageCid {
+        /// File holding the message as `Lotus` JSON, or as raw or hex encoded
+        /// CBOR. Inline JSON is accepted too.
+        input: String,
+        /// Network of the message, which determines the Ethereum chain ID
+        #[arg(long, default_value = "mainnet")]
+        chain: NetworkChain,
+    },
+    /// Attribute the blocks of the state tree of a tipset to each actor and
+    /// builtin actor class, and print the largest ones
+    StateSize {
+        /// Snapshot files holding the state tree
+        #[arg(required = true)]
+        snapshot_files: Vec<PathBuf>,
+        /// CIDs of the blocks of the tipset whose parent state is measured.
+        /// Defaults to the heaviest tipset of the snapshot.
+        #[arg(long, num_args = 1..)]
+        tipset: Vec<Cid>,
+        /// Number of actors printed
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+    },
+    /// Execute a tipset again from the database of a stopped node, compare
+    /// the receipts and the state root with those declared on chain, and
+    /// print the first diverging message
+    Reexecute {
+        /// CIDs of the blocks of the tipset
+        #[arg(long, num_args = 1.., required = true)]
+        tipset: Vec<Cid>,
+        /// Network of the database
+        #[arg(long, default_value = "mainnet")]
+        chain: NetworkChain,
+        /// Data directory of the node, defaults to the one of the default
+        /// configuration
+        #[arg(long)]
+        data_dir: Option<PathBuf>,
+        /// Print the execution trace of the first diverging message
+        #[arg(long)]
+        trace: bool,
+        /// Print the actors that differ between the declared and the computed
+        /// states. The declared state is usually missing locally, it can be
+        /// fetched with `forest-cli state fetch`.
+        #[arg(long)]
+        diff: bool,
+    },
+    /// Draw randomness from a beacon entry or a ticket, as the node and the
+    /// actors do, to check the derivation by hand
+    Rand {
+        /// Domain separation tag, by name or value, e.g. `ticket-production`
+        #[arg(long)]
+        dst: DomainSeparation,
+        /// Epoch the randomness is drawn for
+        #[arg(long)]
+        epoch: ChainEpoch,
+        /// Entropy, as hex
+        #[arg(long, default_value = "")]
+        entropy: String,
+        /// Address whose CBOR encoding is the entropy, as for tickets,
+        /// election proofs and PoSt challenges
+        #[arg(long, conflicts_with = "entropy")]
+        entropy_address: Option<Address>,
+        /// Base of the randomness, as hex: the data of the beacon entry, or
+        /// the VRF proof of the ticket
+        #[arg(long)]
+        base: String,
+    },
+}
+
+impl ShedCommands {
+    pub async fn run(self) -> anyhow::Result<()> {
+        match self {
+            Self::ActorSchema { version } => {
+                let schemas = actor_schemas(version)?;
+                println!("{}", serde_json::to_string_pretty(&schemas)?);
+                Ok(())
+            }
+            Self::ActorSchemaDiff { from, to, actor } => {
+                let from_schemas = actor_schemas(from)?;
+                let to_schemas = actor_schemas(to)?;
+                let actors: BTreeSet<_> = from_schemas.keys().chain(to_schemas.keys()).collect();
+                for name in actors {
+                    if actor.as_deref().is_some_and(|actor| actor != *name) {
+                        continue;
+                    }
+                    match (from_schemas.get(name), to_schemas.get(name)) {
+                        (Some(_), None) => println!("- {name}: removed in v{to}"),
+                        (None, Some(_)) => println!("+ {name}: added in v{to}"),
+                        (Some(from_schema), Some(to_schema)) => {
+                            let mut changes = vec![];
+                            diff_json(name, from_schema, to_schema, &mut changes);
+                            if changes.is_empty() {
+                                println!("= {name}: unchanged");
+                            }
+                            for change in changes {
+                                println!("{change}");
+                            }
+                        }
+                        (None, None) => unreachable!("actor names are taken from both schemas"),
+                    }
+                }
+                Ok(())
+            }
+            Self::MessageCid { input, chain } => {
+                let bytes = if input.trim_start().starts_with('{') {
+                    input.into_bytes()
+                } else {
+                    std::fs::read(&input).with_context(|| format!("Failed to read {input}"))?
+                };
+                let (message, declared_cid) = inspect::decode(&bytes)?;
+                let eth_chain_id = ChainConfig::from_chain(&chain).eth_chain_id;
+                println!("{}", inspect::inspect(message, declared_cid, eth_chain_id)?);
+                Ok(())
+            }
+            Self::StateSize {
+                snapshot_files,
+                tipset,
+                top,
+            } => {
+                let store = Arc::new(ManyCar::try_from(snapshot_files)?);
+                let tipset = match tipset.is_empty() {
+                    true => store.heaviest_tipset()?,
+                    false => Tipset::load_required(&store, &TipsetKeys::from(tipset))?,
+                };
+                let size = state_size(&store, tipset.parent_state())?;
+                for line in format_state_size(&size, top) {
+                    println!("{line}");
+                }
+                Ok(())
+            }
+            Self::Reexecute {
+                tipset,
+                chain,
+                data_dir,
+                trace,
+                diff,
+            } => {
+                let (state_manager, head) = open_state_manager(&chain, data_dir).await?;
+                let tipset = Arc::new(Tipset::load_required(
+                    state_manager.blockstore(),
+                    &TipsetKeys::from(tipset),
+                )?);
+                let child = state_manager.chain_store().chain_index.tipset_by_height(
+                    tipset.epoch() + 1,
+                    head,
+                    ResolveNullTipset::TakeNewer,
+                )?;
+                anyhow::ensure!(
+                    child.parents() == tipset.key(),
+                    "tipset {} isn't on the chain of the node",
+                    tipset.key()
+                );
+                let vm_trace = match trace {
+                    true => VMTrace::Traced,
+                    false => VMTrace::NotTraced,
+                };
+                let reexecution = {
+                    let state_manager = Arc::clone(&state_manager);
+                    tokio::task::spawn_blocking(move || {
+                        state_manager.reexecute_tipset(tipset, child.min_ticket_block(), vm_trace)
+                    })
+                    .await??
+                };
+                for line in format_reexecution(&reexecution, trace) {
+                    println!("{line}");
+                }
+                if diff && reexecution.expected_state_root != reexecution.computed_state_root {
+                    let actors = diff_state_trees(
+                        &state_manager.blockstore_owned(),
+                        &reexecution.expected_state_root,
+                        &reexecution.computed_state_root,
+                    )?;
+                    println!("Actors differing from the declared state:");
+                    for actor in actors {
+                        println!(
+                            "  {:?} {}: balance {}, nonce {}, state changed: {}",
+                            actor.change,
+                            actor.address,
+                            actor.balance_delta.atto(),
+                            actor.nonce_delta,
+                            actor.state_changed
+                        );
+                    }
+                }
+                Ok(())
+            }
+            Self::Rand {
+                dst,
+                epoch,
+                entropy,
+                entropy_address,
+                base,
+            } => {
+                let entropy = match entropy_address {
+                    Some(address) => fvm_ipld_encoding::to_vec(&address)?,
+                    None => parse_hex(&entropy).context("invalid entropy")?,
+                };
+                let base = parse_hex(&base).context("invalid base")?;
+                let randomness = draw_randomness(&base, dst.value(), epoch, &entropy)?;
+                println!("tag:    {} ({})", dst.name, dst.value());
+                println!("hex:    {}", hex::encode(randomness));
+                println!("base64: {}", BASE64_STANDARD.encode(randomness));
+                Ok(())
+            }
+        }
+    }
+}
+
+fn parse_hex(s: &str) -> anyhow::Result<Vec<u8>> {
+    Ok(hex::decode(s.trim_start_matches("0x"))?)
+}
+
+fn parse_actors_version(s: &str) -> anyhow::Result<u64> {
+    s.strip_prefix('v')
+        .unwrap_or(s)
+        .parse()
+        .with_context(|| format!("invalid actors version: {s}"))
+}
+
+/// Traces the `serde` format of `T`, including all the named containers it
+/// refers to.
+fn trace<T: for<'de> Deserialize<'de>>() -> anyhow::Result<Value> {
+    let mut tracer = Tracer::new(TracerConfig::default());
+    // CIDs reject arbitrary bytes. Give the tracer a valid sample instead.
+    let mut samples = Samples::new();
+    // `serde_reflection` errors hold formats that aren't `Send`.
+    let err = |e: serde_reflection::Error| anyhow::anyhow!("{e}");
+    tracer
+        .trace_value(&mut samples, &Cid::default())
+        .map_err(err)?;
+    let (format, _) = tracer.trace_type::<T>(&samples).map_err(err)?;
+    let registry = tracer.registry().map_err(err)?;
+    Ok(serde_json::json!({ "state": format, "types": registry }))
+}
+
+macro_rules! actor_schemas {
+    ($version:ident: $($actor:literal => $krate:ident),+ $(,)?) => {
+        vec![$(($actor, trace::<$krate::$version::State>())),+]
+    };
+}
+
+/// Returns the schema of each builtin actor state, keyed by actor name. Actors
+/// whose state cannot be traced are reported and skipped.
+fn actor_schemas(version: u64) -> anyhow::Result<BTreeMap<&'static str, Value>> {
+    let schemas = match version {
+        8 => actor_schemas!(v8:
+            "account" => fil_actor_account_state,
+            "cron" => fil_actor_cron_state,
+            "init" => fil_actor_init_state,
+            "market" => fil_actor_market_state,
+            "miner" => fil_actor_miner_state,
+            "power" => fil_actor_power_state,
+            "reward" => fil_actor_reward_state,
+            "system" => fil_actor_system_state,
+            "verifreg" => fil_actor_verifreg_state,
+        ),
+        9 => actor_schemas!(v9:
+            "account" => fil_actor_account_state,
+            "cron" => fil_actor_cron_state,
+            "datacap" => fil_actor_datacap_state,
+            "init" => fil_actor_init_state,
+            "market" => fil_actor_market_state,
+            "miner" => fil_actor_miner_state,
+            "power" => fil_actor_power_state,
+            "reward" => fil_actor_reward_state,
+            "system" => fil_actor_system_state,
+            "verifreg" => fil_actor_verifreg_state,
+        ),
+        10 => actor_schemas!(v10:
+            "account" => fil_actor_account_state,
+            "cron" => fil_actor_cron_state,
+            "datacap" => fil_actor_datacap_state,
+            "init" => fil_actor_init_state,
+            "market" => fil_actor_market_state,
+            "miner" => fil_actor_miner_state,
+            "power" => fil_actor_power_state,
+            "reward" => fil_actor_reward_state,
+            "system" => fil_actor_system_state,
+            "verifreg" => fil_actor_verifreg_state,
+        ),
+        11 => actor_schemas!(v11:
+            "account" => fil_actor_account_state,
+            "cron" => fil_actor_cron_state,
+            "datacap" => fil_actor_datacap_state,
+            "init" => fil_actor_init_state,
+            "market" => fil_actor_market_state,
+            "miner" => fil_actor_miner_state,
+            "power" => fil_actor_power_state,
+            "reward" => fil_actor_reward_state,
+            "system" => fil_actor_system_state,
+            "verifreg" => fil_actor_verifreg_state,
+        ),
+        _ => anyhow::bail!("unsupported actors version: v{version}"),
+    };
+
+    Ok(schemas
+        .into_iter()
+        .filter_map(|(actor, schema)| match schema {
+            Ok(schema) => Some((actor, schema)),
+            Err(e) => {
+                eprintln!("skipping {actor} actor state of v{version}: {e}");
+                None
+            }
+        })
+        .collect())
+}
+
+fn format_state_size(size: &StateSize, top: usize) -> Vec<String> {
+    let mut lines = vec![format!(
+        "Total: {} in {} blocks, of which {} in {} blocks for the state tree",
+        HumanBytes(size.total.bytes),
+        size.total.blocks,
+        HumanBytes(size.tree.bytes),
+        size.tree.blocks,
+    )];
+    lines.push("By class:".into());
+    for class in &size.classes {
+        lines.push(format!(
+            "  {:<20} {:>12} in {:>10} blocks, {} actors",
+            class.class,
+            HumanBytes(class.size.bytes).to_string(),
+            class.size.blocks,
+            class.actors,
+        ));
+    }
+    lines.push(format!("Largest {} actors:", top.min(size.actors.len())));
+    for actor in size.actors.iter().take(top) {
+        lines.push(format!(
+            "  {:<20} {:>12} in {:>10} blocks, {}",
+            actor.address.to_string(),
+            HumanBytes(actor.size.bytes).to_string(),
+            actor.size.blocks,
+            actor.class,
+        ));
+    }
+    lines
+}
+
+fn format_reexecution(reexecution: &Reexecution, trace: bool) -> Vec<String> {
+    let mut lines = vec![
+        format!(
+            "Epoch {}: {} messages",
+            reexecution.epoch,
+            reexecution.messages.len()
+        ),
+        format!(
+            "State root: {} (declared), {} (computed)",
+            reexecution.expected_state_root, reexecution.computed_state_root
+        ),
+        format!(
+            "Receipt root: {} (declared), {} (computed)",
+            reexecution.expected_receipt_root, reexecution.computed_receipt_root
+        ),
+    ];
+    if reexecution.matches() {
+        lines.push("The tipset executes as declared".into());
+    }
+    let Some(index) = reexecution.first_divergence() else {
+        if !reexecution.matches() {
+            lines.push("Every receipt matches, the states diverge past the messages".into());
+        }
+        return lines;
+    };
+    let message = &reexecution.messages[index];
+    lines.push(format!("First diverging message: #{index} {}", message.cid));
+    let receipt = |receipt: &Receipt| {
+        format!(
+            "exit code {}, gas used {}, return {}",
+            receipt.exit_code().value(),
+            receipt.gas_used(),
+            hex::encode(receipt.return_data().bytes())
+        )
+    };
+    match &message.expected_receipt {
+        Some(expected) => lines.push(format!("  declared: {}", receipt(expected))),
+        None => lines.push("  declared: missing".into()),
+    }
+    lines.push(format!("  computed: {}", receipt(&message.receipt)));
+    if let Some(failure) = &message.failure_info {
+        lines.push(format!("  failure: {failure}"));
+    }
+    if trace {
+        lines.push("  execution trace:".into());
+        lines.extend(
+            format_trace(&message.trace, false)
+                .into_iter()
+                .map(|line| format!("  {line}")),
+        );
+    }
+    lines
+}
+
+/// Collects the differences between two JSON documents, one line per changed
+/// leaf. Lines are prefixed with `-` (removed), `+` (added) or `~` (changed).
+fn diff_json(path: &str, from: &Value, to: &Value, changes: &mut Vec<String>) {
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => {
+            let keys: BTreeSet<_> = from.keys().chain(to.keys()).collect();
+            for key in keys {
+                let path = format!("{path}.{key}");
+                match (from.get(key), to.get(key)) {
+                    (Some(from), Some(to)) => diff_json(&path, from, to, changes),
+                    (Some(from), None) => changes.push(format!("- {path}: {from}")),
+                    (None, Some(to)) => changes.push(format!("+ {path}: {to}")),
+                    (None, None) => {}
+                }
+            }
+        }
+        (Value::Array(from), Value::Array(to)) => {
+            for (idx, pair) in from.iter().zip_longest(to.iter()).enumerate() {
+                let path = format!("{path}[{idx}]");
+                match pair {
+                    EitherOrBoth::Both(from, to) => diff_json(&path, from, to, changes),
+                    EitherOrBoth::Left(from) => changes.push(format!("- {path}: {from}")),
+                    EitherOrBoth::Right(to) => changes.push(format!("+ {path}: {to}")),
+                }
+            }
+        }
+        (from, to) if from != to => changes.push(format!("~ {path}: {from} -> {to}")),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_version() {
+        assert_eq!(parse_actors_version("v11").unwrap(), 11);
+        assert_eq!(parse_actors_version("10").unwrap(), 10);
+        assert!(parse_actors_version("eleven").is_err());
+    }
+
+    #[test]
+    fn diff_fields() {
+        let from = serde_json::json!({ "STRUCT": [{ "a": "U64" }, { "b": "STR" }] });
+        let to = serde_json::json!({ "STRUCT": [{ "a": "I64" }] });
+        let mut changes = vec![];
+        diff_json("state", &from, &to, &mut changes);
+        assert_eq!(
+            changes,
+            [
+                r#"~ state.STRUCT[0].a: "U64" -> "I64""#,
+                r#"- state.STRUCT[1]: {"b":"STR"}"#,
+            ]
+        );
+    }
+}
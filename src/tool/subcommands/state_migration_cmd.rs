@@ -0,0 +1,131 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Dry-run of the state migration of a network upgrade, to check that a node
+//! is ready for the upgrade before it happens.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::daemon::bundle::load_actor_bundles;
+use crate::networks::{ChainConfig, NetworkChain};
+use crate::shim::clock::ChainEpoch;
+use crate::state_migration::run_state_migrations;
+use crate::utils::db::overlay::OverlayBlockstore;
+use crate::utils::monitoring::MemStatsTracker;
+use anyhow::Context as _;
+use cid::Cid;
+use human_repr::HumanCount;
+
+use super::replay_cmd::open_node_db;
+
+#[derive(Debug, clap::Subcommand)]
+pub enum StateMigrationCommands {
+    /// Run the state migration of an upgrade on the state of the head of a
+    /// stopped node, without modifying its database, and print the resulting
+    /// state root
+    DryRun {
+        /// Epoch of the upgrade
+        #[arg(long)]
+        height: ChainEpoch,
+        /// State root published for the upgrade, to compare the result with.
+        /// Requires the head to be at the epoch before the upgrade, as the
+        /// state of the head is migrated
+        #[arg(long)]
+        expected_root: Option<Cid>,
+        /// Network of the database
+        #[arg(long, default_value = "mainnet")]
+        chain: NetworkChain,
+        /// Data directory of the node, defaults to the one of the default
+        /// configuration
+        #[arg(long)]
+        data_dir: Option<PathBuf>,
+    },
+}
+
+impl StateMigrationCommands {
+    pub async fn run(self) -> anyhow::Result<()> {
+        match self {
+            Self::DryRun {
+                height: epoch,
+                expected_root,
+                chain,
+                data_dir,
+            } => {
+                let chain_config = Arc::new(ChainConfig::from_chain(&chain));
+                let height = chain_config
+                    .height_infos
+                    .iter()
+                    .find(|info| info.epoch == epoch)
+                    .map(|info| info.height)
+                    .with_context(|| {
+                        let upgrades = chain_config
+                            .height_infos
+                            .iter()
+                            .filter(|info| info.epoch > 0)
+                            .map(|info| format!("{} at {}", info.height, info.epoch))
+                            .collect::<Vec<_>>();
+                        format!(
+                            "no upgrade at epoch {epoch} on {chain}, upgrades are: {}",
+                            upgrades.join(", ")
+                        )
+                    })?;
+                let (db, head) = open_node_db(&chain, data_dir)?;
+                anyhow::ensure!(
+                    head.epoch() < epoch,
+                    "the head is at epoch {}, the {height} upgrade already happened",
+                    head.epoch()
+                );
+                anyhow::ensure!(
+                    expected_root.is_none() || head.epoch() == epoch - 1,
+                    "the head is at epoch {}, its state can only be compared with the expected root at epoch {}",
+                    head.epoch(),
+                    epoch - 1
+                );
+
+                // Blocks written by the migration, and the actor bundles it
+                // needs, are kept in memory and discarded.
+                let overlay = Arc::new(OverlayBlockstore::new(db));
+                load_actor_bundles(overlay.as_ref()).await?;
+                let bundle_blocks = overlay.len();
+
+                let parent_state = *head.parent_state();
+                println!(
+                    "Migrating the state {parent_state} of the head at epoch {} for the {height} upgrade at epoch {epoch}",
+                    head.epoch()
+                );
+                let mem_stats_tracker = Arc::new(MemStatsTracker::new(Duration::from_millis(100)));
+                let tracking = tokio::spawn({
+                    let mem_stats_tracker = mem_stats_tracker.clone();
+                    async move { mem_stats_tracker.run_loop().await }
+                });
+                let start = Instant::now();
+                let new_state = tokio::task::spawn_blocking({
+                    let overlay = overlay.clone();
+                    move || run_state_migrations(epoch, &chain_config, &overlay, &parent_state)
+                })
+                .await??
+                .with_context(|| format!("no state migration for the {height} upgrade"))?;
+                let elapsed = start.elapsed();
+                tracking.abort();
+
+                println!("Took: {}s", elapsed.as_secs_f32());
+                println!(
+                    "Peak physical memory usage: {}",
+                    mem_stats_tracker.peak_physical_mem().human_count_bytes()
+                );
+                println!("New blocks: {}", overlay.len() - bundle_blocks);
+                println!("New state root: {new_state}");
+                if let Some(expected_root) = expected_root {
+                    anyhow::ensure!(
+                        new_state == expected_root,
+                        "the new state root {new_state} doesn't match the expected {expected_root}"
+                    );
+                    println!("The new state root matches the expected one");
+                }
+                Ok(())
+            }
+        }
+    }
+}
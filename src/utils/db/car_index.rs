@@ -79,7 +79,8 @@
 //!
 //! [`CarIndexBuilder`] takes a collection of `(Cid, BlockPosition)` pairs and
 //! encodes them to a writer. The only guarantees about the format is that
-//! [`CarIndex`] can read it.
+//! [`CarIndex`] can read it. [`CarIndexBuilder::new_parallel`] builds the same
+//! table using multiple threads.
 //!
 //! ## Internal structures
 //!
@@ -1,6 +1,7 @@
 // Copyright 2019-2023 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 use super::{FrameOffset, Hash, IndexHeader, KeyValuePair, Slot};
+use rayon::prelude::*;
 use tokio::io::{AsyncWrite, AsyncWriteExt as _};
 
 #[derive(Debug)]
@@ -25,6 +26,7 @@ impl CarIndexBuilder {
     }
 
     // Construct a new index builder that maps `Cid` to `FrameOffset`.
+    #[cfg(test)]
     pub fn new(values: impl ExactSizeIterator<Item = (Hash, FrameOffset)>) -> CarIndexBuilder {
         let size = Self::capacity_at(values.len());
         let mut vec = Vec::with_capacity(size);
@@ -41,6 +43,72 @@ impl CarIndexBuilder {
         table
     }
 
+    // Construct the same table as `CarIndexBuilder::new` on all rayon threads.
+    // Only the order of values with identical hashes may differ.
+    pub fn new_parallel(values: Vec<(Hash, FrameOffset)>) -> CarIndexBuilder {
+        Self::new_segmented(values, rayon::current_num_threads())
+    }
+
+    // Robin Hood tables are history-independent: the layout only depends on
+    // the set of keys, not on the insertion order. Within a cluster, entries
+    // are sorted by bucket and then by hash. Hence, the table can be built by
+    // sorting the entries and placing them left-to-right, with each segment of
+    // buckets being filled in parallel. Entries that spill past the end of
+    // their segment are inserted regularly in a final, sequential merge.
+    pub(super) fn new_segmented(
+        values: Vec<(Hash, FrameOffset)>,
+        segments: usize,
+    ) -> CarIndexBuilder {
+        let mut values: Vec<KeyValuePair> = values
+            .into_par_iter()
+            .map(|(hash, value)| KeyValuePair { hash, value })
+            .collect();
+        // Buckets are monotonic in the hash, so this also sorts by bucket.
+        values.par_sort_unstable_by_key(|entry| (entry.hash, entry.value));
+
+        let len = Self::capacity_at(values.len()) as u64;
+        let segments = segments.max(1) as u64;
+        let segment_len = ((len + segments - 1) / segments).max(1);
+        let mut chunks = vec![];
+        let mut rest = values.as_slice();
+        let mut start = 0;
+        while start < len {
+            let end = (start + segment_len).min(len);
+            let (chunk, tail) =
+                rest.split_at(rest.partition_point(|entry| entry.bucket(len) < end));
+            chunks.push((start, end, chunk));
+            rest = tail;
+            start = end;
+        }
+
+        let segments: Vec<_> = chunks
+            .into_par_iter()
+            .map(|(start, end, chunk)| Segment::place(start, end, chunk, len))
+            .collect();
+
+        let mut table = CarIndexBuilder {
+            table: Vec::with_capacity(len as usize),
+            collisions: 0,
+            longest_distance: 0,
+            capacity: 0,
+        };
+        let mut spilled = vec![];
+        for segment in segments {
+            table.table.extend(segment.slots);
+            table.longest_distance = table.longest_distance.max(segment.longest_distance);
+            spilled.extend(segment.spilled);
+        }
+        table.capacity = spilled.len();
+        for entry in spilled {
+            table.insert(entry);
+        }
+        table.collisions = values
+            .windows(2)
+            .filter(|pair| pair[0].hash == pair[1].hash)
+            .count() as u64;
+        table
+    }
+
     #[cfg(feature = "benchmark-private")]
     pub fn hash_at_distance(&self, wanted_dist: u64) -> (Hash, u64) {
         let mut best_diff = u64::MAX;
@@ -142,3 +210,33 @@ impl CarIndexBuilder {
         self.table.len() as u64
     }
 }
+
+// Buckets `start..end` of a table, filled with sorted entries.
+struct Segment {
+    slots: Vec<Slot>,
+    longest_distance: u64,
+    // Entries that didn't fit before the end of the segment
+    spilled: Vec<KeyValuePair>,
+}
+
+impl Segment {
+    fn place(start: u64, end: u64, entries: &[KeyValuePair], len: u64) -> Segment {
+        let mut segment = Segment {
+            slots: vec![Slot::Empty; (end - start) as usize],
+            longest_distance: 0,
+            spilled: vec![],
+        };
+        let mut next = start;
+        for (nth, entry) in entries.iter().enumerate() {
+            let at = next.max(entry.bucket(len));
+            if at >= end {
+                segment.spilled.extend_from_slice(&entries[nth..]);
+                break;
+            }
+            segment.slots[(at - start) as usize] = Slot::Full(*entry);
+            segment.longest_distance = segment.longest_distance.max(entry.distance(at, len));
+            next = at + 1;
+        }
+        segment
+    }
+}
@@ -90,3 +90,47 @@ fn lookup_clash_many(mut entries: Vec<(Hash, FrameOffset)>) {
         assert_eq!(&AHashSet::from_iter(query(&table, hash)), &map[&hash]);
     }
 }
+
+fn encode(builder: &CarIndexBuilder) -> Vec<u8> {
+    let mut store = Vec::new();
+    builder.write(&mut store).unwrap();
+    store
+}
+
+// Building in parallel produces the same table as building sequentially. Hash
+// values are clustered to make entries spill across segment boundaries.
+#[quickcheck]
+fn parallel_matches_sequential(entries: Vec<(Hash, FrameOffset)>, segments: u8, clusters: u8) {
+    let unique: AHashMap<Hash, FrameOffset> = entries.into_iter().collect();
+    let table_len = CarIndexBuilder::capacity_at(unique.len()) as u64;
+    let clusters = u64::from(clusters).clamp(1, table_len.max(1));
+    let entries: Vec<_> = unique
+        .into_iter()
+        .map(|(hash, value)| {
+            let bucket = u64::from(hash) % clusters * (table_len / clusters);
+            (hash.set_bucket(bucket, table_len.max(1)), value)
+        })
+        .collect::<AHashMap<_, _>>()
+        .into_iter()
+        .collect();
+
+    let sequential = CarIndexBuilder::new(entries.iter().copied());
+    let parallel = CarIndexBuilder::new_segmented(entries, usize::from(segments));
+    assert_eq!(encode(&sequential), encode(&parallel));
+}
+
+#[quickcheck]
+fn parallel_lookup_narrow(mut entries: Vec<(Hash, FrameOffset)>, segments: u8) {
+    for (hash, _position) in entries.iter_mut() {
+        *hash = Hash::from(u64::from(*hash) % 10);
+    }
+    let map = mk_map(&entries);
+    let mut store = Vec::new();
+    CarIndexBuilder::new_segmented(entries, usize::from(segments))
+        .write(&mut store)
+        .unwrap();
+    let table = CarIndex::open(store, 0).unwrap();
+    for (&hash, value_set) in map.iter() {
+        assert_eq!(&AHashSet::from_iter(query(&table, hash)), value_set);
+    }
+}
@@ -4,6 +4,7 @@
 pub mod car_index;
 pub mod car_stream;
 pub mod file_backed_obj;
+pub mod overlay;
 
 use async_trait::async_trait;
 use chrono::Utc;
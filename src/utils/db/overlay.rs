@@ -0,0 +1,135 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! The [`OverlayBlockstore`] is a thin in-memory write layer on top of a
+//! read-only base store. Get requests are answered by the overlay first and
+//! then forwarded to the base store. Write requests never reach the base store
+//! unless the overlay is explicitly promoted.
+//!
+//! This is the building block for speculative execution: `StateCall`, gas
+//! estimation and migration dry-runs execute against an overlay and then
+//! either [`OverlayBlockstore::discard`] or [`OverlayBlockstore::promote`] the
+//! intermediate state.
+
+use ahash::HashMap;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use parking_lot::RwLock;
+
+pub struct OverlayBlockstore<BaseT> {
+    base: BaseT,
+    overlay: RwLock<HashMap<Cid, Vec<u8>>>,
+}
+
+impl<BaseT> OverlayBlockstore<BaseT> {
+    pub fn new(base: BaseT) -> Self {
+        OverlayBlockstore {
+            base,
+            overlay: RwLock::new(HashMap::default()),
+        }
+    }
+
+    /// Returns a reference to the read-only base store.
+    pub fn base(&self) -> &BaseT {
+        &self.base
+    }
+
+    /// Number of blocks currently buffered in the overlay.
+    pub fn len(&self) -> usize {
+        self.overlay.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.overlay.read().is_empty()
+    }
+
+    /// Drops all buffered writes. The base store is left untouched.
+    pub fn discard(&self) {
+        self.overlay.write().clear();
+    }
+
+    /// Discards the overlay and returns the base store.
+    pub fn into_inner(self) -> BaseT {
+        self.base
+    }
+}
+
+impl<BaseT: Blockstore> OverlayBlockstore<BaseT> {
+    /// Writes all buffered blocks to the base store and empties the overlay.
+    pub fn promote(&self) -> anyhow::Result<()> {
+        let blocks = std::mem::take(&mut *self.overlay.write());
+        self.base.put_many_keyed(blocks)
+    }
+}
+
+impl<BaseT: Blockstore> Blockstore for OverlayBlockstore<BaseT> {
+    fn get(&self, k: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
+        if let Some(block) = self.overlay.read().get(k) {
+            return Ok(Some(block.clone()));
+        }
+        self.base.get(k)
+    }
+
+    fn has(&self, k: &Cid) -> anyhow::Result<bool> {
+        if self.overlay.read().contains_key(k) {
+            return Ok(true);
+        }
+        self.base.has(k)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> anyhow::Result<()> {
+        self.overlay.write().insert(*k, block.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::MemoryDB;
+    use crate::utils::db::CborStoreExt;
+    use fvm_ipld_encoding::CborStore;
+
+    #[test]
+    fn writes_stay_in_overlay() {
+        let base = MemoryDB::default();
+        let base_cid = base.put_cbor_default(&"base").unwrap();
+
+        let overlay = OverlayBlockstore::new(&base);
+        let overlay_cid = overlay.put_cbor_default(&"overlay").unwrap();
+
+        // Both blocks are visible through the overlay
+        assert_eq!(
+            overlay.get_cbor::<String>(&base_cid).unwrap().as_deref(),
+            Some("base")
+        );
+        assert_eq!(
+            overlay.get_cbor::<String>(&overlay_cid).unwrap().as_deref(),
+            Some("overlay")
+        );
+        assert_eq!(overlay.len(), 1);
+
+        // The base store has not been modified
+        assert!(!base.has(&overlay_cid).unwrap());
+
+        overlay.discard();
+        assert!(overlay.is_empty());
+        assert!(!overlay.has(&overlay_cid).unwrap());
+        assert!(overlay.has(&base_cid).unwrap());
+    }
+
+    #[test]
+    fn promote_flushes_to_base() {
+        let base = MemoryDB::default();
+        let overlay = OverlayBlockstore::new(&base);
+        let cid = overlay.put_cbor_default(&"promoted").unwrap();
+        assert!(!base.has(&cid).unwrap());
+
+        overlay.promote().unwrap();
+        assert!(overlay.is_empty());
+        assert_eq!(
+            base.get_cbor::<String>(&cid).unwrap().as_deref(),
+            Some("promoted")
+        );
+    }
+}
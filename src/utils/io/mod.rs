@@ -4,6 +4,7 @@
 pub mod progress_bar;
 pub mod progress_log;
 pub mod random_access;
+pub mod scheduler;
 mod tempfile;
 mod writer_checksum;
 
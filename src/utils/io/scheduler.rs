@@ -0,0 +1,261 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Throttling of the disk I/O of background tasks.
+//!
+//! Maintenance jobs (garbage collection, chain exports, chain validation)
+//! compete for the disk with the validation of new tipsets. On modest disks
+//! they can slow validation down enough for the node to fall behind the chain.
+//! [`IO_SCHEDULER`] holds them to configured IOPS and bandwidth budgets, and
+//! scales the budgets down while the validation latency reported by the syncer
+//! is above a target.
+//!
+//! The scheduler is configured once by the daemon. Unconfigured, as in the
+//! tools, it never throttles.
+
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+
+lazy_static! {
+    pub static ref IO_SCHEDULER: IoScheduler = Default::default();
+}
+
+/// Lowest fraction of the budgets left to background tasks when the
+/// validation latency is far above its target, so that they still progress.
+const MIN_BUDGET_FACTOR: f64 = 0.05;
+
+/// Weight of the latest sample in the moving average of the validation
+/// latency.
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+/// Time after which the moving average of the validation latency has halved
+/// without new samples, so that the budgets recover once validation stops.
+const LATENCY_HALF_LIFE: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+#[cfg_attr(test, derive(derive_quickcheck_arbitrary::Arbitrary))]
+pub struct IoSchedulerConfig {
+    /// Read and write operations per second allowed to background tasks. `0`
+    /// means unlimited.
+    #[cfg_attr(test, arbitrary(gen(|g| u32::arbitrary(g) as _)))]
+    pub max_iops: u64,
+    /// Bytes per second allowed to background tasks. `0` means unlimited.
+    #[cfg_attr(test, arbitrary(gen(|g| u32::arbitrary(g) as _)))]
+    pub max_bytes_per_sec: u64,
+    /// Validation latency of a tipset above which the budgets are scaled down,
+    /// in proportion. `0` disables the scaling.
+    #[cfg_attr(test, arbitrary(gen(|g| u32::arbitrary(g) as _)))]
+    pub validation_latency_target_ms: u64,
+}
+
+impl Default for IoSchedulerConfig {
+    fn default() -> Self {
+        Self {
+            max_iops: 0,
+            max_bytes_per_sec: 0,
+            validation_latency_target_ms: 2000,
+        }
+    }
+}
+
+/// Token bucket holding up to one second of budget.
+#[derive(Debug)]
+struct Bucket {
+    available: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new() -> Self {
+        Self {
+            available: 0.,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Takes `cost` tokens, going into debt if needed, and returns how long the
+    /// caller has to wait for the debt to be paid back at `rate`.
+    fn take(&mut self, cost: f64, rate: f64, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.available = (self.available + elapsed * rate).min(rate) - cost;
+        self.updated = now;
+        match self.available < 0. {
+            true => Duration::from_secs_f64(-self.available / rate),
+            false => Duration::ZERO,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct IoScheduler {
+    config: RwLock<IoSchedulerConfig>,
+    ops: Mutex<Bucket>,
+    bytes: Mutex<Bucket>,
+    /// Moving average of the validation latency, in seconds, and the time of
+    /// its latest sample.
+    latency: Mutex<Option<(f64, Instant)>>,
+}
+
+impl Default for IoScheduler {
+    fn default() -> Self {
+        Self {
+            config: RwLock::new(IoSchedulerConfig {
+                validation_latency_target_ms: 0,
+                ..Default::default()
+            }),
+            ops: Mutex::new(Bucket::new()),
+            bytes: Mutex::new(Bucket::new()),
+            latency: Default::default(),
+        }
+    }
+}
+
+impl IoScheduler {
+    pub fn configure(&self, config: IoSchedulerConfig) {
+        *self.config.write() = config;
+    }
+
+    /// Records the time taken to validate a tipset.
+    pub fn record_validation_latency(&self, latency: Duration) {
+        self.record_validation_latency_at(latency, Instant::now());
+    }
+
+    fn record_validation_latency_at(&self, latency: Duration, now: Instant) {
+        let sample = latency.as_secs_f64();
+        let average = self.latency_at(now);
+        *self.latency.lock() = Some((
+            match average {
+                Some(average) => average + LATENCY_SMOOTHING * (sample - average),
+                None => sample,
+            },
+            now,
+        ));
+    }
+
+    /// Moving average of the validation latency at `now`, decayed since its
+    /// latest sample.
+    fn latency_at(&self, now: Instant) -> Option<f64> {
+        let (average, updated) = (*self.latency.lock())?;
+        let elapsed = now.saturating_duration_since(updated).as_secs_f64();
+        Some(average * 0.5f64.powf(elapsed / LATENCY_HALF_LIFE.as_secs_f64()))
+    }
+
+    /// Fraction of the budgets currently left to background tasks.
+    pub fn budget_factor(&self) -> f64 {
+        self.budget_factor_at(Instant::now())
+    }
+
+    fn budget_factor_at(&self, now: Instant) -> f64 {
+        let target = self.config.read().validation_latency_target_ms as f64 / 1000.;
+        match self.latency_at(now) {
+            Some(latency) if target > 0. && latency > target => {
+                (target / latency).max(MIN_BUDGET_FACTOR)
+            }
+            _ => 1.,
+        }
+    }
+
+    /// Whether background tasks have any budget to be held to.
+    fn is_limited(&self) -> bool {
+        let config = self.config.read();
+        config.max_iops > 0 || config.max_bytes_per_sec > 0
+    }
+
+    /// Returns how long a background operation of `bytes` has to be delayed.
+    fn delay(&self, bytes: usize) -> Duration {
+        let config = self.config.read().clone();
+        let now = Instant::now();
+        let factor = self.budget_factor_at(now);
+        let mut delay = Duration::ZERO;
+        if config.max_iops > 0 {
+            let rate = config.max_iops as f64 * factor;
+            delay = delay.max(self.ops.lock().take(1., rate, now));
+        }
+        if config.max_bytes_per_sec > 0 {
+            let rate = config.max_bytes_per_sec as f64 * factor;
+            delay = delay.max(self.bytes.lock().take(bytes as f64, rate, now));
+        }
+        delay
+    }
+
+    /// Waits until a background operation of `bytes` fits in the budgets.
+    pub async fn throttle(&self, bytes: usize) {
+        let delay = self.delay(bytes);
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Backs off after `busy` of background work whose I/O isn't metered
+    /// operation by operation, so that the work only takes the current
+    /// fraction of the disk time. Without budgets, the work never backs off.
+    pub fn back_off_blocking(&self, busy: Duration) {
+        if !self.is_limited() {
+            return;
+        }
+        let factor = self.budget_factor();
+        if factor < 1. {
+            std::thread::sleep(busy.mul_f64(1. / factor - 1.));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_delays_once_the_budget_is_spent() {
+        let mut bucket = Bucket::new();
+        // A second of budget is available after a second.
+        let now = bucket.updated + Duration::from_secs(1);
+        assert_eq!(bucket.take(60., 100., now), Duration::ZERO);
+        assert_eq!(bucket.take(40., 100., now), Duration::ZERO);
+        assert_eq!(bucket.take(50., 100., now), Duration::from_millis(500));
+        // Unused budget doesn't pile up.
+        let now = now + Duration::from_secs(10);
+        assert_eq!(bucket.take(100., 100., now), Duration::ZERO);
+        assert_eq!(bucket.take(100., 100., now), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn budgets_shrink_with_the_validation_latency() {
+        let scheduler = IoScheduler::default();
+        scheduler.configure(IoSchedulerConfig {
+            max_iops: 10,
+            max_bytes_per_sec: 0,
+            validation_latency_target_ms: 1000,
+        });
+        let now = Instant::now();
+        assert_eq!(scheduler.budget_factor_at(now), 1.);
+        scheduler.record_validation_latency_at(Duration::from_millis(500), now);
+        assert_eq!(scheduler.budget_factor_at(now), 1.);
+        scheduler.record_validation_latency_at(Duration::from_millis(20500), now);
+        // The average is 4.5s.
+        assert!((scheduler.budget_factor_at(now) - 1. / 4.5).abs() < 1e-9);
+        scheduler.record_validation_latency_at(Duration::from_secs(1000), now);
+        assert_eq!(scheduler.budget_factor_at(now), MIN_BUDGET_FACTOR);
+        // The average decays without new samples.
+        let later = now + LATENCY_HALF_LIFE * 20;
+        assert_eq!(scheduler.budget_factor_at(later), 1.);
+    }
+
+    #[test]
+    fn unlimited_work_never_backs_off() {
+        let scheduler = IoScheduler::default();
+        scheduler.configure(IoSchedulerConfig {
+            max_iops: 0,
+            max_bytes_per_sec: 0,
+            validation_latency_target_ms: 1000,
+        });
+        scheduler.record_validation_latency(Duration::from_secs(1000));
+        assert_eq!(scheduler.budget_factor(), MIN_BUDGET_FACTOR);
+        let started = Instant::now();
+        scheduler.back_off_blocking(Duration::from_secs(1));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -11,6 +11,7 @@ pub mod monitoring;
 pub mod net;
 pub mod proofs_api;
 pub mod stream;
+pub mod tls;
 pub mod version;
 
 use futures::{
@@ -33,7 +34,7 @@ where
     F: Future<Output = Result<T, E>>,
     E: std::fmt::Debug,
 {
-    let mut timeout: Pin<Box<dyn FusedFuture<Output = ()>>> = match args.timeout {
+    let mut timeout: Pin<Box<dyn FusedFuture<Output = ()> + Send>> = match args.timeout {
         Some(duration) => Box::pin(sleep(duration).fuse()),
         None => Box::pin(pending()),
     };
@@ -2,4 +2,6 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 mod mem_tracker;
+mod resource_governor;
 pub use mem_tracker::*;
+pub use resource_governor::*;
@@ -0,0 +1,431 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Degraded mode under CPU or memory pressure.
+//!
+//! The verification of proofs and signatures, and the validation of blocks,
+//! run as many tasks in parallel as there are CPUs. On a machine shared with
+//! other services, this starves them, or runs the node out of memory.
+//! [`RESOURCE_GOVERNOR`] checks the load of the CPUs and the memory in use
+//! periodically, and halves the parallelism of these tasks for every check
+//! above the configured thresholds, down to a single task. The parallelism is
+//! doubled back once the pressure has stayed below the thresholds, minus a
+//! margin, for a few checks, so that it doesn't flap.
+//!
+//! The governor is configured once by the daemon, and is disabled by default:
+//! enabled, it also bounds the parallelism by the number of CPUs unless larger
+//! maximums are configured. Unconfigured, as in the tools, it never limits the
+//! parallelism. The pressure is only known on Linux.
+
+use std::time::Duration;
+
+use crate::metrics;
+use lazy_static::lazy_static;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+lazy_static! {
+    pub static ref RESOURCE_GOVERNOR: ResourceGovernor = Default::default();
+}
+
+/// Margin below the thresholds under which the pressure has to fall for the
+/// parallelism to be restored, in percentage points.
+const HYSTERESIS_MARGIN: f64 = 10.;
+
+/// Number of consecutive checks under the thresholds, minus the margin,
+/// before the parallelism is doubled back.
+const RECOVERY_CHECKS: u32 = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+#[cfg_attr(test, derive(derive_quickcheck_arbitrary::Arbitrary))]
+pub struct ResourceGovernorConfig {
+    /// Whether the parallelism is reduced under pressure. Configured maximums
+    /// apply either way.
+    pub enabled: bool,
+    /// Load average over the last minute, per CPU, above which the
+    /// parallelism is reduced, in percent. `0` ignores the load.
+    pub cpu_load_percent: u32,
+    /// Share of the memory in use above which the parallelism is reduced, in
+    /// percent. `0` ignores the memory.
+    pub memory_percent: u8,
+    /// Interval between the checks of the pressure, in seconds.
+    #[cfg_attr(test, arbitrary(gen(|g| u32::arbitrary(g) as _)))]
+    pub check_interval_secs: u64,
+    /// Maximum number of proofs and signatures verified in parallel. `0`
+    /// means the number of CPUs if enabled, and no limit otherwise.
+    #[cfg_attr(test, arbitrary(gen(|g| u32::arbitrary(g) as _)))]
+    pub max_verification_parallelism: usize,
+    /// Maximum number of blocks validated in parallel. `0` means the number of
+    /// CPUs if enabled, and no limit otherwise.
+    #[cfg_attr(test, arbitrary(gen(|g| u32::arbitrary(g) as _)))]
+    pub max_validation_parallelism: usize,
+}
+
+impl Default for ResourceGovernorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cpu_load_percent: 200,
+            memory_percent: 90,
+            check_interval_secs: 5,
+            max_verification_parallelism: 0,
+            max_validation_parallelism: 0,
+        }
+    }
+}
+
+/// CPU load and memory use, in percent, if known.
+#[derive(Debug, Clone, Copy, Default)]
+struct Pressure {
+    cpu_load: Option<f64>,
+    memory: Option<f64>,
+}
+
+impl Pressure {
+    fn current() -> Self {
+        let cpu_load = std::fs::read_to_string("/proc/loadavg")
+            .ok()
+            .and_then(|loadavg| loadavg.split_whitespace().next()?.parse::<f64>().ok())
+            .map(|load| load / num_cpus::get() as f64 * 100.);
+        let memory = std::fs::read_to_string("/proc/meminfo")
+            .ok()
+            .and_then(|meminfo| {
+                let total = meminfo_bytes(&meminfo, "MemTotal")?;
+                let available = meminfo_bytes(&meminfo, "MemAvailable")?;
+                (total > 0).then(|| total.saturating_sub(available) as f64 / total as f64 * 100.)
+            });
+        Self { cpu_load, memory }
+    }
+}
+
+/// Returns a field of `/proc/meminfo`, in bytes.
+pub fn meminfo_bytes(meminfo: &str, field: &str) -> Option<u64> {
+    let kib = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+/// Number of tasks running, bounded by a limit that may change while tasks
+/// wait.
+#[derive(Debug, Default)]
+struct Gate {
+    running: Mutex<usize>,
+    released: Notify,
+}
+
+impl Gate {
+    async fn acquire(&self, limit: impl Fn() -> usize) -> Permit<'_> {
+        loop {
+            // Registered before checking, so that a release in between isn't
+            // missed.
+            let released = self.released.notified();
+            {
+                let mut running = self.running.lock();
+                if *running < limit() {
+                    *running += 1;
+                    return Permit(self);
+                }
+            }
+            released.await;
+        }
+    }
+}
+
+/// Slot of a task, released when dropped.
+#[derive(Debug)]
+pub struct Permit<'a>(&'a Gate);
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self.0.running.lock() -= 1;
+        self.0.released.notify_waiters();
+    }
+}
+
+impl ResourceGovernorConfig {
+    /// Returns the maximum parallelism `max` as configured, or `None` if
+    /// unlimited.
+    fn resolve(&self, max: usize) -> Option<usize> {
+        match max {
+            0 if self.enabled => Some(num_cpus::get()),
+            0 => None,
+            max => Some(max),
+        }
+    }
+
+    /// Highest degradation level, at which the largest maximum parallelism is
+    /// down to a single task.
+    fn max_level(&self) -> u32 {
+        [
+            self.max_verification_parallelism,
+            self.max_validation_parallelism,
+        ]
+        .into_iter()
+        .filter_map(|max| self.resolve(max))
+        .max()
+        .map_or(0, usize::ilog2)
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// The parallelism is divided by `2^level`.
+    level: u32,
+    /// Consecutive checks under the thresholds, minus the margin.
+    calm_checks: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct ResourceGovernor {
+    config: RwLock<Option<ResourceGovernorConfig>>,
+    state: Mutex<State>,
+    verification: Gate,
+    validation: Gate,
+}
+
+impl ResourceGovernor {
+    pub fn configure(&self, config: ResourceGovernorConfig) {
+        *self.config.write() = Some(config);
+        self.publish();
+    }
+
+    /// Returns `max` divided by `2^level`, or unlimited if unconfigured.
+    fn parallelism(&self, max: impl Fn(&ResourceGovernorConfig) -> usize) -> usize {
+        match &*self.config.read() {
+            Some(config) => match config.resolve(max(config)) {
+                Some(max) => (max >> self.state.lock().level).max(1),
+                None => usize::MAX,
+            },
+            None => usize::MAX,
+        }
+    }
+
+    /// Number of proofs and signatures currently allowed to be verified in
+    /// parallel.
+    pub fn verification_parallelism(&self) -> usize {
+        self.parallelism(|config| config.max_verification_parallelism)
+    }
+
+    /// Number of blocks currently allowed to be validated in parallel.
+    pub fn validation_parallelism(&self) -> usize {
+        self.parallelism(|config| config.max_validation_parallelism)
+    }
+
+    /// Waits for a slot to verify a proof or signature.
+    pub async fn verification_permit(&self) -> Permit<'_> {
+        self.verification
+            .acquire(|| self.verification_parallelism())
+            .await
+    }
+
+    /// Waits for a slot to validate a block.
+    pub async fn validation_permit(&self) -> Permit<'_> {
+        self.validation
+            .acquire(|| self.validation_parallelism())
+            .await
+    }
+
+    /// Degrades or restores the parallelism according to `pressure`.
+    fn update(&self, pressure: Pressure) {
+        let Some(config) = self.config.read().clone() else {
+            return;
+        };
+        let above = |value: Option<f64>, threshold: f64, margin: f64| {
+            threshold > 0. && value.map_or(false, |value| value > threshold - margin)
+        };
+        let thresholds = [
+            (pressure.cpu_load, f64::from(config.cpu_load_percent)),
+            (pressure.memory, f64::from(config.memory_percent)),
+        ];
+        let high = thresholds
+            .iter()
+            .any(|(value, threshold)| above(*value, *threshold, 0.));
+        let calm = !thresholds
+            .iter()
+            .any(|(value, threshold)| above(*value, *threshold, HYSTERESIS_MARGIN));
+
+        let mut state = self.state.lock();
+        if high {
+            state.calm_checks = 0;
+            if state.level < config.max_level() {
+                state.level += 1;
+                warn!(
+                    "Resource pressure (CPU load {}, memory {}), halving the verification parallelism (level {})",
+                    percent(pressure.cpu_load),
+                    percent(pressure.memory),
+                    state.level
+                );
+            }
+        } else if calm && state.level > 0 {
+            state.calm_checks += 1;
+            if state.calm_checks >= RECOVERY_CHECKS {
+                state.calm_checks = 0;
+                state.level -= 1;
+                info!(
+                    "Resource pressure eased, doubling the verification parallelism (level {})",
+                    state.level
+                );
+            }
+        } else {
+            state.calm_checks = 0;
+        }
+        drop(state);
+
+        for (kind, value) in [
+            (metrics::values::CPU_LOAD, pressure.cpu_load),
+            (metrics::values::MEMORY, pressure.memory),
+        ] {
+            if let Some(value) = value {
+                metrics::RESOURCE_PRESSURE
+                    .with_label_values(&[kind])
+                    .set(value);
+            }
+        }
+        self.publish();
+        // Waiting tasks may fit in a raised limit.
+        self.verification.released.notify_waiters();
+        self.validation.released.notify_waiters();
+    }
+
+    /// Updates the metrics of the parallelism.
+    fn publish(&self) {
+        metrics::RESOURCE_GOVERNOR_LEVEL.set(self.state.lock().level.into());
+        for (kind, parallelism) in [
+            (
+                metrics::values::VERIFICATION,
+                self.verification_parallelism(),
+            ),
+            (metrics::values::VALIDATION, self.validation_parallelism()),
+        ] {
+            metrics::RESOURCE_GOVERNOR_PARALLELISM
+                .with_label_values(&[kind])
+                .set(parallelism as u64);
+        }
+    }
+
+    /// Checks the pressure periodically, forever.
+    pub async fn run(&self) -> anyhow::Result<()> {
+        loop {
+            let interval = self
+                .config
+                .read()
+                .as_ref()
+                .map_or(0, |config| config.check_interval_secs);
+            tokio::time::sleep(Duration::from_secs(interval.max(1))).await;
+            self.update(Pressure::current());
+        }
+    }
+}
+
+fn percent(value: Option<f64>) -> String {
+    match value {
+        Some(value) => format!("{value:.0}%"),
+        None => "unknown".into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn governor() -> ResourceGovernor {
+        let governor = ResourceGovernor::default();
+        governor.configure(ResourceGovernorConfig {
+            enabled: true,
+            max_verification_parallelism: 8,
+            max_validation_parallelism: 3,
+            ..Default::default()
+        });
+        governor
+    }
+
+    fn memory(percent: f64) -> Pressure {
+        Pressure {
+            cpu_load: None,
+            memory: Some(percent),
+        }
+    }
+
+    #[test]
+    fn parallelism_degrades_with_hysteresis() {
+        assert_eq!(
+            ResourceGovernor::default().verification_parallelism(),
+            usize::MAX
+        );
+        let disabled = ResourceGovernor::default();
+        disabled.configure(Default::default());
+        assert_eq!(disabled.verification_parallelism(), usize::MAX);
+        let governor = governor();
+        assert_eq!(governor.verification_parallelism(), 8);
+
+        governor.update(memory(95.));
+        governor.update(memory(95.));
+        assert_eq!(governor.verification_parallelism(), 2);
+        assert_eq!(governor.validation_parallelism(), 1);
+        governor.update(memory(95.));
+        assert_eq!(governor.verification_parallelism(), 1);
+        // The level stops rising once the parallelism is down to 1.
+        for _ in 0..10 {
+            governor.update(memory(95.));
+        }
+        assert_eq!(governor.state.lock().level, 3);
+
+        // Within the margin, the parallelism is kept.
+        for _ in 0..RECOVERY_CHECKS {
+            governor.update(memory(85.));
+        }
+        assert_eq!(governor.verification_parallelism(), 1);
+        for _ in 0..RECOVERY_CHECKS - 1 {
+            governor.update(memory(50.));
+        }
+        // A spike restarts the recovery.
+        governor.update(Pressure {
+            cpu_load: Some(300.),
+            memory: Some(50.),
+        });
+        assert_eq!(governor.state.lock().level, 3);
+        for _ in 0..2 * RECOVERY_CHECKS {
+            governor.update(memory(50.));
+        }
+        assert_eq!(governor.state.lock().level, 1);
+        assert_eq!(governor.verification_parallelism(), 4);
+        for _ in 0..RECOVERY_CHECKS {
+            governor.update(Pressure::default());
+        }
+        assert_eq!(governor.verification_parallelism(), 8);
+    }
+
+    #[tokio::test]
+    async fn permits_are_bounded_by_the_parallelism() {
+        let governor: &'static ResourceGovernor = Box::leak(Box::new(governor()));
+        let permits = futures::future::join_all((0..3).map(|_| governor.validation_permit())).await;
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), governor.validation_permit())
+                .await
+                .is_err()
+        );
+
+        // Waiting tasks resume once the running ones fit in the lowered limit.
+        governor.update(memory(95.));
+        let waiting = tokio::spawn(governor.validation_permit());
+        drop(permits);
+        waiting.await.unwrap();
+    }
+
+    #[test]
+    fn parse_meminfo() {
+        let meminfo = "MemTotal:       16000000 kB\nMemAvailable:    8000000 kB\n";
+        assert_eq!(meminfo_bytes(meminfo, "MemTotal"), Some(16_384_000_000));
+        assert_eq!(meminfo_bytes(meminfo, "MemAvailable"), Some(8_192_000_000));
+        assert_eq!(meminfo_bytes(meminfo, "MemFree"), None);
+    }
+}
@@ -7,23 +7,40 @@ use std::{
     path::{Path, PathBuf},
     str::FromStr,
     sync::Arc,
+    time::Duration,
 };
 
-use crate::{shim::sector::SectorSize, utils::net::download_ipfs_file_trustlessly};
+use crate::{shim::sector::SectorSize, utils::net::global_http_client};
 use ahash::HashMap;
+use anyhow::Context as _;
 use backoff::{future::retry, ExponentialBackoff};
 use blake2b_simd::{Hash, State as Blake2b};
 use cid::Cid;
+use fs2::FileExt as _;
+use futures::TryStreamExt as _;
+use reqwest::{header::RANGE, StatusCode};
 use serde::{Deserialize, Serialize};
-use tokio::fs::{self};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::AsyncWriteExt as _,
+    sync::OnceCell,
+};
 use tracing::{debug, error, info, warn};
+use url::Url;
 
-const GATEWAY: &str = "https://proofs.filecoin.io/ipfs/";
+/// Gateways the parameter files are downloaded from, in order of preference.
+/// The files are checked against the digests of the manifest, so any gateway
+/// can serve them.
+const GATEWAYS: [&str; 2] = ["https://proofs.filecoin.io/ipfs/", "https://ipfs.io/ipfs/"];
 const PARAM_DIR: &str = "filecoin-proof-parameters";
 const DIR_ENV: &str = "FIL_PROOFS_PARAMETER_CACHE";
+/// Comma-separated list of gateways that replaces [`GATEWAYS`].
 const GATEWAY_ENV: &str = "IPFS_GATEWAY";
 const TRUST_PARAMS_ENV: &str = "TRUST_PARAMS";
 const DEFAULT_PARAMETERS: &str = include_str!("./parameters.json");
+/// Maximum time spent retrying the download from a single gateway before
+/// moving on to the next one.
+const GATEWAY_RETRY_TIME: Duration = Duration::from_secs(5 * 60);
 
 /// Sector size options for fetching.
 pub enum SectorSizeOpt {
@@ -53,6 +70,18 @@ fn param_dir(data_dir: &Path) -> PathBuf {
         .unwrap_or_else(|_| data_dir.join(PARAM_DIR))
 }
 
+fn gateways() -> Vec<String> {
+    match std::env::var(GATEWAY_ENV) {
+        Ok(gateways) => gateways
+            .split(',')
+            .map(str::trim)
+            .filter(|gateway| !gateway.is_empty())
+            .map(String::from)
+            .collect(),
+        Err(_) => GATEWAYS.map(String::from).to_vec(),
+    }
+}
+
 /// Forest uses a set of external crates for verifying the proofs generated by
 /// the miners. These external crates require a specific set of parameter files
 /// to be located at in a specific folder. By default, it is
@@ -61,24 +90,51 @@ fn param_dir(data_dir: &Path) -> PathBuf {
 /// download the parameter files from IPFS and verify their validity. For
 /// consistency, Forest will prefer to download the files it's local data
 /// directory. To this end, the `FIL_PROOFS_PARAMETER_CACHE` environment
-/// variable is updated before the parameters are downloaded.
+/// variable is updated before the parameters are downloaded. Setting
+/// `cache_dir` instead makes several nodes share the same parameter files.
 ///
 /// More information available here: <https://github.com/filecoin-project/rust-fil-proofs#parameter-file-location>
-pub fn set_proofs_parameter_cache_dir_env(data_dir: &Path) {
-    std::env::set_var(DIR_ENV, param_dir(data_dir));
+pub fn set_proofs_parameter_cache_dir_env(data_dir: &Path, cache_dir: Option<&Path>) {
+    let dir = match (std::env::var_os(DIR_ENV), cache_dir) {
+        (None, Some(cache_dir)) => cache_dir.to_owned(),
+        _ => param_dir(data_dir),
+    };
+    std::env::set_var(DIR_ENV, dir);
 }
 
-/// Ensures the parameter files are downloaded to cache dir
+/// Ensures the parameter files are downloaded to cache dir. The files are only
+/// checked once per process, so this is cheap enough to be called every time
+/// the proofs are about to be verified.
 pub async fn ensure_params_downloaded() -> anyhow::Result<()> {
-    let data_dir = std::env::var(DIR_ENV).unwrap_or_default();
-    if data_dir.is_empty() {
-        anyhow::bail!("Proof parameter data dir is not set");
-    }
-    get_params_default(Path::new(&data_dir), SectorSizeOpt::Keys, false).await?;
-
+    static DOWNLOADED: OnceCell<()> = OnceCell::const_new();
+    DOWNLOADED
+        .get_or_try_init(|| async {
+            let data_dir = std::env::var(DIR_ENV).unwrap_or_default();
+            if data_dir.is_empty() {
+                anyhow::bail!("Proof parameter data dir is not set");
+            }
+            get_params_default(Path::new(&data_dir), SectorSizeOpt::Keys, false)
+                .await
+                .context("proof parameters are unavailable")
+        })
+        .await?;
     Ok(())
 }
 
+/// Returns the names of the verification keys that are missing from the
+/// parameter directory, see [`set_proofs_parameter_cache_dir_env`]. The files
+/// are not checked against their digests.
+pub fn missing_verification_keys(data_dir: &Path) -> anyhow::Result<Vec<String>> {
+    let dir = param_dir(data_dir);
+    let params: ParameterMap = serde_json::from_str(DEFAULT_PARAMETERS)?;
+    let mut missing: Vec<_> = params
+        .into_keys()
+        .filter(|name| !name.ends_with("params") && !dir.join(name).is_file())
+        .collect();
+    missing.sort();
+    Ok(missing)
+}
+
 /// Get proofs parameters and all verification keys for a given sector size
 /// given a parameter JSON manifest.
 pub async fn get_params(
@@ -158,6 +214,12 @@ async fn fetch_verify_params(
 ) -> Result<(), anyhow::Error> {
     let path: PathBuf = param_dir(data_dir).join(name);
 
+    if check_file(&path, &info).await.is_ok() {
+        return Ok(());
+    }
+    // Nodes sharing the parameter directory, or the tools, may be fetching the
+    // same file, and they would corrupt its partial download.
+    let _lock = lock(&path).await?;
     match check_file(&path, &info).await {
         Ok(()) => return Ok(()),
         Err(e) => {
@@ -169,22 +231,104 @@ async fn fetch_verify_params(
 
     fetch_params(&path, &info).await?;
 
-    check_file(&path, &info).await.map_err(|e| {
-        // TODO remove invalid file
-        e.into()
+    if let Err(e) = check_file(&path, &info).await {
+        // Corrupted files would otherwise be checked again by the next run
+        // and never replaced.
+        fs::remove_file(&path).await?;
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Locks the download of the parameter file at `path` until the returned file
+/// is dropped, waiting for other processes to release it.
+async fn lock(path: &Path) -> io::Result<SyncFile> {
+    let mut lock = path.as_os_str().to_owned();
+    lock.push(".lock");
+    let file = SyncFile::create(lock)?;
+    tokio::task::spawn_blocking(move || {
+        file.lock_exclusive()?;
+        Ok(file)
     })
+    .await?
 }
 
+/// Downloads a parameter file from the first gateway that serves it. The
+/// download is resumed from the partial file left by the previous attempt, if
+/// any.
 async fn fetch_params(path: &Path, info: &ParameterData) -> anyhow::Result<()> {
     let cid = Cid::from_str(&info.cid)?;
-    let gw = std::env::var(GATEWAY_ENV).unwrap_or_else(|_| GATEWAY.to_owned());
-    info!("Fetching param file {:?} from {}", path, gw);
-    let result = retry(ExponentialBackoff::default(), || async {
-        Ok(download_ipfs_file_trustlessly(&cid, Some(GATEWAY), path).await?)
-    })
-    .await;
-    debug!("Done fetching param file {:?} from {}", path, gw);
-    result
+    let mut last_error = anyhow::anyhow!("no gateway is configured in {GATEWAY_ENV}");
+    for gw in gateways() {
+        info!("Fetching param file {:?} from {}", path, gw);
+        let url = Url::parse(&gw)?.join(&cid.to_string())?;
+        let backoff = ExponentialBackoff {
+            max_elapsed_time: Some(GATEWAY_RETRY_TIME),
+            ..Default::default()
+        };
+        match retry(backoff, || async {
+            Ok(download_resumable(&url, path).await?)
+        })
+        .await
+        {
+            Ok(()) => {
+                debug!("Done fetching param file {:?} from {}", path, gw);
+                return Ok(());
+            }
+            Err(e) => {
+                warn!("Failed to fetch param file {:?} from {}: {}", path, gw, e);
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Downloads `url` to `path`. The data is written to a `.part` file first,
+/// which is appended to with a range request if it already exists. The caller
+/// holds the lock of `path`, see [`lock`], and checks the downloaded file.
+async fn download_resumable(url: &Url, path: &Path) -> anyhow::Result<()> {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    let part = PathBuf::from(part);
+
+    let offset = match fs::metadata(&part).await {
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    };
+    let mut request = global_http_client().get(url.clone());
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={offset}-"));
+    }
+    let response = request.send().await?;
+    // The partial file is complete, the download was interrupted before it was
+    // renamed.
+    if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        debug!("Download of {:?} is already complete", path);
+        fs::rename(&part, path).await?;
+        return Ok(());
+    }
+    let response = response.error_for_status()?;
+    // Gateways that ignore the range send the whole file again.
+    let resumed = response.status() == StatusCode::PARTIAL_CONTENT;
+    if resumed {
+        debug!("Resuming download of {:?} at byte {}", path, offset);
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(&part)
+        .await?;
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.try_next().await? {
+        file.write_all(&chunk).await?;
+    }
+    file.sync_all().await?;
+    fs::rename(&part, path).await?;
+    Ok(())
 }
 
 async fn check_file(path: &Path, info: &ParameterData) -> Result<(), io::Error> {
@@ -0,0 +1,44 @@
+#!/bin/bash
+# Generates the certificates of the tests of `src/utils/tls.rs`:
+# - `ca` and `other_ca`, two certificate authorities,
+# - `server`, a certificate of `localhost` signed by `ca`,
+# - `renewed`, a certificate of `localhost` signed by `other_ca`,
+# - `client`, a client certificate signed by `ca`,
+# - `rogue`, a client certificate signed by `other_ca`.
+# The keys of the authorities are discarded.
+
+set -euo pipefail
+
+cd "$(dirname "${BASH_SOURCE[0]}")"
+DAYS=36500
+TMP=$(mktemp -d)
+trap 'rm -rf "$TMP"' EXIT
+
+function key {
+  openssl genpkey -algorithm EC -pkeyopt ec_paramgen_curve:P-256 -out "$1"
+}
+
+function authority {
+  key "$TMP/$1.key"
+  openssl req -x509 -new -key "$TMP/$1.key" -subj "/CN=Forest test $1" -days "$DAYS" \
+    -addext "basicConstraints=critical,CA:TRUE" \
+    -addext "keyUsage=critical,keyCertSign" -out "$1.pem"
+}
+
+function leaf {
+  local name=$1 issuer=$2 usage=$3
+  key "$name.key"
+  openssl req -new -key "$name.key" -subj "/CN=localhost" -out "$TMP/$name.csr"
+  printf "basicConstraints=CA:FALSE\nsubjectAltName=DNS:localhost\nextendedKeyUsage=%s\n" \
+    "$usage" > "$TMP/$name.ext"
+  openssl x509 -req -in "$TMP/$name.csr" -CA "$issuer.pem" -CAkey "$TMP/$issuer.key" \
+    -CAcreateserial -CAserial "$TMP/$issuer.srl" -days "$DAYS" -extfile "$TMP/$name.ext" \
+    -out "$name.pem"
+}
+
+authority ca
+authority other_ca
+leaf server ca serverAuth
+leaf renewed other_ca serverAuth
+leaf client ca clientAuth
+leaf rogue other_ca clientAuth
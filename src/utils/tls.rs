@@ -0,0 +1,471 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! TLS termination of the HTTP servers of the node, i.e. the JSON-RPC and the
+//! metrics servers.
+//!
+//! TLS is enabled by a certificate chain and its private key, and client
+//! certificates are required if the certificates of their authorities are
+//! configured (mutual TLS). The files are read again on `SIGHUP`, so that
+//! certificates can be renewed without restarting the node. Connections that
+//! are already established keep the certificate they were accepted with.
+//!
+//! Clients of such servers, like the CLI, are configured with
+//! [`client_config`].
+
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use anyhow::Context as _;
+use hyper::server::accept::Accept;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+use tokio_rustls::{
+    rustls::{
+        server::AllowAnyAuthenticatedClient, Certificate, ClientConfig, OwnedTrustAnchor,
+        PrivateKey, RootCertStore, ServerConfig,
+    },
+    server::TlsStream,
+    TlsAcceptor,
+};
+use tracing::{debug, info, warn};
+
+/// Time allowed to clients to complete the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Pause after failing to accept a connection for lack of resources, e.g.
+/// when the process runs out of file descriptors, as in `hyper`.
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+#[cfg_attr(test, derive(derive_quickcheck_arbitrary::Arbitrary))]
+pub struct TlsConfig {
+    /// PEM file of the certificate chain of the servers. TLS is enabled when
+    /// both the certificate and the private key are set.
+    pub certificate: Option<PathBuf>,
+    /// PEM file of the private key of the certificate.
+    pub private_key: Option<PathBuf>,
+    /// PEM file of the certificates of the authorities of the clients. If
+    /// set, clients must present a certificate signed by one of them.
+    pub client_ca: Option<PathBuf>,
+    /// Whether the JSON-RPC server uses TLS.
+    pub rpc: bool,
+    /// Whether the metrics server uses TLS.
+    pub metrics: bool,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            certificate: None,
+            private_key: None,
+            client_ca: None,
+            rpc: true,
+            metrics: true,
+        }
+    }
+}
+
+/// TLS configuration of the servers, reloaded from the configured files on
+/// demand.
+pub struct TlsServer {
+    certificate: PathBuf,
+    private_key: PathBuf,
+    client_ca: Option<PathBuf>,
+    acceptor: RwLock<TlsAcceptor>,
+}
+
+impl TlsServer {
+    /// Returns `None` if TLS isn't enabled by `config`.
+    pub fn new(config: &TlsConfig) -> anyhow::Result<Option<Arc<Self>>> {
+        let (Some(certificate), Some(private_key)) = (&config.certificate, &config.private_key)
+        else {
+            anyhow::ensure!(
+                config.certificate.is_none() && config.private_key.is_none(),
+                "TLS requires both a certificate and a private key"
+            );
+            return Ok(None);
+        };
+        let acceptor = load_acceptor(certificate, private_key, config.client_ca.as_deref())?;
+        Ok(Some(Arc::new(Self {
+            certificate: certificate.clone(),
+            private_key: private_key.clone(),
+            client_ca: config.client_ca.clone(),
+            acceptor: RwLock::new(acceptor),
+        })))
+    }
+
+    /// Reads the certificates and the key again. The previous ones are kept if
+    /// they can't be read.
+    pub fn reload(&self) -> anyhow::Result<()> {
+        let acceptor = load_acceptor(
+            &self.certificate,
+            &self.private_key,
+            self.client_ca.as_deref(),
+        )?;
+        *self.acceptor.write() = acceptor;
+        info!(
+            "Reloaded the TLS certificate {}",
+            self.certificate.display()
+        );
+        Ok(())
+    }
+
+    /// Reloads the certificates whenever the node receives `SIGHUP`.
+    pub async fn reload_on_hangup(self: Arc<Self>) -> anyhow::Result<()> {
+        let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+        while hangup.recv().await.is_some() {
+            if let Err(e) = self.reload() {
+                warn!("Failed to reload the TLS certificate: {e:#}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Accepts the TLS connections to `listener`.
+    pub fn incoming(self: &Arc<Self>, listener: std::net::TcpListener) -> io::Result<TlsIncoming> {
+        listener.set_nonblocking(true)?;
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+        let (tx, rx) = tokio::sync::mpsc::channel(64);
+        let this = Arc::clone(self);
+        tokio::spawn(async move {
+            while !tx.is_closed() {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) if is_connection_error(&e) => {
+                        debug!("Failed to accept a connection: {e}");
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!("Failed to accept a connection: {e}");
+                        tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                        continue;
+                    }
+                };
+                let acceptor = this.acceptor.read().clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = tx.send(stream).await;
+                        }
+                        Ok(Err(e)) => debug!("TLS handshake with {peer} failed: {e}"),
+                        Err(_) => debug!("TLS handshake with {peer} timed out"),
+                    }
+                });
+            }
+        });
+        Ok(TlsIncoming { rx })
+    }
+}
+
+/// TLS connections accepted by [`TlsServer::incoming`], to be served with
+/// [`axum::Server::builder`].
+pub struct TlsIncoming {
+    rx: tokio::sync::mpsc::Receiver<TlsStream<TcpStream>>,
+}
+
+impl Accept for TlsIncoming {
+    type Conn = TlsStream<TcpStream>;
+    type Error = io::Error;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        self.get_mut().rx.poll_recv(cx).map(|conn| conn.map(Ok))
+    }
+}
+
+/// Serves `app` on `listener`, over TLS if `tls` is set.
+pub async fn serve(
+    listener: std::net::TcpListener,
+    tls: Option<&Arc<TlsServer>>,
+    app: axum::Router,
+) -> anyhow::Result<()> {
+    let service = app.into_make_service();
+    match tls {
+        Some(tls) => {
+            axum::Server::builder(tls.incoming(listener)?)
+                .serve(service)
+                .await?
+        }
+        None => axum::Server::from_tcp(listener)?.serve(service).await?,
+    }
+    Ok(())
+}
+
+/// Errors of a single connection, after which the next one can be accepted
+/// straight away.
+fn is_connection_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+    )
+}
+
+/// Returns the TLS configuration of a client that trusts the authorities in
+/// the PEM file `ca`, or the public ones if unset, and presents the
+/// certificate chain and private key of `identity` to servers that require
+/// client certificates.
+pub fn client_config(
+    ca: Option<&Path>,
+    identity: Option<(&Path, &Path)>,
+) -> anyhow::Result<ClientConfig> {
+    let roots = match ca {
+        Some(ca) => read_roots(ca)?,
+        None => {
+            let mut roots = RootCertStore::empty();
+            roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+                OwnedTrustAnchor::from_subject_spki_name_constraints(
+                    ta.subject,
+                    ta.spki,
+                    ta.name_constraints,
+                )
+            }));
+            roots
+        }
+    };
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots);
+    Ok(match identity {
+        Some((certificate, private_key)) => builder.with_client_auth_cert(
+            read_certificates(certificate)?,
+            read_private_key(private_key)?,
+        )?,
+        None => builder.with_no_client_auth(),
+    })
+}
+
+fn load_acceptor(
+    certificate: &Path,
+    private_key: &Path,
+    client_ca: Option<&Path>,
+) -> anyhow::Result<TlsAcceptor> {
+    let certificates = read_certificates(certificate)?;
+    let private_key = read_private_key(private_key)?;
+
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match client_ca {
+        Some(client_ca) => builder.with_client_cert_verifier(
+            AllowAnyAuthenticatedClient::new(read_roots(client_ca)?).boxed(),
+        ),
+        None => builder.with_no_client_auth(),
+    };
+    let config = builder.with_single_cert(certificates, private_key)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+fn read_certificates(path: &Path) -> anyhow::Result<Vec<Certificate>> {
+    let certificates = read_pem(path, rustls_pemfile::certs)?
+        .into_iter()
+        .map(Certificate)
+        .collect::<Vec<_>>();
+    anyhow::ensure!(
+        !certificates.is_empty(),
+        "no certificate in {}",
+        path.display()
+    );
+    Ok(certificates)
+}
+
+fn read_private_key(path: &Path) -> anyhow::Result<PrivateKey> {
+    read_pem(path, |reader| {
+        Ok(rustls_pemfile::read_all(reader)?
+            .into_iter()
+            .find_map(|item| match item {
+                rustls_pemfile::Item::PKCS8Key(key)
+                | rustls_pemfile::Item::RSAKey(key)
+                | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+                _ => None,
+            }))
+    })?
+    .with_context(|| format!("no private key in {}", path.display()))
+}
+
+/// Reads the certificates of the authorities in `path`.
+fn read_roots(path: &Path) -> anyhow::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    let (valid, _) = roots.add_parsable_certificates(&read_pem(path, rustls_pemfile::certs)?);
+    anyhow::ensure!(valid > 0, "no valid certificate in {}", path.display());
+    Ok(roots)
+}
+
+fn read_pem<T>(
+    path: &Path,
+    read: impl FnOnce(&mut dyn io::BufRead) -> io::Result<T>,
+) -> anyhow::Result<T> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    read(&mut BufReader::new(file)).with_context(|| format!("invalid PEM file {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::TlsConnector;
+
+    /// See `generate.sh` in this directory.
+    const CERTS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/utils/tls-test-certs");
+
+    fn cert(file: &str) -> PathBuf {
+        Path::new(CERTS).join(file)
+    }
+
+    fn config(certificate: PathBuf, private_key: PathBuf, client_ca: Option<&str>) -> TlsConfig {
+        TlsConfig {
+            certificate: Some(certificate),
+            private_key: Some(private_key),
+            client_ca: client_ca.map(cert),
+            ..Default::default()
+        }
+    }
+
+    fn listen(tls: &Arc<TlsServer>) -> (std::net::SocketAddr, TlsIncoming) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        (addr, tls.incoming(listener).unwrap())
+    }
+
+    /// Connects to the server trusting `ca`, with the client certificate
+    /// `client` if set, and exchanges a few bytes.
+    async fn exchange(
+        addr: std::net::SocketAddr,
+        incoming: &mut TlsIncoming,
+        ca: &str,
+        client: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let identity = client.map(|client| {
+            (
+                cert(&format!("{client}.pem")),
+                cert(&format!("{client}.key")),
+            )
+        });
+        let config = client_config(
+            Some(&cert(ca)),
+            identity
+                .as_ref()
+                .map(|(certificate, key)| (certificate.as_path(), key.as_path())),
+        )?;
+        let client = async {
+            let stream = TcpStream::connect(addr).await?;
+            let mut stream = TlsConnector::from(Arc::new(config))
+                .connect("localhost".try_into()?, stream)
+                .await?;
+            stream.write_all(b"ping").await?;
+            let mut pong = [0; 4];
+            stream.read_exact(&mut pong).await?;
+            anyhow::ensure!(&pong == b"pong");
+            Ok(())
+        };
+        let server = async {
+            let mut stream = std::future::poll_fn(|cx| Pin::new(&mut *incoming).poll_accept(cx))
+                .await
+                .context("the server is closed")??;
+            let mut ping = [0; 4];
+            stream.read_exact(&mut ping).await?;
+            stream.write_all(b"pong").await?;
+            stream.flush().await?;
+            anyhow::Ok(())
+        };
+        tokio::time::timeout(Duration::from_secs(10), async {
+            tokio::try_join!(client, server)
+        })
+        .await
+        .context("timed out")??;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tls_handshake() {
+        let tls = TlsServer::new(&config(cert("server.pem"), cert("server.key"), None))
+            .unwrap()
+            .unwrap();
+        let (addr, mut incoming) = listen(&tls);
+        exchange(addr, &mut incoming, "ca.pem", None).await.unwrap();
+        // Clients that don't trust the authority of the server can't connect.
+        assert!(exchange(addr, &mut incoming, "other_ca.pem", None)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn mutual_tls_rejects_unknown_clients() {
+        let config = config(cert("server.pem"), cert("server.key"), Some("ca.pem"));
+        let tls = TlsServer::new(&config).unwrap().unwrap();
+        let (addr, mut incoming) = listen(&tls);
+        exchange(addr, &mut incoming, "ca.pem", Some("client"))
+            .await
+            .unwrap();
+        assert!(exchange(addr, &mut incoming, "ca.pem", None).await.is_err());
+        assert!(exchange(addr, &mut incoming, "ca.pem", Some("rogue"))
+            .await
+            .is_err());
+        // Rejected clients don't stop the server.
+        exchange(addr, &mut incoming, "ca.pem", Some("client"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn certificates_are_reloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let (certificate, private_key) = (dir.path().join("tls.pem"), dir.path().join("tls.key"));
+        let install = |name: &str| {
+            std::fs::copy(cert(&format!("{name}.pem")), &certificate).unwrap();
+            std::fs::copy(cert(&format!("{name}.key")), &private_key).unwrap();
+        };
+        install("server");
+        let tls = TlsServer::new(&config(certificate.clone(), private_key.clone(), None))
+            .unwrap()
+            .unwrap();
+        let (addr, mut incoming) = listen(&tls);
+        exchange(addr, &mut incoming, "ca.pem", None).await.unwrap();
+
+        install("renewed");
+        tls.reload().unwrap();
+        exchange(addr, &mut incoming, "other_ca.pem", None)
+            .await
+            .unwrap();
+        assert!(exchange(addr, &mut incoming, "ca.pem", None).await.is_err());
+
+        // Invalid files don't replace the current certificate.
+        std::fs::write(&certificate, "").unwrap();
+        assert!(tls.reload().is_err());
+        exchange(addr, &mut incoming, "other_ca.pem", None)
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn tls_needs_a_certificate_and_a_key() {
+        assert!(TlsServer::new(&TlsConfig::default()).unwrap().is_none());
+        let only_certificate = TlsConfig {
+            certificate: Some("cert.pem".into()),
+            ..Default::default()
+        };
+        assert!(TlsServer::new(&only_certificate).is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let empty = dir.path().join("empty.pem");
+        std::fs::write(&empty, "").unwrap();
+        let empty_files = TlsConfig {
+            certificate: Some(empty.clone()),
+            private_key: Some(empty),
+            ..Default::default()
+        };
+        let e = TlsServer::new(&empty_files).err().unwrap();
+        assert!(e.to_string().starts_with("no certificate"), "{e}");
+    }
+}
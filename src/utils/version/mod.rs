@@ -8,6 +8,12 @@ use once_cell::sync::Lazy;
 pub const GIT_HASH: &str =
     git_version!(args = ["--always", "--exclude", "*"], fallback = "unknown");
 
+/// Versions of the FVM linked into Forest, e.g. `2.5.0,3.5.0`.
+pub const FVM_VERSIONS: &str = env!("FOREST_FVM_VERSIONS");
+
+/// Features of the build, e.g. `jemalloc,graphsync-server`.
+pub const FEATURES: &str = env!("FOREST_FEATURES");
+
 /// Current version of the Forest repository with git hash embedded
 /// E.g., `0.8.0+git.e69baf3e4`
 pub static FOREST_VERSION_STRING: Lazy<String> =
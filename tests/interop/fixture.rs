@@ -0,0 +1,80 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! A fixture is a directory containing:
+//! - `genesis.car`, the genesis of the devnet,
+//! - `chain.car`, a snapshot of the devnet exported by Lotus,
+//! - `scenario.json`, the messages to gossip and the RPC calls to compare with
+//!   Lotus, see [`Scenario`].
+//!
+//! Fixtures are generated from the local devnet in `scripts/devnet` by
+//! `scripts/devnet/interop_fixture.py`.
+
+use std::path::PathBuf;
+
+use anyhow::Context as _;
+use serde::Deserialize;
+use serde_json::Value;
+
+pub const FIXTURE_ENV: &str = "FOREST_INTEROP_FIXTURE";
+
+pub struct Fixture {
+    pub dir: PathBuf,
+    pub scenario: Scenario,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Scenario {
+    /// Name of the devnet, as in the `chain.network` configuration.
+    pub network: String,
+    /// Epoch of the head of `chain.car`.
+    pub head_epoch: i64,
+    /// Messages to gossip, valid on top of the head.
+    #[serde(default)]
+    pub messages: Vec<GossipedMessage>,
+    /// RPC calls, and their results on Lotus at the head.
+    #[serde(default)]
+    pub rpc: Vec<RpcCall>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct GossipedMessage {
+    /// CID of the signed message, in Lotus JSON.
+    pub cid: Value,
+    /// The signed message, CBOR encoded and in base64.
+    pub bytes: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RpcCall {
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+    pub result: Value,
+}
+
+impl Fixture {
+    /// Loads the fixture pointed to by [`FIXTURE_ENV`], which must be set.
+    pub fn load() -> anyhow::Result<Self> {
+        let dir = std::env::var_os(FIXTURE_ENV)
+            .map(PathBuf::from)
+            .with_context(|| format!("{FIXTURE_ENV} must point to an interop fixture"))?;
+        let scenario = std::fs::read_to_string(dir.join("scenario.json"))
+            .with_context(|| format!("no scenario in {}", dir.display()))?;
+        Ok(Self {
+            scenario: serde_json::from_str(&scenario)?,
+            dir,
+        })
+    }
+
+    pub fn genesis(&self) -> PathBuf {
+        self.dir.join("genesis.car")
+    }
+
+    pub fn chain(&self) -> PathBuf {
+        self.dir.join("chain.car")
+    }
+}
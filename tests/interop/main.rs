@@ -0,0 +1,107 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Interoperability tests of Forest on a devnet generated by Lotus.
+//!
+//! The tests run Forest nodes on the devnet of a [fixture](fixture), which
+//! they sync, gossip messages to, and compare with Lotus. They are only built
+//! with the `interop` feature, and fail unless `FOREST_INTEROP_FIXTURE` points
+//! to a fixture:
+//! ```shell
+//! FOREST_INTEROP_FIXTURE=/path/to/fixture cargo test --features interop --test interop
+//! ```
+
+mod fixture;
+mod node;
+mod peer;
+
+use std::time::Duration;
+
+use anyhow::Context as _;
+use base64::{prelude::BASE64_STANDARD, Engine as _};
+use serde_json::json;
+
+use crate::fixture::Fixture;
+use crate::node::ForestNode;
+use crate::peer::GossipPeer;
+
+const SYNC_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Starts a node with the chain of the fixture, and a node syncing it.
+async fn devnet(fixture: &Fixture) -> anyhow::Result<(ForestNode, ForestNode)> {
+    let source = ForestNode::start(fixture, true).await?;
+    let syncing = ForestNode::start(fixture, false).await?;
+    syncing.connect(&source).await?;
+    tokio::time::timeout(SYNC_TIMEOUT, async {
+        while syncing.head_epoch().await? < fixture.scenario.head_epoch {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        anyhow::Ok(())
+    })
+    .await
+    .context("the node didn't sync")??;
+    Ok((source, syncing))
+}
+
+#[tokio::test]
+async fn syncs_from_a_peer() -> anyhow::Result<()> {
+    let fixture = Fixture::load()?;
+    let (source, syncing) = devnet(&fixture).await?;
+
+    let tipset = json!([fixture.scenario.head_epoch, []]);
+    let by_height = "Filecoin.ChainGetTipSetByHeight";
+    assert_eq!(
+        source.rpc.call(by_height, tipset.clone()).await?["Cids"],
+        syncing.rpc.call(by_height, tipset).await?["Cids"]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn gossiped_messages_reach_the_message_pool() -> anyhow::Result<()> {
+    let fixture = Fixture::load()?;
+    let (_source, node) = devnet(&fixture).await?;
+    let network_name = node
+        .rpc
+        .call("Filecoin.StateNetworkName", json!([]))
+        .await?;
+    let topic = format!("/fil/msgs/{}", network_name.as_str().unwrap_or_default());
+
+    let mut peer = GossipPeer::new()?;
+    peer.connect(&node.p2p_address().await?).await?;
+    for message in &fixture.scenario.messages {
+        let data = BASE64_STANDARD.decode(&message.bytes)?;
+        peer.publish(&topic, data, Duration::from_secs(30)).await?;
+    }
+
+    for _ in 0..60 {
+        peer.poll_for(Duration::from_secs(1)).await;
+        let pending = node.rpc.call("Filecoin.MpoolPending", json!([[]])).await?;
+        let pending: Vec<_> = pending
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|message| &message["CID"])
+            .collect();
+        if fixture
+            .scenario
+            .messages
+            .iter()
+            .all(|message| pending.contains(&&message.cid))
+        {
+            return Ok(());
+        }
+    }
+    anyhow::bail!("the gossiped messages aren't in the message pool")
+}
+
+#[tokio::test]
+async fn rpc_results_match_lotus() -> anyhow::Result<()> {
+    let fixture = Fixture::load()?;
+    let node = ForestNode::start(&fixture, true).await?;
+    for call in &fixture.scenario.rpc {
+        let result = node.rpc.call(&call.method, call.params.clone()).await?;
+        pretty_assertions::assert_eq!(result, call.result, "{}", call.method);
+    }
+    Ok(())
+}
@@ -0,0 +1,169 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Forest nodes running on the devnet of a fixture, each in its own process and
+//! data directory.
+
+use std::{
+    net::TcpListener,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
+use serde_json::{json, Value};
+use tempfile::TempDir;
+
+use crate::fixture::Fixture;
+
+/// Time allowed for a node to start, including the import of the snapshot
+/// and the download of the proof parameters.
+const START_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+pub struct ForestNode {
+    process: Child,
+    pub rpc: Rpc,
+    _data_dir: TempDir,
+}
+
+impl ForestNode {
+    /// Starts a node on the devnet of `fixture`, importing its chain if
+    /// `import` is set, and waits for its RPC server.
+    pub async fn start(fixture: &Fixture, import: bool) -> anyhow::Result<Self> {
+        let data_dir = tempfile::tempdir()?;
+        let config = data_dir.path().join("config.toml");
+        std::fs::write(
+            &config,
+            format!(
+                r#"
+[client]
+data_dir = "{}"
+encrypt_keystore = false
+
+[network]
+listening_multiaddrs = ["/ip4/127.0.0.1/tcp/{}"]
+kademlia = false
+mdns = false
+
+[chain.network]
+type = "devnet"
+name = "{}"
+"#,
+                data_dir.path().display(),
+                free_port()?,
+                fixture.scenario.network,
+            ),
+        )?;
+        let rpc_address = format!("127.0.0.1:{}", free_port()?);
+        let token = data_dir.path().join("token");
+
+        let mut command = Command::new(assert_cmd::cargo::cargo_bin("forest"));
+        command
+            .env("FIL_PROOFS_PARAMETER_CACHE", "/tmp/forest-test-fil-proofs")
+            .arg("--config")
+            .arg(&config)
+            .arg("--genesis")
+            .arg(fixture.genesis())
+            .arg("--rpc-address")
+            .arg(&rpc_address)
+            .arg("--metrics-address")
+            .arg(format!("127.0.0.1:{}", free_port()?))
+            .arg("--save-token")
+            .arg(&token)
+            .arg("--no-gc")
+            .stdout(Stdio::null());
+        if import {
+            command.arg("--import-snapshot").arg(fixture.chain());
+        }
+        let mut node = Self {
+            process: command.spawn()?,
+            rpc: Rpc::new(format!("http://{rpc_address}/rpc/v0"), token),
+            _data_dir: data_dir,
+        };
+
+        let start = Instant::now();
+        while node
+            .rpc
+            .call("Filecoin.ChainHead", json!([]))
+            .await
+            .is_err()
+        {
+            if let Some(status) = node.process.try_wait()? {
+                anyhow::bail!("Forest exited with {status}");
+            }
+            anyhow::ensure!(start.elapsed() < START_TIMEOUT, "Forest didn't start");
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        Ok(node)
+    }
+
+    pub async fn head_epoch(&self) -> anyhow::Result<i64> {
+        let head = self.rpc.call("Filecoin.ChainHead", json!([])).await?;
+        head["Height"].as_i64().context("invalid head")
+    }
+
+    /// Address of the node for its peers, including its peer ID.
+    pub async fn p2p_address(&self) -> anyhow::Result<String> {
+        let info = self.rpc.call("Filecoin.NetAddrsListen", json!([])).await?;
+        let address = info["Addrs"]
+            .as_array()
+            .and_then(|addrs| addrs.iter().find_map(Value::as_str))
+            .context("the node doesn't listen")?;
+        Ok(format!(
+            "{address}/p2p/{}",
+            info["ID"].as_str().unwrap_or_default()
+        ))
+    }
+
+    pub async fn connect(&self, peer: &ForestNode) -> anyhow::Result<()> {
+        let info = peer.rpc.call("Filecoin.NetAddrsListen", json!([])).await?;
+        self.rpc.call("Filecoin.NetConnect", json!([info])).await?;
+        Ok(())
+    }
+}
+
+impl Drop for ForestNode {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+/// JSON-RPC client of a node, authenticated with its admin token.
+pub struct Rpc {
+    url: String,
+    token: PathBuf,
+    client: reqwest::Client,
+}
+
+impl Rpc {
+    fn new(url: String, token: PathBuf) -> Self {
+        Self {
+            url,
+            token,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub async fn call(&self, method: &str, params: Value) -> anyhow::Result<Value> {
+        let mut request = self.client.post(&self.url).json(&json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "method": method,
+            "params": params,
+        }));
+        if let Ok(token) = std::fs::read_to_string(&self.token) {
+            request = request.bearer_auth(token.trim());
+        }
+        let mut response: Value = request.send().await?.json().await?;
+        match response.get("error") {
+            Some(error) => anyhow::bail!("{method} failed: {error}"),
+            None => Ok(response["result"].take()),
+        }
+    }
+}
+
+fn free_port() -> anyhow::Result<u16> {
+    Ok(TcpListener::bind("127.0.0.1:0")?.local_addr()?.port())
+}
@@ -0,0 +1,85 @@
+// Copyright 2019-2023 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! A simulated peer, only speaking `gossipsub`.
+
+use std::time::{Duration, Instant};
+
+use futures::StreamExt as _;
+use libp2p::{
+    core::upgrade::Version,
+    gossipsub::{self, IdentTopic, MessageAuthenticity, PublishError},
+    identity::Keypair,
+    noise,
+    swarm::{SwarmBuilder, SwarmEvent},
+    tcp, yamux, Multiaddr, Swarm, Transport as _,
+};
+
+pub struct GossipPeer {
+    swarm: Swarm<gossipsub::Behaviour>,
+}
+
+impl GossipPeer {
+    pub fn new() -> anyhow::Result<Self> {
+        let keypair = Keypair::generate_ed25519();
+        let transport = tcp::tokio::Transport::new(tcp::Config::new().nodelay(true))
+            .upgrade(Version::V1)
+            .authenticate(noise::Config::new(&keypair)?)
+            .multiplex(yamux::Config::default())
+            .boxed();
+        let gossipsub = gossipsub::Behaviour::new(
+            MessageAuthenticity::Signed(keypair.clone()),
+            gossipsub::Config::default(),
+        )
+        .map_err(anyhow::Error::msg)?;
+        let peer_id = keypair.public().to_peer_id();
+        Ok(Self {
+            swarm: SwarmBuilder::with_tokio_executor(transport, gossipsub, peer_id).build(),
+        })
+    }
+
+    pub async fn connect(&mut self, address: &str) -> anyhow::Result<()> {
+        self.swarm.dial(address.parse::<Multiaddr>()?)?;
+        loop {
+            match self.swarm.select_next_some().await {
+                SwarmEvent::ConnectionEstablished { .. } => return Ok(()),
+                SwarmEvent::OutgoingConnectionError { error, .. } => return Err(error.into()),
+                _ => {}
+            }
+        }
+    }
+
+    /// Publishes `data` on `topic` once the node has subscribed to it.
+    pub async fn publish(
+        &mut self,
+        topic: &str,
+        data: Vec<u8>,
+        timeout: Duration,
+    ) -> anyhow::Result<()> {
+        let topic = IdentTopic::new(topic);
+        self.swarm.behaviour_mut().subscribe(&topic)?;
+        let start = Instant::now();
+        loop {
+            match self
+                .swarm
+                .behaviour_mut()
+                .publish(topic.clone(), data.clone())
+            {
+                Err(PublishError::InsufficientPeers) if start.elapsed() < timeout => {
+                    self.poll_for(Duration::from_millis(100)).await
+                }
+                result => return Ok(result.map(|_| ())?),
+            }
+        }
+    }
+
+    /// Drives the connections of the peer for `duration`.
+    pub async fn poll_for(&mut self, duration: Duration) {
+        let _ = tokio::time::timeout(duration, async {
+            loop {
+                self.swarm.select_next_some().await;
+            }
+        })
+        .await;
+    }
+}